@@ -1,10 +1,44 @@
 use crate::hqm_server::{
-    HQMMuteStatus, HQMServer, HQMServerPlayerData, HQMServerPlayerIndex, ReplayEnabled,
+    HQMInitialGameValues, HQMMuteStatus, HQMServer, HQMServerPlayerData, HQMServerPlayerIndex,
+    ReplayEnabled, MAX_SPECTATOR_DELAY,
 };
 
+use crate::hqm_autosave::write_atomic;
 use crate::hqm_behaviour::HQMServerBehaviour;
+use crate::hqm_game::HQMPhysicsProfile;
+use crate::hqm_names::HQMNamePattern;
+use crate::hqm_snapshot::{list_savegames, read_savegame, savegame_path, HQMGameSnapshot};
+use std::fmt;
 use systemctl::restart;
-use tracing::info;
+use tracing::{info, warn};
+
+/// Who ran an admin command, a logged in player or the admin console. Both go through the same
+/// handlers, which announce the change in chat and log it.
+pub enum HQMAdminSource {
+    Player(HQMServerPlayerIndex, String),
+    Console,
+}
+
+impl HQMAdminSource {
+    /// The name in chat messages such as "Chat muted by ..."
+    pub fn name(&self) -> &str {
+        match self {
+            HQMAdminSource::Player(_, player_name) => player_name,
+            HQMAdminSource::Console => "console",
+        }
+    }
+}
+
+impl fmt::Display for HQMAdminSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HQMAdminSource::Player(player_index, player_name) => {
+                write!(f, "{} ({})", player_name, player_index)
+            }
+            HQMAdminSource::Console => write!(f, "Console"),
+        }
+    }
+}
 
 impl HQMServer {
     pub fn admin_deny_message(&mut self, player_index: HQMServerPlayerIndex) {
@@ -14,19 +48,267 @@ impl HQMServer {
         );
     }
 
-    pub(crate) fn set_allow_join(&mut self, player_index: HQMServerPlayerIndex, allowed: bool) {
+    /// The player as the source of an admin command, or None after asking them to log in
+    pub fn admin_source(&mut self, player_index: HQMServerPlayerIndex) -> Option<HQMAdminSource> {
+        let player = self.players.get(player_index)?;
+        if player.is_admin {
+            Some(HQMAdminSource::Player(
+                player_index,
+                player.player_name.to_string(),
+            ))
+        } else {
+            self.admin_deny_message(player_index);
+            None
+        }
+    }
+
+    /// Tells an admin in chat why their command was refused. Commands that worked are already
+    /// announced to everyone.
+    pub fn reply_to_admin(
+        &mut self,
+        player_index: HQMServerPlayerIndex,
+        result: Result<String, String>,
+    ) {
+        if let Err(msg) = result {
+            self.messages
+                .add_directed_server_chat_message(msg, player_index);
+        }
+    }
+
+    pub(crate) fn set_allow_join_by(&mut self, source: &HQMAdminSource, allowed: bool) -> String {
+        self.allow_join = allowed;
+        let (action, response) = if allowed {
+            ("enabled joins", "Joins enabled")
+        } else {
+            ("disabled joins", "Joins disabled")
+        };
+        info!("{} {}", source, action);
+        let msg = format!("{} by {}", response, source.name());
+        self.messages.add_server_chat_message(msg);
+        response.to_string()
+    }
+
+    pub(crate) fn set_chat_muted_by(&mut self, source: &HQMAdminSource, muted: bool) -> String {
+        self.is_muted = muted;
+        let (action, response) = if muted {
+            ("muted chat", "Chat muted")
+        } else {
+            ("unmuted chat", "Chat unmuted")
+        };
+        info!("{} {}", source, action);
+        let msg = format!("{} by {}", response, source.name());
+        self.messages.add_server_chat_message(msg);
+        response.to_string()
+    }
+
+    pub(crate) fn set_public_by(&mut self, source: &HQMAdminSource, public: bool) -> String {
+        self.set_public(public);
+        let (action, visibility) = if public {
+            ("made the server public", "public")
+        } else {
+            ("made the server private", "private")
+        };
+        info!("{} {}", source, action);
+        let msg = format!("Server made {} by {}", visibility, source.name());
+        self.messages.add_server_chat_message(msg);
+        format!("Server is {}", visibility)
+    }
+
+    pub(crate) fn clear_bans_by(&mut self, source: &HQMAdminSource) -> String {
+        self.ban_list.clear();
+        info!("{} cleared bans", source);
+        let msg = format!("Bans cleared by {}", source.name());
+        self.messages.add_server_chat_message(msg);
+        "Bans cleared".to_string()
+    }
+
+    pub(crate) fn start_shutdown_by(&mut self, source: &HQMAdminSource, seconds: u32) -> String {
+        info!("{} started shutdown", source);
+        self.start_shutdown(seconds);
+        format!("Shutting down in {} seconds", seconds)
+    }
+
+    pub(crate) fn cancel_shutdown_by(&mut self, source: &HQMAdminSource) -> Result<String, String> {
+        if !self.cancel_shutdown() {
+            return Err("No shutdown in progress".to_string());
+        }
+        info!("{} cancelled shutdown", source);
+        let msg = format!("Shutdown cancelled by {}", source.name());
+        self.messages.add_server_chat_message(msg);
+        Ok("Shutdown cancelled".to_string())
+    }
+
+    /// Starts a new game with the initial values of the current mode
+    pub fn reset_game_by(&mut self, source: &HQMAdminSource, values: HQMInitialGameValues) {
+        info!("{} reset game", source);
+        self.new_game(values);
+        let msg = format!("Game reset by {}", source.name());
+        self.messages.add_server_chat_message(msg);
+    }
+
+    /// Removes a connected player from the server, and bans their address if `ban_player` is set
+    pub(crate) fn kick_player_by<B: HQMServerBehaviour>(
+        &mut self,
+        source: &HQMAdminSource,
+        kick_player_index: HQMServerPlayerIndex,
+        ban_player: bool,
+        behaviour: &mut B,
+    ) -> Result<String, String> {
+        let kicked = self
+            .players
+            .get(kick_player_index)
+            .and_then(|player| match &player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => {
+                    Some((player.player_name.clone(), data.addr.ip()))
+                }
+                _ => None,
+            });
+        let Some((kick_player_name, kick_ip)) = kicked else {
+            return Err(format!("No player with ID {}", kick_player_index));
+        };
+        behaviour.before_player_exit(self, kick_player_index);
+        self.remove_player(kick_player_index, true);
+
+        let action = if ban_player {
+            self.ban_list.insert(kick_ip);
+            "banned"
+        } else {
+            "kicked"
+        };
+        info!(
+            player = %kick_player_name,
+            player_index = kick_player_index.0,
+            "{} {} {} ({})",
+            source,
+            action,
+            kick_player_name,
+            kick_player_index
+        );
+        let msg = format!("{} {} by {}", kick_player_name, action, source.name());
+        self.messages.add_server_chat_message(msg);
+        Ok(format!("{} {}", kick_player_name, action))
+    }
+
+    pub(crate) fn set_alerts(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        if let Some(player) = self.players.get_mut(player_index) {
+            if player.is_admin {
+                let data = match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => data,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return,
+                };
+                let msg = match arg {
+                    "on" => {
+                        data.alerts = true;
+                        "Alerts on, you'll be told about high pings, repeated offsides, low disk space and master server failures"
+                    }
+                    "off" => {
+                        data.alerts = false;
+                        "Alerts off"
+                    }
+                    _ => "Usage: /alerts <on|off>",
+                };
+                self.messages
+                    .add_directed_server_chat_message(msg, player_index);
+            } else {
+                self.admin_deny_message(player_index);
+            }
+        }
+    }
+
+    pub(crate) fn save_game(&mut self, player_index: HQMServerPlayerIndex, name: &str) {
         if let Some(player) = self.players.get(player_index) {
             if player.is_admin {
-                self.allow_join = allowed;
+                let player_name = player.player_name.clone();
+                let directory = &self.config.savegame_directory;
+                let msg = match (savegame_path(directory, name), HQMGameSnapshot::take(self)) {
+                    (None, _) => {
+                        "Usage: /savegame <name>, with letters, digits, - and _".to_string()
+                    }
+                    (_, None) => "There is no game in progress to save".to_string(),
+                    (Some(path), Some(snapshot)) => {
+                        let contents = serde_json::to_string_pretty(&snapshot).unwrap();
+                        let result = tokio::task::block_in_place(|| {
+                            std::fs::create_dir_all(directory)?;
+                            write_atomic(&path, contents.as_bytes())
+                        });
+                        match result {
+                            Ok(()) => {
+                                info!(
+                                    "{} ({}) saved the game as {}",
+                                    player_name, player_index, name
+                                );
+                                format!("Game saved as {}, load it with /loadgame {}", name, name)
+                            }
+                            Err(e) => {
+                                warn!("Could not save game {}: {}", path.display(), e);
+                                format!("Could not save the game: {}", e)
+                            }
+                        }
+                    }
+                };
+                self.messages
+                    .add_directed_server_chat_message(msg, player_index);
+            } else {
+                self.admin_deny_message(player_index);
+            }
+        }
+    }
 
-                if allowed {
-                    info!("{} ({}) enabled joins", player.player_name, player_index);
-                    let msg = format!("Joins enabled by {}", player.player_name);
-                    self.messages.add_server_chat_message(msg);
-                } else {
-                    info!("{} ({}) disabled joins", player.player_name, player_index);
-                    let msg = format!("Joins disabled by {}", player.player_name);
-                    self.messages.add_server_chat_message(msg);
+    /// Restores a game saved with /savegame, or lists the saved games if no name is given
+    pub(crate) fn load_game<B: HQMServerBehaviour>(
+        &mut self,
+        player_index: HQMServerPlayerIndex,
+        name: &str,
+        behaviour: &mut B,
+    ) {
+        if let Some(player) = self.players.get(player_index) {
+            if player.is_admin {
+                if !behaviour.can_resume_game() {
+                    self.messages.add_directed_server_chat_message(
+                        "Saved games can only be loaded in match mode",
+                        player_index,
+                    );
+                    return;
+                }
+                let player_name = player.player_name.clone();
+                let directory = &self.config.savegame_directory;
+                if name.is_empty() {
+                    let names = tokio::task::block_in_place(|| list_savegames(directory));
+                    let msg = if names.is_empty() {
+                        "There are no saved games".to_string()
+                    } else {
+                        format!("Saved games: {}", names.join(", "))
+                    };
+                    self.messages
+                        .add_directed_server_chat_message(msg, player_index);
+                    return;
+                }
+                let path = match savegame_path(directory, name) {
+                    Some(path) => path,
+                    None => {
+                        self.messages.add_directed_server_chat_message(
+                            "Usage: /loadgame [name]",
+                            player_index,
+                        );
+                        return;
+                    }
+                };
+                let snapshot = tokio::task::block_in_place(|| read_savegame(&path));
+                match snapshot {
+                    Ok(snapshot) => {
+                        info!(
+                            "{} ({}) loaded the saved game {}",
+                            player_name, player_index, name
+                        );
+                        self.restore_game(&snapshot, behaviour);
+                        let msg = format!("Saved game {} loaded by {}", name, player_name);
+                        self.messages.add_server_chat_message(msg);
+                    }
+                    Err(e) => {
+                        let msg = format!("Could not load the game {}: {}", name, e);
+                        self.messages
+                            .add_directed_server_chat_message(msg, player_index);
+                    }
                 }
             } else {
                 self.admin_deny_message(player_index);
@@ -34,6 +316,12 @@ impl HQMServer {
         }
     }
 
+    pub(crate) fn set_allow_join(&mut self, player_index: HQMServerPlayerIndex, allowed: bool) {
+        if let Some(source) = self.admin_source(player_index) {
+            self.set_allow_join_by(&source, allowed);
+        }
+    }
+
     pub(crate) fn mute_player(
         &mut self,
         admin_player_index: HQMServerPlayerIndex,
@@ -97,6 +385,127 @@ impl HQMServer {
         }
     }
 
+    /// /caster and /uncaster, a caster stays off the teams but can chat while the chat is muted
+    pub(crate) fn set_caster(
+        &mut self,
+        admin_player_index: HQMServerPlayerIndex,
+        caster_player_index: HQMServerPlayerIndex,
+        caster: bool,
+    ) {
+        if let Some(admin_player) = self.players.get(admin_player_index) {
+            if admin_player.is_admin {
+                let admin_player_name = admin_player.player_name.clone();
+
+                let caster_player = match self.players.get_mut(caster_player_index) {
+                    Some(caster_player) => caster_player,
+                    None => return,
+                };
+                let caster_player_name = caster_player.player_name.clone();
+                match &mut caster_player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => data.caster = caster,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {
+                        self.messages.add_directed_server_chat_message(
+                            "Only players can be casters",
+                            admin_player_index,
+                        );
+                        return;
+                    }
+                }
+                if caster {
+                    self.move_to_spectator(caster_player_index);
+                    info!(
+                        "{} ({}) made {} ({}) a caster",
+                        admin_player_name,
+                        admin_player_index,
+                        caster_player_name,
+                        caster_player_index
+                    );
+                    let msg = format!(
+                        "{} is a caster, made by {}",
+                        caster_player_name, admin_player_name
+                    );
+                    self.messages.add_server_chat_message(msg);
+                } else {
+                    info!(
+                        "{} ({}) removed the caster role of {} ({})",
+                        admin_player_name,
+                        admin_player_index,
+                        caster_player_name,
+                        caster_player_index
+                    );
+                    let msg = format!(
+                        "{} is no longer a caster, changed by {}",
+                        caster_player_name, admin_player_name
+                    );
+                    self.messages.add_server_chat_message(msg);
+                }
+            } else {
+                self.admin_deny_message(admin_player_index);
+            }
+        }
+    }
+
+    /// /delay ID seconds sets how long the feed of a spectator is delayed, /delay ID default
+    /// goes back to spectator_delay
+    pub(crate) fn set_feed_delay(&mut self, admin_player_index: HQMServerPlayerIndex, arg: &str) {
+        if let Some(admin_player) = self.players.get(admin_player_index) {
+            if admin_player.is_admin {
+                let admin_player_name = admin_player.player_name.clone();
+                let mut args = arg.split_whitespace();
+                let delay_player_index = args
+                    .next()
+                    .and_then(|x| x.parse::<HQMServerPlayerIndex>().ok());
+                let delay = match args.next() {
+                    Some("default") => Some(None),
+                    Some(delay) => delay
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|delay| *delay <= MAX_SPECTATOR_DELAY)
+                        .map(Some),
+                    None => None,
+                };
+                let (delay_player_index, delay) = match (delay_player_index, delay) {
+                    (Some(delay_player_index), Some(delay)) => (delay_player_index, delay),
+                    _ => {
+                        let msg = format!(
+                            "Usage: /delay <ID> <seconds up to {}|default>",
+                            MAX_SPECTATOR_DELAY
+                        );
+                        self.messages
+                            .add_directed_server_chat_message(msg, admin_player_index);
+                        return;
+                    }
+                };
+                let delay_player = match self.players.get_mut(delay_player_index) {
+                    Some(delay_player) => delay_player,
+                    None => return,
+                };
+                let delay_player_name = delay_player.player_name.clone();
+                match &mut delay_player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => data.feed_delay = delay,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return,
+                }
+                let seconds = delay.unwrap_or(self.config.spectator_delay);
+                info!(
+                    "{} ({}) set the feed delay of {} ({}) to {} seconds",
+                    admin_player_name,
+                    admin_player_index,
+                    delay_player_name,
+                    delay_player_index,
+                    seconds
+                );
+                let msg = format!(
+                    "Feed of {} delayed by {} seconds while spectating",
+                    delay_player_name, seconds
+                );
+                self.messages
+                    .add_directed_server_chat_message(msg, admin_player_index);
+            } else {
+                self.admin_deny_message(admin_player_index);
+            }
+        }
+    }
+
     #[allow(dead_code)]
     pub(crate) fn shadowmute_player(
         &mut self,
@@ -140,31 +549,14 @@ impl HQMServer {
     }
 
     pub(crate) fn mute_chat(&mut self, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = self.players.get(player_index) {
-            if player.is_admin {
-                self.is_muted = true;
-
-                let msg = format!("Chat muted by {}", player.player_name);
-                info!("{} ({}) muted chat", player.player_name, player_index);
-                self.messages.add_server_chat_message(msg);
-            } else {
-                self.admin_deny_message(player_index);
-            }
+        if let Some(source) = self.admin_source(player_index) {
+            self.set_chat_muted_by(&source, true);
         }
     }
 
     pub(crate) fn unmute_chat(&mut self, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = self.players.get(player_index) {
-            if player.is_admin {
-                self.is_muted = false;
-
-                let msg = format!("Chat unmuted by {}", player.player_name);
-                info!("{} ({}) unmuted chat", player.player_name, player_index);
-
-                self.messages.add_server_chat_message(msg);
-            } else {
-                self.admin_deny_message(player_index);
-            }
+        if let Some(source) = self.admin_source(player_index) {
+            self.set_chat_muted_by(&source, false);
         }
     }
 
@@ -205,189 +597,129 @@ impl HQMServer {
         }
     }
 
-    pub(crate) fn kick_all_matching<B: HQMServerBehaviour>(
-        &mut self,
-        admin_player_index: HQMServerPlayerIndex,
-        kick_player_name: &str,
-        ban_player: bool,
-        behaviour: &mut B,
-    ) {
-        if let Some(player) = self.players.get(admin_player_index) {
+    pub(crate) fn set_physics_profile(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        if arg.is_empty() {
+            let current = HQMPhysicsProfile {
+                limit_jump_speed: false,
+                ..self.world.physics_config.clone()
+            };
+            let name = HQMPhysicsProfile::PRESETS
+                .into_iter()
+                .find(|name| HQMPhysicsProfile::preset(name).as_ref() == Some(&current))
+                .unwrap_or("custom");
+            let msg = format!(
+                "Physics profile: {}, presets: {}",
+                name,
+                HQMPhysicsProfile::PRESETS.join(", ")
+            );
+            self.messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        if let Some(player) = self.players.get(player_index) {
             if player.is_admin {
-                let admin_player_name = player.player_name.clone();
-
-                enum Matching<'a> {
-                    StartsWith(&'a str),
-                    EndsWith(&'a str),
-                    Contains(&'a str),
-                    Equals(&'a str),
+                if let Some(mut profile) = HQMPhysicsProfile::preset(arg) {
+                    profile.limit_jump_speed = self.world.physics_config.limit_jump_speed;
+                    self.physics_override = Some(profile);
+                    self.standby_game = None;
+                    info!(
+                        "{} ({}) changed physics profile to {}",
+                        player.player_name, player_index, arg
+                    );
+                    let msg = format!(
+                        "Physics profile {} will be used from the next game, changed by {}",
+                        arg, player.player_name
+                    );
+                    self.messages.add_server_chat_message(msg);
+                } else {
+                    let msg = format!(
+                        "Unknown physics profile, presets: {}",
+                        HQMPhysicsProfile::PRESETS.join(", ")
+                    );
+                    self.messages
+                        .add_directed_server_chat_message(msg, player_index);
                 }
+            } else {
+                self.admin_deny_message(player_index);
+            }
+        }
+    }
 
-                impl<'a> Matching<'a> {
-                    fn is_matching(&self, player_name: &str) -> bool {
-                        match self {
-                            Matching::StartsWith(s) => player_name.starts_with(s),
-                            Matching::EndsWith(s) => player_name.ends_with(s),
-                            Matching::Contains(s) => player_name.contains(s),
-                            Matching::Equals(s) => player_name == *s,
-                        }
-                    }
+    pub(crate) fn admin_set_public(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        if let Some(source) = self.admin_source(player_index) {
+            match arg {
+                "on" => {
+                    self.set_public_by(&source, true);
                 }
-
-                // 0 full string | 1 begins with | 2 ends with | 3 contains
-                let matching = if kick_player_name.starts_with("%") {
-                    if kick_player_name.ends_with("%") {
-                        Matching::Contains(&kick_player_name[1..kick_player_name.len() - 1])
+                "off" => {
+                    self.set_public_by(&source, false);
+                }
+                _ => {
+                    let msg = if self.config.public {
+                        "Server is public"
                     } else {
-                        Matching::StartsWith(&kick_player_name[1..kick_player_name.len()])
-                    }
-                } else if kick_player_name.ends_with("%") {
-                    Matching::EndsWith(&kick_player_name[0..kick_player_name.len() - 1])
-                } else {
-                    Matching::Equals(&kick_player_name)
-                };
-
-                let kick_player_list: Vec<_> = self
-                    .players
-                    .iter()
-                    .filter_map(|(player_index, player)| {
-                        if let HQMServerPlayerData::NetworkPlayer { data } = &player.data {
-                            if matching.is_matching(&player.player_name) {
-                                return Some((player_index, player.player_name.clone(), data.addr));
-                            }
-                        }
-                        None
-                    })
-                    .collect();
-
-                if !kick_player_list.is_empty() {
-                    for (player_index, player_name, player_addr) in kick_player_list {
-                        if player_index != admin_player_index {
-                            behaviour.before_player_exit(self, player_index);
-                            self.remove_player(player_index, true);
-
-                            if ban_player {
-                                self.ban_list.insert(player_addr.ip());
-
-                                info!(
-                                    "{} ({}) banned {} ({})",
-                                    admin_player_name,
-                                    admin_player_index,
-                                    player_name,
-                                    player_index
-                                );
-                                let msg =
-                                    format!("{} banned by {}", player_name, admin_player_name);
-                                self.messages.add_server_chat_message(msg);
-                            } else {
-                                info!(
-                                    "{} ({}) kicked {} ({})",
-                                    admin_player_name,
-                                    admin_player_index,
-                                    player_name,
-                                    player_index
-                                );
-                                let msg =
-                                    format!("{} kicked by {}", player_name, admin_player_name);
-                                self.messages.add_server_chat_message(msg);
-                            }
-                        } else {
-                            if ban_player {
-                                self.messages.add_directed_server_chat_message(
-                                    "You cannot ban yourself",
-                                    admin_player_index,
-                                );
-                            } else {
-                                self.messages.add_directed_server_chat_message(
-                                    "You cannot kick yourself",
-                                    admin_player_index,
-                                );
-                            }
-                        }
-                    }
-                } else {
-                    match matching {
-                        Matching::Equals(_) => {
-                            // full string
-                            let msg = format!("No player names match {}", kick_player_name);
-                            self.messages
-                                .add_directed_server_chat_message(msg, admin_player_index);
-                        }
-                        Matching::StartsWith(_) => {
-                            // begins with%
-                            let msg = format!("No player names begin with {}", kick_player_name);
-                            self.messages
-                                .add_directed_server_chat_message(msg, admin_player_index);
-                        }
-                        Matching::EndsWith(_) => {
-                            // %ends with
-                            let msg = format!("No player names end with {}", kick_player_name);
-                            self.messages
-                                .add_directed_server_chat_message(msg, admin_player_index);
-                        }
-                        Matching::Contains(_) => {
-                            // %contains%
-                            let msg = format!("No player names contain {}", kick_player_name);
-                            self.messages
-                                .add_directed_server_chat_message(msg, admin_player_index);
-                        }
-                    }
+                        "Server is private"
+                    };
+                    self.messages
+                        .add_directed_server_chat_message(msg, player_index);
                 }
+            }
+        }
+    }
+
+    pub(crate) fn admin_shutdown(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        if let Some(source) = self.admin_source(player_index) {
+            if arg == "cancel" {
+                let result = self.cancel_shutdown_by(&source);
+                self.reply_to_admin(player_index, result);
+            } else if let Ok(seconds) = parse_shutdown_seconds(arg) {
+                self.start_shutdown_by(&source, seconds);
             } else {
-                self.admin_deny_message(admin_player_index);
-                return;
+                self.messages.add_directed_server_chat_message(
+                    "Usage: /shutdown [seconds up to 600|cancel]",
+                    player_index,
+                );
             }
         }
     }
 
-    pub(crate) fn kick_player<B: HQMServerBehaviour>(
+    pub(crate) fn kick_all_matching<B: HQMServerBehaviour>(
         &mut self,
         admin_player_index: HQMServerPlayerIndex,
-        kick_player_index: HQMServerPlayerIndex,
+        kick_player_name: &str,
         ban_player: bool,
         behaviour: &mut B,
     ) {
-        if let Some(player) = self.players.get(admin_player_index) {
-            if player.is_admin {
-                let admin_player_name = player.player_name.clone();
-
-                if kick_player_index != admin_player_index {
-                    if let Some(kick_player) = self.players.get(kick_player_index) {
-                        if let HQMServerPlayerData::NetworkPlayer { data } = &kick_player.data {
-                            let kick_player_name = kick_player.player_name.clone();
-                            let kick_ip = data.addr.ip().clone();
-                            behaviour.before_player_exit(self, kick_player_index);
-                            self.remove_player(kick_player_index, true);
-
-                            if ban_player {
-                                self.ban_list.insert(kick_ip);
+        if let Some(source) = self.admin_source(admin_player_index) {
+            // A pattern of only wildcards would match everyone
+            let Some(matching) = HQMNamePattern::parse(kick_player_name) else {
+                self.messages.add_directed_server_chat_message(
+                    "The name needs at least one character besides %",
+                    admin_player_index,
+                );
+                return;
+            };
 
-                                info!(
-                                    "{} ({}) banned {} ({})",
-                                    admin_player_name,
-                                    admin_player_index,
-                                    kick_player_name,
-                                    kick_player_name
-                                );
-                                let msg =
-                                    format!("{} banned by {}", kick_player_name, admin_player_name);
-                                self.messages.add_server_chat_message(msg);
-                            } else {
-                                info!(
-                                    "{} ({}) kicked {} ({})",
-                                    admin_player_name,
-                                    admin_player_index,
-                                    kick_player_name,
-                                    kick_player_name
-                                );
-                                let msg =
-                                    format!("{} kicked by {}", kick_player_name, admin_player_name);
-                                self.messages.add_server_chat_message(msg);
-                            }
+            let kick_player_list: Vec<_> = self
+                .players
+                .iter()
+                .filter_map(|(player_index, player)| {
+                    if let HQMServerPlayerData::NetworkPlayer { .. } = &player.data {
+                        if matching.matches(&player.player_name) {
+                            return Some(player_index);
                         }
                     }
-                } else {
-                    if ban_player {
+                    None
+                })
+                .collect();
+
+            if !kick_player_list.is_empty() {
+                for player_index in kick_player_list {
+                    if player_index != admin_player_index {
+                        let result =
+                            self.kick_player_by(&source, player_index, ban_player, behaviour);
+                        self.reply_to_admin(admin_player_index, result);
+                    } else if ban_player {
                         self.messages.add_directed_server_chat_message(
                             "You cannot ban yourself",
                             admin_player_index,
@@ -400,26 +732,51 @@ impl HQMServer {
                     }
                 }
             } else {
-                self.admin_deny_message(admin_player_index);
-                return;
+                let msg = format!(
+                    "No player names {} {}",
+                    matching.description(),
+                    kick_player_name.trim_matches('%')
+                );
+                self.messages
+                    .add_directed_server_chat_message(msg, admin_player_index);
+                if matching.is_exact() {
+                    self.suggest_player_name(kick_player_name, admin_player_index);
+                }
             }
         }
     }
 
-    pub(crate) fn clear_bans(&mut self, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = self.players.get(player_index) {
-            if player.is_admin {
-                self.ban_list.clear();
-                info!("{} ({}) cleared bans", player.player_name, player_index);
-
-                let msg = format!("Bans cleared by {}", player.player_name);
-                self.messages.add_server_chat_message(msg);
+    pub(crate) fn kick_player<B: HQMServerBehaviour>(
+        &mut self,
+        admin_player_index: HQMServerPlayerIndex,
+        kick_player_index: HQMServerPlayerIndex,
+        ban_player: bool,
+        behaviour: &mut B,
+    ) {
+        if let Some(source) = self.admin_source(admin_player_index) {
+            if kick_player_index != admin_player_index {
+                let result = self.kick_player_by(&source, kick_player_index, ban_player, behaviour);
+                self.reply_to_admin(admin_player_index, result);
+            } else if ban_player {
+                self.messages.add_directed_server_chat_message(
+                    "You cannot ban yourself",
+                    admin_player_index,
+                );
             } else {
-                self.admin_deny_message(player_index);
+                self.messages.add_directed_server_chat_message(
+                    "You cannot kick yourself",
+                    admin_player_index,
+                );
             }
         }
     }
 
+    pub(crate) fn clear_bans(&mut self, player_index: HQMServerPlayerIndex) {
+        if let Some(source) = self.admin_source(player_index) {
+            self.clear_bans_by(&source);
+        }
+    }
+
     pub fn set_replay(&mut self, player_index: HQMServerPlayerIndex, rule: &str) {
         if let Some(player) = self.players.get(player_index) {
             if player.is_admin {
@@ -463,3 +820,25 @@ impl HQMServer {
         }
     }
 }
+
+/// Parses the delay of /shutdown, 10 seconds if it's left out and at most 10 minutes
+pub(crate) fn parse_shutdown_seconds(arg: &str) -> Result<u32, std::num::ParseIntError> {
+    if arg.is_empty() {
+        Ok(10)
+    } else {
+        arg.parse::<u32>().map(|seconds| seconds.min(600))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_delay_is_at_most_ten_minutes() {
+        assert_eq!(parse_shutdown_seconds(""), Ok(10));
+        assert_eq!(parse_shutdown_seconds("30"), Ok(30));
+        assert_eq!(parse_shutdown_seconds("3600"), Ok(600));
+        assert!(parse_shutdown_seconds("soon").is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The same alert about the same subject is sent at most once in this time
+const ALERT_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Time between checks of pings, master servers and disk space
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time between checks of the free disk space
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Thresholds of the alerts admins can subscribe to with /alerts, 0 disables an alert
+#[derive(Debug, Clone)]
+pub struct HQMAlertConfig {
+    /// Average ping in milliseconds
+    pub ping: u32,
+    /// Offsides of one player in a game
+    pub offsides: u32,
+    /// Free disk space in megabytes
+    pub disk_space: u32,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub(crate) enum HQMAlertKind {
+    Ping,
+    Offside,
+    DiskSpace,
+    MasterServer,
+}
+
+pub(crate) struct HQMAlerts {
+    /// When each alert was last sent, by kind and subject
+    sent: HashMap<(HQMAlertKind, String), Instant>,
+    last_check: Instant,
+    last_disk_check: Option<Instant>,
+    /// Free disk space in bytes found by the last check
+    free_disk_space: Option<u64>,
+    /// Failed heartbeats and lookups of each master server at the last check
+    master_server_failures: Vec<u64>,
+}
+
+impl HQMAlerts {
+    pub(crate) fn new() -> Self {
+        HQMAlerts {
+            sent: HashMap::new(),
+            last_check: Instant::now(),
+            last_disk_check: None,
+            free_disk_space: None,
+            master_server_failures: vec![],
+        }
+    }
+
+    /// Returns true if the alert should be sent, and remembers that it was
+    pub(crate) fn should_send(&mut self, kind: HQMAlertKind, subject: &str) -> bool {
+        let now = Instant::now();
+        self.sent
+            .retain(|_, sent| now.duration_since(*sent) < ALERT_COOLDOWN);
+        match self.sent.entry((kind, subject.to_string())) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+
+    /// Returns true when it's time to check pings, master servers and disk space again
+    pub(crate) fn check_due(&mut self) -> bool {
+        if self.last_check.elapsed() >= CHECK_INTERVAL {
+            self.last_check = Instant::now();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the failed heartbeats and lookups of a master server that are new since the
+    /// last check
+    pub(crate) fn new_master_server_failures(&mut self, index: usize, failures: u64) -> u64 {
+        if self.master_server_failures.len() <= index {
+            self.master_server_failures.resize(index + 1, 0);
+        }
+        let new_failures = failures.saturating_sub(self.master_server_failures[index]);
+        self.master_server_failures[index] = failures;
+        new_failures
+    }
+
+    /// Returns the free disk space of the working directory in bytes, checked at most once a
+    /// minute
+    pub(crate) fn free_disk_space(&mut self) -> Option<u64> {
+        let due = match self.last_disk_check {
+            Some(last_disk_check) => last_disk_check.elapsed() >= DISK_CHECK_INTERVAL,
+            None => true,
+        };
+        if due {
+            self.last_disk_check = Some(Instant::now());
+            self.free_disk_space = free_disk_space_of(Path::new("."));
+        }
+        self.free_disk_space
+    }
+}
+
+/// Free disk space of the file system a path is on that is available to the server, from
+/// statvfs, since the standard library has no way to find it
+#[cfg(unix)]
+fn free_disk_space_of(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain data, for which all zeroes is valid
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: the path is a nul-terminated string and stats is valid to write to, both outlive
+    // the call
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn free_disk_space_of(_path: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn free_disk_space_is_found_for_existing_paths() {
+        assert!(free_disk_space_of(Path::new(".")).is_some_and(|free| free > 0));
+        assert_eq!(free_disk_space_of(Path::new("/no/such/directory")), None);
+    }
+}
@@ -0,0 +1,110 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use tracing::{info, warn};
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+/// Replaces the file with new contents through a temporary file, so that a crash leaves either
+/// the old or the new file behind, never a partially written one. The previous contents are kept
+/// as a .bak file next to it.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let temp_path = with_extension(path, "tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    match std::fs::rename(path, with_extension(path, "bak")) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    std::fs::rename(&temp_path, path)
+}
+
+/// Remembers what was last written to a persistent file, so that it's only written when it changed
+pub struct HQMAutosaveFile {
+    path: PathBuf,
+    saved_contents: Option<String>,
+}
+
+impl HQMAutosaveFile {
+    pub fn new(path: PathBuf) -> Self {
+        HQMAutosaveFile {
+            path,
+            saved_contents: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the file. If it's missing or can't be parsed, the last good snapshot in the .bak file
+    /// is used instead, and the file is written again at the next save.
+    pub fn load<T>(&mut self, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+        for candidate in [self.path.clone(), with_extension(&self.path, "bak")] {
+            match std::fs::read_to_string(&candidate) {
+                Ok(contents) => match parse(&contents) {
+                    Some(value) => {
+                        if candidate == self.path {
+                            self.saved_contents = Some(contents);
+                        } else {
+                            warn!(
+                                "Recovered {} from snapshot {}",
+                                self.path.display(),
+                                candidate.display()
+                            );
+                        }
+                        return Some(value);
+                    }
+                    None if candidate == self.path => {
+                        // Moved away so that the next save doesn't replace the snapshot with it
+                        let corrupt_path = with_extension(&self.path, "corrupt");
+                        warn!(
+                            "{} is corrupt, moving it to {}",
+                            candidate.display(),
+                            corrupt_path.display()
+                        );
+                        if let Err(e) = std::fs::rename(&candidate, &corrupt_path) {
+                            warn!("Could not move {}: {}", candidate.display(), e);
+                        }
+                    }
+                    None => warn!("{} is corrupt, ignoring it", candidate.display()),
+                },
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => warn!("Could not read {}: {}", candidate.display(), e),
+            }
+        }
+        None
+    }
+
+    pub fn save(&mut self, contents: String) {
+        if self.saved_contents.as_ref() == Some(&contents) {
+            return;
+        }
+        match write_atomic(&self.path, contents.as_bytes()) {
+            Ok(()) => {
+                info!("Saved {}", self.path.display());
+                self.saved_contents = Some(contents);
+            }
+            // Retried at the next autosave
+            Err(e) => warn!("Could not save {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+pub(crate) struct HQMAutosave {
+    pub(crate) ban_file: Option<HQMAutosaveFile>,
+    pub(crate) snapshot_file: Option<HQMAutosaveFile>,
+    pub(crate) preferences_file: Option<HQMAutosaveFile>,
+    pub(crate) last_save: Instant,
+}
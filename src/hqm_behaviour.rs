@@ -1,5 +1,6 @@
 use crate::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex};
 use crate::hqm_simulate::HQMSimulationEvent;
+use crate::hqm_snapshot::HQMGameSnapshot;
 
 pub trait HQMServerBehaviour {
     fn init(&mut self, _server: &mut HQMServer) {}
@@ -15,12 +16,46 @@ pub trait HQMServerBehaviour {
     ) {
     }
 
+    /// Handles an admin console command of the game mode, returning None if the command is unknown
+    fn handle_console_command(
+        &mut self,
+        _server: &mut HQMServer,
+        _cmd: &str,
+        _arg: &str,
+    ) -> Option<String> {
+        None
+    }
+
+    /// Admin console commands of the game mode, listed by help
+    fn console_help(&self) -> Option<&'static str> {
+        None
+    }
+
     fn get_initial_game_values(&mut self) -> HQMInitialGameValues;
     fn game_started(&mut self, _server: &mut HQMServer) {}
 
+    /// Called when a game is restored from a snapshot, after game_started when the server was
+    /// started with `--resume`, or by /loadgame. The score, clock and pucks are already restored
+    fn game_resumed(&mut self, _server: &mut HQMServer, _snapshot: &HQMGameSnapshot) {}
+
+    /// Whether the game mode can continue a game restored from a snapshot, which /loadgame
+    /// requires
+    fn can_resume_game(&self) -> bool {
+        false
+    }
+
     fn before_player_exit(&mut self, _server: &mut HQMServer, _player_index: HQMServerPlayerIndex) {
     }
 
+    /// Called before [before_player_exit](Self::before_player_exit) when a player left or
+    /// timed out, but not when they were kicked
+    fn before_player_disconnect(
+        &mut self,
+        _server: &mut HQMServer,
+        _player_index: HQMServerPlayerIndex,
+    ) {
+    }
+
     fn after_player_join(&mut self, _server: &mut HQMServer, _player_index: HQMServerPlayerIndex) {}
 
     fn get_number_of_players(&self) -> u32;
@@ -28,4 +63,91 @@ pub trait HQMServerBehaviour {
     fn save_replay_data(&self, _server: &HQMServer) -> bool {
         false
     }
+
+    /// Number of periods before overtime, None if the game mode has no overtime
+    fn regular_periods(&self) -> Option<u32> {
+        None
+    }
+}
+
+/// Lets the game mode be chosen while the server runs, as with playlists
+impl<B: HQMServerBehaviour + ?Sized> HQMServerBehaviour for Box<B> {
+    fn init(&mut self, server: &mut HQMServer) {
+        (**self).init(server)
+    }
+
+    fn before_tick(&mut self, server: &mut HQMServer) {
+        (**self).before_tick(server)
+    }
+
+    fn after_tick(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        (**self).after_tick(server, events)
+    }
+
+    fn handle_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        (**self).handle_command(server, cmd, arg, player_index)
+    }
+
+    fn handle_console_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+    ) -> Option<String> {
+        (**self).handle_console_command(server, cmd, arg)
+    }
+
+    fn console_help(&self) -> Option<&'static str> {
+        (**self).console_help()
+    }
+
+    fn get_initial_game_values(&mut self) -> HQMInitialGameValues {
+        (**self).get_initial_game_values()
+    }
+
+    fn game_started(&mut self, server: &mut HQMServer) {
+        (**self).game_started(server)
+    }
+
+    fn game_resumed(&mut self, server: &mut HQMServer, snapshot: &HQMGameSnapshot) {
+        (**self).game_resumed(server, snapshot)
+    }
+
+    fn can_resume_game(&self) -> bool {
+        (**self).can_resume_game()
+    }
+
+    fn before_player_exit(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        (**self).before_player_exit(server, player_index)
+    }
+
+    fn before_player_disconnect(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        (**self).before_player_disconnect(server, player_index)
+    }
+
+    fn after_player_join(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        (**self).after_player_join(server, player_index)
+    }
+
+    fn get_number_of_players(&self) -> u32 {
+        (**self).get_number_of_players()
+    }
+
+    fn save_replay_data(&self, server: &HQMServer) -> bool {
+        (**self).save_replay_data(server)
+    }
+
+    fn regular_periods(&self) -> Option<u32> {
+        (**self).regular_periods()
+    }
 }
@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+use crate::hqm_parse::HQMMessageWriter;
+
+/// Packet type of rink info, both for requests from clients and the answers
+pub(crate) const RINK_INFO: u8 = 0x40;
+
+/// Version of the rink info packet. Clients send the version they understand in their request
+pub(crate) const RINK_INFO_VERSION: u32 = 1;
+
+/// Longest board text and most boards that fit in a rink info packet
+pub const MAX_BOARD_TEXT_LENGTH: usize = 255;
+pub const MAX_BOARDS: usize = 255;
+
+/// Keeps track of the sponsor texts on the boards, which are rotated by one board at a time
+pub(crate) struct HQMBoards {
+    offset: usize,
+    last_rotation: Instant,
+    revision: u32,
+    texts: Vec<String>,
+}
+
+impl HQMBoards {
+    pub(crate) fn new() -> Self {
+        HQMBoards {
+            offset: 0,
+            last_rotation: Instant::now(),
+            revision: 0,
+            texts: vec![],
+        }
+    }
+
+    /// Rotates the boards if it's time to, and returns true if the texts changed since the
+    /// last call. The configured texts can change when the configuration is reloaded.
+    pub(crate) fn update(&mut self, texts: &[String], rotation_seconds: u32) -> bool {
+        if texts.is_empty() {
+            self.offset = 0;
+        } else if rotation_seconds > 0
+            && self.last_rotation.elapsed() >= Duration::from_secs(rotation_seconds.into())
+        {
+            self.offset = (self.offset + 1) % texts.len();
+            self.last_rotation = Instant::now();
+        }
+        let offset = if texts.is_empty() {
+            0
+        } else {
+            self.offset % texts.len()
+        };
+        let rotated = texts[offset..].iter().chain(texts[..offset].iter());
+        if rotated.clone().eq(self.texts.iter()) {
+            return false;
+        }
+        self.texts = rotated.cloned().collect();
+        self.revision = self.revision.wrapping_add(1);
+        true
+    }
+
+    /// Writes the body of a rink info packet. Clients show the texts on the boards in order,
+    /// and can use the revision to ignore packets that arrive out of order.
+    pub(crate) fn write_rink_info(&self, writer: &mut HQMMessageWriter) {
+        writer.write_u32_aligned(RINK_INFO_VERSION);
+        writer.write_u32_aligned(self.revision);
+        let texts = &self.texts[..self.texts.len().min(MAX_BOARDS)];
+        writer.write_byte_aligned(texts.len() as u8);
+        for text in texts {
+            let bytes = &text.as_bytes()[..text.len().min(MAX_BOARD_TEXT_LENGTH)];
+            writer.write_byte_aligned(bytes.len() as u8);
+            writer.write_bytes_aligned(bytes);
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, FRAC_PI_8, PI};
+
+use nalgebra::{Point3, Vector2, Vector3};
+
+use crate::hqm_game::{HQMPlayerInput, HQMRinkNet, HQMSkater, HQMSkaterHand};
+use crate::hqm_server::{HQMServer, HQMServerPlayerIndex, HQMTeam};
+
+const STICK_LENGTH: f32 = 1.75;
+/// A puck closer than this to the stick blade in meters is on the stick
+const PUCK_CONTROL_DISTANCE: f32 = 0.5;
+/// Bots shoot when they are closer to the net than this in meters
+const SHOOTING_DISTANCE: f32 = 10.0;
+/// Passes go to team mates at least this much closer to the net in meters
+const PASS_GAIN: f32 = 5.0;
+const MAX_PASS_DISTANCE: f32 = 25.0;
+/// A team mate with an opponent closer than this in meters isn't open for a pass
+const OPEN_DISTANCE: f32 = 2.5;
+/// Steps of pulling the stick back before a shot or pass
+const PULL_STEPS: u32 = 12;
+/// Steps of pushing the stick through the puck
+const PUSH_STEPS: u32 = 10;
+/// Steps after a shot or pass before the bot tries to get the puck again
+const SHOT_COOLDOWN: u32 = 50;
+/// Steps a bot that tries to skate but doesn't get anywhere waits before it backs off
+const STUCK_STEPS: u32 = 200;
+/// Steps a stuck bot skates backwards
+const BACK_OFF_STEPS: u32 = 60;
+
+#[derive(Copy, Clone)]
+enum HQMBotShotPhase {
+    Pull,
+    Push,
+}
+
+/// A shot or pass that a bot is making
+#[derive(Copy, Clone)]
+struct HQMBotShot {
+    phase: HQMBotShotPhase,
+    target: Point3<f32>,
+    steps: u32,
+}
+
+#[derive(Default)]
+struct HQMBot {
+    shot: Option<HQMBotShot>,
+    cooldown: u32,
+    /// Where the bot started trying to skate without getting anywhere, and for how many steps
+    stuck: Option<(Point3<f32>, u32)>,
+    back_off: u32,
+}
+
+/// The skaters of a team and where they are, as the bots see the game
+struct HQMBotView {
+    skaters: Vec<(HQMServerPlayerIndex, HQMTeam, Point3<f32>)>,
+}
+
+impl HQMBotView {
+    fn closest_to(
+        &self,
+        pos: &Point3<f32>,
+        team: Option<HQMTeam>,
+    ) -> Option<(HQMServerPlayerIndex, HQMTeam, f32)> {
+        self.skaters
+            .iter()
+            .filter(|(_, t, _)| team.is_none() || team == Some(*t))
+            .map(|(player_index, team, skater_pos)| {
+                (*player_index, *team, flat(skater_pos - pos).norm())
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+    }
+
+    fn is_open(&self, team: HQMTeam, pos: &Point3<f32>) -> bool {
+        self.skaters
+            .iter()
+            .filter(|(_, t, _)| *t != team)
+            .all(|(_, _, skater_pos)| flat(skater_pos - pos).norm() > OPEN_DISTANCE)
+    }
+}
+
+/// Controllers of the bots on the server. A bot is a player whose input is set by the server
+/// every step: it chases the puck, carries it towards the net, passes to open team mates and
+/// shoots, and otherwise stays between the puck and its own net
+#[derive(Default)]
+pub struct HQMBots {
+    bots: HashMap<HQMServerPlayerIndex, HQMBot>,
+}
+
+impl HQMBots {
+    /// Sets the input of every bot for the next step. Bots that are spectators join the team
+    /// with fewer players if it has fewer than team_max. rink_end gives the net a team defends
+    pub fn update(
+        &mut self,
+        server: &mut HQMServer,
+        team_max: usize,
+        rink_end: impl Fn(HQMTeam) -> HQMTeam,
+    ) {
+        let bot_players: Vec<_> = server
+            .players
+            .iter()
+            .filter(|(_, player)| player.is_bot())
+            .map(|(player_index, player)| (player_index, player.object))
+            .collect();
+        self.bots
+            .retain(|player_index, _| bot_players.iter().any(|(i, _)| i == player_index));
+        if bot_players.is_empty() {
+            return;
+        }
+
+        let view = HQMBotView {
+            skaters: server
+                .players
+                .iter()
+                .filter_map(|(player_index, player)| {
+                    let (object_index, team) = player.object?;
+                    let skater = server.world.objects.get_skater(object_index)?;
+                    Some((player_index, team, skater.body.pos))
+                })
+                .collect(),
+        };
+        let red_count = view.skaters.iter().filter(|x| x.1 == HQMTeam::Red).count();
+        let blue_count = view.skaters.len() - red_count;
+        let join_team = if red_count <= blue_count && red_count < team_max {
+            Some(HQMTeam::Red)
+        } else if blue_count < team_max {
+            Some(HQMTeam::Blue)
+        } else {
+            None
+        };
+
+        for (player_index, object) in bot_players {
+            let bot = self.bots.entry(player_index).or_default();
+            let input = match object {
+                Some((object_index, team)) => match server.world.objects.get_skater(object_index) {
+                    Some(skater) => {
+                        let own_net = net_center(server, rink_end(team));
+                        let opponent_net = net_center(server, rink_end(team.get_other_team()));
+                        let input = bot.skate(
+                            server,
+                            &view,
+                            player_index,
+                            team,
+                            skater,
+                            own_net,
+                            opponent_net,
+                        );
+                        bot.unstick(skater, input)
+                    }
+                    None => HQMPlayerInput::default(),
+                },
+                None => {
+                    let keys = match join_team {
+                        Some(HQMTeam::Red) => 0x4,
+                        Some(HQMTeam::Blue) => 0x8,
+                        None => 0,
+                    };
+                    HQMPlayerInput {
+                        keys,
+                        ..Default::default()
+                    }
+                }
+            };
+            if let Some(player) = server.players.get_mut(player_index) {
+                player.input = input;
+            }
+        }
+    }
+}
+
+impl HQMBot {
+    #[allow(clippy::too_many_arguments)]
+    fn skate(
+        &mut self,
+        server: &HQMServer,
+        view: &HQMBotView,
+        player_index: HQMServerPlayerIndex,
+        team: HQMTeam,
+        skater: &HQMSkater,
+        own_net: Point3<f32>,
+        opponent_net: Point3<f32>,
+    ) -> HQMPlayerInput {
+        self.cooldown = self.cooldown.saturating_sub(1);
+        let pos = skater.body.pos;
+        let puck = server
+            .world
+            .objects
+            .pucks()
+            .map(|(_, puck)| (puck.body.pos, puck.body.linear_velocity))
+            .min_by(|a, b| {
+                let a = (a.0 - skater.stick_pos).norm();
+                let b = (b.0 - skater.stick_pos).norm();
+                a.total_cmp(&b)
+            });
+        let (puck_pos, puck_velocity) = match puck {
+            Some(puck) => puck,
+            None => {
+                self.shot = None;
+                let spot = own_net + (center_of(server) - own_net) * 0.25;
+                return input(skate_to(skater, &spot, true), Vector2::zeros());
+            }
+        };
+
+        if let Some(shot) = &mut self.shot {
+            let direction = flat(shot.target - puck_pos).normalize();
+            let target = shot.target;
+            shot.steps += 1;
+            let stick = match shot.phase {
+                HQMBotShotPhase::Pull => {
+                    if shot.steps >= PULL_STEPS {
+                        shot.phase = HQMBotShotPhase::Push;
+                        shot.steps = 0;
+                    }
+                    stick_towards(skater, &(puck_pos - direction * 0.4))
+                }
+                HQMBotShotPhase::Push => {
+                    if shot.steps >= PUSH_STEPS {
+                        self.shot = None;
+                        self.cooldown = SHOT_COOLDOWN;
+                    }
+                    stick_towards(skater, &(puck_pos + direction * 1.5))
+                }
+            };
+            return input(skate_to(skater, &target, false), stick);
+        }
+
+        let has_puck = self.cooldown == 0
+            && (puck_pos - skater.stick_pos).norm() < PUCK_CONTROL_DISTANCE
+            && puck_pos.y < 0.5;
+        if has_puck {
+            let to_net = flat(opponent_net - pos);
+            let forward = flat(skater.body.rot * -Vector3::z());
+            // Nets can only be scored on from the side facing the center of the rink
+            let in_front = (pos - opponent_net).dot(&(center_of(server) - opponent_net)) > 0.0;
+            // Shoot from further out when an opponent comes to take the puck
+            let (distance, angle) = if view.is_open(team, &pos) {
+                (SHOOTING_DISTANCE, 0.6)
+            } else {
+                (SHOOTING_DISTANCE * 1.5, 1.0)
+            };
+            if in_front
+                && to_net.norm() < distance
+                && angle_between(&forward, &to_net).abs() < angle
+            {
+                // Aim off the middle of the net, changing sides from shot to shot
+                let side = if server.game_step % 200 < 100 {
+                    0.5
+                } else {
+                    -0.5
+                };
+                let target = opponent_net + Vector3::new(side, 0.3, 0.0);
+                self.start_shot(target);
+            } else if !view.is_open(team, &pos) {
+                // Pass to a team mate when an opponent comes to take the puck
+                if let Some(target) = pass_target(view, player_index, team, &pos, &opponent_net) {
+                    self.start_shot(target);
+                }
+            }
+            // Carry the puck towards the net, with the blade pushing it from behind. Skaters
+            // head for the slot first, and turn to the net when they get close
+            let spot = if in_front && to_net.norm() < SHOOTING_DISTANCE * 1.5 {
+                opponent_net
+            } else {
+                opponent_net + (center_of(server) - opponent_net) * 0.2
+            };
+            let blade = puck_pos - flat(spot - puck_pos).normalize() * 0.25;
+            return input(
+                skate_to(skater, &spot, false),
+                stick_towards(skater, &blade),
+            );
+        }
+
+        // The skater of each team closest to the puck goes for it
+        let closest_team_mate = view.closest_to(&puck_pos, Some(team));
+        if matches!(closest_team_mate, Some((i, _, _)) if i == player_index) {
+            // Skate to where the puck is going, coming from the side away from the net to
+            // face the net with the puck
+            let lead = puck_pos + puck_velocity * 20.0;
+            let target = if flat(lead - pos).norm() > 3.0 {
+                lead - flat(opponent_net - lead).normalize() * 1.5
+            } else {
+                lead
+            };
+            return input(
+                skate_to(skater, &target, false),
+                stick_towards(skater, &puck_pos),
+            );
+        }
+        let spot = match view.closest_to(&puck_pos, None) {
+            // Support the attack in the slot
+            Some((_, t, _)) if t == team => {
+                let side = if player_index.0 & 1 == 0 { 4.0 } else { -4.0 };
+                opponent_net
+                    + (center_of(server) - opponent_net).normalize() * 8.0
+                    + Vector3::new(side, 0.0, 0.0)
+            }
+            // Stay between the puck and the own net
+            _ => own_net + (puck_pos - own_net) * 0.4,
+        };
+        let stick = if flat(puck_pos - pos).norm() < 3.0 {
+            stick_towards(skater, &puck_pos)
+        } else {
+            Vector2::zeros()
+        };
+        input(skate_to(skater, &spot, true), stick)
+    }
+
+    /// Skates backwards for a while if the bot has been trying to skate without moving, when
+    /// it is caught in a pile of skaters or pushing against the boards
+    fn unstick(&mut self, skater: &HQMSkater, input: HQMPlayerInput) -> HQMPlayerInput {
+        if self.back_off > 0 {
+            self.back_off -= 1;
+            return HQMPlayerInput {
+                fwbw: -1.0,
+                ..input
+            };
+        }
+        let pos = skater.body.pos;
+        self.stuck = match self.stuck {
+            // Staying within a meter counts as not moving, skaters pushing against each other
+            // are never quite still
+            Some((stuck_pos, steps)) if input.fwbw > 0.0 && (pos - stuck_pos).norm() < 1.0 => {
+                if steps >= STUCK_STEPS {
+                    self.back_off = BACK_OFF_STEPS;
+                    self.shot = None;
+                    None
+                } else {
+                    Some((stuck_pos, steps + 1))
+                }
+            }
+            _ if input.fwbw > 0.0 => Some((pos, 0)),
+            _ => None,
+        };
+        input
+    }
+
+    fn start_shot(&mut self, target: Point3<f32>) {
+        self.shot = Some(HQMBotShot {
+            phase: HQMBotShotPhase::Pull,
+            target,
+            steps: 0,
+        });
+    }
+}
+
+/// An open team mate who is closer to the net, with the spot ahead of them to pass to
+fn pass_target(
+    view: &HQMBotView,
+    player_index: HQMServerPlayerIndex,
+    team: HQMTeam,
+    pos: &Point3<f32>,
+    opponent_net: &Point3<f32>,
+) -> Option<Point3<f32>> {
+    let own_distance = flat(opponent_net - pos).norm();
+    view.skaters
+        .iter()
+        .filter(|(i, t, _)| *i != player_index && *t == team)
+        .filter(|(_, _, skater_pos)| {
+            let distance = flat(skater_pos - pos).norm();
+            flat(opponent_net - skater_pos).norm() < own_distance - PASS_GAIN
+                && distance < MAX_PASS_DISTANCE
+                && view.is_open(team, skater_pos)
+        })
+        .map(|(_, _, skater_pos)| *skater_pos)
+        .min_by(|a, b| {
+            let a = flat(opponent_net - a).norm();
+            let b = flat(opponent_net - b).norm();
+            a.total_cmp(&b)
+        })
+}
+
+fn flat(v: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(v.x, 0.0, v.z)
+}
+
+fn center_of(server: &HQMServer) -> Point3<f32> {
+    server.world.rink.center_ice()
+}
+
+fn net_center(server: &HQMServer, rink_end: HQMTeam) -> Point3<f32> {
+    let rink = &server.world.rink;
+    let net: &HQMRinkNet = match rink_end {
+        HQMTeam::Red => &rink.red_net,
+        HQMTeam::Blue => &rink.blue_net,
+    };
+    nalgebra::center(&net.left_post, &net.right_post)
+}
+
+/// Angle from forward to direction on the ice, positive to the right
+fn angle_between(forward: &Vector3<f32>, direction: &Vector3<f32>) -> f32 {
+    let right = Vector3::new(-forward.z, 0.0, forward.x);
+    direction.dot(&right).atan2(direction.dot(forward))
+}
+
+fn input((turn, fwbw): (f32, f32), stick: Vector2<f32>) -> HQMPlayerInput {
+    HQMPlayerInput {
+        turn,
+        fwbw,
+        stick,
+        ..Default::default()
+    }
+}
+
+/// Turn and forward input to skate towards a spot, slowing down to stop there if stop is set
+fn skate_to(skater: &HQMSkater, target: &Point3<f32>, stop: bool) -> (f32, f32) {
+    let forward = flat(skater.body.rot * -Vector3::z());
+    let diff = flat(target - skater.body.pos);
+    let distance = diff.norm();
+    let angle = angle_between(&forward, &diff);
+    // Turning keeps going for a while, so it is damped by how fast the skater already turns
+    let turn = (3.0 * angle - 40.0 * skater.body.angular_velocity.y).clamp(-1.0, 1.0);
+    let speed = skater.body.linear_velocity.dot(&forward);
+    // Skaters turn in wide circles at full speed, so they slow down for spots beside or
+    // behind them, and for spots to stop at
+    let max_speed = if stop {
+        (0.001 * (distance - 0.5).max(0.0)).sqrt()
+    } else if angle.abs() > 0.8 {
+        0.01 + 0.002 * distance
+    } else {
+        f32::MAX
+    };
+    let fwbw = if speed > max_speed {
+        -1.0
+    } else if stop && distance < 0.5 {
+        0.0
+    } else if angle.abs() < 1.2 {
+        1.0
+    } else {
+        0.25
+    };
+    (turn, fwbw)
+}
+
+/// Stick input that puts the blade at a spot on the ice, as far as the stick reaches
+fn stick_towards(skater: &HQMSkater, target: &Point3<f32>) -> Vector2<f32> {
+    let mul = match skater.hand {
+        HQMSkaterHand::Right => 1.0,
+        HQMSkaterHand::Left => -1.0,
+    };
+    let stick_top = skater.body.pos + skater.body.rot * Vector3::new(-0.375 * mul, 0.5, -0.125);
+    let local = skater.body.rot.inverse() * (target - stick_top);
+    let azimuth = local.x.atan2(-local.z).clamp(-FRAC_PI_2, FRAC_PI_2);
+    let reach = (local.x.powi(2) + local.z.powi(2)).sqrt();
+    // The stick points down by a quarter turn more than the inclination
+    let down = (reach / STICK_LENGTH).clamp(0.0, 1.0).acos();
+    let inclination = (down - FRAC_PI_4).clamp(-5.0 * PI / 16.0, FRAC_PI_8);
+    Vector2::new(azimuth, inclination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::angle_between;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn angles_to_the_right_are_positive() {
+        let forward = Vector3::new(0.0, 0.0, -1.0);
+        let right = Vector3::new(1.0, 0.0, 0.0);
+        let left = Vector3::new(-1.0, 0.0, 0.0);
+        assert!((angle_between(&forward, &right) - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!((angle_between(&forward, &left) + std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert!(angle_between(&forward, &forward).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::hqm_object_selection::{HQMObjectSlots, NETWORK_OBJECT_SLOTS};
+use crate::hqm_parse::{HQMMessageWriter, HQMObjectPacket};
+
+/// Packet type of world checksums, both for requests from clients and the checksums the server
+/// sends them
+pub(crate) const WORLD_CHECKSUM: u8 = 0x41;
+
+/// Packet type of the checksums clients computed themselves
+pub(crate) const WORLD_CHECKSUM_REPORT: u8 = 0x42;
+
+/// Version of the world checksum packets. Clients send the version they understand in their
+/// request
+pub(crate) const WORLD_CHECKSUM_VERSION: u32 = 1;
+
+/// Steps between checksums, one second
+pub(crate) const WORLD_CHECKSUM_INTERVAL: u32 = 100;
+
+/// Number of sent checksums that are kept to compare reports with, so that reports can arrive
+/// up to ten seconds late
+const KEPT_CHECKSUMS: usize = 10;
+
+/// Least time between two logged mismatches of the same client
+const MISMATCH_WARNING_INTERVAL: Duration = Duration::from_secs(10);
+
+const FNV_OFFSET: u32 = 0x811c9dc5;
+const FNV_PRIME: u32 = 0x01000193;
+
+/// FNV-1a hash of the object state a client was sent for a step, slot by slot, as the numbers
+/// in the update packet. An empty slot is a 0 byte, a puck is a 1 byte followed by its position
+/// and rotation, and a skater is a 2 byte followed by its position, rotation, stick position,
+/// stick rotation, head rotation and body rotation, all as 32-bit little-endian numbers
+pub(crate) fn world_checksum(packets: &[HQMObjectPacket], slots: Option<&HQMObjectSlots>) -> u32 {
+    let mut hash = FNV_OFFSET;
+    let mut add = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash ^= u32::from(byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    };
+    for slot in 0..NETWORK_OBJECT_SLOTS {
+        let object_index = match slots {
+            Some(slots) => slots[slot],
+            None => Some(slot),
+        };
+        let packet = object_index.and_then(|object_index| packets.get(object_index));
+        let numbers = match packet {
+            Some(HQMObjectPacket::Puck(puck)) => {
+                add(&[1]);
+                vec![puck.pos.0, puck.pos.1, puck.pos.2, puck.rot.0, puck.rot.1]
+            }
+            Some(HQMObjectPacket::Skater(skater)) => {
+                add(&[2]);
+                vec![
+                    skater.pos.0,
+                    skater.pos.1,
+                    skater.pos.2,
+                    skater.rot.0,
+                    skater.rot.1,
+                    skater.stick_pos.0,
+                    skater.stick_pos.1,
+                    skater.stick_pos.2,
+                    skater.stick_rot.0,
+                    skater.stick_rot.1,
+                    skater.head_rot,
+                    skater.body_rot,
+                ]
+            }
+            Some(HQMObjectPacket::None) | None => {
+                add(&[0]);
+                vec![]
+            }
+        };
+        for number in numbers {
+            add(&number.to_le_bytes());
+        }
+    }
+    hash
+}
+
+/// The checksums sent to a client that asked for them, to compare with the ones it reports back
+pub(crate) struct HQMChecksums {
+    /// Step and checksum, newest first
+    sent: VecDeque<(u32, u32)>,
+    pub(crate) mismatches: u32,
+    last_mismatch_warning: Option<Instant>,
+}
+
+impl HQMChecksums {
+    pub(crate) fn new() -> Self {
+        HQMChecksums {
+            sent: VecDeque::with_capacity(KEPT_CHECKSUMS),
+            mismatches: 0,
+            last_mismatch_warning: None,
+        }
+    }
+
+    pub(crate) fn record_sent(&mut self, game_step: u32, checksum: u32) {
+        self.sent.truncate(KEPT_CHECKSUMS - 1);
+        self.sent.push_front((game_step, checksum));
+    }
+
+    /// Forgets the sent checksums when a new game starts, because steps start over
+    pub(crate) fn clear(&mut self) {
+        self.sent.clear();
+    }
+
+    /// Compares a checksum the client computed with the one it was sent for the same step.
+    /// Returns the sent checksum if they differ, and None if they match or the step is unknown.
+    /// Each step is only checked once, so resending a report does nothing
+    pub(crate) fn check(&mut self, game_step: u32, checksum: u32) -> Option<u32> {
+        let position = self.sent.iter().position(|(step, _)| *step == game_step)?;
+        let (_, expected) = self.sent.remove(position)?;
+        if expected == checksum {
+            None
+        } else {
+            self.mismatches += 1;
+            Some(expected)
+        }
+    }
+
+    /// Whether a mismatch should be logged, at most once every ten seconds per client
+    pub(crate) fn should_warn(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last_mismatch_warning {
+            if now.duration_since(last) < MISMATCH_WARNING_INTERVAL {
+                return false;
+            }
+        }
+        self.last_mismatch_warning = Some(now);
+        true
+    }
+}
+
+/// Writes the body of a world checksum packet
+pub(crate) fn write_world_checksum(writer: &mut HQMMessageWriter, game_step: u32, checksum: u32) {
+    writer.write_u32_aligned(WORLD_CHECKSUM_VERSION);
+    writer.write_u32_aligned(game_step);
+    writer.write_u32_aligned(checksum);
+}
@@ -0,0 +1,2207 @@
+use ini::{Ini, Properties};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use tracing::{info, warn, Level};
+use tracing_appender::rolling::Rotation;
+
+use crate::hqm_practice_mode::HQMPracticeModeConfiguration;
+use migo_hqm_server::hqm_alerts::HQMAlertConfig;
+use migo_hqm_server::hqm_boards::{MAX_BOARDS, MAX_BOARD_TEXT_LENGTH};
+use migo_hqm_server::hqm_console::{HQMConsoleConfiguration, HQMConsoleTlsConfiguration};
+use migo_hqm_server::hqm_game::{
+    objects_fit, HQMPhysicsProfile, HQMRinkConfiguration, HQMRinkCreases, MAX_PUCKS,
+};
+use migo_hqm_server::hqm_game_log::HQMGameLogConfiguration;
+use migo_hqm_server::hqm_master_server::HQMMasterServer;
+use migo_hqm_server::hqm_match_util::{
+    HQMGoalReplayView, HQMGoalStreakReset, HQMIcingConfiguration, HQMMatchConfiguration,
+    HQMOffsideConfiguration, HQMOffsideLineConfiguration, HQMRuleset, HQMSpawnPoint,
+    HQMTwoLinePassConfiguration, HQMVoteVoters,
+};
+use migo_hqm_server::hqm_network_quality::HQMNetworkLimits;
+use migo_hqm_server::hqm_server::{
+    HQMInstantReplay, HQMMovementCheck, HQMServer, HQMServerPlayerIndex, ReplaySaving,
+    MAX_SPECTATOR_DELAY,
+};
+
+pub(crate) fn is_true(s: &str) -> bool {
+    s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("on")
+}
+
+pub(crate) fn get_optional<U, F: FnOnce(&str) -> U>(
+    section: Option<&Properties>,
+    property: &str,
+    default: U,
+    f: F,
+) -> U {
+    section.and_then(|x| x.get(property)).map_or(default, f)
+}
+
+pub(crate) fn load_welcome(server_section: &Properties) -> Vec<String> {
+    let welcome = server_section.get("welcome").unwrap_or("");
+
+    welcome
+        .lines()
+        .map(String::from)
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+pub(crate) fn load_boards(server_section: &Properties) -> Vec<String> {
+    let boards = server_section.get("boards").unwrap_or("");
+
+    boards
+        .lines()
+        .map(String::from)
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+pub(crate) fn load_board_rotation(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "board_rotation", 30, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_instant_replay(server_section: &Properties) -> HQMInstantReplay {
+    match server_section.get("instant_replay") {
+        Some("off") => HQMInstantReplay::Off,
+        Some("all") => HQMInstantReplay::All,
+        _ => HQMInstantReplay::Admins,
+    }
+}
+
+pub(crate) fn load_movement_check(server_section: &Properties) -> HQMMovementCheck {
+    match server_section.get("movement_check") {
+        Some("off") => HQMMovementCheck::Off,
+        Some("log") => HQMMovementCheck::Log,
+        Some("kick") => HQMMovementCheck::Kick,
+        _ => HQMMovementCheck::Flag,
+    }
+}
+
+pub(crate) fn load_replay_stream_rate(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "replay_stream_rate", 0, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_alerts(server_section: &Properties) -> HQMAlertConfig {
+    let server_section = Some(server_section);
+    HQMAlertConfig {
+        ping: get_optional(server_section, "alert_ping", 300, |x| {
+            x.parse::<u32>().unwrap()
+        }),
+        offsides: get_optional(server_section, "alert_offsides", 3, |x| {
+            x.parse::<u32>().unwrap()
+        }),
+        disk_space: get_optional(server_section, "alert_disk_space", 1024, |x| {
+            x.parse::<u32>().unwrap()
+        }),
+    }
+}
+
+pub(crate) fn load_network_limits(server_section: &Properties) -> HQMNetworkLimits {
+    let server_section = Some(server_section);
+    HQMNetworkLimits {
+        ping: get_optional(server_section, "max_ping", 0, |x| x.parse::<u32>().unwrap()),
+        jitter: get_optional(server_section, "max_jitter", 0, |x| {
+            x.parse::<u32>().unwrap()
+        }),
+        kick: server_section.and_then(|x| x.get("network_limit_action")) == Some("kick"),
+    }
+}
+
+pub(crate) fn load_log_name(server_section: &Properties) -> String {
+    let server_name = server_section.get("name").unwrap_or_default();
+    server_section
+        .get("log_name")
+        .map_or(format!("{}.log", server_name), String::from)
+}
+
+pub(crate) fn load_log_level(server_section: &Properties) -> Level {
+    get_optional(Some(server_section), "log_level", Level::INFO, |x| {
+        x.parse::<Level>().unwrap()
+    })
+}
+
+pub(crate) fn load_log_json(server_section: &Properties) -> bool {
+    get_optional(Some(server_section), "log_format", false, |x| x == "json")
+}
+
+pub(crate) fn load_game_log_config(server_section: &Properties) -> Option<HQMGameLogConfiguration> {
+    if !get_optional(Some(server_section), "game_log", false, is_true) {
+        return None;
+    }
+    let server_name = server_section.get("name").unwrap_or_default();
+    let name = server_section
+        .get("game_log_name")
+        .map_or(format!("{}.games.log", server_name), String::from);
+    let rotation = get_optional(
+        Some(server_section),
+        "game_log_rotation",
+        Rotation::DAILY,
+        |x| match x {
+            "hourly" => Rotation::HOURLY,
+            "never" => Rotation::NEVER,
+            _ => Rotation::DAILY,
+        },
+    );
+    let max_files = get_optional(Some(server_section), "game_log_max_files", 0, |x| {
+        x.parse::<usize>().unwrap()
+    });
+    Some(HQMGameLogConfiguration {
+        name,
+        rotation,
+        max_files: if max_files > 0 { Some(max_files) } else { None },
+    })
+}
+
+pub(crate) fn load_replay_file_saving(server_section: &Properties) -> ReplaySaving {
+    let directory = server_section
+        .get("replay_directory")
+        .map_or(PathBuf::from("replays"), PathBuf::from);
+    let max_files = get_optional(Some(server_section), "replay_max_files", 0, |x| {
+        x.parse::<usize>().unwrap()
+    });
+    ReplaySaving::File {
+        directory,
+        max_files,
+    }
+}
+
+/// Entries of the playlist, as the path of the entry's configuration file and its number of games
+pub(crate) fn load_playlist(server_section: &Properties) -> Vec<(String, u32)> {
+    server_section.get("playlist").map_or(vec![], |playlist| {
+        playlist
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.rsplit_once('*') {
+                Some((path, games)) => (path.trim().to_string(), games.trim().parse().unwrap()),
+                None => (entry.to_string(), 1),
+            })
+            .collect()
+    })
+}
+
+/// The configuration of a playlist entry, which is the entry's file applied over the configuration
+/// of the server
+pub(crate) fn load_playlist_config(conf: &Ini, path: &str) -> Result<Ini, ini::Error> {
+    let entry = Ini::load_from_file(path)?;
+    let mut conf = conf.clone();
+    for (section, properties) in entry.iter() {
+        for (key, value) in properties.iter() {
+            conf.with_section(section).set(key, value);
+        }
+    }
+    Ok(conf)
+}
+
+pub(crate) fn load_chat_history(server_section: &Properties) -> usize {
+    get_optional(Some(server_section), "chat_history", 5, |x| {
+        x.parse::<usize>().unwrap()
+    })
+}
+
+pub(crate) fn load_spectator_slots(server_section: &Properties) -> usize {
+    get_optional(Some(server_section), "spectator_slots", 0, |x| {
+        x.parse::<usize>().unwrap()
+    })
+}
+
+pub(crate) fn load_spectator_delay(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "spectator_delay", 0, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_afk_time(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "afk_time", 0, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_compression(server_section: &Properties) -> bool {
+    get_optional(Some(server_section), "compression", false, is_true)
+}
+
+pub(crate) fn load_join_challenge(server_section: &Properties) -> bool {
+    get_optional(Some(server_section), "join_challenge", true, is_true)
+}
+
+pub(crate) fn load_debug_commands(server_section: &Properties) -> bool {
+    get_optional(Some(server_section), "debug_commands", false, is_true)
+}
+
+pub(crate) fn load_max_packet_size(server_section: &Properties) -> usize {
+    get_optional(Some(server_section), "max_packet_size", 1400, |x| {
+        x.parse::<usize>().unwrap()
+    })
+}
+
+pub(crate) fn load_ban_file(server_section: &Properties) -> Option<PathBuf> {
+    server_section.get("ban_file").map(PathBuf::from)
+}
+
+pub(crate) fn load_snapshot_file(server_section: &Properties) -> Option<PathBuf> {
+    server_section.get("snapshot_file").map(PathBuf::from)
+}
+
+pub(crate) fn load_preferences_file(server_section: &Properties) -> Option<PathBuf> {
+    server_section.get("preferences_file").map(PathBuf::from)
+}
+
+pub(crate) fn load_savegame_directory(server_section: &Properties) -> PathBuf {
+    PathBuf::from(
+        server_section
+            .get("savegame_directory")
+            .unwrap_or("savegames"),
+    )
+}
+
+pub(crate) fn load_stats_database(server_section: &Properties) -> Option<PathBuf> {
+    server_section.get("stats_database").map(PathBuf::from)
+}
+
+pub(crate) fn load_results_directory(server_section: &Properties) -> Option<PathBuf> {
+    server_section.get("results_directory").map(PathBuf::from)
+}
+
+pub(crate) fn load_highlights_directory(server_section: &Properties) -> Option<PathBuf> {
+    server_section
+        .get("highlights_directory")
+        .map(PathBuf::from)
+}
+
+pub(crate) fn load_autosave_interval(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "autosave_interval", 60, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_input_toggle_limit(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "input_toggle_limit", 0, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_restart_after_games(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "restart_after_games", 0, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+/// Master servers to notify while the server is public. `public_address` is still accepted
+/// as a single master server, and regional master servers are listed in the `MasterServers` section
+pub(crate) fn load_master_servers(conf: &Ini) -> Vec<HQMMasterServer> {
+    let server_section = conf.section(Some("Server"));
+    let split = |s: &str| {
+        s.split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    let mut master_servers = vec![];
+    for key in ["public_address", "master_servers"] {
+        for address in get_optional(server_section, key, vec![], split) {
+            master_servers.push(HQMMasterServer::new(None, &address));
+        }
+    }
+    if let Some(regions) = conf.section(Some("MasterServers")) {
+        for (region, addresses) in regions.iter() {
+            for address in split(addresses) {
+                master_servers.push(HQMMasterServer::new(Some(region), &address));
+            }
+        }
+    }
+    if master_servers.is_empty() {
+        master_servers.push(HQMMasterServer::new(
+            None,
+            "https://sam2.github.io/HQMMasterServerEndpoint/",
+        ));
+    }
+    master_servers
+}
+
+pub(crate) fn load_packet_rate_limit(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "packet_rate_limit", 500, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_console_config(server_section: &Properties) -> Option<HQMConsoleConfiguration> {
+    let port = server_section
+        .get("console_port")
+        .map(|x| x.parse::<u16>().unwrap());
+    let unix_socket = server_section.get("console_socket").map(PathBuf::from);
+    if port.is_none() && unix_socket.is_none() {
+        return None;
+    }
+    let ip = get_optional(
+        Some(server_section),
+        "console_address",
+        IpAddr::from([127, 0, 0, 1]),
+        |x| x.parse::<IpAddr>().unwrap(),
+    );
+    let password = server_section.get("console_password")?.to_string();
+    let tls = match (
+        server_section.get("console_tls_certificate"),
+        server_section.get("console_tls_key"),
+    ) {
+        (Some(certificate), Some(key)) => Some(HQMConsoleTlsConfiguration {
+            certificate: PathBuf::from(certificate),
+            key: PathBuf::from(key),
+        }),
+        _ => None,
+    };
+    Some(HQMConsoleConfiguration {
+        address: port.map(|port| SocketAddr::new(ip, port)),
+        unix_socket,
+        password,
+        tls,
+    })
+}
+
+pub(crate) fn load_event_feed_address(server_section: &Properties) -> Option<SocketAddr> {
+    let port = server_section
+        .get("event_feed_port")?
+        .parse::<u16>()
+        .unwrap();
+    let ip = get_optional(
+        Some(server_section),
+        "event_feed_address",
+        IpAddr::from([127, 0, 0, 1]),
+        |x| x.parse::<IpAddr>().unwrap(),
+    );
+    Some(SocketAddr::new(ip, port))
+}
+
+pub(crate) fn load_stats_page_address(server_section: &Properties) -> Option<SocketAddr> {
+    let port = server_section
+        .get("stats_page_port")?
+        .parse::<u16>()
+        .unwrap();
+    let ip = get_optional(
+        Some(server_section),
+        "stats_page_address",
+        IpAddr::from([127, 0, 0, 1]),
+        |x| x.parse::<IpAddr>().unwrap(),
+    );
+    Some(SocketAddr::new(ip, port))
+}
+
+pub(crate) fn load_webhooks(server_section: &Properties) -> Vec<String> {
+    get_optional(Some(server_section), "webhooks", vec![], |x| {
+        x.split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+pub(crate) fn load_webhook_retries(server_section: &Properties) -> u32 {
+    get_optional(Some(server_section), "webhook_retries", 5, |x| {
+        x.parse::<u32>().unwrap()
+    })
+}
+
+pub(crate) fn load_physics_config(conf: &Ini) -> HQMPhysicsProfile {
+    let game_section = conf.section(Some("Game"));
+    let physics_section = conf.section(Some("Physics"));
+
+    let profile = get_optional(
+        physics_section,
+        "profile",
+        HQMPhysicsProfile::classic(),
+        |x| HQMPhysicsProfile::preset(x).unwrap(),
+    );
+
+    let limit_jump_speed = get_optional(game_section, "limit_jump_speed", false, is_true);
+
+    // Accelerations are configured in meters per second squared, speeds in meters per second
+    let acceleration = |key: &str, default: f32| {
+        get_optional(physics_section, key, default, |x| {
+            x.parse::<f32>().unwrap() / 10000.0
+        })
+    };
+    let speed = |key: &str, default: f32| {
+        get_optional(physics_section, key, default, |x| {
+            x.parse::<f32>().unwrap() / 100.0
+        })
+    };
+
+    HQMPhysicsProfile {
+        gravity: acceleration("gravity", profile.gravity),
+        limit_jump_speed,
+        player_acceleration: acceleration("player_acceleration", profile.player_acceleration),
+        player_deceleration: acceleration("player_deceleration", profile.player_deceleration),
+        max_player_speed: speed("max_player_speed", profile.max_player_speed),
+        puck_rink_friction: get_optional(
+            physics_section,
+            "puck_rink_friction",
+            profile.puck_rink_friction,
+            |x| x.parse::<f32>().unwrap(),
+        ),
+        puck_rink_bounce: get_optional(
+            physics_section,
+            "puck_rink_bounce",
+            profile.puck_rink_bounce,
+            |x| x.parse::<f32>().unwrap(),
+        ),
+        stick_puck_force: get_optional(
+            physics_section,
+            "stick_puck_force",
+            profile.stick_puck_force,
+            |x| x.parse::<f32>().unwrap(),
+        ),
+        player_turning: acceleration("player_turning", profile.player_turning),
+        player_shift_acceleration: acceleration(
+            "player_shift_acceleration",
+            profile.player_shift_acceleration,
+        ),
+        max_player_shift_speed: speed("max_player_shift_speed", profile.max_player_shift_speed),
+        player_shift_turning: acceleration("player_shift_turning", profile.player_shift_turning),
+        jump_speed: speed("jump_speed", profile.jump_speed),
+        puck_air_drag: get_optional(
+            physics_section,
+            "puck_air_drag",
+            profile.puck_air_drag,
+            |x| x.parse::<f32>().unwrap(),
+        ),
+    }
+}
+
+/// Reads a rink file, the JSON form of the [Rink] section with the placement of the nets, the
+/// center line and the faceoff dots
+fn read_rink_file(path: &str) -> Result<HQMRinkConfiguration, String> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read rink file {}: {}", path, e))?;
+    serde_json::from_str(&data).map_err(|e| format!("Rink file {} is invalid: {}", path, e))
+}
+
+/// The rink of the rink file if there is one, with the keys of the [Rink] section on top
+pub(crate) fn load_rink_config(conf: &Ini) -> HQMRinkConfiguration {
+    let rink_section = conf.section(Some("Rink"));
+    let default = match rink_section.and_then(|x| x.get("file")) {
+        Some(path) => read_rink_file(path).unwrap(),
+        None => HQMRinkConfiguration::default(),
+    };
+    let meters = |key: &str, default: f32| {
+        get_optional(rink_section, key, default, |x| x.parse::<f32>().unwrap())
+    };
+
+    HQMRinkConfiguration {
+        width: meters("width", default.width),
+        length: meters("length", default.length),
+        corner_radius: meters("corner_radius", default.corner_radius),
+        goal_width: meters("goal_width", default.goal_width),
+        goal_height: meters("goal_height", default.goal_height),
+        goal_line_distance: meters("goal_line", default.goal_line_distance),
+        blue_line_distance: meters("blue_line", default.blue_line_distance),
+        boards_height: get_optional(rink_section, "boards_height", default.boards_height, |x| {
+            Some(x.parse::<f32>().unwrap())
+        }),
+        ..default
+    }
+}
+
+pub(crate) fn load_spawn_point(conf: &Ini) -> HQMSpawnPoint {
+    let game_section = conf.section(Some("Game"));
+
+    get_optional(game_section, "spawn", HQMSpawnPoint::Center, |x| match x {
+        "bench" => HQMSpawnPoint::Bench,
+        _ => HQMSpawnPoint::Center,
+    })
+}
+
+/// The settings of practice mode, from its [Game] section
+pub(crate) fn load_practice_config(
+    conf: &Ini,
+    physics_config: HQMPhysicsProfile,
+) -> HQMPracticeModeConfiguration {
+    let game_section = conf.section(Some("Game"));
+
+    HQMPracticeModeConfiguration {
+        physics_config,
+        pucks: get_optional(game_section, "warmup_pucks", 1, |x| {
+            x.parse::<usize>().unwrap()
+        }),
+        practice_pucks: get_optional(game_section, "practice_pucks", 5, |x| {
+            x.parse::<usize>().unwrap()
+        }),
+        spawn_point: load_spawn_point(conf),
+        spawn_point_offset: get_optional(game_section, "spawn_offset", 2.75f32, |x| {
+            x.parse::<f32>().unwrap()
+        }),
+        spawn_player_altitude: get_optional(game_section, "spawn_player_altitude", 1.5f32, |x| {
+            x.parse::<f32>().unwrap()
+        }),
+        spawn_keep_stick_position: get_optional(
+            game_section,
+            "spawn_player_keep_stick",
+            false,
+            is_true,
+        ),
+        use_mph: get_optional(game_section, "use_mph", false, is_true),
+    }
+}
+
+pub(crate) fn load_team_max(conf: &Ini) -> Option<usize> {
+    conf.section(Some("Server"))
+        .and_then(|x| x.get("team_max"))
+        .and_then(|x| x.parse::<usize>().ok())
+}
+
+/// The icing rule of a setting or command, None if it's not one
+pub(crate) fn icing_by_name(x: &str) -> Option<HQMIcingConfiguration> {
+    match x {
+        "on" | "touch" => Some(HQMIcingConfiguration::Touch),
+        "notouch" => Some(HQMIcingConfiguration::NoTouch),
+        "off" => Some(HQMIcingConfiguration::Off),
+        _ => None,
+    }
+}
+
+/// The offside rule of a setting or command, None if it's not one
+pub(crate) fn offside_by_name(x: &str) -> Option<HQMOffsideConfiguration> {
+    match x {
+        "on" | "delayed" => Some(HQMOffsideConfiguration::Delayed),
+        "immediate" | "imm" => Some(HQMOffsideConfiguration::Immediate),
+        "off" => Some(HQMOffsideConfiguration::Off),
+        _ => None,
+    }
+}
+
+fn parse_icing(x: &str) -> HQMIcingConfiguration {
+    icing_by_name(x).unwrap_or(HQMIcingConfiguration::Off)
+}
+
+fn parse_offside(x: &str) -> HQMOffsideConfiguration {
+    offside_by_name(x).unwrap_or(HQMOffsideConfiguration::Off)
+}
+
+fn parse_offside_line(x: &str) -> HQMOffsideLineConfiguration {
+    match x {
+        "center" => HQMOffsideLineConfiguration::Center,
+        _ => HQMOffsideLineConfiguration::OffensiveBlue,
+    }
+}
+
+fn parse_twoline_pass(x: &str) -> HQMTwoLinePassConfiguration {
+    match x {
+        "on" => HQMTwoLinePassConfiguration::On,
+        "forward" => HQMTwoLinePassConfiguration::Forward,
+        "double" | "both" => HQMTwoLinePassConfiguration::Double,
+        "blue" | "three" | "threeline" => HQMTwoLinePassConfiguration::ThreeLine,
+        _ => HQMTwoLinePassConfiguration::Off,
+    }
+}
+
+/// Rule presets from the sections named "Ruleset <name>", in the order of the file
+fn load_rulesets(conf: &Ini) -> Vec<HQMRuleset> {
+    conf.iter()
+        .filter_map(|(section, properties)| {
+            let name = section?.strip_prefix(RULESET_SECTION)?.trim();
+            let get = |key| properties.get(key);
+            Some(HQMRuleset {
+                name: name.to_string(),
+                icing: get("icing").map(parse_icing),
+                offside: get("offside").map(parse_offside),
+                offside_line: get("offsideline").map(parse_offside_line),
+                twoline_pass: get("twolinepass").map(parse_twoline_pass),
+                mercy: get("mercy").and_then(|x| x.parse::<u32>().ok()),
+                first_to: get("first").and_then(|x| x.parse::<u32>().ok()),
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn load_match_config(
+    conf: &Ini,
+    physics_config: HQMPhysicsProfile,
+) -> HQMMatchConfiguration {
+    let game_section = conf.section(Some("Game"));
+
+    let periods = get_optional(game_section, "periods", 3, |x| x.parse::<u32>().unwrap());
+
+    let rules_time_period = get_optional(game_section, "time_period", 300, |x| {
+        x.parse::<u32>().unwrap()
+    });
+    let period_lengths = get_optional(game_section, "time_periods", vec![], |x| {
+        x.split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse::<u32>().unwrap())
+            .collect()
+    });
+    let time_overtime = get_optional(game_section, "time_overtime", rules_time_period, |x| {
+        x.parse::<u32>().unwrap()
+    });
+    let running_clock = get_optional(game_section, "running_clock", false, is_true);
+    let swap_ends = get_optional(game_section, "swap_ends", false, is_true);
+    let ready_players = get_optional(game_section, "ready_players", 0, |x| {
+        x.parse::<u32>().unwrap()
+    });
+    let rules_time_warmup = get_optional(game_section, "time_warmup", 300, |x| {
+        x.parse::<u32>().unwrap()
+    });
+    let rule_time_break = get_optional(game_section, "time_break", 10, |x| {
+        x.parse::<u32>().unwrap()
+    });
+    let rule_time_intermission = get_optional(game_section, "time_intermission", 20, |x| {
+        x.parse::<u32>().unwrap()
+    });
+    let warmup_pucks = get_optional(game_section, "warmup_pucks", 1, |x| {
+        x.parse::<usize>().unwrap()
+    });
+    let warmup_practice = get_optional(game_section, "warmup_practice", false, is_true);
+    let pucks_in_play = get_optional(game_section, "pucks_in_play", 1, |x| {
+        x.parse::<usize>().unwrap()
+    });
+    let delay_of_game = get_optional(game_section, "delay_of_game", false, is_true);
+    // Configured in meters per second
+    let net_dislodge_speed = get_optional(game_section, "net_dislodge_speed", 0.0, |x| {
+        x.parse::<f32>().unwrap() / 100.0
+    });
+
+    let mercy = get_optional(game_section, "mercy", 0, |x| x.parse::<u32>().unwrap());
+    let first_to = get_optional(game_section, "first", 0, |x| x.parse::<u32>().unwrap());
+
+    let icing = get_optional(
+        game_section,
+        "icing",
+        HQMIcingConfiguration::Off,
+        parse_icing,
+    );
+
+    let offside = get_optional(
+        game_section,
+        "offside",
+        HQMOffsideConfiguration::Off,
+        parse_offside,
+    );
+
+    let offside_line = get_optional(
+        game_section,
+        "offsideline",
+        HQMOffsideLineConfiguration::OffensiveBlue,
+        parse_offside_line,
+    );
+
+    let twoline_pass = get_optional(
+        game_section,
+        "twolinepass",
+        HQMTwoLinePassConfiguration::Off,
+        parse_twoline_pass,
+    );
+
+    let spawn_point_offset = get_optional(game_section, "spawn_offset", 2.75f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let spawn_player_altitude = get_optional(game_section, "spawn_player_altitude", 1.5f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let spawn_puck_altitude = get_optional(game_section, "spawn_puck_altitude", 1.5f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let spawn_keep_stick_position =
+        get_optional(game_section, "spawn_player_keep_stick", false, is_true);
+
+    let faceoff_drop_delay = get_optional(game_section, "faceoff_drop_delay", 0f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let high_stick_height = get_optional(game_section, "high_stick_height", 0f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let hand_pass = get_optional(game_section, "hand_pass", false, is_true);
+
+    let disallow_body_goals = get_optional(game_section, "disallow_body_goals", false, is_true);
+
+    let shootout = get_optional(game_section, "shootout", 0, |x| x.parse::<u32>().unwrap());
+
+    let use_mph = get_optional(game_section, "use_mph", false, is_true);
+
+    let goal_replay = get_optional(game_section, "goal_replay", false, is_true);
+
+    let goal_replay_before = get_optional(game_section, "goal_replay_before", 6.0f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let goal_replay_after = get_optional(game_section, "goal_replay_after", 2.0f32, |x| {
+        x.parse::<f32>().unwrap()
+    });
+
+    let goal_replay_view = get_optional(
+        game_section,
+        "goal_replay_view",
+        HQMGoalReplayView::Scorer,
+        |x| match x {
+            "goalie" => HQMGoalReplayView::Goalie,
+            "puck" => HQMGoalReplayView::Puck,
+            _ => HQMGoalReplayView::Scorer,
+        },
+    );
+
+    let hints = get_optional(game_section, "hints", false, is_true);
+
+    let announce_win_probability =
+        get_optional(game_section, "announce_win_probability", false, is_true);
+
+    let goal_streak = get_optional(game_section, "goal_streak", 0, |x| {
+        x.parse::<u32>().unwrap()
+    });
+
+    let goal_streak_reset = get_optional(
+        game_section,
+        "goal_streak_reset",
+        HQMGoalStreakReset::Never,
+        |x| match x {
+            "period" => HQMGoalStreakReset::Period,
+            "faceoff" => HQMGoalStreakReset::Faceoff,
+            _ => HQMGoalStreakReset::Never,
+        },
+    );
+
+    let announce_comebacks = get_optional(game_section, "announce_comebacks", false, is_true);
+
+    let rematch_vote = get_optional(game_section, "rematch_vote", false, is_true);
+
+    let series = get_optional(game_section, "series", 0, |x| x.parse::<u32>().unwrap());
+
+    let votes = get_optional(game_section, "votes", false, is_true);
+    let vote_voters = get_optional(
+        game_section,
+        "vote_voters",
+        HQMVoteVoters::Players,
+        |x| match x {
+            "everyone" => HQMVoteVoters::Everyone,
+            _ => HQMVoteVoters::Players,
+        },
+    );
+    let vote_time = get_optional(game_section, "vote_time", 30, |x| x.parse::<u32>().unwrap());
+    let vote_threshold = get_optional(game_section, "vote_threshold", 50, |x| {
+        x.parse::<u32>().unwrap()
+    });
+
+    let default_creases = HQMRinkCreases::default();
+    let creases = HQMRinkCreases {
+        goal_radius: get_optional(
+            game_section,
+            "crease_radius",
+            default_creases.goal_radius,
+            |x| x.parse::<f32>().unwrap(),
+        ),
+        goal_depth: get_optional(
+            game_section,
+            "crease_depth",
+            default_creases.goal_depth,
+            |x| x.parse::<f32>().unwrap(),
+        ),
+    };
+
+    HQMMatchConfiguration {
+        time_period: rules_time_period,
+        period_lengths,
+        time_overtime,
+        running_clock,
+        swap_ends,
+        ready_players,
+        time_warmup: rules_time_warmup,
+        time_break: rule_time_break,
+        time_intermission: rule_time_intermission,
+        mercy,
+        first_to,
+        icing,
+        offside,
+        offside_line,
+        twoline_pass,
+        warmup_pucks,
+        warmup_practice,
+        pucks_in_play,
+        net_dislodge_speed,
+        delay_of_game,
+        use_mph,
+        goal_replay,
+        goal_replay_before: (goal_replay_before * 100.0) as u32,
+        goal_replay_after: (goal_replay_after * 100.0) as u32,
+        goal_replay_view,
+        hints,
+        announce_win_probability,
+        goal_streak,
+        goal_streak_reset,
+        announce_comebacks,
+        rematch_vote,
+        series,
+        rulesets: load_rulesets(conf),
+        votes,
+        vote_voters,
+        vote_time,
+        vote_threshold,
+        physics_config,
+        periods,
+        spawn_point_offset,
+        spawn_player_altitude,
+        spawn_puck_altitude,
+        spawn_keep_stick_position,
+        faceoff_drop_delay,
+        creases,
+        high_stick_height,
+        hand_pass,
+        disallow_body_goals,
+        shootout,
+        bots: get_optional(game_section, "bots", 0, |x| x.parse::<usize>().unwrap()),
+        rejoin_grace: get_optional(game_section, "rejoin_grace", 0, |x| {
+            x.parse::<u32>().unwrap()
+        }),
+        rejoin_keep_skater: get_optional(game_section, "rejoin_keep_skater", false, is_true),
+        auto_pause_skaters: get_optional(game_section, "auto_pause_skaters", 0, |x| {
+            x.parse::<usize>().unwrap()
+        }),
+    }
+}
+
+/// The settings of match mode that can be changed with commands while the server runs, as
+/// section, key and value in the format of the configuration file
+pub(crate) fn match_settings(
+    config: &HQMMatchConfiguration,
+    team_max: usize,
+) -> Vec<(&'static str, &'static str, String)> {
+    let icing = match config.icing {
+        HQMIcingConfiguration::Off => "off",
+        HQMIcingConfiguration::Touch => "touch",
+        HQMIcingConfiguration::NoTouch => "notouch",
+    };
+    let offside = match config.offside {
+        HQMOffsideConfiguration::Off => "off",
+        HQMOffsideConfiguration::Delayed => "delayed",
+        HQMOffsideConfiguration::Immediate => "immediate",
+    };
+    let offside_line = match config.offside_line {
+        HQMOffsideLineConfiguration::OffensiveBlue => "blue",
+        HQMOffsideLineConfiguration::Center => "center",
+    };
+    let twoline_pass = match config.twoline_pass {
+        HQMTwoLinePassConfiguration::Off => "off",
+        HQMTwoLinePassConfiguration::On => "on",
+        HQMTwoLinePassConfiguration::Forward => "forward",
+        HQMTwoLinePassConfiguration::Double => "double",
+        HQMTwoLinePassConfiguration::ThreeLine => "threeline",
+    };
+    vec![
+        ("Server", "team_max", team_max.to_string()),
+        ("Game", "icing", icing.to_string()),
+        ("Game", "offside", offside.to_string()),
+        ("Game", "offsideline", offside_line.to_string()),
+        ("Game", "twolinepass", twoline_pass.to_string()),
+        ("Game", "mercy", config.mercy.to_string()),
+        ("Game", "first", config.first_to.to_string()),
+        ("Game", "goal_replay", config.goal_replay.to_string()),
+        ("Game", "pucks_in_play", config.pucks_in_play.to_string()),
+        (
+            "Game",
+            "spawn_offset",
+            config.spawn_point_offset.to_string(),
+        ),
+        (
+            "Game",
+            "spawn_player_altitude",
+            config.spawn_player_altitude.to_string(),
+        ),
+        (
+            "Game",
+            "spawn_puck_altitude",
+            config.spawn_puck_altitude.to_string(),
+        ),
+        (
+            "Game",
+            "spawn_player_keep_stick",
+            config.spawn_keep_stick_position.to_string(),
+        ),
+    ]
+}
+
+/// The configuration file of a server and the command line flags that override it, kept so
+/// that /reloadconfig loads the file the way it was loaded when the server started
+#[derive(Clone)]
+pub(crate) struct HQMConfigSource {
+    pub path: String,
+    pub overrides: Vec<(String, String)>,
+}
+
+pub(crate) struct HQMCommandLine {
+    pub config_paths: Vec<String>,
+    pub overrides: Vec<(String, String)>,
+    /// Ignore the state file and start with the configuration file only
+    pub fresh: bool,
+    /// Resume the game saved in the snapshot file
+    pub resume: bool,
+}
+
+/// Parses `[config path]... [--fresh] [--resume] [--key value | --key=value]...`. Each config path runs a separate server.
+/// Flags override keys of the [Server] section, with dashes treated as underscores,
+/// so `--player-max 20` overrides player_max
+pub(crate) fn parse_command_line(
+    mut args: impl Iterator<Item = String>,
+) -> Result<HQMCommandLine, String> {
+    let mut config_paths = vec![];
+    let mut overrides = vec![];
+    let mut fresh = false;
+    let mut resume = false;
+    while let Some(arg) = args.next() {
+        if arg == "--fresh" {
+            fresh = true;
+        } else if arg == "--resume" {
+            resume = true;
+        } else if let Some(flag) = arg.strip_prefix("--") {
+            let (key, value) = match flag.split_once("=") {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => match args.next() {
+                    Some(value) => (flag.to_string(), value),
+                    None => return Err(format!("Missing value for --{}", flag)),
+                },
+            };
+            let key = key.replace("-", "_");
+            let key = if key == "server_name" {
+                "name".to_string()
+            } else {
+                key
+            };
+            overrides.push((key, value));
+        } else {
+            config_paths.push(arg);
+        }
+    }
+    if config_paths.is_empty() {
+        config_paths.push("config.ini".to_string());
+    }
+    Ok(HQMCommandLine {
+        config_paths,
+        overrides,
+        fresh,
+        resume,
+    })
+}
+
+/// Keys of the [Server] section that configure the log, which all servers in one process share
+pub(crate) const LOG_KEYS: &[&str] = &[
+    "log_name",
+    "log_level",
+    "log_format",
+    "game_log",
+    "game_log_name",
+    "game_log_rotation",
+    "game_log_max_files",
+];
+
+/// Names of the environment variables that override settings of the [Server] section
+pub(crate) fn env_overrides() -> Vec<String> {
+    SERVER_KEYS
+        .iter()
+        .map(|key| env_var_name(key))
+        .filter(|name| std::env::var_os(name).is_some())
+        .collect()
+}
+
+fn env_var_name(key: &str) -> String {
+    match key {
+        "name" => "HQM_SERVER_NAME".to_string(),
+        _ => format!("HQM_{}", key.to_ascii_uppercase()),
+    }
+}
+
+/// Loads the configuration file and the settings saved in the state file, then applies overrides
+/// for the [Server] section from environment variables (e.g. `HQM_PORT`, `HQM_SERVER_NAME`) and
+/// finally from command line flags
+pub(crate) fn load_config(
+    config_path: &str,
+    state: Option<&Ini>,
+    overrides: &[(String, String)],
+) -> Result<Ini, ini::Error> {
+    let mut conf = Ini::load_from_file(config_path)?;
+    if let Some(state) = state {
+        for (section, properties) in state.iter() {
+            for (key, value) in properties.iter() {
+                conf.with_section(section).set(key, value);
+            }
+        }
+    }
+    for key in SERVER_KEYS {
+        if let Ok(value) = std::env::var(env_var_name(key)) {
+            conf.with_section(Some("Server")).set(*key, value);
+        }
+    }
+    for (key, value) in overrides.iter() {
+        conf.with_section(Some("Server")).set(key, value);
+    }
+    Ok(conf)
+}
+
+/// Re-reads the configuration file and applies the server settings that are safe to change
+/// while players are connected. Returns the loaded file so that the game mode can apply its own settings.
+pub(crate) fn reload_server_config(
+    server: &mut HQMServer,
+    player_index: HQMServerPlayerIndex,
+    source: &HQMConfigSource,
+) -> Option<Ini> {
+    if let Some(player) = server.players.get(player_index) {
+        if player.is_admin {
+            let player_name = player.player_name.clone();
+            let config_path = &source.path;
+            let conf = load_config(config_path, None, &source.overrides)
+                .map(|conf| (validate_config(&conf), conf));
+            match conf {
+                Ok((problems, _)) if !problems.errors.is_empty() => {
+                    for error in problems.errors {
+                        warn!("Configuration error: {}", error);
+                        server
+                            .messages
+                            .add_directed_server_chat_message(error, player_index);
+                    }
+                    server.messages.add_directed_server_chat_message(
+                        "Configuration file is invalid, nothing was changed",
+                        player_index,
+                    );
+                }
+                Ok((problems, conf)) => {
+                    for warning in problems.warnings {
+                        warn!("Configuration warning: {}", warning);
+                        server
+                            .messages
+                            .add_directed_server_chat_message(warning, player_index);
+                    }
+                    if let Some(server_section) = conf.section(Some("Server")) {
+                        server.config.welcome = load_welcome(server_section);
+                        server.config.chat_history = load_chat_history(server_section);
+                        server.config.spectator_slots = load_spectator_slots(server_section);
+                        server.config.spectator_delay = load_spectator_delay(server_section);
+                        server.config.afk_time = load_afk_time(server_section);
+                        server.config.compression = load_compression(server_section);
+                        server.config.input_toggle_limit = load_input_toggle_limit(server_section);
+                        server.config.restart_after_games =
+                            load_restart_after_games(server_section);
+                        server.config.packet_rate_limit = load_packet_rate_limit(server_section);
+                        server.config.join_challenge = load_join_challenge(server_section);
+                        server.config.max_packet_size = load_max_packet_size(server_section);
+                        server.config.autosave_interval = load_autosave_interval(server_section);
+                        server.config.boards = load_boards(server_section);
+                        server.config.board_rotation = load_board_rotation(server_section);
+                        server.config.alerts = load_alerts(server_section);
+                        server.config.instant_replay = load_instant_replay(server_section);
+                        server.config.movement_check = load_movement_check(server_section);
+                        server.config.network_limits = load_network_limits(server_section);
+                        server.set_replay_stream_rate(load_replay_stream_rate(server_section));
+                    }
+                    info!(
+                        "{} ({}) reloaded configuration file {}",
+                        player_name, player_index, config_path
+                    );
+                    let msg = format!("Configuration reloaded by {}", player_name);
+                    server.messages.add_server_chat_message(msg);
+                    return Some(conf);
+                }
+                Err(e) => {
+                    warn!("Could not reload configuration file {}: {}", config_path, e);
+                    server.messages.add_directed_server_chat_message(
+                        "Could not reload configuration file",
+                        player_index,
+                    );
+                }
+            }
+        } else {
+            server.admin_deny_message(player_index);
+        }
+    }
+    None
+}
+
+const SERVER_KEYS: &[&str] = &[
+    "name",
+    "port",
+    "mode",
+    "public",
+    "public_address",
+    "master_servers",
+    "log_name",
+    "log_level",
+    "log_format",
+    "team_max",
+    "player_max",
+    "spectator_slots",
+    "spectator_delay",
+    "afk_time",
+    "password",
+    "welcome",
+    "replays",
+    "service",
+    "replay_endpoint",
+    "chat_history",
+    "compression",
+    "input_toggle_limit",
+    "restart_after_games",
+    "packet_rate_limit",
+    "join_challenge",
+    "debug_commands",
+    "max_packet_size",
+    "console_port",
+    "console_address",
+    "console_password",
+    "console_tls_certificate",
+    "console_tls_key",
+    "console_socket",
+    "event_feed_port",
+    "event_feed_address",
+    "webhooks",
+    "webhook_retries",
+    "ban_file",
+    "snapshot_file",
+    "preferences_file",
+    "savegame_directory",
+    "autosave_interval",
+    "stats_database",
+    "stats_page_port",
+    "stats_page_address",
+    "stats_page_url",
+    "results_directory",
+    "highlights_directory",
+    "boards",
+    "board_rotation",
+    "alert_ping",
+    "alert_offsides",
+    "alert_disk_space",
+    "instant_replay",
+    "movement_check",
+    "max_ping",
+    "max_jitter",
+    "network_limit_action",
+    "replay_stream_rate",
+    "game_log",
+    "game_log_name",
+    "game_log_rotation",
+    "game_log_max_files",
+    "replay_directory",
+    "replay_max_files",
+    "state_file",
+    "playlist",
+    // Accepted for compatibility with older configuration files, but unused
+    "force_team_size_parity",
+];
+
+const GAME_KEYS: &[&str] = &[
+    "spawn",
+    "spawn_offset",
+    "spawn_player_altitude",
+    "spawn_puck_altitude",
+    "spawn_player_keep_stick",
+    "faceoff_drop_delay",
+    "limit_jump_speed",
+    "offside",
+    "offsideline",
+    "icing",
+    "twolinepass",
+    "crease_radius",
+    "crease_depth",
+    "high_stick_height",
+    "hand_pass",
+    "disallow_body_goals",
+    "shootout",
+    "time_period",
+    "time_periods",
+    "time_overtime",
+    "running_clock",
+    "swap_ends",
+    "ready_players",
+    "time_warmup",
+    "time_break",
+    "time_intermission",
+    "warmup_pucks",
+    "pucks_in_play",
+    "net_dislodge_speed",
+    "delay_of_game",
+    "warmup_practice",
+    "practice_pucks",
+    "bots",
+    "periods",
+    "mercy",
+    "first",
+    "attempts",
+    "replay_file",
+    "use_mph",
+    "goal_replay",
+    "goal_replay_before",
+    "goal_replay_after",
+    "goal_replay_view",
+    "hints",
+    "announce_win_probability",
+    "goal_streak",
+    "goal_streak_reset",
+    "announce_comebacks",
+    "rematch_vote",
+    "series",
+    "votes",
+    "vote_voters",
+    "vote_time",
+    "vote_threshold",
+    "rejoin_grace",
+    "rejoin_keep_skater",
+    "auto_pause_skaters",
+    // Accepted for compatibility with older configuration files, but unused
+    "blue_line_location",
+];
+
+const PHYSICS_KEYS: &[&str] = &[
+    "profile",
+    "gravity",
+    "player_acceleration",
+    "player_deceleration",
+    "max_player_speed",
+    "max_player_shift_speed",
+    "puck_rink_friction",
+    "puck_rink_bounce",
+    "stick_puck_force",
+    "player_turning",
+    "player_shift_turning",
+    "player_shift_acceleration",
+    "jump_speed",
+    "puck_air_drag",
+];
+
+const RINK_KEYS: &[&str] = &[
+    "file",
+    "width",
+    "length",
+    "corner_radius",
+    "goal_width",
+    "goal_height",
+    "goal_line",
+    "blue_line",
+    "boards_height",
+];
+
+/// Sections named "Ruleset <name>" hold rule presets that admins can switch to with /ruleset
+const RULESET_SECTION: &str = "Ruleset ";
+
+const RULESET_KEYS: &[&str] = &[
+    "icing",
+    "offside",
+    "offsideline",
+    "twolinepass",
+    "mercy",
+    "first",
+];
+
+/// Checks that the lines, goals and corners of the rink fit on it
+fn check_rink(rink: &HQMRinkConfiguration) -> Vec<String> {
+    let mut errors = vec![];
+    if rink.corner_radius > rink.width.min(rink.length) / 2.0 {
+        errors.push("corner_radius in [Rink] is more than half the width or length".to_string());
+    }
+    // The back of the net is half a meter narrower than its front
+    if rink.goal_width <= 0.5 {
+        errors.push("goal_width in [Rink] must be more than 0.5".to_string());
+    }
+    if rink.goal_width > rink.width - 2.0 * rink.corner_radius {
+        errors.push("goal_width in [Rink] doesn't fit between the corners".to_string());
+    }
+    if rink.goal_line_distance < 1.0 {
+        errors.push("goal_line in [Rink] leaves no room behind the goals".to_string());
+    }
+    if rink.blue_line_distance <= rink.goal_line_distance + 1.0
+        || rink.blue_line_distance >= rink.length / 2.0
+    {
+        errors.push(
+            "blue_line in [Rink] must be between the goal line and the center of the rink"
+                .to_string(),
+        );
+    }
+    let on_ice =
+        |[x, z]: [f32; 2]| (0.0..=rink.width).contains(&x) && (0.0..=rink.length).contains(&z);
+    for (name, net) in [("red_net", rink.red_net), ("blue_net", rink.blue_net)] {
+        if net.is_some_and(|net| !on_ice(net)) {
+            errors.push(format!("{} of the rink file is outside the rink", name));
+        }
+    }
+    if rink
+        .center_line
+        .is_some_and(|z| !(0.0..=rink.length).contains(&z))
+    {
+        errors.push("center_line of the rink file is outside the rink".to_string());
+    }
+    if rink
+        .faceoff_dots
+        .as_ref()
+        .is_some_and(|dots| !dots.iter().all(|dot| on_ice(*dot)))
+    {
+        errors.push("A faceoff dot of the rink file is outside the rink".to_string());
+    }
+    errors
+}
+
+/// Problems found in a configuration file. Errors keep the server from starting, warnings are
+/// settings that are ignored or fall back to their defaults, as they always have
+pub(crate) struct HQMConfigProblems {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Checks the whole configuration file and returns all problems found, so that the server can
+/// refuse to start instead of panicking on a value it can't load, and point out settings that
+/// are ignored or fall back to defaults
+pub(crate) fn validate_config(conf: &Ini) -> HQMConfigProblems {
+    let mut errors = vec![];
+    let mut warnings = vec![];
+
+    for (section, properties) in conf.iter() {
+        let known_keys = match section {
+            Some("Server") => SERVER_KEYS,
+            Some("Game") => GAME_KEYS,
+            Some("Physics") => PHYSICS_KEYS,
+            Some("Rink") => RINK_KEYS,
+            Some("MasterServers") => {
+                for (region, master_servers) in properties.iter() {
+                    check_master_servers(&mut warnings, region, master_servers);
+                }
+                continue;
+            }
+            // The name of the section is trimmed, so [Ruleset ] is read as [Ruleset]
+            Some(section) if section == RULESET_SECTION.trim_end() => {
+                errors.push(format!("[{}] needs a name, like [Ruleset league]", section));
+                continue;
+            }
+            Some(section) if section.starts_with(RULESET_SECTION) => {
+                check_rules(&mut warnings, Some(properties));
+                for key in ["mercy", "first"] {
+                    check::<u32>(
+                        &mut errors,
+                        Some(properties),
+                        key,
+                        "a non-negative whole number",
+                        |_| true,
+                    );
+                }
+                RULESET_KEYS
+            }
+            Some(section) => {
+                warnings.push(format!("Unknown section [{}]", section));
+                continue;
+            }
+            None => &[],
+        };
+        for (key, _) in properties.iter() {
+            if !known_keys.contains(&key) {
+                match section {
+                    Some(section) => warnings.push(format!("Unknown key {} in [{}]", key, section)),
+                    None => warnings.push(format!("Key {} is outside of any section", key)),
+                }
+            }
+        }
+    }
+
+    let server_section = match conf.section(Some("Server")) {
+        Some(server_section) => server_section,
+        None => {
+            errors.push("Missing [Server] section".to_string());
+            return HQMConfigProblems { errors, warnings };
+        }
+    };
+    for key in [
+        "name",
+        "port",
+        "public",
+        "player_max",
+        "team_max",
+        "password",
+    ] {
+        if server_section.get(key).is_none() {
+            errors.push(format!("Missing required key {} in [Server]", key));
+        }
+    }
+
+    fn check<T: std::str::FromStr>(
+        errors: &mut Vec<String>,
+        section: Option<&Properties>,
+        key: &str,
+        description: &str,
+        f: impl FnOnce(&T) -> bool,
+    ) -> Option<T> {
+        let value = section.and_then(|x| x.get(key))?;
+        match value.parse::<T>() {
+            Ok(v) if f(&v) => Some(v),
+            _ => {
+                errors.push(format!(
+                    "{} must be {}, was \"{}\"",
+                    key, description, value
+                ));
+                None
+            }
+        }
+    }
+
+    fn check_one_of(
+        problems: &mut Vec<String>,
+        section: Option<&Properties>,
+        key: &str,
+        allowed: &[&str],
+    ) -> Option<String> {
+        let value = section.and_then(|x| x.get(key))?;
+        if allowed.contains(&value) {
+            Some(value.to_string())
+        } else {
+            problems.push(format!(
+                "{} must be one of {}, was \"{}\"",
+                key,
+                allowed.join(", "),
+                value
+            ));
+            None
+        }
+    }
+
+    /// Checks the rule settings that rulesets can change too, returns the offside setting
+    fn check_rules(problems: &mut Vec<String>, section: Option<&Properties>) -> Option<String> {
+        let offside = check_one_of(
+            problems,
+            section,
+            "offside",
+            &["off", "on", "delayed", "immediate", "imm"],
+        );
+        check_one_of(problems, section, "offsideline", &["blue", "center"]);
+        check_one_of(
+            problems,
+            section,
+            "icing",
+            &["off", "on", "touch", "notouch"],
+        );
+        check_one_of(
+            problems,
+            section,
+            "twolinepass",
+            &[
+                "off",
+                "on",
+                "forward",
+                "double",
+                "both",
+                "blue",
+                "three",
+                "threeline",
+            ],
+        );
+        offside
+    }
+
+    fn check_master_servers(problems: &mut Vec<String>, key: &str, master_servers: &str) {
+        for master_server in master_servers.split(',').map(str::trim) {
+            let is_url =
+                master_server.starts_with("http://") || master_server.starts_with("https://");
+            let has_port = matches!(
+                master_server.rsplit_once(':'),
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok()
+            );
+            if !is_url && !has_port {
+                problems.push(format!(
+                    "{} must be a comma separated list of URLs or host:port addresses, was \"{}\"",
+                    key, master_server
+                ));
+            }
+        }
+    }
+
+    fn check_bool(problems: &mut Vec<String>, section: Option<&Properties>, key: &str) {
+        if let Some(value) = section.and_then(|x| x.get(key)) {
+            if !is_true(value)
+                && !value.eq_ignore_ascii_case("false")
+                && !value.eq_ignore_ascii_case("off")
+            {
+                problems.push(format!(
+                    "{} must be one of true, false, on, off, was \"{}\"",
+                    key, value
+                ));
+            }
+        }
+    }
+
+    let server = Some(server_section);
+    check::<u16>(&mut errors, server, "port", "a port number", |_| true);
+    check_bool(&mut warnings, server, "public");
+    if let Some(master_servers) = server_section.get("master_servers") {
+        check_master_servers(&mut warnings, "master_servers", master_servers);
+    }
+    check_one_of(
+        &mut warnings,
+        server,
+        "mode",
+        &[
+            "match", "warmup", "practice", "russian", "shootout", "playback",
+        ],
+    );
+    if server_section.get("mode") == Some("playback")
+        && conf
+            .section(Some("Game"))
+            .and_then(|x| x.get("replay_file"))
+            .is_none()
+    {
+        errors.push("mode playback needs replay_file in [Game]".to_string());
+    }
+    check_one_of(
+        &mut errors,
+        server,
+        "log_level",
+        &["error", "warn", "info", "debug", "trace"],
+    );
+    check_one_of(&mut warnings, server, "log_format", &["text", "json"]);
+    check_bool(&mut warnings, server, "game_log");
+    check_one_of(
+        &mut warnings,
+        server,
+        "game_log_rotation",
+        &["hourly", "daily", "never"],
+    );
+    if let Some(playlist) = server_section.get("playlist") {
+        let is_valid = playlist
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .all(|entry| match entry.rsplit_once('*') {
+                Some((path, games)) => {
+                    !path.trim().is_empty() && games.trim().parse::<u32>().is_ok_and(|x| x > 0)
+                }
+                None => true,
+            });
+        if !is_valid {
+            errors.push(format!(
+                "playlist must be a comma separated list of configuration files, each optionally followed by * and a number of games, was \"{}\"",
+                playlist
+            ));
+        }
+    }
+    for key in ["game_log_max_files", "replay_max_files"] {
+        check::<usize>(&mut errors, server, key, "a non-negative number", |_| true);
+    }
+    let player_max = check::<usize>(
+        &mut errors,
+        server,
+        "player_max",
+        "a number between 1 and 64",
+        |x| (1..=64).contains(x),
+    );
+    let spectator_slots = check::<usize>(
+        &mut errors,
+        server,
+        "spectator_slots",
+        "a non-negative number",
+        |_| true,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "afk_time",
+        "a number of seconds",
+        |_| true,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "spectator_delay",
+        "a number of seconds between 0 and 120",
+        |x| *x <= MAX_SPECTATOR_DELAY,
+    );
+    let team_max = check::<usize>(
+        &mut errors,
+        server,
+        "team_max",
+        "a number between 1 and 15",
+        |x| (1..=15).contains(x),
+    );
+    let replays = server_section
+        .get("replays")
+        .map(|x| x.to_ascii_lowercase());
+    if let Some(replays) = replays.as_deref() {
+        if !["true", "false", "on", "off", "standby"].contains(&replays) {
+            errors.push(format!(
+                "replays must be one of true, false, on, off, standby, was \"{}\"",
+                replays
+            ));
+        }
+    }
+    check::<usize>(
+        &mut errors,
+        server,
+        "chat_history",
+        "a non-negative number",
+        |_| true,
+    );
+    check_bool(&mut warnings, server, "compression");
+    check_bool(&mut warnings, server, "join_challenge");
+    check_bool(&mut warnings, server, "debug_commands");
+    check::<usize>(
+        &mut errors,
+        server,
+        "max_packet_size",
+        "a number of bytes, at least 548",
+        |x| *x >= 548,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "input_toggle_limit",
+        "a non-negative number",
+        |_| true,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "restart_after_games",
+        "a non-negative number",
+        |_| true,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "packet_rate_limit",
+        "a non-negative number",
+        |_| true,
+    );
+    check::<u16>(&mut errors, server, "console_port", "a port number", |_| {
+        true
+    });
+    check::<IpAddr>(
+        &mut errors,
+        server,
+        "console_address",
+        "an IP address",
+        |_| true,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "autosave_interval",
+        "a non-negative number",
+        |_| true,
+    );
+    for webhook in load_webhooks(server_section) {
+        if !webhook.starts_with("http://") && !webhook.starts_with("https://") {
+            warnings.push(format!(
+                "webhooks must be a comma separated list of URLs, was \"{}\"",
+                webhook
+            ));
+        }
+    }
+    check::<u32>(
+        &mut errors,
+        server,
+        "webhook_retries",
+        "a non-negative number",
+        |_| true,
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "board_rotation",
+        "a non-negative number",
+        |_| true,
+    );
+    check_one_of(
+        &mut warnings,
+        server,
+        "instant_replay",
+        &["off", "admins", "all"],
+    );
+    check_one_of(
+        &mut warnings,
+        server,
+        "movement_check",
+        &["off", "log", "flag", "kick"],
+    );
+    check::<u32>(
+        &mut errors,
+        server,
+        "replay_stream_rate",
+        "a non-negative number",
+        |_| true,
+    );
+    for key in ["alert_ping", "alert_offsides", "alert_disk_space"] {
+        check::<u32>(&mut errors, server, key, "a non-negative number", |_| true);
+    }
+    for key in ["max_ping", "max_jitter"] {
+        check::<u32>(&mut errors, server, key, "a non-negative number", |_| true);
+    }
+    check_one_of(
+        &mut warnings,
+        server,
+        "network_limit_action",
+        &["warn", "kick"],
+    );
+    let boards = load_boards(server_section);
+    if boards.len() > MAX_BOARDS {
+        errors.push(format!("boards can have at most {} lines", MAX_BOARDS));
+    }
+    for board in boards {
+        if board.len() > MAX_BOARD_TEXT_LENGTH {
+            errors.push(format!(
+                "boards can have lines of at most {} bytes, \"{}\" is too long",
+                MAX_BOARD_TEXT_LENGTH, board
+            ));
+        }
+    }
+    check::<u16>(
+        &mut errors,
+        server,
+        "event_feed_port",
+        "a port number",
+        |_| true,
+    );
+    check::<IpAddr>(
+        &mut errors,
+        server,
+        "event_feed_address",
+        "an IP address",
+        |_| true,
+    );
+    check::<u16>(
+        &mut errors,
+        server,
+        "stats_page_port",
+        "a port number",
+        |_| true,
+    );
+    check::<IpAddr>(
+        &mut errors,
+        server,
+        "stats_page_address",
+        "an IP address",
+        |_| true,
+    );
+    if let Some(url) = server_section.get("stats_page_url") {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            warnings.push(format!("stats_page_url must be a URL, was \"{}\"", url));
+        }
+    }
+
+    if let (Some(player_max), Some(team_max)) = (player_max, team_max) {
+        if player_max < team_max * 2 {
+            warnings.push(format!(
+                "player_max ({}) is too small for two teams of team_max ({}) players",
+                player_max, team_max
+            ));
+        }
+    }
+    if let (Some(player_max), Some(spectator_slots)) = (player_max, spectator_slots) {
+        if player_max + spectator_slots > 64 {
+            errors.push(format!(
+                "player_max ({}) and spectator_slots ({}) don't fit in the 64 player slots",
+                player_max, spectator_slots
+            ));
+        }
+    }
+    if server_section.get("replay_endpoint").is_some()
+        && matches!(replays.as_deref(), None | Some("false") | Some("off"))
+    {
+        warnings.push("replay_endpoint is set, but replays are disabled".to_string());
+    }
+    if server_section.get("console_tls_certificate").is_some()
+        != server_section.get("console_tls_key").is_some()
+    {
+        errors.push("console_tls_certificate and console_tls_key must be set together".to_string());
+    }
+    if (server_section.get("console_port").is_some()
+        || server_section.get("console_socket").is_some())
+        && server_section
+            .get("console_password")
+            .map_or(true, |x| x.is_empty())
+    {
+        errors.push(
+            "console_password must be set to a non-empty password to open the console".to_string(),
+        );
+    }
+
+    let game = conf.section(Some("Game"));
+    check_one_of(&mut warnings, game, "spawn", &["center", "bench"]);
+    for key in [
+        "spawn_offset",
+        "spawn_player_altitude",
+        "spawn_puck_altitude",
+        "faceoff_drop_delay",
+        "crease_radius",
+        "crease_depth",
+        "high_stick_height",
+    ] {
+        check::<f32>(&mut errors, game, key, "a non-negative number", |x| {
+            *x >= 0.0
+        });
+    }
+    for key in [
+        "spawn_player_keep_stick",
+        "limit_jump_speed",
+        "use_mph",
+        "goal_replay",
+        "hints",
+        "announce_win_probability",
+        "announce_comebacks",
+        "rematch_vote",
+        "votes",
+        "running_clock",
+        "swap_ends",
+        "hand_pass",
+        "disallow_body_goals",
+        "delay_of_game",
+        "warmup_practice",
+        "rejoin_keep_skater",
+    ] {
+        check_bool(&mut warnings, game, key);
+    }
+    check_one_of(
+        &mut warnings,
+        game,
+        "goal_streak_reset",
+        &["never", "period", "faceoff"],
+    );
+    check_one_of(
+        &mut warnings,
+        game,
+        "goal_replay_view",
+        &["scorer", "goalie", "puck"],
+    );
+    check_one_of(&mut warnings, game, "vote_voters", &["players", "everyone"]);
+    check::<u32>(
+        &mut errors,
+        game,
+        "vote_time",
+        "a positive whole number",
+        |x| *x > 0,
+    );
+    check::<u32>(
+        &mut errors,
+        game,
+        "vote_threshold",
+        "a whole number from 1 to 100",
+        |x| (1..=100).contains(x),
+    );
+    check::<u32>(
+        &mut errors,
+        game,
+        "rejoin_grace",
+        "a number of seconds",
+        |_| true,
+    );
+    check::<usize>(
+        &mut errors,
+        game,
+        "auto_pause_skaters",
+        "a non-negative number",
+        |_| true,
+    );
+    let goal_replay_before = check::<f32>(
+        &mut errors,
+        game,
+        "goal_replay_before",
+        "a non-negative number",
+        |x| *x >= 0.0,
+    );
+    let goal_replay_after = check::<f32>(
+        &mut errors,
+        game,
+        "goal_replay_after",
+        "a non-negative number",
+        |x| *x >= 0.0,
+    );
+    if goal_replay_before.unwrap_or(6.0) + goal_replay_after.unwrap_or(2.0) > 10.0 {
+        errors.push(
+            "goal_replay_before and goal_replay_after can be at most 10 seconds together, the history the server keeps".to_string(),
+        );
+    }
+    let offside = check_rules(&mut warnings, game);
+    if let Some(period_lengths) = game.and_then(|x| x.get("time_periods")) {
+        let is_valid = period_lengths
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .all(|x| x.parse::<u32>().is_ok());
+        if !is_valid {
+            errors.push(format!(
+                "time_periods must be a comma separated list of whole numbers, was \"{}\"",
+                period_lengths
+            ));
+        }
+    }
+    for key in [
+        "time_period",
+        "time_overtime",
+        "time_warmup",
+        "time_break",
+        "time_intermission",
+        "mercy",
+        "first",
+        "goal_streak",
+        "shootout",
+        "series",
+    ] {
+        check::<u32>(
+            &mut errors,
+            game,
+            key,
+            "a non-negative whole number",
+            |_| true,
+        );
+    }
+    let ready_players = check::<usize>(
+        &mut errors,
+        game,
+        "ready_players",
+        "a non-negative whole number",
+        |_| true,
+    );
+    if let (Some(ready_players), Some(team_max)) = (ready_players, team_max) {
+        if ready_players > team_max {
+            warnings.push(format!(
+                "ready_players ({}) is larger than team_max ({}), the teams can never be ready",
+                ready_players, team_max
+            ));
+        }
+    }
+    check::<u32>(
+        &mut errors,
+        game,
+        "periods",
+        "a positive whole number",
+        |x| *x > 0,
+    );
+    check::<u32>(
+        &mut errors,
+        game,
+        "attempts",
+        "a positive whole number",
+        |x| *x > 0,
+    );
+    let warmup_pucks = check::<usize>(
+        &mut errors,
+        game,
+        "warmup_pucks",
+        "a number between 1 and 64",
+        |x| (1..=MAX_PUCKS).contains(x),
+    );
+    let pucks_in_play = check::<usize>(
+        &mut errors,
+        game,
+        "pucks_in_play",
+        "a number between 1 and 64",
+        |x| (1..=MAX_PUCKS).contains(x),
+    );
+    check::<f32>(
+        &mut errors,
+        game,
+        "net_dislodge_speed",
+        "a non-negative number",
+        |x| *x >= 0.0,
+    );
+    check::<usize>(
+        &mut errors,
+        game,
+        "practice_pucks",
+        "a number between 1 and 64",
+        |x| (1..=MAX_PUCKS).contains(x),
+    );
+    let bots = check::<usize>(
+        &mut errors,
+        game,
+        "bots",
+        "a non-negative whole number",
+        |_| true,
+    );
+    if let (Some(bots), Some(team_max)) = (bots, team_max) {
+        if bots > team_max * 2 {
+            warnings.push(format!(
+                "bots ({}) don't fit in two teams of team_max ({}) players",
+                bots, team_max
+            ));
+        }
+    }
+    if let (Some(warmup_pucks), Some(team_max)) = (warmup_pucks, team_max) {
+        if !objects_fit(warmup_pucks, team_max) {
+            warnings.push(format!(
+                "warmup_pucks ({}) and two teams of team_max ({}) players don't fit in the 32 available objects",
+                warmup_pucks, team_max
+            ));
+        }
+    }
+    if let (Some(pucks_in_play), Some(team_max)) = (pucks_in_play, team_max) {
+        if !objects_fit(pucks_in_play, team_max) {
+            warnings.push(format!(
+                "pucks_in_play ({}) and two teams of team_max ({}) players don't fit in the 32 available objects",
+                pucks_in_play, team_max
+            ));
+        }
+    }
+    if game.and_then(|x| x.get("offsideline")).is_some()
+        && matches!(offside.as_deref(), None | Some("off"))
+    {
+        warnings.push("offsideline is set, but offside is disabled".to_string());
+    }
+
+    let physics = conf.section(Some("Physics"));
+    check_one_of(&mut errors, physics, "profile", &HQMPhysicsProfile::PRESETS);
+    for key in PHYSICS_KEYS.iter().filter(|key| **key != "profile") {
+        check::<f32>(&mut errors, physics, key, "a non-negative number", |x| {
+            *x >= 0.0
+        });
+    }
+
+    let rink = conf.section(Some("Rink"));
+    let errors_before_rink = errors.len();
+    if let Some(path) = rink.and_then(|x| x.get("file")) {
+        if let Err(e) = read_rink_file(path) {
+            errors.push(e);
+        }
+    }
+    for key in RINK_KEYS.iter().filter(|key| **key != "file") {
+        check::<f32>(&mut errors, rink, key, "a positive number", |x| *x > 0.0);
+    }
+    // The rink can only be loaded once all of its values are numbers
+    if errors.len() == errors_before_rink {
+        errors.extend(check_rink(&load_rink_config(conf)));
+    }
+
+    HQMConfigProblems { errors, warnings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use migo_hqm_server::hqm_game::HQMGameValues;
+
+    #[test]
+    fn rink_file_outside_the_rink_is_an_error() {
+        let rink: HQMRinkConfiguration =
+            serde_json::from_str(r#"{"width": 20, "corner_radius": 5}"#).unwrap();
+        assert!(check_rink(&rink).is_empty());
+
+        let rink: HQMRinkConfiguration =
+            serde_json::from_str(r#"{"red_net": [35, 57], "center_line": 70}"#).unwrap();
+        assert_eq!(
+            check_rink(&rink),
+            vec![
+                "red_net of the rink file is outside the rink",
+                "center_line of the rink file is outside the rink"
+            ]
+        );
+
+        let rink: HQMRinkConfiguration = serde_json::from_str(r#"{"goal_width": 0.5}"#).unwrap();
+        assert_eq!(
+            check_rink(&rink),
+            vec!["goal_width in [Rink] must be more than 0.5"]
+        );
+    }
+
+    /// The keys [Server] must have, for configurations that are validated
+    const SERVER_SECTION: &str =
+        "[Server]\nname=Test\nport=27585\npublic=false\nplayer_max=20\nteam_max=5\npassword=\n";
+
+    fn match_config(conf: &str) -> HQMMatchConfiguration {
+        let conf = Ini::load_from_str(conf).unwrap();
+        load_match_config(&conf, load_physics_config(&conf))
+    }
+
+    #[test]
+    fn periods_have_the_lengths_they_are_given() {
+        let config = match_config(
+            "[Game]\nperiods=3\ntime_period=300\ntime_periods=600, 480\ntime_overtime=120\n",
+        );
+        assert_eq!(config.period_length(1), 60000);
+        assert_eq!(config.period_length(2), 48000);
+        assert_eq!(config.period_length(3), 30000);
+        assert_eq!(config.period_length(4), 12000);
+        assert_eq!(config.period_length(5), 12000);
+
+        let config = match_config("[Game]\ntime_period=200\n");
+        assert_eq!(config.period_length(1), 20000);
+        assert_eq!(config.period_length(4), 20000);
+
+        let conf = Ini::load_from_str(&format!("{}[Game]\ntime_periods=600,ten\n", SERVER_SECTION))
+            .unwrap();
+        assert!(validate_config(&conf)
+            .errors
+            .iter()
+            .any(|error| error.starts_with("time_periods must be")));
+    }
+
+    #[test]
+    fn running_clock_runs_only_while_periods_are_played() {
+        let values = HQMGameValues {
+            period: 2,
+            time: 12000,
+            ..Default::default()
+        };
+        assert!(!match_config("[Game]\n").clock_runs_in_stoppage(&values));
+
+        let config = match_config("[Game]\nrunning_clock=true\n");
+        assert!(config.clock_runs_in_stoppage(&values));
+        for values in [
+            HQMGameValues {
+                period: 0,
+                ..values
+            },
+            HQMGameValues { time: 0, ..values },
+            HQMGameValues {
+                game_over: true,
+                ..values
+            },
+        ] {
+            assert!(!config.clock_runs_in_stoppage(&values));
+        }
+    }
+
+    #[test]
+    fn rulesets_need_a_name() {
+        let conf = Ini::load_from_str(
+            "[Ruleset league]\nicing=notouch\nmercy=5\n[Ruleset no-rules]\noffside=off\n",
+        )
+        .unwrap();
+        let rulesets = load_rulesets(&conf);
+        let names: Vec<_> = rulesets
+            .iter()
+            .map(|ruleset| ruleset.name.as_str())
+            .collect();
+        assert_eq!(names, ["league", "no-rules"]);
+        assert_eq!(rulesets[0].mercy, Some(5));
+        assert!(rulesets[0].offside.is_none());
+
+        let conf =
+            Ini::load_from_str(&format!("{}[Ruleset ]\nicing=on\n", SERVER_SECTION)).unwrap();
+        assert!(load_rulesets(&conf).is_empty());
+        assert_eq!(
+            validate_config(&conf).errors,
+            ["[Ruleset] needs a name, like [Ruleset league]"]
+        );
+    }
+
+    #[test]
+    fn disconnects_pause_only_when_a_team_is_left_short() {
+        let values = HQMGameValues {
+            period: 1,
+            time: 20000,
+            ..Default::default()
+        };
+        assert!(!match_config("[Game]\n").pauses_for_disconnect(&values, 0));
+
+        let config = match_config("[Game]\nauto_pause_skaters=4\n");
+        assert!(config.pauses_for_disconnect(&values, 3));
+        assert!(!config.pauses_for_disconnect(&values, 4));
+        let warmup = HQMGameValues {
+            period: 0,
+            ..values
+        };
+        assert!(!config.pauses_for_disconnect(&warmup, 3));
+        let game_over = HQMGameValues {
+            game_over: true,
+            ..values
+        };
+        assert!(!config.pauses_for_disconnect(&game_over, 3));
+    }
+}
@@ -0,0 +1,527 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot};
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, info_span, warn};
+
+use crate::hqm_admin_commands::{parse_shutdown_seconds, HQMAdminSource};
+use crate::hqm_behaviour::HQMServerBehaviour;
+use crate::hqm_server::{HQMServer, HQMServerPlayerIndex};
+
+#[derive(Debug, Clone)]
+pub struct HQMConsoleConfiguration {
+    pub address: Option<SocketAddr>,
+    /// Only used on Unix-like systems
+    pub unix_socket: Option<PathBuf>,
+    pub password: String,
+    /// Only used for TCP connections
+    pub tls: Option<HQMConsoleTlsConfiguration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HQMConsoleTlsConfiguration {
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Time a connection has to send the password
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest password line that is read
+const MAX_PASSWORD_LINE: u64 = 256;
+
+/// Failed logins from one address after which further logins are refused. Logins that are
+/// still waiting for a password count as well, so that many connections at once can't get more
+/// guesses
+const MAX_FAILED_LOGINS: u32 = 5;
+
+/// Time an address is blocked after too many failed logins, and time after which a failed
+/// login that didn't lead to a block is forgotten
+const FAILED_LOGIN_WINDOW: Duration = Duration::from_secs(300);
+
+pub(crate) struct HQMConsoleCommand {
+    pub(crate) line: String,
+    pub(crate) response: oneshot::Sender<String>,
+}
+
+pub(crate) async fn run_console(
+    config: HQMConsoleConfiguration,
+    sender: mpsc::Sender<HQMConsoleCommand>,
+) -> std::io::Result<()> {
+    let password = Arc::new(config.password);
+    let tcp = async {
+        match config.address {
+            Some(address) => {
+                run_tcp_console(address, config.tls.as_ref(), &password, sender.clone()).await
+            }
+            None => Ok(()),
+        }
+    };
+    let unix = async {
+        match &config.unix_socket {
+            Some(path) => run_unix_console(path, &password, sender.clone()).await,
+            None => Ok(()),
+        }
+    };
+    tokio::try_join!(tcp, unix)?;
+    Ok(())
+}
+
+async fn run_tcp_console(
+    address: SocketAddr,
+    tls: Option<&HQMConsoleTlsConfiguration>,
+    password: &Arc<String>,
+    sender: mpsc::Sender<HQMConsoleCommand>,
+) -> std::io::Result<()> {
+    let acceptor = match tls {
+        Some(tls) => Some(load_tls_acceptor(tls)?),
+        None => None,
+    };
+    let failed_logins = Arc::new(Mutex::new(HQMFailedLogins::default()));
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Admin console listening at address {:?}",
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let sender = sender.clone();
+        let password = password.clone();
+        let acceptor = acceptor.clone();
+        if !failed_logins
+            .lock()
+            .unwrap()
+            .start(addr.ip(), Instant::now())
+        {
+            info!(
+                "Admin console connection from {:?} refused after too many failed logins",
+                addr
+            );
+            continue;
+        }
+        let attempt = Some(HQMLoginAttempt {
+            failed_logins: failed_logins.clone(),
+            ip: addr.ip(),
+            failed: false,
+        });
+        tokio::spawn(async move {
+            let res = match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => handle_connection(stream, addr, &password, attempt, sender).await,
+                    Err(e) => Err(e),
+                },
+                None => handle_connection(stream, addr, &password, attempt, sender).await,
+            };
+            if let Err(e) = res {
+                warn!("Admin console connection from {:?} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+async fn run_unix_console(
+    path: &Path,
+    password: &Arc<String>,
+    sender: mpsc::Sender<HQMConsoleCommand>,
+) -> std::io::Result<()> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use tokio::net::UnixListener;
+
+    // A socket file left behind by a previous run would make binding fail
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    info!("Admin console listening at {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let sender = sender.clone();
+        let password = password.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, "Unix socket", &password, None, sender).await
+            {
+                warn!("Admin console connection from Unix socket failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+async fn run_unix_console(
+    path: &Path,
+    _password: &Arc<String>,
+    _sender: mpsc::Sender<HQMConsoleCommand>,
+) -> std::io::Result<()> {
+    warn!(
+        "Admin console socket {} is only supported on Unix-like systems",
+        path.display()
+    );
+    Ok(())
+}
+
+fn load_tls_acceptor(tls: &HQMConsoleTlsConfiguration) -> std::io::Result<TlsAcceptor> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(File::open(&tls.certificate)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(&tls.key)?))?
+        .into_iter()
+        .next()
+        .map(PrivateKey)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "No private key found"))?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Login attempts per address, so that passwords can't be guessed over TCP
+#[derive(Default)]
+struct HQMFailedLogins {
+    by_address: HashMap<IpAddr, HQMLoginAttempts>,
+}
+
+#[derive(Default)]
+struct HQMLoginAttempts {
+    /// Connections that haven't sent their password yet
+    in_flight: u32,
+    failures: u32,
+    last_failure: Option<Instant>,
+    /// When the address was blocked for too many failed logins
+    blocked_since: Option<Instant>,
+}
+
+impl HQMFailedLogins {
+    /// Starts a login attempt, or returns false if the address is blocked or has as many
+    /// attempts going as it may still fail
+    fn start(&mut self, ip: IpAddr, now: Instant) -> bool {
+        self.expire(now);
+        let attempts = self.by_address.entry(ip).or_default();
+        if attempts.blocked_since.is_some()
+            || attempts.in_flight + attempts.failures >= MAX_FAILED_LOGINS
+        {
+            return false;
+        }
+        attempts.in_flight += 1;
+        true
+    }
+
+    /// Ends a login attempt that was started, blocking the address if it failed too often
+    fn end(&mut self, ip: IpAddr, now: Instant, failed: bool) {
+        if let Some(attempts) = self.by_address.get_mut(&ip) {
+            attempts.in_flight = attempts.in_flight.saturating_sub(1);
+            if failed {
+                attempts.failures += 1;
+                attempts.last_failure = Some(now);
+                if attempts.failures >= MAX_FAILED_LOGINS {
+                    attempts.blocked_since = Some(now);
+                }
+            }
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let expired = |time: Option<Instant>| {
+            time.is_some_and(|time| now.duration_since(time) >= FAILED_LOGIN_WINDOW)
+        };
+        self.by_address.retain(|_, attempts| {
+            if expired(attempts.blocked_since) || expired(attempts.last_failure) {
+                attempts.failures = 0;
+                attempts.last_failure = None;
+                attempts.blocked_since = None;
+            }
+            attempts.in_flight > 0 || attempts.failures > 0
+        });
+    }
+}
+
+/// A login attempt that ends when it's dropped, also if the connection fails before the
+/// password is checked
+struct HQMLoginAttempt {
+    failed_logins: Arc<Mutex<HQMFailedLogins>>,
+    ip: IpAddr,
+    failed: bool,
+}
+
+impl Drop for HQMLoginAttempt {
+    fn drop(&mut self) {
+        self.failed_logins
+            .lock()
+            .unwrap()
+            .end(self.ip, Instant::now(), self.failed);
+    }
+}
+
+/// Compares the whole password regardless of where the first difference is, so that the
+/// response time doesn't tell how much of a guess was right
+fn password_matches(guess: &str, password: &str) -> bool {
+    let (guess, password) = (guess.as_bytes(), password.as_bytes());
+    let mut difference = guess.len() ^ password.len();
+    for (i, &byte) in password.iter().enumerate() {
+        difference |= usize::from(byte ^ guess.get(i).copied().unwrap_or(0));
+    }
+    difference == 0
+}
+
+/// Reads the password line. Longer lines are cut off, so a peer that never sends a newline
+/// can't make the buffer grow
+async fn read_password<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> std::io::Result<String> {
+    let mut line = Vec::new();
+    reader
+        .take(MAX_PASSWORD_LINE)
+        .read_until(b'\n', &mut line)
+        .await?;
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+async fn handle_connection<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    peer: impl Display,
+    password: &str,
+    attempt: Option<HQMLoginAttempt>,
+    sender: mpsc::Sender<HQMConsoleCommand>,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = tokio::io::BufReader::new(reader);
+
+    writer.write_all(b"Password: ").await?;
+    let guess = match tokio::time::timeout(LOGIN_TIMEOUT, read_password(&mut reader)).await {
+        Ok(guess) => guess?,
+        Err(_) => {
+            info!("Admin console login from {} timed out", peer);
+            return Ok(());
+        }
+    };
+    if !password_matches(&guess, password) {
+        info!("Admin console login from {} failed", peer);
+        if let Some(mut attempt) = attempt {
+            attempt.failed = true;
+        }
+        writer.write_all(b"Wrong password\n").await?;
+        return Ok(());
+    }
+    drop(attempt);
+    info!("Admin console login from {}", peer);
+    writer.write_all(b"Logged in\n").await?;
+
+    let mut lines = reader.lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "quit" || line == "exit" {
+            break;
+        }
+        let (response, receiver) = oneshot::channel();
+        let command = HQMConsoleCommand {
+            line: line.to_string(),
+            response,
+        };
+        if sender.send(command).await.is_err() {
+            break;
+        }
+        if let Ok(response) = receiver.await {
+            writer.write_all(response.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+        }
+    }
+    info!("Admin console connection from {} closed", peer);
+    Ok(())
+}
+
+impl HQMServer {
+    pub(crate) fn process_console_command<B: HQMServerBehaviour>(
+        &mut self,
+        line: &str,
+        behaviour: &mut B,
+    ) -> String {
+        let (command, arg) = match line.split_once(" ") {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (line, ""),
+        };
+        let _span = info_span!("console_command", command).entered();
+        info!("Admin console command: {}", line);
+        match command {
+            "help" => {
+                let mut help = "Commands: list, masters, public <on|off>, say <message>, kick <ID>, ban <ID>, clearbans, mutechat, unmutechat, enablejoin, disablejoin, reset, shutdown [seconds|cancel], quit".to_string();
+                if let Some(game_help) = behaviour.console_help() {
+                    help.push_str("\nGame commands: ");
+                    help.push_str(game_help);
+                }
+                help
+            }
+            "list" => {
+                let lines: Vec<String> = self
+                    .players
+                    .iter()
+                    .map(|(player_index, player)| {
+                        let team = match player.object {
+                            Some((_, team)) => format!("{:?}", team),
+                            None => "Spectating".to_string(),
+                        };
+                        let mut line =
+                            format!("{}: {} ({})", player_index, player.player_name, team);
+                        if let (Some(ping), Some(network_quality)) =
+                            (player.ping_data(), player.network_quality())
+                        {
+                            line.push_str(&format!(
+                                ", ping {:.0} ms, {}",
+                                ping.avg * 1000.0,
+                                network_quality
+                            ));
+                        }
+                        line
+                    })
+                    .collect();
+                if lines.is_empty() {
+                    "No players".to_string()
+                } else {
+                    lines.join("\n")
+                }
+            }
+            "masters" => {
+                let lines: Vec<String> = self
+                    .master_servers
+                    .iter()
+                    .map(|master_server| master_server.status_line())
+                    .collect();
+                if lines.is_empty() {
+                    "Not notifying any master servers".to_string()
+                } else {
+                    lines.join("\n")
+                }
+            }
+            "public" => match arg {
+                "on" => self.set_public_by(&HQMAdminSource::Console, true),
+                "off" => self.set_public_by(&HQMAdminSource::Console, false),
+                _ => "Usage: public <on|off>".to_string(),
+            },
+            "say" => {
+                if arg.is_empty() {
+                    return "Usage: say <message>".to_string();
+                }
+                self.messages.add_server_chat_message(arg.to_string());
+                "Message sent".to_string()
+            }
+            "kick" | "ban" => match arg.parse::<HQMServerPlayerIndex>() {
+                Ok(kick_player_index) => console_response(self.kick_player_by(
+                    &HQMAdminSource::Console,
+                    kick_player_index,
+                    command == "ban",
+                    behaviour,
+                )),
+                Err(_) => format!("Usage: {} <ID>", command),
+            },
+            "clearbans" => self.clear_bans_by(&HQMAdminSource::Console),
+            "mutechat" => self.set_chat_muted_by(&HQMAdminSource::Console, true),
+            "unmutechat" => self.set_chat_muted_by(&HQMAdminSource::Console, false),
+            "enablejoin" => self.set_allow_join_by(&HQMAdminSource::Console, true),
+            "disablejoin" => self.set_allow_join_by(&HQMAdminSource::Console, false),
+            "reset" | "resetgame" => {
+                let values = behaviour.get_initial_game_values();
+                self.reset_game_by(&HQMAdminSource::Console, values);
+                "Game reset".to_string()
+            }
+            "shutdown" => {
+                if arg == "cancel" {
+                    console_response(self.cancel_shutdown_by(&HQMAdminSource::Console))
+                } else {
+                    match parse_shutdown_seconds(arg) {
+                        Ok(seconds) => self.start_shutdown_by(&HQMAdminSource::Console, seconds),
+                        Err(_) => "Usage: shutdown [seconds|cancel]".to_string(),
+                    }
+                }
+            }
+            _ => match behaviour.handle_console_command(self, command, arg) {
+                Some(response) => response,
+                None => format!(
+                    "Unknown command {}, type help for a list of commands",
+                    command
+                ),
+            },
+        }
+    }
+}
+
+/// The text the console prints for a command that can be refused
+pub fn console_response(result: Result<String, String>) -> String {
+    match result {
+        Ok(response) | Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_whole_password_matches() {
+        assert!(password_matches("hunter2", "hunter2"));
+        assert!(!password_matches("hunter", "hunter2"));
+        assert!(!password_matches("hunter22", "hunter2"));
+        assert!(!password_matches("Hunter2", "hunter2"));
+        assert!(!password_matches("", "hunter2"));
+    }
+
+    #[tokio::test]
+    async fn password_line_is_read_up_to_the_limit() {
+        let mut reader: &[u8] = b"hunter2\r\nlist\n";
+        assert_eq!(read_password(&mut reader).await.unwrap(), "hunter2");
+        assert_eq!(reader, b"list\n");
+
+        let long = vec![b'a'; 1000];
+        let mut reader = &long[..];
+        let guess = read_password(&mut reader).await.unwrap();
+        assert_eq!(guess.len(), MAX_PASSWORD_LINE as usize);
+    }
+
+    #[test]
+    fn logins_waiting_for_a_password_count_as_failures() {
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        let now = Instant::now();
+        let mut failed_logins = HQMFailedLogins::default();
+        for _ in 0..MAX_FAILED_LOGINS {
+            assert!(failed_logins.start(ip, now));
+        }
+        assert!(!failed_logins.start(ip, now));
+        assert!(failed_logins.start(IpAddr::from([10, 0, 0, 2]), now));
+
+        // Connections that end without a wrong password don't count
+        failed_logins.end(ip, now, false);
+        assert!(failed_logins.start(ip, now));
+    }
+
+    #[test]
+    fn address_is_blocked_for_the_window_after_the_last_failure() {
+        let ip = IpAddr::from([10, 0, 0, 1]);
+        let start = Instant::now();
+        let mut failed_logins = HQMFailedLogins::default();
+        for i in 0..MAX_FAILED_LOGINS {
+            let now = start + Duration::from_secs(100) * i;
+            assert!(failed_logins.start(ip, now));
+            failed_logins.end(ip, now, true);
+        }
+        let blocked_at = start + Duration::from_secs(100) * (MAX_FAILED_LOGINS - 1);
+        // Longer than the window after the first failure, but not after the block
+        assert!(!failed_logins.start(ip, blocked_at + FAILED_LOGIN_WINDOW / 2));
+        assert!(failed_logins.start(ip, blocked_at + FAILED_LOGIN_WINDOW));
+    }
+}
@@ -0,0 +1,59 @@
+/// Announces a countdown in chat at a few points instead of every second: every full minute,
+/// at 30 and 10 seconds and every second of the last five. The caller owns the timer and passes
+/// the ticks that are left every tick.
+#[derive(Debug, Clone, Default)]
+pub struct HQMChatCountdown {
+    last_seconds: Option<u32>,
+}
+
+impl HQMChatCountdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the seconds left if they should be announced now. Each point is announced once
+    /// when the countdown reaches it, so a paused timer doesn't repeat its last announcement.
+    /// The first update only sets where the countdown starts, its start is announced by the caller.
+    /// A timer of 0 ends the countdown, so that the next update starts a new one.
+    pub fn update(&mut self, ticks_left: u32) -> Option<u32> {
+        if ticks_left == 0 {
+            self.last_seconds = None;
+            return None;
+        }
+        // Rounded up, so that "1 second" is shown during the last second
+        let seconds = (ticks_left - 1) / 100 + 1;
+        if self.last_seconds == Some(seconds) {
+            return None;
+        }
+        let is_first = self.last_seconds.is_none();
+        self.last_seconds = Some(seconds);
+        let is_announced =
+            seconds <= 5 || seconds == 10 || seconds == 30 || seconds / 60 * 60 == seconds;
+        if is_announced && !is_first {
+            Some(seconds)
+        } else {
+            None
+        }
+    }
+}
+
+/// Formats seconds as minutes and seconds, like 1:05
+pub fn format_countdown(seconds: u32) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_is_announced_at_a_few_points() {
+        let mut countdown = HQMChatCountdown::new();
+        let announced: Vec<u32> = (0..=6100)
+            .rev()
+            .filter_map(|ticks_left| countdown.update(ticks_left))
+            .collect();
+        assert_eq!(announced, vec![60, 30, 10, 5, 4, 3, 2, 1]);
+        assert_eq!(format_countdown(65), "1:05");
+    }
+}
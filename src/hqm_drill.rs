@@ -0,0 +1,396 @@
+use crate::hqm_puck_machine::{load_preset, HQMPuckMachine};
+use migo_hqm_server::hqm_game::HQMObjectIndex;
+use migo_hqm_server::hqm_server::{HQMServer, HQMServerPlayerIndex};
+use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
+use nalgebra::{Point3, Rotation3, Vector3};
+use serde::Deserialize;
+use std::path::PathBuf;
+use tracing::{info, warn};
+
+/// A sequence of steps that the players on the ice have to complete together, each within its time limit
+#[derive(Debug, Deserialize)]
+pub struct HQMDrill {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub steps: Vec<HQMDrillStep>,
+}
+
+impl HQMDrill {
+    /// Pucks stay on the ice until the drill ends, so this is the total over all steps
+    pub fn puck_count(&self) -> usize {
+        self.steps.iter().map(|step| step.pucks.len()).sum()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HQMDrillStep {
+    /// Shown in chat when the step starts
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Pucks that are added when the step starts
+    #[serde(default)]
+    pub pucks: Vec<HQMDrillPuck>,
+    pub target: HQMDrillTarget,
+    /// Measured in seconds
+    pub time_limit: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HQMDrillPuck {
+    /// Measured in meters, x across the rink, y up and z along the rink
+    pub position: [f32; 3],
+    /// Measured in meters per second
+    #[serde(default)]
+    pub velocity: [f32; 3],
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HQMDrillTarget {
+    /// A puck has to get within the radius of a point on the ice, given as x and z
+    PuckInZone { position: [f32; 2], radius: f32 },
+    /// A skater has to get within the radius of a point on the ice, given as x and z
+    SkaterInZone { position: [f32; 2], radius: f32 },
+    /// A puck has to go into either net
+    Goal,
+}
+
+impl HQMDrillPuck {
+    /// Creates the puck with its velocity, if there is a free slot for it
+    pub fn spawn(&self, server: &mut HQMServer) -> Option<HQMObjectIndex> {
+        let [x, y, z] = self.position;
+        let object_index = server
+            .world
+            .create_puck_object(Point3::new(x, y, z), Rotation3::identity())?;
+        if let Some(puck) = server.world.objects.get_puck_mut(object_index) {
+            let [vx, vy, vz] = self.velocity;
+            puck.body.linear_velocity = Vector3::new(vx, vy, vz) / 100.0;
+        }
+        Some(object_index)
+    }
+}
+
+/// Names of drills and other files that are loaded by name can't leave their directory
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Reads drills/<name>.json, the error is short enough for a chat message
+pub fn load_drill(name: &str) -> Result<HQMDrill, String> {
+    if !is_valid_name(name) {
+        return Err(format!("Invalid drill name {}", name));
+    }
+    let path = PathBuf::from("drills").join(format!("{}.json", name));
+    let data = std::fs::read_to_string(&path).map_err(|e| {
+        warn!("Could not read drill {}: {}", path.display(), e);
+        format!("Could not read drill {}", name)
+    })?;
+    let drill: HQMDrill = serde_json::from_str(&data).map_err(|e| {
+        warn!("Invalid drill {}: {}", path.display(), e);
+        format!("Drill {} is invalid, see the server log", name)
+    })?;
+    if drill.steps.is_empty() {
+        return Err(format!("Drill {} has no steps", name));
+    }
+    if let Some(i) = drill.steps.iter().position(|step| step.time_limit <= 0.0) {
+        return Err(format!(
+            "Step {} of drill {} needs a positive time limit",
+            i + 1,
+            name
+        ));
+    }
+    Ok(drill)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HQMDrillStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+fn format_ticks(ticks: u32) -> String {
+    format!("{}.{:02} s", ticks / 100, ticks % 100)
+}
+
+fn is_in_zone(pos: &Point3<f32>, position: [f32; 2], radius: f32) -> bool {
+    let dx = pos.x - position[0];
+    let dz = pos.z - position[1];
+    dx * dx + dz * dz <= radius * radius
+}
+
+pub struct HQMDrillRun {
+    drill: HQMDrill,
+    step: usize,
+    step_start: u32,
+    step_times: Vec<u32>,
+}
+
+impl HQMDrillRun {
+    /// Removes all pucks and starts the first step
+    pub fn start(drill: HQMDrill, server: &mut HQMServer) -> Self {
+        server.world.clear_pucks();
+        let msg = format!("Drill {} started", drill.name);
+        server.messages.add_server_chat_message(msg);
+        if let Some(description) = &drill.description {
+            server
+                .messages
+                .add_server_chat_message(description.to_string());
+        }
+        let mut run = HQMDrillRun {
+            drill,
+            step: 0,
+            step_start: 0,
+            step_times: vec![],
+        };
+        run.start_step(server);
+        run
+    }
+
+    pub fn name(&self) -> &str {
+        &self.drill.name
+    }
+
+    fn start_step(&mut self, server: &mut HQMServer) {
+        self.step_start = server.game_step;
+        let step = &self.drill.steps[self.step];
+        for puck in step.pucks.iter() {
+            puck.spawn(server);
+        }
+        if let Some(message) = &step.message {
+            let msg = format!(
+                "Step {}/{}: {}",
+                self.step + 1,
+                self.drill.steps.len(),
+                message
+            );
+            server.messages.add_server_chat_message(msg);
+        }
+    }
+
+    fn is_target_reached(&self, server: &HQMServer, events: &[HQMSimulationEvent]) -> bool {
+        match self.drill.steps[self.step].target {
+            HQMDrillTarget::PuckInZone { position, radius } => server
+                .world
+                .objects
+                .pucks()
+                .any(|(_, puck)| is_in_zone(&puck.body.pos, position, radius)),
+            HQMDrillTarget::SkaterInZone { position, radius } => {
+                server.players.iter().any(|(_, player)| {
+                    let skater = player
+                        .object
+                        .and_then(|(i, _)| server.world.objects.get_skater(i));
+                    matches!(skater, Some(skater) if is_in_zone(&skater.body.pos, position, radius))
+                })
+            }
+            HQMDrillTarget::Goal => events
+                .iter()
+                .any(|event| matches!(event, HQMSimulationEvent::PuckEnteredNet { .. })),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        server: &mut HQMServer,
+        events: &[HQMSimulationEvent],
+    ) -> HQMDrillStatus {
+        let elapsed = server.game_step.wrapping_sub(self.step_start);
+        if self.is_target_reached(server, events) {
+            self.step_times.push(elapsed);
+            self.step += 1;
+            if self.step == self.drill.steps.len() {
+                self.report_completed(server);
+                return HQMDrillStatus::Completed;
+            }
+            self.start_step(server);
+        } else if elapsed as f32 > self.drill.steps[self.step].time_limit * 100.0 {
+            self.report_failed(server);
+            return HQMDrillStatus::Failed;
+        }
+        HQMDrillStatus::Running
+    }
+
+    fn report_completed(&self, server: &mut HQMServer) {
+        let total = self.step_times.iter().sum();
+        let step_times = self
+            .step_times
+            .iter()
+            .map(|&ticks| format_ticks(ticks))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "Drill {} completed in {}, steps: {}",
+            self.drill.name,
+            format_ticks(total),
+            step_times
+        );
+        let msg = format!(
+            "Drill {} completed in {}",
+            self.drill.name,
+            format_ticks(total)
+        );
+        server.messages.add_server_chat_message(msg);
+        for (i, &ticks) in self.step_times.iter().enumerate() {
+            let msg = format!("Step {}: {}", i + 1, format_ticks(ticks));
+            server.messages.add_server_chat_message(msg);
+        }
+    }
+
+    fn report_failed(&self, server: &mut HQMServer) {
+        info!(
+            "Drill {} failed at step {} after {} completed steps",
+            self.drill.name,
+            self.step + 1,
+            self.step_times.len()
+        );
+        let msg = format!(
+            "Drill {} failed, step {} took too long",
+            self.drill.name,
+            self.step + 1
+        );
+        server.messages.add_server_chat_message(msg);
+    }
+}
+
+/// The drill and the puck machine that admins run with /drill, in the modes that have them
+#[derive(Default)]
+pub struct HQMDrillControl {
+    drill: Option<HQMDrillRun>,
+    machine: Option<HQMPuckMachine>,
+}
+
+impl HQMDrillControl {
+    /// Handles /drill. Drills may use up to drill_pucks pucks. respawn_pucks puts the pucks of
+    /// the mode back after a drill, which removes all pucks
+    pub fn command(
+        &mut self,
+        server: &mut HQMServer,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+        drill_pucks: usize,
+        respawn_pucks: impl FnOnce(&mut HQMServer),
+    ) {
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                let player_name = player.player_name.clone();
+                let (command, name) = arg.split_once(' ').unwrap_or((arg, ""));
+                match command {
+                    "load" => match load_drill(name.trim()) {
+                        Ok(drill) if drill.puck_count() > drill_pucks => {
+                            let msg = format!(
+                                "Drill {} needs {} pucks, there are {}",
+                                drill.name,
+                                drill.puck_count(),
+                                drill_pucks
+                            );
+                            server
+                                .messages
+                                .add_directed_server_chat_message(msg, player_index);
+                        }
+                        Ok(drill) => {
+                            info!(
+                                "{} ({}) started drill {}",
+                                player_name, player_index, drill.name
+                            );
+                            // The drill removes all pucks, including those of the machine
+                            self.machine = None;
+                            self.drill = Some(HQMDrillRun::start(drill, server));
+                        }
+                        Err(e) => {
+                            server
+                                .messages
+                                .add_directed_server_chat_message(e, player_index);
+                        }
+                    },
+                    "start" => match load_preset(name.trim(), &server.world.rink) {
+                        Ok(preset) => {
+                            if self.drill.take().is_some() {
+                                server.world.clear_pucks();
+                                respawn_pucks(server);
+                            }
+                            if let Some(machine) = self.machine.take() {
+                                machine.stop(server);
+                            }
+                            info!(
+                                "{} ({}) started puck machine {}",
+                                player_name, player_index, preset.name
+                            );
+                            self.machine = Some(HQMPuckMachine::start(preset, server));
+                        }
+                        Err(e) => {
+                            server
+                                .messages
+                                .add_directed_server_chat_message(e, player_index);
+                        }
+                    },
+                    "stop" => {
+                        if let Some(machine) = self.machine.take() {
+                            info!(
+                                "{} ({}) stopped puck machine {}",
+                                player_name,
+                                player_index,
+                                machine.name()
+                            );
+                            let msg = format!(
+                                "Puck machine {} stopped by {}",
+                                machine.name(),
+                                player_name
+                            );
+                            server.messages.add_server_chat_message(msg);
+                            machine.stop(server);
+                        }
+                        if let Some(drill) = self.drill.take() {
+                            info!(
+                                "{} ({}) stopped drill {}",
+                                player_name,
+                                player_index,
+                                drill.name()
+                            );
+                            let msg = format!("Drill {} stopped by {}", drill.name(), player_name);
+                            server.messages.add_server_chat_message(msg);
+                            server.world.clear_pucks();
+                            respawn_pucks(server);
+                        }
+                    }
+                    _ => {
+                        server.messages.add_directed_server_chat_message(
+                            "Usage: /drill load <name>, /drill start <preset> or /drill stop",
+                            player_index,
+                        );
+                    }
+                }
+            } else {
+                server.admin_deny_message(player_index);
+            }
+        }
+    }
+
+    /// Called after every step. respawn_pucks puts the pucks of the mode back after a drill
+    pub fn update(
+        &mut self,
+        server: &mut HQMServer,
+        events: &[HQMSimulationEvent],
+        respawn_pucks: impl FnOnce(&mut HQMServer),
+    ) {
+        if let Some(machine) = &mut self.machine {
+            machine.update(server);
+        }
+        if let Some(drill) = &mut self.drill {
+            if drill.update(server, events) != HQMDrillStatus::Running {
+                self.drill = None;
+                server.world.clear_pucks();
+                respawn_pucks(server);
+            }
+        }
+    }
+
+    /// Forgets the drill and the machine, when a new game removed their pucks
+    pub fn clear(&mut self) {
+        self.drill = None;
+        self.machine = None;
+    }
+}
@@ -0,0 +1,298 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::Utc;
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::error::ProtocolError;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::hqm_game::HQMGameValues;
+use crate::hqm_server::HQMTeam;
+
+/// Version of the JSON messages of the event feed, the webhooks and the HTTP API. It's raised
+/// when fields are removed or change meaning, fields can be added without raising it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Number of events a slow feed client can fall behind before it starts missing events
+const EVENT_FEED_CAPACITY: usize = 256;
+
+/// An event sent to event feed clients, as a JSON object with a type field
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HQMEvent {
+    Join {
+        player_index: usize,
+        name: String,
+    },
+    Exit {
+        player_index: usize,
+        name: String,
+    },
+    /// Only public chat, not commands or team chat
+    Chat {
+        player_index: usize,
+        name: String,
+        message: String,
+    },
+    Goal {
+        team: HQMTeam,
+        scorer: Option<String>,
+        assist: Option<String>,
+        red_score: u32,
+        blue_score: u32,
+        period: u32,
+        /// Time left of the period, measured in hundredths of a second
+        time: u32,
+        /// Speed of the puck across the goal line, in meters per second
+        speed: f32,
+        /// Speed of the puck when it left the stick of the scorer, in meters per second
+        shot_speed: Option<f32>,
+        /// Where the puck left the stick of the scorer
+        shot_position: Option<HQMIcePosition>,
+        /// None after the deciding goal
+        red_win_probability: Option<f32>,
+        strength: HQMGoalStrength,
+    },
+    Faceoff {
+        /// The team that touched the puck first
+        team: HQMTeam,
+        /// Centers of the winning and the losing team, None if a team had no center
+        winner: Option<String>,
+        loser: Option<String>,
+        period: u32,
+        /// Time left of the period, measured in hundredths of a second
+        time: u32,
+    },
+    /// A goalie stopped a shot from the other team
+    Save {
+        /// The team that shot
+        team: HQMTeam,
+        /// None if the shooter has left
+        shooter: Option<String>,
+        goalie: String,
+        /// Where the puck left the stick of the shooter
+        shot_position: HQMIcePosition,
+        period: u32,
+        /// Time left of the period, measured in hundredths of a second
+        time: u32,
+    },
+    /// A skater ran into a skater of the other team hard enough for a hit
+    Hit {
+        /// The team of the player who hit
+        team: HQMTeam,
+        hitter: String,
+        target: String,
+        /// Speed at which the skaters came together, in meters per second
+        impact: f32,
+        period: u32,
+        /// Time left of the period, measured in hundredths of a second
+        time: u32,
+    },
+    RuleCall {
+        rule: HQMRuleCall,
+        team: HQMTeam,
+        /// The player who broke the rule, None if the player has left
+        player: Option<String>,
+        period: u32,
+        /// Time left of the period, measured in hundredths of a second
+        time: u32,
+    },
+    /// A player was sent to the penalty box by an admin
+    Penalty {
+        team: HQMTeam,
+        player: String,
+        /// Length of the penalty in minutes of game time
+        minutes: u32,
+        period: u32,
+        /// Time left of the period, measured in hundredths of a second
+        time: u32,
+    },
+    /// An attempt of the shootout after overtime
+    ShootoutAttempt {
+        team: HQMTeam,
+        player: Option<String>,
+        goal: bool,
+        round: u32,
+        /// Goals of each team in the shootout so far
+        red_goals: u32,
+        blue_goals: u32,
+    },
+    Period {
+        period: u32,
+    },
+    GameOver {
+        red_score: u32,
+        blue_score: u32,
+        /// ID of the game in the statistics database and on the statistics pages
+        game_uuid: String,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HQMRuleCall {
+    Offside,
+    TwoLinePass,
+    Icing,
+    HighStick,
+    HandPass,
+    BodyGoal,
+    NetDislodged,
+    PuckOutOfPlay,
+}
+
+/// Players on the ice of the scoring team compared to the other team, by who is in the
+/// penalty box
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HQMGoalStrength {
+    Even,
+    PowerPlay,
+    Shorthanded,
+}
+
+/// A spot on the ice in meters from the corner of the rink, x across the rink and z along it
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HQMIcePosition {
+    pub x: f32,
+    pub z: f32,
+}
+
+#[derive(Serialize)]
+struct HQMEventMessage<'a> {
+    version: u32,
+    game_id: u32,
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a HQMEvent,
+}
+
+pub(crate) struct HQMEventFeed {
+    sender: Option<broadcast::Sender<Arc<str>>>,
+    period: u32,
+    game_over: bool,
+}
+
+impl HQMEventFeed {
+    pub(crate) fn new() -> Self {
+        HQMEventFeed {
+            sender: None,
+            period: 0,
+            game_over: false,
+        }
+    }
+
+    /// Starts publishing events, the returned sender is used to subscribe new clients.
+    /// The event feed and webhooks share the same sender.
+    pub(crate) fn enable(&mut self) -> broadcast::Sender<Arc<str>> {
+        self.sender
+            .get_or_insert_with(|| broadcast::channel(EVENT_FEED_CAPACITY).0)
+            .clone()
+    }
+
+    pub(crate) fn publish(&self, game_id: u32, event: HQMEvent) {
+        if let Some(sender) = &self.sender {
+            if sender.receiver_count() == 0 {
+                return;
+            }
+            let message = HQMEventMessage {
+                version: SCHEMA_VERSION,
+                game_id,
+                timestamp: Utc::now().to_rfc3339(),
+                event: &event,
+            };
+            match serde_json::to_string(&message) {
+                Ok(json) => {
+                    let _ = sender.send(json.into());
+                }
+                Err(e) => {
+                    warn!("Could not serialize event {:?}: {}", event, e);
+                }
+            }
+        }
+    }
+
+    /// Publishes period changes and the end of the game, whichever mode changed them
+    pub(crate) fn update_values(&mut self, game_id: u32, game_uuid: Uuid, values: &HQMGameValues) {
+        if values.period != self.period {
+            self.period = values.period;
+            self.publish(
+                game_id,
+                HQMEvent::Period {
+                    period: values.period,
+                },
+            );
+        }
+        if values.game_over != self.game_over {
+            self.game_over = values.game_over;
+            if values.game_over {
+                self.publish(
+                    game_id,
+                    HQMEvent::GameOver {
+                        red_score: values.red_score,
+                        blue_score: values.blue_score,
+                        game_uuid: game_uuid.to_string(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+pub(crate) async fn run_event_feed(
+    address: SocketAddr,
+    sender: broadcast::Sender<Arc<str>>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Event feed listening at address {:?}",
+        listener.local_addr()?
+    );
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let receiver = sender.subscribe();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, addr, receiver).await {
+                warn!("Event feed connection from {:?} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    addr: SocketAddr,
+    mut receiver: broadcast::Receiver<Arc<str>>,
+) -> Result<(), tungstenite::Error> {
+    let (mut writer, mut reader) = tokio_tungstenite::accept_async(stream).await?.split();
+    info!("Event feed connection from {:?}", addr);
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => writer.send(Message::Text(event.to_string())).await?,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Event feed connection from {:?} missed {} events", addr, skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // Clients aren't expected to send anything, but reading answers pings and notices closes
+            message = reader.next() => match message {
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Err(tungstenite::Error::Protocol(
+                    ProtocolError::ResetWithoutClosingHandshake,
+                ))) => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+        }
+    }
+    info!("Event feed connection from {:?} closed", addr);
+    Ok(())
+}
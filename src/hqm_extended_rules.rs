@@ -0,0 +1,23 @@
+use crate::hqm_game::HQMGameValues;
+
+/// Packet type of requests for extended rules
+pub(crate) const EXTENDED_RULES: u8 = 0x44;
+
+/// Version of the extended rules bits. Clients send the version they understand in their
+/// request
+pub(crate) const EXTENDED_RULES_VERSION: u32 = 1;
+
+/// Bits added to the rules of the update packets of clients that asked for extended rules.
+/// Bit 4 is set while red is on the power play and bit 5 while blue is, and the number of
+/// players of each team in the penalty box are bits 8 to 11 for red and 12 to 15 for blue
+pub(crate) fn extended_rules_bits(values: &HQMGameValues) -> u32 {
+    let red = values.red_penalized.min(15);
+    let blue = values.blue_penalized.min(15);
+    let mut bits = (red << 8) | (blue << 12);
+    if red < blue {
+        bits |= 16;
+    } else if blue < red {
+        bits |= 32;
+    }
+    bits
+}
@@ -1,3 +1,4 @@
+use crate::hqm_object_selection::NETWORK_OBJECT_SLOTS;
 use crate::hqm_parse;
 use nalgebra::{point, Matrix3, Point3, Rotation3, Unit, Vector2, Vector3};
 
@@ -6,24 +7,56 @@ use std::fmt::Formatter;
 use crate::hqm_game::HQMRinkSideOfLine::{BlueSide, On, RedSide};
 use crate::hqm_parse::{HQMPuckPacket, HQMSkaterPacket};
 use arr_macro::arr;
-use std::f32::consts::PI;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_8, PI};
+
+/// Pucks beyond this number are kept in extra slots after the network slots, so that the network
+/// slots are left for skaters. Clients see the extra pucks when they are close to them
+pub const MAX_NETWORK_PUCK_SLOTS: usize = 16;
+
+/// Most pucks a world can have. Extra puck slots are added when they are needed, up to this number
+pub const MAX_PUCKS: usize = 64;
+
+/// Whether a number of pucks and two teams of team_max skaters fit in the network slots together
+pub fn objects_fit(pucks: usize, team_max: usize) -> bool {
+    pucks.min(MAX_NETWORK_PUCK_SLOTS) + team_max * 2 <= NETWORK_OBJECT_SLOTS
+}
 
 pub struct HQMGameWorld {
     pub objects: HQMGameWorldObjectList,
     pub puck_slots: usize,
     pub rink: HQMRink,
-    pub physics_config: HQMPhysicsConfiguration,
+    pub physics_config: HQMPhysicsProfile,
+    /// Pucks and skaters that couldn't be created because there was no free slot for them,
+    /// until the server has reported them
+    pub(crate) failed_pucks: usize,
+    pub(crate) failed_skaters: usize,
+    /// Raised whenever the pucks are cleared, so that pucks remembered by their slot aren't
+    /// mistaken for new pucks in the same slots
+    pub(crate) puck_clears: u32,
 }
 
 impl HQMGameWorld {
-    pub(crate) fn new(puck_slots: usize, physics_config: HQMPhysicsConfiguration) -> Self {
+    pub(crate) fn new(
+        puck_slots: usize,
+        physics_config: HQMPhysicsProfile,
+        rink_config: &HQMRinkConfiguration,
+    ) -> Self {
+        let puck_slots = puck_slots.min(MAX_PUCKS);
         HQMGameWorld {
             objects: HQMGameWorldObjectList {
-                objects: vec![HQMGameObject::None; 32],
+                objects: vec![
+                    HQMGameObject::None;
+                    NETWORK_OBJECT_SLOTS
+                        + puck_slots.saturating_sub(MAX_NETWORK_PUCK_SLOTS)
+                ],
             },
             puck_slots,
-            rink: HQMRink::new(30.0, 61.0, 8.5),
+            rink: HQMRink::new(rink_config),
             physics_config,
+            failed_pucks: 0,
+            failed_skaters: 0,
+            puck_clears: 0,
         }
     }
 }
@@ -33,6 +66,16 @@ pub struct HQMGameWorldObjectList {
 }
 
 impl HQMGameWorldObjectList {
+    pub fn pucks(&self) -> impl Iterator<Item = (HQMObjectIndex, &HQMPuck)> + '_ {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter_map(|(i, object)| match object {
+                HQMGameObject::Puck(puck) => Some((HQMObjectIndex(i), puck)),
+                _ => None,
+            })
+    }
+
     pub fn get_puck(&self, HQMObjectIndex(object_index): HQMObjectIndex) -> Option<&HQMPuck> {
         if let Some(HQMGameObject::Puck(puck)) = self.objects.get(object_index) {
             Some(puck)
@@ -84,6 +127,8 @@ impl HQMGameWorld {
         if let Some(i) = object_slot {
             self.objects.objects[i.0] =
                 HQMGameObject::Player(HQMSkater::new(start, rot, hand, mass));
+        } else {
+            self.failed_skaters += 1;
         }
         return object_slot;
     }
@@ -93,15 +138,43 @@ impl HQMGameWorld {
         start: Point3<f32>,
         rot: Rotation3<f32>,
     ) -> Option<HQMObjectIndex> {
-        let object_slot = self.find_empty_puck_slot();
+        let object_slot = self.find_empty_puck_slot().or_else(|| self.add_puck_slot());
         if let Some(i) = object_slot {
             self.objects.objects[i.0] = HQMGameObject::Puck(HQMPuck::new(start, rot));
+        } else {
+            self.failed_pucks += 1;
         }
         return object_slot;
     }
 
+    /// Creates pucks in a line across center ice, and returns how many were created
+    pub fn create_puck_line(&mut self, count: usize, altitude: f32) -> usize {
+        let center = self.rink.center_ice();
+        let start = center.x - 0.4 * (count.saturating_sub(1) as f32);
+        let mut created = 0;
+        for i in 0..count {
+            let pos = Point3::new(start + 0.8 * (i as f32), altitude, center.z);
+            if self
+                .create_puck_object(pos, Rotation3::identity())
+                .is_none()
+            {
+                break;
+            }
+            created += 1;
+        }
+        created
+    }
+
+    fn network_puck_slots(&self) -> usize {
+        self.puck_slots.min(MAX_NETWORK_PUCK_SLOTS)
+    }
+
+    fn puck_slot_indices(&self) -> impl Iterator<Item = usize> {
+        (0..self.network_puck_slots()).chain(NETWORK_OBJECT_SLOTS..self.objects.objects.len())
+    }
+
     fn find_empty_puck_slot(&self) -> Option<HQMObjectIndex> {
-        for i in 0..self.puck_slots {
+        for i in self.puck_slot_indices() {
             if let HQMGameObject::None = self.objects.objects[i] {
                 return Some(HQMObjectIndex(i));
             }
@@ -109,8 +182,22 @@ impl HQMGameWorld {
         None
     }
 
+    /// Adds an extra slot after the network slots, if the world doesn't have the most pucks yet.
+    /// Slots are never removed during a game, removed pucks leave free slots that are used again.
+    fn add_puck_slot(&mut self) -> Option<HQMObjectIndex> {
+        if self.objects.pucks().count() >= MAX_PUCKS {
+            return None;
+        }
+        self.objects.objects.push(HQMGameObject::None);
+        Some(HQMObjectIndex(self.objects.objects.len() - 1))
+    }
+
     fn find_empty_player_slot(&self) -> Option<HQMObjectIndex> {
-        for i in self.puck_slots..self.objects.objects.len() {
+        // Skaters have to be in the network slots, clients link players to them by slot.
+        // When the skater slots are full, unused puck slots are taken from the end,
+        // pucks created after that go to the extra slots.
+        let skater_slots = self.network_puck_slots()..NETWORK_OBJECT_SLOTS;
+        for i in skater_slots.chain((0..self.network_puck_slots()).rev()) {
             if let HQMGameObject::None = self.objects.objects[i] {
                 return Some(HQMObjectIndex(i));
             }
@@ -119,8 +206,12 @@ impl HQMGameWorld {
     }
 
     pub fn clear_pucks(&mut self) {
-        for x in self.objects.objects[0..self.puck_slots].iter_mut() {
-            *x = HQMGameObject::None;
+        self.puck_clears = self.puck_clears.wrapping_add(1);
+        // Puck slots can also have skaters that didn't fit in the skater slots
+        for i in self.puck_slot_indices() {
+            if let r @ HQMGameObject::Puck(_) = &mut self.objects.objects[i] {
+                *r = HQMGameObject::None;
+            }
         }
     }
 
@@ -152,23 +243,112 @@ pub struct HQMGameValues {
     pub period: u32,
     pub time: u32,
     pub goal_message_timer: u32,
+    /// Players of each team in the penalty box
+    pub red_penalized: u32,
+    pub blue_penalized: u32,
+    /// Red defends the blue end of the rink and the other way around
+    pub ends_swapped: bool,
 
     pub game_over: bool,
 }
 
-#[derive(Debug, Clone)]
-pub struct HQMPhysicsConfiguration {
+/// Tunable physics constants. Speeds are in meters per tick and accelerations in meters per tick squared,
+/// with 100 ticks per second
+#[derive(Debug, Clone, PartialEq)]
+pub struct HQMPhysicsProfile {
     pub gravity: f32,
+    /// Jumping can't make a skater move upwards faster than [`jump_speed`](Self::jump_speed)
     pub limit_jump_speed: bool,
     pub player_acceleration: f32,
+    /// Acceleration used when skating against the current direction of movement
     pub player_deceleration: f32,
     pub max_player_speed: f32,
+    /// Friction between the puck and the rink, both ice and boards
     pub puck_rink_friction: f32,
+    /// How hard the ice and boards push back a puck that sinks into them, so how much it bounces
+    pub puck_rink_bounce: f32,
+    /// Share of the force between a stick and the puck that is passed on to the puck
+    pub stick_puck_force: f32,
     pub player_turning: f32,
     pub player_shift_acceleration: f32,
     pub max_player_shift_speed: f32,
     pub player_shift_turning: f32,
+    /// Upwards speed added when a skater jumps
+    pub jump_speed: f32,
+    /// Puck speed lost per tick, relative to the square of the speed
+    pub puck_air_drag: f32,
 }
+
+/// The name physics constants had before presets were added
+pub type HQMPhysicsConfiguration = HQMPhysicsProfile;
+
+impl HQMPhysicsProfile {
+    pub const PRESETS: [&'static str; 3] = ["classic", "fast-ice", "arcade"];
+
+    /// The physics of the original game
+    pub fn classic() -> Self {
+        HQMPhysicsProfile {
+            gravity: 0.000680555,
+            limit_jump_speed: false,
+            player_acceleration: 0.000208333,
+            player_deceleration: 0.000555555,
+            max_player_speed: 0.05,
+            puck_rink_friction: 0.05,
+            puck_rink_bounce: 0.5,
+            stick_puck_force: 0.75,
+            player_turning: 0.00041666666,
+            player_shift_acceleration: 0.00027777,
+            max_player_shift_speed: 0.0333333,
+            player_shift_turning: 0.00038888888,
+            jump_speed: 0.025,
+            puck_air_drag: 0.015625,
+        }
+    }
+
+    /// Faster skating and a puck that glides further
+    pub fn fast_ice() -> Self {
+        HQMPhysicsProfile {
+            player_acceleration: 0.00025,
+            max_player_speed: 0.06,
+            puck_rink_friction: 0.03,
+            max_player_shift_speed: 0.04,
+            puck_air_drag: 0.01,
+            ..Self::classic()
+        }
+    }
+
+    /// Quick turns, high jumps and low gravity
+    pub fn arcade() -> Self {
+        HQMPhysicsProfile {
+            gravity: 0.0005,
+            player_acceleration: 0.0003,
+            player_deceleration: 0.0007,
+            max_player_speed: 0.065,
+            player_turning: 0.0005,
+            player_shift_acceleration: 0.00035,
+            max_player_shift_speed: 0.045,
+            player_shift_turning: 0.00045,
+            jump_speed: 0.035,
+            ..Self::classic()
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(Self::classic()),
+            "fast-ice" => Some(Self::fast_ice()),
+            "arcade" => Some(Self::arcade()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for HQMPhysicsProfile {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
 impl Default for HQMGameValues {
     fn default() -> Self {
         HQMGameValues {
@@ -181,6 +361,9 @@ impl Default for HQMGameValues {
             period: 0,
             time: 30000,
             goal_message_timer: 0,
+            red_penalized: 0,
+            blue_penalized: 0,
+            ends_swapped: false,
             game_over: false,
         }
     }
@@ -220,15 +403,31 @@ pub struct HQMRinkNet {
     pub(crate) normal: Vector3<f32>,
     pub(crate) left_post_inside: Vector3<f32>,
     pub(crate) right_post_inside: Vector3<f32>,
+    /// How far the net was knocked from its place
+    pub(crate) offset: Vector3<f32>,
 }
 
 impl HQMRinkNet {
-    fn new(pos: Point3<f32>, rot: Matrix3<f32>) -> Self {
-        let front_width = 3.0;
-        let back_width = 2.5;
+    /// Where a goalie stands, a meter in front of the middle of the goal line
+    pub fn goalie_position(&self, altitude: f32) -> Point3<f32> {
+        self.in_front(1.0, altitude)
+    }
+
+    /// A position on the ice in front of the goal line
+    pub fn in_front(&self, distance: f32, altitude: f32) -> Point3<f32> {
+        let pos = self.goal_line_center() + self.normal * distance;
+        Point3::new(pos.x, altitude, pos.z)
+    }
+
+    /// The middle of the goal line where the net is moored, also while it's dislodged
+    pub fn goal_line_center(&self) -> Point3<f32> {
+        nalgebra::center(&self.left_post, &self.right_post) - self.offset
+    }
+
+    fn new(pos: Point3<f32>, rot: Matrix3<f32>, front_width: f32, height: f32) -> Self {
+        let back_width = front_width - 0.5;
         let front_half_width = front_width / 2.0;
         let back_half_width = back_width / 2.0;
-        let height = 1.0;
         let upper_depth = 0.75;
         let lower_depth = 1.0;
 
@@ -297,6 +496,129 @@ impl HQMRinkNet {
             normal: rot * Vector3::z(),
             left_post_inside: &rot * Vector3::x(),
             right_post_inside: &rot * -Vector3::x(),
+            offset: Vector3::zeros(),
+        }
+    }
+
+    pub fn is_dislodged(&self) -> bool {
+        self.offset != Vector3::zeros()
+    }
+
+    /// Moves the whole net, posts and mesh
+    pub(crate) fn shift(&mut self, offset: Vector3<f32>) {
+        for (a, b, _) in self.posts.iter_mut() {
+            *a += offset;
+            *b += offset;
+        }
+        for (a, b, c, d) in self.surfaces.iter_mut() {
+            for p in [a, b, c, d] {
+                *p += offset;
+            }
+        }
+        self.left_post += offset;
+        self.right_post += offset;
+        self.offset += offset;
+    }
+
+    /// Puts the net back in its place
+    pub fn reset(&mut self) {
+        let offset = -self.offset;
+        self.shift(offset);
+        self.offset = Vector3::zeros();
+    }
+}
+
+/// The goal creases are semicircles around the middle of each goal line, cut off at a depth
+/// from the goal line
+#[derive(Debug, Clone)]
+pub struct HQMRinkCreases {
+    /// Radius of the goal creases in meters
+    pub goal_radius: f32,
+    /// Distance from the goal line where the goal creases end, in meters
+    pub goal_depth: f32,
+}
+
+impl Default for HQMRinkCreases {
+    fn default() -> Self {
+        HQMRinkCreases {
+            goal_radius: 1.83, // IIHF rule 17viii
+            goal_depth: 1.83,  // IIHF rule 17viii, the whole semicircle
+        }
+    }
+}
+
+/// Dimensions of the rink in meters. The defaults are the rink the clients draw. Rink files
+/// are this in JSON, with goal_line and blue_line as names of the distances
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HQMRinkConfiguration {
+    pub width: f32,
+    pub length: f32,
+    pub corner_radius: f32,
+    /// Width of the goals between the posts
+    pub goal_width: f32,
+    pub goal_height: f32,
+    /// Distance from the end boards to the middle of the goal lines
+    #[serde(rename = "goal_line")]
+    pub goal_line_distance: f32,
+    /// Distance from the end boards to the edge of the blue lines on the neutral zone side
+    #[serde(rename = "blue_line")]
+    pub blue_line_distance: f32,
+    /// Middle of the goal line of the red and blue nets, given as x and z. In the middle of the
+    /// goal lines if not set
+    pub red_net: Option<[f32; 2]>,
+    pub blue_net: Option<[f32; 2]>,
+    /// Position of the center line along the rink, in the middle if not set
+    pub center_line: Option<f32>,
+    /// Placed by the goal and blue lines if not set
+    pub faceoff_dots: Option<HQMRinkFaceoffDots>,
+    /// Height of the boards and glass, pucks that hit the boards higher up are out of play. The
+    /// boards have no top if not set
+    pub boards_height: Option<f32>,
+}
+
+/// Faceoff dots given as x and z. Of each pair, the first is the one on the left side, where x
+/// is lower
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HQMRinkFaceoffDots {
+    pub center: [f32; 2],
+    pub red_zone: [[f32; 2]; 2],
+    pub blue_zone: [[f32; 2]; 2],
+    pub red_neutral: [[f32; 2]; 2],
+    pub blue_neutral: [[f32; 2]; 2],
+}
+
+impl HQMRinkFaceoffDots {
+    pub fn iter(&self) -> impl Iterator<Item = &[f32; 2]> {
+        std::iter::once(&self.center).chain(
+            [
+                &self.red_zone,
+                &self.blue_zone,
+                &self.red_neutral,
+                &self.blue_neutral,
+            ]
+            .into_iter()
+            .flatten(),
+        )
+    }
+}
+
+impl Default for HQMRinkConfiguration {
+    fn default() -> Self {
+        HQMRinkConfiguration {
+            width: 30.0,
+            length: 61.0,
+            corner_radius: 8.5,
+            goal_width: 3.0,
+            goal_height: 1.0,
+            goal_line_distance: 4.0,   // IIHF rule 17iv
+            blue_line_distance: 22.86, // IIHF rule 17v and 17vi
+            red_net: None,
+            blue_net: None,
+            center_line: None,
+            faceoff_dots: None,
+            boards_height: None,
         }
     }
 }
@@ -312,10 +634,40 @@ pub struct HQMRink {
     pub blue_zone_blue_line: HQMRinkLine,
     pub width: f32,
     pub length: f32,
+    pub faceoff_dots: HQMRinkFaceoffDots,
+    pub creases: HQMRinkCreases,
+    /// Pucks that hit the boards above this height are out of play
+    pub boards_height: Option<f32>,
+    /// Speed in meters per tick a skater has to hit a net with to knock it off its moorings, or
+    /// 0 if the nets stay in place
+    pub net_dislodge_speed: f32,
 }
 
 impl HQMRink {
-    fn new(width: f32, length: f32, corner_radius: f32) -> Self {
+    /// Center ice, at the center faceoff dot
+    pub fn center_ice(&self) -> Point3<f32> {
+        let [x, z] = self.faceoff_dots.center;
+        Point3::new(x, 0.0, z)
+    }
+
+    /// Direction along the ice from center ice to the goal line of a net
+    pub fn direction_to_net(&self, net: &HQMRinkNet) -> Vector3<f32> {
+        let diff = net.goal_line_center() - self.center_ice();
+        Vector3::new(diff.x, 0.0, diff.z).normalize()
+    }
+
+    /// Whether a position is in the goal crease in front of a net
+    pub fn in_goal_crease(&self, net: &HQMRinkNet, pos: &Point3<f32>) -> bool {
+        let diff = pos - net.goal_line_center();
+        let depth = diff.dot(&net.normal);
+        let distance = Vector2::new(diff.x, diff.z).norm();
+        (0.0..=self.creases.goal_depth).contains(&depth) && distance <= self.creases.goal_radius
+    }
+
+    pub fn new(config: &HQMRinkConfiguration) -> Self {
+        let width = config.width;
+        let length = config.length;
+        let corner_radius = config.corner_radius;
         let zero = Point3::new(0.0, 0.0, 0.0);
         let planes = vec![
             (zero.clone(), Vector3::y_axis()),
@@ -351,25 +703,33 @@ impl HQMRink {
         ];
 
         let line_width = 0.3; // IIHF rule 17iii, 17iv
-        let goal_line_distance = 4.0; // IIHF rule 17iv
+        let goal_line_distance = config.goal_line_distance;
 
-        let blue_line_distance_neutral_zone_edge = 22.86;
+        let blue_line_distance_neutral_zone_edge = config.blue_line_distance;
         let blue_line_distance_mid = blue_line_distance_neutral_zone_edge - line_width / 2.0; // IIHF rule 17v and 17vi
                                                                                               // IIHF specifies distance between end boards and edge closest to the neutral zone, but my code specifies middle of line
 
         let center_x = width / 2.0;
 
         let red_zone_blueline_z = length - blue_line_distance_mid;
-        let center_z = length / 2.0;
+        let center_z = config.center_line.unwrap_or(length / 2.0);
         let blue_zone_blueline_z = blue_line_distance_mid;
 
+        let [blue_net_x, blue_net_z] = config.blue_net.unwrap_or([center_x, goal_line_distance]);
+        let [red_net_x, red_net_z] = config
+            .red_net
+            .unwrap_or([center_x, length - goal_line_distance]);
         let blue_net = HQMRinkNet::new(
-            Point3::new(center_x, 0.0, goal_line_distance),
+            Point3::new(blue_net_x, 0.0, blue_net_z),
             Matrix3::identity(),
+            config.goal_width,
+            config.goal_height,
         );
         let red_net = HQMRinkNet::new(
-            Point3::new(center_x, 0.0, length - goal_line_distance),
+            Point3::new(red_net_x, 0.0, red_net_z),
             Matrix3::from_columns(&[-Vector3::x(), Vector3::y(), -Vector3::z()]),
+            config.goal_width,
+            config.goal_height,
         );
 
         let red_zone_blue_line = HQMRinkLine {
@@ -385,6 +745,29 @@ impl HQMRink {
             width: line_width,
         };
 
+        let faceoff_dots = config.faceoff_dots.clone().unwrap_or_else(|| {
+            let distance_neutral_faceoff_spot = blue_zone_blueline_z + 1.5; // IIHF rule 18iv and 18vii
+
+            // IIHF rule 18vi and 18vii, but at most halfway to the blue line on rinks with small
+            // zones
+            let zone_faceoff_spot_max = (goal_line_distance + blue_zone_blueline_z) / 2.0;
+            let distance_zone_faceoff_spot = (goal_line_distance + 6.0).min(zone_faceoff_spot_max);
+
+            // Closer to the middle on narrow rinks, so that the wingers still fit
+            let faceoff_x_offset = 7.0f32.min(center_x - 4.0); // IIHF rule 18vi and 18iv
+            let left_x = center_x - faceoff_x_offset;
+            let right_x = center_x + faceoff_x_offset;
+
+            let pair = |z: f32| [[left_x, z], [right_x, z]];
+            HQMRinkFaceoffDots {
+                center: [center_x, center_z],
+                red_zone: pair(length - distance_zone_faceoff_spot),
+                blue_zone: pair(distance_zone_faceoff_spot),
+                red_neutral: pair(length - distance_neutral_faceoff_spot),
+                blue_neutral: pair(distance_neutral_faceoff_spot),
+            }
+        });
+
         HQMRink {
             planes,
             corners,
@@ -395,6 +778,10 @@ impl HQMRink {
             blue_zone_blue_line,
             width,
             length,
+            faceoff_dots,
+            creases: HQMRinkCreases::default(),
+            boards_height: config.boards_height,
+            net_dislodge_speed: 0.0,
         }
     }
 }
@@ -423,6 +810,8 @@ pub struct HQMSkater {
     pub stick_placement_delta: Vector2<f32>, // Change in azimuth and inclination per hundred of a second
     pub collision_balls: Vec<HQMSkaterCollisionBall>,
     pub hand: HQMSkaterHand,
+    /// Ghosts are sent to the clients but left out of the simulation, so nothing touches them
+    pub ghost: bool,
 }
 
 impl HQMSkater {
@@ -512,6 +901,7 @@ impl HQMSkater {
             stick_placement_delta: Vector2::new(0.0, 0.0),
             hand,
             collision_balls,
+            ghost: false,
         }
     }
 
@@ -567,7 +957,7 @@ impl HQMSkaterCollisionBall {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct HQMPlayerInput {
     pub stick_angle: f32,
     pub turn: f32,
@@ -592,7 +982,43 @@ impl Default for HQMPlayerInput {
     }
 }
 
+/// How far a client may go beyond the limits of an input value through rounding before the
+/// value counts as impossible
+const INPUT_TOLERANCE: f32 = 0.01;
+
+/// Clamps a value, and replaces it with 0 if it isn't finite. Returns whether the value was
+/// impossible for a client to send
+fn sanitize_input_value(value: &mut f32, min: f32, max: f32) -> bool {
+    if !value.is_finite() {
+        *value = 0.0;
+        return true;
+    }
+    let impossible = *value < min - INPUT_TOLERANCE || *value > max + INPUT_TOLERANCE;
+    *value = value.clamp(min, max);
+    impossible
+}
+
 impl HQMPlayerInput {
+    /// Clamps every value to the range the simulation uses, and replaces values that aren't
+    /// finite with 0, so raw values from the network never reach the physics. Returns whether
+    /// a value was impossible for a client to send
+    pub fn sanitize(&mut self) -> bool {
+        let mut impossible = false;
+        impossible |= sanitize_input_value(&mut self.stick_angle, -1.0, 1.0);
+        impossible |= sanitize_input_value(&mut self.turn, -1.0, 1.0);
+        impossible |= sanitize_input_value(&mut self.fwbw, -1.0, 1.0);
+        impossible |= sanitize_input_value(&mut self.stick.x, -FRAC_PI_2, FRAC_PI_2);
+        impossible |= sanitize_input_value(&mut self.stick.y, -5.0 * PI / 16.0, FRAC_PI_8);
+        impossible |= sanitize_input_value(&mut self.head_rot, -7.0 * FRAC_PI_8, 7.0 * FRAC_PI_8);
+        impossible |= sanitize_input_value(&mut self.body_rot, -FRAC_PI_2, FRAC_PI_2);
+        impossible
+    }
+
+    /// Lets go of the keys for joining a team
+    pub fn clear_join(&mut self) {
+        self.keys &= !(0x4 | 0x8);
+    }
+
     pub fn jump(&self) -> bool {
         self.keys & 0x1 != 0
     }
@@ -613,7 +1039,8 @@ impl HQMPlayerInput {
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum HQMSkaterHand {
     Left,
     Right,
@@ -708,3 +1135,72 @@ impl std::fmt::Display for HQMObjectIndex {
         write!(f, "{}", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_is_clamped_and_impossible_values_are_reported() {
+        let mut input = HQMPlayerInput {
+            turn: 1.005,
+            ..Default::default()
+        };
+        assert!(!input.sanitize());
+        assert_eq!(input.turn, 1.0);
+
+        let mut input = HQMPlayerInput {
+            fwbw: -3.0,
+            head_rot: f32::NAN,
+            ..Default::default()
+        };
+        assert!(input.sanitize());
+        assert_eq!(input.fwbw, -1.0);
+        assert_eq!(input.head_rot, 0.0);
+
+        let mut input = HQMPlayerInput {
+            stick_angle: f32::INFINITY,
+            ..Default::default()
+        };
+        assert!(input.sanitize());
+        assert_eq!(input.stick_angle, 0.0);
+    }
+
+    #[test]
+    fn rink_file_places_the_nets_and_center_ice() {
+        let config: HQMRinkConfiguration = serde_json::from_str(
+            r#"{
+                "length": 50,
+                "red_net": [12, 46],
+                "center_line": 20
+            }"#,
+        )
+        .unwrap();
+        let rink = HQMRink::new(&config);
+
+        assert_eq!(rink.center_line.z, 20.0);
+        assert_eq!(rink.center_ice(), Point3::new(15.0, 0.0, 20.0));
+        let goal_line = rink.red_net.goal_line_center();
+        assert!((goal_line.x - 12.0).abs() < 1e-4 && (goal_line.z - 46.0).abs() < 1e-4);
+        // The blue net is in the middle of its goal line
+        assert_eq!(
+            rink.blue_net.goal_line_center(),
+            Point3::new(15.0, 0.0, 4.0)
+        );
+
+        let direction = rink.direction_to_net(&rink.red_net);
+        let expected = Vector3::new(-3.0, 0.0, 26.0).normalize();
+        assert!((direction - expected).norm() < 1e-4);
+        let in_front = rink.red_net.in_front(2.0, 0.5);
+        assert!((in_front - Point3::new(12.0, 0.5, 44.0)).norm() < 1e-4);
+    }
+
+    #[test]
+    fn rink_file_with_unknown_keys_is_rejected() {
+        assert!(serde_json::from_str::<HQMRinkConfiguration>(r#"{"boards": "oval"}"#).is_err());
+        assert!(serde_json::from_str::<HQMRinkConfiguration>(
+            r#"{"faceoff_dots": {"center": [15, 30]}}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use tracing::{Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::field::MakeExt;
+use tracing_subscriber::filter::DynFilterFn;
+use tracing_subscriber::fmt::format::debug_fn;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Target of events that belong in the game log, like chat, goals and rule calls
+pub const GAME_LOG: &str = "game_log";
+
+/// Everything that commands log is written to the game log, so that admin actions can be audited
+const COMMAND_SPANS: [&str; 2] = ["command", "console_command"];
+
+/// Shows which server wrote a line when several servers run in one process
+const SERVER_SPAN: &str = "server";
+
+#[derive(Debug, Clone)]
+pub struct HQMGameLogConfiguration {
+    /// File name prefix, the date and time of the rotation is added to it
+    pub name: String,
+    pub rotation: Rotation,
+    /// Older files are deleted when there are more than this many, all are kept if None
+    pub max_files: Option<usize>,
+}
+
+/// Creates a layer that writes the game log to its own rotated files in the log directory.
+/// The returned guard flushes the log when it's dropped.
+pub fn game_log_layer<S>(
+    config: HQMGameLogConfiguration,
+) -> Result<(impl Layer<S>, WorkerGuard), InitError>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let mut builder = RollingFileAppender::builder()
+        .rotation(config.rotation)
+        .filename_prefix(config.name);
+    if let Some(max_files) = config.max_files {
+        builder = builder.max_log_files(max_files);
+    }
+    let (writer, guard) = tracing_appender::non_blocking(builder.build("log")?);
+
+    // Spans have to be enabled for this layer too, otherwise the filter can't see them
+    let filter = DynFilterFn::new(|metadata, cx| {
+        if metadata.is_span() {
+            metadata.name() == SERVER_SPAN || COMMAND_SPANS.contains(&metadata.name())
+        } else if *metadata.level() > Level::INFO {
+            false
+        } else if metadata.target() == GAME_LOG {
+            true
+        } else {
+            cx.lookup_current().is_some_and(|span| {
+                span.scope()
+                    .any(|span| COMMAND_SPANS.contains(&span.name()))
+            })
+        }
+    });
+    // Fields of spans are formatted once per formatter type, so the game log needs a formatter
+    // type of its own to not get the colors of the other log
+    let fields = debug_fn(|writer, field, value| match field.name() {
+        "message" => write!(writer, "{:?}", value),
+        name => write!(writer, "{}={:?}", name, value),
+    });
+    let layer = tracing_subscriber::fmt::layer()
+        .fmt_fields(fields.delimited(" "))
+        .with_ansi(false)
+        .with_target(false)
+        .with_writer(writer)
+        .with_filter(filter);
+    Ok((layer, guard))
+}
@@ -0,0 +1,282 @@
+use migo_hqm_server::hqm_server::{HQMServer, HQMServerPlayerIndex};
+use nalgebra::{Point3, Rotation3, Vector3};
+use std::collections::HashMap;
+use std::rc::Rc;
+use tracing::info;
+use uuid::Uuid;
+
+/// Longest run that is recorded, in steps
+const MAX_RUN_STEPS: usize = 6000;
+
+/// Where a skater and its stick were in one step of a run
+struct HQMGhostFrame {
+    pos: Point3<f32>,
+    rot: Rotation3<f32>,
+    stick_pos: Point3<f32>,
+    stick_rot: Rotation3<f32>,
+    head_rot: f32,
+    body_rot: f32,
+}
+
+/// A ghost skater that skates a recorded run again, shown to everyone but left out of the
+/// simulation
+struct HQMGhost {
+    owner: HQMServerPlayerIndex,
+    player: HQMServerPlayerIndex,
+    /// The slot of a ghost may be taken by someone else once the ghost is gone
+    id: Uuid,
+    run: Rc<Vec<HQMGhostFrame>>,
+    step: usize,
+}
+
+/// Runs that players record in practice mode, and the ghosts that replay them so players can
+/// race their previous attempt
+#[derive(Default)]
+pub struct HQMGhosts {
+    /// Runs being recorded
+    recording: HashMap<HQMServerPlayerIndex, Vec<HQMGhostFrame>>,
+    /// The last finished run of each player
+    runs: HashMap<HQMServerPlayerIndex, Rc<Vec<HQMGhostFrame>>>,
+    ghosts: Vec<HQMGhost>,
+}
+
+impl HQMGhosts {
+    /// /ghost record, /ghost stop and /ghost play
+    pub fn command(
+        &mut self,
+        server: &mut HQMServer,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        match arg {
+            "record" => {
+                if !is_on_ice(server, player_index) {
+                    server.messages.add_directed_server_chat_message(
+                        "Join a team to record a run",
+                        player_index,
+                    );
+                    return;
+                }
+                self.recording.insert(player_index, vec![]);
+                server.messages.add_directed_server_chat_message(
+                    "Recording your run, /ghost stop ends it",
+                    player_index,
+                );
+            }
+            "stop" => {
+                let removed = self.remove_ghosts(server, player_index);
+                if removed {
+                    server
+                        .messages
+                        .add_directed_server_chat_message("Removed your ghost", player_index);
+                }
+                if !self.finish_run(server, player_index) && !removed {
+                    server.messages.add_directed_server_chat_message(
+                        "You aren't recording a run",
+                        player_index,
+                    );
+                }
+            }
+            "play" => self.play(server, player_index),
+            _ => {
+                server.messages.add_directed_server_chat_message(
+                    "Usage: /ghost record|stop|play",
+                    player_index,
+                );
+            }
+        }
+    }
+
+    /// Keeps the run that a player was recording, and tells how long it was
+    fn finish_run(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) -> bool {
+        let run = match self.recording.remove(&player_index) {
+            Some(run) => run,
+            None => return false,
+        };
+        let msg = if run.is_empty() {
+            "Your run was empty".to_string()
+        } else {
+            let msg = format!(
+                "Recorded {:.1} seconds, /ghost play races it",
+                run.len() as f32 / 100.0
+            );
+            self.runs.insert(player_index, Rc::new(run));
+            msg
+        };
+        server
+            .messages
+            .add_directed_server_chat_message(msg, player_index);
+        true
+    }
+
+    /// Puts a ghost of the last run of a player where the run started, and moves the player
+    /// there too
+    fn play(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        let run = match self.runs.get(&player_index) {
+            Some(run) => run.clone(),
+            None => {
+                server.messages.add_directed_server_chat_message(
+                    "You have no recorded run, /ghost record starts one",
+                    player_index,
+                );
+                return;
+            }
+        };
+        let (player_name, team) = match server.players.get(player_index) {
+            Some(player) => match player.object {
+                Some((_, team)) => (player.player_name.clone(), team),
+                None => {
+                    server.messages.add_directed_server_chat_message(
+                        "Join a team to race your ghost",
+                        player_index,
+                    );
+                    return;
+                }
+            },
+            None => return,
+        };
+        self.remove_ghosts(server, player_index);
+        let start = &run[0];
+        let ghost = server.add_dummy("Ghost".to_string()).and_then(|ghost| {
+            match server.spawn_skater(ghost, team, start.pos, start.rot, false) {
+                Some(object) => {
+                    if let Some(skater) = server.world.objects.get_skater_mut(object) {
+                        skater.ghost = true;
+                    }
+                }
+                None => {
+                    server.remove_player(ghost, true);
+                    return None;
+                }
+            }
+            Some((ghost, server.players.get(ghost)?.id))
+        });
+        let (ghost, id) = match ghost {
+            Some(ghost) => ghost,
+            None => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("There is no room for a ghost", player_index);
+                return;
+            }
+        };
+        info!("{} ({}) is racing a ghost", player_name, player_index);
+        server.spawn_skater(player_index, team, start.pos, start.rot, false);
+        self.ghosts.push(HQMGhost {
+            owner: player_index,
+            player: ghost,
+            id,
+            run,
+            step: 0,
+        });
+        server
+            .messages
+            .add_directed_server_chat_message("Go!", player_index);
+    }
+
+    fn remove_ghosts(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) -> bool {
+        let before = self.ghosts.len();
+        self.ghosts.retain(|ghost| {
+            if ghost.owner == player_index {
+                remove_ghost(server, ghost);
+                false
+            } else {
+                true
+            }
+        });
+        self.ghosts.len() != before
+    }
+
+    /// Records a step of the runs and moves the ghosts, called after every step
+    pub fn update(&mut self, server: &mut HQMServer) {
+        let mut finished = vec![];
+        for (player_index, run) in self.recording.iter_mut() {
+            let skater = server
+                .players
+                .get(*player_index)
+                .and_then(|player| player.object)
+                .and_then(|(object, _)| server.world.objects.get_skater(object));
+            match skater {
+                Some(skater) if run.len() < MAX_RUN_STEPS => run.push(HQMGhostFrame {
+                    pos: skater.body.pos,
+                    rot: skater.body.rot,
+                    stick_pos: skater.stick_pos,
+                    stick_rot: skater.stick_rot,
+                    head_rot: skater.head_rot,
+                    body_rot: skater.body_rot,
+                }),
+                _ => finished.push(*player_index),
+            }
+        }
+        for player_index in finished {
+            self.finish_run(server, player_index);
+        }
+
+        self.ghosts.retain_mut(|ghost| {
+            let object = match server.players.get(ghost.player) {
+                Some(player) if player.id == ghost.id => player.object,
+                _ => return false,
+            };
+            let frame = match ghost.run.get(ghost.step) {
+                Some(frame) => frame,
+                None => {
+                    remove_ghost(server, ghost);
+                    return false;
+                }
+            };
+            ghost.step += 1;
+            let skater = object.and_then(|(object, _)| server.world.objects.get_skater_mut(object));
+            match skater {
+                Some(skater) => {
+                    skater.body.pos = frame.pos;
+                    skater.body.rot = frame.rot;
+                    skater.body.linear_velocity = Vector3::zeros();
+                    skater.body.angular_velocity = Vector3::zeros();
+                    skater.stick_pos = frame.stick_pos;
+                    skater.stick_rot = frame.stick_rot;
+                    skater.head_rot = frame.head_rot;
+                    skater.body_rot = frame.body_rot;
+                    true
+                }
+                None => {
+                    remove_ghost(server, ghost);
+                    false
+                }
+            }
+        });
+    }
+
+    /// Forgets the runs and ghosts of a player who left, and removes the ghosts
+    pub fn remove_player(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        self.recording.remove(&player_index);
+        self.runs.remove(&player_index);
+        self.remove_ghosts(server, player_index);
+    }
+
+    /// Forgets the ghosts when the world was replaced, which removes them. The runs are kept
+    pub fn clear(&mut self) {
+        self.recording.clear();
+        self.ghosts.clear();
+    }
+}
+
+fn remove_ghost(server: &mut HQMServer, ghost: &HQMGhost) {
+    if server
+        .players
+        .get(ghost.player)
+        .is_some_and(|player| player.id == ghost.id)
+    {
+        server.remove_player(ghost.player, true);
+    }
+}
+
+fn is_on_ice(server: &HQMServer, player_index: HQMServerPlayerIndex) -> bool {
+    server
+        .players
+        .get(player_index)
+        .is_some_and(|player| player.object.is_some())
+}
@@ -0,0 +1,69 @@
+use crate::hqm_server::HQMTeam;
+
+/// Keeps track of unanswered goals and how far behind each team has been during the game
+#[derive(Debug, Default)]
+pub(crate) struct HQMGoalStreak {
+    team: Option<HQMTeam>,
+    goals: u32,
+    red_largest_deficit: u32,
+    blue_largest_deficit: u32,
+}
+
+impl HQMGoalStreak {
+    pub(crate) fn reset_streak(&mut self) {
+        self.team = None;
+        self.goals = 0;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Called after a goal with the new score. Returns the chat messages to announce, streaks
+    /// only from min_streak unanswered goals and comebacks only if enabled.
+    pub(crate) fn goal(
+        &mut self,
+        team: HQMTeam,
+        red_score: u32,
+        blue_score: u32,
+        min_streak: u32,
+        comebacks: bool,
+    ) -> Vec<String> {
+        let mut messages = vec![];
+        if self.team == Some(team) {
+            self.goals += 1;
+        } else {
+            self.team = Some(team);
+            self.goals = 1;
+        }
+        if min_streak > 0 && self.goals >= min_streak {
+            messages.push(format!("{} has scored {} unanswered", team, self.goals));
+        }
+
+        let (score, other_score, largest_deficit) = match team {
+            HQMTeam::Red => (red_score, blue_score, self.red_largest_deficit),
+            HQMTeam::Blue => (blue_score, red_score, self.blue_largest_deficit),
+        };
+        if comebacks && largest_deficit >= 2 {
+            if score == other_score {
+                messages.push(format!(
+                    "{} came back from {} goals down to tie the game",
+                    team, largest_deficit
+                ));
+            } else if score == other_score + 1 {
+                messages.push(format!(
+                    "{} takes the lead after trailing by {}",
+                    team, largest_deficit
+                ));
+            }
+        }
+
+        self.red_largest_deficit = self
+            .red_largest_deficit
+            .max(blue_score.saturating_sub(red_score));
+        self.blue_largest_deficit = self
+            .blue_largest_deficit
+            .max(red_score.saturating_sub(blue_score));
+        messages
+    }
+}
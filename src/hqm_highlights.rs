@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::hqm_autosave::write_atomic;
+use crate::hqm_game::{HQMGameWorld, HQMObjectIndex};
+use crate::hqm_replay::{cut_replay, REPLAY_STEPS_PER_SECOND};
+use crate::hqm_server::{HQMServerPlayerList, HQMTeam};
+use crate::hqm_simulate::HQMSimulationEvent;
+
+/// Seconds of the game before and after a goal that its clip shows
+const GOAL_CLIP_SECONDS: (usize, usize) = (6, 3);
+/// Seconds before and after the hardest hit and the fastest shot
+const MOMENT_CLIP_SECONDS: (usize, usize) = (3, 2);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HQMHighlightKind {
+    Goal,
+    Hit,
+    Shot,
+}
+
+impl HQMHighlightKind {
+    fn name(self) -> &'static str {
+        match self {
+            HQMHighlightKind::Goal => "goal",
+            HQMHighlightKind::Hit => "hit",
+            HQMHighlightKind::Shot => "shot",
+        }
+    }
+}
+
+/// A moment of the game that gets its own clip when the replay is saved
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HQMHighlight {
+    pub(crate) kind: HQMHighlightKind,
+    /// Step of the replay the moment happened in
+    #[serde(skip)]
+    step: usize,
+    period: u32,
+    /// Time left of the period, measured in hundredths of a second
+    time: u32,
+    red_score: u32,
+    blue_score: u32,
+    team: Option<HQMTeam>,
+    /// The scorer, the player who hit or the shooter
+    player: Option<String>,
+    /// The player who was hit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    /// Speed of the puck for goals and shots, and the speed the skaters came together at for
+    /// hits, in meters per second
+    speed: f32,
+}
+
+impl HQMHighlight {
+    /// Steps of the replay shown in the clip
+    fn range(&self) -> Range<usize> {
+        let (before, after) = match self.kind {
+            HQMHighlightKind::Goal => GOAL_CLIP_SECONDS,
+            HQMHighlightKind::Hit | HQMHighlightKind::Shot => MOMENT_CLIP_SECONDS,
+        };
+        let start = self.step.saturating_sub(before * REPLAY_STEPS_PER_SECOND);
+        start..self.step + after * REPLAY_STEPS_PER_SECOND
+    }
+}
+
+/// The clock and score when a moment happened, and the step of the replay it's in
+pub(crate) struct HQMHighlightTime {
+    pub(crate) step: usize,
+    pub(crate) period: u32,
+    pub(crate) time: u32,
+    pub(crate) red_score: u32,
+    pub(crate) blue_score: u32,
+}
+
+/// Finds the goals, the hardest hit and the fastest shot of a game while it's played
+#[derive(Default)]
+pub(crate) struct HQMHighlightTracker {
+    goals: Vec<HQMHighlight>,
+    hardest_hit: Option<HQMHighlight>,
+    fastest_shot: Option<HQMHighlight>,
+    /// Pucks that were touched in the last step, and the skater who touched them
+    touches: HashMap<HQMObjectIndex, HQMObjectIndex>,
+}
+
+impl HQMHighlightTracker {
+    pub(crate) fn add_goal(
+        &mut self,
+        at: &HQMHighlightTime,
+        team: HQMTeam,
+        scorer: Option<String>,
+        speed: f32,
+    ) {
+        self.goals.push(highlight(
+            HQMHighlightKind::Goal,
+            at,
+            Some(team),
+            scorer,
+            speed,
+        ));
+    }
+
+    /// Looks for hits and shots in a step that was just simulated
+    pub(crate) fn update(
+        &mut self,
+        at: &HQMHighlightTime,
+        world: &HQMGameWorld,
+        players: &HQMServerPlayerList,
+        events: &[HQMSimulationEvent],
+    ) {
+        let name = |object_index: HQMObjectIndex| {
+            players.iter().find_map(|(_, player)| match player.object {
+                Some((i, team)) if i == object_index => {
+                    Some((team, player.player_name.to_string()))
+                }
+                _ => None,
+            })
+        };
+
+        // Hits are the collisions the simulation found, the same ones that count for the
+        // statistics
+        for event in events {
+            if let HQMSimulationEvent::PlayerCollision {
+                player_a,
+                player_b,
+                impact,
+            } = *event
+            {
+                let speed = impact * 100.0;
+                if matches!(&self.hardest_hit, Some(hit) if hit.speed >= speed) {
+                    continue;
+                }
+                if let (Some((team, hitter)), Some((_, target))) = (name(player_a), name(player_b))
+                {
+                    let mut hit =
+                        highlight(HQMHighlightKind::Hit, at, Some(team), Some(hitter), speed);
+                    hit.target = Some(target);
+                    self.hardest_hit = Some(hit);
+                }
+            }
+        }
+
+        let mut touches = HashMap::new();
+        for event in events {
+            if let HQMSimulationEvent::PuckTouch { player, puck } = event {
+                touches.insert(*puck, *player);
+            }
+        }
+        // A shot is when the puck leaves the stick, the step after the last touch
+        for (puck_index, player_index) in self.touches.iter() {
+            if touches.contains_key(puck_index) {
+                continue;
+            }
+            let speed = match world.objects.get_puck(*puck_index) {
+                Some(puck) => puck.body.linear_velocity.norm() * 100.0,
+                None => continue,
+            };
+            if matches!(&self.fastest_shot, Some(shot) if shot.speed >= speed) {
+                continue;
+            }
+            if let Some((team, shooter)) = name(*player_index) {
+                self.fastest_shot = Some(highlight(
+                    HQMHighlightKind::Shot,
+                    at,
+                    Some(team),
+                    Some(shooter),
+                    speed,
+                ));
+            }
+        }
+        self.touches = touches;
+    }
+
+    /// The highlights of the game, in the order they happened
+    pub(crate) fn into_highlights(self) -> Vec<HQMHighlight> {
+        let mut highlights = self.goals;
+        highlights.extend(self.hardest_hit);
+        highlights.extend(self.fastest_shot);
+        highlights.sort_by_key(|highlight| highlight.step);
+        highlights
+    }
+}
+
+fn highlight(
+    kind: HQMHighlightKind,
+    at: &HQMHighlightTime,
+    team: Option<HQMTeam>,
+    player: Option<String>,
+    speed: f32,
+) -> HQMHighlight {
+    HQMHighlight {
+        kind,
+        step: at.step,
+        period: at.period,
+        time: at.time,
+        red_score: at.red_score,
+        blue_score: at.blue_score,
+        team,
+        player,
+        target: None,
+        speed,
+    }
+}
+
+/// Cuts the clips of the highlights out of the replay file and saves each of them in the
+/// directory as a small replay, next to a JSON file with what happened in it. The files are
+/// named after the replay, with the kind and number of the highlight added
+pub(crate) fn save_highlights(
+    directory: &Path,
+    replay_name: &str,
+    replay_file: &[u8],
+    highlights: &[HQMHighlight],
+) {
+    let ranges: Vec<_> = highlights.iter().map(HQMHighlight::range).collect();
+    let clips = match cut_replay(replay_file, &ranges) {
+        Ok(clips) => clips,
+        Err(e) => {
+            warn!("Could not cut highlights of {}: {}", replay_name, e);
+            return;
+        }
+    };
+    if let Err(e) = std::fs::create_dir_all(directory) {
+        warn!(error = %e, "Could not create {}: {}", directory.display(), e);
+        return;
+    }
+    let mut counts: HashMap<HQMHighlightKind, usize> = HashMap::new();
+    for (highlight, clip) in highlights.iter().zip(clips) {
+        let count = counts.entry(highlight.kind).or_insert(0);
+        *count += 1;
+        let name = format!("{}.{}{}", replay_name, highlight.kind.name(), count);
+        let metadata = match serde_json::to_string_pretty(highlight) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Could not write highlight {}: {}", name, e);
+                continue;
+            }
+        };
+        let result = write_atomic(&directory.join(format!("{}.hrp", name)), &clip).and_then(|()| {
+            write_atomic(
+                &directory.join(format!("{}.json", name)),
+                metadata.as_bytes(),
+            )
+        });
+        match result {
+            Ok(()) => info!("Saved highlight {}", name),
+            Err(e) => warn!(error = %e, "Could not save highlight {}: {}", name, e),
+        }
+    }
+}
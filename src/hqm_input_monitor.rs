@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Keys that change skater physics when toggled, jump, crouch and shift
+const MONITORED_KEYS: [(u32, &str); 3] = [(0x1, "jump"), (0x2, "crouch"), (0x10, "shift")];
+
+const WINDOW_TICKS: usize = 100;
+const RECORDING_TICKS: usize = 500;
+
+/// Number of seconds with limited input before a player is reported to the admins
+const STRIKES_BEFORE_FLAG: u32 = 3;
+/// Number of packets with impossible input values within IMPOSSIBLE_INPUT_WINDOW_TICKS before
+/// a player is reported to the admins
+const IMPOSSIBLE_INPUTS_BEFORE_FLAG: usize = 50;
+/// A client with a rare glitch sends an impossible value now and then, a modified client
+/// keeps sending them
+const IMPOSSIBLE_INPUT_WINDOW_TICKS: u32 = 1000;
+
+/// Keeps track of how often a player toggles keys, and limits toggles that are too fast to be human
+pub(crate) struct HQMInputMonitor {
+    applied_keys: VecDeque<u32>,
+    recording: VecDeque<(u32, u32)>,
+    strikes: u32,
+    last_strike_step: Option<u32>,
+    flagged: bool,
+    impossible_inputs: u32,
+    /// Game steps of the recent packets with impossible input values, newest first
+    recent_impossible_inputs: VecDeque<u32>,
+    impossible_flagged: bool,
+}
+
+impl HQMInputMonitor {
+    pub(crate) fn new() -> Self {
+        HQMInputMonitor {
+            applied_keys: VecDeque::with_capacity(WINDOW_TICKS),
+            recording: VecDeque::with_capacity(RECORDING_TICKS),
+            strikes: 0,
+            last_strike_step: None,
+            flagged: false,
+            impossible_inputs: 0,
+            recent_impossible_inputs: VecDeque::with_capacity(IMPOSSIBLE_INPUTS_BEFORE_FLAG),
+            impossible_flagged: false,
+        }
+    }
+
+    fn toggles_in_window(&self, key: u32) -> u32 {
+        self.applied_keys
+            .iter()
+            .zip(self.applied_keys.iter().skip(1))
+            .filter(|(a, b)| (*a ^ *b) & key != 0)
+            .count() as u32
+    }
+
+    /// Returns the keys that should be used for this tick, and whether the player should now be reported
+    pub(crate) fn filter_keys(
+        &mut self,
+        keys: u32,
+        game_step: u32,
+        toggle_limit: u32,
+    ) -> (u32, bool) {
+        self.recording.truncate(RECORDING_TICKS - 1);
+        self.recording.push_front((game_step, keys));
+
+        let previous = self.applied_keys.front().copied().unwrap_or(keys);
+        let mut applied = keys;
+        let mut limited = false;
+        if toggle_limit > 0 {
+            for (key, _) in MONITORED_KEYS {
+                if (keys ^ previous) & key != 0 && self.toggles_in_window(key) >= toggle_limit {
+                    applied = (applied & !key) | (previous & key);
+                    limited = true;
+                }
+            }
+        }
+        self.applied_keys.truncate(WINDOW_TICKS - 1);
+        self.applied_keys.push_front(applied);
+
+        let mut report = false;
+        if limited {
+            let is_new_strike = match self.last_strike_step {
+                Some(step) => game_step.wrapping_sub(step) >= WINDOW_TICKS as u32,
+                None => true,
+            };
+            if is_new_strike {
+                self.strikes += 1;
+                self.last_strike_step = Some(game_step);
+                if self.strikes >= STRIKES_BEFORE_FLAG && !self.flagged {
+                    self.flagged = true;
+                    report = true;
+                }
+            }
+        }
+        (applied, report)
+    }
+
+    pub(crate) fn strikes(&self) -> u32 {
+        self.strikes
+    }
+
+    /// Counts a packet with impossible input values, and returns whether the player should now
+    /// be reported, after too many of them in a short time
+    pub(crate) fn count_impossible_input(&mut self, game_step: u32) -> bool {
+        self.impossible_inputs += 1;
+        while self
+            .recent_impossible_inputs
+            .back()
+            .is_some_and(|&step| game_step.wrapping_sub(step) >= IMPOSSIBLE_INPUT_WINDOW_TICKS)
+        {
+            self.recent_impossible_inputs.pop_back();
+        }
+        self.recent_impossible_inputs
+            .truncate(IMPOSSIBLE_INPUTS_BEFORE_FLAG - 1);
+        self.recent_impossible_inputs.push_front(game_step);
+        if self.recent_impossible_inputs.len() >= IMPOSSIBLE_INPUTS_BEFORE_FLAG
+            && !self.impossible_flagged
+        {
+            self.impossible_flagged = true;
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn impossible_inputs(&self) -> u32 {
+        self.impossible_inputs
+    }
+
+    /// Toggles per second for each monitored key in the recorded input
+    pub(crate) fn summary(&self) -> String {
+        let seconds = (self.recording.len() as f32 / 100.0).max(0.01);
+        let mut res = String::new();
+        for (key, name) in MONITORED_KEYS {
+            let toggles = self
+                .recording
+                .iter()
+                .zip(self.recording.iter().skip(1))
+                .filter(|((_, a), (_, b))| (*a ^ *b) & key != 0)
+                .count();
+            if !res.is_empty() {
+                res.push_str(", ");
+            }
+            let _ = write!(res, "{} {:.0}/s", name, toggles as f32 / seconds);
+        }
+        res
+    }
+
+    /// The recorded input of the last few seconds, one line per tick
+    pub(crate) fn recording(&self) -> String {
+        let mut res = String::new();
+        for (game_step, keys) in self.recording.iter().rev() {
+            let _ = writeln!(res, "{} {:#04x}", game_step, keys);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_many_impossible_inputs_in_a_short_time_are_reported() {
+        let mut monitor = HQMInputMonitor::new();
+        // One every second for a long time
+        for i in 0..200 {
+            assert!(!monitor.count_impossible_input(i * 100));
+        }
+        // A burst
+        let reports: Vec<_> = (0..IMPOSSIBLE_INPUTS_BEFORE_FLAG as u32)
+            .map(|i| monitor.count_impossible_input(30000 + i))
+            .collect();
+        assert_eq!(reports.iter().filter(|&&report| report).count(), 1);
+        assert!(reports[IMPOSSIBLE_INPUTS_BEFORE_FLAG - 1]);
+        assert_eq!(monitor.impossible_inputs(), 250);
+    }
+}
@@ -0,0 +1,203 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone)]
+pub struct HQMMasterServer {
+    pub region: Option<String>,
+    pub address: HQMMasterServerAddress,
+}
+
+#[derive(Debug, Clone)]
+pub enum HQMMasterServerAddress {
+    /// URL of a page that contains the address of the master server
+    Endpoint(String),
+    /// Host name or IP address of the master server, followed by the port
+    Address(String),
+}
+
+impl HQMMasterServer {
+    pub fn new(region: Option<&str>, address: &str) -> Self {
+        let address = if address.starts_with("http://") || address.starts_with("https://") {
+            HQMMasterServerAddress::Endpoint(address.to_string())
+        } else {
+            HQMMasterServerAddress::Address(address.to_string())
+        };
+        HQMMasterServer {
+            region: region.map(String::from),
+            address,
+        }
+    }
+
+    async fn resolve(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<SocketAddr, Box<dyn Error + Send + Sync>> {
+        match &self.address {
+            HQMMasterServerAddress::Endpoint(url) => {
+                let response = client.get(url).send().await?.text().await?;
+
+                let split = response.split_ascii_whitespace().collect::<Vec<&str>>();
+
+                let addr = split.get(1).unwrap_or(&"").parse::<IpAddr>()?;
+                let port = split.get(2).unwrap_or(&"").parse::<u16>()?;
+                Ok(SocketAddr::new(addr, port))
+            }
+            HQMMasterServerAddress::Address(address) => tokio::net::lookup_host(address)
+                .await?
+                .find(|addr| addr.is_ipv4())
+                .ok_or_else(|| "no IPv4 address found".into()),
+        }
+    }
+}
+
+impl Display for HQMMasterServer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let address = match &self.address {
+            HQMMasterServerAddress::Endpoint(s) | HQMMasterServerAddress::Address(s) => s,
+        };
+        match &self.region {
+            Some(region) => write!(f, "{} ({})", address, region),
+            None => write!(f, "{}", address),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HQMMasterServerStatus {
+    addr: Option<SocketAddr>,
+    heartbeats: u64,
+    failed_heartbeats: u64,
+    failed_lookups: u64,
+    last_error: Option<String>,
+}
+
+pub(crate) struct HQMMasterServerNotifier {
+    pub(crate) master_server: HQMMasterServer,
+    socket: Arc<UdpSocket>,
+    client: reqwest::Client,
+    status: Arc<Mutex<HQMMasterServerStatus>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl HQMMasterServerNotifier {
+    pub(crate) fn new(
+        master_server: HQMMasterServer,
+        socket: Arc<UdpSocket>,
+        client: reqwest::Client,
+    ) -> Self {
+        HQMMasterServerNotifier {
+            master_server,
+            socket,
+            client,
+            status: Arc::new(Mutex::new(HQMMasterServerStatus::default())),
+            task: None,
+        }
+    }
+
+    pub(crate) fn start(&mut self) {
+        if self.task.is_none() {
+            self.task = Some(tokio::spawn(notify_master_server(
+                self.master_server.clone(),
+                self.socket.clone(),
+                self.client.clone(),
+                self.status.clone(),
+            )));
+        }
+    }
+
+    /// Stops sending heartbeats, the master server drops the server from the list once they time out
+    pub(crate) fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+
+    /// Failed heartbeats and lookups so far, with the last error
+    pub(crate) fn failures(&self) -> (u64, Option<String>) {
+        let status = self.status.lock().unwrap();
+        (
+            status.failed_heartbeats + status.failed_lookups,
+            status.last_error.clone(),
+        )
+    }
+
+    pub(crate) fn status_line(&self) -> String {
+        let status = self.status.lock().unwrap();
+        let addr = match status.addr {
+            _ if self.task.is_none() => "stopped".to_string(),
+            Some(addr) => addr.to_string(),
+            None => "unresolved".to_string(),
+        };
+        let mut line = format!(
+            "{}: {}, {} heartbeats sent, {} failed, {} failed lookups",
+            self.master_server,
+            addr,
+            status.heartbeats,
+            status.failed_heartbeats,
+            status.failed_lookups
+        );
+        if let Some(last_error) = &status.last_error {
+            line.push_str(", last error: ");
+            line.push_str(last_error);
+        }
+        line
+    }
+}
+
+/// Sends a heartbeat every 10 seconds, and looks the master server up again every 10 minutes.
+/// The last known address keeps getting heartbeats if a lookup fails
+async fn notify_master_server(
+    master_server: HQMMasterServer,
+    socket: Arc<UdpSocket>,
+    client: reqwest::Client,
+    status: Arc<Mutex<HQMMasterServerStatus>>,
+) {
+    let mut addr = None;
+    loop {
+        match master_server.resolve(&client).await {
+            Ok(new_addr) => {
+                if addr.is_some_and(|addr| addr != new_addr) {
+                    info!("Master server {} moved to {}", master_server, new_addr);
+                }
+                addr = Some(new_addr);
+                status.lock().unwrap().addr = addr;
+            }
+            Err(e) => {
+                warn!("Could not find master server {}: {}", master_server, e);
+                let mut status = status.lock().unwrap();
+                status.failed_lookups += 1;
+                status.last_error = Some(e.to_string());
+            }
+        }
+        match addr {
+            Some(addr) => {
+                for _ in 0..60 {
+                    let msg = b"Hock\x20";
+                    let res = socket.send_to(msg, addr).await;
+                    match res {
+                        Ok(_) => {
+                            status.lock().unwrap().heartbeats += 1;
+                        }
+                        Err(e) => {
+                            warn!("Could not notify master server {}: {}", master_server, e);
+                            let mut status = status.lock().unwrap();
+                            status.failed_heartbeats += 1;
+                            status.last_error = Some(e.to_string());
+                        }
+                    }
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
+            None => {
+                tokio::time::sleep(Duration::from_secs(15)).await;
+            }
+        }
+    }
+}
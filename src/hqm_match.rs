@@ -1,13 +1,28 @@
 use tracing::info;
 
+use crate::hqm_config::{
+    load_match_config, load_spawn_point, load_team_max, match_settings, reload_server_config,
+    HQMConfigSource,
+};
+use crate::hqm_mode_state::HQMModeState;
+use crate::hqm_rejoin::HQMRejoins;
+use crate::hqm_rematch::HQMRematchVote;
+use crate::hqm_series::HQMSeries;
+use crate::hqm_vote::{HQMVote, HQMVoteSubject};
 use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
+use migo_hqm_server::hqm_bot::HQMBots;
+use migo_hqm_server::hqm_console::console_response;
 use migo_hqm_server::hqm_match_util::{
     get_spawnpoint, HQMMatch, HQMMatchConfiguration, HQMSpawnPoint,
 };
 use migo_hqm_server::hqm_server::HQMTeam;
-use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex};
+use migo_hqm_server::hqm_server::{
+    HQMAdminSource, HQMInitialGameValues, HQMServer, HQMServerPlayerIndex,
+};
 use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
+use migo_hqm_server::hqm_snapshot::HQMGameSnapshot;
 use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
 
 pub struct HQMMatchBehaviour {
     pub m: HQMMatch,
@@ -15,23 +30,364 @@ pub struct HQMMatchBehaviour {
     pub(crate) team_switch_timer: HashMap<HQMServerPlayerIndex, u32>,
     pub(crate) show_extra_messages: HashSet<HQMServerPlayerIndex>,
     pub team_max: usize,
+    config_source: HQMConfigSource,
+    state: Option<HQMModeState>,
+    /// The settings as they were last compared, to find which ones a command changed
+    settings: Vec<(&'static str, &'static str, String)>,
+    rematch_vote: Option<HQMRematchVote>,
+    vote: Option<HQMVote>,
+    series: Option<HQMSeries>,
+    /// Winner of the game that just ended, counted in the series when the next game starts
+    series_game_winner: Option<HQMTeam>,
+    /// Players who are put back on their teams when the next game starts, after a rematch vote
+    sticky_teams: Vec<(HQMServerPlayerIndex, Uuid, HQMTeam)>,
+    /// Players of a game resumed from a snapshot, by name, who are put back on their teams
+    /// when they join again
+    resumed_roster: HashMap<String, HQMTeam>,
+    /// Spots of players who disconnected during the game
+    rejoins: HQMRejoins,
+    bots: HQMBots,
 }
 
 impl HQMMatchBehaviour {
-    pub fn new(config: HQMMatchConfiguration, team_max: usize, spawn_point: HQMSpawnPoint) -> Self {
+    pub fn new(
+        config: HQMMatchConfiguration,
+        team_max: usize,
+        spawn_point: HQMSpawnPoint,
+        config_source: HQMConfigSource,
+        state: Option<HQMModeState>,
+    ) -> Self {
+        let settings = match_settings(&config, team_max);
         HQMMatchBehaviour {
             m: HQMMatch::new(config),
             spawn_point,
             team_switch_timer: Default::default(),
             show_extra_messages: Default::default(),
             team_max,
+            config_source,
+            state,
+            settings,
+            rematch_vote: None,
+            vote: None,
+            series: None,
+            series_game_winner: None,
+            sticky_teams: vec![],
+            resumed_roster: HashMap::new(),
+            rejoins: HQMRejoins::default(),
+            bots: HQMBots::default(),
+        }
+    }
+
+    fn reload_config(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if let Some(conf) = reload_server_config(server, player_index, &self.config_source) {
+            // Physics changes are left for a server restart
+            let physics_config = self.m.config.physics_config.clone();
+            self.m.config = load_match_config(&conf, physics_config);
+            self.spawn_point = load_spawn_point(&conf);
+            if let Some(team_max) = load_team_max(&conf) {
+                self.team_max = team_max;
+            }
+            // The configuration file is used as it is now, until settings are changed again
+            self.settings = match_settings(&self.m.config, self.team_max);
+            if let Some(state) = &mut self.state {
+                state.clear_settings();
+            }
+        }
+    }
+
+    /// Saves the settings that a command changed, so that they're kept after a restart
+    fn save_state(&mut self) {
+        let settings = match_settings(&self.m.config, self.team_max);
+        if let Some(state) = &mut self.state {
+            for (old, new) in self.settings.iter().zip(settings.iter()) {
+                if old != new {
+                    let (section, key, value) = new;
+                    state.set(section, key, value.clone());
+                }
+            }
+        }
+        self.settings = settings;
+    }
+
+    fn vote_rematch(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) {
+        match &mut self.rematch_vote {
+            Some(vote) => vote.vote(server, player_index, arg),
+            None => server
+                .messages
+                .add_directed_server_chat_message("There is no rematch vote", player_index),
+        }
+    }
+
+    /// Starts a vote with /vote <subject>, or votes in the running vote with /vote yes|no
+    fn vote(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex, arg: &str) {
+        if !self.m.config.votes {
+            server
+                .messages
+                .add_directed_server_chat_message("Votes are disabled", player_index);
+            return;
+        }
+        let result = match &mut self.vote {
+            Some(vote) => match HQMVoteSubject::parse(arg) {
+                Some(_) => {
+                    server.messages.add_directed_server_chat_message(
+                        "A vote is already running",
+                        player_index,
+                    );
+                    return;
+                }
+                None => vote.vote(server, player_index, arg),
+            },
+            None => match HQMVoteSubject::parse(arg) {
+                Some(subject) => {
+                    self.vote = HQMVote::open(server, &self.m.config, player_index, subject);
+                    // The vote passes at once if the player who started it is enough
+                    self.vote.as_ref().and_then(HQMVote::result)
+                }
+                None => {
+                    server.messages.add_directed_server_chat_message(
+                        "Usage: /vote resetgame|startgame|icing <rule>|offside <rule>|mercy <goals>|first <goals>",
+                        player_index,
+                    );
+                    return;
+                }
+            },
+        };
+        if let Some(passed) = result {
+            self.finish_vote(server, passed);
+        }
+    }
+
+    /// Announces the series score when a game of a series ends
+    fn series_game_over(&mut self, server: &mut HQMServer) {
+        let series = match &self.series {
+            Some(series) => series,
+            None => return,
+        };
+        let (red_score, blue_score) = (server.values.red_score, server.values.blue_score);
+        let team = match red_score.cmp(&blue_score) {
+            std::cmp::Ordering::Greater => HQMTeam::Red,
+            std::cmp::Ordering::Less => HQMTeam::Blue,
+            std::cmp::Ordering::Equal => return,
+        };
+        let mut after = series.clone();
+        after.add_win(team);
+        info!(
+            "{} won game {} of the series, {}",
+            team,
+            series.next_game(),
+            after
+        );
+        let msg = format!("{} wins game {}, {}", team, series.next_game(), after);
+        server.messages.add_server_chat_message(msg);
+        self.series_game_winner = Some(team);
+    }
+
+    /// Counts the last game in the series when the next game starts, and starts a new series
+    /// when the last one is over
+    fn update_series(&mut self, server: &mut HQMServer) {
+        let best_of = self.m.config.series;
+        let winner = self.series_game_winner.take();
+        if best_of == 0 {
+            self.series = None;
+            return;
+        }
+        let mut series = match self.series.take() {
+            Some(series) if series.best_of == best_of => series,
+            _ => HQMSeries::new(best_of),
+        };
+        if let Some(team) = winner {
+            series.add_win(team);
+        }
+        if series.winner().is_some() {
+            series = HQMSeries::new(best_of);
+        }
+        // The teams change ends every game
+        self.m
+            .set_ends_swapped(server, (series.next_game() - 1) % 2 == 1);
+        let msg = if series.next_game() == 1 {
+            format!("Game 1 of a best-of-{} series", best_of)
+        } else {
+            format!("Game {} of the series, {}", series.next_game(), series)
+        };
+        server.messages.add_server_chat_message(msg);
+        self.series = Some(series);
+    }
+
+    fn msg_series(&self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        let msg = match &self.series {
+            Some(series) => format!(
+                "Game {} of a best-of-{} series, {}",
+                series.next_game(),
+                series.best_of,
+                series
+            ),
+            None => "No series is being played".to_string(),
+        };
+        server
+            .messages
+            .add_directed_server_chat_message(msg, player_index);
+    }
+
+    /// Ends the running vote, and applies it if it passed
+    fn finish_vote(&mut self, server: &mut HQMServer, passed: bool) {
+        let subject = match self.vote.take() {
+            Some(vote) => vote.subject,
+            None => return,
+        };
+        if !passed {
+            info!("Vote to {} failed", subject);
+            let msg = format!("Vote to {} failed", subject);
+            server.messages.add_server_chat_message(msg);
+            return;
+        }
+        info!("Vote to {} passed", subject);
+        let msg = format!("Vote to {} passed", subject);
+        match subject {
+            HQMVoteSubject::ResetGame => {
+                server.new_game(self.m.get_initial_game_values());
+            }
+            HQMVoteSubject::StartGame => {
+                if server.values.period == 0 && server.values.time > 1 {
+                    self.m.force_start(server);
+                }
+            }
+            HQMVoteSubject::Icing(icing) => self.m.config.icing = icing,
+            HQMVoteSubject::Offside(offside) => self.m.config.offside = offside,
+            HQMVoteSubject::Mercy(goals) => self.m.config.mercy = goals,
+            HQMVoteSubject::FirstTo(goals) => self.m.config.first_to = goals,
+        }
+        server.messages.add_server_chat_message(msg);
+        self.save_state();
+    }
+
+    /// Puts the players back on their teams after a rematch vote passed
+    fn keep_sticky_teams(&mut self, server: &mut HQMServer) {
+        let mut red_player_count = 0;
+        let mut blue_player_count = 0;
+        for (player_index, id, team) in std::mem::take(&mut self.sticky_teams) {
+            let player_name = match server.players.get(player_index) {
+                Some(player) if player.id == id && player.object.is_none() => {
+                    player.player_name.clone()
+                }
+                _ => continue,
+            };
+            let player_count = match team {
+                HQMTeam::Red => &mut red_player_count,
+                HQMTeam::Blue => &mut blue_player_count,
+            };
+            add_player(
+                &mut self.m,
+                player_index,
+                &player_name,
+                server,
+                team,
+                self.spawn_point,
+                player_count,
+                self.team_max,
+            );
+        }
+        if server.values.period == 0
+            && server.values.time > 2000
+            && red_player_count > 0
+            && blue_player_count > 0
+            && self.m.teams_ready(server)
+        {
+            server.values.time = 2000;
+        }
+    }
+
+    /// Puts a player of a resumed game back on the team they played for before the server
+    /// stopped
+    fn return_to_resumed_team(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        let player_name = match server.players.get(player_index) {
+            Some(player) if player.object.is_none() => player.player_name.clone(),
+            _ => return,
+        };
+        let team = match self.resumed_roster.remove(player_name.as_str()) {
+            Some(team) => team,
+            None => return,
+        };
+        let mut player_count = server
+            .players
+            .iter()
+            .filter(|(_, player)| matches!(player.object, Some((_, t)) if t == team))
+            .count();
+        add_player(
+            &mut self.m,
+            player_index,
+            &player_name,
+            server,
+            team,
+            self.spawn_point,
+            &mut player_count,
+            self.team_max,
+        );
+    }
+
+    /// Puts a player who disconnected during the game back where they were, if they rejoined
+    /// within the grace time
+    fn return_to_held_spot(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        let (team, pos, rot, position) = match self.rejoins.take(server, player_index) {
+            Some(spot) => spot,
+            None => return,
+        };
+        self.update_held_positions();
+        if let Some(position) = position {
+            self.m.set_preferred_position(player_index, position);
+        }
+        let player_name = match server.players.get(player_index) {
+            Some(player) if player.object.is_none() => player.player_name.clone(),
+            _ => return,
+        };
+        let player_count = server
+            .players
+            .iter()
+            .filter(|(_, player)| {
+                !player.is_dummy() && matches!(player.object, Some((_, t)) if t == team)
+            })
+            .count()
+            + self.m.players_in_penalty_box(team)
+            + self.rejoins.held_spots(team);
+        if player_count >= self.team_max {
+            server.messages.add_directed_server_chat_message(
+                "Your team is full, your spot couldn't be kept",
+                player_index,
+            );
+            return;
         }
+        if server
+            .spawn_skater(player_index, team, pos, rot, false)
+            .is_some()
+        {
+            info!(
+                "{} ({}) rejoined team {:?}",
+                player_name, player_index, team
+            );
+            self.m.clear_started_goalie(player_index);
+            let msg = format!("{} is back", player_name);
+            server.messages.add_server_chat_message(msg);
+        }
+    }
+
+    /// Lets the positions of players who may rejoin count as taken
+    fn update_held_positions(&mut self) {
+        self.m.held_positions = self.rejoins.held_positions();
     }
 
     fn update_players(&mut self, server: &mut HQMServer) {
         let mut spectating_players = smallvec::SmallVec::<[_; 32]>::new();
         let mut joining_red = smallvec::SmallVec::<[_; 32]>::new();
         let mut joining_blue = smallvec::SmallVec::<[_; 32]>::new();
+        let mut in_penalty_box = smallvec::SmallVec::<[_; 4]>::new();
         for (player_index, player) in server.players.iter() {
             self.team_switch_timer
                 .get_mut(&player_index)
@@ -44,7 +400,9 @@ impl HQMMatchBehaviour {
                         .get(&player_index)
                         .map_or(true, |x| *x == 0)
                 {
-                    if player.input.join_red() {
+                    if let Some(time_left) = self.m.penalty_time_left(&player.player_name) {
+                        in_penalty_box.push((player_index, time_left));
+                    } else if player.input.join_red() {
                         joining_red.push((player_index, player.player_name.clone()));
                     } else if player.input.join_blue() {
                         joining_blue.push((player_index, player.player_name.clone()));
@@ -58,6 +416,18 @@ impl HQMMatchBehaviour {
                 }
             }
         }
+        for (player_index, time_left) in in_penalty_box {
+            self.team_switch_timer.insert(player_index, 500);
+            let seconds = time_left / 100;
+            let msg = format!(
+                "You are in the penalty box for {}:{:02}",
+                seconds / 60,
+                seconds % 60
+            );
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+        }
         for (player_index, player_name) in spectating_players {
             info!("{} ({}) is spectating", player_name, player_index);
             server.move_to_spectator(player_index);
@@ -72,7 +442,8 @@ impl HQMMatchBehaviour {
             let (red_player_count, blue_player_count) = {
                 let mut red_player_count = 0usize;
                 let mut blue_player_count = 0usize;
-                for (_, player) in server.players.iter() {
+                // Dummies of warmup practice don't take a spot on the team
+                for (_, player) in server.players.iter().filter(|(_, x)| !x.is_dummy()) {
                     if let Some((_, team)) = player.object {
                         if team == HQMTeam::Red {
                             red_player_count += 1;
@@ -81,12 +452,24 @@ impl HQMMatchBehaviour {
                         }
                     }
                 }
+                // Players in the penalty box keep their spot on the team, and so do players
+                // who disconnected until they rejoin
+                red_player_count += self.m.players_in_penalty_box(HQMTeam::Red);
+                blue_player_count += self.m.players_in_penalty_box(HQMTeam::Blue);
+                red_player_count += self.rejoins.held_spots(HQMTeam::Red);
+                blue_player_count += self.rejoins.held_spots(HQMTeam::Blue);
                 (red_player_count, blue_player_count)
             };
             let mut new_red_player_count = red_player_count;
             let mut new_blue_player_count = blue_player_count;
 
             for (player_index, player_name) in joining_red {
+                self.make_room_for_player(
+                    server,
+                    player_index,
+                    HQMTeam::Red,
+                    &mut new_red_player_count,
+                );
                 if add_player(
                     &mut self.m,
                     player_index,
@@ -106,6 +489,12 @@ impl HQMMatchBehaviour {
                 }
             }
             for (player_index, player_name) in joining_blue {
+                self.make_room_for_player(
+                    server,
+                    player_index,
+                    HQMTeam::Blue,
+                    &mut new_blue_player_count,
+                );
                 if add_player(
                     &mut self.m,
                     player_index,
@@ -129,6 +518,7 @@ impl HQMMatchBehaviour {
                 && server.values.time > 2000
                 && new_red_player_count > 0
                 && new_blue_player_count > 0
+                && self.m.teams_ready(server)
             {
                 server.values.time = 2000;
             }
@@ -170,6 +560,100 @@ impl HQMMatchBehaviour {
         }
     }
 
+    pub(crate) fn add_bot(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                let admin_player_name = player.player_name.clone();
+                match server.add_bot("Bot".to_string()) {
+                    Some(bot_index) => {
+                        let bot_name = server
+                            .players
+                            .get(bot_index)
+                            .map(|x| x.player_name.clone())
+                            .unwrap_or_default();
+                        info!(
+                            "{} ({}) added bot {} ({})",
+                            admin_player_name, player_index, bot_name, bot_index
+                        );
+                        let msg = format!("{} added by {}", bot_name, admin_player_name);
+                        server.messages.add_server_chat_message(msg);
+                    }
+                    None => {
+                        server
+                            .messages
+                            .add_directed_server_chat_message("The server is full", player_index);
+                    }
+                }
+            } else {
+                server.admin_deny_message(player_index);
+            }
+        }
+    }
+
+    /// Removes the bot with a name, or all bots
+    pub(crate) fn remove_bot(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) {
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                let admin_player_name = player.player_name.clone();
+                let bots: Vec<_> = server
+                    .players
+                    .iter()
+                    .filter(|(_, x)| {
+                        x.is_bot() && (arg == "all" || x.player_name.eq_ignore_ascii_case(arg))
+                    })
+                    .map(|(bot_index, x)| (bot_index, x.player_name.clone()))
+                    .collect();
+                if bots.is_empty() {
+                    server.messages.add_directed_server_chat_message(
+                        "Usage: /removebot <name> or /removebot all",
+                        player_index,
+                    );
+                }
+                for (bot_index, bot_name) in bots {
+                    info!(
+                        "{} ({}) removed bot {} ({})",
+                        admin_player_name, player_index, bot_name, bot_index
+                    );
+                    self.before_player_exit(server, bot_index);
+                    server.remove_player(bot_index, true);
+                    let msg = format!("{} removed by {}", bot_name, admin_player_name);
+                    server.messages.add_server_chat_message(msg);
+                }
+            } else {
+                server.admin_deny_message(player_index);
+            }
+        }
+    }
+
+    /// Moves a bot of a full team to the spectators when a player joins the team
+    fn make_room_for_player(
+        &self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        team: HQMTeam,
+        player_count: &mut usize,
+    ) {
+        let is_bot = server.players.get(player_index).is_some_and(|x| x.is_bot());
+        if is_bot || *player_count < self.team_max {
+            return;
+        }
+        let bot = server
+            .players
+            .iter()
+            .find(|(_, x)| x.is_bot() && matches!(x.object, Some((_, t)) if t == team))
+            .map(|(bot_index, _)| bot_index);
+        if let Some(bot_index) = bot {
+            info!("Bot {} makes room for {}", bot_index, player_index);
+            server.move_to_spectator(bot_index);
+            *player_count -= 1;
+        }
+    }
+
     pub(crate) fn set_team_size(
         &mut self,
         server: &mut HQMServer,
@@ -201,14 +685,51 @@ impl HQMMatchBehaviour {
 impl HQMServerBehaviour for HQMMatchBehaviour {
     fn init(&mut self, server: &mut HQMServer) {
         server.history_length = 1000;
+        for _ in 0..self.m.config.bots {
+            server.add_bot("Bot".to_string());
+        }
     }
 
     fn before_tick(&mut self, server: &mut HQMServer) {
+        let m = &self.m;
+        self.bots
+            .update(server, self.team_max, |team| m.rink_end(team));
+        self.rejoins.tick(server);
+        self.update_held_positions();
         self.update_players(server);
+        self.m.update_positions(server);
     }
 
     fn after_tick(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        let was_game_over = server.values.game_over;
+        let had_pending_ruleset = self.m.has_pending_ruleset();
         self.m.after_tick(server, events);
+        if had_pending_ruleset && !self.m.has_pending_ruleset() {
+            // Keeps the rules of the ruleset after a restart
+            self.save_state();
+        }
+        if let Some(vote) = &mut self.vote {
+            if vote.tick() {
+                self.finish_vote(server, false);
+            }
+        }
+        // The game can also be ended or continued by admins changing the score
+        if server.values.game_over {
+            if self.series_game_winner.is_none() {
+                self.series_game_over(server);
+            }
+        } else if server.values.period > 0 {
+            self.series_game_winner = None;
+        }
+        if server.values.game_over && !was_game_over {
+            if self.m.config.rematch_vote {
+                self.rematch_vote = HQMRematchVote::open(server);
+            }
+        } else if was_game_over && !server.values.game_over {
+            if let Some(vote) = self.rematch_vote.take() {
+                self.sticky_teams = vote.finish(server).unwrap_or_default();
+            }
+        }
     }
 
     fn handle_command(
@@ -246,45 +767,8 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
                             }
                         }
                         "clock" => {
-                            let time_part_string = match args[1].parse::<String>() {
-                                Ok(time_part_string) => time_part_string,
-                                Err(_) => {
-                                    return;
-                                }
-                            };
-
-                            fn parse_t(
-                                s: &str,
-                            ) -> Result<(u32, u32, u32), std::num::ParseIntError>
-                            {
-                                let (time_minutes, rest) =
-                                    if let Some((time_minutes, rest)) = s.split_once(':') {
-                                        (time_minutes.parse::<u32>()?, rest)
-                                    } else {
-                                        (0, s)
-                                    };
-                                let (time_seconds, time_centis) =
-                                    if let Some((time_seconds, time_centis)) = rest.split_once(".")
-                                    {
-                                        let mut centis = time_centis.parse::<u32>()?;
-                                        if time_centis.len() == 1 {
-                                            centis *= 10;
-                                        }
-                                        (time_seconds.parse::<u32>()?, centis)
-                                    } else {
-                                        (rest.parse::<u32>()?, 0)
-                                    };
-                                Ok((time_minutes, time_seconds, time_centis))
-                            }
-
-                            if let Ok((time_minutes, time_seconds, time_centis)) =
-                                parse_t(&time_part_string)
-                            {
-                                self.m.set_clock(
-                                    server,
-                                    (time_minutes * 100 * 60) + (time_seconds * 100) + time_centis,
-                                    player_index,
-                                );
+                            if let Ok(time) = parse_clock(args[1]) {
+                                self.m.set_clock(server, time, player_index);
                             }
                         }
                         "icing" => {
@@ -317,6 +801,12 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
                                 self.m.set_first_to_rule(server, player_index, arg);
                             }
                         }
+                        "pucks" => {
+                            if let Some(arg) = args.get(1) {
+                                self.m
+                                    .set_pucks_in_play(server, player_index, arg, self.team_max);
+                            }
+                        }
                         "teamsize" => {
                             if let Some(arg) = args.get(1) {
                                 self.set_team_size(server, player_index, arg);
@@ -380,6 +870,9 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
                     self.force_player_off_ice(server, player_index, force_player_index);
                 }
             }
+            "penalty" => {
+                self.m.penalty(server, player_index, arg);
+            }
             "icing" => {
                 self.m.set_icing_rule(server, player_index, arg);
             }
@@ -389,6 +882,36 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
             "rules" => {
                 self.m.msg_rules(server, player_index);
             }
+            "series" => {
+                self.msg_series(server, player_index);
+            }
+            "ruleset" => {
+                self.m.set_ruleset(server, player_index, arg);
+            }
+            "winprob" => {
+                self.m.msg_win_probability(server, player_index);
+            }
+            "rematch" => {
+                self.vote_rematch(server, player_index, arg);
+            }
+            "ready" => {
+                self.m.ready(server, player_index);
+            }
+            "addbot" => {
+                self.add_bot(server, player_index);
+            }
+            "removebot" => {
+                self.remove_bot(server, player_index, arg);
+            }
+            "puck" | "cone" | "target" | "tp" | "dummy" => {
+                self.m.practice(server, command, arg, player_index);
+            }
+            "vote" => {
+                self.vote(server, player_index, arg);
+            }
+            "reloadconfig" => {
+                self.reload_config(server, player_index);
+            }
             "chatextend" => {
                 if arg.eq_ignore_ascii_case("true") || arg.eq_ignore_ascii_case("on") {
                     if self.show_extra_messages.insert(player_index) {
@@ -408,6 +931,62 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
             }
             _ => {}
         };
+        self.save_state();
+    }
+
+    fn handle_console_command(
+        &mut self,
+        server: &mut HQMServer,
+        command: &str,
+        arg: &str,
+    ) -> Option<String> {
+        let response = match command {
+            "start" | "startgame" => {
+                console_response(self.m.start_game_by(server, &HQMAdminSource::Console))
+            }
+            "pause" | "pausegame" => self.m.pause_by(server, &HQMAdminSource::Console),
+            "unpause" | "unpausegame" => self.m.unpause_by(server, &HQMAdminSource::Console),
+            "faceoff" => console_response(self.m.faceoff_by(server, &HQMAdminSource::Console)),
+            "set" => {
+                let usage = "Usage: set <clock|redscore|bluescore|period> <value>";
+                let (setting, value) = match arg.split_once(' ') {
+                    Some((setting, value)) => (setting, value.trim()),
+                    None => return Some(usage.to_string()),
+                };
+                match setting {
+                    "clock" => match parse_clock(value) {
+                        Ok(time) => self.m.set_clock_by(server, &HQMAdminSource::Console, time),
+                        Err(_) => "Usage: set clock <[minutes:]seconds[.centiseconds]>".to_string(),
+                    },
+                    "redscore" | "bluescore" => match value.parse::<u32>() {
+                        Ok(score) => {
+                            let team = if setting == "redscore" {
+                                HQMTeam::Red
+                            } else {
+                                HQMTeam::Blue
+                            };
+                            self.m
+                                .set_score_by(server, &HQMAdminSource::Console, team, score)
+                        }
+                        Err(_) => format!("Usage: set {} <score>", setting),
+                    },
+                    "period" => match value.parse::<u32>() {
+                        Ok(period) => {
+                            self.m
+                                .set_period_by(server, &HQMAdminSource::Console, period)
+                        }
+                        Err(_) => "Usage: set period <period>".to_string(),
+                    },
+                    _ => usage.to_string(),
+                }
+            }
+            _ => return None,
+        };
+        Some(response)
+    }
+
+    fn console_help(&self) -> Option<&'static str> {
+        Some("start, pause, unpause, faceoff, set <clock|redscore|bluescore|period> <value>")
     }
 
     fn get_initial_game_values(&mut self) -> HQMInitialGameValues {
@@ -416,10 +995,89 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
 
     fn game_started(&mut self, server: &mut HQMServer) {
         self.m.game_started(server);
+        self.update_series(server);
+        self.resumed_roster.clear();
+        self.rejoins.clear();
+        self.update_held_positions();
+        self.keep_sticky_teams(server);
+    }
+
+    fn game_resumed(&mut self, server: &mut HQMServer, snapshot: &HQMGameSnapshot) {
+        self.m.paused = true;
+        self.m.set_ends_swapped(server, snapshot.ends_swapped);
+        self.resumed_roster = snapshot
+            .roster
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.team))
+            .collect();
+        let mut player_indices = vec![];
+        let mut on_wrong_team = vec![];
+        for (player_index, player) in server.players.iter() {
+            player_indices.push(player_index);
+            if let Some((_, team)) = player.object {
+                match self.resumed_roster.get(player.player_name.as_str()) {
+                    Some(resumed_team) if *resumed_team != team => on_wrong_team.push(player_index),
+                    _ => {}
+                }
+            }
+        }
+        for player_index in on_wrong_team {
+            server.move_to_spectator(player_index);
+        }
+        for player_index in player_indices {
+            self.return_to_resumed_team(server, player_index);
+        }
+        let msg = format!(
+            "Game restored in period {} at {}-{}, paused until an admin unpauses",
+            snapshot.period, snapshot.red_score, snapshot.blue_score
+        );
+        server.messages.add_server_chat_message(msg);
     }
 
-    fn before_player_exit(&mut self, _server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        self.m.cleanup_player(player_index);
+    fn can_resume_game(&self) -> bool {
+        true
+    }
+
+    fn after_player_join(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if server.values.period == 0 {
+            self.m.msg_last_game_result(server, player_index);
+        }
+        self.m.restore_position(server, player_index);
+        self.return_to_resumed_team(server, player_index);
+        self.return_to_held_spot(server, player_index);
+    }
+
+    fn before_player_disconnect(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        self.m.pause_for_disconnect(server, player_index);
+        let grace = self.m.config.rejoin_grace;
+        if grace > 0 && server.values.period > 0 && !server.values.game_over {
+            self.rejoins.hold(
+                server,
+                player_index,
+                grace,
+                self.m.config.rejoin_keep_skater,
+                self.m.preferred_position(player_index),
+            );
+            self.update_held_positions();
+        }
+    }
+
+    fn before_player_exit(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if let Some(vote) = &mut self.rematch_vote {
+            vote.remove_player(server, player_index);
+        }
+        if let Some(passed) = self
+            .vote
+            .as_mut()
+            .and_then(|vote| vote.remove_player(player_index))
+        {
+            self.finish_vote(server, passed);
+        }
+        self.m.cleanup_player(server, player_index);
         self.team_switch_timer.remove(&player_index);
         self.show_extra_messages.remove(&player_index);
     }
@@ -431,6 +1089,30 @@ impl HQMServerBehaviour for HQMMatchBehaviour {
     fn save_replay_data(&self, server: &HQMServer) -> bool {
         server.values.period > 0
     }
+
+    fn regular_periods(&self) -> Option<u32> {
+        Some(self.m.config.periods)
+    }
+}
+
+/// Parses a clock time like 4:30, 4:30.5 or 90, returned in hundredths of a second
+pub(crate) fn parse_clock(s: &str) -> Result<u32, std::num::ParseIntError> {
+    let (time_minutes, rest) = if let Some((time_minutes, rest)) = s.split_once(':') {
+        (time_minutes.parse::<u32>()?, rest)
+    } else {
+        (0, s)
+    };
+    let (time_seconds, time_centis) =
+        if let Some((time_seconds, time_centis)) = rest.split_once(".") {
+            let mut centis = time_centis.parse::<u32>()?;
+            if time_centis.len() == 1 {
+                centis *= 10;
+            }
+            (time_seconds.parse::<u32>()?, centis)
+        } else {
+            (rest.parse::<u32>()?, 0)
+        };
+    Ok((time_minutes * 100 * 60) + (time_seconds * 100) + time_centis)
 }
 
 fn add_player(
@@ -447,7 +1129,7 @@ fn add_player(
         return false;
     }
 
-    let (pos, rot) = get_spawnpoint(&server.world.rink, team, spawn_point);
+    let (pos, rot) = get_spawnpoint(&server.world.rink, m.rink_end(team), spawn_point);
 
     if server
         .spawn_skater(player_index, team, pos, rot, false)
@@ -1,73 +1,124 @@
+use crate::hqm_game::{objects_fit, MAX_PUCKS};
+use crate::hqm_game_log::GAME_LOG;
 use crate::hqm_match_util::{
     HQMIcingConfiguration, HQMMatch, HQMOffsideConfiguration, HQMOffsideLineConfiguration,
     HQMTwoLinePassConfiguration, ALLOWED_POSITIONS,
 };
+use crate::hqm_penalty::{MAJOR_PENALTY, MINOR_PENALTY};
+use crate::hqm_practice::teleport;
 use crate::hqm_server::HQMTeam;
-use crate::hqm_server::{HQMServer, HQMServerPlayerIndex};
+use crate::hqm_server::{HQMAdminSource, HQMServer, HQMServerPlayerIndex};
+use crate::hqm_stats_page::format_time;
 use tracing::info;
 
 impl HQMMatch {
     pub fn reset_game(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                info!("{} ({}) reset game", player.player_name, player_index);
-                let msg = format!("Game reset by {}", player.player_name);
-
-                server.new_game(self.get_initial_game_values());
-
-                server.messages.add_server_chat_message(msg);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            server.reset_game_by(&source, self.get_initial_game_values());
         }
     }
 
     pub fn start_game(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                if server.values.period == 0 && server.values.time > 1 {
-                    info!("{} ({}) started game", player.player_name, player_index);
-                    let msg = format!("Game started by {}", player.player_name);
-                    self.paused = false;
-                    server.values.time = 1;
+        if let Some(source) = server.admin_source(player_index) {
+            let result = self.start_game_by(server, &source);
+            server.reply_to_admin(player_index, result);
+        }
+    }
 
-                    server.messages.add_server_chat_message(msg);
-                }
-            } else {
-                server.admin_deny_message(player_index);
+    pub fn ready(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if self.config.ready_players == 0 {
+            server
+                .messages
+                .add_directed_server_chat_message("Ready-up is disabled", player_index);
+            return;
+        }
+        if server.values.period != 0 {
+            server
+                .messages
+                .add_directed_server_chat_message("The game has already started", player_index);
+            return;
+        }
+        let player_name = match server.players.get(player_index) {
+            Some(player) if player.object.is_some() => player.player_name.clone(),
+            Some(_) => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("Join a team to get ready", player_index);
+                return;
             }
+            None => return,
+        };
+        let state = if self.ready.insert(player_index) {
+            "ready"
+        } else {
+            self.ready.remove(&player_index);
+            "no longer ready"
+        };
+        let msg = format!(
+            "{} is {} (Red {}/{}, Blue {}/{})",
+            player_name,
+            state,
+            self.ready_count(server, HQMTeam::Red),
+            self.config.ready_players,
+            self.ready_count(server, HQMTeam::Blue),
+            self.config.ready_players
+        );
+        server.messages.add_server_chat_message(msg);
+        if self.teams_ready(server) && server.values.time > 2000 {
+            server.values.time = 2000;
+            server
+                .messages
+                .add_server_chat_message("Both teams are ready, the game starts in 20 seconds");
+        }
+    }
+
+    /// /puck, /cone, /target, /tp and /dummy, which players can use on their own during warmup
+    pub fn practice(
+        &mut self,
+        server: &mut HQMServer,
+        command: &str,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        if !self.config.warmup_practice {
+            server
+                .messages
+                .add_directed_server_chat_message("Warmup practice is disabled", player_index);
+            return;
+        }
+        if server.values.period != 0 {
+            server.messages.add_directed_server_chat_message(
+                "This command only works during warmup",
+                player_index,
+            );
+            return;
+        }
+        match command {
+            "puck" => self.practice.spawn_puck(server, player_index, arg),
+            "cone" => self.practice.place_cone(server, player_index, false, arg),
+            "target" => self.practice.place_cone(server, player_index, true, arg),
+            "dummy" => self.practice.place_dummy(server, player_index, arg),
+            "tp" => teleport(
+                server,
+                player_index,
+                arg,
+                self.config.spawn_point_offset,
+                self.config.spawn_player_altitude,
+                self.config.spawn_keep_stick_position,
+            ),
+            _ => {}
         }
     }
 
     pub fn pause(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                self.paused = true;
-                if self.pause_timer > 0 && self.pause_timer < self.config.time_break {
-                    // If we're currently in a break, with very little time left,
-                    // we reset the timer
-                    self.pause_timer = self.config.time_break;
-                }
-                info!("{} ({}) paused game", player.player_name, player_index);
-                let msg = format!("Game paused by {}", player.player_name);
-                server.messages.add_server_chat_message(msg);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            self.pause_by(server, &source);
         }
     }
 
     pub fn unpause(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                self.paused = false;
-                info!("{} ({}) resumed game", player.player_name, player_index);
-                let msg = format!("Game resumed by {}", player.player_name);
-
-                server.messages.add_server_chat_message(msg);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            self.unpause_by(server, &source);
         }
     }
 
@@ -77,25 +128,8 @@ impl HQMMatch {
         input_time: u32,
         player_index: HQMServerPlayerIndex,
     ) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                server.values.time = input_time;
-
-                let input_minutes = input_time / (60 * 100);
-                let input_rest = input_time % (60 * 100);
-                let input_seconds = input_rest / 100;
-                let input_centis = input_time % 100;
-
-                info!(
-                    "Clock set to {}:{:02}.{:02} by {} ({})",
-                    input_minutes, input_seconds, input_centis, player.player_name, player_index
-                );
-                let msg = format!("Clock set by {}", player.player_name);
-                server.messages.add_server_chat_message(msg);
-                self.update_game_over(server);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            self.set_clock_by(server, &source, input_time);
         }
     }
 
@@ -106,34 +140,8 @@ impl HQMMatch {
         input_score: u32,
         player_index: HQMServerPlayerIndex,
     ) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                match input_team {
-                    HQMTeam::Red => {
-                        server.values.red_score = input_score;
-
-                        info!(
-                            "{} ({}) changed red score to {}",
-                            player.player_name, player_index, input_score
-                        );
-                        let msg = format!("Red score changed by {}", player.player_name);
-                        server.messages.add_server_chat_message(msg);
-                    }
-                    HQMTeam::Blue => {
-                        server.values.blue_score = input_score;
-
-                        info!(
-                            "{} ({}) changed blue score to {}",
-                            player.player_name, player_index, input_score
-                        );
-                        let msg = format!("Blue score changed by {}", player.player_name);
-                        server.messages.add_server_chat_message(msg);
-                    }
-                }
-                self.update_game_over(server);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            self.set_score_by(server, &source, input_team, input_score);
         }
     }
 
@@ -143,20 +151,8 @@ impl HQMMatch {
         input_period: u32,
         player_index: HQMServerPlayerIndex,
     ) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                server.values.period = input_period;
-
-                info!(
-                    "{} ({}) set period to {}",
-                    player.player_name, player_index, input_period
-                );
-                let msg = format!("Period set by {}", player.player_name);
-                server.messages.add_server_chat_message(msg);
-                self.update_game_over(server);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            self.set_period_by(server, &source, input_period);
         }
     }
 
@@ -386,6 +382,62 @@ impl HQMMatch {
         }
     }
 
+    pub fn set_ruleset(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        name: &str,
+    ) {
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                if name.is_empty() {
+                    let msg = if self.config.rulesets.is_empty() {
+                        "No rulesets are configured".to_string()
+                    } else {
+                        let names: Vec<&str> = self
+                            .config
+                            .rulesets
+                            .iter()
+                            .map(|ruleset| ruleset.name.as_str())
+                            .collect();
+                        format!("Rulesets: {}", names.join(", "))
+                    };
+                    server
+                        .messages
+                        .add_directed_server_chat_message(msg, player_index);
+                    return;
+                }
+                let ruleset = self
+                    .config
+                    .rulesets
+                    .iter()
+                    .find(|ruleset| ruleset.name.eq_ignore_ascii_case(name));
+                match ruleset {
+                    Some(ruleset) => {
+                        info!(
+                            "{} ({}) switched to ruleset {}",
+                            player.player_name, player_index, ruleset.name
+                        );
+                        let msg = format!(
+                            "{} switched to the {} rules, starting with the next faceoff",
+                            player.player_name, ruleset.name
+                        );
+                        server.messages.add_server_chat_message(msg);
+                        self.pending_ruleset = Some(ruleset.clone());
+                    }
+                    None => {
+                        let msg = format!("Unknown ruleset {}", name);
+                        server
+                            .messages
+                            .add_directed_server_chat_message(msg, player_index);
+                    }
+                }
+            } else {
+                server.admin_deny_message(player_index);
+            }
+        }
+    }
+
     pub fn set_goal_replay(
         &mut self,
         server: &mut HQMServer,
@@ -495,43 +547,104 @@ impl HQMMatch {
         }
     }
 
-    pub fn faceoff(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if !server.values.game_over {
-            if let Some(player) = server.players.get(player_index) {
-                if player.is_admin {
-                    self.pause_timer = 5 * 100;
-                    self.paused = false; // Unpause if it's paused as well
+    pub fn set_pucks_in_play(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        num: &str,
+        team_max: usize,
+    ) {
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                match num.parse::<usize>() {
+                    Ok(new_num) if !objects_fit(new_num, team_max) => {
+                        let msg = format!(
+                            "{} pucks and two teams of {} players don't fit in the 32 objects",
+                            new_num, team_max
+                        );
+                        server
+                            .messages
+                            .add_directed_server_chat_message(msg, player_index);
+                    }
+                    Ok(new_num) if (1..=MAX_PUCKS).contains(&new_num) => {
+                        self.config.pucks_in_play = new_num;
 
-                    let msg = format!("Faceoff initiated by {}", player.player_name);
-                    info!(
-                        "{} ({}) initiated faceoff",
-                        player.player_name, player_index
-                    );
-                    server.messages.add_server_chat_message(msg);
-                } else {
-                    server.admin_deny_message(player_index);
+                        info!(
+                            "{} ({}) set pucks in play to {}",
+                            player.player_name, player_index, new_num
+                        );
+                        let msg = format!(
+                            "Pucks in play set to {} by {}, from the next faceoff on",
+                            new_num, player.player_name
+                        );
+                        server.messages.add_server_chat_message(msg);
+                    }
+                    _ => {
+                        let msg = format!("Pucks in play must be between 1 and {}", MAX_PUCKS);
+                        server
+                            .messages
+                            .add_directed_server_chat_message(msg, player_index);
+                    }
                 }
+            } else {
+                server.admin_deny_message(player_index);
             }
         }
     }
 
+    pub fn faceoff(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if let Some(source) = server.admin_source(player_index) {
+            let result = self.faceoff_by(server, &source);
+            server.reply_to_admin(player_index, result);
+        }
+    }
+
     pub fn set_preferred_faceoff_position(
         &mut self,
         server: &mut HQMServer,
         player_index: HQMServerPlayerIndex,
-        input_position: &str,
+        arg: &str,
     ) {
-        let input_position = input_position.to_uppercase();
+        let mut args = arg.split_whitespace();
+        let input_position = args.next().unwrap_or_default().to_uppercase();
+        let wait = args.next().is_some_and(|x| x.eq_ignore_ascii_case("wait"));
         if let Some(position) = ALLOWED_POSITIONS
             .into_iter()
             .find(|x| x.eq_ignore_ascii_case(input_position.as_str()))
         {
             if let Some(player) = server.players.get(player_index) {
+                let team = player.object.map(|(_, team)| team);
+                let holder = team.and_then(|team| {
+                    self.position_holder(server, team, position, &[player_index])
+                        .map(|holder_name| (team, holder_name))
+                });
+                if let Some((team, holder_name)) = holder {
+                    let msg = if wait {
+                        let place = self.position_claims.wait(player_index, team, position);
+                        format!(
+                            "{} is taken by {}, you get it when it's free ({} in line)",
+                            position, holder_name, place
+                        )
+                    } else {
+                        format!(
+                            "{} is taken by {}, use /sp {} wait to get it when it's free",
+                            position, holder_name, position
+                        )
+                    };
+                    server
+                        .messages
+                        .add_directed_server_chat_message(msg, player_index);
+                    return;
+                }
+                self.position_claims.remove(player_index);
                 info!(
                     "{} ({}) set position {}",
                     player.player_name, player_index, position
                 );
                 let msg = format!("{} position {}", player.player_name, position);
+                server.preferences.update(&player.player_name, |x| {
+                    x.position = Some(position.to_string())
+                });
 
                 self.preferred_positions.insert(player_index, position);
                 server.messages.add_server_chat_message(msg);
@@ -539,6 +652,24 @@ impl HQMMatch {
         }
     }
 
+    pub fn msg_win_probability(
+        &self,
+        server: &mut HQMServer,
+        receiver_index: HQMServerPlayerIndex,
+    ) {
+        let msg = match self.win_probability(server) {
+            Some(red) => format!(
+                "Win probability: Red {:.0}%, Blue {:.0}%",
+                red * 100.0,
+                (1.0 - red) * 100.0
+            ),
+            None => "The game is not in progress".to_string(),
+        };
+        server
+            .messages
+            .add_directed_server_chat_message(msg, receiver_index);
+    }
+
     pub fn msg_rules(&self, server: &mut HQMServer, receiver_index: HQMServerPlayerIndex) {
         let offside_str = match self.config.offside {
             HQMOffsideConfiguration::Off => "Offside disabled",
@@ -695,4 +826,235 @@ impl HQMMatch {
             }
         }
     }
+
+    /// Sends a player to the penalty box for 2 or 5 minutes of game time, or lets them out with
+    /// 0 minutes. Without arguments, lists the penalties that are being served
+    pub fn penalty(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) {
+        let admin_name = match server.players.get(player_index) {
+            Some(player) if player.is_admin => player.player_name.clone(),
+            Some(_) => {
+                server.admin_deny_message(player_index);
+                return;
+            }
+            None => return,
+        };
+        let args: Vec<&str> = arg.split_whitespace().collect();
+        if args.is_empty() {
+            let penalties: Vec<_> = self
+                .penalties
+                .penalties()
+                .iter()
+                .map(|penalty| {
+                    format!(
+                        "{} ({}) {}",
+                        penalty.player_name,
+                        penalty.team,
+                        format_time(penalty.time_left)
+                    )
+                })
+                .collect();
+            let msg = if penalties.is_empty() {
+                "Nobody is in the penalty box".to_string()
+            } else {
+                format!("Penalties: {}", penalties.join(", "))
+            };
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        let minutes = match args.get(1) {
+            Some(minutes) => minutes.parse::<u32>().ok(),
+            None => Some(MINOR_PENALTY),
+        };
+        let penalized_index = args[0].parse::<HQMServerPlayerIndex>().ok();
+        let (penalized_index, minutes) = match (penalized_index, minutes) {
+            (Some(penalized_index), Some(minutes @ (0 | MINOR_PENALTY | MAJOR_PENALTY)))
+                if args.len() <= 2 =>
+            {
+                (penalized_index, minutes)
+            }
+            _ => {
+                server.messages.add_directed_server_chat_message(
+                    "Usage: /penalty <ID> [2|5], 0 minutes ends the penalty",
+                    player_index,
+                );
+                return;
+            }
+        };
+        let (penalized_name, penalized_team) = match server.players.get(penalized_index) {
+            Some(player) => (
+                player.player_name.clone(),
+                player.object.map(|(_, team)| team),
+            ),
+            None => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("No player with that ID", player_index);
+                return;
+            }
+        };
+
+        if minutes == 0 {
+            match self.penalties.remove(&penalized_name) {
+                Some(team) => {
+                    info!(
+                        "{} ({}) ended the penalty of {}",
+                        admin_name, player_index, penalized_name
+                    );
+                    self.release_from_penalty_box(server, &penalized_name, team);
+                    self.update_power_play(server, false);
+                }
+                None => {
+                    let msg = format!("{} is not in the penalty box", penalized_name);
+                    server
+                        .messages
+                        .add_directed_server_chat_message(msg, player_index);
+                }
+            }
+            return;
+        }
+
+        // A player who is already in the box serves the new penalty after the old one
+        let team = match penalized_team.or_else(|| self.penalties.team(&penalized_name)) {
+            Some(team) => team,
+            None => {
+                let msg = format!("{} is not on a team", penalized_name);
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, player_index);
+                return;
+            }
+        };
+        info!(
+            target: GAME_LOG,
+            %team,
+            player = penalized_name.as_str(),
+            minutes,
+            period = server.values.period,
+            time = server.values.time,
+            "{} minute penalty to {} by {}",
+            minutes,
+            penalized_name,
+            admin_name
+        );
+        self.send_to_penalty_box(server, penalized_index, penalized_name, team, minutes);
+    }
+
+    // The admin commands below are shared by chat and the admin console, which prints the
+    // returned text
+
+    pub fn start_game_by(
+        &mut self,
+        server: &mut HQMServer,
+        source: &HQMAdminSource,
+    ) -> Result<String, String> {
+        if server.values.period != 0 || server.values.time <= 1 {
+            return Err("The game has already started".to_string());
+        }
+        info!("{} started game", source);
+        self.force_start(server);
+        let msg = format!("Game started by {}", source.name());
+        server.messages.add_server_chat_message(msg);
+        Ok("Game started".to_string())
+    }
+
+    pub fn pause_by(&mut self, server: &mut HQMServer, source: &HQMAdminSource) -> String {
+        self.paused = true;
+        if self.pause_timer > 0 && self.pause_timer < self.config.time_break {
+            // If we're currently in a break, with very little time left,
+            // we reset the timer
+            self.pause_timer = self.config.time_break;
+        }
+        info!("{} paused game", source);
+        let msg = format!("Game paused by {}", source.name());
+        server.messages.add_server_chat_message(msg);
+        "Game paused".to_string()
+    }
+
+    pub fn unpause_by(&mut self, server: &mut HQMServer, source: &HQMAdminSource) -> String {
+        self.paused = false;
+        info!("{} resumed game", source);
+        let msg = format!("Game resumed by {}", source.name());
+        server.messages.add_server_chat_message(msg);
+        "Game resumed".to_string()
+    }
+
+    pub fn faceoff_by(
+        &mut self,
+        server: &mut HQMServer,
+        source: &HQMAdminSource,
+    ) -> Result<String, String> {
+        if server.values.game_over {
+            return Err("The game is over".to_string());
+        }
+        self.pause_timer = 5 * 100;
+        self.paused = false; // Unpause if it's paused as well
+        info!("{} initiated faceoff", source);
+        let msg = format!("Faceoff initiated by {}", source.name());
+        server.messages.add_server_chat_message(msg);
+        Ok("Faceoff initiated".to_string())
+    }
+
+    pub fn set_clock_by(
+        &mut self,
+        server: &mut HQMServer,
+        source: &HQMAdminSource,
+        input_time: u32,
+    ) -> String {
+        server.values.time = input_time;
+        let clock = format!(
+            "{}:{:02}.{:02}",
+            input_time / (60 * 100),
+            (input_time % (60 * 100)) / 100,
+            input_time % 100
+        );
+        info!("Clock set to {} by {}", clock, source);
+        let msg = format!("Clock set by {}", source.name());
+        server.messages.add_server_chat_message(msg);
+        self.update_game_over(server);
+        format!("Clock set to {}", clock)
+    }
+
+    pub fn set_score_by(
+        &mut self,
+        server: &mut HQMServer,
+        source: &HQMAdminSource,
+        input_team: HQMTeam,
+        input_score: u32,
+    ) -> String {
+        match input_team {
+            HQMTeam::Red => server.values.red_score = input_score,
+            HQMTeam::Blue => server.values.blue_score = input_score,
+        }
+        info!(
+            "{} changed {} score to {}",
+            source,
+            input_team.to_string().to_lowercase(),
+            input_score
+        );
+        let msg = format!("{} score changed by {}", input_team, source.name());
+        server.messages.add_server_chat_message(msg);
+        self.update_game_over(server);
+        format!("{} score set to {}", input_team, input_score)
+    }
+
+    pub fn set_period_by(
+        &mut self,
+        server: &mut HQMServer,
+        source: &HQMAdminSource,
+        input_period: u32,
+    ) -> String {
+        server.values.period = input_period;
+        info!("{} set period to {}", source, input_period);
+        let msg = format!("Period set by {}", source.name());
+        server.messages.add_server_chat_message(msg);
+        self.update_game_over(server);
+        format!("Period set to {}", input_period)
+    }
 }
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use crate::hqm_game::HQMObjectIndex;
+use crate::hqm_server::{HQMServer, HQMServerPlayerIndex, HQMTeam};
+
+/// Time a player has to spend on the ice before any hints are given, and between two hints
+const HINT_INTERVAL: u32 = 6000;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum HQMHint {
+    Position,
+    Backwards,
+    Passing,
+}
+
+impl HQMHint {
+    fn bit(self) -> u8 {
+        match self {
+            HQMHint::Position => 0x1,
+            HQMHint::Backwards => 0x2,
+            HQMHint::Passing => 0x4,
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            HQMHint::Position => "Tip: you can pick a position other than C with /sp, for example /sp LW",
+            HQMHint::Backwards => "Tip: you skate faster forwards than backwards",
+            HQMHint::Passing => "Tip: passing the puck to a teammate often gets it up the ice faster than skating with it",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct HQMPlayerHintState {
+    ticks_on_ice: u32,
+    backwards_ticks: u32,
+    touches: u32,
+    passes: u32,
+    last_hint_tick: u32,
+    hints_given: u8,
+}
+
+impl HQMPlayerHintState {
+    fn next_hint(&self, has_position: bool, has_teammates: bool) -> Option<HQMHint> {
+        if self.ticks_on_ice < self.last_hint_tick + HINT_INTERVAL {
+            return None;
+        }
+        let hints = [
+            (HQMHint::Position, !has_position && has_teammates),
+            (
+                HQMHint::Backwards,
+                self.backwards_ticks > self.ticks_on_ice / 2,
+            ),
+            (
+                HQMHint::Passing,
+                has_teammates && self.touches >= 30 && self.passes == 0,
+            ),
+        ];
+        hints
+            .into_iter()
+            .find(|(hint, applies)| *applies && self.hints_given & hint.bit() == 0)
+            .map(|(hint, _)| hint)
+    }
+}
+
+/// Looks for behaviour typical of new players, and sends them a tip now and then
+#[derive(Debug, Default)]
+pub(crate) struct HQMNewPlayerHints {
+    players: HashMap<HQMServerPlayerIndex, HQMPlayerHintState>,
+    /// The player whose stick is on each puck and the last step it was, as a stick touches
+    /// the puck in every step while it's carried
+    contacts: HashMap<HQMObjectIndex, (HQMServerPlayerIndex, u32)>,
+    timer: u32,
+}
+
+impl HQMNewPlayerHints {
+    /// Records a puck touch, and a pass for the previous player if they are on the same team.
+    /// Touches in consecutive steps are one contact, which is counted once when it starts
+    pub(crate) fn record_touch(
+        &mut self,
+        puck_index: HQMObjectIndex,
+        player_index: HQMServerPlayerIndex,
+        team: HQMTeam,
+        previous_touch: Option<(HQMServerPlayerIndex, HQMTeam)>,
+        game_step: u32,
+    ) {
+        let contact = self.contacts.insert(puck_index, (player_index, game_step));
+        let is_new_contact = match contact {
+            Some((contact_player_index, last_step)) => {
+                contact_player_index != player_index || game_step.wrapping_sub(last_step) > 1
+            }
+            None => true,
+        };
+        if !is_new_contact {
+            return;
+        }
+        self.players.entry(player_index).or_default().touches += 1;
+        if let Some((previous_player_index, previous_team)) = previous_touch {
+            if previous_player_index != player_index && previous_team == team {
+                self.players
+                    .entry(previous_player_index)
+                    .or_default()
+                    .passes += 1;
+            }
+        }
+    }
+
+    pub(crate) fn remove_player(&mut self, player_index: HQMServerPlayerIndex) {
+        self.players.remove(&player_index);
+        self.contacts
+            .retain(|_, (contact_player_index, _)| *contact_player_index != player_index);
+    }
+
+    pub(crate) fn update(
+        &mut self,
+        server: &mut HQMServer,
+        preferred_positions: &HashMap<HQMServerPlayerIndex, &'static str>,
+    ) {
+        let mut red_players = 0;
+        let mut blue_players = 0;
+        for (player_index, player) in server.players.iter() {
+            if let Some((_, team)) = player.object {
+                match team {
+                    HQMTeam::Red => red_players += 1,
+                    HQMTeam::Blue => blue_players += 1,
+                }
+                let state = self.players.entry(player_index).or_default();
+                state.ticks_on_ice += 1;
+                if player.input.fwbw < 0.0 {
+                    state.backwards_ticks += 1;
+                }
+            }
+        }
+
+        self.timer += 1;
+        if self.timer < 100 {
+            return;
+        }
+        self.timer = 0;
+
+        let mut hints = vec![];
+        for (player_index, player) in server.players.iter() {
+            if let Some((_, team)) = player.object {
+                let has_teammates = match team {
+                    HQMTeam::Red => red_players > 1,
+                    HQMTeam::Blue => blue_players > 1,
+                };
+                let has_position = preferred_positions.contains_key(&player_index);
+                if let Some(state) = self.players.get_mut(&player_index) {
+                    if let Some(hint) = state.next_hint(has_position, has_teammates) {
+                        state.hints_given |= hint.bit();
+                        state.last_hint_tick = state.ticks_on_ice;
+                        hints.push((player_index, hint));
+                    }
+                }
+            }
+        }
+        for (player_index, hint) in hints {
+            server
+                .messages
+                .add_directed_server_chat_message(hint.message(), player_index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carrying_the_puck_is_one_touch() {
+        let mut hints = HQMNewPlayerHints::default();
+        let puck = HQMObjectIndex(0);
+        let player = HQMServerPlayerIndex(1);
+        let teammate = HQMServerPlayerIndex(2);
+        // Carried for a second, then touched again after a gap
+        for step in 100..200 {
+            hints.record_touch(puck, player, HQMTeam::Red, None, step);
+        }
+        hints.record_touch(puck, player, HQMTeam::Red, None, 300);
+        assert_eq!(hints.players[&player].touches, 2);
+        assert_eq!(hints.players[&player].passes, 0);
+
+        // Passed to a teammate, who carries it
+        for step in 400..450 {
+            let previous = Some((player, HQMTeam::Red));
+            hints.record_touch(puck, teammate, HQMTeam::Red, previous, step);
+        }
+        assert_eq!(hints.players[&teammate].touches, 1);
+        assert_eq!(hints.players[&player].passes, 1);
+    }
+}
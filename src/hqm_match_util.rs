@@ -1,18 +1,36 @@
+use crate::hqm_alerts::HQMAlertKind;
+use crate::hqm_countdown::{format_countdown, HQMChatCountdown};
+use crate::hqm_event_feed::{HQMEvent, HQMGoalStrength, HQMIcePosition, HQMRuleCall};
 use crate::hqm_game::{
-    HQMGameValues, HQMObjectIndex, HQMPhysicsConfiguration, HQMPuck, HQMRink, HQMRinkLine,
-    HQMRulesState,
+    HQMGameValues, HQMObjectIndex, HQMPhysicsProfile, HQMPuck, HQMRink, HQMRinkCreases,
+    HQMRinkLine, HQMRulesState, HQMSkater,
 };
+use crate::hqm_goal_streak::HQMGoalStreak;
+use crate::hqm_match_hints::HQMNewPlayerHints;
+use crate::hqm_penalty::{HQMPenaltyBox, MINOR_PENALTY};
+use crate::hqm_positions::HQMPositionClaims;
+use crate::hqm_practice::HQMPractice;
 use crate::hqm_server::{
-    HQMInitialGameValues, HQMServer, HQMServerPlayer, HQMServerPlayerIndex, HQMServerPlayerList,
-    HQMTeam,
+    HQMInitialGameValues, HQMServer, HQMServerPlayer, HQMServerPlayerData, HQMServerPlayerIndex,
+    HQMServerPlayerList, HQMTeam,
 };
+use crate::hqm_win_probability::win_probability;
 
 use crate::hqm_game::HQMRinkSideOfLine::{BlueSide, RedSide};
+use crate::hqm_game_log::GAME_LOG;
 use crate::hqm_simulate::HQMSimulationEvent;
+use crate::hqm_tiebreaker::{
+    HQMShootoutAttempt, HQMShootoutAttemptState, HQMShootoutTiebreaker, SHOOTOUT_ATTEMPT_BREAK,
+    SHOOTOUT_ATTEMPT_TIME,
+};
 use nalgebra::{Point3, Rotation3, Vector3};
-use std::collections::hash_map::Entry;
-use std::collections::{HashMap, VecDeque};
+use std::cmp::Ordering;
+use std::collections::hash_map::{Entry, RandomState};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::f32::consts::{FRAC_PI_2, PI};
+use std::hash::{BuildHasher, Hasher};
+use std::rc::Rc;
+use tracing::info;
 
 pub const ALLOWED_POSITIONS: [&str; 18] = [
     "C", "LW", "RW", "LD", "RD", "G", "LM", "RM", "LLM", "RRM", "LLD", "RRD", "CM", "CD", "LW2",
@@ -41,6 +59,17 @@ pub enum HQMRinkFaceoffSpot {
 
 pub struct HQMMatchConfiguration {
     pub time_period: u32,
+    /// Lengths of the regular periods in seconds. Periods without a length are time_period long
+    pub period_lengths: Vec<u32>,
+    /// Length of overtime periods in seconds
+    pub time_overtime: u32,
+    /// The clock keeps running during stoppages after goals and rule calls
+    pub running_clock: bool,
+    /// The teams change the net they attack every period
+    pub swap_ends: bool,
+    /// Players of each team who have to be ready before the warmup clock runs, 0 starts the
+    /// game when the warmup is over
+    pub ready_players: u32,
     pub time_warmup: u32,
     pub time_break: u32,
     pub time_intermission: u32,
@@ -52,13 +81,100 @@ pub struct HQMMatchConfiguration {
     pub offside_line: HQMOffsideLineConfiguration,
     pub twoline_pass: HQMTwoLinePassConfiguration,
     pub warmup_pucks: usize,
-    pub physics_config: HQMPhysicsConfiguration,
+    /// Players can place pucks, cones and targets and move to faceoff spots during warmup
+    pub warmup_practice: bool,
+    /// Speed in meters per tick a skater has to hit a net with to knock it off, 0 if never
+    pub net_dislodge_speed: f32,
+    /// A player who shoots the puck over the boards from their own zone gets a minor penalty
+    pub delay_of_game: bool,
+    /// Pucks dropped at each faceoff, the rules are applied to each of them on its own
+    pub pucks_in_play: usize,
+    pub physics_config: HQMPhysicsProfile,
     pub use_mph: bool,
     pub goal_replay: bool,
+    /// Steps of goal replays before the goal
+    pub goal_replay_before: u32,
+    /// Steps of goal replays after the goal
+    pub goal_replay_after: u32,
+    pub goal_replay_view: HQMGoalReplayView,
+    pub hints: bool,
+    pub announce_win_probability: bool,
+    /// Unanswered goals from which streaks are announced, 0 disables
+    pub goal_streak: u32,
+    pub goal_streak_reset: HQMGoalStreakReset,
+    pub announce_comebacks: bool,
+    /// Players on the ice vote about keeping the teams for the next game when a game ends
+    pub rematch_vote: bool,
+    /// Games of a best-of series, 0 plays single games
+    pub series: u32,
+    /// Rule presets admins can switch to with /ruleset
+    pub rulesets: Vec<HQMRuleset>,
+    /// Players can start votes about resetting the game and the rules with /vote
+    pub votes: bool,
+    pub vote_voters: HQMVoteVoters,
+    /// Seconds a vote is open
+    pub vote_time: u32,
+    /// Percentage of the voters who have to vote yes for a vote to pass, rounded up
+    pub vote_threshold: u32,
     pub spawn_point_offset: f32,
     pub spawn_player_altitude: f32,
     pub spawn_puck_altitude: f32,
     pub spawn_keep_stick_position: bool,
+    /// Longest random wait in seconds before the puck is dropped in faceoffs, 0 drops it at once
+    pub faceoff_drop_delay: f32,
+    pub creases: HQMRinkCreases,
+    /// Goals are waved off if the scoring team last touched the puck above this height in
+    /// meters, 0 disables
+    pub high_stick_height: f32,
+    /// Play is stopped when a skater passes the puck with the body to a team mate outside the
+    /// own zone
+    pub hand_pass: bool,
+    /// Goals are waved off if the puck went in off the body of a skater of the scoring team
+    pub disallow_body_goals: bool,
+    /// Rounds of the shootout if the game is tied after an overtime period, 0 plays overtime
+    /// until a goal
+    pub shootout: u32,
+    /// Bots that fill the teams when the server starts, see [crate::hqm_bot]
+    pub bots: usize,
+    /// Seconds the team and position of a player who disconnected in a game are kept for them
+    /// to rejoin, 0 disables it
+    pub rejoin_grace: u32,
+    /// Leaves the skater of a player who disconnected on the ice until they rejoin
+    pub rejoin_keep_skater: bool,
+    /// The game is paused when a disconnect in live play leaves a team with fewer skaters than
+    /// this, 0 disables it
+    pub auto_pause_skaters: usize,
+}
+
+impl HQMMatchConfiguration {
+    /// Length of a period, measured in hundredths of a second
+    pub fn period_length(&self, period: u32) -> u32 {
+        let seconds = if period > self.periods {
+            self.time_overtime
+        } else {
+            (period as usize)
+                .checked_sub(1)
+                .and_then(|i| self.period_lengths.get(i))
+                .copied()
+                .unwrap_or(self.time_period)
+        };
+        seconds * 100
+    }
+
+    /// Whether the clock keeps running while play is stopped, which a running clock does
+    /// while a period is played, but not in warmup, at the end of a period or after the game
+    pub fn clock_runs_in_stoppage(&self, values: &HQMGameValues) -> bool {
+        self.running_clock && values.time > 0 && values.period > 0 && !values.game_over
+    }
+
+    /// Whether a disconnect that leaves a team with this many skaters pauses the game, which
+    /// it only does while a period is played
+    pub fn pauses_for_disconnect(&self, values: &HQMGameValues, skaters_left: usize) -> bool {
+        skaters_left < self.auto_pause_skaters
+            && values.period > 0
+            && values.time > 0
+            && !values.game_over
+    }
 }
 
 pub enum HQMMatchEvent {
@@ -73,24 +189,160 @@ pub enum HQMMatchEvent {
     },
 }
 
+#[derive(Debug, Clone)]
+pub struct HQMGoalResult {
+    pub team: HQMTeam,
+    pub scorer: Option<Rc<String>>,
+    pub assist: Option<Rc<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HQMGameResult {
+    pub red_score: u32,
+    pub blue_score: u32,
+    pub goals: Vec<HQMGoalResult>,
+}
+
+impl HQMGameResult {
+    /// Player with the most points, goals breaking ties
+    pub fn mvp(&self) -> Option<(Rc<String>, usize, usize)> {
+        let mut points: Vec<(Rc<String>, usize, usize)> = vec![];
+        for goal in self.goals.iter() {
+            for (name, is_goal) in [(&goal.scorer, true), (&goal.assist, false)] {
+                if let Some(name) = name {
+                    let i = match points.iter().position(|(x, _, _)| x == name) {
+                        Some(i) => i,
+                        None => {
+                            points.push((name.clone(), 0, 0));
+                            points.len() - 1
+                        }
+                    };
+                    if is_goal {
+                        points[i].1 += 1;
+                    } else {
+                        points[i].2 += 1;
+                    }
+                }
+            }
+        }
+        points.into_iter().max_by_key(|(_, g, a)| (g + a, *g))
+    }
+}
+
+/// Number of game steps after the puck is dropped in which the first touch decides who won
+/// the faceoff
+const FACEOFF_WINDOW: u32 = 300;
+
+/// Hundredths of a second after a touch in which the player still has the puck
+const POSSESSION_WINDOW: u32 = 50;
+
+/// Random number generator for the puck drops of a game (SplitMix64). The seed is logged and
+/// stored in the game record, so that the drops of a recorded game can be reproduced.
+struct HQMDropRandom {
+    seed: u64,
+    state: u64,
+}
+
+impl HQMDropRandom {
+    fn new() -> Self {
+        let seed = RandomState::new().build_hasher().finish();
+        HQMDropRandom { seed, state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Where each puck is in terms of the icing, offside and two-line pass rules
+#[derive(Debug, Clone)]
+struct HQMPuckRules {
+    icing_status: HQMIcingStatus,
+    offside_status: HQMOffsideStatus,
+    twoline_pass_status: HQMTwoLinePassStatus,
+    pass: Option<HQMPass>,
+}
+
+impl Default for HQMPuckRules {
+    fn default() -> Self {
+        HQMPuckRules {
+            icing_status: HQMIcingStatus::No,
+            offside_status: HQMOffsideStatus::Neutral,
+            twoline_pass_status: HQMTwoLinePassStatus::No,
+            pass: None,
+        }
+    }
+}
+
+/// A faceoff waiting for the pucks to be dropped
+struct HQMPendingDrop {
+    pos: Point3<f32>,
+    steps_left: u32,
+}
+
+/// The centers taking the current faceoff, until someone touches the puck
+struct HQMFaceoffCenters {
+    red: Option<Rc<String>>,
+    blue: Option<Rc<String>>,
+}
+
 pub struct HQMMatch {
     pub config: HQMMatchConfiguration,
     pub paused: bool,
     pub(crate) pause_timer: u32,
     is_pause_goal: bool,
     next_faceoff_spot: HQMRinkFaceoffSpot,
-    icing_status: HQMIcingStatus,
-    offside_status: HQMOffsideStatus,
-    twoline_pass_status: HQMTwoLinePassStatus,
-    pass: Option<HQMPass>,
+    /// Rule state of the puck whose events are handled, the other pucks keep theirs in
+    /// `puck_rules` until their events come up
+    rules: HQMPuckRules,
+    rules_puck: Option<HQMObjectIndex>,
+    puck_rules: HashMap<HQMObjectIndex, HQMPuckRules>,
     pub(crate) preferred_positions: HashMap<HQMServerPlayerIndex, &'static str>,
+    pub(crate) position_claims: HQMPositionClaims,
+    /// Positions of players whose spot is held until they rejoin, by name
+    pub held_positions: Vec<(Rc<String>, HQMTeam, &'static str)>,
 
     pub started_as_goalie: Vec<HQMServerPlayerIndex>,
     faceoff_game_step: u32,
+    faceoff_centers: Option<HQMFaceoffCenters>,
+    drop_random: HQMDropRandom,
+    pending_drop: Option<HQMPendingDrop>,
     step_where_period_ended: u32,
     too_late_printed_this_period: bool,
+    /// Announces the end of the period in chat
+    period_countdown: HQMChatCountdown,
+    /// Announces the start of the next period or game in chat
+    break_countdown: HQMChatCountdown,
     start_next_replay: Option<(u32, u32, Option<HQMServerPlayerIndex>)>,
     puck_touches: HashMap<HQMObjectIndex, VecDeque<HQMPuckTouch>>,
+    skater_zones: HQMSkaterZones,
+    goals: Vec<HQMGoalResult>,
+    goal_streak: HQMGoalStreak,
+    pub last_game_result: Option<HQMGameResult>,
+    hints: HQMNewPlayerHints,
+    /// Offsides of each player in this game, for alerts about repeat offenders
+    offsides: HashMap<HQMServerPlayerIndex, u32>,
+    pub(crate) penalties: HQMPenaltyBox,
+    /// The team with more players on the ice because of penalties
+    power_play: Option<HQMTeam>,
+    /// Pucks that touched the body of a skater since they were last touched by a stick
+    body_touches: HashMap<HQMObjectIndex, HQMBodyTouch>,
+    shootout: Option<HQMShootoutTiebreaker>,
+    /// Red defends the blue end of the rink and the other way around
+    ends_swapped: bool,
+    /// Players who are ready for the game to start
+    pub(crate) ready: HashSet<HQMServerPlayerIndex>,
+    /// The game was started with /start or a vote, so the warmup clock runs whether the teams
+    /// are ready or not
+    forced_start: bool,
+    /// Ruleset that is applied at the next faceoff
+    pub(crate) pending_ruleset: Option<HQMRuleset>,
+    /// Pucks, cones and targets placed by players during warmup
+    pub(crate) practice: HQMPractice,
 }
 
 impl HQMMatch {
@@ -101,17 +353,82 @@ impl HQMMatch {
             pause_timer: 0,
             is_pause_goal: false,
             next_faceoff_spot: HQMRinkFaceoffSpot::Center,
-            icing_status: HQMIcingStatus::No,
-            offside_status: HQMOffsideStatus::Neutral,
-            twoline_pass_status: HQMTwoLinePassStatus::No,
-            pass: None,
+            rules: HQMPuckRules::default(),
+            rules_puck: None,
+            puck_rules: HashMap::new(),
             preferred_positions: HashMap::new(),
+            position_claims: HQMPositionClaims::default(),
+            held_positions: vec![],
             started_as_goalie: vec![],
             faceoff_game_step: 0,
+            faceoff_centers: None,
+            drop_random: HQMDropRandom::new(),
+            pending_drop: None,
             too_late_printed_this_period: false,
             step_where_period_ended: 0,
+            period_countdown: HQMChatCountdown::new(),
+            break_countdown: HQMChatCountdown::new(),
             start_next_replay: None,
             puck_touches: Default::default(),
+            hints: Default::default(),
+            skater_zones: Default::default(),
+            goals: vec![],
+            goal_streak: Default::default(),
+            last_game_result: None,
+            offsides: HashMap::new(),
+            penalties: HQMPenaltyBox::default(),
+            power_play: None,
+            body_touches: HashMap::new(),
+            shootout: None,
+            ends_swapped: false,
+            ready: HashSet::new(),
+            forced_start: false,
+            pending_ruleset: None,
+            practice: HQMPractice::new(1),
+        }
+    }
+
+    /// Sets the ends the teams play at, which is also kept in the game values for snapshots
+    pub fn set_ends_swapped(&mut self, server: &mut HQMServer, ends_swapped: bool) {
+        self.ends_swapped = ends_swapped;
+        server.values.ends_swapped = ends_swapped;
+    }
+
+    pub fn has_pending_ruleset(&self) -> bool {
+        self.pending_ruleset.is_some()
+    }
+
+    fn apply_ruleset(&mut self, server: &mut HQMServer, ruleset: HQMRuleset) {
+        if let Some(icing) = ruleset.icing {
+            self.config.icing = icing;
+        }
+        if let Some(offside) = ruleset.offside {
+            self.config.offside = offside;
+        }
+        if let Some(offside_line) = ruleset.offside_line {
+            self.config.offside_line = offside_line;
+        }
+        if let Some(twoline_pass) = ruleset.twoline_pass {
+            self.config.twoline_pass = twoline_pass;
+        }
+        if let Some(mercy) = ruleset.mercy {
+            self.config.mercy = mercy;
+        }
+        if let Some(first_to) = ruleset.first_to {
+            self.config.first_to = first_to;
+        }
+        info!(target: GAME_LOG, ruleset = ruleset.name, "Ruleset {}", ruleset.name);
+        let msg = format!("The {} rules are now in effect", ruleset.name);
+        server.messages.add_server_chat_message(msg);
+    }
+
+    /// The end of the rink a team defends, named after the team that defends it when the ends
+    /// aren't swapped. Also gives the team that defends an end
+    pub fn rink_end(&self, team: HQMTeam) -> HQMTeam {
+        if self.ends_swapped {
+            team.get_other_team()
+        } else {
+            team
         }
     }
 
@@ -126,14 +443,29 @@ impl HQMMatch {
     }
 
     fn do_faceoff(&mut self, server: &mut HQMServer) {
+        if let Some(ruleset) = self.pending_ruleset.take() {
+            self.apply_ruleset(server, ruleset);
+        }
         let positions = get_faceoff_positions(&server.players, &self.preferred_positions);
 
         server.world.clear_pucks();
+        server.world.rink.red_net.reset();
+        server.world.rink.blue_net.reset();
         self.puck_touches.clear();
+        self.body_touches.clear();
 
+        let spot = match self.next_faceoff_spot {
+            HQMRinkFaceoffSpot::Center => HQMRinkFaceoffSpot::Center,
+            HQMRinkFaceoffSpot::DefensiveZone(team, side) => {
+                HQMRinkFaceoffSpot::DefensiveZone(self.rink_end(team), side)
+            }
+            HQMRinkFaceoffSpot::Offside(team, side) => {
+                HQMRinkFaceoffSpot::Offside(self.rink_end(team), side)
+            }
+        };
         let next_faceoff_spot = get_faceoff_spot(
             &server.world.rink,
-            self.next_faceoff_spot,
+            spot,
             self.config.spawn_point_offset,
             self.config.spawn_player_altitude,
         );
@@ -141,13 +473,43 @@ impl HQMMatch {
         let puck_pos =
             next_faceoff_spot.center_position + &(self.config.spawn_puck_altitude * Vector3::y());
 
-        server
-            .world
-            .create_puck_object(puck_pos, Rotation3::identity());
+        let rink = &server.world.rink;
+        let offside_status = if rink.blue_zone_blue_line.side_of_line(&puck_pos, 0.0) == BlueSide {
+            HQMOffsideStatus::InOffensiveZone(self.rink_end(HQMTeam::Red))
+        } else if rink.red_zone_blue_line.side_of_line(&puck_pos, 0.0) == RedSide {
+            HQMOffsideStatus::InOffensiveZone(self.rink_end(HQMTeam::Blue))
+        } else {
+            HQMOffsideStatus::Neutral
+        };
+        // Every puck that is dropped starts out with these rules
+        self.rules = HQMPuckRules {
+            offside_status,
+            ..HQMPuckRules::default()
+        };
+        self.rules_puck = None;
+        self.puck_rules.clear();
+
+        // The puck is dropped after a random number of steps, and sticks are held until then,
+        // so that a faceoff isn't won by whoever sees the drop first
+        let max_delay = (self.config.faceoff_drop_delay * 100.0).round() as u64;
+        let steps_left = if max_delay > 0 {
+            (self.drop_random.next() % (max_delay + 1)) as u32
+        } else {
+            0
+        };
+        self.pending_drop = Some(HQMPendingDrop {
+            pos: puck_pos,
+            steps_left,
+        });
+        self.update_pending_drop(server);
 
         self.started_as_goalie.clear();
+        let mut centers = HQMFaceoffCenters {
+            red: None,
+            blue: None,
+        };
         for (player_index, (team, faceoff_position)) in positions {
-            let (player_position, player_rotation) = match team {
+            let (player_position, player_rotation) = match self.rink_end(team) {
                 HQMTeam::Red => next_faceoff_spot.red_player_positions[faceoff_position].clone(),
                 HQMTeam::Blue => next_faceoff_spot.blue_player_positions[faceoff_position].clone(),
             };
@@ -160,22 +522,235 @@ impl HQMMatch {
             );
             if faceoff_position == "G" {
                 self.started_as_goalie.push(player_index);
+            } else if faceoff_position == "C" {
+                let name = server
+                    .players
+                    .get(player_index)
+                    .map(|player| player.player_name.clone());
+                match team {
+                    HQMTeam::Red => centers.red = name,
+                    HQMTeam::Blue => centers.blue = name,
+                }
             }
         }
 
-        let rink = &server.world.rink;
-        self.icing_status = HQMIcingStatus::No;
-        self.offside_status = if rink.blue_zone_blue_line.side_of_line(&puck_pos, 0.0) == BlueSide {
-            HQMOffsideStatus::InOffensiveZone(HQMTeam::Red)
-        } else if rink.red_zone_blue_line.side_of_line(&puck_pos, 0.0) == RedSide {
-            HQMOffsideStatus::InOffensiveZone(HQMTeam::Blue)
+        self.faceoff_centers = Some(centers);
+    }
+
+    /// Drops the pucks of the current faceoff once its wait is over
+    fn update_pending_drop(&mut self, server: &mut HQMServer) {
+        let drop = match self.pending_drop.take() {
+            Some(mut drop) if drop.steps_left > 0 => {
+                if !self.paused {
+                    drop.steps_left -= 1;
+                }
+                self.pending_drop = Some(drop);
+                server.sticks_held = true;
+                return;
+            }
+            Some(drop) => drop,
+            None => return,
+        };
+        let xs = puck_drop_xs(
+            self.config.pucks_in_play,
+            drop.pos.x,
+            server.world.rink.width,
+        );
+        for x in xs {
+            let pos = Point3::new(x, drop.pos.y, drop.pos.z);
+            if let Some(puck) = server.world.create_puck_object(pos, Rotation3::identity()) {
+                self.puck_rules.insert(puck, self.rules.clone());
+            }
+        }
+        server.sticks_held = false;
+        self.faceoff_game_step = server.game_step;
+    }
+
+    /// The team that touches the puck first after it's dropped wins the faceoff. If nobody
+    /// touches it in time, the faceoff isn't counted.
+    fn check_faceoff_won(&mut self, server: &mut HQMServer, team: HQMTeam) {
+        let centers = match self.faceoff_centers.take() {
+            Some(centers) => centers,
+            None => return,
+        };
+        if server.values.period == 0
+            || server.values.game_over
+            || server.game_step.saturating_sub(self.faceoff_game_step) > FACEOFF_WINDOW
+        {
+            return;
+        }
+        let (winner, loser) = match team {
+            HQMTeam::Red => (centers.red, centers.blue),
+            HQMTeam::Blue => (centers.blue, centers.red),
+        };
+        server.publish_event(HQMEvent::Faceoff {
+            team,
+            winner: winner.map(|x| x.to_string()),
+            loser: loser.map(|x| x.to_string()),
+            period: server.values.period,
+            time: server.values.time,
+        });
+    }
+
+    /// The player of a team who started as goalie and is still on the ice
+    fn goalie(&self, server: &HQMServer, team: HQMTeam) -> Option<HQMServerPlayerIndex> {
+        self.started_as_goalie
+            .iter()
+            .copied()
+            .find(|&player_index| {
+                matches!(
+                    server.players.get(player_index).and_then(|player| player.object),
+                    Some((_, goalie_team)) if goalie_team == team
+                )
+            })
+    }
+
+    /// Whether a position is in the zone a team defends
+    fn in_defensive_zone(&self, rink: &HQMRink, team: HQMTeam, pos: &Point3<f32>) -> bool {
+        match self.rink_end(team) {
+            HQMTeam::Red => rink.red_zone_blue_line.side_of_line(pos, 0.0) == RedSide,
+            HQMTeam::Blue => rink.blue_zone_blue_line.side_of_line(pos, 0.0) == BlueSide,
+        }
+    }
+
+    /// The faceoff spot in the zone of a position, or center ice for the neutral zone
+    fn faceoff_spot_at(&self, rink: &HQMRink, pos: &Point3<f32>) -> HQMRinkFaceoffSpot {
+        let side = if pos.x > rink.width / 2.0 {
+            HQMRinkSide::Right
         } else {
-            HQMOffsideStatus::Neutral
+            HQMRinkSide::Left
+        };
+        if rink.red_zone_blue_line.side_of_line(pos, 0.0) == RedSide {
+            HQMRinkFaceoffSpot::DefensiveZone(self.rink_end(HQMTeam::Red), side)
+        } else if rink.blue_zone_blue_line.side_of_line(pos, 0.0) == BlueSide {
+            HQMRinkFaceoffSpot::DefensiveZone(self.rink_end(HQMTeam::Blue), side)
+        } else {
+            HQMRinkFaceoffSpot::Center
+        }
+    }
+
+    /// A goalie touching the puck in their own zone right after the other team did counts as
+    /// a save, and the touch of the other team as a shot. Players who didn't start as goalie
+    /// count as goalie while they are in their own goal crease
+    fn check_save(
+        &self,
+        server: &mut HQMServer,
+        goalie_index: HQMServerPlayerIndex,
+        team: HQMTeam,
+        puck_index: HQMObjectIndex,
+    ) {
+        if server.values.period == 0 || server.values.game_over {
+            return;
+        }
+        if !self.started_as_goalie.contains(&goalie_index)
+            && !is_in_own_crease(server, goalie_index, self.rink_end(team))
+        {
+            return;
+        }
+        let (shooter_index, shot_position) = match self
+            .puck_touches
+            .get(&puck_index)
+            .and_then(|touches| touches.front())
+        {
+            Some(touch) if touch.team != team => {
+                (touch.player_index, ice_position(&touch.puck_pos))
+            }
+            _ => return,
         };
-        self.twoline_pass_status = HQMTwoLinePassStatus::No;
-        self.pass = None;
+        let in_own_zone = match server.world.objects.get_puck(puck_index) {
+            Some(puck) => self.in_defensive_zone(&server.world.rink, team, &puck.body.pos),
+            None => false,
+        };
+        if !in_own_zone {
+            return;
+        }
+        let goalie = match server.players.get(goalie_index) {
+            Some(player) => player.player_name.to_string(),
+            None => return,
+        };
+        let shooter = server
+            .players
+            .get(shooter_index)
+            .map(|player| player.player_name.to_string());
+        server.publish_event(HQMEvent::Save {
+            team: team.get_other_team(),
+            shooter,
+            goalie,
+            shot_position,
+            period: server.values.period,
+            time: server.values.time,
+        });
+    }
 
-        self.faceoff_game_step = server.game_step;
+    /// Counts a collision between skaters of different teams as a hit by the one that moved
+    /// towards the other faster
+    fn handle_hit(
+        &self,
+        server: &mut HQMServer,
+        hitter: HQMObjectIndex,
+        target: HQMObjectIndex,
+        impact: f32,
+    ) {
+        if server.values.period == 0 || server.values.game_over {
+            return;
+        }
+        let (team, hitter) = match server.players.get_from_object_index(hitter) {
+            Some((_, team, player)) => (team, player.player_name.to_string()),
+            None => return,
+        };
+        let target = match server.players.get_from_object_index(target) {
+            Some((_, target_team, player)) if target_team != team => player.player_name.to_string(),
+            _ => return,
+        };
+        server.publish_event(HQMEvent::Hit {
+            team,
+            hitter,
+            target,
+            impact: impact * 100.0,
+            period: server.values.period,
+            time: server.values.time,
+        });
+    }
+
+    /// The player who touched a puck last has it, as long as the touch was recent
+    fn update_possession(&self, server: &mut HQMServer) {
+        for touches in self.puck_touches.values() {
+            if let Some(touch) = touches.front() {
+                if touch.last_time.saturating_sub(server.values.time) <= POSSESSION_WINDOW {
+                    server.add_possession(touch.player_index);
+                }
+            }
+        }
+    }
+
+    /// Live estimate of the probability that red wins, or None if the game isn't being played
+    pub fn win_probability(&self, server: &HQMServer) -> Option<f32> {
+        if server.values.period == 0 || server.values.game_over {
+            return None;
+        }
+        let sudden_death = server.values.period > self.config.periods;
+        let time_left = server.values.time
+            + (server.values.period + 1..=self.config.periods)
+                .map(|period| self.config.period_length(period))
+                .sum::<u32>();
+        let seconds_left = time_left as f32 / 100.0;
+        let mut red_skaters = 0;
+        let mut blue_skaters = 0;
+        for (_, player) in server.players.iter() {
+            match player.object {
+                Some((_, HQMTeam::Red)) => red_skaters += 1,
+                Some((_, HQMTeam::Blue)) => blue_skaters += 1,
+                None => {}
+            }
+        }
+        Some(win_probability(
+            server.values.red_score,
+            server.values.blue_score,
+            seconds_left,
+            red_skaters,
+            blue_skaters,
+            sudden_death,
+        ))
     }
 
     pub(crate) fn update_game_over(&mut self, server: &mut HQMServer) {
@@ -214,6 +789,7 @@ impl HQMMatch {
         puck_index: HQMObjectIndex,
     ) -> HQMMatchEvent {
         let time_break = self.config.time_break * 100;
+        let win_probability_before = self.win_probability(server);
 
         match team {
             HQMTeam::Red => {
@@ -226,17 +802,33 @@ impl HQMMatch {
 
         self.next_faceoff_spot = HQMRinkFaceoffSpot::Center;
 
+        let strength = match self
+            .penalties
+            .players_in_box(team)
+            .cmp(&self.penalties.players_in_box(team.get_other_team()))
+        {
+            Ordering::Less => HQMGoalStrength::PowerPlay,
+            Ordering::Greater => HQMGoalStrength::Shorthanded,
+            Ordering::Equal => HQMGoalStrength::Even,
+        };
+        for (player_name, penalized_team) in self.penalties.power_play_goal(team) {
+            self.release_from_penalty_box(server, &player_name, penalized_team);
+        }
+        self.update_power_play(server, true);
+
         let (
             goal_scorer_index,
             assist_index,
             puck_speed_across_line,
             puck_speed_from_stick,
+            shot_position,
             last_touch,
         ) = if let Some(this_puck) = server.world.objects.get_puck_mut(puck_index) {
             let mut goal_scorer_index = None;
             let mut assist_index = None;
             let mut goal_scorer_first_touch = 0;
             let mut puck_speed_from_stick = None;
+            let mut shot_position = None;
             let mut last_touch = None;
             let puck_speed_across_line = this_puck.body.linear_velocity.norm();
             if let Some(touches) = self.puck_touches.get(&puck_index) {
@@ -248,6 +840,7 @@ impl HQMMatch {
                             goal_scorer_index = Some(touch.player_index);
                             goal_scorer_first_touch = touch.first_time;
                             puck_speed_from_stick = Some(touch.puck_speed);
+                            shot_position = Some(ice_position(&touch.puck_pos));
                         }
                     } else {
                         if touch.team == team {
@@ -275,35 +868,62 @@ impl HQMMatch {
                 assist_index,
                 puck_speed_across_line,
                 puck_speed_from_stick,
+                shot_position,
                 last_touch,
             )
         } else {
-            (None, None, 0.0, None, None)
+            (None, None, 0.0, None, None, None)
         };
 
         server
             .messages
             .add_goal_message(team, goal_scorer_index, assist_index);
 
-        fn convert(puck_speed: f32, use_mph: bool) -> (f32, &'static str) {
-            if use_mph {
-                (puck_speed * 100f32 * 2.23693, "mph")
-            } else {
-                (puck_speed * 100f32 * 3.6, "km/h")
-            }
-        }
+        let get_name = |player_index: Option<HQMServerPlayerIndex>| {
+            player_index
+                .and_then(|x| server.players.get(x))
+                .map(|x| x.player_name.clone())
+        };
+        let scorer = get_name(goal_scorer_index);
+        let assist = get_name(assist_index);
+        self.goals.push(HQMGoalResult {
+            team,
+            scorer: scorer.clone(),
+            assist: assist.clone(),
+        });
+        info!(
+            target: GAME_LOG,
+            %team,
+            scorer = scorer.as_deref().map(String::as_str),
+            assist = assist.as_deref().map(String::as_str),
+            red_score = server.values.red_score,
+            blue_score = server.values.blue_score,
+            period = server.values.period,
+            time = server.values.time,
+            ?strength,
+            "{} goal by {}, {} - {}",
+            team,
+            scorer.as_deref().map_or("unknown", String::as_str),
+            server.values.red_score,
+            server.values.blue_score
+        );
 
         let (puck_speed_across_line_converted, puck_speed_unit) =
-            convert(puck_speed_across_line, self.config.use_mph);
+            convert_puck_speed(puck_speed_across_line, self.config.use_mph);
 
+        let goal = match strength {
+            HQMGoalStrength::Even => "Goal scored",
+            HQMGoalStrength::PowerPlay => "Power play goal",
+            HQMGoalStrength::Shorthanded => "Shorthanded goal",
+        };
         let str1 = format!(
-            "Goal scored, {:.1} {} across line",
-            puck_speed_across_line_converted, puck_speed_unit
+            "{}, {:.1} {} across line",
+            goal, puck_speed_across_line_converted, puck_speed_unit
         );
 
         let str2 = if let Some(puck_speed_from_stick) = puck_speed_from_stick {
             let (puck_speed_converted, puck_speed_unit) =
-                convert(puck_speed_from_stick, self.config.use_mph);
+                convert_puck_speed(puck_speed_from_stick, self.config.use_mph);
             format!(
                 ", {:.1} {} from stick",
                 puck_speed_converted, puck_speed_unit
@@ -329,17 +949,70 @@ impl HQMMatch {
 
         self.update_game_over(server);
 
+        for msg in self.goal_streak.goal(
+            team,
+            server.values.red_score,
+            server.values.blue_score,
+            self.config.goal_streak,
+            self.config.announce_comebacks,
+        ) {
+            server.messages.add_server_chat_message(msg);
+        }
+
+        let win_probability_after = self.win_probability(server);
+        server.publish_event(HQMEvent::Goal {
+            team,
+            scorer: scorer.map(|x| x.to_string()),
+            assist: assist.map(|x| x.to_string()),
+            red_score: server.values.red_score,
+            blue_score: server.values.blue_score,
+            period: server.values.period,
+            time: server.values.time,
+            speed: puck_speed_across_line * 100.0,
+            shot_speed: puck_speed_from_stick.map(|speed| speed * 100.0),
+            shot_position,
+            red_win_probability: win_probability_after,
+            strength,
+        });
+
+        if self.config.announce_win_probability {
+            if let (Some(before), Some(after)) = (win_probability_before, win_probability_after) {
+                let (before, after) = match team {
+                    HQMTeam::Red => (before, after),
+                    HQMTeam::Blue => (1.0 - before, 1.0 - after),
+                };
+                if after - before >= 0.1 {
+                    let msg = format!(
+                        "{} win probability up from {:.0}% to {:.0}%",
+                        team,
+                        before * 100.0,
+                        after * 100.0
+                    );
+                    server.messages.add_server_chat_message(msg);
+                }
+            }
+        }
+
         let gamestep = server.game_step;
 
         if self.config.goal_replay {
-            let force_view = goal_scorer_index.or(last_touch);
-            self.start_next_replay = Some((
-                self.faceoff_game_step.max(gamestep - 600),
-                gamestep + 200,
-                force_view,
-            ));
-
-            self.pause_timer = self.pause_timer.saturating_sub(800).max(400);
+            let force_view = match self.config.goal_replay_view {
+                HQMGoalReplayView::Scorer => goal_scorer_index.or(last_touch),
+                HQMGoalReplayView::Goalie => self.goalie(server, team.get_other_team()),
+                HQMGoalReplayView::Puck => None,
+            };
+            let before = self.config.goal_replay_before;
+            let after = self.config.goal_replay_after;
+            let end = gamestep + after;
+            // The replay can't start before the oldest step the server keeps
+            let oldest = end.saturating_sub(server.history_length.saturating_sub(1) as u32);
+            let start = self
+                .faceoff_game_step
+                .max(gamestep.saturating_sub(before))
+                .max(oldest);
+            self.start_next_replay = Some((start, end, force_view));
+
+            self.pause_timer = self.pause_timer.saturating_sub(before + after).max(400);
         }
         HQMMatchEvent::Goal {
             team,
@@ -374,6 +1047,45 @@ impl HQMMatch {
         }
     }
 
+    fn handle_puck_touched_body(
+        &mut self,
+        server: &mut HQMServer,
+        player: HQMObjectIndex,
+        puck_index: HQMObjectIndex,
+    ) {
+        // Goalies may catch the puck and throw it to a team mate
+        if let Some((player_index, team, _)) = server.players.get_from_object_index(player) {
+            if self.started_as_goalie.contains(&player_index) {
+                return;
+            }
+            if let Some(puck) = server.world.objects.get_puck(puck_index) {
+                let rink = &server.world.rink;
+                let in_own_zone = match self.rink_end(team) {
+                    HQMTeam::Red => {
+                        rink.red_zone_blue_line.side_of_line(&puck.body.pos, 0.0) == RedSide
+                    }
+                    HQMTeam::Blue => {
+                        rink.blue_zone_blue_line.side_of_line(&puck.body.pos, 0.0) == BlueSide
+                    }
+                };
+                let side = if puck.body.pos.x <= rink.width / 2.0 {
+                    HQMRinkSide::Left
+                } else {
+                    HQMRinkSide::Right
+                };
+                self.body_touches.insert(
+                    puck_index,
+                    HQMBodyTouch {
+                        player_index,
+                        team,
+                        side,
+                        in_own_zone,
+                    },
+                );
+            }
+        }
+    }
+
     fn handle_puck_touch(
         &mut self,
         server: &mut HQMServer,
@@ -382,7 +1094,38 @@ impl HQMMatch {
     ) {
         if let Some((player_index, touching_team, _)) = server.players.get_from_object_index(player)
         {
+            if let Some(body_touch) = self.body_touches.remove(&puck_index) {
+                if self.config.hand_pass
+                    && body_touch.team == touching_team
+                    && body_touch.player_index != player_index
+                    && !body_touch.in_own_zone
+                {
+                    self.call_hand_pass(
+                        server,
+                        touching_team,
+                        body_touch.side,
+                        body_touch.player_index,
+                    );
+                    return;
+                }
+            }
+            self.check_faceoff_won(server, touching_team);
+            self.check_save(server, player_index, touching_team, puck_index);
             if let Some(puck) = server.world.objects.get_puck_mut(puck_index) {
+                if self.config.hints {
+                    let previous_touch = self
+                        .puck_touches
+                        .get(&puck_index)
+                        .and_then(|touches| touches.front())
+                        .map(|touch| (touch.player_index, touch.team));
+                    self.hints.record_touch(
+                        puck_index,
+                        player_index,
+                        touching_team,
+                        previous_touch,
+                        server.game_step,
+                    );
+                }
                 add_touch(
                     puck,
                     self.puck_touches.entry(puck_index),
@@ -396,7 +1139,7 @@ impl HQMMatch {
                 } else {
                     HQMRinkSide::Right
                 };
-                self.pass = Some(HQMPass {
+                self.rules.pass = Some(HQMPass {
                     team: touching_team,
                     side,
                     from: None,
@@ -405,32 +1148,41 @@ impl HQMMatch {
 
                 let other_team = touching_team.get_other_team();
 
-                if let HQMOffsideStatus::Warning(team, side, position, i) = self.offside_status {
+                if let HQMOffsideStatus::Warning(team, side, position, i) =
+                    self.rules.offside_status
+                {
                     if team == touching_team {
                         let self_touch = player_index == i;
 
-                        self.call_offside(server, touching_team, side, position, self_touch);
+                        self.call_offside(
+                            server,
+                            touching_team,
+                            side,
+                            position,
+                            self_touch,
+                            player_index,
+                        );
                         return;
                     }
                 }
-                if let HQMTwoLinePassStatus::Warning(team, side, position, ref i) =
-                    self.twoline_pass_status
+                if let HQMTwoLinePassStatus::Warning(team, side, position, passer, ref i) =
+                    self.rules.twoline_pass_status
                 {
                     if team == touching_team && i.contains(&player_index) {
-                        self.call_twoline_pass(server, touching_team, side, position);
+                        self.call_twoline_pass(server, touching_team, side, position, passer);
                         return;
                     } else {
-                        self.twoline_pass_status = HQMTwoLinePassStatus::No;
+                        self.rules.twoline_pass_status = HQMTwoLinePassStatus::No;
                         server
                             .messages
                             .add_server_chat_message("Two-line pass waved off");
                     }
                 }
-                if let HQMIcingStatus::Warning(team, side) = self.icing_status {
+                if let HQMIcingStatus::Warning(team, side, icing_player) = self.rules.icing_status {
                     if touching_team != team && !self.started_as_goalie.contains(&player_index) {
-                        self.call_icing(server, other_team, side);
+                        self.call_icing(server, other_team, side, icing_player);
                     } else {
-                        self.icing_status = HQMIcingStatus::No;
+                        self.rules.icing_status = HQMIcingStatus::No;
                         server.messages.add_server_chat_message("Icing waved off");
                     }
                 }
@@ -446,34 +1198,68 @@ impl HQMMatch {
         puck: HQMObjectIndex,
     ) {
         let team = net_team.get_other_team();
-        match self.offside_status {
-            HQMOffsideStatus::Warning(offside_team, side, position, _) if offside_team == team => {
-                self.call_offside(server, team, side, position, false);
+        match self.rules.offside_status {
+            HQMOffsideStatus::Warning(offside_team, side, position, player)
+                if offside_team == team =>
+            {
+                self.call_offside(server, team, side, position, false, player);
             }
             HQMOffsideStatus::Offside(_) => {}
             _ => {
-                events.push(self.call_goal(server, team, puck));
+                if let Some(player) = self.body_goal(team, puck) {
+                    self.wave_off_goal(server, HQMRuleCall::BodyGoal, team, puck, player);
+                } else if let Some(player) = self.high_stick(team, puck) {
+                    self.wave_off_goal(server, HQMRuleCall::HighStick, team, puck, player);
+                } else {
+                    events.push(self.call_goal(server, team, puck));
+                }
             }
         }
     }
 
+    /// The skater of the scoring team the puck went in off, if it wasn't touched by a stick
+    /// since
+    fn body_goal(&self, team: HQMTeam, puck: HQMObjectIndex) -> Option<HQMServerPlayerIndex> {
+        if !self.config.disallow_body_goals {
+            return None;
+        }
+        self.body_touches
+            .get(&puck)
+            .filter(|touch| touch.team == team)
+            .map(|touch| touch.player_index)
+    }
+
+    /// The player of the scoring team who last touched the puck, if that was above the
+    /// crossbar height
+    fn high_stick(&self, team: HQMTeam, puck: HQMObjectIndex) -> Option<HQMServerPlayerIndex> {
+        if self.config.high_stick_height <= 0.0 {
+            return None;
+        }
+        let touch = self.puck_touches.get(&puck)?.front()?;
+        if touch.team == team && touch.puck_pos.y > self.config.high_stick_height {
+            Some(touch.player_index)
+        } else {
+            None
+        }
+    }
+
     fn handle_puck_passed_goal_line(&mut self, server: &mut HQMServer, line_team: HQMTeam) {
         if let Some(HQMPass {
             team: icing_team,
             side,
             from: Some(transition),
-            ..
-        }) = self.pass
+            player,
+        }) = self.rules.pass
         {
             let team = line_team.get_other_team();
             if team == icing_team && transition <= HQMPassPosition::ReachedCenter {
                 match self.config.icing {
                     HQMIcingConfiguration::Touch => {
-                        self.icing_status = HQMIcingStatus::Warning(team, side);
+                        self.rules.icing_status = HQMIcingStatus::Warning(team, side, player);
                         server.messages.add_server_chat_message("Icing warning");
                     }
                     HQMIcingConfiguration::NoTouch => {
-                        self.call_icing(server, team, side);
+                        self.call_icing(server, team, side, player);
                     }
                     HQMIcingConfiguration::Off => {}
                 }
@@ -482,7 +1268,11 @@ impl HQMMatch {
     }
 
     fn puck_into_offside_zone(&mut self, server: &mut HQMServer, team: HQMTeam) {
-        if self.offside_status == HQMOffsideStatus::InOffensiveZone(team) {
+        if self.rules.offside_status == HQMOffsideStatus::InOffensiveZone(team) {
+            return;
+        }
+        if self.config.offside == HQMOffsideConfiguration::Off {
+            self.rules.offside_status = HQMOffsideStatus::InOffensiveZone(team);
             return;
         }
         if let Some(HQMPass {
@@ -490,37 +1280,41 @@ impl HQMMatch {
             side,
             from: transition,
             player,
-        }) = self.pass
+        }) = self.rules.pass
         {
-            if team == pass_team && has_players_in_offensive_zone(&server, team, Some(player)) {
+            if team == pass_team
+                && self
+                    .skater_zones
+                    .has_players_in_offensive_zone(team, Some(player))
+            {
                 match self.config.offside {
                     HQMOffsideConfiguration::Delayed => {
-                        self.offside_status =
+                        self.rules.offside_status =
                             HQMOffsideStatus::Warning(team, side, transition, player);
                         server.messages.add_server_chat_message("Offside warning");
                     }
                     HQMOffsideConfiguration::Immediate => {
-                        self.call_offside(server, team, side, transition, false);
+                        self.call_offside(server, team, side, transition, false, player);
                     }
                     HQMOffsideConfiguration::Off => {
-                        self.offside_status = HQMOffsideStatus::InOffensiveZone(team);
+                        self.rules.offside_status = HQMOffsideStatus::InOffensiveZone(team);
                     }
                 }
             } else {
-                self.offside_status = HQMOffsideStatus::InOffensiveZone(team);
+                self.rules.offside_status = HQMOffsideStatus::InOffensiveZone(team);
             }
         } else {
-            self.offside_status = HQMOffsideStatus::InOffensiveZone(team);
+            self.rules.offside_status = HQMOffsideStatus::InOffensiveZone(team);
         }
     }
 
     fn handle_puck_entered_offensive_half(&mut self, server: &mut HQMServer, team: HQMTeam) {
-        if !matches!(&self.offside_status, HQMOffsideStatus::Offside(_))
+        if !matches!(&self.rules.offside_status, HQMOffsideStatus::Offside(_))
             && self.config.offside_line == HQMOffsideLineConfiguration::Center
         {
             self.puck_into_offside_zone(server, team);
         }
-        if let HQMOffsideStatus::Warning(warning_team, _, _, _) = self.offside_status {
+        if let HQMOffsideStatus::Warning(warning_team, _, _, _) = self.rules.offside_status {
             if warning_team != team {
                 server.messages.add_server_chat_message("Offside waved off");
             }
@@ -530,9 +1324,9 @@ impl HQMMatch {
             side,
             from: Some(from),
             player: pass_player,
-        }) = self.pass
+        }) = self.rules.pass
         {
-            if self.twoline_pass_status == HQMTwoLinePassStatus::No && pass_team == team {
+            if self.rules.twoline_pass_status == HQMTwoLinePassStatus::No && pass_team == team {
                 let is_regular_twoline_pass_active = self.config.twoline_pass
                     == HQMTwoLinePassConfiguration::Double
                     || self.config.twoline_pass == HQMTwoLinePassConfiguration::On;
@@ -544,7 +1338,7 @@ impl HQMMatch {
     }
 
     fn handle_puck_entered_offensive_zone(&mut self, server: &mut HQMServer, team: HQMTeam) {
-        if !matches!(&self.offside_status, HQMOffsideStatus::Offside(_))
+        if !matches!(&self.rules.offside_status, HQMOffsideStatus::Offside(_))
             && self.config.offside_line == HQMOffsideLineConfiguration::OffensiveBlue
         {
             self.puck_into_offside_zone(server, team);
@@ -554,9 +1348,9 @@ impl HQMMatch {
             side,
             from: Some(from),
             player: pass_player,
-        }) = self.pass
+        }) = self.rules.pass
         {
-            if self.twoline_pass_status == HQMTwoLinePassStatus::No && pass_team == team {
+            if self.rules.twoline_pass_status == HQMTwoLinePassStatus::No && pass_team == team {
                 let is_forward_twoline_pass_active = self.config.twoline_pass
                     == HQMTwoLinePassConfiguration::Double
                     || self.config.twoline_pass == HQMTwoLinePassConfiguration::Forward;
@@ -580,26 +1374,12 @@ impl HQMMatch {
         pass_player: HQMServerPlayerIndex,
         is_offensive_line: bool,
     ) {
-        let line = if is_offensive_line {
-            match team {
-                HQMTeam::Red => &server.world.rink.blue_zone_blue_line,
-                HQMTeam::Blue => &server.world.rink.red_zone_blue_line,
-            }
-        } else {
-            &server.world.rink.center_line
-        };
-        let mut players_past_line = vec![];
-        for (player_index, player) in server.players.iter() {
-            if player_index == pass_player {
-                continue;
-            }
-            if is_past_line(server, player, team, line) {
-                players_past_line.push(player_index);
-            }
-        }
+        let players_past_line =
+            self.skater_zones
+                .players_past_line(team, is_offensive_line, pass_player);
         if !players_past_line.is_empty() {
-            self.twoline_pass_status =
-                HQMTwoLinePassStatus::Warning(team, side, from, players_past_line);
+            self.rules.twoline_pass_status =
+                HQMTwoLinePassStatus::Warning(team, side, from, pass_player, players_past_line);
             server
                 .messages
                 .add_server_chat_message("Two-line pass warning");
@@ -607,20 +1387,20 @@ impl HQMMatch {
     }
 
     fn handle_puck_passed_defensive_line(&mut self, server: &mut HQMServer, team: HQMTeam) {
-        if !matches!(&self.offside_status, HQMOffsideStatus::Offside(_))
+        if !matches!(&self.rules.offside_status, HQMOffsideStatus::Offside(_))
             && self.config.offside_line == HQMOffsideLineConfiguration::OffensiveBlue
         {
-            if let HQMOffsideStatus::Warning(t, _, _, _) = self.offside_status {
+            if let HQMOffsideStatus::Warning(t, _, _, _) = self.rules.offside_status {
                 if team.get_other_team() == t {
                     server.messages.add_server_chat_message("Offside waved off");
                 }
             }
-            self.offside_status = HQMOffsideStatus::Neutral;
+            self.rules.offside_status = HQMOffsideStatus::Neutral;
         }
     }
 
     fn update_pass(&mut self, team: HQMTeam, p: HQMPassPosition) {
-        if let Some(pass) = &mut self.pass {
+        if let Some(pass) = &mut self.rules.pass {
             if pass.team == team && pass.from.is_none() {
                 pass.from = Some(p);
             }
@@ -628,9 +1408,11 @@ impl HQMMatch {
     }
 
     fn check_wave_off_twoline(&mut self, server: &mut HQMServer, team: HQMTeam) {
-        if let HQMTwoLinePassStatus::Warning(warning_team, _, _, _) = self.twoline_pass_status {
+        if let HQMTwoLinePassStatus::Warning(warning_team, _, _, _, _) =
+            self.rules.twoline_pass_status
+        {
             if team != warning_team {
-                self.twoline_pass_status = HQMTwoLinePassStatus::No;
+                self.rules.twoline_pass_status = HQMTwoLinePassStatus::No;
                 server
                     .messages
                     .add_server_chat_message("Two-line pass waved off");
@@ -645,39 +1427,68 @@ impl HQMMatch {
         match_events: &mut Vec<HQMMatchEvent>,
     ) {
         for event in events {
+            if let Some(puck) = event.puck() {
+                self.select_puck_rules(puck);
+            }
+            // Lines and nets are named after the end of the rink, not the team defending it
             match *event {
                 HQMSimulationEvent::PuckEnteredNet { team, puck } => {
+                    let team = self.rink_end(team);
                     self.handle_puck_entered_net(server, match_events, team, puck);
                 }
                 HQMSimulationEvent::PuckTouch { player, puck, .. } => {
                     self.handle_puck_touch(server, player, puck);
                 }
+                HQMSimulationEvent::PuckTouchedBody { player, puck } => {
+                    self.handle_puck_touched_body(server, player, puck);
+                }
                 HQMSimulationEvent::PuckReachedDefensiveLine { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.check_wave_off_twoline(server, team);
                     self.update_pass(team, HQMPassPosition::ReachedOwnBlue);
                 }
                 HQMSimulationEvent::PuckPassedDefensiveLine { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.update_pass(team, HQMPassPosition::PassedOwnBlue);
                     self.handle_puck_passed_defensive_line(server, team);
                 }
                 HQMSimulationEvent::PuckReachedCenterLine { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.check_wave_off_twoline(server, team);
                     self.update_pass(team, HQMPassPosition::ReachedCenter);
                 }
                 HQMSimulationEvent::PuckPassedCenterLine { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.update_pass(team, HQMPassPosition::PassedCenter);
                     self.handle_puck_entered_offensive_half(server, team);
                 }
                 HQMSimulationEvent::PuckReachedOffensiveZone { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.update_pass(team, HQMPassPosition::ReachedOffensive);
                 }
                 HQMSimulationEvent::PuckEnteredOffensiveZone { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.update_pass(team, HQMPassPosition::PassedOffensive);
                     self.handle_puck_entered_offensive_zone(server, team);
                 }
                 HQMSimulationEvent::PuckPassedGoalLine { team, puck: _ } => {
+                    let team = self.rink_end(team);
                     self.handle_puck_passed_goal_line(server, team);
                 }
+                HQMSimulationEvent::PuckOutOfPlay { puck } => {
+                    self.call_puck_out_of_play(server, puck);
+                }
+                HQMSimulationEvent::NetDislodged { team, player } => {
+                    let team = self.rink_end(team);
+                    self.call_net_dislodged(server, team, player);
+                }
+                HQMSimulationEvent::PlayerCollision {
+                    player_a,
+                    player_b,
+                    impact,
+                } => {
+                    self.handle_hit(server, player_a, player_b, impact);
+                }
                 _ => {}
             }
 
@@ -691,6 +1502,17 @@ impl HQMMatch {
         }
     }
 
+    /// Makes `rules` the rule state of the given puck, and puts away the state of the puck it
+    /// was before
+    fn select_puck_rules(&mut self, puck: HQMObjectIndex) {
+        select_puck_state(
+            &mut self.rules,
+            &mut self.rules_puck,
+            &mut self.puck_rules,
+            puck,
+        );
+    }
+
     fn call_offside(
         &mut self,
         server: &mut HQMServer,
@@ -698,6 +1520,7 @@ impl HQMMatch {
         side: HQMRinkSide,
         position: Option<HQMPassPosition>,
         self_touch: bool,
+        player: HQMServerPlayerIndex,
     ) {
         let time_break = self.config.time_break * 100;
 
@@ -722,8 +1545,22 @@ impl HQMMatch {
 
         self.next_faceoff_spot = faceoff_spot;
         self.pause_timer = time_break;
-        self.offside_status = HQMOffsideStatus::Offside(team);
-        server.messages.add_server_chat_message("Offside");
+        self.rules.offside_status = HQMOffsideStatus::Offside(team);
+        self.announce_rule_call(server, HQMRuleCall::Offside, team, player);
+
+        let offsides = self.offsides.entry(player).or_insert(0);
+        *offsides += 1;
+        let limit = server.config.alerts.offsides;
+        if limit > 0 && *offsides >= limit {
+            if let Some(offender) = server.players.get(player) {
+                let player_name = offender.player_name.clone();
+                let msg = format!(
+                    "Repeated offsides: {} ({}) was offside {} times this game",
+                    player_name, player, offsides
+                );
+                server.send_alert(HQMAlertKind::Offside, &player_name, msg);
+            }
+        }
     }
 
     fn call_twoline_pass(
@@ -732,6 +1569,7 @@ impl HQMMatch {
         team: HQMTeam,
         side: HQMRinkSide,
         position: HQMPassPosition,
+        player: HQMServerPlayerIndex,
     ) {
         let time_break = self.config.time_break * 100;
 
@@ -745,17 +1583,193 @@ impl HQMMatch {
 
         self.next_faceoff_spot = faceoff_spot;
         self.pause_timer = time_break;
-        self.twoline_pass_status = HQMTwoLinePassStatus::Offside(team);
-        server.messages.add_server_chat_message("Two-line pass");
+        self.rules.twoline_pass_status = HQMTwoLinePassStatus::Offside(team);
+        self.announce_rule_call(server, HQMRuleCall::TwoLinePass, team, player);
     }
 
-    fn call_icing(&mut self, server: &mut HQMServer, team: HQMTeam, side: HQMRinkSide) {
+    fn call_icing(
+        &mut self,
+        server: &mut HQMServer,
+        team: HQMTeam,
+        side: HQMRinkSide,
+        player: HQMServerPlayerIndex,
+    ) {
         let time_break = self.config.time_break * 100;
 
         self.next_faceoff_spot = HQMRinkFaceoffSpot::DefensiveZone(team, side);
         self.pause_timer = time_break;
-        self.icing_status = HQMIcingStatus::Icing(team);
-        server.messages.add_server_chat_message("Icing");
+        self.rules.icing_status = HQMIcingStatus::Icing(team);
+        self.announce_rule_call(server, HQMRuleCall::Icing, team, player);
+    }
+
+    fn wave_off_goal(
+        &mut self,
+        server: &mut HQMServer,
+        rule: HQMRuleCall,
+        team: HQMTeam,
+        puck_index: HQMObjectIndex,
+        player: HQMServerPlayerIndex,
+    ) {
+        let time_break = self.config.time_break * 100;
+
+        let side = match server.world.objects.get_puck(puck_index) {
+            Some(puck) if puck.body.pos.x > server.world.rink.width / 2.0 => HQMRinkSide::Right,
+            _ => HQMRinkSide::Left,
+        };
+        self.next_faceoff_spot = HQMRinkFaceoffSpot::DefensiveZone(team.get_other_team(), side);
+        self.pause_timer = time_break;
+        let msg = if rule == HQMRuleCall::HighStick {
+            "High stick, no goal"
+        } else {
+            "Off the body, no goal"
+        };
+        server.messages.add_server_chat_message(msg);
+        self.announce_rule_call(server, rule, team, player);
+    }
+
+    fn call_hand_pass(
+        &mut self,
+        server: &mut HQMServer,
+        team: HQMTeam,
+        side: HQMRinkSide,
+        player: HQMServerPlayerIndex,
+    ) {
+        let time_break = self.config.time_break * 100;
+
+        self.next_faceoff_spot = HQMRinkFaceoffSpot::Offside(team, side);
+        self.pause_timer = time_break;
+        self.announce_rule_call(server, HQMRuleCall::HandPass, team, player);
+    }
+
+    /// Play stops when a puck goes over the boards, with a faceoff in the zone where it was last
+    /// played. A player who shot it out of their own zone can get a minor for delay of game
+    fn call_puck_out_of_play(&mut self, server: &mut HQMServer, puck_index: HQMObjectIndex) {
+        let last_touch = self
+            .puck_touches
+            .get(&puck_index)
+            .and_then(|touches| touches.front())
+            .cloned();
+        let pos = match (&last_touch, server.world.objects.get_puck(puck_index)) {
+            (Some(touch), _) => touch.puck_pos,
+            (None, Some(puck)) => puck.body.pos,
+            (None, None) => return,
+        };
+        self.next_faceoff_spot = self.faceoff_spot_at(&server.world.rink, &pos);
+        self.pause_timer = self.config.time_break * 100;
+
+        let touch = match last_touch {
+            Some(touch) => touch,
+            None => {
+                info!(target: GAME_LOG, "Puck out of play");
+                server.messages.add_server_chat_message("Puck out of play");
+                return;
+            }
+        };
+        self.announce_rule_call(
+            server,
+            HQMRuleCall::PuckOutOfPlay,
+            touch.team,
+            touch.player_index,
+        );
+        if self.config.delay_of_game
+            && self.in_defensive_zone(&server.world.rink, touch.team, &touch.puck_pos)
+        {
+            let player_name = match server.players.get(touch.player_index) {
+                Some(player) if player.object.is_some_and(|(_, t)| t == touch.team) => {
+                    player.player_name.clone()
+                }
+                _ => return,
+            };
+            info!(
+                target: GAME_LOG,
+                team = %touch.team,
+                player = player_name.as_str(),
+                minutes = MINOR_PENALTY,
+                period = server.values.period,
+                time = server.values.time,
+                "{} minute penalty to {} for delay of game",
+                MINOR_PENALTY,
+                player_name
+            );
+            self.send_to_penalty_box(
+                server,
+                touch.player_index,
+                player_name,
+                touch.team,
+                MINOR_PENALTY,
+            );
+        }
+    }
+
+    /// Play stops when a skater knocks a net off its moorings, with a faceoff in the zone of the
+    /// team whose net it is. The net is put back at the faceoff
+    fn call_net_dislodged(
+        &mut self,
+        server: &mut HQMServer,
+        net_team: HQMTeam,
+        player: HQMObjectIndex,
+    ) {
+        let (player_index, team, _) = match server.players.get_from_object_index(player) {
+            Some(x) => x,
+            None => return,
+        };
+        let side = match server.world.objects.get_skater(player) {
+            Some(skater) if skater.body.pos.x > server.world.rink.width / 2.0 => HQMRinkSide::Right,
+            _ => HQMRinkSide::Left,
+        };
+        let time_break = self.config.time_break * 100;
+
+        self.next_faceoff_spot = HQMRinkFaceoffSpot::DefensiveZone(net_team, side);
+        self.pause_timer = time_break;
+        self.announce_rule_call(server, HQMRuleCall::NetDislodged, team, player_index);
+    }
+
+    /// Tells everyone which player broke the rule and where the next faceoff is, so the call
+    /// can be followed without watching the replay
+    fn announce_rule_call(
+        &self,
+        server: &mut HQMServer,
+        rule: HQMRuleCall,
+        team: HQMTeam,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        let rule_name = match rule {
+            HQMRuleCall::Offside => "Offside",
+            HQMRuleCall::TwoLinePass => "Two-line pass",
+            HQMRuleCall::Icing => "Icing",
+            HQMRuleCall::HighStick => "High stick",
+            HQMRuleCall::HandPass => "Hand pass",
+            HQMRuleCall::BodyGoal => "Goal off the body",
+            HQMRuleCall::NetDislodged => "Net dislodged",
+            HQMRuleCall::PuckOutOfPlay => "Puck out of play",
+        };
+        // The player may have left since touching the puck
+        let player = server
+            .players
+            .get(player_index)
+            .filter(|player| player.object.is_some_and(|(_, t)| t == team))
+            .map(|player| player.player_name.to_string());
+        let msg = match &player {
+            Some(player) => format!("{} by {} ({})", rule_name, player, team),
+            None => format!("{} by {}", rule_name, team),
+        };
+        server.messages.add_server_chat_message(msg);
+        let faceoff = match self.next_faceoff_spot {
+            HQMRinkFaceoffSpot::Center => "Faceoff at center ice".to_string(),
+            HQMRinkFaceoffSpot::DefensiveZone(team, _) => format!("Faceoff in the {} zone", team),
+            HQMRinkFaceoffSpot::Offside(team, _) => {
+                format!("Faceoff outside the {} zone", team)
+            }
+        };
+        server.messages.add_server_chat_message(faceoff);
+        info!(target: GAME_LOG, %team, player, "{} by {}", rule_name, team);
+        server.publish_event(HQMEvent::RuleCall {
+            rule,
+            team,
+            player,
+            period: server.values.period,
+            time: server.values.time,
+        });
     }
 
     pub fn after_tick(
@@ -764,39 +1778,75 @@ impl HQMMatch {
         events: &[HQMSimulationEvent],
     ) -> Vec<HQMMatchEvent> {
         let mut match_events = vec![];
+        if server.values.period == 0 {
+            self.practice.update(server, events);
+        } else if !self.practice.is_empty() {
+            self.practice.remove(server);
+        }
+        let is_live = self.shootout.is_none()
+            && self.pause_timer == 0
+            && server.values.time > 0
+            && !server.values.game_over
+            && server.values.period > 0
+            && !self.paused;
+        // Nets can only be knocked off in live play, where the rule call puts them back
+        server.world.rink.net_dislodge_speed = if is_live {
+            self.config.net_dislodge_speed
+        } else {
+            0.0
+        };
+        if self.shootout.is_some() {
+            self.update_shootout(server, events);
+            return match_events;
+        }
+        self.update_pending_drop(server);
         if server.values.time == 0 && server.values.period > 1 {
             self.handle_events_end_of_period(server, events);
-        } else if self.pause_timer > 0
-            || server.values.time == 0
-            || server.values.game_over
-            || server.values.period == 0
-            || self.paused
-        {
+        } else if !is_live {
             // Nothing
         } else {
+            if self.config.offside != HQMOffsideConfiguration::Off
+                || self.config.twoline_pass != HQMTwoLinePassConfiguration::Off
+            {
+                self.skater_zones = HQMSkaterZones::new(server, self.ends_swapped);
+            }
             self.handle_events(server, events, &mut match_events);
+            self.update_possession(server);
 
-            if let HQMOffsideStatus::Warning(team, _, _, _) = self.offside_status {
-                if !has_players_in_offensive_zone(server, team, None) {
-                    self.offside_status = HQMOffsideStatus::InOffensiveZone(team);
-                    server.messages.add_server_chat_message("Offside waved off");
+            if self.config.hints {
+                self.hints.update(server, &self.preferred_positions);
+            }
+
+            let all_rules = std::iter::once(&mut self.rules).chain(self.puck_rules.values_mut());
+            for rules in all_rules {
+                if let HQMOffsideStatus::Warning(team, _, _, _) = rules.offside_status {
+                    if !self.skater_zones.has_players_in_offensive_zone(team, None) {
+                        rules.offside_status = HQMOffsideStatus::InOffensiveZone(team);
+                        server.messages.add_server_chat_message("Offside waved off");
+                    }
                 }
             }
 
-            let rules_state = if matches!(self.offside_status, HQMOffsideStatus::Offside(_))
-                || matches!(self.twoline_pass_status, HQMTwoLinePassStatus::Offside(_))
-            {
+            let all_rules = || std::iter::once(&self.rules).chain(self.puck_rules.values());
+            let rules_state = if all_rules().any(|rules| {
+                matches!(rules.offside_status, HQMOffsideStatus::Offside(_))
+                    || matches!(rules.twoline_pass_status, HQMTwoLinePassStatus::Offside(_))
+            }) {
                 HQMRulesState::Offside
-            } else if matches!(self.icing_status, HQMIcingStatus::Icing(_)) {
+            } else if all_rules()
+                .any(|rules| matches!(rules.icing_status, HQMIcingStatus::Icing(_)))
+            {
                 HQMRulesState::Icing
             } else {
-                let icing_warning = matches!(self.icing_status, HQMIcingStatus::Warning(_, _));
-                let offside_warning =
-                    matches!(self.offside_status, HQMOffsideStatus::Warning(_, _, _, _))
+                let icing_warning = all_rules()
+                    .any(|rules| matches!(rules.icing_status, HQMIcingStatus::Warning(_, _, _)));
+                let offside_warning = all_rules().any(|rules| {
+                    matches!(rules.offside_status, HQMOffsideStatus::Warning(_, _, _, _))
                         || matches!(
-                            self.twoline_pass_status,
-                            HQMTwoLinePassStatus::Warning(_, _, _, _)
-                        );
+                            rules.twoline_pass_status,
+                            HQMTwoLinePassStatus::Warning(_, _, _, _, _)
+                        )
+                });
                 HQMRulesState::Regular {
                     offside_warning,
                     icing_warning,
@@ -819,36 +1869,45 @@ impl HQMMatch {
     }
 
     fn update_clock(&mut self, server: &mut HQMServer) {
-        let period_length = self.config.time_period * 100;
-        let intermission_time = self.config.time_intermission * 100;
+        let period_length = self.config.period_length(server.values.period);
 
         if !self.paused {
             if self.pause_timer > 0 {
-                self.pause_timer -= 1;
+                if self.config.clock_runs_in_stoppage(&server.values) {
+                    // The period may end and the shootout start during the stoppage
+                    self.run_clock(server);
+                    if self.shootout.is_some() {
+                        return;
+                    }
+                }
+                self.pause_timer = self.pause_timer.saturating_sub(1);
                 if self.pause_timer == 0 {
+                    let is_goal_faceoff = self.is_pause_goal;
                     self.is_pause_goal = false;
                     if server.values.game_over {
+                        self.last_game_result = Some(HQMGameResult {
+                            red_score: server.values.red_score,
+                            blue_score: server.values.blue_score,
+                            goals: std::mem::take(&mut self.goals),
+                        });
                         server.new_game(self.get_initial_game_values());
                     } else {
                         if server.values.time == 0 {
                             server.values.time = period_length;
                         }
+                        if !is_goal_faceoff
+                            && self.config.goal_streak_reset == HQMGoalStreakReset::Faceoff
+                        {
+                            self.goal_streak.reset_streak();
+                        }
 
                         self.do_faceoff(server);
                     }
                 }
-            } else {
-                server.values.time = server.values.time.saturating_sub(1);
-                if server.values.time == 0 {
-                    server.values.period += 1;
-                    self.pause_timer = intermission_time;
-                    self.is_pause_goal = false;
-                    self.step_where_period_ended = server.game_step;
-                    self.too_late_printed_this_period = false;
-                    self.next_faceoff_spot = HQMRinkFaceoffSpot::Center;
-                    self.update_game_over(server);
-                }
+            } else if server.values.period > 0 || self.teams_ready(server) {
+                self.run_clock(server);
             }
+            self.announce_countdowns(server);
         }
         server.values.goal_message_timer = if self.is_pause_goal {
             self.pause_timer
@@ -857,7 +1916,630 @@ impl HQMMatch {
         };
     }
 
-    pub fn cleanup_player(&mut self, player_index: HQMServerPlayerIndex) {
+    /// Tells the players in chat when the period is about to end, in its last minute, and when
+    /// the next period or game starts
+    fn announce_countdowns(&mut self, server: &mut HQMServer) {
+        let period_left = if server.values.period > 0 && !server.values.game_over {
+            server.values.time
+        } else {
+            0
+        };
+        if let Some(seconds) = self.period_countdown.update(period_left) {
+            if (10..=60).contains(&seconds) {
+                let msg = format!("{} left in the period", format_countdown(seconds));
+                server.messages.add_server_chat_message(msg);
+            }
+        }
+        let break_left = if server.values.time == 0 || server.values.game_over {
+            self.pause_timer
+        } else {
+            0
+        };
+        if let Some(seconds) = self.break_countdown.update(break_left) {
+            let msg = if server.values.game_over {
+                format!("Next game in {}", format_countdown(seconds))
+            } else if server.values.period > self.config.periods {
+                format!("Overtime starts in {}", format_countdown(seconds))
+            } else {
+                format!(
+                    "Period {} starts in {}",
+                    server.values.period,
+                    format_countdown(seconds)
+                )
+            };
+            server.messages.add_server_chat_message(msg);
+        }
+    }
+
+    /// Runs the game clock for a step, and ends the period when the time is up
+    fn run_clock(&mut self, server: &mut HQMServer) {
+        let intermission_time = self.config.time_intermission * 100;
+        server.values.time = server.values.time.saturating_sub(1);
+        let served = self.penalties.tick();
+        if !served.is_empty() {
+            for (player_name, team) in served {
+                self.release_from_penalty_box(server, &player_name, team);
+            }
+            self.update_power_play(server, false);
+        }
+        if server.values.time == 0 {
+            server.values.period += 1;
+            if self.config.swap_ends && server.values.period > 1 {
+                self.set_ends_swapped(server, !self.ends_swapped);
+            }
+            if self.config.goal_streak_reset == HQMGoalStreakReset::Period {
+                self.goal_streak.reset_streak();
+            }
+            self.pause_timer = intermission_time;
+            self.is_pause_goal = false;
+            self.step_where_period_ended = server.game_step;
+            self.too_late_printed_this_period = false;
+            self.next_faceoff_spot = HQMRinkFaceoffSpot::Center;
+            self.update_game_over(server);
+            if !server.values.game_over
+                && self.config.shootout > 0
+                && server.values.period > self.config.periods + 1
+            {
+                self.start_shootout(server);
+            }
+        }
+    }
+
+    fn start_shootout(&mut self, server: &mut HQMServer) {
+        let rounds = self.config.shootout;
+        let mut shootout = HQMShootoutTiebreaker::new(rounds);
+        shootout.state = HQMShootoutAttemptState::Break {
+            timer: self.config.time_intermission * 100,
+            goal: false,
+        };
+        self.shootout = Some(shootout);
+        self.pause_timer = 0;
+        // Shootout attempts are always taken at the ends the game started with
+        self.set_ends_swapped(server, false);
+        info!(target: GAME_LOG, rounds, "Shootout");
+        let msg = format!("Still tied after overtime, shootout with {} rounds", rounds);
+        server.messages.add_server_chat_message(msg);
+    }
+
+    fn update_shootout(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        let mut shootout = match self.shootout.take() {
+            Some(shootout) => shootout,
+            None => return,
+        };
+        if !self.paused {
+            match shootout.state {
+                HQMShootoutAttemptState::Break { timer, goal } => {
+                    let timer = timer.saturating_sub(1);
+                    server.values.goal_message_timer = if goal { timer } else { 0 };
+                    if timer == 0 {
+                        self.start_shootout_attempt(server, &mut shootout);
+                    } else {
+                        shootout.state = HQMShootoutAttemptState::Break { timer, goal };
+                    }
+                }
+                _ => {
+                    if let Some(goal) = shootout_attempt_result(server, &mut shootout, events) {
+                        if self.end_shootout_attempt(server, &mut shootout, goal) {
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+        self.shootout = Some(shootout);
+    }
+
+    /// Puts the shooter with the puck at center ice and the goalie of the other team in the
+    /// net. Everyone else waits at the benches
+    fn start_shootout_attempt(
+        &mut self,
+        server: &mut HQMServer,
+        shootout: &mut HQMShootoutTiebreaker,
+    ) {
+        let team = shootout.next_team();
+        let round = shootout.next_round();
+        let mut attackers = vec![];
+        let mut defenders = vec![];
+        for (player_index, player) in server.players.iter() {
+            if let Some((_, player_team)) = player.object {
+                if player_team == team {
+                    attackers.push((player_index, player.player_name.clone()));
+                } else {
+                    defenders.push(player_index);
+                }
+            }
+        }
+        let shooter = match shootout.pick_shooter(&attackers) {
+            Some(shooter) => shooter,
+            None => {
+                let msg = format!("{} has nobody to shoot", team);
+                server.messages.add_server_chat_message(msg);
+                self.end_shootout_attempt(server, shootout, false);
+                return;
+            }
+        };
+        let goalie = defenders
+            .iter()
+            .find(|player_index| self.started_as_goalie.contains(player_index))
+            .or_else(|| defenders.first())
+            .copied();
+
+        let shooter_name = server
+            .players
+            .get(shooter)
+            .map(|player| player.player_name.to_string())
+            .unwrap_or_default();
+        let msg = if shootout.is_sudden_death(round) {
+            format!(
+                "Sudden death round {}: {} for {}",
+                round, shooter_name, team
+            )
+        } else {
+            format!(
+                "Round {} of {}: {} for {}",
+                round,
+                shootout.rounds(),
+                shooter_name,
+                team
+            )
+        };
+        server.messages.add_server_chat_message(msg);
+
+        server.world.clear_pucks();
+        self.puck_touches.clear();
+        self.body_touches.clear();
+        let rink = &server.world.rink;
+        let center = rink.center_ice();
+        let red_goalie_pos = rink.red_net.goalie_position(1.5);
+        let blue_goalie_pos = rink.blue_net.goalie_position(1.5);
+        let puck = server
+            .world
+            .create_puck_object(Point3::new(center.x, 1.0, center.z), Rotation3::identity());
+
+        let (goalie_pos, goalie_rot) = match team {
+            HQMTeam::Red => (blue_goalie_pos, PI),
+            HQMTeam::Blue => (red_goalie_pos, 0.0),
+        };
+        for player_index in attackers
+            .iter()
+            .map(|(player_index, _)| *player_index)
+            .chain(defenders.iter().copied())
+        {
+            let player_team = if attackers.iter().any(|(i, _)| *i == player_index) {
+                team
+            } else {
+                team.get_other_team()
+            };
+            let (pos, rot) = if player_index == shooter {
+                get_spawnpoint(&server.world.rink, team, HQMSpawnPoint::Center)
+            } else if Some(player_index) == goalie {
+                (
+                    goalie_pos,
+                    Rotation3::from_euler_angles(0.0, goalie_rot, 0.0),
+                )
+            } else {
+                get_spawnpoint(&server.world.rink, player_team, HQMSpawnPoint::Bench)
+            };
+            server.spawn_skater(player_index, player_team, pos, rot, false);
+        }
+
+        server.values.time = SHOOTOUT_ATTEMPT_TIME;
+        server.values.goal_message_timer = 0;
+        shootout.state = HQMShootoutAttemptState::Attack { progress: 0.0 };
+        shootout.attempt = puck.map(|puck| HQMShootoutAttempt {
+            team,
+            shooter,
+            puck,
+        });
+    }
+
+    /// Returns true if the shootout is over
+    fn end_shootout_attempt(
+        &mut self,
+        server: &mut HQMServer,
+        shootout: &mut HQMShootoutTiebreaker,
+        goal: bool,
+    ) -> bool {
+        let (team, shooter) = match shootout.attempt.take() {
+            Some(attempt) => (attempt.team, Some(attempt.shooter)),
+            None => (shootout.next_team(), None),
+        };
+        let round = shootout.next_round();
+        shootout.add_attempt(team, goal);
+        let player = shooter
+            .and_then(|shooter| server.players.get(shooter))
+            .map(|player| player.player_name.to_string());
+        if goal {
+            server.messages.add_goal_message(team, shooter, None);
+        } else {
+            server.messages.add_server_chat_message("Miss");
+        }
+        let red_goals = shootout.goals(HQMTeam::Red);
+        let blue_goals = shootout.goals(HQMTeam::Blue);
+        info!(
+            target: GAME_LOG,
+            %team,
+            player = player.as_deref(),
+            goal,
+            round,
+            red_goals,
+            blue_goals,
+            "Shootout attempt by {}: {}",
+            team,
+            if goal { "goal" } else { "miss" }
+        );
+        let msg = format!("Shootout: Red {} - {} Blue", red_goals, blue_goals);
+        server.messages.add_server_chat_message(msg);
+        server.publish_event(HQMEvent::ShootoutAttempt {
+            team,
+            player,
+            goal,
+            round,
+            red_goals,
+            blue_goals,
+        });
+
+        if let Some(winner) = shootout.winner() {
+            let msg = format!("{} wins the shootout", winner);
+            server.messages.add_server_chat_message(msg);
+            // The winner of the shootout gets a goal on the final score
+            match winner {
+                HQMTeam::Red => server.values.red_score += 1,
+                HQMTeam::Blue => server.values.blue_score += 1,
+            }
+            self.update_game_over(server);
+            true
+        } else {
+            shootout.state = HQMShootoutAttemptState::Break {
+                timer: SHOOTOUT_ATTEMPT_BREAK,
+                goal,
+            };
+            false
+        }
+    }
+
+    pub fn msg_last_game_result(
+        &self,
+        server: &mut HQMServer,
+        receiver_index: HQMServerPlayerIndex,
+    ) {
+        if let Some(result) = &self.last_game_result {
+            let msg = format!(
+                "Last game: Red {} - {} Blue",
+                result.red_score, result.blue_score
+            );
+            server
+                .messages
+                .add_directed_server_chat_message(msg, receiver_index);
+
+            let mut scorers: Vec<(Rc<String>, usize)> = vec![];
+            for scorer in result.goals.iter().filter_map(|x| x.scorer.as_ref()) {
+                match scorers.iter_mut().find(|(name, _)| name == scorer) {
+                    Some((_, goals)) => *goals += 1,
+                    None => scorers.push((scorer.clone(), 1)),
+                }
+            }
+            if !scorers.is_empty() {
+                let scorers = scorers
+                    .iter()
+                    .map(|(name, goals)| format!("{} {}", name, goals))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let msg = format!("Scorers: {}", scorers);
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, receiver_index);
+            }
+            if let Some((name, goals, assists)) = result.mvp() {
+                let msg = format!("MVP: {} ({} G, {} A)", name, goals, assists);
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, receiver_index);
+            }
+        }
+    }
+
+    /// Time the player still has to serve in the penalty box, None if the player isn't in it
+    pub fn penalty_time_left(&self, player_name: &str) -> Option<u32> {
+        self.penalties.time_left(player_name)
+    }
+
+    /// Number of players of the team in the penalty box, who still count towards the team size
+    pub fn players_in_penalty_box(&self, team: HQMTeam) -> usize {
+        self.penalties.players_in_box(team)
+    }
+
+    /// Moves a player to the penalty box and announces the penalty
+    pub(crate) fn send_to_penalty_box(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        player_name: Rc<String>,
+        team: HQMTeam,
+        minutes: u32,
+    ) {
+        server.move_to_spectator(player_index);
+        self.penalties.add(player_name.clone(), team, minutes);
+        let msg = format!("{} minute penalty to {} ({})", minutes, player_name, team);
+        server.messages.add_server_chat_message(msg);
+        server.publish_event(HQMEvent::Penalty {
+            team,
+            player: player_name.to_string(),
+            minutes,
+            period: server.values.period,
+            time: server.values.time,
+        });
+        self.update_power_play(server, false);
+    }
+
+    /// Keeps track of which team has more players on the ice because of penalties, and
+    /// announces when a power play starts or ends. A power play that ends with a goal isn't
+    /// announced as killed
+    pub(crate) fn update_power_play(&mut self, server: &mut HQMServer, after_goal: bool) {
+        let red = self.penalties.players_in_box(HQMTeam::Red) as u32;
+        let blue = self.penalties.players_in_box(HQMTeam::Blue) as u32;
+        let old_values = (server.values.red_penalized, server.values.blue_penalized);
+        server.values.red_penalized = red;
+        server.values.blue_penalized = blue;
+        let power_play = match red.cmp(&blue) {
+            Ordering::Less => Some(HQMTeam::Red),
+            Ordering::Greater => Some(HQMTeam::Blue),
+            Ordering::Equal => None,
+        };
+        if power_play == self.power_play {
+            return;
+        }
+        let on_ice = |team: HQMTeam| {
+            server
+                .players
+                .iter()
+                .filter(|(_, player)| matches!(player.object, Some((_, t)) if t == team))
+                .count()
+        };
+        let msg = match (power_play, self.power_play) {
+            (Some(team), _) => Some(format!(
+                "{} power play, {} on {}",
+                team,
+                on_ice(team),
+                on_ice(team.get_other_team())
+            )),
+            (None, Some(old_team)) if !after_goal => {
+                // The shorthanded team got a player back, instead of the other team losing one
+                let (old_red, old_blue) = old_values;
+                let killed = match old_team {
+                    HQMTeam::Red => blue < old_blue,
+                    HQMTeam::Blue => red < old_red,
+                };
+                Some(if killed {
+                    "Penalty killed".to_string()
+                } else {
+                    format!(
+                        "Even strength, {} on {}",
+                        on_ice(HQMTeam::Red),
+                        on_ice(HQMTeam::Blue)
+                    )
+                })
+            }
+            _ => None,
+        };
+        info!(
+            target: GAME_LOG,
+            red_penalized = red,
+            blue_penalized = blue,
+            "Power play: {}",
+            power_play.map_or("none".to_string(), |team| team.to_string())
+        );
+        if let Some(msg) = msg {
+            server.messages.add_server_chat_message(msg);
+        }
+        self.power_play = power_play;
+    }
+
+    /// Puts a player who has served their penalties back on the ice at their bench, if the
+    /// player is still on the server
+    pub(crate) fn release_from_penalty_box(
+        &mut self,
+        server: &mut HQMServer,
+        player_name: &str,
+        team: HQMTeam,
+    ) {
+        info!(target: GAME_LOG, %team, "{} is out of the penalty box", player_name);
+        let msg = format!("{} is out of the penalty box", player_name);
+        server.messages.add_server_chat_message(msg);
+        let player_index = server
+            .players
+            .iter()
+            .find(|(_, player)| {
+                player.player_name.as_str() == player_name && player.object.is_none()
+            })
+            .map(|(player_index, _)| player_index);
+        if let Some(player_index) = player_index {
+            let (pos, rot) = get_spawnpoint(
+                &server.world.rink,
+                self.rink_end(team),
+                HQMSpawnPoint::Bench,
+            );
+            if server
+                .spawn_skater(player_index, team, pos, rot, false)
+                .is_some()
+            {
+                self.clear_started_goalie(player_index);
+            }
+        }
+    }
+
+    /// Pauses the game and schedules a faceoff where the puck is if the player who is about to
+    /// disconnect leaves their team with fewer than auto_pause_skaters skaters in live play
+    pub fn pause_for_disconnect(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        if self.config.auto_pause_skaters == 0
+            || self.paused
+            || self.pause_timer > 0
+            || self.shootout.is_some()
+        {
+            return;
+        }
+        let (player_name, team) = match server.players.get(player_index) {
+            Some(HQMServerPlayer {
+                player_name,
+                object: Some((_, team)),
+                ..
+            }) => (player_name.clone(), *team),
+            _ => return,
+        };
+        // Dummies left on the ice for players who may rejoin don't play
+        let skaters = server
+            .players
+            .iter()
+            .filter(|(i, player)| {
+                *i != player_index
+                    && player.object.is_some_and(|(_, t)| t == team)
+                    && !matches!(player.data, HQMServerPlayerData::Dummy)
+            })
+            .count();
+        if !self.config.pauses_for_disconnect(&server.values, skaters) {
+            return;
+        }
+
+        self.next_faceoff_spot = match server.world.objects.pucks().next() {
+            Some((_, puck)) => self.faceoff_spot_at(&server.world.rink, &puck.body.pos),
+            None => HQMRinkFaceoffSpot::Center,
+        };
+        self.paused = true;
+        self.pause_timer = self.config.time_break * 100;
+        info!(
+            target: GAME_LOG,
+            %team,
+            skaters,
+            "Paused, {} ({}) disconnected",
+            player_name,
+            player_index
+        );
+        let msg = format!(
+            "Game paused, {} left {} with {} skaters. Faceoff when an admin unpauses",
+            player_name, team, skaters
+        );
+        server.messages.add_server_chat_message(msg);
+    }
+
+    /// Gives a player who joined the position they picked in an earlier session
+    pub fn restore_position(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        let position = server
+            .players
+            .get(player_index)
+            .and_then(|player| server.preferences.get(&player.player_name))
+            .and_then(|x| x.position.as_deref())
+            .and_then(|position| ALLOWED_POSITIONS.into_iter().find(|x| *x == position));
+        if let Some(position) = position {
+            self.preferred_positions.insert(player_index, position);
+        }
+    }
+
+    /// The faceoff position a player picked with /sp
+    pub fn preferred_position(&self, player_index: HQMServerPlayerIndex) -> Option<&'static str> {
+        self.preferred_positions.get(&player_index).copied()
+    }
+
+    /// Gives a player the faceoff position they had before they left
+    pub fn set_preferred_position(
+        &mut self,
+        player_index: HQMServerPlayerIndex,
+        position: &'static str,
+    ) {
+        self.preferred_positions.insert(player_index, position);
+    }
+
+    /// The name of the team mate who holds a position, other than the players in except. Players
+    /// in the penalty box and players whose spot is held until they rejoin keep their positions
+    pub(crate) fn position_holder(
+        &self,
+        server: &HQMServer,
+        team: HQMTeam,
+        position: &str,
+        except: &[HQMServerPlayerIndex],
+    ) -> Option<Rc<String>> {
+        let holder = server
+            .players
+            .iter()
+            .find(|(player_index, player)| {
+                let player_team = match player.object {
+                    Some((_, t)) => Some(t),
+                    None => self.penalties.team(&player.player_name),
+                };
+                !except.contains(player_index)
+                    && !player.is_dummy()
+                    && player_team == Some(team)
+                    && self.preferred_positions.get(player_index) == Some(&position)
+            })
+            .map(|(_, player)| player.player_name.clone());
+        holder.or_else(|| {
+            self.held_positions
+                .iter()
+                .find(|(_, t, p)| *t == team && *p == position)
+                .map(|(player_name, _, _)| player_name.clone())
+        })
+    }
+
+    /// Keeps positions exclusive per team. A player who joins a team where their position is
+    /// taken loses it, and a position that is no longer held goes to the first player in line
+    pub fn update_positions(&mut self, server: &mut HQMServer) {
+        let teams = server
+            .players
+            .iter()
+            .filter(|(_, player)| !player.is_dummy())
+            .filter_map(|(player_index, player)| Some((player_index, player.object?.1)))
+            .collect();
+        let joined = self.position_claims.update_teams(teams);
+        for (i, &player_index) in joined.iter().enumerate() {
+            let (position, team) = match (
+                self.preferred_positions.get(&player_index),
+                server.players.get(player_index).and_then(|x| x.object),
+            ) {
+                (Some(position), Some((_, team))) => (*position, team),
+                _ => continue,
+            };
+            // Players who joined in the same step get their positions in the order of their
+            // index
+            let mut except = vec![player_index];
+            except.extend_from_slice(&joined[i + 1..]);
+            if let Some(holder_name) = self.position_holder(server, team, position, &except) {
+                self.preferred_positions.remove(&player_index);
+                let msg = format!(
+                    "{} is taken by {}, use /sp {} wait to get it when it's free",
+                    position, holder_name, position
+                );
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, player_index);
+            }
+        }
+
+        let waiting: Vec<_> = self.position_claims.waiting().collect();
+        for (player_index, team, position) in waiting {
+            if self.position_holder(server, team, position, &[]).is_some() {
+                continue;
+            }
+            if let Some(player) = server.players.get(player_index) {
+                info!(
+                    "{} ({}) got position {}",
+                    player.player_name, player_index, position
+                );
+                let msg = format!("{} position {}", player.player_name, position);
+                server.preferences.update(&player.player_name, |x| {
+                    x.position = Some(position.to_string())
+                });
+                self.preferred_positions.insert(player_index, position);
+                self.position_claims.remove(player_index);
+                server.messages.add_server_chat_message(msg);
+            }
+        }
+    }
+
+    pub fn cleanup_player(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        self.practice.remove_player(server, player_index);
         if let Some(x) = self
             .started_as_goalie
             .iter()
@@ -866,6 +2548,39 @@ impl HQMMatch {
             self.started_as_goalie.remove(x);
         }
         self.preferred_positions.remove(&player_index);
+        self.position_claims.remove(player_index);
+        self.hints.remove_player(player_index);
+        self.offsides.remove(&player_index);
+        self.ready.remove(&player_index);
+    }
+
+    /// Number of players on the ice of a team who are ready
+    pub(crate) fn ready_count(&self, server: &HQMServer, team: HQMTeam) -> u32 {
+        server
+            .players
+            .iter()
+            .filter(|(player_index, player)| {
+                matches!(player.object, Some((_, t)) if t == team)
+                    && self.ready.contains(player_index)
+            })
+            .count() as u32
+    }
+
+    /// Whether the warmup clock may run
+    pub fn teams_ready(&self, server: &HQMServer) -> bool {
+        self.forced_start
+            || teams_ready(
+                self.config.ready_players,
+                self.ready_count(server, HQMTeam::Red),
+                self.ready_count(server, HQMTeam::Blue),
+            )
+    }
+
+    /// Ends the warmup at once, whether the teams are ready or not
+    pub fn force_start(&mut self, server: &mut HQMServer) {
+        self.paused = false;
+        self.forced_start = true;
+        server.values.time = 1;
     }
 
     pub fn get_initial_game_values(&mut self) -> HQMInitialGameValues {
@@ -874,7 +2589,7 @@ impl HQMMatch {
         values.time = self.config.time_warmup * 100;
         HQMInitialGameValues {
             values,
-            puck_slots: self.config.warmup_pucks,
+            puck_slots: self.config.warmup_pucks.max(self.config.pucks_in_play),
             physics_configuration: self.config.physics_config.clone(),
         }
     }
@@ -882,26 +2597,86 @@ impl HQMMatch {
         self.paused = false;
         self.pause_timer = 0;
         self.next_faceoff_spot = HQMRinkFaceoffSpot::Center;
-        self.icing_status = HQMIcingStatus::No;
-        self.offside_status = HQMOffsideStatus::Neutral;
-        self.twoline_pass_status = HQMTwoLinePassStatus::No;
+        self.rules = HQMPuckRules::default();
+        self.rules_puck = None;
+        self.puck_rules.clear();
         self.start_next_replay = None;
-        let warmup_pucks = self.config.warmup_pucks;
-
-        let puck_line_start = server.world.rink.width / 2.0 - 0.4 * ((warmup_pucks - 1) as f32);
-
-        for i in 0..warmup_pucks {
-            let pos = Point3::new(
-                puck_line_start + 0.8 * (i as f32),
-                self.config.spawn_puck_altitude,
-                server.world.rink.length / 2.0,
+        self.goals.clear();
+        self.goal_streak.clear();
+        self.offsides.clear();
+        self.penalties.clear();
+        self.shootout = None;
+        self.set_ends_swapped(server, false);
+        self.ready.clear();
+        self.forced_start = false;
+        self.practice.clear();
+        self.power_play = None;
+        server.values.red_penalized = 0;
+        server.values.blue_penalized = 0;
+        server.world.rink.creases = self.config.creases.clone();
+        self.pending_drop = None;
+        self.drop_random = HQMDropRandom::new();
+        if self.config.faceoff_drop_delay > 0.0 {
+            info!(
+                target: GAME_LOG,
+                seed = self.drop_random.seed,
+                "Puck drops use seed {}",
+                self.drop_random.seed
             );
-            let rot = Rotation3::identity();
-            server.world.create_puck_object(pos, rot);
+            server.record_drop_seed(self.drop_random.seed);
         }
+        server
+            .world
+            .create_puck_line(self.config.warmup_pucks, self.config.spawn_puck_altitude);
+    }
+}
+
+/// Makes `current` the state of the given puck, taken from `others`, and puts the state of the
+/// puck `current` was for into `others`. Pucks that had no state yet start with the default
+fn select_puck_state<T: Default>(
+    current: &mut T,
+    current_puck: &mut Option<HQMObjectIndex>,
+    others: &mut HashMap<HQMObjectIndex, T>,
+    puck: HQMObjectIndex,
+) {
+    if *current_puck == Some(puck) {
+        return;
+    }
+    let state = others.remove(&puck).unwrap_or_default();
+    let previous = std::mem::replace(current, state);
+    if let Some(previous_puck) = current_puck.replace(puck) {
+        others.insert(previous_puck, previous);
     }
 }
 
+/// Gap between pucks dropped next to each other at a faceoff, in meters
+const PUCK_DROP_SPACING: f32 = 0.8;
+/// Distance in meters from the boards that pucks are dropped at
+const PUCK_DROP_MARGIN: f32 = 0.5;
+
+/// Where across the rink the pucks of a faceoff are dropped. They are dropped in a row across the
+/// faceoff spot, which is narrowed to fit the rink and moved away from the boards if needed
+fn puck_drop_xs(count: usize, center_x: f32, rink_width: f32) -> Vec<f32> {
+    let gaps = count.saturating_sub(1) as f32;
+    let room = (rink_width - 2.0 * PUCK_DROP_MARGIN).max(0.0);
+    let spacing = if gaps > 0.0 {
+        PUCK_DROP_SPACING.min(room / gaps)
+    } else {
+        0.0
+    };
+    let half = spacing * gaps / 2.0;
+    let center_x = center_x
+        .min(rink_width - PUCK_DROP_MARGIN - half)
+        .max(PUCK_DROP_MARGIN + half);
+    (0..count)
+        .map(|i| center_x - half + spacing * i as f32)
+        .collect()
+}
+
+fn teams_ready(ready_players: u32, red_ready: u32, blue_ready: u32) -> bool {
+    ready_players == 0 || (red_ready >= ready_players && blue_ready >= ready_players)
+}
+
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum HQMIcingConfiguration {
     Off,
@@ -925,6 +2700,48 @@ pub enum HQMTwoLinePassConfiguration {
     ThreeLine,
 }
 
+/// Whose view goal replays are shown from
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum HQMGoalReplayView {
+    /// The scorer, or the last player to touch the puck for own goals
+    Scorer,
+    /// The goalie who was scored on, if the team has one
+    Goalie,
+    /// No forced view, so that everyone keeps their own camera, which follows the puck for
+    /// spectators
+    Puck,
+}
+
+/// A named preset of rules. Rules that are left out aren't changed when it's applied
+#[derive(Debug, Clone)]
+pub struct HQMRuleset {
+    pub name: String,
+    pub icing: Option<HQMIcingConfiguration>,
+    pub offside: Option<HQMOffsideConfiguration>,
+    pub offside_line: Option<HQMOffsideLineConfiguration>,
+    pub twoline_pass: Option<HQMTwoLinePassConfiguration>,
+    pub mercy: Option<u32>,
+    pub first_to: Option<u32>,
+}
+
+/// Who may vote in votes started with /vote
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum HQMVoteVoters {
+    /// The players on the ice when the vote starts
+    Players,
+    /// Everyone connected when the vote starts, spectators included
+    Everyone,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum HQMGoalStreakReset {
+    /// Only a goal by the other team ends a streak
+    Never,
+    Period,
+    /// Faceoffs after stoppages and between periods, but not the faceoffs after goals
+    Faceoff,
+}
+
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
 pub enum HQMOffsideLineConfiguration {
     OffensiveBlue,
@@ -952,9 +2769,9 @@ pub struct HQMPass {
 
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum HQMIcingStatus {
-    No,                            // No icing
-    Warning(HQMTeam, HQMRinkSide), // Puck has reached the goal line, delayed icing
-    Icing(HQMTeam),                // Icing has been called
+    No,                                                  // No icing
+    Warning(HQMTeam, HQMRinkSide, HQMServerPlayerIndex), // Puck has reached the goal line, delayed icing
+    Icing(HQMTeam),                                      // Icing has been called
 }
 
 #[derive(PartialEq, Eq, Debug, Clone)]
@@ -977,11 +2794,107 @@ pub enum HQMTwoLinePassStatus {
         HQMTeam,
         HQMRinkSide,
         HQMPassPosition,
+        HQMServerPlayerIndex,
         Vec<HQMServerPlayerIndex>,
     ), // Warning, puck entered offensive zone in an offside situation but not touched yet
     Offside(HQMTeam), // Offside has been called
 }
 
+/// Whether the attempt of the shootout is over after a step, and if it was a goal. The attempt
+/// is a miss if the puck goes backwards, the shooter touches it after the goalie or the post, a
+/// team mate of the shooter touches it, or the time runs out
+fn shootout_attempt_result(
+    server: &mut HQMServer,
+    shootout: &mut HQMShootoutTiebreaker,
+    events: &[HQMSimulationEvent],
+) -> Option<bool> {
+    let attempt = match shootout.attempt.clone() {
+        Some(attempt) => attempt,
+        None => return Some(false),
+    };
+    for event in events {
+        match *event {
+            HQMSimulationEvent::PuckEnteredNet { team, puck } if puck == attempt.puck => {
+                return Some(team != attempt.team);
+            }
+            HQMSimulationEvent::PuckPassedGoalLine { puck, .. } if puck == attempt.puck => {
+                return Some(false);
+            }
+            HQMSimulationEvent::PuckTouch { player, puck } if puck == attempt.puck => {
+                if let Some((player_index, team, _)) = server.players.get_from_object_index(player)
+                {
+                    if team != attempt.team {
+                        if let HQMShootoutAttemptState::Attack { progress } = shootout.state {
+                            shootout.state = HQMShootoutAttemptState::NoMoreAttack {
+                                final_progress: progress,
+                            };
+                        }
+                    } else if player_index != attempt.shooter
+                        || matches!(shootout.state, HQMShootoutAttemptState::NoMoreAttack { .. })
+                    {
+                        return Some(false);
+                    }
+                }
+            }
+            HQMSimulationEvent::PuckTouchedNet { team, puck }
+                if puck == attempt.puck && team != attempt.team =>
+            {
+                if let HQMShootoutAttemptState::Attack { progress } = shootout.state {
+                    shootout.state = HQMShootoutAttemptState::NoMoreAttack {
+                        final_progress: progress,
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    server.values.time = server.values.time.saturating_sub(1);
+    if server.values.time == 0 {
+        // Keeps the clients from showing the intermission
+        server.values.time = 1;
+        return Some(false);
+    }
+    let puck = match server.world.objects.get_puck(attempt.puck) {
+        Some(puck) => puck,
+        None => return Some(false),
+    };
+    let rink = &server.world.rink;
+    // Red shoots at the net at the start of the rink
+    let net = match attempt.team {
+        HQMTeam::Red => &rink.blue_net,
+        HQMTeam::Blue => &rink.red_net,
+    };
+    let progress = (puck.body.pos - rink.center_ice()).dot(&rink.direction_to_net(net));
+    match shootout.state {
+        HQMShootoutAttemptState::Attack {
+            progress: current_progress,
+        } => {
+            if progress > current_progress {
+                shootout.state = HQMShootoutAttemptState::Attack { progress };
+            } else if progress - current_progress < -0.5 {
+                return Some(false);
+            }
+        }
+        HQMShootoutAttemptState::NoMoreAttack { final_progress } => {
+            if progress - final_progress < -5.0 {
+                return Some(false);
+            }
+        }
+        HQMShootoutAttemptState::Break { .. } => {}
+    }
+    None
+}
+
+/// A skater the puck went through, which is a hand pass if a team mate touches it next
+#[derive(Debug, Clone)]
+struct HQMBodyTouch {
+    player_index: HQMServerPlayerIndex,
+    team: HQMTeam,
+    side: HQMRinkSide,
+    in_own_zone: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct HQMPuckTouch {
     pub player_index: HQMServerPlayerIndex,
@@ -993,6 +2906,11 @@ pub struct HQMPuckTouch {
     pub last_time: u32,
 }
 
+/// Where a puck is on the ice, for the shot map
+fn ice_position(pos: &Point3<f32>) -> HQMIcePosition {
+    HQMIcePosition { x: pos.x, z: pos.z }
+}
+
 pub fn add_touch(
     puck: &HQMPuck,
     entry: Entry<HQMObjectIndex, VecDeque<HQMPuckTouch>>,
@@ -1065,20 +2983,101 @@ pub fn is_past_line(
     if let Some((object_index, skater_team)) = player.object {
         if skater_team == team {
             if let Some(skater) = server.world.objects.get_skater(object_index) {
-                let feet_pos =
-                    &skater.body.pos - (&skater.body.rot * Vector3::y().scale(skater.height));
-                if (team == HQMTeam::Red && line.side_of_line(&feet_pos, 0.0) == BlueSide)
-                    || (team == HQMTeam::Blue && line.side_of_line(&feet_pos, 0.0) == RedSide)
-                {
-                    // Player is past line
-                    return true;
-                }
+                return is_feet_past_line(&get_feet_pos(skater), team, line);
             }
         }
     }
     false
 }
 
+fn get_feet_pos(skater: &HQMSkater) -> Point3<f32> {
+    skater.body.pos - skater.body.rot * Vector3::y().scale(skater.height)
+}
+
+fn is_feet_past_line(feet_pos: &Point3<f32>, team: HQMTeam, line: &HQMRinkLine) -> bool {
+    (team == HQMTeam::Red && line.side_of_line(feet_pos, 0.0) == BlueSide)
+        || (team == HQMTeam::Blue && line.side_of_line(feet_pos, 0.0) == RedSide)
+}
+
+#[derive(Debug, Clone)]
+pub struct HQMSkaterZone {
+    pub player_index: HQMServerPlayerIndex,
+    pub team: HQMTeam,
+    pub feet_pos: Point3<f32>,
+    pub past_center_line: bool,
+    pub in_offensive_zone: bool,
+}
+
+/// Feet positions and zone membership of all skaters, computed once per tick
+/// so that offside and two-line pass checks don't have to walk the player list again
+#[derive(Debug, Clone, Default)]
+pub struct HQMSkaterZones {
+    pub skaters: Vec<HQMSkaterZone>,
+}
+
+impl HQMSkaterZones {
+    /// Ends swapped means that each team attacks the net it defends in the rink geometry
+    pub fn new(server: &HQMServer, ends_swapped: bool) -> Self {
+        let rink = &server.world.rink;
+        let mut skaters = vec![];
+        for (player_index, player) in server.players.iter() {
+            if let Some((object_index, team)) = player.object {
+                if let Some(skater) = server.world.objects.get_skater(object_index) {
+                    let feet_pos = get_feet_pos(skater);
+                    let end = if ends_swapped {
+                        team.get_other_team()
+                    } else {
+                        team
+                    };
+                    let offensive_line = match end {
+                        HQMTeam::Red => &rink.blue_zone_blue_line,
+                        HQMTeam::Blue => &rink.red_zone_blue_line,
+                    };
+                    skaters.push(HQMSkaterZone {
+                        player_index,
+                        team,
+                        feet_pos,
+                        past_center_line: is_feet_past_line(&feet_pos, end, &rink.center_line),
+                        in_offensive_zone: is_feet_past_line(&feet_pos, end, offensive_line),
+                    });
+                }
+            }
+        }
+        Self { skaters }
+    }
+
+    pub fn has_players_in_offensive_zone(
+        &self,
+        team: HQMTeam,
+        ignore_player: Option<HQMServerPlayerIndex>,
+    ) -> bool {
+        self.skaters
+            .iter()
+            .any(|x| x.team == team && x.in_offensive_zone && Some(x.player_index) != ignore_player)
+    }
+
+    pub fn players_past_line(
+        &self,
+        team: HQMTeam,
+        is_offensive_line: bool,
+        ignore_player: HQMServerPlayerIndex,
+    ) -> Vec<HQMServerPlayerIndex> {
+        self.skaters
+            .iter()
+            .filter(|x| {
+                x.team == team
+                    && x.player_index != ignore_player
+                    && if is_offensive_line {
+                        x.in_offensive_zone
+                    } else {
+                        x.past_center_line
+                    }
+            })
+            .map(|x| x.player_index)
+            .collect()
+    }
+}
+
 pub fn has_players_in_offensive_zone(
     server: &HQMServer,
     team: HQMTeam,
@@ -1167,43 +3166,27 @@ fn setup_position(
     }
 }
 
-fn get_faceoff_spot(
+pub(crate) fn get_faceoff_spot(
     rink: &HQMRink,
     spot: HQMRinkFaceoffSpot,
     spawn_point_offset: f32,
     spawn_player_altitude: f32,
 ) -> HQMFaceoffSpot {
-    let length = rink.length;
-    let width = rink.width;
+    let center_x = rink.width / 2.0;
 
     let red_rot = Rotation3::identity();
     let blue_rot = Rotation3::from_euler_angles(0.0, PI, 0.0);
-    let red_goalie_pos = Point3::new(width / 2.0, spawn_player_altitude, length - 5.0);
-    let blue_goalie_pos = Point3::new(width / 2.0, spawn_player_altitude, 5.0);
-
-    let goal_line_distance = 4.0; // IIHF rule 17iv
-
-    let blue_line_distance_neutral_zone_edge = rink.blue_zone_blue_line.z;
-    // IIHF specifies distance between end boards and edge closest to the neutral zone, but my code specifies middle of line
-    let distance_neutral_faceoff_spot = blue_line_distance_neutral_zone_edge + 1.5; // IIHF rule 18iv and 18vii
-    let distance_zone_faceoff_spot = goal_line_distance + 6.0; // IIHF rule 18vi and 18vii
-
-    let center_x = width / 2.0;
-    let left_faceoff_x = center_x - 7.0; // IIHF rule 18vi and 18iv
-    let right_faceoff_x = center_x + 7.0; // IIHF rule 18vi and 18iv
-
-    let red_zone_faceoff_z = length - distance_zone_faceoff_spot;
-    let red_neutral_faceoff_z = length - distance_neutral_faceoff_spot;
-    let center_z = length / 2.0;
-    let blue_neutral_faceoff_z = distance_neutral_faceoff_spot;
-    let blue_zone_faceoff_z = distance_zone_faceoff_spot;
-
-    let create_faceoff_spot = |center_position: Point3<f32>| {
-        let red_defensive_zone = center_position.z > length - 11.0;
-        let blue_defensive_zone = center_position.z < 11.0;
-        let (red_left, red_right) = if center_position.x < 9.0 {
+    let red_goalie_pos = rink.red_net.goalie_position(spawn_player_altitude);
+    let blue_goalie_pos = rink.blue_net.goalie_position(spawn_player_altitude);
+
+    // Spots further out than the default dots are close to the boards
+    let close_to_boards_x = 7.0f32.min(center_x - 4.0) - 1.0;
+
+    let create_faceoff_spot = |[x, z]: [f32; 2], red_defensive_zone, blue_defensive_zone| {
+        let center_position = Point3::new(x, 0.0, z);
+        let (red_left, red_right) = if center_position.x < center_x - close_to_boards_x {
             (true, false)
-        } else if center_position.x > width - 9.0 {
+        } else if center_position.x > center_x + close_to_boards_x {
             (false, true)
         } else {
             (false, false)
@@ -1353,29 +3336,23 @@ fn get_faceoff_spot(
         }
     };
 
+    let dots = &rink.faceoff_dots;
+    let side_index = |side| match side {
+        HQMRinkSide::Left => 0,
+        HQMRinkSide::Right => 1,
+    };
     match spot {
-        HQMRinkFaceoffSpot::Center => create_faceoff_spot(Point3::new(center_x, 0.0, center_z)),
-        HQMRinkFaceoffSpot::DefensiveZone(team, side) => {
-            let z = match team {
-                HQMTeam::Red => red_zone_faceoff_z,
-                HQMTeam::Blue => blue_zone_faceoff_z,
-            };
-            let x = match side {
-                HQMRinkSide::Left => left_faceoff_x,
-                HQMRinkSide::Right => right_faceoff_x,
-            };
-            create_faceoff_spot(Point3::new(x, 0.0, z))
-        }
+        HQMRinkFaceoffSpot::Center => create_faceoff_spot(dots.center, false, false),
+        HQMRinkFaceoffSpot::DefensiveZone(team, side) => match team {
+            HQMTeam::Red => create_faceoff_spot(dots.red_zone[side_index(side)], true, false),
+            HQMTeam::Blue => create_faceoff_spot(dots.blue_zone[side_index(side)], false, true),
+        },
         HQMRinkFaceoffSpot::Offside(team, side) => {
-            let z = match team {
-                HQMTeam::Red => red_neutral_faceoff_z,
-                HQMTeam::Blue => blue_neutral_faceoff_z,
-            };
-            let x = match side {
-                HQMRinkSide::Left => left_faceoff_x,
-                HQMRinkSide::Right => right_faceoff_x,
+            let dot = match team {
+                HQMTeam::Red => dots.red_neutral[side_index(side)],
+                HQMTeam::Blue => dots.blue_neutral[side_index(side)],
             };
-            create_faceoff_spot(Point3::new(x, 0.0, z))
+            create_faceoff_spot(dot, false, false)
         }
     }
 }
@@ -1385,21 +3362,51 @@ pub enum HQMSpawnPoint {
     Center,
     Bench,
 }
+/// Whether the skater of a player is in the goal crease of their team
+fn is_in_own_crease(server: &HQMServer, player_index: HQMServerPlayerIndex, team: HQMTeam) -> bool {
+    let skater = server
+        .players
+        .get(player_index)
+        .and_then(|player| player.object)
+        .and_then(|(object_index, _)| server.world.objects.get_skater(object_index));
+    match skater {
+        Some(skater) => {
+            let rink = &server.world.rink;
+            let net = match team {
+                HQMTeam::Red => &rink.red_net,
+                HQMTeam::Blue => &rink.blue_net,
+            };
+            rink.in_goal_crease(net, &skater.body.pos)
+        }
+        None => false,
+    }
+}
+
+/// Converts a puck speed in meters per step to km/h, or to mph if use_mph is set
+pub fn convert_puck_speed(puck_speed: f32, use_mph: bool) -> (f32, &'static str) {
+    if use_mph {
+        (puck_speed * 100f32 * 2.23693, "mph")
+    } else {
+        (puck_speed * 100f32 * 3.6, "km/h")
+    }
+}
+
 pub fn get_spawnpoint(
     rink: &HQMRink,
     team: HQMTeam,
     spawn_point: HQMSpawnPoint,
 ) -> (Point3<f32>, Rotation3<f32>) {
+    let center = rink.center_ice();
     match team {
         HQMTeam::Red => match spawn_point {
             HQMSpawnPoint::Center => {
-                let (z, rot) = ((rink.length / 2.0) + 3.0, 0.0);
-                let pos = Point3::new(rink.width / 2.0, 2.0, z);
+                let (z, rot) = (center.z + 3.0, 0.0);
+                let pos = Point3::new(center.x, 2.0, z);
                 let rot = Rotation3::from_euler_angles(0.0, rot, 0.0);
                 (pos, rot)
             }
             HQMSpawnPoint::Bench => {
-                let z = (rink.length / 2.0) + 4.0;
+                let z = center.z + 4.0;
                 let pos = Point3::new(0.5, 2.0, z);
                 let rot = Rotation3::from_euler_angles(0.0, 3.0 * FRAC_PI_2, 0.0);
                 (pos, rot)
@@ -1407,13 +3414,13 @@ pub fn get_spawnpoint(
         },
         HQMTeam::Blue => match spawn_point {
             HQMSpawnPoint::Center => {
-                let (z, rot) = ((rink.length / 2.0) - 3.0, PI);
-                let pos = Point3::new(rink.width / 2.0, 2.0, z);
+                let (z, rot) = (center.z - 3.0, PI);
+                let pos = Point3::new(center.x, 2.0, z);
                 let rot = Rotation3::from_euler_angles(0.0, rot, 0.0);
                 (pos, rot)
             }
             HQMSpawnPoint::Bench => {
-                let z = (rink.length / 2.0) - 4.0;
+                let z = center.z - 4.0;
                 let pos = Point3::new(0.5, 2.0, z);
                 let rot = Rotation3::from_euler_angles(0.0, 3.0 * FRAC_PI_2, 0.0);
                 (pos, rot)
@@ -1424,7 +3431,10 @@ pub fn get_spawnpoint(
 
 #[cfg(test)]
 mod tests {
-    use crate::hqm_match_util::setup_position;
+    use crate::hqm_game::HQMObjectIndex;
+    use crate::hqm_match_util::{
+        puck_drop_xs, select_puck_state, setup_position, teams_ready, HQMIcingStatus, HQMPuckRules,
+    };
     use crate::hqm_server::HQMServerPlayerIndex;
     use crate::hqm_server::HQMTeam;
     use std::collections::HashMap;
@@ -1500,4 +3510,45 @@ mod tests {
         assert_eq!(res1[&HQMServerPlayerIndex(0)].1, "C");
         assert_eq!(res1[&HQMServerPlayerIndex(1)].1, "LW");
     }
+
+    #[test]
+    fn teams_are_ready_when_both_have_enough_ready_players() {
+        assert!(teams_ready(0, 0, 0));
+        assert!(!teams_ready(2, 2, 1));
+        assert!(!teams_ready(2, 0, 3));
+        assert!(teams_ready(2, 2, 2));
+        assert!(teams_ready(2, 3, 2));
+    }
+
+    #[test]
+    fn pucks_are_dropped_on_the_ice() {
+        assert_eq!(puck_drop_xs(1, 7.0, 30.0), vec![7.0]);
+        assert_eq!(puck_drop_xs(3, 15.0, 30.0), vec![14.2, 15.0, 15.8]);
+        for (count, center_x) in [(30, 7.0), (64, 15.0), (64, 23.0)] {
+            let xs = puck_drop_xs(count, center_x, 30.0);
+            assert_eq!(xs.len(), count);
+            assert!(xs.iter().all(|x| (0.5..=29.5).contains(x)));
+            assert!(xs.windows(2).all(|pair| pair[1] > pair[0]));
+        }
+    }
+
+    #[test]
+    fn each_puck_keeps_its_rules() {
+        let (first, second) = (HQMObjectIndex(1), HQMObjectIndex(2));
+        let mut rules = HQMPuckRules::default();
+        let mut rules_puck = None;
+        let mut puck_rules = HashMap::new();
+        let mut select = |rules: &mut HQMPuckRules, puck| {
+            select_puck_state(rules, &mut rules_puck, &mut puck_rules, puck)
+        };
+        select(&mut rules, first);
+        rules.icing_status = HQMIcingStatus::Icing(HQMTeam::Red);
+        select(&mut rules, second);
+        assert_eq!(rules.icing_status, HQMIcingStatus::No);
+        rules.icing_status = HQMIcingStatus::Icing(HQMTeam::Blue);
+        select(&mut rules, first);
+        assert_eq!(rules.icing_status, HQMIcingStatus::Icing(HQMTeam::Red));
+        select(&mut rules, second);
+        assert_eq!(rules.icing_status, HQMIcingStatus::Icing(HQMTeam::Blue));
+    }
 }
@@ -0,0 +1,91 @@
+use std::path::{Path, PathBuf};
+
+use ini::Ini;
+use migo_hqm_server::hqm_autosave::HQMAutosaveFile;
+use tracing::warn;
+
+/// The mode and the settings that were changed while the server was running, such as rules set
+/// with /set. They are saved after every change and applied over the configuration file when
+/// the server starts again, unless it's started with --fresh.
+pub(crate) struct HQMModeState {
+    file: HQMAutosaveFile,
+    settings: Ini,
+}
+
+impl HQMModeState {
+    pub(crate) fn load(path: &str, fresh: bool) -> Self {
+        let mut file = HQMAutosaveFile::new(PathBuf::from(path));
+        let settings = if fresh {
+            Ini::new()
+        } else {
+            file.load(|contents| Ini::load_from_str(contents).ok())
+                .unwrap_or_default()
+        };
+        HQMModeState { file, settings }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.file.path()
+    }
+
+    /// The saved settings, or None if there are none to resume from
+    pub(crate) fn settings(&self) -> Option<&Ini> {
+        if self
+            .settings
+            .iter()
+            .all(|(_, properties)| properties.is_empty())
+        {
+            None
+        } else {
+            Some(&self.settings)
+        }
+    }
+
+    /// The mode the settings were saved in
+    pub(crate) fn mode(&self) -> Option<&str> {
+        self.settings.get_from(Some("Server"), "mode")
+    }
+
+    /// Records the mode the server runs in, so that a restart comes back in the same mode
+    pub(crate) fn set_mode(&mut self, mode: &str) {
+        self.set("Server", "mode", mode.to_string());
+    }
+
+    /// Records a changed setting and saves the file if it's different from what was saved
+    pub(crate) fn set(&mut self, section: &str, key: &str, value: String) {
+        if self.settings.get_from(Some(section), key) == Some(value.as_str()) {
+            return;
+        }
+        self.settings.with_section(Some(section)).set(key, value);
+        self.save();
+    }
+
+    /// Forgets the changed settings after the configuration file was reloaded, but keeps the mode
+    pub(crate) fn clear_settings(&mut self) {
+        let mode = self
+            .settings
+            .get_from(Some("Server"), "mode")
+            .map(String::from);
+        self.settings = Ini::new();
+        if let Some(mode) = mode {
+            self.settings.with_section(Some("Server")).set("mode", mode);
+        }
+        self.save();
+    }
+
+    /// Forgets the mode and the changed settings
+    pub(crate) fn clear(&mut self) {
+        self.settings = Ini::new();
+        self.save();
+    }
+
+    fn save(&mut self) {
+        let mut contents = vec![];
+        if let Err(e) = self.settings.write_to(&mut contents) {
+            warn!("Could not save {}: {}", self.file.path().display(), e);
+            return;
+        }
+        self.file
+            .save(String::from_utf8_lossy(&contents).into_owned());
+    }
+}
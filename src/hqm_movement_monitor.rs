@@ -0,0 +1,135 @@
+use nalgebra::Vector3;
+
+use crate::hqm_game::HQMPhysicsProfile;
+
+const WINDOW_TICKS: u32 = 100;
+
+/// Skating can't make a skater faster than the maximum speed, but checks and falls can. Only
+/// speeds this many times the maximum are anomalies
+const SPEED_MARGIN: f32 = 2.0;
+
+/// Collisions change the speed of a skater much faster than skating, so only speed gains this
+/// many times the highest acceleration are anomalies
+const ACCELERATION_MARGIN: f32 = 10.0;
+
+/// Ticks of a window that have to be anomalies for a strike, so single collisions don't count
+const ANOMALIES_PER_STRIKE: u32 = 50;
+
+/// Number of strikes before a player is reported to the admins
+const STRIKES_BEFORE_FLAG: u32 = 3;
+
+/// A window in which a skater kept moving faster than the physics allow
+pub(crate) struct HQMMovementStrike {
+    pub(crate) strikes: u32,
+    pub(crate) anomalies: u32,
+    /// Measured in meters per second
+    pub(crate) top_speed: f32,
+    /// Whether this strike is the one that gets the player reported
+    pub(crate) flag: bool,
+}
+
+/// Compares the movement of a skater with what skating can do, one window of ticks at a time
+pub(crate) struct HQMMovementMonitor {
+    last_speed: Option<f32>,
+    window_ticks: u32,
+    anomalies: u32,
+    top_speed: f32,
+    strikes: u32,
+    flagged: bool,
+}
+
+impl HQMMovementMonitor {
+    pub(crate) fn new() -> Self {
+        HQMMovementMonitor {
+            last_speed: None,
+            window_ticks: 0,
+            anomalies: 0,
+            top_speed: 0.0,
+            strikes: 0,
+            flagged: false,
+        }
+    }
+
+    /// Forgets the last speed, when the player has no skater or was moved by the server
+    pub(crate) fn reset(&mut self) {
+        self.last_speed = None;
+    }
+
+    /// Checks the velocity of the skater after a tick, and returns a strike at the end of a
+    /// window with too many anomalies
+    pub(crate) fn check(
+        &mut self,
+        velocity: &Vector3<f32>,
+        physics_config: &HQMPhysicsProfile,
+    ) -> Option<HQMMovementStrike> {
+        let speed = Vector3::new(velocity.x, 0.0, velocity.z).norm();
+        let max_speed = physics_config
+            .max_player_speed
+            .max(physics_config.max_player_shift_speed);
+        let max_acceleration = physics_config
+            .player_acceleration
+            .max(physics_config.player_deceleration)
+            .max(physics_config.player_shift_acceleration);
+        let too_fast = speed > max_speed * SPEED_MARGIN;
+        let speeding_up = self
+            .last_speed
+            .is_some_and(|last_speed| speed - last_speed > max_acceleration * ACCELERATION_MARGIN);
+        self.last_speed = Some(speed);
+        if too_fast || speeding_up {
+            self.anomalies += 1;
+        }
+        self.top_speed = self.top_speed.max(speed);
+        self.window_ticks += 1;
+        if self.window_ticks < WINDOW_TICKS {
+            return None;
+        }
+
+        let anomalies = self.anomalies;
+        let top_speed = self.top_speed;
+        self.window_ticks = 0;
+        self.anomalies = 0;
+        self.top_speed = 0.0;
+        if anomalies < ANOMALIES_PER_STRIKE {
+            return None;
+        }
+        self.strikes += 1;
+        let flag = self.strikes >= STRIKES_BEFORE_FLAG && !self.flagged;
+        if flag {
+            self.flagged = true;
+        }
+        Some(HQMMovementStrike {
+            strikes: self.strikes,
+            anomalies,
+            top_speed: top_speed * 100.0,
+            flag,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skating_at_full_speed_is_no_anomaly() {
+        let physics_config = HQMPhysicsProfile::classic();
+        let mut monitor = HQMMovementMonitor::new();
+        let velocity = Vector3::new(0.0, 0.0, physics_config.max_player_speed);
+        for _ in 0..1000 {
+            assert!(monitor.check(&velocity, &physics_config).is_none());
+        }
+    }
+
+    #[test]
+    fn is_flagged_once_after_strikes() {
+        let physics_config = HQMPhysicsProfile::classic();
+        let mut monitor = HQMMovementMonitor::new();
+        let velocity = Vector3::new(0.0, 0.0, physics_config.max_player_speed * 3.0);
+        let strikes: Vec<_> = (0..WINDOW_TICKS * 5)
+            .filter_map(|_| monitor.check(&velocity, &physics_config))
+            .collect();
+        assert_eq!(strikes.len(), 5);
+        let flags: Vec<_> = strikes.iter().map(|strike| strike.flag).collect();
+        assert_eq!(flags, [false, false, true, false, false]);
+    }
+}
@@ -0,0 +1,222 @@
+use unicode_normalization::UnicodeNormalization;
+
+/// Longest name the client can show
+const MAX_NAME_LENGTH: usize = 31;
+
+/// Characters that are not visible, so that names with them look like names without them
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}' | '\u{034F}' | '\u{200B}'..='\u{200F}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}'
+        )
+}
+
+/// Returns the Latin letter that a Cyrillic or Greek letter can't be told apart from, so that
+/// "mіgo" with a Cyrillic і is the same name as "migo"
+fn fold_homoglyph(c: char) -> char {
+    match c {
+        'а' | 'α' => 'a',
+        'ь' => 'b',
+        'с' | 'ϲ' => 'c',
+        'ԁ' => 'd',
+        'е' | 'ε' => 'e',
+        'ɡ' => 'g',
+        'һ' => 'h',
+        'і' | 'ι' | 'ı' => 'i',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'ո' => 'n',
+        'о' | 'ο' | 'σ' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ѕ' => 's',
+        'т' | 'τ' => 't',
+        'υ' => 'u',
+        'ν' => 'v',
+        'ԝ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'γ' => 'y',
+        c => c,
+    }
+}
+
+/// Returns the form of a name that is used to compare names. Names that look the same compare
+/// equal: compatibility characters like full-width letters become their plain form (NFKC),
+/// case is folded, Cyrillic and Greek letters that look like Latin letters become those,
+/// invisible characters are removed and whitespace is trimmed and collapsed.
+pub(crate) fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    let mut pending_space = false;
+    for c in name.nfkc().flat_map(char::to_lowercase).map(fold_homoglyph) {
+        if c.is_whitespace() {
+            pending_space = !normalized.is_empty();
+        } else if !is_invisible(c) {
+            if pending_space {
+                normalized.push(' ');
+                pending_space = false;
+            }
+            normalized.push(c);
+        }
+    }
+    normalized
+}
+
+/// A name with % as wildcard at the start, the end or both, as used by /kickall
+pub(crate) struct HQMNamePattern {
+    text: String,
+    any_start: bool,
+    any_end: bool,
+}
+
+impl HQMNamePattern {
+    /// Returns None if the pattern has nothing but wildcards, which would match every name
+    pub(crate) fn parse(pattern: &str) -> Option<Self> {
+        let pattern = normalize_name(pattern);
+        let rest = pattern.strip_prefix('%');
+        let any_start = rest.is_some();
+        let rest = rest.unwrap_or(&pattern);
+        let text = rest.strip_suffix('%');
+        let any_end = text.is_some();
+        let text = text.unwrap_or(rest).trim();
+        if text.is_empty() || text.chars().all(|c| c == '%') {
+            return None;
+        }
+        Some(HQMNamePattern {
+            text: text.to_string(),
+            any_start,
+            any_end,
+        })
+    }
+
+    /// True if the pattern has no wildcards
+    pub(crate) fn is_exact(&self) -> bool {
+        !self.any_start && !self.any_end
+    }
+
+    /// How names are matched, like "begin with"
+    pub(crate) fn description(&self) -> &'static str {
+        match (self.any_start, self.any_end) {
+            (true, true) => "contain",
+            (true, false) => "end with",
+            (false, true) => "begin with",
+            (false, false) => "match",
+        }
+    }
+
+    /// Names are compared in normalized form, so that names that look the same match
+    pub(crate) fn matches(&self, name: &str) -> bool {
+        let name = normalize_name(name);
+        match (self.any_start, self.any_end) {
+            (true, true) => name.contains(&self.text),
+            (true, false) => name.ends_with(&self.text),
+            (false, true) => name.starts_with(&self.text),
+            (false, false) => name == self.text,
+        }
+    }
+}
+
+/// Number of characters that have to be inserted, removed or replaced to turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Returns the name that is closest to a name that didn't match, if it's close enough to be
+/// a typo. About every third character can be wrong.
+pub(crate) fn closest_name<'a>(
+    name: &str,
+    names: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let name = normalize_name(name);
+    let max_distance = (name.chars().count() / 3).max(1);
+    names
+        .into_iter()
+        .map(|candidate| (edit_distance(&name, &normalize_name(candidate)), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Adds a number to a name that is already taken, like "migo (2)", so that players with names
+/// that look the same can be told apart
+pub(crate) fn unique_name(name: String, is_taken: impl Fn(&str) -> bool) -> String {
+    if !is_taken(&normalize_name(&name)) {
+        return name;
+    }
+    (2..)
+        .map(|n| {
+            let suffix = format!(" ({})", n);
+            let mut end = MAX_NAME_LENGTH.saturating_sub(suffix.len()).min(name.len());
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            format!("{}{}", name[..end].trim_end(), suffix)
+        })
+        .find(|candidate| !is_taken(&normalize_name(candidate)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_that_look_the_same_are_equal() {
+        assert_eq!(normalize_name("  Migo "), "migo");
+        assert_eq!(normalize_name("ＭＩＧＯ"), "migo");
+        assert_eq!(normalize_name("mi\u{200B}go"), "migo");
+        assert_eq!(normalize_name("big   migo"), "big migo");
+        // Composed and decomposed accents
+        assert_eq!(
+            normalize_name("Jos\u{00E9}"),
+            normalize_name("Jose\u{0301}")
+        );
+        // Cyrillic а, о and Greek ο
+        assert_eq!(normalize_name("Migo"), normalize_name("Mig\u{043E}"));
+        assert_eq!(normalize_name("p\u{0430}ss"), "pass");
+        assert_eq!(normalize_name("g\u{03BF}al"), "goal");
+    }
+
+    #[test]
+    fn wildcards_match_the_start_and_end_of_names() {
+        let matches = |pattern: &str| HQMNamePattern::parse(pattern).unwrap().matches("MigoMipo");
+        assert!(matches("migomipo"));
+        assert!(matches("migo%"));
+        assert!(matches("%mipo"));
+        assert!(matches("%gomi%"));
+        assert!(!matches("%migo"));
+        assert!(!matches("mipo%"));
+        assert!(!matches("migo"));
+        // Only wildcards would match everyone
+        assert!(HQMNamePattern::parse("%").is_none());
+        assert!(HQMNamePattern::parse("%%").is_none());
+        assert!(HQMNamePattern::parse(" % % ").is_none());
+    }
+
+    #[test]
+    fn close_names_are_suggested() {
+        let names = ["migo", "Hockeyplayer", "someone"];
+        assert_eq!(closest_name("mgio", names), None);
+        assert_eq!(closest_name("mig", names), Some("migo"));
+        assert_eq!(closest_name("hockeyplyer", names), Some("Hockeyplayer"));
+        assert_eq!(closest_name("xyz", names), None);
+    }
+
+    #[test]
+    fn taken_names_get_a_number() {
+        let taken = |name: &str| name == "migo" || name == "migo (2)";
+        assert_eq!(unique_name("other".to_string(), taken), "other");
+        assert_eq!(unique_name("MIGO".to_string(), taken), "MIGO (3)");
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Instant;
+
+/// Seconds above a limit before a player is warned
+pub(crate) const WARN_SECONDS: u32 = 10;
+/// Seconds above a limit before a player is kicked, if kicking is on
+pub(crate) const KICK_SECONDS: u32 = 30;
+
+/// Connection limits for the players, 0 disables a limit
+#[derive(Debug, Clone)]
+pub struct HQMNetworkLimits {
+    /// Average ping in milliseconds
+    pub ping: u32,
+    /// Jitter in milliseconds
+    pub jitter: u32,
+    /// Whether players who stay above a limit are kicked, not only warned
+    pub kick: bool,
+}
+
+/// Tracks how evenly the updates of a client arrive. Packet loss isn't tracked: a client only
+/// acknowledges the last packet it received, so packet numbers it skips tell how often it
+/// sends updates, not which packets were lost
+pub(crate) struct HQMNetworkQuality {
+    last_arrival: Option<Instant>,
+    last_interval: Option<f32>,
+    /// Smoothed like RTP jitter, measured in seconds
+    jitter: f32,
+    /// Seconds in a row above a limit
+    over_limit: u32,
+}
+
+impl HQMNetworkQuality {
+    pub(crate) fn new() -> Self {
+        HQMNetworkQuality {
+            last_arrival: None,
+            last_interval: None,
+            jitter: 0.0,
+            over_limit: 0,
+        }
+    }
+
+    /// Records an update of the client
+    pub(crate) fn update(&mut self, time_received: Instant) {
+        if let Some(last_arrival) = self.last_arrival {
+            let interval = time_received
+                .saturating_duration_since(last_arrival)
+                .as_secs_f32();
+            if let Some(last_interval) = self.last_interval {
+                let difference = (interval - last_interval).abs();
+                self.jitter += (difference - self.jitter) / 16.0;
+            }
+            self.last_interval = Some(interval);
+        }
+        self.last_arrival = Some(time_received);
+    }
+
+    /// Measured in seconds
+    pub(crate) fn jitter(&self) -> f32 {
+        self.jitter
+    }
+
+    /// Counts another second above or below the limits, and returns the seconds in a row
+    /// above them
+    pub(crate) fn count_over_limit(&mut self, over_limit: bool) -> u32 {
+        self.over_limit = if over_limit { self.over_limit + 1 } else { 0 };
+        self.over_limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn steady_updates_have_no_jitter() {
+        // 60 updates per second, slower than the server sends packets
+        let mut quality = HQMNetworkQuality::new();
+        let start = Instant::now();
+        for i in 0..400u64 {
+            quality.update(start + Duration::from_micros(i * 16667));
+        }
+        assert!(quality.jitter() < 0.001);
+    }
+
+    #[test]
+    fn uneven_updates_have_jitter() {
+        let mut quality = HQMNetworkQuality::new();
+        let mut time = Instant::now();
+        for interval in [5, 25].into_iter().cycle().take(400) {
+            time += Duration::from_millis(interval);
+            quality.update(time);
+        }
+        assert!((quality.jitter() - 0.02).abs() < 0.001);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::hqm_parse::HQMObjectPacket;
+
+/// Number of object slots in the network protocol
+pub(crate) const NETWORK_OBJECT_SLOTS: usize = 32;
+
+/// Object index shown in each network slot of a packet
+pub(crate) type HQMObjectSlots = [Option<usize>; NETWORK_OBJECT_SLOTS];
+
+/// Objects that were sent in the previous packet count as this much closer, so that objects
+/// at about the same distance don't keep replacing each other
+const SELECTED_OBJECT_BIAS: f32 = 0.8;
+
+pub(crate) fn object_position(packet: &HQMObjectPacket) -> Option<(u32, u32, u32)> {
+    match packet {
+        HQMObjectPacket::Puck(puck) => Some(puck.pos),
+        HQMObjectPacket::Skater(skater) => Some(skater.pos),
+        HQMObjectPacket::None => None,
+    }
+}
+
+fn distance(a: (u32, u32, u32), b: (u32, u32, u32)) -> f32 {
+    let dx = a.0 as f32 - b.0 as f32;
+    let dy = a.1 as f32 - b.1 as f32;
+    let dz = a.2 as f32 - b.2 as f32;
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+/// Chooses which objects a client sees when the world has more objects than the protocol has slots.
+/// Skaters always keep their own slot, because clients link players to skaters by slot. The other
+/// slots go to the pucks closest to the position the client is viewing, or the pucks with the
+/// lowest index if there is no such position. A puck stays in the same slot while it's selected.
+pub(crate) fn select_objects(
+    packets: &[HQMObjectPacket],
+    view_position: Option<(u32, u32, u32)>,
+    previous: Option<&HQMObjectSlots>,
+) -> HQMObjectSlots {
+    let mut slots = [None; NETWORK_OBJECT_SLOTS];
+    let mut free_slots = 0;
+    for (slot, packet) in packets.iter().take(NETWORK_OBJECT_SLOTS).enumerate() {
+        if let HQMObjectPacket::Skater(_) = packet {
+            slots[slot] = Some(slot);
+        } else {
+            free_slots += 1;
+        }
+    }
+    let previous_slot = |object_index: usize| {
+        previous.and_then(|previous| {
+            previous
+                .iter()
+                .position(|&selected| selected == Some(object_index))
+        })
+    };
+
+    let mut candidates: Vec<(f32, usize)> = packets
+        .iter()
+        .enumerate()
+        .filter_map(|(object_index, packet)| {
+            let pos = match packet {
+                HQMObjectPacket::Puck(puck) => puck.pos,
+                _ => return None,
+            };
+            let mut score = match view_position {
+                Some(view_position) => distance(pos, view_position),
+                None => object_index as f32,
+            };
+            if previous_slot(object_index).is_some() {
+                score *= SELECTED_OBJECT_BIAS;
+            }
+            Some((score, object_index))
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+    candidates.truncate(free_slots);
+
+    // Keep objects in the slot they had in the previous packet, or their own slot
+    let mut unplaced = vec![];
+    for (_, object_index) in candidates {
+        let own_slot = if object_index < NETWORK_OBJECT_SLOTS {
+            Some(object_index)
+        } else {
+            None
+        };
+        let slot = [previous_slot(object_index), own_slot]
+            .into_iter()
+            .flatten()
+            .find(|&slot| slots[slot].is_none());
+        match slot {
+            Some(slot) => slots[slot] = Some(object_index),
+            None => unplaced.push(object_index),
+        }
+    }
+    let empty_slots: Vec<usize> = (0..NETWORK_OBJECT_SLOTS)
+        .filter(|&slot| slots[slot].is_none())
+        .collect();
+    for (slot, object_index) in empty_slots.into_iter().zip(unplaced) {
+        slots[slot] = Some(object_index);
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hqm_parse::{HQMPuckPacket, HQMSkaterPacket};
+
+    /// Two skaters and 40 pucks, puck `i` at x = 100 * i
+    fn packets() -> Vec<HQMObjectPacket> {
+        let skater = HQMObjectPacket::Skater(HQMSkaterPacket {
+            pos: (0, 0, 0),
+            rot: (0, 0),
+            stick_pos: (0, 0, 0),
+            stick_rot: (0, 0),
+            head_rot: 0,
+            body_rot: 0,
+        });
+        let pucks = (2..42).map(|i| {
+            HQMObjectPacket::Puck(HQMPuckPacket {
+                pos: (100 * i, 0, 0),
+                rot: (0, 0),
+            })
+        });
+        [skater.clone(), skater].into_iter().chain(pucks).collect()
+    }
+
+    fn selected(slots: &HQMObjectSlots) -> Vec<usize> {
+        let mut selected: Vec<usize> = slots.iter().flatten().copied().collect();
+        selected.sort();
+        selected
+    }
+
+    #[test]
+    fn closest_pucks_are_selected() {
+        let packets = packets();
+        let slots = select_objects(&packets, None, None);
+        assert_eq!(slots, std::array::from_fn(Some));
+
+        let slots = select_objects(&packets, Some((4100, 0, 0)), None);
+        assert_eq!(&slots[..2], &[Some(0), Some(1)]);
+        assert_eq!(
+            selected(&slots),
+            [0, 1].into_iter().chain(12..42).collect::<Vec<_>>()
+        );
+        // Pucks keep their own slot when they can, the others fill the slots left over, closest
+        // first
+        assert_eq!(slots[20], Some(20));
+        assert_eq!(slots[2], Some(41));
+        assert_eq!(slots[11], Some(32));
+    }
+
+    #[test]
+    fn selected_pucks_stay_selected_in_the_same_slot() {
+        let packets = packets();
+        let previous = select_objects(&packets, Some((4100, 0, 0)), None);
+
+        // Puck 11 is a bit closer than puck 41 now, but not enough to replace it
+        let view_position = Some((2560, 0, 0));
+        let fresh = select_objects(&packets, view_position, None);
+        assert!(fresh.contains(&Some(11)) && !fresh.contains(&Some(41)));
+        let slots = select_objects(&packets, view_position, Some(&previous));
+        assert_eq!(slots, previous);
+    }
+}
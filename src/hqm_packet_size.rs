@@ -0,0 +1,80 @@
+use std::collections::VecDeque;
+
+/// Fits in the smallest MTU that IPv4 hosts have to accept, 576 bytes, after the IP and UDP headers
+pub(crate) const MIN_PACKET_SIZE: usize = 548;
+
+/// Number of packets a client can fall behind before the packet size limit is lowered
+const STALL_PACKETS: u32 = 50;
+
+/// Finds out how large update packets to a client can be. Starting from the configured maximum,
+/// the limit is lowered whenever the client stops acknowledging packets while larger packets
+/// than it has ever acknowledged are being sent. Only chat and player messages can be moved to
+/// later packets, so the limit decides how many of them are sent at once
+pub(crate) struct HQMPacketSizeProbe {
+    limit: usize,
+    /// Sizes of the last sent packets, newest first
+    sent: VecDeque<usize>,
+    largest_delivered: usize,
+    last_reduction: Option<u32>,
+}
+
+impl HQMPacketSizeProbe {
+    pub(crate) fn new(limit: usize) -> Self {
+        HQMPacketSizeProbe {
+            limit,
+            sent: VecDeque::with_capacity(192),
+            largest_delivered: 0,
+            last_reduction: None,
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub(crate) fn record_sent(&mut self, size: usize) {
+        self.sent.truncate(192 - 1);
+        self.sent.push_front(size);
+    }
+
+    /// Called when the client acknowledges a packet. Returns the new limit if it was lowered
+    pub(crate) fn update(&mut self, current_packet: u32, known_packet: u32) -> Option<usize> {
+        let behind = if known_packet == u32::MAX {
+            self.sent.len() as u32
+        } else {
+            let behind = current_packet.wrapping_sub(known_packet);
+            if let Some(&size) = self.sent.get(behind as usize) {
+                self.largest_delivered = self.largest_delivered.max(size);
+            }
+            behind
+        };
+        if behind < STALL_PACKETS || self.limit <= MIN_PACKET_SIZE {
+            return None;
+        }
+        if let Some(last_reduction) = self.last_reduction {
+            if current_packet.wrapping_sub(last_reduction) < STALL_PACKETS {
+                return None;
+            }
+        }
+        let largest_sent = self
+            .sent
+            .iter()
+            .take(behind as usize)
+            .copied()
+            .max()
+            .unwrap_or(0);
+        if largest_sent <= self.largest_delivered {
+            // The packets aren't larger than ones that got through, so their size isn't the problem
+            return None;
+        }
+        let limit = (self.limit * 3 / 4)
+            .max(self.largest_delivered)
+            .max(MIN_PACKET_SIZE);
+        if limit >= self.limit {
+            return None;
+        }
+        self.limit = limit;
+        self.last_reduction = Some(current_packet);
+        Some(limit)
+    }
+}
@@ -1,6 +1,11 @@
+use crate::hqm_boards::RINK_INFO;
+use crate::hqm_checksum::{WORLD_CHECKSUM, WORLD_CHECKSUM_REPORT};
+use crate::hqm_extended_rules::EXTENDED_RULES;
 use crate::hqm_game::{HQMGameObject, HQMPlayerInput};
+use crate::hqm_object_selection::{HQMObjectSlots, NETWORK_OBJECT_SLOTS};
+use crate::hqm_quick_chat::SOUND_CUE;
+use crate::hqm_replay_stream::REPLAY_STREAM;
 use crate::hqm_server::{HQMClientVersion, HQMMessage};
-use arr_macro::arr;
 use bytes::{BufMut, BytesMut};
 use nalgebra::storage::Storage;
 use nalgebra::{Matrix3, Vector2, Vector3, U1, U3};
@@ -48,6 +53,26 @@ pub enum HQMClientToServerMessage {
         version: u32,
         ping: u32,
     },
+    RinkInfo {
+        version: u32,
+    },
+    WorldChecksum {
+        version: u32,
+    },
+    ExtendedRules {
+        version: u32,
+    },
+    WorldChecksumReport {
+        game_step: u32,
+        checksum: u32,
+    },
+    ReplayStream {
+        version: u32,
+        rate: u32,
+    },
+    SoundCues {
+        version: u32,
+    },
 }
 
 pub struct HQMMessageCodec;
@@ -70,7 +95,34 @@ impl HQMMessageCodec {
             4 => self.parse_player_update(&mut parser, HQMClientVersion::Vanilla),
             8 => self.parse_player_update(&mut parser, HQMClientVersion::Ping),
             0x10 => self.parse_player_update(&mut parser, HQMClientVersion::PingRules),
+            0x20 => self.parse_player_update(&mut parser, HQMClientVersion::PingRulesCompression),
             7 => Ok(HQMClientToServerMessage::Exit),
+            RINK_INFO => self.parse_request_rink_info(&mut parser),
+            WORLD_CHECKSUM => {
+                let version = parser.read_u32_aligned();
+                Ok(HQMClientToServerMessage::WorldChecksum { version })
+            }
+            EXTENDED_RULES => {
+                let version = parser.read_u32_aligned();
+                Ok(HQMClientToServerMessage::ExtendedRules { version })
+            }
+            WORLD_CHECKSUM_REPORT => {
+                let game_step = parser.read_u32_aligned();
+                let checksum = parser.read_u32_aligned();
+                Ok(HQMClientToServerMessage::WorldChecksumReport {
+                    game_step,
+                    checksum,
+                })
+            }
+            REPLAY_STREAM => {
+                let version = parser.read_u32_aligned();
+                let rate = parser.read_u32_aligned();
+                Ok(HQMClientToServerMessage::ReplayStream { version, rate })
+            }
+            SOUND_CUE => {
+                let version = parser.read_u32_aligned();
+                Ok(HQMClientToServerMessage::SoundCues { version })
+            }
             _ => Err(HQMClientToServerMessageDecoderError::UnknownType),
         }
     }
@@ -84,6 +136,14 @@ impl HQMMessageCodec {
         Ok(HQMClientToServerMessage::ServerInfo { version, ping })
     }
 
+    fn parse_request_rink_info(
+        &self,
+        parser: &mut HQMMessageReader,
+    ) -> Result<HQMClientToServerMessage, HQMClientToServerMessageDecoderError> {
+        let version = parser.read_u32_aligned();
+        Ok(HQMClientToServerMessage::RinkInfo { version })
+    }
+
     fn parse_player_join(
         &self,
         parser: &mut HQMMessageReader,
@@ -325,7 +385,6 @@ impl<'a> HQMMessageWriter<'a> {
         self.buf.put_u32_le(v);
     }
 
-    #[allow(dead_code)]
     pub fn write_f32_aligned(&mut self, v: f32) {
         self.write_u32_aligned(f32::to_bits(v));
     }
@@ -377,6 +436,10 @@ impl<'a> HQMMessageWriter<'a> {
         }
     }
 
+    pub fn bytes_written(&self) -> usize {
+        self.buf.len()
+    }
+
     pub fn replay_fix(&mut self) {
         if self.bit_pos == 0 {
             self.buf.put_u8(0);
@@ -400,6 +463,10 @@ impl<'a> HQMMessageReader<'a> {
         self.pos
     }
 
+    pub(crate) fn get_bit_pos(&self) -> u8 {
+        self.bit_pos
+    }
+
     fn safe_get_byte(&self, pos: usize) -> u8 {
         if pos < self.buf.len() {
             self.buf[pos]
@@ -559,6 +626,15 @@ pub(crate) struct HQMPuckPacket {
     pub rot: (u32, u32),
 }
 
+/// Size of a message written by [`write_message`], in bits
+pub(crate) fn message_bits(message: &HQMMessage) -> usize {
+    match message {
+        HQMMessage::Chat { message, .. } => 18 + 7 * min(63, message.len()),
+        HQMMessage::Goal { .. } => 20,
+        HQMMessage::PlayerUpdate { .. } => 21 + 7 * 31,
+    }
+}
+
 pub(crate) fn write_message(writer: &mut HQMMessageWriter, message: &HQMMessage) {
     match message {
         HQMMessage::Chat {
@@ -632,15 +708,51 @@ pub(crate) fn write_message(writer: &mut HQMMessageWriter, message: &HQMMessage)
     };
 }
 
+/// Writes the objects in the network slots. Without a slot history, slot i holds object i
 pub(crate) fn write_objects(
     writer: &mut HQMMessageWriter,
-    packets: &VecDeque<[HQMObjectPacket; 32]>,
+    packets: &VecDeque<Vec<HQMObjectPacket>>,
+    slots: Option<&VecDeque<HQMObjectSlots>>,
     current_packet: u32,
     known_packet: u32,
 ) {
-    let current_packets = packets[0].as_slice();
+    write_delayed_objects(writer, packets, 0, slots, current_packet, known_packet);
+}
+
+/// The packet `history_index` packets before the current one of a feed that is `delay` packets
+/// behind. Until the game has gone on for the delay, a delayed feed stays at the oldest packet,
+/// so that the delay is the same from the start of the game
+pub(crate) fn delayed_packet<T>(
+    packets: &VecDeque<T>,
+    delay: usize,
+    history_index: usize,
+) -> Option<&T> {
+    if delay > 0 && delay + history_index >= packets.len() {
+        packets.back()
+    } else {
+        packets.get(delay + history_index)
+    }
+}
 
-    let old_packets = {
+/// Like [write_objects], but writes the objects of `delay` packets ago as the current packet.
+/// The packets the client knows must have been written with the same delay
+pub(crate) fn write_delayed_objects(
+    writer: &mut HQMMessageWriter,
+    packets: &VecDeque<Vec<HQMObjectPacket>>,
+    delay: usize,
+    slots: Option<&VecDeque<HQMObjectSlots>>,
+    current_packet: u32,
+    known_packet: u32,
+) {
+    let object_in_slot = |history_index: usize, slot: usize| {
+        let object_index = match slots {
+            Some(slots) => slots.get(history_index)?[slot]?,
+            None => slot,
+        };
+        delayed_packet(packets, delay, history_index)?.get(object_index)
+    };
+
+    let old_index = {
         let diff = if known_packet == u32::MAX {
             None
         } else {
@@ -649,7 +761,7 @@ pub(crate) fn write_objects(
         if let Some(diff) = diff {
             let index = diff as usize;
             if index < 192 && index > 0 {
-                packets.get(index)
+                Some(index)
             } else {
                 None
             }
@@ -661,9 +773,9 @@ pub(crate) fn write_objects(
     writer.write_u32_aligned(current_packet);
     writer.write_u32_aligned(known_packet);
 
-    for i in 0..32 {
-        let current_packet = &current_packets[i];
-        let old_packet = old_packets.map(|x| &x[i]);
+    for i in 0..NETWORK_OBJECT_SLOTS {
+        let current_packet = object_in_slot(0, i).unwrap_or(&HQMObjectPacket::None);
+        let old_packet = old_index.and_then(|index| object_in_slot(index, i));
         match current_packet {
             HQMObjectPacket::Puck(puck) => {
                 let old_puck = old_packet.and_then(|x| match x {
@@ -733,15 +845,79 @@ pub(crate) fn write_objects(
     }
 }
 
-pub(crate) fn get_packets(objects: &[HQMGameObject]) -> [HQMObjectPacket; 32] {
-    let mut packets = arr![HQMObjectPacket::None; 32];
-    for i in 0usize..32 {
-        let packet = match &objects[i] {
+pub(crate) fn get_packets(objects: &[HQMGameObject]) -> Vec<HQMObjectPacket> {
+    objects
+        .iter()
+        .map(|object| match object {
             HQMGameObject::Puck(puck) => HQMObjectPacket::Puck(puck.get_packet()),
             HQMGameObject::Player(player) => HQMObjectPacket::Skater(player.get_packet()),
             HQMGameObject::None => HQMObjectPacket::None,
-        };
-        packets[i] = packet;
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puck_packets(count: u32) -> VecDeque<Vec<HQMObjectPacket>> {
+        // Newest first, like the packets the server keeps
+        (0..count)
+            .rev()
+            .map(|i| {
+                vec![HQMObjectPacket::Puck(HQMPuckPacket {
+                    pos: (1000 + i * 10, 200, 3000),
+                    rot: (0, 0),
+                })]
+            })
+            .collect()
+    }
+
+    fn written(
+        packets: &VecDeque<Vec<HQMObjectPacket>>,
+        delay: usize,
+        current_packet: u32,
+        known_packet: u32,
+    ) -> BytesMut {
+        let mut buf = BytesMut::new();
+        let mut writer = HQMMessageWriter::new(&mut buf);
+        write_delayed_objects(
+            &mut writer,
+            packets,
+            delay,
+            None,
+            current_packet,
+            known_packet,
+        );
+        buf
+    }
+
+    #[test]
+    fn delayed_objects_are_the_objects_of_earlier_packets() {
+        let packets = puck_packets(6);
+        let mut earlier = packets.clone();
+        earlier.drain(..2);
+        assert_eq!(written(&packets, 2, 20, 19), written(&earlier, 0, 20, 19));
+        assert_eq!(
+            written(&packets, 2, 20, u32::MAX),
+            written(&earlier, 0, 20, u32::MAX)
+        );
+        assert_ne!(written(&packets, 0, 20, 19), written(&earlier, 0, 20, 19));
+    }
+
+    #[test]
+    fn delayed_feed_waits_at_the_start_of_the_game() {
+        let mut packets = VecDeque::new();
+        for i in 0..4 {
+            packets.push_front(i);
+            // The feed stays at the first packet until the game has gone on for the delay
+            assert_eq!(delayed_packet(&packets, 3, 0), Some(&0));
+            assert_eq!(delayed_packet(&packets, 3, 1), Some(&0));
+        }
+        packets.push_front(4);
+        assert_eq!(delayed_packet(&packets, 3, 0), Some(&1));
+        assert_eq!(delayed_packet(&packets, 3, 1), Some(&0));
+        assert_eq!(delayed_packet(&packets, 0, 0), Some(&4));
+        assert_eq!(delayed_packet(&packets, 0, 5), None);
     }
-    packets
 }
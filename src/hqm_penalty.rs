@@ -0,0 +1,183 @@
+use std::rc::Rc;
+
+use crate::hqm_server::HQMTeam;
+
+/// Length of a minor penalty in minutes, which ends early when the other team scores on the
+/// power play. Other penalties are always served in full
+pub(crate) const MINOR_PENALTY: u32 = 2;
+pub(crate) const MAJOR_PENALTY: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub(crate) struct HQMPenalty {
+    pub(crate) player_name: Rc<String>,
+    pub(crate) team: HQMTeam,
+    pub(crate) minutes: u32,
+    /// Time left to serve, measured in hundredths of a second of game time
+    pub(crate) time_left: u32,
+}
+
+/// Penalties that are being served, in the order they were called. Players are kept by name,
+/// so that leaving and joining again doesn't get them out of the box. A player with more than
+/// one penalty serves them one after the other
+#[derive(Debug, Default)]
+pub(crate) struct HQMPenaltyBox {
+    penalties: Vec<HQMPenalty>,
+}
+
+impl HQMPenaltyBox {
+    pub(crate) fn add(&mut self, player_name: Rc<String>, team: HQMTeam, minutes: u32) {
+        self.penalties.push(HQMPenalty {
+            player_name,
+            team,
+            minutes,
+            time_left: minutes * 60 * 100,
+        });
+    }
+
+    /// Removes all penalties of a player, returns the team of the player if there were any
+    pub(crate) fn remove(&mut self, player_name: &str) -> Option<HQMTeam> {
+        let team = self.team(player_name);
+        self.penalties
+            .retain(|penalty| penalty.player_name.as_str() != player_name);
+        team
+    }
+
+    /// Team of a player in the box
+    pub(crate) fn team(&self, player_name: &str) -> Option<HQMTeam> {
+        self.penalties
+            .iter()
+            .find(|penalty| penalty.player_name.as_str() == player_name)
+            .map(|penalty| penalty.team)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.penalties.clear();
+    }
+
+    pub(crate) fn penalties(&self) -> &[HQMPenalty] {
+        &self.penalties
+    }
+
+    /// Time the player still has to serve, None if the player isn't in the box
+    pub(crate) fn time_left(&self, player_name: &str) -> Option<u32> {
+        self.penalties
+            .iter()
+            .filter(|penalty| penalty.player_name.as_str() == player_name)
+            .map(|penalty| penalty.time_left)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Number of players of the team in the box
+    pub(crate) fn players_in_box(&self, team: HQMTeam) -> usize {
+        let mut players: Vec<_> = self
+            .penalties
+            .iter()
+            .filter(|penalty| penalty.team == team)
+            .map(|penalty| penalty.player_name.as_str())
+            .collect();
+        players.sort_unstable();
+        players.dedup();
+        players.len()
+    }
+
+    /// Called every step the game clock runs. Returns the players who have served all their
+    /// penalties
+    pub(crate) fn tick(&mut self) -> Vec<(Rc<String>, HQMTeam)> {
+        let mut serving: Vec<&str> = vec![];
+        for penalty in self.penalties.iter_mut() {
+            if serving.contains(&penalty.player_name.as_str()) {
+                continue;
+            }
+            serving.push(penalty.player_name.as_str());
+            penalty.time_left = penalty.time_left.saturating_sub(1);
+        }
+        self.take_served()
+    }
+
+    /// Ends the minor penalty with the least time left of the team that was scored on, if the
+    /// scoring team had more players on the ice. Returns the player if that was their last
+    /// penalty
+    pub(crate) fn power_play_goal(&mut self, scoring_team: HQMTeam) -> Vec<(Rc<String>, HQMTeam)> {
+        let other_team = scoring_team.get_other_team();
+        if self.players_in_box(other_team) <= self.players_in_box(scoring_team) {
+            return vec![];
+        }
+        let mut serving: Vec<&str> = vec![];
+        let mut ended = None;
+        for (i, penalty) in self.penalties.iter().enumerate() {
+            if serving.contains(&penalty.player_name.as_str()) {
+                continue;
+            }
+            serving.push(penalty.player_name.as_str());
+            let shorter = match ended {
+                Some((_, time_left)) => penalty.time_left < time_left,
+                None => true,
+            };
+            if penalty.team == other_team && penalty.minutes == MINOR_PENALTY && shorter {
+                ended = Some((i, penalty.time_left));
+            }
+        }
+        if let Some((i, _)) = ended {
+            self.penalties[i].time_left = 0;
+        }
+        self.take_served()
+    }
+
+    fn take_served(&mut self) -> Vec<(Rc<String>, HQMTeam)> {
+        let mut served = vec![];
+        for penalty in self.penalties.iter() {
+            if penalty.time_left == 0 {
+                served.push((penalty.player_name.clone(), penalty.team));
+            }
+        }
+        self.penalties.retain(|penalty| penalty.time_left > 0);
+        served.retain(|(player_name, _)| self.time_left(player_name).is_none());
+        served
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::hqm_penalty::{HQMPenaltyBox, MAJOR_PENALTY, MINOR_PENALTY};
+    use crate::hqm_server::HQMTeam;
+
+    #[test]
+    fn penalties_are_served_one_after_the_other() {
+        let mut penalty_box = HQMPenaltyBox::default();
+        let name = Rc::new("migo".to_string());
+        penalty_box.add(name.clone(), HQMTeam::Red, MINOR_PENALTY);
+        penalty_box.add(name.clone(), HQMTeam::Red, MINOR_PENALTY);
+        assert_eq!(penalty_box.time_left("migo"), Some(24000));
+        for _ in 0..11999 {
+            assert!(penalty_box.tick().is_empty());
+        }
+        // The first penalty is over, but the second one still has to be served
+        assert!(penalty_box.tick().is_empty());
+        assert_eq!(penalty_box.time_left("migo"), Some(12000));
+        for _ in 0..11999 {
+            penalty_box.tick();
+        }
+        assert_eq!(penalty_box.tick(), vec![(name, HQMTeam::Red)]);
+        assert_eq!(penalty_box.time_left("migo"), None);
+    }
+
+    #[test]
+    fn power_play_goal_ends_minor_penalty() {
+        let mut penalty_box = HQMPenaltyBox::default();
+        let minor = Rc::new("minor".to_string());
+        let major = Rc::new("major".to_string());
+        penalty_box.add(major.clone(), HQMTeam::Blue, MAJOR_PENALTY);
+        penalty_box.add(minor.clone(), HQMTeam::Blue, MINOR_PENALTY);
+        // Goals by the shorthanded team don't end penalties
+        assert!(penalty_box.power_play_goal(HQMTeam::Blue).is_empty());
+        assert_eq!(
+            penalty_box.power_play_goal(HQMTeam::Red),
+            vec![(minor, HQMTeam::Blue)]
+        );
+        // Majors are served in full
+        assert!(penalty_box.power_play_goal(HQMTeam::Red).is_empty());
+        assert_eq!(penalty_box.players_in_box(HQMTeam::Blue), 1);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::hqm_match::parse_clock;
+use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
+use migo_hqm_server::hqm_game::HQMPhysicsProfile;
+use migo_hqm_server::hqm_replay::{HQMReplayPlayback, REPLAY_STEPS_PER_SECOND};
+use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex};
+use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
+use tracing::info;
+
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 8.0;
+
+/// Plays a saved replay to everyone on the server, who can only spectate, because the mode
+/// never puts anyone on the ice. Administrators can pause it, move to another point and change
+/// the playback speed.
+pub struct HQMPlaybackBehaviour {
+    playback: HQMReplayPlayback,
+    file_name: String,
+    physics_config: HQMPhysicsProfile,
+    /// Whether the end of the replay was announced, so that it's announced once
+    end_announced: bool,
+}
+
+impl HQMPlaybackBehaviour {
+    pub fn new(
+        playback: HQMReplayPlayback,
+        file_name: String,
+        physics_config: HQMPhysicsProfile,
+    ) -> Self {
+        HQMPlaybackBehaviour {
+            playback,
+            file_name,
+            physics_config,
+            end_announced: false,
+        }
+    }
+
+    fn status(&self) -> String {
+        let (period, time) = self.playback.clock();
+        let mut status = format!(
+            "Replay {} at {} of {}, period {} with {} left, speed {}x",
+            self.file_name,
+            format_time(self.playback.position()),
+            format_time(self.playback.duration()),
+            period,
+            format_time(time as usize),
+            self.playback.speed()
+        );
+        if self.playback.is_paused() {
+            status.push_str(", paused");
+        }
+        status
+    }
+
+    /// Runs a playback command for an administrator or the console. Returns what happened,
+    /// which is announced to everyone, or the usage of the command if the argument is wrong
+    fn control(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+    ) -> Option<Result<String, &'static str>> {
+        let result = match cmd {
+            "pause" => {
+                self.playback.set_paused(true);
+                Ok("Replay paused".to_string())
+            }
+            "unpause" => {
+                self.playback.set_paused(false);
+                Ok("Replay resumed".to_string())
+            }
+            "seek" => match parse_seek(arg, self.playback.position()) {
+                Some(position) => {
+                    self.playback.seek(server, position);
+                    self.end_announced = false;
+                    Ok(format!(
+                        "Replay moved to {}",
+                        format_time(self.playback.position())
+                    ))
+                }
+                None => Err("seek <[+|-][minutes:]seconds>"),
+            },
+            "speed" => match arg.parse::<f64>() {
+                Ok(speed) if (MIN_SPEED..=MAX_SPEED).contains(&speed) => {
+                    self.playback.set_speed(speed);
+                    Ok(format!("Replay speed set to {}x", speed))
+                }
+                _ => Err("speed <0.1 to 8>"),
+            },
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+impl HQMServerBehaviour for HQMPlaybackBehaviour {
+    fn before_tick(&mut self, server: &mut HQMServer) {
+        self.playback.step(server);
+        if self.playback.is_finished() && !self.end_announced {
+            self.end_announced = true;
+            server
+                .messages
+                .add_server_chat_message("End of replay, /seek 0 starts it over");
+        }
+    }
+
+    fn after_tick(&mut self, _server: &mut HQMServer, _events: &[HQMSimulationEvent]) {}
+
+    fn handle_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        if cmd == "playback" {
+            let msg = self.status();
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        if !matches!(cmd, "pause" | "unpause" | "seek" | "speed") {
+            return;
+        }
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                let player_name = player.player_name.clone();
+                match self.control(server, cmd, arg) {
+                    Some(Ok(msg)) => {
+                        info!("{} ({}): {}", player_name, player_index, msg);
+                        let msg = format!("{} by {}", msg, player_name);
+                        server.messages.add_server_chat_message(msg);
+                    }
+                    Some(Err(usage)) => {
+                        let msg = format!("Usage: /{}", usage);
+                        server
+                            .messages
+                            .add_directed_server_chat_message(msg, player_index);
+                    }
+                    None => {}
+                }
+            } else {
+                server.admin_deny_message(player_index);
+            }
+        }
+    }
+
+    fn handle_console_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+    ) -> Option<String> {
+        if cmd == "playback" {
+            return Some(self.status());
+        }
+        let response = match self.control(server, cmd, arg)? {
+            Ok(msg) => {
+                let chat = format!("{} by console", msg);
+                server.messages.add_server_chat_message(chat);
+                msg
+            }
+            Err(usage) => format!("Usage: {}", usage),
+        };
+        Some(response)
+    }
+
+    fn console_help(&self) -> Option<&'static str> {
+        Some("playback, pause, unpause, seek <[+|-][minutes:]seconds>, speed <0.1 to 8>")
+    }
+
+    fn get_initial_game_values(&mut self) -> HQMInitialGameValues {
+        HQMInitialGameValues {
+            values: Default::default(),
+            puck_slots: 0,
+            physics_configuration: self.physics_config.clone(),
+        }
+    }
+
+    fn game_started(&mut self, server: &mut HQMServer) {
+        self.playback.show_players(server);
+    }
+
+    fn get_number_of_players(&self) -> u32 {
+        0
+    }
+
+    fn save_replay_data(&self, _server: &HQMServer) -> bool {
+        false
+    }
+}
+
+/// Parses a point in the replay, either a time from the start or, with + or -, a time from the
+/// current position. Returned in steps
+fn parse_seek(arg: &str, position: usize) -> Option<usize> {
+    if let Some(time) = arg.strip_prefix('+') {
+        let steps = parse_clock(time).ok()? as usize;
+        Some(position.saturating_add(steps))
+    } else if let Some(time) = arg.strip_prefix('-') {
+        let steps = parse_clock(time).ok()? as usize;
+        Some(position.saturating_sub(steps))
+    } else {
+        parse_clock(arg).ok().map(|steps| steps as usize)
+    }
+}
+
+fn format_time(steps: usize) -> String {
+    let seconds = steps / REPLAY_STEPS_PER_SECOND;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
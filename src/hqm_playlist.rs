@@ -0,0 +1,251 @@
+use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
+use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex};
+use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
+use migo_hqm_server::hqm_snapshot::HQMGameSnapshot;
+use tracing::info;
+
+pub(crate) struct HQMPlaylistEntry {
+    pub(crate) name: String,
+    pub(crate) games: u32,
+    pub(crate) behaviour: Box<dyn HQMServerBehaviour>,
+}
+
+/// Plays the game modes of a playlist in turn, each for its number of completed games, and
+/// starts over after the last one. The next game is announced when a game ends.
+pub(crate) struct HQMPlaylistBehaviour {
+    entries: Vec<HQMPlaylistEntry>,
+    current: usize,
+    /// Completed games of the current entry
+    completed_games: u32,
+    /// The last game that was counted, so that a game that ends again after admins changed the
+    /// score or the clock is counted once
+    last_completed_game: Option<u32>,
+    /// The replay history length each entry set in init, or None for entries that weren't played
+    /// yet. Entries are only initialized once, when they are first played.
+    history_lengths: Vec<Option<usize>>,
+}
+
+impl HQMPlaylistBehaviour {
+    pub(crate) fn new(entries: Vec<HQMPlaylistEntry>) -> Self {
+        let history_lengths = vec![None; entries.len()];
+        HQMPlaylistBehaviour {
+            entries,
+            current: 0,
+            completed_games: 0,
+            last_completed_game: None,
+            history_lengths,
+        }
+    }
+
+    /// Starts a game of the current entry, and initializes the entry if it's played for the first
+    /// time. Entries that were played before keep their state, so that match mode doesn't add
+    /// its bots again.
+    fn start_entry(&mut self, server: &mut HQMServer) {
+        let values = self.current().get_initial_game_values();
+        server.new_game(values);
+        match self.history_lengths[self.current] {
+            Some(history_length) => server.history_length = history_length,
+            None => {
+                server.history_length = 0;
+                self.current().init(server);
+                self.history_lengths[self.current] = Some(server.history_length);
+            }
+        }
+    }
+
+    fn current(&mut self) -> &mut Box<dyn HQMServerBehaviour> {
+        &mut self.entries[self.current].behaviour
+    }
+
+    fn next_entry(&self) -> usize {
+        (self.current + 1) % self.entries.len()
+    }
+
+    fn is_entry_done(&self) -> bool {
+        self.completed_games >= self.entries[self.current].games
+    }
+
+    fn next_game_description(&self) -> String {
+        if self.is_entry_done() {
+            let next = &self.entries[self.next_entry()];
+            if next.games > 1 {
+                format!("{}, game 1 of {}", next.name, next.games)
+            } else {
+                next.name.clone()
+            }
+        } else {
+            let entry = &self.entries[self.current];
+            format!(
+                "{}, game {} of {}",
+                entry.name,
+                self.completed_games + 1,
+                entry.games
+            )
+        }
+    }
+
+    /// Counts games that end and switches to the next entry when the mode starts a new game
+    /// after the last game of the current entry
+    fn update(&mut self, server: &mut HQMServer, was_game_over: bool, game_id: u32) {
+        if server.values.game_over
+            && !was_game_over
+            && self.last_completed_game != Some(server.game_id())
+        {
+            self.last_completed_game = Some(server.game_id());
+            self.completed_games += 1;
+            let msg = format!("Next game: {}", self.next_game_description());
+            server.messages.add_server_chat_message(msg);
+        }
+        if server.game_id() != game_id && self.is_entry_done() {
+            let next = self.next_entry();
+            self.completed_games = 0;
+            if next == self.current {
+                return;
+            }
+            self.current = next;
+            info!("Playlist continues with {}", self.entries[next].name);
+            // The game the previous mode just started is replaced by one of the next mode
+            self.start_entry(server);
+        }
+    }
+
+    fn show_playlist(&self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        let entries = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let games = if entry.games == 1 {
+                    "1 game".to_string()
+                } else {
+                    format!("{} games", entry.games)
+                };
+                if i == self.current {
+                    format!("[{}, {}]", entry.name, games)
+                } else {
+                    format!("{}, {}", entry.name, games)
+                }
+            })
+            .collect::<Vec<_>>();
+        let msg = format!("Playlist: {}", entries.join(" / "));
+        server
+            .messages
+            .add_directed_server_chat_message(msg, player_index);
+        let msg = format!("Next game: {}", self.next_game_description());
+        server
+            .messages
+            .add_directed_server_chat_message(msg, player_index);
+    }
+
+    /// Ends the current entry after the game that is being played
+    fn skip(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        if let Some(player) = server.players.get(player_index) {
+            if player.is_admin {
+                info!(
+                    "{} ({}) skipped the rest of {}",
+                    player.player_name, player_index, self.entries[self.current].name
+                );
+                self.completed_games = self.completed_games.max(self.entries[self.current].games);
+                let msg = format!("Next game: {}", self.next_game_description());
+                server.messages.add_server_chat_message(msg);
+            } else {
+                server.admin_deny_message(player_index);
+            }
+        }
+    }
+}
+
+impl HQMServerBehaviour for HQMPlaylistBehaviour {
+    fn init(&mut self, server: &mut HQMServer) {
+        self.current().init(server);
+        self.history_lengths[self.current] = Some(server.history_length);
+    }
+
+    fn before_tick(&mut self, server: &mut HQMServer) {
+        let (was_game_over, game_id) = (server.values.game_over, server.game_id());
+        self.current().before_tick(server);
+        self.update(server, was_game_over, game_id);
+    }
+
+    fn after_tick(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        let (was_game_over, game_id) = (server.values.game_over, server.game_id());
+        self.current().after_tick(server, events);
+        self.update(server, was_game_over, game_id);
+    }
+
+    fn handle_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        match (cmd, arg) {
+            ("playlist", "") => self.show_playlist(server, player_index),
+            ("playlist", "skip") => self.skip(server, player_index),
+            _ => self
+                .current()
+                .handle_command(server, cmd, arg, player_index),
+        }
+    }
+
+    fn handle_console_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+    ) -> Option<String> {
+        self.current().handle_console_command(server, cmd, arg)
+    }
+
+    fn console_help(&self) -> Option<&'static str> {
+        self.entries[self.current].behaviour.console_help()
+    }
+
+    fn get_initial_game_values(&mut self) -> HQMInitialGameValues {
+        self.current().get_initial_game_values()
+    }
+
+    fn game_started(&mut self, server: &mut HQMServer) {
+        self.current().game_started(server);
+    }
+
+    fn game_resumed(&mut self, server: &mut HQMServer, snapshot: &HQMGameSnapshot) {
+        self.current().game_resumed(server, snapshot);
+    }
+
+    fn can_resume_game(&self) -> bool {
+        self.entries[self.current].behaviour.can_resume_game()
+    }
+
+    fn before_player_exit(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        self.current().before_player_exit(server, player_index);
+    }
+
+    fn before_player_disconnect(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        self.current()
+            .before_player_disconnect(server, player_index);
+    }
+
+    fn after_player_join(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        self.current().after_player_join(server, player_index);
+    }
+
+    fn get_number_of_players(&self) -> u32 {
+        self.entries[self.current].behaviour.get_number_of_players()
+    }
+
+    fn save_replay_data(&self, server: &HQMServer) -> bool {
+        self.entries[self.current]
+            .behaviour
+            .save_replay_data(server)
+    }
+
+    fn regular_periods(&self) -> Option<u32> {
+        self.entries[self.current].behaviour.regular_periods()
+    }
+}
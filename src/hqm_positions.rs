@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use crate::hqm_server::{HQMServerPlayerIndex, HQMTeam};
+
+#[derive(Debug, Clone, PartialEq)]
+struct HQMPositionWait {
+    player_index: HQMServerPlayerIndex,
+    team: HQMTeam,
+    position: &'static str,
+}
+
+/// Keeps positions exclusive per team. Players who want a position a team mate holds wait in
+/// line for it, first come first served, as long as they stay on the team
+#[derive(Debug, Default)]
+pub(crate) struct HQMPositionClaims {
+    /// Team of each player on the ice in the last step, to find the players who joined a team
+    teams: HashMap<HQMServerPlayerIndex, HQMTeam>,
+    waitlist: Vec<HQMPositionWait>,
+}
+
+impl HQMPositionClaims {
+    /// Puts a player in line for a position, instead of any position they waited for before.
+    /// Returns the place in line
+    pub(crate) fn wait(
+        &mut self,
+        player_index: HQMServerPlayerIndex,
+        team: HQMTeam,
+        position: &'static str,
+    ) -> usize {
+        self.remove(player_index);
+        self.waitlist.push(HQMPositionWait {
+            player_index,
+            team,
+            position,
+        });
+        self.waitlist
+            .iter()
+            .filter(|wait| wait.team == team && wait.position == position)
+            .count()
+    }
+
+    pub(crate) fn remove(&mut self, player_index: HQMServerPlayerIndex) {
+        self.waitlist
+            .retain(|wait| wait.player_index != player_index);
+    }
+
+    /// Stores the teams of the players on the ice, and returns the players who joined a team
+    /// since the last step in the order of their index. Players who are no longer on the team
+    /// they waited on leave the line
+    pub(crate) fn update_teams(
+        &mut self,
+        teams: HashMap<HQMServerPlayerIndex, HQMTeam>,
+    ) -> Vec<HQMServerPlayerIndex> {
+        let mut joined: Vec<_> = teams
+            .iter()
+            .filter(|(player_index, team)| self.teams.get(player_index) != Some(team))
+            .map(|(player_index, _)| *player_index)
+            .collect();
+        joined.sort_by_key(|player_index| player_index.0);
+        self.waitlist
+            .retain(|wait| teams.get(&wait.player_index) == Some(&wait.team));
+        self.teams = teams;
+        joined
+    }
+
+    /// The waiting players in order, with the team and position they wait for
+    pub(crate) fn waiting(
+        &self,
+    ) -> impl Iterator<Item = (HQMServerPlayerIndex, HQMTeam, &'static str)> + '_ {
+        self.waitlist
+            .iter()
+            .map(|wait| (wait.player_index, wait.team, wait.position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::hqm_positions::HQMPositionClaims;
+    use crate::hqm_server::{HQMServerPlayerIndex, HQMTeam};
+
+    #[test]
+    fn players_leave_the_line_when_they_leave_the_team() {
+        let mut claims = HQMPositionClaims::default();
+        let a = HQMServerPlayerIndex(0);
+        let b = HQMServerPlayerIndex(1);
+        let c = HQMServerPlayerIndex(2);
+        let teams = HashMap::from([(a, HQMTeam::Red), (b, HQMTeam::Red), (c, HQMTeam::Blue)]);
+        assert_eq!(claims.update_teams(teams.clone()), vec![a, b, c]);
+        assert_eq!(claims.wait(a, HQMTeam::Red, "C"), 1);
+        assert_eq!(claims.wait(b, HQMTeam::Red, "C"), 2);
+        assert_eq!(claims.wait(c, HQMTeam::Blue, "C"), 1);
+        assert!(claims.update_teams(teams).is_empty());
+
+        let teams = HashMap::from([(a, HQMTeam::Blue), (b, HQMTeam::Red)]);
+        assert_eq!(claims.update_teams(teams), vec![a]);
+        assert_eq!(
+            claims.waiting().collect::<Vec<_>>(),
+            vec![(b, HQMTeam::Red, "C")]
+        );
+    }
+}
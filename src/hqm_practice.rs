@@ -0,0 +1,605 @@
+use std::collections::{HashMap, VecDeque};
+
+use nalgebra::{Point3, Rotation3, Vector3};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::hqm_game::HQMObjectIndex;
+use crate::hqm_match_util::{get_faceoff_spot, HQMRinkFaceoffSpot, HQMRinkSide};
+use crate::hqm_server::{HQMServer, HQMServerPlayerIndex, HQMTeam};
+use crate::hqm_simulate::HQMSimulationEvent;
+
+/// Most cones and targets on the ice at once
+const MAX_CONES: usize = 10;
+/// A puck closer than this to the center of a target in meters hits it
+const TARGET_RADIUS: f32 = 0.35;
+/// Steps after a hit before the same target counts another one
+const TARGET_COOLDOWN: u32 = 100;
+/// Most dummies on the ice at once
+const MAX_DUMMIES: usize = 4;
+
+/// A puck that is held in place. Cones lie on the ice to skate around, targets stay where the
+/// stick blade was when they were placed, and count the pucks shot at them
+struct HQMCone {
+    object: HQMObjectIndex,
+    owner: HQMServerPlayerIndex,
+    pos: Point3<f32>,
+    target: bool,
+    cooldown: u32,
+}
+
+/// A skater without input that is held in place, to stickhandle around or to screen shots
+struct HQMDummy {
+    player: HQMServerPlayerIndex,
+    /// The slot of a dummy may be taken by someone else once the dummy is gone
+    id: Uuid,
+    owner: HQMServerPlayerIndex,
+    pos: Point3<f32>,
+    rot: Rotation3<f32>,
+}
+
+/// Pucks, cones and targets that players place for themselves to practice, like during warmup
+/// in match mode. The objects are remembered by their slot, so they are forgotten as soon as
+/// something else clears the pucks of the world
+pub struct HQMPractice {
+    /// Pucks of each player, oldest first
+    pucks: HashMap<HQMServerPlayerIndex, VecDeque<HQMObjectIndex>>,
+    /// Pucks a player can have, the oldest is removed for a new one
+    puck_limit: usize,
+    cones: Vec<HQMCone>,
+    dummies: Vec<HQMDummy>,
+    /// The last player who touched each puck, to tell who hit a target
+    last_touches: HashMap<HQMObjectIndex, HQMServerPlayerIndex>,
+    /// Clears of the pucks of the world when the objects were placed
+    puck_clears: u32,
+}
+
+impl HQMPractice {
+    pub fn new(puck_limit: usize) -> Self {
+        HQMPractice {
+            pucks: HashMap::new(),
+            puck_limit,
+            cones: vec![],
+            dummies: vec![],
+            last_touches: HashMap::new(),
+            puck_clears: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pucks.is_empty() && self.cones.is_empty() && self.dummies.is_empty()
+    }
+
+    /// Forgets the objects without removing them, when the world was replaced or its pucks
+    /// were cleared. Dummies are players, so they stay until the world is replaced, which
+    /// removes them
+    pub fn clear(&mut self) {
+        self.pucks.clear();
+        self.cones.clear();
+        self.last_touches.clear();
+    }
+
+    fn check_cleared(&mut self, server: &HQMServer) {
+        if server.world.puck_clears != self.puck_clears {
+            self.clear();
+            self.puck_clears = server.world.puck_clears;
+        }
+    }
+
+    /// Removes every object that players placed
+    pub fn remove(&mut self, server: &mut HQMServer) {
+        self.check_cleared(server);
+        let objects = self
+            .pucks
+            .values()
+            .flatten()
+            .copied()
+            .chain(self.cones.iter().map(|cone| cone.object));
+        for object in objects {
+            server.world.remove_puck(object);
+        }
+        self.clear();
+        for dummy in self.dummies.drain(..) {
+            server.remove_player(dummy.player, true);
+        }
+    }
+
+    /// Removes the objects of a player who left
+    pub fn remove_player(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        self.check_cleared(server);
+        for object in self.pucks.remove(&player_index).unwrap_or_default() {
+            server.world.remove_puck(object);
+        }
+        self.remove_cones(server, player_index);
+        self.remove_dummies(server, player_index);
+    }
+
+    fn remove_dummies(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) -> usize {
+        let before = self.dummies.len();
+        self.dummies.retain(|dummy| {
+            if dummy.owner == player_index {
+                server.remove_player(dummy.player, true);
+                false
+            } else {
+                true
+            }
+        });
+        before - self.dummies.len()
+    }
+
+    fn remove_cones(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) -> usize {
+        let before = self.cones.len();
+        self.cones.retain(|cone| {
+            if cone.owner == player_index {
+                server.world.remove_puck(cone.object);
+                false
+            } else {
+                true
+            }
+        });
+        before - self.cones.len()
+    }
+
+    /// Where the stick blade of a player on the ice is, kept inside the rink
+    fn stick_position(
+        server: &HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) -> Option<Point3<f32>> {
+        let (object, _) = server.players.get(player_index)?.object?;
+        let skater = server.world.objects.get_skater(object)?;
+        let rink = &server.world.rink;
+        Some(Point3::new(
+            skater.stick_pos.x.clamp(0.5, rink.width - 0.5),
+            skater.stick_pos.y.max(0.0),
+            skater.stick_pos.z.clamp(0.5, rink.length - 0.5),
+        ))
+    }
+
+    /// Puts a puck of the player at the stick blade, and removes the oldest puck of the player
+    /// if the player has too many. "clear" removes the pucks of the player
+    pub fn spawn_puck(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) {
+        self.check_cleared(server);
+        let pucks = self.pucks.entry(player_index).or_default();
+        if arg == "clear" {
+            let msg = format!("Removed {} of your pucks", pucks.len());
+            for object in pucks.drain(..) {
+                server.world.remove_puck(object);
+            }
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        let pos = match Self::stick_position(server, player_index) {
+            Some(pos) => pos,
+            None => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("Join a team to get a puck", player_index);
+                return;
+            }
+        };
+        if pucks.len() >= self.puck_limit {
+            if let Some(object) = pucks.pop_front() {
+                server.world.remove_puck(object);
+            }
+        }
+        let pos = Point3::new(pos.x, 0.5, pos.z);
+        match server.world.create_puck_object(pos, Rotation3::identity()) {
+            Some(object) => pucks.push_back(object),
+            None => server.messages.add_directed_server_chat_message(
+                "There is no room for another puck",
+                player_index,
+            ),
+        }
+    }
+
+    /// Puts a cone on the ice at the stick blade of the player, or a target right at the
+    /// stick blade. "clear" removes the cones and targets of the player
+    pub fn place_cone(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        target: bool,
+        arg: &str,
+    ) {
+        self.check_cleared(server);
+        if arg == "clear" {
+            let removed = self.remove_cones(server, player_index);
+            let msg = format!("Removed {} of your cones and targets", removed);
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        let pos = match Self::stick_position(server, player_index) {
+            Some(pos) => pos,
+            None => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("Join a team to place cones", player_index);
+                return;
+            }
+        };
+        if self.cones.len() >= MAX_CONES {
+            let msg = format!(
+                "There are already {} cones and targets, /cone clear removes yours",
+                MAX_CONES
+            );
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        // Cones are dropped and only held in place across the ice
+        let pos = if target {
+            pos
+        } else {
+            Point3::new(pos.x, 0.5, pos.z)
+        };
+        match server.world.create_puck_object(pos, Rotation3::identity()) {
+            Some(object) => {
+                self.cones.push(HQMCone {
+                    object,
+                    owner: player_index,
+                    pos,
+                    target,
+                    cooldown: 0,
+                });
+            }
+            None => server.messages.add_directed_server_chat_message(
+                "There is no room for another cone",
+                player_index,
+            ),
+        }
+    }
+
+    /// Puts a dummy of the other team on the ice at x and z in meters, facing the player.
+    /// "clear" removes the dummies of the player
+    pub fn place_dummy(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) {
+        let rink = &server.world.rink;
+        let (x, z) = match parse_dummy_command(arg, rink.width, rink.length) {
+            Some(HQMDummyCommand::Add(x, z)) => (x, z),
+            Some(HQMDummyCommand::Clear) => {
+                let removed = self.remove_dummies(server, player_index);
+                let msg = format!("Removed {} of your dummies", removed);
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, player_index);
+                return;
+            }
+            None => {
+                let msg = format!(
+                    "Usage: /dummy add <x> <z> or /dummy clear, with x from 0 to {} and z from 0 to {}",
+                    rink.width, rink.length
+                );
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, player_index);
+                return;
+            }
+        };
+        let (player_name, skater_pos, team) = match server.players.get(player_index) {
+            Some(player) => match player
+                .object
+                .and_then(|(object, team)| Some((server.world.objects.get_skater(object)?, team)))
+            {
+                Some((skater, team)) => (player.player_name.clone(), skater.body.pos, team),
+                None => {
+                    server.messages.add_directed_server_chat_message(
+                        "Join a team to place dummies",
+                        player_index,
+                    );
+                    return;
+                }
+            },
+            None => return,
+        };
+        if self.dummies.len() >= MAX_DUMMIES {
+            let msg = format!(
+                "There are already {} dummies, /dummy clear removes yours",
+                MAX_DUMMIES
+            );
+            server
+                .messages
+                .add_directed_server_chat_message(msg, player_index);
+            return;
+        }
+        let pos = Point3::new(x, 1.5, z);
+        // Skaters look along -z
+        let angle = (x - skater_pos.x).atan2(z - skater_pos.z);
+        let rot = Rotation3::from_axis_angle(&Vector3::y_axis(), angle);
+        let dummy = server.add_dummy("Dummy".to_string()).and_then(|dummy| {
+            if server
+                .spawn_skater(dummy, team.get_other_team(), pos, rot, false)
+                .is_none()
+            {
+                server.remove_player(dummy, true);
+                return None;
+            }
+            Some((dummy, server.players.get(dummy)?.id))
+        });
+        match dummy {
+            Some((dummy, id)) => {
+                info!(
+                    "{} ({}) placed dummy {} at {:.1} {:.1}",
+                    player_name, player_index, dummy, x, z
+                );
+                self.dummies.push(HQMDummy {
+                    player: dummy,
+                    id,
+                    owner: player_index,
+                    pos,
+                    rot,
+                });
+            }
+            None => {
+                server.messages.add_directed_server_chat_message(
+                    "There is no room for another dummy",
+                    player_index,
+                );
+            }
+        }
+    }
+
+    /// Holds the cones, targets and dummies in place and announces target hits, called after
+    /// every step of the warmup
+    pub fn update(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        self.check_cleared(server);
+        if self.is_empty() {
+            return;
+        }
+        // Skaters push dummies around like anyone else, but they slide back at once
+        self.dummies.retain(|dummy| {
+            let object = match server.players.get(dummy.player) {
+                Some(player) if player.id == dummy.id => player.object,
+                _ => return false,
+            };
+            let skater = object.and_then(|(object, _)| server.world.objects.get_skater_mut(object));
+            match skater {
+                Some(skater) => {
+                    skater.body.pos.x = dummy.pos.x;
+                    skater.body.pos.z = dummy.pos.z;
+                    skater.body.rot = dummy.rot;
+                    skater.body.angular_velocity = Vector3::zeros();
+                    skater.body.linear_velocity.x = 0.0;
+                    skater.body.linear_velocity.z = 0.0;
+                    true
+                }
+                // A dummy that was moved off the ice has nothing to do
+                None => {
+                    server.remove_player(dummy.player, true);
+                    false
+                }
+            }
+        });
+        for event in events {
+            if let HQMSimulationEvent::PuckTouch { player, puck } = *event {
+                if let Some((player_index, _, _)) = server.players.get_from_object_index(player) {
+                    self.last_touches.insert(puck, player_index);
+                }
+            }
+        }
+        let cone_objects: Vec<_> = self.cones.iter().map(|cone| cone.object).collect();
+        let mut hits = vec![];
+        for cone in self.cones.iter_mut() {
+            if let Some(puck) = server.world.objects.get_puck_mut(cone.object) {
+                puck.body.pos.x = cone.pos.x;
+                puck.body.pos.z = cone.pos.z;
+                puck.body.rot = Rotation3::identity();
+                puck.body.angular_velocity = Vector3::zeros();
+                if cone.target {
+                    puck.body.pos.y = cone.pos.y;
+                    puck.body.linear_velocity = Vector3::zeros();
+                } else {
+                    puck.body.linear_velocity.x = 0.0;
+                    puck.body.linear_velocity.z = 0.0;
+                }
+            }
+            if !cone.target {
+                continue;
+            }
+            if cone.cooldown > 0 {
+                cone.cooldown -= 1;
+                continue;
+            }
+            let hit = server.world.objects.pucks().find(|(object, puck)| {
+                !cone_objects.contains(object)
+                    && hits_target(&cone.pos, &puck.body.pos, &puck.body.linear_velocity)
+            });
+            if let Some((object, _)) = hit {
+                cone.cooldown = TARGET_COOLDOWN;
+                hits.push(object);
+            }
+        }
+        for object in hits {
+            let player_name = self
+                .last_touches
+                .get(&object)
+                .and_then(|player_index| server.players.get(*player_index))
+                .map(|player| player.player_name.clone());
+            if let Some(player_name) = player_name {
+                let msg = format!("{} hit a target", player_name);
+                server.messages.add_server_chat_message(msg);
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum HQMDummyCommand {
+    /// Place a dummy at x and z
+    Add(f32, f32),
+    Clear,
+}
+
+/// Parses the arguments of /dummy, None if they are wrong or the spot is too close to the
+/// boards of a rink of this width and length
+fn parse_dummy_command(arg: &str, width: f32, length: f32) -> Option<HQMDummyCommand> {
+    let args: Vec<&str> = arg.split_whitespace().collect();
+    match args.as_slice() {
+        ["clear"] => Some(HQMDummyCommand::Clear),
+        ["add", x, z] => {
+            let x = x.parse::<f32>().ok()?;
+            let z = z.parse::<f32>().ok()?;
+            let on_ice = (0.5..=width - 0.5).contains(&x) && (0.5..=length - 0.5).contains(&z);
+            on_ice.then_some(HQMDummyCommand::Add(x, z))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a puck hits a target. Pucks lying still next to a target don't hit it
+fn hits_target(target: &Point3<f32>, puck_pos: &Point3<f32>, puck_velocity: &Vector3<f32>) -> bool {
+    (puck_pos - target).norm() < TARGET_RADIUS && puck_velocity.norm() > 0.01
+}
+
+/// Moves a player to the center position at a faceoff spot like "center", "red left" or
+/// "blue neutral right"
+pub fn teleport(
+    server: &mut HQMServer,
+    player_index: HQMServerPlayerIndex,
+    arg: &str,
+    spawn_point_offset: f32,
+    spawn_player_altitude: f32,
+    keep_stick_position: bool,
+) {
+    let spot = match parse_faceoff_spot(arg) {
+        Some(spot) => spot,
+        None => {
+            server.messages.add_directed_server_chat_message(
+                "Usage: /tp center|<red|blue> [neutral] <left|right>",
+                player_index,
+            );
+            return;
+        }
+    };
+    let team = match server
+        .players
+        .get(player_index)
+        .and_then(|player| player.object)
+    {
+        Some((_, team)) => team,
+        None => {
+            server
+                .messages
+                .add_directed_server_chat_message("Join a team to move", player_index);
+            return;
+        }
+    };
+    let faceoff_spot = get_faceoff_spot(
+        &server.world.rink,
+        spot,
+        spawn_point_offset,
+        spawn_player_altitude,
+    );
+    let (pos, rot) = match team {
+        HQMTeam::Red => faceoff_spot.red_player_positions["C"],
+        HQMTeam::Blue => faceoff_spot.blue_player_positions["C"],
+    };
+    server.spawn_skater(player_index, team, pos, rot, keep_stick_position);
+}
+
+/// Parses a faceoff spot like "center", "red left" or "blue neutral right". The team is the
+/// team whose side of the center line the spot is on
+fn parse_faceoff_spot(arg: &str) -> Option<HQMRinkFaceoffSpot> {
+    let args: Vec<&str> = arg.split_whitespace().collect();
+    let team = |s: &str| match s {
+        "red" => Some(HQMTeam::Red),
+        "blue" => Some(HQMTeam::Blue),
+        _ => None,
+    };
+    let side = |s: &str| match s {
+        "left" => Some(HQMRinkSide::Left),
+        "right" => Some(HQMRinkSide::Right),
+        _ => None,
+    };
+    match args.as_slice() {
+        ["center"] => Some(HQMRinkFaceoffSpot::Center),
+        [t, s] => Some(HQMRinkFaceoffSpot::DefensiveZone(team(t)?, side(s)?)),
+        [t, "neutral", s] => Some(HQMRinkFaceoffSpot::Offside(team(t)?, side(s)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faceoff_spots_are_parsed() {
+        assert_eq!(
+            parse_faceoff_spot("center"),
+            Some(HQMRinkFaceoffSpot::Center)
+        );
+        assert_eq!(
+            parse_faceoff_spot("red left"),
+            Some(HQMRinkFaceoffSpot::DefensiveZone(
+                HQMTeam::Red,
+                HQMRinkSide::Left
+            ))
+        );
+        assert_eq!(
+            parse_faceoff_spot(" blue  neutral right "),
+            Some(HQMRinkFaceoffSpot::Offside(
+                HQMTeam::Blue,
+                HQMRinkSide::Right
+            ))
+        );
+        assert_eq!(parse_faceoff_spot("red"), None);
+        assert_eq!(parse_faceoff_spot("green left"), None);
+        assert_eq!(parse_faceoff_spot("blue neutral"), None);
+    }
+
+    #[test]
+    fn only_moving_pucks_hit_targets() {
+        let target = Point3::new(15.0, 1.0, 10.0);
+        let moving = Vector3::new(0.0, 0.0, -0.3);
+        assert!(hits_target(&target, &Point3::new(15.1, 1.0, 10.2), &moving));
+        assert!(!hits_target(
+            &target,
+            &Point3::new(15.5, 1.0, 10.0),
+            &moving
+        ));
+        assert!(!hits_target(
+            &target,
+            &Point3::new(15.1, 1.0, 10.2),
+            &Vector3::zeros()
+        ));
+    }
+
+    #[test]
+    fn dummies_are_placed_on_the_ice() {
+        assert_eq!(
+            parse_dummy_command("add 15 10.5", 30.0, 61.0),
+            Some(HQMDummyCommand::Add(15.0, 10.5))
+        );
+        assert_eq!(
+            parse_dummy_command("clear", 30.0, 61.0),
+            Some(HQMDummyCommand::Clear)
+        );
+        assert_eq!(parse_dummy_command("add 30 10", 30.0, 61.0), None);
+        assert_eq!(parse_dummy_command("add 15 -1", 30.0, 61.0), None);
+        assert_eq!(parse_dummy_command("add 15 NaN", 30.0, 61.0), None);
+        assert_eq!(parse_dummy_command("add 15", 30.0, 61.0), None);
+        assert_eq!(parse_dummy_command("", 30.0, 61.0), None);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::hqm_drill::HQMDrillControl;
+use crate::hqm_ghost::HQMGhosts;
+use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
+use migo_hqm_server::hqm_game::{HQMObjectIndex, HQMPhysicsProfile, MAX_PUCKS};
+use migo_hqm_server::hqm_match_util::{convert_puck_speed, get_spawnpoint, HQMSpawnPoint};
+use migo_hqm_server::hqm_practice::{teleport, HQMPractice};
+use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex, HQMTeam};
+use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
+use std::collections::HashMap;
+
+/// Pucks slower than this in meters per step when they leave the stick aren't shots
+const MIN_SHOT_SPEED: f32 = 0.15;
+
+pub struct HQMPracticeModeConfiguration {
+    pub physics_config: HQMPhysicsProfile,
+    /// Pucks on the ice when the server starts
+    pub pucks: usize,
+    /// Pucks each player can have with /puck
+    pub practice_pucks: usize,
+    pub spawn_point: HQMSpawnPoint,
+    pub spawn_point_offset: f32,
+    pub spawn_player_altitude: f32,
+    pub spawn_keep_stick_position: bool,
+    pub use_mph: bool,
+}
+
+/// A drills server without clock, rules or teams. Players get pucks, cones and targets for
+/// themselves, can move to any faceoff spot, and are told how fast they shoot
+pub struct HQMPracticeMode {
+    config: HQMPracticeModeConfiguration,
+    practice: HQMPractice,
+    /// Pucks on a stick in the last step, with the player holding them
+    held_pucks: HashMap<HQMObjectIndex, HQMServerPlayerIndex>,
+    /// The last player who touched each puck, to tell who scored
+    last_touches: HashMap<HQMObjectIndex, HQMServerPlayerIndex>,
+    /// Goals of the players who turned their goal counter on
+    goals: HashMap<HQMServerPlayerIndex, u32>,
+    drills: HQMDrillControl,
+    ghosts: HQMGhosts,
+}
+
+impl HQMPracticeMode {
+    pub fn new(config: HQMPracticeModeConfiguration) -> Self {
+        let practice = HQMPractice::new(config.practice_pucks);
+        HQMPracticeMode {
+            config,
+            practice,
+            held_pucks: HashMap::new(),
+            last_touches: HashMap::new(),
+            goals: HashMap::new(),
+            drills: HQMDrillControl::default(),
+            ghosts: HQMGhosts::default(),
+        }
+    }
+
+    fn goals_command(
+        &mut self,
+        server: &mut HQMServer,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        let msg = match arg {
+            "on" => {
+                self.goals.insert(player_index, 0);
+                "Your goals are counted".to_string()
+            }
+            "off" => {
+                self.goals.remove(&player_index);
+                "Your goals are no longer counted".to_string()
+            }
+            _ => match self.goals.get(&player_index) {
+                Some(goals) => {
+                    format!("You have scored {} goals, /goals off stops counting", goals)
+                }
+                None => "Usage: /goals on|off".to_string(),
+            },
+        };
+        server
+            .messages
+            .add_directed_server_chat_message(msg, player_index);
+    }
+
+    fn update_players(&mut self, server: &mut HQMServer) {
+        let mut spectating_players = vec![];
+        let mut joining_team = vec![];
+        for (player_index, player) in server.players.iter() {
+            let has_skater = player.object.is_some();
+            if has_skater && player.input.spectate() {
+                spectating_players.push(player_index);
+            } else if !has_skater {
+                if player.input.join_red() {
+                    joining_team.push((player_index, HQMTeam::Red));
+                } else if player.input.join_blue() {
+                    joining_team.push((player_index, HQMTeam::Blue));
+                }
+            }
+        }
+        for player_index in spectating_players {
+            server.move_to_spectator(player_index);
+        }
+        for (player_index, team) in joining_team {
+            let (pos, rot) = get_spawnpoint(&server.world.rink, team, self.config.spawn_point);
+            server.spawn_skater(player_index, team, pos, rot, false);
+        }
+    }
+
+    /// Tells players how fast the pucks that left their sticks in this step were shot
+    fn update_shots(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        let mut held_pucks = HashMap::new();
+        for event in events {
+            if let HQMSimulationEvent::PuckTouch { player, puck } = *event {
+                if let Some((player_index, _, _)) = server.players.get_from_object_index(player) {
+                    held_pucks.insert(puck, player_index);
+                    self.last_touches.insert(puck, player_index);
+                }
+            }
+        }
+        let released = std::mem::replace(&mut self.held_pucks, held_pucks);
+        for (puck, player_index) in released {
+            if self.held_pucks.contains_key(&puck) {
+                continue;
+            }
+            let speed = match server.world.objects.get_puck(puck) {
+                Some(puck) => puck.body.linear_velocity.norm(),
+                None => continue,
+            };
+            if speed >= MIN_SHOT_SPEED {
+                let (speed, unit) = convert_puck_speed(speed, self.config.use_mph);
+                let msg = format!("Shot: {:.1} {}", speed, unit);
+                server
+                    .messages
+                    .add_directed_server_chat_message(msg, player_index);
+            }
+        }
+    }
+
+    fn update_goals(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        for event in events {
+            if let HQMSimulationEvent::PuckEnteredNet { puck, .. } = *event {
+                let player_index = match self.last_touches.get(&puck) {
+                    Some(player_index) => *player_index,
+                    None => continue,
+                };
+                if let Some(goals) = self.goals.get_mut(&player_index) {
+                    *goals += 1;
+                    let msg = format!("Goal! You have scored {} goals", goals);
+                    server
+                        .messages
+                        .add_directed_server_chat_message(msg, player_index);
+                }
+            }
+        }
+    }
+}
+
+fn spawn_pucks(server: &mut HQMServer, pucks: usize) {
+    server.world.create_puck_line(pucks, 1.5);
+}
+
+impl HQMServerBehaviour for HQMPracticeMode {
+    fn before_tick(&mut self, server: &mut HQMServer) {
+        self.update_players(server);
+    }
+
+    fn after_tick(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        let pucks = self.config.pucks;
+        self.drills
+            .update(server, events, |server| spawn_pucks(server, pucks));
+        self.practice.update(server, events);
+        self.ghosts.update(server);
+        self.update_shots(server, events);
+        self.update_goals(server, events);
+    }
+
+    fn handle_command(
+        &mut self,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        match cmd {
+            "puck" => self.practice.spawn_puck(server, player_index, arg),
+            "cone" => self.practice.place_cone(server, player_index, false, arg),
+            "target" => self.practice.place_cone(server, player_index, true, arg),
+            "dummy" => self.practice.place_dummy(server, player_index, arg),
+            "tp" => teleport(
+                server,
+                player_index,
+                arg,
+                self.config.spawn_point_offset,
+                self.config.spawn_player_altitude,
+                self.config.spawn_keep_stick_position,
+            ),
+            "goals" => self.goals_command(server, arg, player_index),
+            "drill" => {
+                // Drills remove every puck, also those of the players, and can use all slots
+                let pucks = self.config.pucks;
+                self.drills
+                    .command(server, arg, player_index, MAX_PUCKS, |server| {
+                        spawn_pucks(server, pucks)
+                    });
+            }
+            "ghost" => self.ghosts.command(server, arg, player_index),
+            _ => {}
+        }
+    }
+
+    fn get_initial_game_values(&mut self) -> HQMInitialGameValues {
+        HQMInitialGameValues {
+            values: Default::default(),
+            puck_slots: MAX_PUCKS,
+            physics_configuration: self.config.physics_config.clone(),
+        }
+    }
+
+    fn game_started(&mut self, server: &mut HQMServer) {
+        self.practice.clear();
+        self.held_pucks.clear();
+        self.last_touches.clear();
+        self.drills.clear();
+        self.ghosts.clear();
+        spawn_pucks(server, self.config.pucks);
+    }
+
+    fn before_player_exit(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
+        self.practice.remove_player(server, player_index);
+        self.ghosts.remove_player(server, player_index);
+        self.goals.remove(&player_index);
+        self.held_pucks.retain(|_, x| *x != player_index);
+        self.last_touches.retain(|_, x| *x != player_index);
+    }
+
+    fn get_number_of_players(&self) -> u32 {
+        0
+    }
+}
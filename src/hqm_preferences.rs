@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hqm_game::HQMSkaterHand;
+use crate::hqm_names::normalize_name;
+
+/// Most players whose preferences are kept. The players who haven't joined for the longest
+/// are forgotten first
+const MAX_PLAYERS: usize = 10000;
+
+/// Settings a player picked with commands, which are restored when they join again
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HQMPlayerPreferences {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hand: Option<HQMSkaterHand>,
+    /// Faceoff position in match mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) position: Option<String>,
+    /// Counts up every time a player joins or changes a preference, to find the players who
+    /// haven't been seen for the longest
+    #[serde(default)]
+    last_seen: u64,
+}
+
+impl HQMPlayerPreferences {
+    fn is_empty(&self) -> bool {
+        self.hand.is_none() && self.position.is_none()
+    }
+}
+
+/// Preferences of the players by normalized name, so that they are kept across sessions.
+/// Players have no accounts in HQM, so the name is all there is to know them by
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct HQMPreferences {
+    players: BTreeMap<String, HQMPlayerPreferences>,
+}
+
+impl HQMPreferences {
+    pub(crate) fn parse(contents: &str) -> Option<Self> {
+        serde_json::from_str(contents).ok()
+    }
+
+    pub(crate) fn contents(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub(crate) fn get(&self, player_name: &str) -> Option<&HQMPlayerPreferences> {
+        self.players.get(&normalize_name(player_name))
+    }
+
+    /// Marks a player who joined as seen, so that their preferences are kept the longest
+    pub(crate) fn seen(&mut self, player_name: &str) {
+        let last_seen = self.next_seen();
+        if let Some(preferences) = self.players.get_mut(&normalize_name(player_name)) {
+            preferences.last_seen = last_seen;
+        }
+    }
+
+    pub(crate) fn update(&mut self, player_name: &str, f: impl FnOnce(&mut HQMPlayerPreferences)) {
+        let name = normalize_name(player_name);
+        let last_seen = self.next_seen();
+        let preferences = self.players.entry(name.clone()).or_default();
+        f(preferences);
+        preferences.last_seen = last_seen;
+        if preferences.is_empty() {
+            self.players.remove(&name);
+        }
+        if self.players.len() > MAX_PLAYERS {
+            let oldest = self
+                .players
+                .iter()
+                .min_by_key(|(_, preferences)| preferences.last_seen)
+                .map(|(name, _)| name.clone());
+            if let Some(oldest) = oldest {
+                self.players.remove(&oldest);
+            }
+        }
+    }
+
+    fn next_seen(&self) -> u64 {
+        self.players
+            .values()
+            .map(|preferences| preferences.last_seen + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hqm_game::HQMSkaterHand;
+    use crate::hqm_preferences::{HQMPreferences, MAX_PLAYERS};
+
+    #[test]
+    fn preferences_are_kept_by_normalized_name() {
+        let mut preferences = HQMPreferences::default();
+        preferences.update("Migo", |x| x.hand = Some(HQMSkaterHand::Left));
+        preferences.update(" MIGO ", |x| x.position = Some("LD".to_string()));
+        let preferences = HQMPreferences::parse(&preferences.contents()).unwrap();
+        assert_eq!(preferences.len(), 1);
+        let migo = preferences.get("migo").unwrap();
+        assert_eq!(migo.hand, Some(HQMSkaterHand::Left));
+        assert_eq!(migo.position.as_deref(), Some("LD"));
+    }
+
+    #[test]
+    fn players_not_seen_for_the_longest_are_forgotten() {
+        let mut preferences = HQMPreferences::default();
+        for i in 0..MAX_PLAYERS {
+            preferences.update(&format!("player{}", i), |x| {
+                x.hand = Some(HQMSkaterHand::Left)
+            });
+        }
+        preferences.seen("player0");
+        preferences.update("migo", |x| x.position = Some("C".to_string()));
+        assert_eq!(preferences.len(), MAX_PLAYERS);
+        assert!(preferences.get("player0").is_some());
+        assert!(preferences.get("player1").is_none());
+        assert!(preferences.get("migo").is_some());
+
+        preferences.update("migo", |x| x.position = None);
+        assert!(preferences.get("migo").is_none());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::hqm_drill::{is_valid_name, HQMDrillPuck};
+use migo_hqm_server::hqm_game::{HQMObjectIndex, HQMRink};
+use migo_hqm_server::hqm_server::HQMServer;
+use nalgebra::{Point3, Vector3};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// Pucks of the machine on the ice at once, the oldest is removed for a new one
+const MAX_MACHINE_PUCKS: usize = 8;
+
+/// Names of the presets that are available without a file
+const BUILTIN_PRESETS: [&str; 2] = ["slot", "point"];
+
+/// Pucks that a puck machine sends one at a time, to practice goaltending or one-timers
+#[derive(Debug, Deserialize)]
+pub struct HQMPuckMachinePreset {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Measured in seconds
+    pub interval: f32,
+    /// Sent in turn, one every interval
+    pub pucks: Vec<HQMDrillPuck>,
+}
+
+/// A puck on the ice at from, sent towards to at the speed in meters per second
+fn aimed_puck(from: Point3<f32>, to: Point3<f32>, speed: f32) -> HQMDrillPuck {
+    let direction = Vector3::new(to.x - from.x, 0.0, to.z - from.z).normalize();
+    let velocity = direction * speed;
+    HQMDrillPuck {
+        position: [from.x, 0.1, from.z],
+        velocity: [velocity.x, 0.0, velocity.z],
+    }
+}
+
+/// The presets that are available without a file, all aimed at the blue net. The pucks are
+/// placed by the net, the blue line and the boards of the rink, so that they fit any rink size
+fn builtin_preset(name: &str, rink: &HQMRink) -> Option<HQMPuckMachinePreset> {
+    let net = &rink.blue_net;
+    let goal = net.in_front(0.0, 0.0);
+    // Out from the net into the zone, and across the rink
+    let forward = -rink.direction_to_net(net);
+    let across = Vector3::new(forward.z, 0.0, -forward.x);
+    let to_boards = goal.x.min(rink.width - goal.x);
+    let to_blue_line = (rink.blue_zone_blue_line.z - goal.z).abs();
+    match name {
+        "slot" => {
+            let slot = goal + forward * 8.0;
+            let corner = goal + forward * 2.0;
+            let corner_offset = across * (to_boards - 3.0).max(1.0);
+            Some(HQMPuckMachinePreset {
+                name: "slot".to_string(),
+                description: Some("Passes from the corners to the slot".to_string()),
+                interval: 3.0,
+                pucks: vec![
+                    aimed_puck(corner - corner_offset, slot, 7.0),
+                    aimed_puck(corner + corner_offset, slot, 7.0),
+                ],
+            })
+        }
+        "point" => {
+            let point = goal + forward * (to_blue_line - 1.0).max(2.0);
+            let point_offset = across * (to_boards - 8.0).clamp(0.0, 7.0);
+            Some(HQMPuckMachinePreset {
+                name: "point".to_string(),
+                description: Some("Shots from the blue line at the net".to_string()),
+                interval: 2.5,
+                pucks: vec![
+                    aimed_puck(point - point_offset, goal, 25.0),
+                    aimed_puck(point, goal, 25.0),
+                    aimed_puck(point + point_offset, goal, 25.0),
+                ],
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Reads puck_machines/<name>.json, or one of the built-in presets for the rink if there is no
+/// such file. The error is short enough for a chat message
+pub fn load_preset(name: &str, rink: &HQMRink) -> Result<HQMPuckMachinePreset, String> {
+    if !is_valid_name(name) {
+        return Err(format!("Invalid preset name {}", name));
+    }
+    let path = PathBuf::from("puck_machines").join(format!("{}.json", name));
+    let preset: HQMPuckMachinePreset = match std::fs::read_to_string(&path) {
+        Ok(data) => serde_json::from_str(&data).map_err(|e| {
+            warn!("Invalid puck machine preset {}: {}", path.display(), e);
+            format!("Preset {} is invalid, see the server log", name)
+        })?,
+        Err(_) => builtin_preset(name, rink).ok_or_else(|| {
+            format!(
+                "There is no preset {}, built in are {}",
+                name,
+                BUILTIN_PRESETS.join(", ")
+            )
+        })?,
+    };
+    if preset.pucks.is_empty() {
+        return Err(format!("Preset {} has no pucks", name));
+    }
+    if preset.interval <= 0.0 {
+        return Err(format!("Preset {} needs a positive interval", name));
+    }
+    Ok(preset)
+}
+
+pub struct HQMPuckMachine {
+    preset: HQMPuckMachinePreset,
+    next_puck: usize,
+    last_step: u32,
+    /// Pucks of the machine on the ice, oldest first
+    pucks: VecDeque<HQMObjectIndex>,
+}
+
+impl HQMPuckMachine {
+    /// Sends the first puck at once
+    pub fn start(preset: HQMPuckMachinePreset, server: &mut HQMServer) -> Self {
+        let msg = format!("Puck machine {} started", preset.name);
+        server.messages.add_server_chat_message(msg);
+        if let Some(description) = &preset.description {
+            server
+                .messages
+                .add_server_chat_message(description.to_string());
+        }
+        let mut machine = HQMPuckMachine {
+            preset,
+            next_puck: 0,
+            last_step: server.game_step,
+            pucks: VecDeque::new(),
+        };
+        machine.send_puck(server);
+        machine
+    }
+
+    pub fn name(&self) -> &str {
+        &self.preset.name
+    }
+
+    fn send_puck(&mut self, server: &mut HQMServer) {
+        self.last_step = server.game_step;
+        if self.pucks.len() >= MAX_MACHINE_PUCKS {
+            if let Some(object) = self.pucks.pop_front() {
+                server.world.remove_puck(object);
+            }
+        }
+        let puck = &self.preset.pucks[self.next_puck];
+        if let Some(object) = puck.spawn(server) {
+            self.pucks.push_back(object);
+        }
+        self.next_puck = (self.next_puck + 1) % self.preset.pucks.len();
+    }
+
+    pub fn update(&mut self, server: &mut HQMServer) {
+        let elapsed = server.game_step.wrapping_sub(self.last_step);
+        if elapsed as f32 >= self.preset.interval * 100.0 {
+            self.send_puck(server);
+        }
+    }
+
+    /// Removes the pucks of the machine
+    pub fn stop(self, server: &mut HQMServer) {
+        for object in self.pucks {
+            server.world.remove_puck(object);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use migo_hqm_server::hqm_game::HQMRinkConfiguration;
+
+    #[test]
+    fn every_preset_loads() {
+        let rink = HQMRink::new(&HQMRinkConfiguration::default());
+        for name in BUILTIN_PRESETS {
+            let preset = load_preset(name, &rink).unwrap();
+            assert_eq!(preset.name, name);
+        }
+        assert!(load_preset("nothing", &rink).is_err());
+        // The format of preset files
+        let preset: HQMPuckMachinePreset = serde_json::from_str(
+            r#"{
+                "name": "wrist",
+                "interval": 2,
+                "pucks": [{"position": [15, 0.1, 12], "velocity": [0, 0, -20]}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(preset.pucks.len(), 1);
+    }
+
+    #[test]
+    fn builtin_presets_fit_the_rink() {
+        for config in [
+            r#"{}"#,
+            r#"{"width": 20, "length": 40, "corner_radius": 5}"#,
+        ] {
+            let config: HQMRinkConfiguration = serde_json::from_str(config).unwrap();
+            let rink = HQMRink::new(&config);
+            let goal = rink.blue_net.in_front(0.0, 0.0);
+            for name in ["slot", "point"] {
+                let preset = builtin_preset(name, &rink).unwrap();
+                for puck in preset.pucks {
+                    let [x, _, z] = puck.position;
+                    assert!((0.0..=rink.width).contains(&x), "{} {:?}", name, puck);
+                    assert!(
+                        z > goal.z && z < rink.blue_zone_blue_line.z,
+                        "{} {:?}",
+                        name,
+                        puck
+                    );
+                }
+            }
+            // Passed out from the corners to the slot
+            let slot = builtin_preset("slot", &rink).unwrap();
+            assert!(slot.pucks.iter().all(|puck| puck.velocity[2] > 0.0));
+            let point = builtin_preset("point", &rink).unwrap();
+            for puck in point.pucks {
+                let [x, _, z] = puck.position;
+                let [vx, _, vz] = puck.velocity;
+                // Aimed at the middle of the goal line
+                let t = (goal.z - z) / vz;
+                assert!((x + vx * t - goal.x).abs() < 0.01);
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use crate::hqm_parse::HQMMessageWriter;
+use crate::hqm_server::HQMServerPlayerIndex;
+
+/// Packet type of sound cues, both for requests from clients and the cues
+pub(crate) const SOUND_CUE: u8 = 0x45;
+
+/// Version of the sound cue packet. Clients send the version they understand in their request
+pub(crate) const SOUND_CUE_VERSION: u32 = 1;
+
+/// Shortest time between two quick-chat messages of a player
+pub(crate) const QUICK_CHAT_INTERVAL: Duration = Duration::from_secs(3);
+
+/// A signal of /qc, with the message it sends to the team and the number of the sound that
+/// clients which asked for sound cues play for it
+pub(crate) struct HQMQuickChatSignal {
+    pub(crate) name: &'static str,
+    pub(crate) message: &'static str,
+    pub(crate) sound: u8,
+}
+
+pub(crate) const QUICK_CHAT_SIGNALS: [HQMQuickChatSignal; 3] = [
+    HQMQuickChatSignal {
+        name: "pass",
+        message: "Pass!",
+        sound: 1,
+    },
+    HQMQuickChatSignal {
+        name: "open",
+        message: "I'm open!",
+        sound: 2,
+    },
+    HQMQuickChatSignal {
+        name: "sorry",
+        message: "Sorry!",
+        sound: 3,
+    },
+];
+
+pub(crate) fn find_signal(name: &str) -> Option<&'static HQMQuickChatSignal> {
+    QUICK_CHAT_SIGNALS
+        .iter()
+        .find(|signal| signal.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Writes the sound cue after the header and the packet type: the version as a 32-bit number,
+/// then the sound and the index of the player who sent the signal as bytes
+pub(crate) fn write_sound_cue(
+    writer: &mut HQMMessageWriter,
+    sound: u8,
+    sender_index: HQMServerPlayerIndex,
+) {
+    writer.write_u32_aligned(SOUND_CUE_VERSION);
+    writer.write_byte_aligned(sound);
+    writer.write_byte_aligned(sender_index.0 as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn signals_are_found_by_name() {
+        assert_eq!(find_signal("pass").map(|signal| signal.sound), Some(1));
+        assert_eq!(find_signal(" Open ").map(|signal| signal.sound), Some(2));
+        assert!(find_signal("").is_none());
+        assert!(find_signal("shoot").is_none());
+    }
+
+    #[test]
+    fn sound_cue_has_the_version_sound_and_sender() {
+        let mut buf = BytesMut::new();
+        let mut writer = HQMMessageWriter::new(&mut buf);
+        write_sound_cue(&mut writer, 3, HQMServerPlayerIndex(7));
+        assert_eq!(&buf[..], &[1, 0, 0, 0, 3, 7]);
+    }
+}
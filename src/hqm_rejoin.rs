@@ -0,0 +1,239 @@
+use std::net::IpAddr;
+use std::rc::Rc;
+
+use nalgebra::{Point3, Rotation3};
+use tracing::info;
+use uuid::Uuid;
+
+use migo_hqm_server::hqm_server::{HQMServer, HQMServerPlayerData, HQMServerPlayerIndex, HQMTeam};
+
+/// A player who disconnected during a game, whose spot on the team is held for a while
+#[derive(Clone)]
+struct HQMRejoinSpot {
+    player_name: Rc<String>,
+    ip: IpAddr,
+    team: HQMTeam,
+    /// The position picked with /sp, which nobody else on the team can take meanwhile
+    position: Option<&'static str>,
+    pos: Point3<f32>,
+    rot: Rotation3<f32>,
+    /// Steps until the spot is given up
+    time_left: u32,
+    /// The skater left on the ice until the player rejoins
+    skater: Option<(HQMServerPlayerIndex, Uuid)>,
+}
+
+/// Spots of players who disconnected or timed out in a game. A player who joins again with
+/// the same name from the same address within the grace time gets their spot back.
+#[derive(Default)]
+pub(crate) struct HQMRejoins {
+    spots: Vec<HQMRejoinSpot>,
+}
+
+impl HQMRejoins {
+    /// Holds the spot of a player who is about to leave, if they were on the ice
+    pub(crate) fn hold(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        grace: u32,
+        keep_skater: bool,
+        position: Option<&'static str>,
+    ) {
+        let player = match server.players.get(player_index) {
+            Some(player) => player,
+            None => return,
+        };
+        let ip = match &player.data {
+            HQMServerPlayerData::NetworkPlayer { data } => data.addr.ip(),
+            HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return,
+        };
+        let (object, team) = match player.object {
+            Some(object) => object,
+            None => return,
+        };
+        let (pos, rot) = match server.world.objects.get_skater(object) {
+            Some(skater) => (skater.body.pos, skater.body.rot),
+            None => return,
+        };
+        let player_name = player.player_name.clone();
+        let skater = if keep_skater {
+            server
+                .add_dummy(format!("{} (away)", player_name))
+                .and_then(|dummy| {
+                    if server.spawn_skater(dummy, team, pos, rot, false).is_none() {
+                        server.remove_player(dummy, true);
+                        return None;
+                    }
+                    Some((dummy, server.players.get(dummy)?.id))
+                })
+        } else {
+            None
+        };
+        info!(
+            "Holding the spot of {} ({}) on team {:?} for {} seconds",
+            player_name, player_index, team, grace
+        );
+        self.hold_spot(HQMRejoinSpot {
+            player_name,
+            ip,
+            team,
+            position,
+            pos,
+            rot,
+            time_left: grace * 100,
+            skater,
+        });
+    }
+
+    fn hold_spot(&mut self, spot: HQMRejoinSpot) {
+        self.spots
+            .retain(|held| held.player_name != spot.player_name);
+        self.spots.push(spot);
+    }
+
+    /// Gives up the spots whose grace time is over
+    pub(crate) fn tick(&mut self, server: &mut HQMServer) {
+        for spot in self.expire() {
+            info!("{} didn't rejoin in time", spot.player_name);
+            remove_skater(server, &spot);
+        }
+    }
+
+    fn expire(&mut self) -> Vec<HQMRejoinSpot> {
+        let mut expired = vec![];
+        self.spots.retain_mut(|spot| {
+            spot.time_left = spot.time_left.saturating_sub(1);
+            if spot.time_left > 0 {
+                return true;
+            }
+            expired.push(spot.clone());
+            false
+        });
+        expired
+    }
+
+    /// Spots held on a team, which nobody else can take
+    pub(crate) fn held_spots(&self, team: HQMTeam) -> usize {
+        self.spots.iter().filter(|spot| spot.team == team).count()
+    }
+
+    /// The positions of the players whose spots are held, with their names and teams
+    pub(crate) fn held_positions(&self) -> Vec<(Rc<String>, HQMTeam, &'static str)> {
+        self.spots
+            .iter()
+            .filter_map(|spot| Some((spot.player_name.clone(), spot.team, spot.position?)))
+            .collect()
+    }
+
+    /// Takes the spot held for a player who joined again, and returns the team, where the
+    /// skater was and the position the player had picked
+    pub(crate) fn take(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) -> Option<(HQMTeam, Point3<f32>, Rotation3<f32>, Option<&'static str>)> {
+        let player = server.players.get(player_index)?;
+        let ip = match &player.data {
+            HQMServerPlayerData::NetworkPlayer { data } => data.addr.ip(),
+            HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return None,
+        };
+        let spot = self.take_spot(&player.player_name, ip)?;
+        // The skater that was left on the ice can have been pushed around
+        let (pos, rot) = spot
+            .skater
+            .and_then(|(dummy, id)| {
+                let dummy = server.players.get(dummy).filter(|dummy| dummy.id == id)?;
+                let skater = server.world.objects.get_skater(dummy.object?.0)?;
+                Some((skater.body.pos, skater.body.rot))
+            })
+            .unwrap_or((spot.pos, spot.rot));
+        remove_skater(server, &spot);
+        Some((spot.team, pos, rot, spot.position))
+    }
+
+    fn take_spot(&mut self, player_name: &str, ip: IpAddr) -> Option<HQMRejoinSpot> {
+        let i = self
+            .spots
+            .iter()
+            .position(|spot| spot.player_name.as_str() == player_name && spot.ip == ip)?;
+        Some(self.spots.remove(i))
+    }
+
+    /// Forgets the spots when a new game starts, which also removes the skaters
+    pub(crate) fn clear(&mut self) {
+        self.spots.clear();
+    }
+}
+
+fn remove_skater(server: &mut HQMServer, spot: &HQMRejoinSpot) {
+    if let Some((dummy, id)) = spot.skater {
+        if server
+            .players
+            .get(dummy)
+            .is_some_and(|player| player.id == id)
+        {
+            server.remove_player(dummy, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spot(player_name: &str, ip: [u8; 4], position: Option<&'static str>) -> HQMRejoinSpot {
+        HQMRejoinSpot {
+            player_name: Rc::new(player_name.to_string()),
+            ip: IpAddr::from(ip),
+            team: HQMTeam::Red,
+            position,
+            pos: Point3::origin(),
+            rot: Rotation3::identity(),
+            time_left: 3,
+            skater: None,
+        }
+    }
+
+    #[test]
+    fn spot_is_held_until_the_grace_time_is_over() {
+        let mut rejoins = HQMRejoins::default();
+        rejoins.hold_spot(spot("migo", [10, 0, 0, 1], Some("C")));
+        rejoins.hold_spot(spot("migo", [10, 0, 0, 1], Some("LW")));
+        assert_eq!(rejoins.held_spots(HQMTeam::Red), 1);
+        assert_eq!(rejoins.held_spots(HQMTeam::Blue), 0);
+        assert_eq!(
+            rejoins.held_positions(),
+            vec![(Rc::new("migo".to_string()), HQMTeam::Red, "LW")]
+        );
+
+        assert!(rejoins.expire().is_empty());
+        assert!(rejoins.expire().is_empty());
+        let expired = rejoins.expire();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].player_name.as_str(), "migo");
+        assert_eq!(rejoins.held_spots(HQMTeam::Red), 0);
+        assert!(rejoins.held_positions().is_empty());
+    }
+
+    #[test]
+    fn rejoining_player_gets_their_position_back() {
+        let mut rejoins = HQMRejoins::default();
+        rejoins.hold_spot(spot("migo", [10, 0, 0, 1], Some("RD")));
+        rejoins.hold_spot(spot("ehl", [10, 0, 0, 2], None));
+
+        assert!(rejoins
+            .take_spot("migo", IpAddr::from([10, 0, 0, 3]))
+            .is_none());
+        let taken = rejoins
+            .take_spot("migo", IpAddr::from([10, 0, 0, 1]))
+            .unwrap();
+        assert_eq!((taken.team, taken.position), (HQMTeam::Red, Some("RD")));
+        assert!(rejoins
+            .take_spot("migo", IpAddr::from([10, 0, 0, 1]))
+            .is_none());
+        // Players without a position hold a spot but no position
+        assert_eq!(rejoins.held_spots(HQMTeam::Red), 1);
+        assert!(rejoins.held_positions().is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use tracing::info;
+use uuid::Uuid;
+
+use migo_hqm_server::hqm_server::{HQMServer, HQMServerPlayerIndex, HQMTeam};
+
+struct HQMRematchVoter {
+    id: Uuid,
+    team: HQMTeam,
+    vote: Option<bool>,
+}
+
+/// A vote among the players who were on the ice when the game ended, about keeping the teams
+/// for the next game. It passes when more than half of them vote yes.
+pub(crate) struct HQMRematchVote {
+    voters: HashMap<HQMServerPlayerIndex, HQMRematchVoter>,
+    result: Option<bool>,
+}
+
+impl HQMRematchVote {
+    /// Opens a vote if anyone is on the ice
+    pub(crate) fn open(server: &mut HQMServer) -> Option<Self> {
+        let voters: HashMap<_, _> = server
+            .players
+            .iter()
+            .filter_map(|(player_index, player)| {
+                let (_, team) = player.object?;
+                let voter = HQMRematchVoter {
+                    id: player.id,
+                    team,
+                    vote: None,
+                };
+                Some((player_index, voter))
+            })
+            .collect();
+        if voters.is_empty() {
+            return None;
+        }
+        for player_index in voters.keys() {
+            server.messages.add_directed_server_chat_message(
+                "Rematch? /rematch keeps the teams, /rematch no doesn't",
+                *player_index,
+            );
+        }
+        Some(HQMRematchVote {
+            voters,
+            result: None,
+        })
+    }
+
+    pub(crate) fn vote(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) {
+        let vote = match arg {
+            "" | "yes" | "y" => true,
+            "no" | "n" => false,
+            _ => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("Usage: /rematch [yes|no]", player_index);
+                return;
+            }
+        };
+        let voter = match self.voters.get_mut(&player_index) {
+            Some(voter) => voter,
+            None => {
+                server.messages.add_directed_server_chat_message(
+                    "Only players who played the game can vote",
+                    player_index,
+                );
+                return;
+            }
+        };
+        if self.result.is_some() {
+            server
+                .messages
+                .add_directed_server_chat_message("The vote is already decided", player_index);
+            return;
+        }
+        voter.vote = Some(vote);
+        if let Some(player) = server.players.get(player_index) {
+            let msg = format!(
+                "{} voted {} a rematch",
+                player.player_name,
+                if vote { "for" } else { "against" }
+            );
+            server.messages.add_server_chat_message(msg);
+        }
+        self.update_result(server);
+    }
+
+    pub(crate) fn remove_player(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+    ) {
+        if self.voters.remove(&player_index).is_some() {
+            self.update_result(server);
+        }
+    }
+
+    fn update_result(&mut self, server: &mut HQMServer) {
+        if self.result.is_some() {
+            return;
+        }
+        let count = |vote| {
+            self.voters
+                .values()
+                .filter(|voter| voter.vote == Some(vote))
+                .count()
+        };
+        let (yes, no) = (count(true), count(false));
+        let majority = self.voters.len() / 2 + 1;
+        if yes >= majority && yes > 0 {
+            self.result = Some(true);
+            info!("Rematch vote passed");
+            server
+                .messages
+                .add_server_chat_message("Rematch vote passed, the teams are kept");
+        } else if self.voters.is_empty() || no > self.voters.len() - majority {
+            self.result = Some(false);
+            info!("Rematch vote failed");
+            server
+                .messages
+                .add_server_chat_message("Rematch vote failed");
+        }
+    }
+
+    /// Ends the vote when the next game starts, and returns the players and their teams if it
+    /// passed. Players who didn't vote count as votes against.
+    pub(crate) fn finish(
+        self,
+        server: &mut HQMServer,
+    ) -> Option<Vec<(HQMServerPlayerIndex, Uuid, HQMTeam)>> {
+        if self.result.is_none() {
+            info!("Rematch vote failed");
+            server
+                .messages
+                .add_server_chat_message("Rematch vote failed");
+        }
+        if self.result == Some(true) {
+            Some(
+                self.voters
+                    .into_iter()
+                    .map(|(player_index, voter)| (player_index, voter.id, voter.team))
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,563 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+use bytes::BytesMut;
+
+use crate::hqm_game::HQMObjectIndex;
+use crate::hqm_object_selection::NETWORK_OBJECT_SLOTS;
+use crate::hqm_parse::{
+    write_message, write_objects, HQMMessageReader, HQMMessageWriter, HQMObjectPacket,
+    HQMPuckPacket, HQMSkaterPacket,
+};
+use crate::hqm_server::{replay_file_data, HQMMessage, HQMServer, HQMServerPlayerIndex, HQMTeam};
+
+/// Steps per second of a replay
+pub const REPLAY_STEPS_PER_SECOND: usize = 100;
+
+/// Player index that means no player in goal and chat messages
+const NO_PLAYER_INDEX: usize = 63;
+
+/// Highest player index of the network protocol. Players of the replay are shown with their
+/// index counted down from it, so that they don't collide with the players who are watching,
+/// who get the lowest free indices
+const MAX_PLAYER_INDEX: usize = NO_PLAYER_INDEX - 1;
+
+#[derive(Debug)]
+pub enum HQMReplayError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for HQMReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HQMReplayError::Io(e) => write!(f, "{}", e),
+            HQMReplayError::Invalid(reason) => write!(f, "not a valid replay, {}", reason),
+        }
+    }
+}
+
+/// One step of a replay, with the messages that were sent in it
+struct HQMReplayStep {
+    game_over: bool,
+    red_score: u32,
+    blue_score: u32,
+    time: u32,
+    goal_message_timer: u32,
+    period: u32,
+    packets: Vec<HQMObjectPacket>,
+    messages: Vec<HQMMessage>,
+}
+
+type HQMReplayPlayer = (Rc<String>, Option<(HQMObjectIndex, HQMTeam)>);
+
+/// Plays a replay file saved by the server to the players of a server, who watch it as
+/// spectators. Playback can be paused, sped up, slowed down and moved to any step.
+pub struct HQMReplayPlayback {
+    steps: Vec<HQMReplayStep>,
+    /// Step that is shown, with the fraction of the next step at speeds other than 1
+    position: f64,
+    speed: f64,
+    paused: bool,
+    /// The last step whose messages were sent
+    last_step: Option<usize>,
+    /// Players of the replay that the players watching know about, by their index in the replay
+    shown_players: HashMap<usize, HQMReplayPlayer>,
+}
+
+impl HQMReplayPlayback {
+    pub fn load(path: &Path) -> Result<Self, HQMReplayError> {
+        let data = std::fs::read(path).map_err(HQMReplayError::Io)?;
+        let steps = read_replay(&data)?;
+        if steps.is_empty() {
+            return Err(HQMReplayError::Invalid("it has no steps".to_string()));
+        }
+        Ok(HQMReplayPlayback {
+            steps,
+            position: 0.0,
+            speed: 1.0,
+            paused: false,
+            last_step: None,
+            shown_players: HashMap::new(),
+        })
+    }
+
+    /// Length of the replay in steps
+    pub fn duration(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position as usize
+    }
+
+    /// Period and time left of the period in hundredths of a second at the shown step
+    pub fn clock(&self) -> (u32, u32) {
+        let step = &self.steps[self.position()];
+        (step.period, step.time)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position() + 1 >= self.steps.len()
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    /// Moves playback to a step. The players of the replay are updated to how they were at
+    /// that step, but chat and goal messages of the skipped steps aren't sent
+    pub fn seek(&mut self, server: &mut HQMServer, position: usize) {
+        let position = position.min(self.steps.len() - 1);
+        let mut players = HashMap::new();
+        for step in &self.steps[..=position] {
+            for message in step.messages.iter() {
+                update_players(&mut players, message);
+            }
+        }
+        for (&index, (player_name, _)) in self.shown_players.iter() {
+            if !players.contains_key(&index) {
+                let message = player_update(index, player_name.clone(), None, false);
+                server.messages.add_global_message(message, true, false);
+            }
+        }
+        self.shown_players.clear();
+        for (index, (player_name, object)) in players {
+            let message = player_update(index, player_name.clone(), object, true);
+            server.messages.add_global_message(message, true, false);
+            self.shown_players.insert(index, (player_name, object));
+        }
+        self.position = position as f64;
+        self.last_step = Some(position);
+    }
+
+    /// Sends the players of the replay again, after the messages of the server were cleared
+    /// for a new game
+    pub fn show_players(&mut self, server: &mut HQMServer) {
+        for (&index, (player_name, object)) in self.shown_players.iter() {
+            let message = player_update(index, player_name.clone(), *object, true);
+            server.messages.add_global_message(message, true, false);
+        }
+    }
+
+    /// Advances playback by one server step, unless it's paused, and shows the step it's at.
+    /// Messages of every step that was passed are sent in order
+    pub fn step(&mut self, server: &mut HQMServer) {
+        if !self.paused {
+            let end = (self.steps.len() - 1) as f64;
+            self.position = (self.position + self.speed).min(end);
+        }
+        let position = self.position();
+        let first = self.last_step.map_or(0, |last_step| last_step + 1);
+        for step in first..=position {
+            for message in self.steps[step].messages.iter() {
+                update_players(&mut self.shown_players, message);
+                let (persistent, message) = shown_message(message);
+                server
+                    .messages
+                    .add_global_message(message, persistent, false);
+            }
+        }
+        self.last_step = Some(position);
+
+        let step = &self.steps[position];
+        server.values.game_over = step.game_over;
+        server.values.red_score = step.red_score;
+        server.values.blue_score = step.blue_score;
+        server.values.time = step.time;
+        server.values.goal_message_timer = step.goal_message_timer;
+        server.values.period = step.period;
+        server.playback_packets = Some(step.packets.clone());
+    }
+}
+
+fn shown_index(index: usize) -> HQMServerPlayerIndex {
+    HQMServerPlayerIndex(MAX_PLAYER_INDEX.saturating_sub(index))
+}
+
+fn player_update(
+    index: usize,
+    player_name: Rc<String>,
+    object: Option<(HQMObjectIndex, HQMTeam)>,
+    in_server: bool,
+) -> HQMMessage {
+    HQMMessage::PlayerUpdate {
+        player_name,
+        object,
+        player_index: shown_index(index),
+        in_server,
+    }
+}
+
+fn update_players(players: &mut HashMap<usize, HQMReplayPlayer>, message: &HQMMessage) {
+    if let HQMMessage::PlayerUpdate {
+        player_name,
+        object,
+        player_index,
+        in_server,
+    } = message
+    {
+        if *in_server {
+            players.insert(player_index.0, (player_name.clone(), *object));
+        } else {
+            players.remove(&player_index.0);
+        }
+    }
+}
+
+/// The message as it's sent to the players watching, and whether it's persistent
+fn shown_message(message: &HQMMessage) -> (bool, HQMMessage) {
+    let shown = |index: &Option<HQMServerPlayerIndex>| index.map(|index| shown_index(index.0));
+    match message {
+        HQMMessage::PlayerUpdate {
+            player_name,
+            object,
+            player_index,
+            in_server,
+        } => (
+            true,
+            player_update(player_index.0, player_name.clone(), *object, *in_server),
+        ),
+        HQMMessage::Goal {
+            team,
+            goal_player_index,
+            assist_player_index,
+        } => (
+            false,
+            HQMMessage::Goal {
+                team: *team,
+                goal_player_index: shown(goal_player_index),
+                assist_player_index: shown(assist_player_index),
+            },
+        ),
+        HQMMessage::Chat {
+            player_index,
+            message,
+        } => (
+            false,
+            HQMMessage::Chat {
+                player_index: shown(player_index),
+                message: message.clone(),
+            },
+        ),
+    }
+}
+
+/// Cuts clips out of a replay file, each of which is a replay file of its own. A clip starts
+/// with the players who were on the server at its first step, and the objects of the first
+/// step aren't relative to earlier steps. Ranges past the end of the replay are cut short
+pub(crate) fn cut_replay(
+    data: &[u8],
+    ranges: &[Range<usize>],
+) -> Result<Vec<bytes::Bytes>, HQMReplayError> {
+    let steps = read_replay(data)?;
+    let clips = ranges
+        .iter()
+        .map(|range| {
+            let end = range.end.min(steps.len());
+            let start = range.start.min(end);
+            write_clip(&steps, start..end)
+        })
+        .collect();
+    Ok(clips)
+}
+
+fn write_clip(steps: &[HQMReplayStep], range: Range<usize>) -> bytes::Bytes {
+    let mut players = HashMap::new();
+    for step in &steps[..range.start] {
+        for message in step.messages.iter() {
+            update_players(&mut players, message);
+        }
+    }
+    let mut players: Vec<_> = players.into_iter().collect();
+    players.sort_by_key(|(index, _)| *index);
+    let mut first_messages: Vec<_> = players
+        .into_iter()
+        .map(|(index, (player_name, object))| HQMMessage::PlayerUpdate {
+            player_name,
+            object,
+            player_index: HQMServerPlayerIndex(index),
+            in_server: true,
+        })
+        .collect();
+
+    let mut data = BytesMut::new();
+    let mut writer = HQMMessageWriter::new(&mut data);
+    let mut packets = VecDeque::with_capacity(2);
+    let mut message_pos = 0;
+    for (packet, step) in (0u32..).zip(&steps[range]) {
+        writer.write_byte_aligned(5);
+        writer.write_bits(1, u32::from(step.game_over));
+        writer.write_bits(8, step.red_score);
+        writer.write_bits(8, step.blue_score);
+        writer.write_bits(16, step.time);
+        writer.write_bits(16, step.goal_message_timer);
+        writer.write_bits(8, step.period);
+
+        packets.truncate(1);
+        packets.push_front(step.packets.clone());
+        let known_packet = packet.checked_sub(1).unwrap_or(u32::MAX);
+        write_objects(&mut writer, &packets, None, packet, known_packet);
+
+        let messages = std::mem::take(&mut first_messages);
+        let message_count = messages.len() + step.messages.len();
+        writer.write_bits(16, message_count as u32);
+        writer.write_bits(16, message_pos as u32);
+        for message in messages.iter().chain(step.messages.iter()) {
+            write_message(&mut writer, message);
+        }
+        message_pos += message_count;
+        writer.replay_fix();
+    }
+    replay_file_data(&data)
+}
+
+/// Reads the steps of a replay file, which has the same format as the update packets, see
+/// [`HQMServer::write_replay`]
+fn read_replay(data: &[u8]) -> Result<Vec<HQMReplayStep>, HQMReplayError> {
+    if data.len() < 8 {
+        return Err(HQMReplayError::Invalid("it's too short".to_string()));
+    }
+    let size = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let data = &data[8..];
+    if size > data.len() {
+        return Err(HQMReplayError::Invalid("it's cut off".to_string()));
+    }
+    let data = &data[..size];
+    let mut reader = HQMMessageReader::new(data);
+    let mut steps: Vec<HQMReplayStep> = vec![];
+    while reader.get_pos() < data.len() {
+        if reader.read_byte_aligned() != 5 {
+            return Err(HQMReplayError::Invalid(format!(
+                "step {} doesn't start with an update",
+                steps.len()
+            )));
+        }
+        let game_over = reader.read_bits(1) == 1;
+        let red_score = reader.read_bits(8);
+        let blue_score = reader.read_bits(8);
+        let time = reader.read_bits(16);
+        let goal_message_timer = reader.read_bits(16);
+        let period = reader.read_bits(8);
+
+        let current_packet = reader.read_u32_aligned();
+        let known_packet = reader.read_u32_aligned();
+        let old_packets = if known_packet == u32::MAX {
+            None
+        } else {
+            match current_packet.checked_sub(known_packet) {
+                Some(diff @ 1..=191) => steps
+                    .len()
+                    .checked_sub(diff as usize)
+                    .map(|index| &steps[index].packets),
+                _ => None,
+            }
+        };
+        let packets = read_objects(&mut reader, old_packets)?;
+
+        let message_count = reader.read_bits(16);
+        let _message_pos = reader.read_bits(16);
+        let mut messages = Vec::with_capacity(message_count as usize);
+        for _ in 0..message_count {
+            messages.push(read_message(&mut reader)?);
+        }
+        // The writer adds an empty byte when a step ends on a byte boundary
+        if reader.get_bit_pos() == 0 {
+            reader.read_byte_aligned();
+        }
+        reader.align();
+        steps.push(HQMReplayStep {
+            game_over,
+            red_score,
+            blue_score,
+            time,
+            goal_message_timer,
+            period,
+            packets,
+            messages,
+        });
+    }
+    Ok(steps)
+}
+
+/// Reads a position written by [`crate::hqm_parse::HQMMessageWriter::write_pos`], which is
+/// either the value or the difference to the old value
+fn read_pos(
+    reader: &mut HQMMessageReader,
+    bits: u8,
+    old: Option<u32>,
+) -> Result<u32, HQMReplayError> {
+    let diff_bits = match reader.read_bits(2) {
+        0 => 3,
+        1 => 6,
+        2 => 12,
+        _ => return Ok(reader.read_bits(bits)),
+    };
+    let diff = reader.read_bits_signed(diff_bits);
+    let old = old.ok_or_else(|| {
+        HQMReplayError::Invalid("a position refers to a missing old position".to_string())
+    })?;
+    Ok((old as i32 + diff).max(0) as u32)
+}
+
+fn read_objects(
+    reader: &mut HQMMessageReader,
+    old_packets: Option<&Vec<HQMObjectPacket>>,
+) -> Result<Vec<HQMObjectPacket>, HQMReplayError> {
+    let mut packets = Vec::with_capacity(NETWORK_OBJECT_SLOTS);
+    for i in 0..NETWORK_OBJECT_SLOTS {
+        let old_packet = old_packets.and_then(|old_packets| old_packets.get(i));
+        if reader.read_bits(1) == 0 {
+            packets.push(HQMObjectPacket::None);
+            continue;
+        }
+        match reader.read_bits(2) {
+            0 => {
+                let old = match old_packet {
+                    Some(HQMObjectPacket::Skater(old)) => Some(old),
+                    _ => None,
+                };
+                let pos = (
+                    read_pos(reader, 17, old.map(|old| old.pos.0))?,
+                    read_pos(reader, 17, old.map(|old| old.pos.1))?,
+                    read_pos(reader, 17, old.map(|old| old.pos.2))?,
+                );
+                let rot = (
+                    read_pos(reader, 31, old.map(|old| old.rot.0))?,
+                    read_pos(reader, 31, old.map(|old| old.rot.1))?,
+                );
+                let stick_pos = (
+                    read_pos(reader, 13, old.map(|old| old.stick_pos.0))?,
+                    read_pos(reader, 13, old.map(|old| old.stick_pos.1))?,
+                    read_pos(reader, 13, old.map(|old| old.stick_pos.2))?,
+                );
+                let stick_rot = (
+                    read_pos(reader, 25, old.map(|old| old.stick_rot.0))?,
+                    read_pos(reader, 25, old.map(|old| old.stick_rot.1))?,
+                );
+                let head_rot = read_pos(reader, 16, old.map(|old| old.head_rot))?;
+                let body_rot = read_pos(reader, 16, old.map(|old| old.body_rot))?;
+                packets.push(HQMObjectPacket::Skater(HQMSkaterPacket {
+                    pos,
+                    rot,
+                    stick_pos,
+                    stick_rot,
+                    head_rot,
+                    body_rot,
+                }));
+            }
+            1 => {
+                let old = match old_packet {
+                    Some(HQMObjectPacket::Puck(old)) => Some(old),
+                    _ => None,
+                };
+                let pos = (
+                    read_pos(reader, 17, old.map(|old| old.pos.0))?,
+                    read_pos(reader, 17, old.map(|old| old.pos.1))?,
+                    read_pos(reader, 17, old.map(|old| old.pos.2))?,
+                );
+                let rot = (
+                    read_pos(reader, 31, old.map(|old| old.rot.0))?,
+                    read_pos(reader, 31, old.map(|old| old.rot.1))?,
+                );
+                packets.push(HQMObjectPacket::Puck(HQMPuckPacket { pos, rot }));
+            }
+            object_type => {
+                return Err(HQMReplayError::Invalid(format!(
+                    "unknown object type {}",
+                    object_type
+                )))
+            }
+        }
+    }
+    Ok(packets)
+}
+
+fn read_player_index(reader: &mut HQMMessageReader) -> Option<HQMServerPlayerIndex> {
+    match reader.read_bits(6) as usize {
+        NO_PLAYER_INDEX => None,
+        index => Some(HQMServerPlayerIndex(index)),
+    }
+}
+
+fn read_team(team: u32) -> Option<HQMTeam> {
+    match team {
+        0 => Some(HQMTeam::Red),
+        1 => Some(HQMTeam::Blue),
+        _ => None,
+    }
+}
+
+fn read_text(reader: &mut HQMMessageReader, length: usize) -> String {
+    let bytes = (0..length)
+        .map(|_| reader.read_bits(7) as u8)
+        .collect::<Vec<_>>();
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(length);
+    let bytes = &bytes[..end];
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn read_message(reader: &mut HQMMessageReader) -> Result<HQMMessage, HQMReplayError> {
+    match reader.read_bits(6) {
+        0 => {
+            let player_index = HQMServerPlayerIndex(reader.read_bits(6) as usize);
+            let in_server = reader.read_bits(1) == 1;
+            let team = read_team(reader.read_bits(2));
+            let object_index = reader.read_bits(6) as usize;
+            let object = match team {
+                Some(team) if object_index < NETWORK_OBJECT_SLOTS => {
+                    Some((HQMObjectIndex(object_index), team))
+                }
+                _ => None,
+            };
+            // Names always take 31 characters, padded with zeros
+            let player_name = read_text(reader, 31);
+            Ok(HQMMessage::PlayerUpdate {
+                player_name: Rc::new(player_name),
+                object,
+                player_index,
+                in_server,
+            })
+        }
+        1 => {
+            let team = read_team(reader.read_bits(2))
+                .ok_or_else(|| HQMReplayError::Invalid("a goal has no team".to_string()))?;
+            let goal_player_index = read_player_index(reader);
+            let assist_player_index = read_player_index(reader);
+            Ok(HQMMessage::Goal {
+                team,
+                goal_player_index,
+                assist_player_index,
+            })
+        }
+        2 => {
+            let player_index = read_player_index(reader);
+            let size = reader.read_bits(6) as usize;
+            let message = read_text(reader, size);
+            Ok(HQMMessage::Chat {
+                player_index,
+                message: Cow::Owned(message),
+            })
+        }
+        message_type => Err(HQMReplayError::Invalid(format!(
+            "unknown message type {}",
+            message_type
+        ))),
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+use crate::hqm_game::{HQMGameValues, HQMPlayerInput};
+use crate::hqm_parse::{write_objects, HQMMessageWriter, HQMObjectPacket};
+
+/// Packet type of replay streams, both for requests from clients and the steps the server
+/// sends them
+pub(crate) const REPLAY_STREAM: u8 = 0x43;
+
+/// Version of the replay stream packets. Clients send the version they understand in their
+/// request
+pub(crate) const REPLAY_STREAM_VERSION: u32 = 1;
+
+/// Size of the input of a skater in a step: the player index, seven floats and the keys
+const INPUT_SIZE: usize = 1 + 7 * 4 + 4;
+
+/// A client that asked to be streamed every step of the game, for recording it locally.
+/// Steps that would go over the client's bandwidth are skipped
+pub(crate) struct HQMReplayStream {
+    /// Bytes per second
+    rate: u32,
+    /// Bytes that can be sent now, which grows by the rate every second up to one second's worth
+    budget: f64,
+    pub(crate) skipped_steps: u64,
+}
+
+impl HQMReplayStream {
+    pub(crate) fn new(rate: u32) -> Self {
+        HQMReplayStream {
+            rate,
+            budget: f64::from(rate),
+            skipped_steps: 0,
+        }
+    }
+
+    /// Lowers the rate to at most the given bytes per second
+    pub(crate) fn limit_rate(&mut self, rate: u32) {
+        self.rate = self.rate.min(rate);
+        self.budget = self.budget.min(f64::from(self.rate));
+    }
+
+    /// Called every step with the size of the step's packet. Returns true if it can be sent
+    pub(crate) fn allow(&mut self, len: usize) -> bool {
+        self.budget = (self.budget + f64::from(self.rate) / 100.0).min(f64::from(self.rate));
+        if self.budget >= len as f64 {
+            self.budget -= len as f64;
+            true
+        } else {
+            self.skipped_steps += 1;
+            false
+        }
+    }
+}
+
+/// Writes the answer to a request, with the granted rate in bytes per second, 0 if streaming
+/// is off
+pub(crate) fn write_replay_stream_answer(writer: &mut HQMMessageWriter, rate: u32) {
+    writer.write_u32_aligned(REPLAY_STREAM_VERSION);
+    writer.write_u32_aligned(0);
+    writer.write_u32_aligned(rate);
+}
+
+/// Writes one step of the stream. Unlike update packets, the objects are never relative to an
+/// earlier step, so that a skipped step doesn't affect the ones after it. The packet is kept
+/// within max_size: if the inputs of all skaters don't fit, the inputs that are left out go
+/// round with the game step, so that every skater's input is sent in some steps. Returns false
+/// if not even the objects fit.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_replay_stream_step(
+    writer: &mut HQMMessageWriter,
+    game_id: u32,
+    game_step: u32,
+    values: &HQMGameValues,
+    packets: &VecDeque<Vec<HQMObjectPacket>>,
+    current_packet: u32,
+    inputs: &[(usize, &HQMPlayerInput)],
+    max_size: usize,
+) -> bool {
+    writer.write_u32_aligned(REPLAY_STREAM_VERSION);
+    writer.write_u32_aligned(game_id);
+    writer.write_u32_aligned(game_step);
+    writer.write_byte_aligned(u8::from(values.game_over));
+    writer.write_byte_aligned(values.red_score as u8);
+    writer.write_byte_aligned(values.blue_score as u8);
+    writer.write_byte_aligned(values.period as u8);
+    writer.write_u32_aligned(values.time);
+    writer.write_u32_aligned(values.goal_message_timer);
+    write_objects(writer, packets, None, current_packet, u32::MAX);
+    // The number of inputs is a byte after the objects
+    let inputs_start = writer.bytes_written() + 1;
+    if inputs_start > max_size {
+        return false;
+    }
+    let count = inputs.len().min((max_size - inputs_start) / INPUT_SIZE);
+    let first = if count < inputs.len() {
+        game_step as usize % inputs.len()
+    } else {
+        0
+    };
+    writer.write_byte_aligned(count as u8);
+    for (player_index, input) in inputs.iter().cycle().skip(first).take(count) {
+        writer.write_byte_aligned(*player_index as u8);
+        writer.write_f32_aligned(input.stick_angle);
+        writer.write_f32_aligned(input.turn);
+        writer.write_f32_aligned(input.fwbw);
+        writer.write_f32_aligned(input.stick.x);
+        writer.write_f32_aligned(input.stick.y);
+        writer.write_f32_aligned(input.head_rot);
+        writer.write_f32_aligned(input.body_rot);
+        writer.write_u32_aligned(input.keys);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn inputs_that_dont_fit_are_left_out_in_turn() {
+        let input = HQMPlayerInput::default();
+        let inputs: Vec<_> = (0..10).map(|i| (i, &input)).collect();
+        let values = HQMGameValues::default();
+        let packets = VecDeque::from([vec![]]);
+        let write = |game_step, max_size| {
+            let mut buf = BytesMut::new();
+            let mut writer = HQMMessageWriter::new(&mut buf);
+            let fits = write_replay_stream_step(
+                &mut writer,
+                1,
+                game_step,
+                &values,
+                &packets,
+                0,
+                &inputs,
+                max_size,
+            );
+            (fits, buf)
+        };
+        let (fits, all) = write(0, 1000);
+        assert!(fits);
+        let inputs_start = all.len() - 10 * INPUT_SIZE;
+        assert_eq!(all[inputs_start - 1], 10);
+
+        // Room for three inputs
+        let (fits, some) = write(5, inputs_start + 3 * INPUT_SIZE + 1);
+        assert!(fits);
+        assert!(some.len() <= inputs_start + 3 * INPUT_SIZE + 1);
+        assert_eq!(some[inputs_start - 1], 3);
+        assert_eq!(some[inputs_start], 5);
+        assert_eq!(some[inputs_start + 2 * INPUT_SIZE], 7);
+
+        let (fits, _) = write(0, 10);
+        assert!(!fits);
+    }
+}
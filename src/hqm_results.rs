@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::hqm_server::HQMTeam;
+use crate::hqm_stats::{
+    HQMFaceoffRecord, HQMFinishedGame, HQMGoalRecord, HQMPenaltyRecord, HQMPlayerGameRecord,
+    HQMRuleCallRecord, HQMShootoutRecord,
+};
+
+/// Raised when fields of the report are removed or change meaning
+const REPORT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct HQMTeamReport<'a> {
+    score: u32,
+    shots: u32,
+    /// Ordered by points, then goals
+    players: Vec<&'a HQMPlayerGameRecord>,
+}
+
+/// Everything about a finished game, for leagues to read results from
+#[derive(Serialize)]
+struct HQMGameReport<'a> {
+    version: u32,
+    id: String,
+    server: &'a str,
+    started: String,
+    ended: String,
+    red_score: u32,
+    blue_score: u32,
+    /// None if the game ended in a tie
+    winner: Option<HQMTeam>,
+    red: HQMTeamReport<'a>,
+    blue: HQMTeamReport<'a>,
+    goals: &'a [HQMGoalRecord],
+    rule_calls: &'a [HQMRuleCallRecord],
+    penalties: &'a [HQMPenaltyRecord],
+    faceoffs: &'a [HQMFaceoffRecord],
+    /// None if the game didn't go to a shootout
+    shootout: Option<&'a HQMShootoutRecord>,
+    /// Seed of the random puck drop delays, None if pucks were dropped at once. A string,
+    /// because not all JSON readers keep every digit of a 64-bit number
+    drop_seed: Option<String>,
+}
+
+fn team_report(game: &HQMFinishedGame, team: HQMTeam) -> HQMTeamReport<'_> {
+    let mut players = game
+        .record
+        .players
+        .values()
+        .filter(|player| player.team == team)
+        .collect::<Vec<_>>();
+    players.sort_by(|a, b| {
+        (b.goals + b.assists, b.goals)
+            .cmp(&(a.goals + a.assists, a.goals))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    HQMTeamReport {
+        score: match team {
+            HQMTeam::Red => game.red_score,
+            HQMTeam::Blue => game.blue_score,
+        },
+        shots: game.record.shots(team),
+        players,
+    }
+}
+
+fn game_report(game: &HQMFinishedGame) -> HQMGameReport<'_> {
+    HQMGameReport {
+        version: REPORT_VERSION,
+        id: game.record.id.to_string(),
+        server: &game.server,
+        started: game.started.to_rfc3339(),
+        ended: game.ended.to_rfc3339(),
+        red_score: game.red_score,
+        blue_score: game.blue_score,
+        winner: match game.red_score.cmp(&game.blue_score) {
+            std::cmp::Ordering::Greater => Some(HQMTeam::Red),
+            std::cmp::Ordering::Less => Some(HQMTeam::Blue),
+            std::cmp::Ordering::Equal => None,
+        },
+        red: team_report(game, HQMTeam::Red),
+        blue: team_report(game, HQMTeam::Blue),
+        goals: &game.record.goals,
+        rule_calls: &game.record.rule_calls,
+        penalties: &game.record.penalties,
+        faceoffs: &game.record.faceoffs,
+        shootout: game.record.shootout.as_ref(),
+        drop_seed: game.record.drop_seed.map(|seed| seed.to_string()),
+    }
+}
+
+/// Writes the result of a finished game as JSON to a file of its own in the directory, named
+/// after the time the game ended and its ID
+pub(crate) fn export_game_result(directory: PathBuf, game: &HQMFinishedGame) {
+    let json = match serde_json::to_string_pretty(&game_report(game)) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!("Could not export result of game {}: {}", game.record.id, e);
+            return;
+        }
+    };
+    let file_name = format!(
+        "{}-{}.json",
+        game.ended.format("%Y%m%dT%H%M%S"),
+        game.record.id.simple()
+    );
+    tokio::spawn(async move {
+        let path = directory.join(&file_name);
+        // Written under another name first, so that readers never see half a file
+        let temp_path = directory.join(format!(".{}.tmp", file_name));
+        let result = async {
+            tokio::fs::create_dir_all(&directory).await?;
+            tokio::fs::write(&temp_path, json).await?;
+            tokio::fs::rename(&temp_path, &path).await
+        }
+        .await;
+        match result {
+            Ok(()) => info!("Exported game result to {}", path.display()),
+            Err(e) => warn!("Could not export game result to {}: {}", path.display(), e),
+        }
+    });
+}
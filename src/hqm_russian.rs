@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use tracing::info;
 
 use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
-use migo_hqm_server::hqm_game::{HQMGameValues, HQMPhysicsConfiguration};
+use migo_hqm_server::hqm_game::{HQMGameValues, HQMPhysicsProfile};
 use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex, HQMTeam};
 use migo_hqm_server::hqm_simulate;
 use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
@@ -25,14 +25,14 @@ enum HQMRussianStatus {
 
 pub(crate) struct HQMRussianBehaviour {
     attempts: u32,
-    physics_config: HQMPhysicsConfiguration,
+    physics_config: HQMPhysicsProfile,
     status: HQMRussianStatus,
     team_switch_timer: HashMap<HQMServerPlayerIndex, u32>,
     team_max: usize,
 }
 
 impl HQMRussianBehaviour {
-    pub fn new(attempts: u32, team_max: usize, physics_config: HQMPhysicsConfiguration) -> Self {
+    pub fn new(attempts: u32, team_max: usize, physics_config: HQMPhysicsProfile) -> Self {
         HQMRussianBehaviour {
             attempts,
             physics_config,
@@ -102,7 +102,7 @@ impl HQMRussianBehaviour {
                 player_count: &mut usize,
                 team_max: usize,
             ) {
-                let mid_z = server.world.rink.length / 2.0;
+                let mid_z = server.world.rink.center_line.z;
                 let z = match team {
                     HQMTeam::Red => mid_z + 12.0,
                     HQMTeam::Blue => mid_z - 12.0,
@@ -152,11 +152,11 @@ impl HQMRussianBehaviour {
     fn place_puck_for_team(&mut self, server: &mut HQMServer, team: HQMTeam) {
         server.world.clear_pucks();
 
-        let z = match team {
-            HQMTeam::Red => 55.0,
-            HQMTeam::Blue => 6.0,
+        let net = match team {
+            HQMTeam::Red => &server.world.rink.red_net,
+            HQMTeam::Blue => &server.world.rink.blue_net,
         };
-        let puck_pos = Point3::new(server.world.rink.width / 2.0, 0.5, z);
+        let puck_pos = net.in_front(2.0, 0.5);
 
         server
             .world
@@ -236,12 +236,12 @@ impl HQMRussianBehaviour {
 
         let rot = Rotation3::from_euler_angles(0.0, 3.0 * FRAC_PI_2, 0.0);
         for (index, player_index) in red_players.into_iter().enumerate() {
-            let z = (server.world.rink.length / 2.0) + (12.0 + index as f32);
+            let z = server.world.rink.center_line.z + (12.0 + index as f32);
             let pos = Point3::new(0.5, 2.0, z);
             server.spawn_skater(player_index, HQMTeam::Red, pos, rot.clone(), false);
         }
         for (index, player_index) in blue_players.into_iter().enumerate() {
-            let z = (server.world.rink.length / 2.0) - (12.0 + index as f32);
+            let z = server.world.rink.center_line.z - (12.0 + index as f32);
             let pos = Point3::new(0.5, 2.0, z);
             server.spawn_skater(player_index, HQMTeam::Blue, pos, rot.clone(), false);
         }
@@ -270,17 +270,8 @@ impl HQMRussianBehaviour {
     }
 
     fn reset_game(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                info!("{} ({}) reset game", player.player_name, player_index);
-                let msg = format!("Game reset by {}", player.player_name);
-
-                server.new_game(self.get_initial_game_values());
-
-                server.messages.add_server_chat_message(msg);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            server.reset_game_by(&source, self.get_initial_game_values());
         }
     }
 
@@ -0,0 +1,226 @@
+use std::net::SocketAddr;
+use std::ops::Range;
+use tokio::net::UdpSocket;
+use tracing::debug;
+
+/// Most packets sent with one sendmmsg call
+#[cfg(target_os = "linux")]
+const MAX_BATCH_SIZE: usize = 64;
+
+#[derive(Debug, Default)]
+pub(crate) struct HQMSendStats {
+    pub(crate) packets: u64,
+    pub(crate) system_calls: u64,
+}
+
+/// Packets collected during a tick, so that they can be sent together with as few system calls
+/// as possible. On Linux they're sent with sendmmsg, elsewhere one at a time
+#[derive(Default)]
+pub(crate) struct HQMSendBatch {
+    data: Vec<u8>,
+    packets: Vec<(SocketAddr, Range<usize>)>,
+    pub(crate) stats: HQMSendStats,
+    /// Set if sendmmsg turned out not to be available, like in some sandboxes
+    one_at_a_time: bool,
+}
+
+impl HQMSendBatch {
+    pub(crate) fn push(&mut self, addr: SocketAddr, packet: &[u8]) {
+        let start = self.data.len();
+        self.data.extend_from_slice(packet);
+        self.packets.push((addr, start..self.data.len()));
+    }
+
+    /// Sends all collected packets. If the socket's send buffer is full, waits until it can
+    /// be written to again instead of dropping packets
+    pub(crate) async fn send(&mut self, socket: &UdpSocket) {
+        let mut sent = 0;
+        while sent < self.packets.len() {
+            sent += if self.one_at_a_time {
+                self.send_one(socket, sent).await
+            } else {
+                self.send_from(socket, sent).await
+            };
+        }
+        self.stats.packets += self.packets.len() as u64;
+        self.data.clear();
+        self.packets.clear();
+    }
+
+    /// Sends some of the packets from index start, returns how many were sent or skipped. Sends
+    /// none if sendmmsg isn't available, the packets are then sent one at a time
+    #[cfg(target_os = "linux")]
+    async fn send_from(&mut self, socket: &UdpSocket, start: usize) -> usize {
+        use std::os::unix::io::AsRawFd;
+        use tokio::io::Interest;
+
+        let end = self.packets.len().min(start + MAX_BATCH_SIZE);
+        let packets = &self.packets[start..end];
+        let mut addrs: Vec<_> = packets.iter().map(|(addr, _)| to_sockaddr(addr)).collect();
+        let data = &self.data;
+        let system_calls = &mut self.stats.system_calls;
+        loop {
+            if socket.writable().await.is_err() {
+                return packets.len();
+            }
+            let result = socket.try_io(Interest::WRITABLE, || {
+                // The headers point into addrs and data, so they're made right before the call
+                let mut iovecs: Vec<_> = packets
+                    .iter()
+                    .map(|(_, range)| libc::iovec {
+                        iov_base: data[range.clone()].as_ptr() as *mut libc::c_void,
+                        iov_len: range.len(),
+                    })
+                    .collect();
+                let mut headers: Vec<_> = addrs
+                    .iter_mut()
+                    .zip(iovecs.iter_mut())
+                    .map(|((addr, addr_len), iovec)| {
+                        // SAFETY: mmsghdr is plain data, for which all zeroes is valid
+                        let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+                        header.msg_hdr.msg_name = addr as *mut _ as *mut libc::c_void;
+                        header.msg_hdr.msg_namelen = *addr_len;
+                        header.msg_hdr.msg_iov = iovec;
+                        header.msg_hdr.msg_iovlen = 1;
+                        header
+                    })
+                    .collect();
+                *system_calls += 1;
+                // SAFETY: the socket is open for as long as it's borrowed. There is one header for
+                // each of at most MAX_BATCH_SIZE packets, so the length fits. Each header points to
+                // its address in addrs with the length of it, and to one iovec in iovecs, which
+                // points to the packet in data. None of them are moved or dropped before the call
+                // returns, and the kernel only reads from the packets, even though iov_base is a
+                // mutable pointer. It writes only the number of bytes sent into the headers
+                let n = unsafe {
+                    libc::sendmmsg(
+                        socket.as_raw_fd(),
+                        headers.as_mut_ptr(),
+                        headers.len() as libc::c_uint,
+                        0,
+                    )
+                };
+                if n < 0 {
+                    Err(std::io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+            match result {
+                Ok(n) => return n.max(1),
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+                    debug!("sendmmsg is not available, sending packets one at a time");
+                    self.one_at_a_time = true;
+                    return 0;
+                }
+                Err(e) => {
+                    // The first packet couldn't be sent, skip it and send the rest
+                    let addr = packets[0].0;
+                    debug!(%addr, error = %e, "Could not send packet to {}: {}", addr, e);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn send_from(&mut self, socket: &UdpSocket, start: usize) -> usize {
+        self.send_one(socket, start).await
+    }
+
+    /// Sends the packet at an index on its own, returns 1 whether it was sent or skipped
+    async fn send_one(&mut self, socket: &UdpSocket, index: usize) -> usize {
+        let (addr, range) = &self.packets[index];
+        self.stats.system_calls += 1;
+        if let Err(e) = socket.send_to(&self.data[range.clone()], addr).await {
+            debug!(%addr, error = %e, "Could not send packet to {}: {}", addr, e);
+        }
+        1
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: sockaddr_storage is plain data, for which all zeroes is valid. The fields that
+    // aren't written below stay zero, as the kernel expects of padding
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sockaddr = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: addr.port().to_be(),
+                sin_addr: libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            // SAFETY: sockaddr_storage is large enough and aligned for any address, so the pointer
+            // is valid to write a sockaddr_in to
+            unsafe { std::ptr::write(&mut storage as *mut _ as *mut libc::sockaddr_in, sockaddr) };
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sockaddr = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: addr.port().to_be(),
+                sin6_flowinfo: addr.flowinfo(),
+                sin6_addr: libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                },
+                sin6_scope_id: addr.scope_id(),
+            };
+            // SAFETY: sockaddr_storage is large enough and aligned for any address, so the pointer
+            // is valid to write a sockaddr_in6 to
+            unsafe { std::ptr::write(&mut storage as *mut _ as *mut libc::sockaddr_in6, sockaddr) };
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends three packets to a socket of its own and returns what arrived
+    async fn send_and_receive(batch: &mut HQMSendBatch) -> Vec<Vec<u8>> {
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = receiver.local_addr().unwrap();
+        batch.push(addr, b"first");
+        batch.push(addr, b"second");
+        batch.push(addr, b"third");
+        batch.send(&sender).await;
+
+        let mut received = vec![];
+        let mut buf = [0u8; 64];
+        for _ in 0..3 {
+            let (n, _) = receiver.recv_from(&mut buf).await.unwrap();
+            received.push(buf[..n].to_vec());
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn batch_sends_every_packet_in_order() {
+        let mut batch = HQMSendBatch::default();
+        let received = send_and_receive(&mut batch).await;
+        assert_eq!(received, [&b"first"[..], b"second", b"third"]);
+        assert_eq!(batch.stats.packets, 3);
+        #[cfg(target_os = "linux")]
+        assert_eq!(batch.stats.system_calls, 1);
+    }
+
+    #[tokio::test]
+    async fn packets_are_sent_one_at_a_time_without_sendmmsg() {
+        let mut batch = HQMSendBatch {
+            one_at_a_time: true,
+            ..Default::default()
+        };
+        let received = send_and_receive(&mut batch).await;
+        assert_eq!(received, [&b"first"[..], b"second", b"third"]);
+        assert_eq!(batch.stats.packets, 3);
+        assert_eq!(batch.stats.system_calls, 3);
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::{Display, Formatter};
+
+use migo_hqm_server::hqm_server::HQMTeam;
+
+/// A best-of series of games. The series is over when a team has won more than half of the
+/// games, and a new series starts with the next game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HQMSeries {
+    pub(crate) best_of: u32,
+    red_wins: u32,
+    blue_wins: u32,
+}
+
+impl HQMSeries {
+    pub(crate) fn new(best_of: u32) -> Self {
+        HQMSeries {
+            best_of,
+            red_wins: 0,
+            blue_wins: 0,
+        }
+    }
+
+    /// Game number of the next game, starting at 1
+    pub(crate) fn next_game(&self) -> u32 {
+        self.red_wins + self.blue_wins + 1
+    }
+
+    pub(crate) fn add_win(&mut self, team: HQMTeam) {
+        match team {
+            HQMTeam::Red => self.red_wins += 1,
+            HQMTeam::Blue => self.blue_wins += 1,
+        }
+    }
+
+    pub(crate) fn winner(&self) -> Option<HQMTeam> {
+        let wins_needed = self.best_of / 2 + 1;
+        if self.red_wins >= wins_needed {
+            Some(HQMTeam::Red)
+        } else if self.blue_wins >= wins_needed {
+            Some(HQMTeam::Blue)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for HQMSeries {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (high, low) = (
+            self.red_wins.max(self.blue_wins),
+            self.red_wins.min(self.blue_wins),
+        );
+        let leader = if self.red_wins > self.blue_wins {
+            HQMTeam::Red
+        } else {
+            HQMTeam::Blue
+        };
+        match self.winner() {
+            Some(winner) => write!(f, "{} wins the series {}-{}", winner, high, low),
+            None if high == low => write!(f, "Series tied {}-{}", high, low),
+            None => write!(f, "{} leads the series {}-{}", leader, high, low),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hqm_series::HQMSeries;
+    use migo_hqm_server::hqm_server::HQMTeam;
+
+    #[test]
+    fn series_is_won_by_majority_of_games() {
+        let mut series = HQMSeries::new(5);
+        series.add_win(HQMTeam::Blue);
+        series.add_win(HQMTeam::Red);
+        series.add_win(HQMTeam::Blue);
+        assert_eq!(series.winner(), None);
+        assert_eq!(series.to_string(), "Blue leads the series 2-1");
+        assert_eq!(series.next_game(), 4);
+        series.add_win(HQMTeam::Blue);
+        assert_eq!(series.winner(), Some(HQMTeam::Blue));
+        assert_eq!(series.to_string(), "Blue wins the series 3-1");
+    }
+}
@@ -1,46 +1,102 @@
 use std::borrow::Cow;
 use std::cmp::min;
-use std::collections::{HashSet, VecDeque};
-use std::error::Error;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{Debug, Display, Formatter};
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::net::{IpAddr, SocketAddr};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+pub use crate::hqm_admin_commands::HQMAdminSource;
+use crate::hqm_alerts::{HQMAlertConfig, HQMAlertKind, HQMAlerts};
+use crate::hqm_autosave::{HQMAutosave, HQMAutosaveFile};
 pub use crate::hqm_behaviour::HQMServerBehaviour;
+use crate::hqm_boards::{HQMBoards, RINK_INFO, RINK_INFO_VERSION};
+use crate::hqm_checksum::{
+    world_checksum, write_world_checksum, HQMChecksums, WORLD_CHECKSUM, WORLD_CHECKSUM_INTERVAL,
+    WORLD_CHECKSUM_VERSION,
+};
+use crate::hqm_console::{run_console, HQMConsoleCommand, HQMConsoleConfiguration};
+use crate::hqm_countdown::HQMChatCountdown;
+use crate::hqm_event_feed::{run_event_feed, HQMEvent, HQMEventFeed};
+use crate::hqm_extended_rules::{extended_rules_bits, EXTENDED_RULES_VERSION};
+use crate::hqm_game_log::GAME_LOG;
+use crate::hqm_highlights::{save_highlights, HQMHighlightTime, HQMHighlightTracker};
+use crate::hqm_input_monitor::HQMInputMonitor;
+use crate::hqm_master_server::{HQMMasterServer, HQMMasterServerNotifier};
+use crate::hqm_movement_monitor::HQMMovementMonitor;
+use crate::hqm_names::{closest_name, normalize_name, unique_name};
+use crate::hqm_network_quality::{HQMNetworkLimits, HQMNetworkQuality, KICK_SECONDS, WARN_SECONDS};
+use crate::hqm_object_selection::{
+    object_position, select_objects, HQMObjectSlots, NETWORK_OBJECT_SLOTS,
+};
+use crate::hqm_packet_size::{HQMPacketSizeProbe, MIN_PACKET_SIZE};
+use crate::hqm_preferences::HQMPreferences;
+use crate::hqm_quick_chat::{
+    find_signal, write_sound_cue, QUICK_CHAT_INTERVAL, QUICK_CHAT_SIGNALS, SOUND_CUE,
+    SOUND_CUE_VERSION,
+};
+use crate::hqm_replay_stream::{
+    write_replay_stream_answer, write_replay_stream_step, HQMReplayStream, REPLAY_STREAM,
+    REPLAY_STREAM_VERSION,
+};
+use crate::hqm_results::export_game_result;
+use crate::hqm_send_batch::HQMSendBatch;
+use crate::hqm_snapshot::HQMGameSnapshot;
+use crate::hqm_stats::{
+    HQMFaceoffRecord, HQMFinishedGame, HQMGameRecord, HQMGoalRecord, HQMMatchup, HQMPenaltyRecord,
+    HQMRuleCallRecord, HQMShootoutRecord, HQMShotRecord, HQMStatsReceiver, HQMStatsStore,
+    HQMTopCategory,
+};
+use crate::hqm_stats_page::{run_stats_page, HQMServerStatus, GAME_PAGE_PATH};
+use crate::hqm_webhook::run_webhook;
 use bytes::{BufMut, BytesMut};
 use chrono::{DateTime, Utc};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
 use nalgebra::{Point3, Rotation3};
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::net::UdpSocket;
 use tokio::time::MissedTickBehavior;
-use tracing::{info, warn};
+use tracing::{debug, info, info_span, warn};
 use uuid::Uuid;
 
 use async_stream::stream;
 use futures::StreamExt;
 
 use crate::hqm_game::{
-    HQMGameValues, HQMGameWorld, HQMObjectIndex, HQMPhysicsConfiguration, HQMPlayerInput,
-    HQMRulesState, HQMSkater, HQMSkaterHand,
+    HQMGameValues, HQMGameWorld, HQMObjectIndex, HQMPhysicsProfile, HQMPlayerInput,
+    HQMRinkConfiguration, HQMRulesState, HQMSkater, HQMSkaterHand, MAX_PUCKS,
 };
 use crate::hqm_parse;
 use crate::hqm_parse::{
-    write_message, write_objects, HQMClientToServerMessage, HQMMessageCodec, HQMMessageWriter,
-    HQMObjectPacket,
+    delayed_packet, message_bits, write_delayed_objects, write_message, HQMClientToServerMessage,
+    HQMMessageCodec, HQMMessageWriter, HQMObjectPacket,
 };
 
 pub(crate) const GAME_HEADER: &[u8] = b"Hock";
 
+/// Length of an instant replay when /replay is used without a number of seconds
+const DEFAULT_INSTANT_REPLAY_SECONDS: u32 = 5;
+
+/// Steps before a player who is away is moved to the spectators that they are warned
+const AFK_WARNING_STEPS: u32 = 1000;
+
+/// Longest feed delay of spectators in seconds, every step of it is kept in memory
+pub const MAX_SPECTATOR_DELAY: u32 = 120;
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum HQMClientVersion {
     Vanilla,
     Ping,
     PingRules,
+    PingRulesCompression,
 }
 
 impl HQMClientVersion {
@@ -49,6 +105,7 @@ impl HQMClientVersion {
             HQMClientVersion::Vanilla => false,
             HQMClientVersion::Ping => true,
             HQMClientVersion::PingRules => true,
+            HQMClientVersion::PingRulesCompression => true,
         }
     }
 
@@ -57,6 +114,16 @@ impl HQMClientVersion {
             HQMClientVersion::Vanilla => false,
             HQMClientVersion::Ping => false,
             HQMClientVersion::PingRules => true,
+            HQMClientVersion::PingRulesCompression => true,
+        }
+    }
+
+    pub(crate) fn has_compression(self) -> bool {
+        match self {
+            HQMClientVersion::Vanilla => false,
+            HQMClientVersion::Ping => false,
+            HQMClientVersion::PingRules => false,
+            HQMClientVersion::PingRulesCompression => true,
         }
     }
 }
@@ -257,7 +324,12 @@ impl HQMServerMessages {
         self.add_global_message(message, true, true);
     }
 
-    fn add_global_message(&mut self, message: HQMMessage, persistent: bool, replay: bool) {
+    pub(crate) fn add_global_message(
+        &mut self,
+        message: HQMMessage,
+        persistent: bool,
+        replay: bool,
+    ) {
         let rc = Rc::new(message);
         if replay {
             self.replay_messages.push(rc.clone());
@@ -280,6 +352,8 @@ pub struct HQMServer {
     pub players: HQMServerPlayerList,
     pub messages: HQMServerMessages,
     pub(crate) ban_list: HashSet<std::net::IpAddr>,
+    /// Hand and position the players picked, restored when they join again
+    pub(crate) preferences: HQMPreferences,
     pub(crate) allow_join: bool,
     pub config: HQMServerConfiguration,
     pub values: HQMGameValues,
@@ -289,6 +363,8 @@ pub struct HQMServer {
     game_id: u32,
     pub game_step: u32,
     pub is_muted: bool,
+    /// Stick inputs are ignored while a faceoff waits for the puck to drop
+    pub(crate) sticks_held: bool,
     pub start_time: DateTime<Utc>,
     reqwest_client: reqwest::Client,
 
@@ -298,12 +374,54 @@ pub struct HQMServer {
     replay_data: BytesMut,
     replay_msg_pos: usize,
     replay_last_packet: u32,
-
-    saved_packets: VecDeque<[HQMObjectPacket; 32]>,
+    /// Number of steps in replay_data
+    replay_steps: usize,
+    highlights: HQMHighlightTracker,
+
+    saved_packets: VecDeque<Vec<HQMObjectPacket>>,
+    /// The header values sent with each of the saved packets
+    saved_steps: VecDeque<HQMSavedStep>,
+    /// Objects of a replay that is played back, sent instead of the objects of the world
+    pub(crate) playback_packets: Option<Vec<HQMObjectPacket>>,
+    /// Packet the last world checksums were sent with
+    last_world_checksum: u32,
     saved_pings: VecDeque<Instant>,
     saved_history: VecDeque<ReplayTick>,
 
     pub history_length: usize,
+
+    chat_history: VecDeque<String>,
+
+    pub compression_stats: HQMCompressionStats,
+    send_batch: HQMSendBatch,
+
+    pub(crate) standby_game: Option<HQMStandbyGame>,
+
+    shutdown: Option<HQMShutdown>,
+    completed_games: u32,
+    pub(crate) physics_override: Option<HQMPhysicsProfile>,
+    pub(crate) master_servers: Vec<HQMMasterServerNotifier>,
+    pending_replay_saves: Vec<tokio::task::JoinHandle<()>>,
+    packet_rate_limiter: HQMPacketRateLimiter,
+    join_challenge: HQMJoinChallenge,
+    event_feed: HQMEventFeed,
+    stats: Option<HQMStatsStore>,
+    alerts: HQMAlerts,
+    last_network_check: Instant,
+    /// Statistics of the current game, saved in the statistics database when the game is over
+    pub(crate) game_record: HQMGameRecord,
+    /// Status shown by the HTTP API, if it's enabled
+    status: Option<tokio::sync::watch::Sender<HQMServerStatus>>,
+    autosave: HQMAutosave,
+    /// Game loaded from the snapshot file with `--resume`, restored when the first game starts
+    resume_snapshot: Option<HQMGameSnapshot>,
+    last_failed_objects_warning: Option<Instant>,
+    /// The simulation is frozen by /freeze, and only runs for the ticks requested with /step
+    frozen: bool,
+    pending_steps: u32,
+    boards: HQMBoards,
+    /// Sound cues of quick-chat signals waiting to be sent, with the address of the client
+    sound_cues: Vec<(SocketAddr, u8, HQMServerPlayerIndex)>,
 }
 
 impl HQMServer {
@@ -315,12 +433,17 @@ impl HQMServer {
         behaviour: &mut B,
         write_buf: &mut BytesMut,
     ) {
+        let limit = self.config.packet_rate_limit;
+        if !self.packet_rate_limiter.allow(addr, limit, Instant::now()) {
+            return;
+        }
         match command {
             HQMClientToServerMessage::Join {
                 version,
                 player_name,
             } => {
-                self.player_join(addr, version, player_name, behaviour);
+                self.player_join(socket, addr, version, player_name, behaviour, write_buf)
+                    .await;
             }
             HQMClientToServerMessage::Update {
                 current_game_id,
@@ -346,7 +469,276 @@ impl HQMServer {
                 self.request_info(socket, addr, version, ping, behaviour, write_buf)
                     .await;
             }
+            HQMClientToServerMessage::RinkInfo { version } => {
+                self.request_rink_info(socket, addr, version, write_buf)
+                    .await;
+            }
+            HQMClientToServerMessage::WorldChecksum { version } => {
+                self.request_world_checksum(addr, version);
+            }
+            HQMClientToServerMessage::ExtendedRules { version } => {
+                self.request_extended_rules(addr, version);
+            }
+            HQMClientToServerMessage::WorldChecksumReport {
+                game_step,
+                checksum,
+            } => self.report_world_checksum(addr, game_step, checksum),
+            HQMClientToServerMessage::ReplayStream { version, rate } => {
+                self.request_replay_stream(socket, addr, version, rate, write_buf)
+                    .await;
+            }
+            HQMClientToServerMessage::SoundCues { version } => {
+                self.request_sound_cues(addr, version);
+            }
+        }
+    }
+
+    /// Clients that can show the rink info ask for it after joining, and are sent it again
+    /// whenever it changes
+    async fn request_rink_info(
+        &mut self,
+        socket: &UdpSocket,
+        addr: SocketAddr,
+        version: u32,
+        write_buf: &mut BytesMut,
+    ) {
+        if version < RINK_INFO_VERSION {
+            return;
+        }
+        if let Some(player_index) = self.find_player_slot(addr) {
+            if let Some(player) = self.players.get_mut(player_index) {
+                match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => data.rink_info = true,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {}
+                }
+                self.boards
+                    .update(&self.config.boards, self.config.board_rotation);
+                send_rink_info(socket, addr, &self.boards, write_buf).await;
+            }
+        }
+    }
+
+    /// Clients that can compute world checksums ask for them after joining, and are sent the
+    /// checksum of the objects they were sent every second
+    fn request_world_checksum(&mut self, addr: SocketAddr, version: u32) {
+        if version < WORLD_CHECKSUM_VERSION {
+            return;
+        }
+        if let Some(player_index) = self.find_player_slot(addr) {
+            if let Some(player) = self.players.get_mut(player_index) {
+                match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => {
+                        if data.world_checksums.is_none() {
+                            data.world_checksums = Some(HQMChecksums::new());
+                        }
+                    }
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {}
+                }
+            }
+        }
+    }
+
+    /// Clients that understand the extended rules bits ask for them after joining
+    fn request_extended_rules(&mut self, addr: SocketAddr, version: u32) {
+        if version < EXTENDED_RULES_VERSION {
+            return;
+        }
+        if let Some(player_index) = self.find_player_slot(addr) {
+            if let Some(player) = self.players.get_mut(player_index) {
+                match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => data.extended_rules = true,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {}
+                }
+            }
+        }
+    }
+
+    /// Clients that can play the sounds of quick-chat signals ask for sound cues after joining
+    fn request_sound_cues(&mut self, addr: SocketAddr, version: u32) {
+        if version < SOUND_CUE_VERSION {
+            return;
+        }
+        if let Some(player_index) = self.find_player_slot(addr) {
+            if let Some(player) = self.players.get_mut(player_index) {
+                match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => data.sound_cues = true,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {}
+                }
+            }
+        }
+    }
+
+    /// Compares the checksum a client computed for a step with the one it was sent, so that
+    /// prediction and desync problems of clients can be found in the log
+    fn report_world_checksum(&mut self, addr: SocketAddr, game_step: u32, checksum: u32) {
+        if let Some(player_index) = self.find_player_slot(addr) {
+            if let Some(player) = self.players.get_mut(player_index) {
+                let checksums = match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => &mut data.world_checksums,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return,
+                };
+                let mismatch = checksums.as_mut().and_then(|checksums| {
+                    let expected = checksums.check(game_step, checksum)?;
+                    checksums
+                        .should_warn(Instant::now())
+                        .then_some((expected, checksums.mismatches))
+                });
+                if let Some((expected, mismatches)) = mismatch {
+                    warn!(
+                        "World checksum mismatch for {} ({}) in step {}: sent {:08x}, client has {:08x} ({} mismatches)",
+                        player.player_name,
+                        player_index,
+                        game_step,
+                        expected,
+                        checksum,
+                        mismatches
+                    );
+                }
+            }
+        }
+    }
+
+    async fn send_world_checksums(
+        &mut self,
+        socket: &UdpSocket,
+        game_step: u32,
+        write_buf: &mut BytesMut,
+    ) {
+        let packets = match self.saved_packets.front() {
+            Some(packets) => packets,
+            None => return,
+        };
+        // Without object selection, every client sees the same objects
+        let mut shared_checksum = None;
+        for (_, player) in self.players.iter_mut() {
+            let data = match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => continue,
+            };
+            let checksums = match &mut data.world_checksums {
+                Some(checksums) if data.game_id == self.game_id => checksums,
+                _ => continue,
+            };
+            let checksum = match data.object_slots.front() {
+                Some(slots) if packets.len() > NETWORK_OBJECT_SLOTS => {
+                    world_checksum(packets, Some(slots))
+                }
+                _ => *shared_checksum.get_or_insert_with(|| world_checksum(packets, None)),
+            };
+            checksums.record_sent(game_step, checksum);
+
+            write_buf.clear();
+            let mut writer = HQMMessageWriter::new(write_buf);
+            writer.write_bytes_aligned(GAME_HEADER);
+            writer.write_byte_aligned(WORLD_CHECKSUM);
+            write_world_checksum(&mut writer, game_step, checksum);
+            let slice: &[u8] = write_buf;
+            send_packet(socket, slice, data.addr).await;
+        }
+    }
+
+    /// Clients that record the game ask for a replay stream with the rate in kilobytes per
+    /// second they can take, and are answered with the rate they get in bytes per second, at
+    /// most replay_stream_rate. Asking for 0 stops the stream
+    async fn request_replay_stream(
+        &mut self,
+        socket: &UdpSocket,
+        addr: SocketAddr,
+        version: u32,
+        rate: u32,
+        write_buf: &mut BytesMut,
+    ) {
+        if version < REPLAY_STREAM_VERSION {
+            return;
+        }
+        if let Some(player_index) = self.find_player_slot(addr) {
+            if let Some(player) = self.players.get_mut(player_index) {
+                let rate = rate
+                    .min(self.config.replay_stream_rate)
+                    .saturating_mul(1000);
+                let replay_stream = match &mut player.data {
+                    HQMServerPlayerData::NetworkPlayer { data } => &mut data.replay_stream,
+                    HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return,
+                };
+                if let Some(old_stream) = replay_stream.take() {
+                    info!(
+                        "{} ({}) stopped streaming the game, {} steps were skipped",
+                        player.player_name, player_index, old_stream.skipped_steps
+                    );
+                }
+                if rate > 0 {
+                    info!(
+                        "{} ({}) is streaming the game at {} bytes per second",
+                        player.player_name, player_index, rate
+                    );
+                    *replay_stream = Some(HQMReplayStream::new(rate));
+                }
+
+                write_buf.clear();
+                let mut writer = HQMMessageWriter::new(write_buf);
+                writer.write_bytes_aligned(GAME_HEADER);
+                writer.write_byte_aligned(REPLAY_STREAM);
+                write_replay_stream_answer(&mut writer, rate);
+                let slice: &[u8] = write_buf;
+                send_packet(socket, slice, addr).await;
+            }
+        }
+    }
+
+    /// Sends the step to the clients that asked for a replay stream and have bandwidth left
+    async fn send_replay_streams(
+        &mut self,
+        socket: &UdpSocket,
+        game_step: u32,
+        write_buf: &mut BytesMut,
+    ) {
+        let streaming = self.players.iter().any(|(_, player)| match &player.data {
+            HQMServerPlayerData::NetworkPlayer { data } => data.replay_stream.is_some(),
+            HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => false,
+        });
+        if !streaming {
+            return;
+        }
+        let inputs: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.object.is_some())
+            .map(|(player_index, player)| (player_index.0, &player.input))
+            .collect();
+        write_buf.clear();
+        let mut writer = HQMMessageWriter::new(write_buf);
+        writer.write_bytes_aligned(GAME_HEADER);
+        writer.write_byte_aligned(REPLAY_STREAM);
+        let fits = write_replay_stream_step(
+            &mut writer,
+            self.game_id,
+            game_step,
+            &self.values,
+            &self.saved_packets,
+            self.packet,
+            &inputs,
+            self.config.max_packet_size.max(MIN_PACKET_SIZE),
+        );
+        if !fits {
+            return;
+        }
+        let slice: &[u8] = write_buf;
+        for (_, player) in self.players.iter_mut() {
+            let data = match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => continue,
+            };
+            // The replay stream would show a delayed spectator the live game
+            if let Some(replay_stream) = &mut data.replay_stream {
+                if data.game_id == self.game_id
+                    && data.feed_message_counts.is_empty()
+                    && replay_stream.allow(slice.len())
+                {
+                    self.send_batch.push(data.addr, slice);
+                }
+            }
         }
+        self.send_batch.send(socket).await;
     }
 
     async fn request_info<'a, B: HQMServerBehaviour>(
@@ -376,7 +768,7 @@ impl HQMServer {
         let addr = addr.clone();
 
         let slice: &[u8] = &write_buf;
-        let _ = socket.send_to(slice, addr).await;
+        send_packet(&socket, slice, addr).await;
     }
 
     fn player_count(&self) -> usize {
@@ -384,6 +776,7 @@ impl HQMServer {
         for (_, player) in self.players.iter() {
             let is_actual_player = match player.data {
                 HQMServerPlayerData::NetworkPlayer { .. } => true,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => false,
             };
             if is_actual_player {
                 player_count += 1;
@@ -392,11 +785,26 @@ impl HQMServer {
         player_count
     }
 
+    /// Counts the network players in skater slots and in spectator slots
+    fn slot_counts(&self) -> HQMSlotCounts {
+        let mut counts = HQMSlotCounts::default();
+        for (_, player) in self.players.iter() {
+            if let HQMServerPlayerData::NetworkPlayer { data } = &player.data {
+                if data.spectator_slot {
+                    counts.spectators += 1;
+                } else {
+                    counts.skaters += 1;
+                }
+            }
+        }
+        counts
+    }
+
     fn player_update<B: HQMServerBehaviour>(
         &mut self,
         addr: SocketAddr,
         current_game_id: u32,
-        input: HQMPlayerInput,
+        mut input: HQMPlayerInput,
         deltatime: Option<u32>,
         new_known_packet: u32,
         known_msgpos: usize,
@@ -404,13 +812,19 @@ impl HQMServer {
         client_version: HQMClientVersion,
         behaviour: &mut B,
     ) {
-        let current_slot = self.find_player_slot(addr);
-        let (player_index, player) = match current_slot {
-            Some(x) => (x, self.players.get_mut(x).unwrap()),
-            None => {
-                return;
-            }
+        let player_index = match self.find_player_slot(addr) {
+            Some(x) => x,
+            None => match self.complete_join_challenge(addr, current_game_id, behaviour) {
+                Some(x) => x,
+                None => {
+                    return;
+                }
+            },
         };
+        let skater_slot_free = (input.join_red() || input.join_blue())
+            && self.slot_counts().skater_slot_free(self.config.player_max);
+        let player = self.players.get_mut(player_index).unwrap();
+        let mut impossible_input = None;
         if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
             let time_received = Instant::now();
 
@@ -433,9 +847,37 @@ impl HQMServer {
                     .push_front(duration_since_packet.as_secs_f32());
             }
 
+            if current_game_id == self.game_id {
+                data.network_quality.update(time_received);
+            }
+            if data.game_id == current_game_id {
+                if let Some(limit) = data.packet_size.update(self.packet, new_known_packet) {
+                    info!(
+                        "Lowered update packet size limit for {} ({}) to {} bytes",
+                        player.player_name, player_index, limit
+                    );
+                }
+            }
+
             data.inactivity = 0;
+            if current_game_id == self.game_id {
+                data.handshake_steps = 0;
+            }
             data.client_version = client_version;
             data.known_packet = new_known_packet;
+            let raw_input = input.clone();
+            if input.sanitize() && data.input_monitor.count_impossible_input(self.game_step) {
+                impossible_input = Some(raw_input);
+            }
+            if data.spectator_slot && skater_slot_free {
+                data.spectator_slot = false;
+            }
+            if data.spectator_slot || data.caster {
+                input.clear_join();
+            }
+            if input != player.input {
+                data.idle_steps = 0;
+            }
             player.input = input;
             data.game_id = current_game_id;
             data.known_msgpos = known_msgpos;
@@ -451,52 +893,126 @@ impl HQMServer {
                 }
             }
         }
+        if let Some(raw_input) = impossible_input {
+            self.report_impossible_input(player_index, &raw_input);
+        }
     }
 
-    fn player_join<B: HQMServerBehaviour>(
+    async fn player_join<B: HQMServerBehaviour>(
         &mut self,
+        socket: &Arc<UdpSocket>,
         addr: SocketAddr,
         player_version: u32,
         name: String,
         behaviour: &mut B,
+        write_buf: &mut BytesMut,
     ) {
-        let player_count = self.player_count();
-        let max_player_count = self.config.player_max;
-        if player_count >= max_player_count {
-            return; // Ignore join request
-        }
         if player_version != 55 {
             return; // Not the right version
         }
+        if self
+            .packet_rate_limiter
+            .is_join_blocked(addr.ip(), Instant::now())
+        {
+            return;
+        }
+        if !self.is_join_allowed(addr) {
+            return;
+        }
+
+        if self.config.join_challenge {
+            // The client answers with updates for this game ID, which proves that it owns the address
+            let cookie = self.join_challenge.challenge(addr, name, Instant::now());
+            write_buf.clear();
+            let mut writer = HQMMessageWriter::new(write_buf);
+            writer.write_bytes_aligned(GAME_HEADER);
+            writer.write_byte_aligned(6);
+            writer.write_u32_aligned(cookie);
+
+            let slice: &[u8] = write_buf;
+            send_packet(socket, slice, addr).await;
+        } else {
+            self.accept_join(addr, name, behaviour);
+        }
+    }
+
+    fn is_join_allowed(&self, addr: SocketAddr) -> bool {
+        if !self
+            .slot_counts()
+            .slot_free(self.config.player_max, self.config.spectator_slots)
+        {
+            return false; // Ignore join request
+        }
         let current_slot = self.find_player_slot(addr);
         if current_slot.is_some() {
-            return; // Player has already joined
+            return false; // Player has already joined
         }
 
         // Check ban list
         if self.ban_list.contains(&addr.ip()) {
-            return;
+            return false;
         }
 
         // Disabled join
-        if !self.allow_join {
-            return;
+        self.allow_join
+    }
+
+    fn complete_join_challenge<B: HQMServerBehaviour>(
+        &mut self,
+        addr: SocketAddr,
+        cookie: u32,
+        behaviour: &mut B,
+    ) -> Option<HQMServerPlayerIndex> {
+        if !self.config.join_challenge || !self.is_join_allowed(addr) {
+            return None;
         }
+        let name = self.join_challenge.verify(addr, cookie, Instant::now())?;
+        self.accept_join(addr, name, behaviour)
+    }
 
-        if let Some(player_index) = self.add_player(name.clone(), addr) {
-            behaviour.after_player_join(self, player_index);
-            info!(
-                "{} ({}) joined server from address {:?}",
-                name, player_index, addr
-            );
-            let msg = format!("{} joined", name);
-            self.messages.add_server_chat_message(msg);
+    fn accept_join<B: HQMServerBehaviour>(
+        &mut self,
+        addr: SocketAddr,
+        name: String,
+        behaviour: &mut B,
+    ) -> Option<HQMServerPlayerIndex> {
+        let player_index = self.add_player(name, addr)?;
+        self.restore_hand(player_index);
+        // The name can have been changed to tell it apart from the names of other players
+        let name = self.players.get(player_index)?.player_name.clone();
+        behaviour.after_player_join(self, player_index);
+        info!(
+            player = %name,
+            player_index = player_index.0,
+            %addr,
+            "{} ({}) joined server from address {:?}",
+            name,
+            player_index,
+            addr
+        );
+        let msg = format!("{} joined", name);
+        self.messages.add_server_chat_message(msg);
+        Some(player_index)
+    }
+
+    fn restore_hand(&mut self, player_index: HQMServerPlayerIndex) {
+        if let Some(player) = self.players.get_mut(player_index) {
+            self.preferences.seen(&player.player_name);
+            if let Some(hand) = self
+                .preferences
+                .get(&player.player_name)
+                .and_then(|x| x.hand)
+            {
+                player.hand = hand;
+            }
         }
     }
 
     pub fn set_hand(&mut self, hand: HQMSkaterHand, player_index: HQMServerPlayerIndex) {
         if let Some(player) = self.players.get_mut(player_index) {
             player.hand = hand;
+            self.preferences
+                .update(&player.player_name, |x| x.hand = Some(hand));
             let object_index = player.object.map(|x| x.0);
 
             fn change_skater(
@@ -549,6 +1065,19 @@ impl HQMServer {
                     self.unmute_player(player_index, mute_player_index);
                 }
             }
+            "caster" => {
+                if let Ok(caster_player_index) = arg.parse::<HQMServerPlayerIndex>() {
+                    self.set_caster(player_index, caster_player_index, true);
+                }
+            }
+            "uncaster" => {
+                if let Ok(caster_player_index) = arg.parse::<HQMServerPlayerIndex>() {
+                    self.set_caster(player_index, caster_player_index, false);
+                }
+            }
+            "delay" => {
+                self.set_feed_delay(player_index, arg);
+            }
             /*"shadowmute" => {
                 if let Ok(mute_player_index) = arg.parse::<usize>() {
                     if mute_player_index < self.players.len() {
@@ -559,6 +1088,15 @@ impl HQMServer {
             "mutechat" => {
                 self.mute_chat(player_index);
             }
+            "alerts" => {
+                self.set_alerts(player_index, arg);
+            }
+            "savegame" => {
+                self.save_game(player_index, arg);
+            }
+            "loadgame" => {
+                self.load_game(player_index, arg, behaviour);
+            }
             "unmutechat" => {
                 self.unmute_chat(player_index);
             }
@@ -593,6 +1131,15 @@ impl HQMServer {
             "serverrestart" => {
                 self.restart_server(player_index);
             }
+            "shutdown" => {
+                self.admin_shutdown(player_index, arg);
+            }
+            "physics" => {
+                self.set_physics_profile(player_index, arg);
+            }
+            "public" => {
+                self.admin_set_public(player_index, arg);
+            }
             "list" => {
                 if arg.is_empty() {
                     self.list_players(player_index, 0);
@@ -616,6 +1163,7 @@ impl HQMServer {
                     if matches.is_empty() {
                         self.messages
                             .add_directed_server_chat_message("No matches found", player_index);
+                        self.suggest_player_name(arg, player_index);
                     } else if matches.len() > 1 {
                         self.messages.add_directed_server_chat_message(
                             "Multiple matches found, use /ping X",
@@ -632,63 +1180,199 @@ impl HQMServer {
                 }
             }
             "view" => {
-                if let Ok(view_player_index) = arg.parse::<HQMServerPlayerIndex>() {
+                if arg.is_empty() {
+                    self.messages.add_directed_server_chat_message(
+                        "Usage: /view <ID|name|off>",
+                        player_index,
+                    );
+                } else if arg == "off" {
+                    self.restore_view(player_index);
+                } else if let Ok(view_player_index) = arg.parse::<HQMServerPlayerIndex>() {
                     self.view(view_player_index, player_index);
+                } else {
+                    self.view_by_name(arg, player_index);
                 }
             }
             "views" => {
-                if let Some((view_player_index, _name)) = self.player_exact_unique_match(arg) {
-                    self.view(view_player_index, player_index);
-                } else {
-                    let matches = self.player_search(arg);
-                    if matches.is_empty() {
-                        self.messages
-                            .add_directed_server_chat_message("No matches found", player_index);
-                    } else if matches.len() > 1 {
-                        self.messages.add_directed_server_chat_message(
-                            "Multiple matches found, use /view X",
-                            player_index,
-                        );
-                        for (found_player_index, found_player_name) in matches.into_iter().take(5) {
-                            let str = format!("{}: {}", found_player_index, found_player_name);
-                            self.messages
-                                .add_directed_server_chat_message(str, player_index);
-                        }
-                    } else {
-                        self.view(matches[0].0, player_index);
-                    }
-                }
+                self.view_by_name(arg, player_index);
             }
             "restoreview" => {
-                if let Some(player) = self.players.get_mut(player_index) {
-                    if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
-                        if data.view_player_index != player_index {
-                            data.view_player_index = player_index;
-                            self.messages.add_directed_server_chat_message(
-                                "View has been restored",
-                                player_index,
-                            );
-                        }
-                    }
-                }
+                self.restore_view(player_index);
             }
             "t" => {
                 self.add_user_team_message(arg, player_index);
             }
+            "qc" => {
+                self.quick_chat(arg, player_index);
+            }
+            "freeze" => {
+                self.freeze(player_index, true);
+            }
+            "unfreeze" => {
+                self.freeze(player_index, false);
+            }
+            "step" => {
+                self.step(player_index, arg);
+            }
+            "stats" => {
+                self.request_player_stats(player_index, arg);
+            }
+            "replay" => {
+                self.instant_replay(player_index, arg);
+            }
+            "top" => {
+                self.request_top(player_index, arg);
+            }
+            "standings" => {
+                self.request_standings(player_index);
+            }
+            "matchup" => {
+                self.set_matchup(player_index, arg);
+            }
             _ => behaviour.handle_command(self, command, arg, player_index),
         }
     }
 
-    fn list_players(&mut self, receiver_index: HQMServerPlayerIndex, first_index: usize) {
-        for (player_index, player) in self
-            .players
-            .iter()
-            .filter(|(x, _)| x.0 >= first_index)
-            .take(5)
-        {
-            let msg = format!("{}: {}", player_index, player.player_name);
-            self.messages
-                .add_directed_server_chat_message(msg, receiver_index);
+    fn stats_receiver(&self, player_index: HQMServerPlayerIndex) -> Option<HQMStatsReceiver> {
+        let player = self.players.get(player_index)?;
+        Some(HQMStatsReceiver {
+            player_index,
+            player_id: player.id,
+        })
+    }
+
+    fn request_player_stats(&mut self, player_index: HQMServerPlayerIndex, name: &str) {
+        let name = if name.trim().is_empty() {
+            match self.players.get(player_index) {
+                Some(player) => player.player_name.to_string(),
+                None => return,
+            }
+        } else {
+            name.trim().to_string()
+        };
+        match (&self.stats, self.stats_receiver(player_index)) {
+            (Some(stats), Some(receiver)) => stats.request_player_stats(name, receiver),
+            _ => self
+                .messages
+                .add_directed_server_chat_message("Statistics are not enabled", player_index),
+        }
+    }
+
+    fn request_top(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        let category = match HQMTopCategory::parse(arg.trim()) {
+            Some(category) => category,
+            None => {
+                self.messages.add_directed_server_chat_message(
+                    "Usage: /top goals|assists|games",
+                    player_index,
+                );
+                return;
+            }
+        };
+        match (&self.stats, self.stats_receiver(player_index)) {
+            (Some(stats), Some(receiver)) => stats.request_top(category, receiver),
+            _ => self
+                .messages
+                .add_directed_server_chat_message("Statistics are not enabled", player_index),
+        }
+    }
+
+    fn request_standings(&mut self, player_index: HQMServerPlayerIndex) {
+        match (&self.stats, self.stats_receiver(player_index)) {
+            (Some(stats), Some(receiver)) => stats.request_standings(receiver),
+            _ => self
+                .messages
+                .add_directed_server_chat_message("Statistics are not enabled", player_index),
+        }
+    }
+
+    /// Makes the current game a league game between two teams, recorded in the standings when
+    /// it's over
+    fn set_matchup(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        let player = match self.players.get(player_index) {
+            Some(player) => player,
+            None => return,
+        };
+        if !player.is_admin {
+            self.admin_deny_message(player_index);
+            return;
+        }
+        let player_name = player.player_name.clone();
+        if self.stats.is_none() {
+            self.messages
+                .add_directed_server_chat_message("Statistics are not enabled", player_index);
+            return;
+        }
+        let args: Vec<&str> = arg.split_whitespace().collect();
+        let msg = match (args.as_slice(), &self.game_record.matchup) {
+            ([], Some(matchup)) => format!(
+                "League game: {} (Red) vs {} (Blue)",
+                matchup.red, matchup.blue
+            ),
+            (["off"], _) => {
+                self.game_record.matchup = None;
+                info!(
+                    "{} ({}) cleared the league matchup",
+                    player_name, player_index
+                );
+                self.messages
+                    .add_server_chat_message("This game is not a league game anymore");
+                return;
+            }
+            ([_, _], _) if self.values.game_over => {
+                "The game is over, set the matchup when the next game has started".to_string()
+            }
+            ([red, blue], _) => {
+                info!(
+                    "{} ({}) set the league matchup {} vs {}",
+                    player_name, player_index, red, blue
+                );
+                let msg = format!("League game: {} (Red) vs {} (Blue)", red, blue);
+                self.messages.add_server_chat_message(msg);
+                self.game_record.matchup = Some(HQMMatchup {
+                    red: red.to_string(),
+                    blue: blue.to_string(),
+                });
+                return;
+            }
+            _ => "Usage: /matchup <red team> <blue team>|off".to_string(),
+        };
+        self.messages
+            .add_directed_server_chat_message(msg, player_index);
+    }
+
+    /// Sends answers from the statistics database to the players who asked, if they're
+    /// still on the server
+    fn deliver_stats_replies(&mut self) {
+        let stats = match &mut self.stats {
+            Some(stats) => stats,
+            None => return,
+        };
+        while let Some(reply) = stats.reply() {
+            let receiver = reply.receiver;
+            if self
+                .players
+                .get(receiver.player_index)
+                .is_some_and(|player| player.id == receiver.player_id)
+            {
+                for message in reply.messages {
+                    self.messages
+                        .add_directed_server_chat_message(message, receiver.player_index);
+                }
+            }
+        }
+    }
+
+    fn list_players(&mut self, receiver_index: HQMServerPlayerIndex, first_index: usize) {
+        for (player_index, player) in self
+            .players
+            .iter()
+            .filter(|(x, _)| x.0 >= first_index)
+            .take(5)
+        {
+            let msg = format!("{}: {}", player_index, player.player_name);
+            self.messages
+                .add_directed_server_chat_message(msg, receiver_index);
         }
     }
 
@@ -697,6 +1381,7 @@ impl HQMServer {
         if matches.is_empty() {
             self.messages
                 .add_directed_server_chat_message("No matches found", player_index);
+            self.suggest_player_name(name, player_index);
             return;
         }
         for (found_player_index, found_player_name) in matches.into_iter().take(5) {
@@ -742,6 +1427,43 @@ impl HQMServer {
         }
     }
 
+    fn view_by_name(&mut self, arg: &str, player_index: HQMServerPlayerIndex) {
+        if let Some((view_player_index, _name)) = self.player_exact_unique_match(arg) {
+            self.view(view_player_index, player_index);
+        } else {
+            let matches = self.player_search(arg);
+            if matches.is_empty() {
+                self.messages
+                    .add_directed_server_chat_message("No matches found", player_index);
+                self.suggest_player_name(arg, player_index);
+            } else if matches.len() > 1 {
+                self.messages.add_directed_server_chat_message(
+                    "Multiple matches found, use /view X",
+                    player_index,
+                );
+                for (found_player_index, found_player_name) in matches.into_iter().take(5) {
+                    let str = format!("{}: {}", found_player_index, found_player_name);
+                    self.messages
+                        .add_directed_server_chat_message(str, player_index);
+                }
+            } else {
+                self.view(matches[0].0, player_index);
+            }
+        }
+    }
+
+    fn restore_view(&mut self, player_index: HQMServerPlayerIndex) {
+        if let Some(player) = self.players.get_mut(player_index) {
+            if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
+                if data.view_player_index != player_index {
+                    data.view_player_index = player_index;
+                    self.messages
+                        .add_directed_server_chat_message("View has been restored", player_index);
+                }
+            }
+        }
+    }
+
     fn ping(
         &mut self,
         ping_player_index: HQMServerPlayerIndex,
@@ -764,6 +1486,10 @@ impl HQMServer {
                     .add_directed_server_chat_message(msg1, player_index);
                 self.messages
                     .add_directed_server_chat_message(msg2, player_index);
+                if let Some(msg3) = ping_player.network_quality() {
+                    self.messages
+                        .add_directed_server_chat_message(msg3, player_index);
+                }
             } else {
                 self.messages.add_directed_server_chat_message(
                     "This player is not a connected player",
@@ -780,9 +1506,10 @@ impl HQMServer {
         &self,
         name: &str,
     ) -> Option<(HQMServerPlayerIndex, Rc<String>)> {
+        let name = normalize_name(name);
         let mut found = None;
         for (player_index, player) in self.players.iter() {
-            if player.player_name.as_str() == name {
+            if normalize_name(&player.player_name) == name {
                 if found.is_none() {
                     found = Some((player_index, player.player_name.clone()));
                 } else {
@@ -797,10 +1524,10 @@ impl HQMServer {
         &self,
         name: &str,
     ) -> smallvec::SmallVec<[(HQMServerPlayerIndex, Rc<String>); 64]> {
-        let name = name.to_lowercase();
+        let name = normalize_name(name);
         let mut found = smallvec::SmallVec::<[_; 64]>::new();
         for (player_index, player) in self.players.iter() {
-            if player.player_name.to_lowercase().contains(&name) {
+            if normalize_name(&player.player_name).contains(&name) {
                 found.push((player_index, player.player_name.clone()));
                 if found.len() >= 5 {
                     break;
@@ -810,6 +1537,21 @@ impl HQMServer {
         found
     }
 
+    /// Tells the player about a name that is close to a name that didn't match anyone
+    pub(crate) fn suggest_player_name(&mut self, name: &str, receiver_index: HQMServerPlayerIndex) {
+        let suggestion = closest_name(
+            name,
+            self.players
+                .iter()
+                .map(|(_, player)| player.player_name.as_str()),
+        )
+        .map(|suggestion| format!("Did you mean {}?", suggestion));
+        if let Some(msg) = suggestion {
+            self.messages
+                .add_directed_server_chat_message(msg, receiver_index);
+        }
+    }
+
     fn process_message<B: HQMServerBehaviour>(
         &mut self,
         msg: String,
@@ -821,13 +1563,51 @@ impl HQMServer {
                 let split: Vec<&str> = msg.splitn(2, " ").collect();
                 let command = &split[0][1..];
                 let arg = if split.len() < 2 { "" } else { &split[1] };
+                // The argument isn't recorded, because it can be a password
+                let sender = self
+                    .players
+                    .get(player_index)
+                    .map(|x| x.player_name.clone());
+                let _span = info_span!(
+                    "command",
+                    sender = sender.as_deref().map(String::as_str),
+                    sender_index = player_index.0,
+                    command
+                )
+                .entered();
                 self.process_command(command, arg, player_index, behaviour);
             } else {
-                if !self.is_muted {
+                let is_caster = self
+                    .players
+                    .get(player_index)
+                    .is_some_and(|player| player.is_caster());
+                if !self.is_muted || is_caster {
                     match self.players.get(player_index) {
                         Some(player) => match player.is_muted {
                             HQMMuteStatus::NotMuted => {
-                                info!("{} ({}): {}", &player.player_name, player_index, &msg);
+                                info!(
+                                    target: GAME_LOG,
+                                    player = %player.player_name,
+                                    player_index = player_index.0,
+                                    chat = %msg,
+                                    "{} ({}): {}",
+                                    &player.player_name,
+                                    player_index,
+                                    &msg
+                                );
+                                self.event_feed.publish(
+                                    self.game_id,
+                                    HQMEvent::Chat {
+                                        player_index: player_index.0,
+                                        name: player.player_name.to_string(),
+                                        message: msg.clone(),
+                                    },
+                                );
+                                if self.config.chat_history > 0 {
+                                    self.chat_history.truncate(self.config.chat_history - 1);
+                                    self.chat_history
+                                        .push_front(format!("{}: {}", player.player_name, msg));
+                                }
                                 self.messages.add_user_chat_message(msg, player_index);
                             }
                             HQMMuteStatus::ShadowMuted => {
@@ -856,9 +1636,16 @@ impl HQMServer {
                 let player = self.players.get(player_index).unwrap();
                 player.player_name.clone()
             };
+            behaviour.before_player_disconnect(self, player_index);
             behaviour.before_player_exit(self, player_index);
             self.remove_player(player_index, true);
-            info!("{} ({}) exited server", player_name, player_index);
+            info!(
+                player = %player_name,
+                player_index = player_index.0,
+                "{} ({}) exited server",
+                player_name,
+                player_index
+            );
             let msg = format!("{} exited", player_name);
             self.messages.add_server_chat_message(msg);
         }
@@ -870,19 +1657,34 @@ impl HQMServer {
         addr: SocketAddr,
     ) -> Option<HQMServerPlayerIndex> {
         let player_index = self.find_empty_player_slot();
+        let spectator_slot = !self.slot_counts().skater_slot_free(self.config.player_max);
         match player_index {
             Some(player_index) => {
+                let taken_names: Vec<String> = self
+                    .players
+                    .iter()
+                    .map(|(_, player)| normalize_name(&player.player_name))
+                    .collect();
+                let player_name =
+                    unique_name(player_name, |name| taken_names.iter().any(|x| x == name));
                 let new_player = HQMServerPlayer::new_network_player(
                     player_index,
                     player_name,
                     addr,
                     &self.messages.persistent_messages,
+                    self.config.max_packet_size.max(MIN_PACKET_SIZE),
+                    spectator_slot,
                 );
                 let update = new_player.get_update_message(player_index);
+                let event = HQMEvent::Join {
+                    player_index: player_index.0,
+                    name: new_player.player_name.to_string(),
+                };
 
                 self.players.add_player(player_index, new_player);
 
                 self.messages.add_global_message(update, true, true);
+                self.publish_event(event);
 
                 let welcome = self.config.welcome.clone();
                 for welcome_msg in welcome {
@@ -890,12 +1692,62 @@ impl HQMServer {
                         .add_directed_server_chat_message(welcome_msg, player_index);
                 }
 
+                for history_msg in self.chat_history.iter().rev() {
+                    let msg = format!("[history] {}", history_msg);
+                    self.messages
+                        .add_directed_server_chat_message(msg, player_index);
+                }
+
+                if spectator_slot {
+                    self.messages.add_directed_server_chat_message(
+                        "The server is full, you are in a spectator slot until a skater leaves",
+                        player_index,
+                    );
+                }
+
                 Some(player_index)
             }
             _ => None,
         }
     }
 
+    /// Adds a bot as a spectator, with a number added to the name if it is taken
+    pub fn add_bot(&mut self, player_name: String) -> Option<HQMServerPlayerIndex> {
+        self.add_server_player(player_name, HQMServerPlayerData::Bot)
+    }
+
+    /// Adds a dummy as a spectator, with a number added to the name if it is taken
+    pub fn add_dummy(&mut self, player_name: String) -> Option<HQMServerPlayerIndex> {
+        self.add_server_player(player_name, HQMServerPlayerData::Dummy)
+    }
+
+    fn add_server_player(
+        &mut self,
+        player_name: String,
+        data: HQMServerPlayerData,
+    ) -> Option<HQMServerPlayerIndex> {
+        let player_index = self.find_empty_player_slot()?;
+        let taken_names: Vec<String> = self
+            .players
+            .iter()
+            .map(|(_, player)| normalize_name(&player.player_name))
+            .collect();
+        let player_name = unique_name(player_name, |name| taken_names.iter().any(|x| x == name));
+        let new_player = HQMServerPlayer::new_server_player(player_name, data);
+        let update = new_player.get_update_message(player_index);
+        let event = HQMEvent::Join {
+            player_index: player_index.0,
+            name: new_player.player_name.to_string(),
+        };
+
+        self.players.add_player(player_index, new_player);
+
+        self.messages.add_global_message(update, true, true);
+        self.publish_event(event);
+
+        Some(player_index)
+    }
+
     pub fn remove_player(&mut self, player_index: HQMServerPlayerIndex, on_replay: bool) {
         if let Some(player) = self.players.get(player_index) {
             let player_name = player.player_name.clone();
@@ -905,6 +1757,11 @@ impl HQMServer {
                 self.world.remove_player(object_index);
             }
 
+            self.publish_event(HQMEvent::Exit {
+                player_index: player_index.0,
+                name: player_name.to_string(),
+            });
+
             let update = HQMMessage::PlayerUpdate {
                 player_name,
                 object: None,
@@ -916,6 +1773,20 @@ impl HQMServer {
 
             self.players.remove_player(player_index);
 
+            // Spectators who followed the player would follow whoever gets the slot next
+            let followers: smallvec::SmallVec<[_; 8]> = self
+                .players
+                .iter()
+                .filter(|(follower_index, player)| {
+                    matches!(&player.data, HQMServerPlayerData::NetworkPlayer { data }
+                        if data.view_player_index == player_index && *follower_index != player_index)
+                })
+                .map(|(follower_index, _)| follower_index)
+                .collect();
+            for follower_index in followers {
+                self.restore_view(follower_index);
+            }
+
             if is_admin {
                 let admin_found = self.players.iter().any(|(_, x)| x.is_admin);
 
@@ -926,6 +1797,131 @@ impl HQMServer {
         }
     }
 
+    /// Sends an event to the clients of the event feed, if it is enabled
+    pub fn publish_event(&mut self, event: HQMEvent) {
+        // Goals, faceoffs, saves, hits, rule calls and penalties are recorded in the statistics
+        // too, whichever mode reported them
+        match &event {
+            HQMEvent::Goal {
+                team,
+                scorer,
+                assist,
+                period,
+                time,
+                speed,
+                shot_speed,
+                shot_position,
+                ..
+            } => {
+                if self.config.highlights_directory.is_some() {
+                    let at = self.highlight_time();
+                    self.highlights.add_goal(&at, *team, scorer.clone(), *speed);
+                }
+                self.game_record.add_goal(HQMGoalRecord {
+                    team: *team,
+                    scorer: scorer.clone(),
+                    assist: assist.clone(),
+                    period: *period,
+                    time: *time,
+                    speed: Some(*speed),
+                    shot_speed: *shot_speed,
+                });
+                if let Some(position) = shot_position {
+                    self.game_record.shots.push(HQMShotRecord {
+                        team: *team,
+                        shooter: scorer.clone(),
+                        goal: true,
+                        position: *position,
+                    });
+                }
+            }
+            HQMEvent::RuleCall {
+                rule,
+                team,
+                player,
+                period,
+                time,
+            } => {
+                self.game_record.add_rule_call(HQMRuleCallRecord {
+                    rule: *rule,
+                    team: *team,
+                    player: player.clone(),
+                    period: *period,
+                    time: *time,
+                });
+            }
+            HQMEvent::Penalty {
+                team,
+                player,
+                minutes,
+                period,
+                time,
+            } => {
+                self.game_record.penalties.push(HQMPenaltyRecord {
+                    team: *team,
+                    player: player.clone(),
+                    minutes: *minutes,
+                    period: *period,
+                    time: *time,
+                });
+            }
+            HQMEvent::ShootoutAttempt {
+                red_goals,
+                blue_goals,
+                ..
+            } => {
+                self.game_record.shootout = Some(HQMShootoutRecord {
+                    red_goals: *red_goals,
+                    blue_goals: *blue_goals,
+                });
+            }
+            HQMEvent::Faceoff {
+                team,
+                winner,
+                loser,
+                period,
+                time,
+            } => {
+                self.game_record.add_faceoff(HQMFaceoffRecord {
+                    team: *team,
+                    winner: winner.clone(),
+                    loser: loser.clone(),
+                    period: *period,
+                    time: *time,
+                });
+            }
+            HQMEvent::Save {
+                team,
+                shooter,
+                goalie,
+                shot_position,
+                ..
+            } => {
+                self.game_record.add_save(*team, goalie);
+                self.game_record.shots.push(HQMShotRecord {
+                    team: *team,
+                    shooter: shooter.clone(),
+                    goal: false,
+                    position: *shot_position,
+                });
+            }
+            HQMEvent::Hit { team, hitter, .. } => {
+                self.game_record.add_hit(*team, hitter);
+            }
+            _ => {}
+        }
+        self.event_feed.publish(self.game_id, event);
+    }
+
+    /// Records that a player had the puck for this step, for the statistics
+    pub fn add_possession(&mut self, player_index: HQMServerPlayerIndex) {
+        if let Some(player) = self.players.get(player_index) {
+            if let Some((_, team)) = player.object {
+                self.game_record.add_possession(&player.player_name, team);
+            }
+        }
+    }
+
     pub fn move_to_spectator(&mut self, player_index: HQMServerPlayerIndex) -> bool {
         if let Some(player) = self.players.get_mut(player_index) {
             if let Some((object_index, _)) = player.object {
@@ -979,6 +1975,7 @@ impl HQMServer {
 
                     let object = Some((skater, team));
                     player.object = object;
+                    self.game_record.add_player(&player.player_name, team);
                     let update = player.get_update_message(player_index);
                     self.messages.add_global_message(update, true, true);
                     return Some(skater);
@@ -997,6 +1994,7 @@ impl HQMServer {
             };
             if let Some(team) = team {
                 info!(
+                    target: GAME_LOG,
                     "{} ({}) to team {}: {}",
                     &player.player_name, sender_index, team, message
                 );
@@ -1037,6 +2035,74 @@ impl HQMServer {
         }
     }
 
+    /// Sends one of the predefined quick-chat messages to the team of the player
+    fn quick_chat(&mut self, arg: &str, player_index: HQMServerPlayerIndex) {
+        let signal = match find_signal(arg) {
+            Some(signal) => signal,
+            None => {
+                let signals = QUICK_CHAT_SIGNALS
+                    .iter()
+                    .map(|signal| signal.name)
+                    .collect::<Vec<_>>()
+                    .join("|");
+                self.messages.add_directed_server_chat_message(
+                    format!("Usage: /qc {}", signals),
+                    player_index,
+                );
+                return;
+            }
+        };
+        let now = Instant::now();
+        if let Some(player) = self.players.get_mut(player_index) {
+            if self.is_muted || player.is_muted != HQMMuteStatus::NotMuted {
+                return;
+            }
+            if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
+                if matches!(data.last_quick_chat, Some(last) if now.duration_since(last) < QUICK_CHAT_INTERVAL)
+                {
+                    return;
+                }
+                data.last_quick_chat = Some(now);
+            }
+        }
+        self.add_user_team_message(signal.message, player_index);
+        self.queue_sound_cues(signal.sound, player_index);
+    }
+
+    /// Queues the sound of a quick-chat signal for the team mates who asked for sound cues.
+    /// They are sent at the end of the tick.
+    fn queue_sound_cues(&mut self, sound: u8, sender_index: HQMServerPlayerIndex) {
+        let team = match self
+            .players
+            .get(sender_index)
+            .and_then(|player| player.object)
+        {
+            Some((_, team)) => team,
+            None => return,
+        };
+        for (_, player) in self.players.iter() {
+            if let (Some((_, player_team)), HQMServerPlayerData::NetworkPlayer { data }) =
+                (player.object, &player.data)
+            {
+                if player_team == team && data.sound_cues {
+                    self.sound_cues.push((data.addr, sound, sender_index));
+                }
+            }
+        }
+    }
+
+    async fn send_sound_cues(&mut self, socket: &UdpSocket, write_buf: &mut BytesMut) {
+        for (addr, sound, sender_index) in std::mem::take(&mut self.sound_cues) {
+            write_buf.clear();
+            let mut writer = HQMMessageWriter::new(write_buf);
+            writer.write_bytes_aligned(GAME_HEADER);
+            writer.write_byte_aligned(SOUND_CUE);
+            write_sound_cue(&mut writer, sound, sender_index);
+            let slice: &[u8] = write_buf;
+            send_packet(socket, slice, addr).await;
+        }
+    }
+
     fn find_player_slot(&self, addr: SocketAddr) -> Option<HQMServerPlayerIndex> {
         return self
             .players
@@ -1065,19 +2131,66 @@ impl HQMServer {
 
         behaviour.before_tick(self);
 
-        for (_, player) in self.players.iter() {
+        let toggle_limit = self.config.input_toggle_limit;
+        let mut reported_players: smallvec::SmallVec<[_; 4]> = smallvec::SmallVec::new();
+        for (player_index, player) in self.players.iter_mut() {
             if let Some((object_index, _)) = player.object {
                 if let Some(skater) = self.world.objects.get_skater_mut(object_index) {
-                    skater.input = player.input.clone()
+                    let mut input = player.input.clone();
+                    // Bots set their own input, so only clients are checked for key toggling
+                    if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
+                        let (keys, report) = data.input_monitor.filter_keys(
+                            input.keys,
+                            self.game_step,
+                            toggle_limit,
+                        );
+                        input.keys = keys;
+                        if report {
+                            reported_players.push(player_index);
+                        }
+                    }
+                    if self.sticks_held {
+                        input.stick = skater.input.stick;
+                        input.stick_angle = skater.input.stick_angle;
+                    }
+                    skater.input = input
                 }
             }
         }
+        for player_index in reported_players {
+            self.report_input_macro(player_index);
+        }
 
         let events = self.world.simulate_step();
+        self.check_movement(behaviour);
 
-        let packets = hqm_parse::get_packets(&self.world.objects.objects);
+        let packets = self
+            .playback_packets
+            .take()
+            .unwrap_or_else(|| hqm_parse::get_packets(&self.world.objects.objects));
 
+        let was_game_over = self.values.game_over;
+        let clock = self.values.time;
         behaviour.after_tick(self, &events);
+        // Players are only away from the game while the clock runs
+        if self.values.period > 0 && !self.values.game_over && self.values.time != clock {
+            self.check_afk();
+        }
+        if self.values.game_over && !was_game_over {
+            info!(
+                target: GAME_LOG,
+                red_score = self.values.red_score,
+                blue_score = self.values.blue_score,
+                "Game {} over, {} - {}",
+                self.game_id,
+                self.values.red_score,
+                self.values.blue_score
+            );
+            self.send_box_score();
+            self.save_game_record(behaviour.regular_periods());
+        }
+        self.event_feed
+            .update_values(self.game_id, self.game_record.id, &self.values);
 
         if self.history_length > 0 {
             let new_replay_tick = ReplayTick {
@@ -1091,82 +2204,294 @@ impl HQMServer {
             self.saved_history.clear();
         }
 
-        self.saved_packets.truncate(192 - 1);
+        self.saved_packets.truncate(self.packet_history_len() - 1);
         self.saved_packets.push_front(packets);
         self.packet = self.packet.wrapping_add(1);
         self.saved_pings.truncate(100 - 1);
         self.saved_pings.push_front(Instant::now());
 
         if self.config.replays_enabled != ReplayEnabled::Off && behaviour.save_replay_data(self) {
+            if self.config.highlights_directory.is_some()
+                && self.values.period > 0
+                && !self.values.game_over
+            {
+                let at = self.highlight_time();
+                self.highlights
+                    .update(&at, &self.world, &self.players, &events);
+            }
             self.write_replay();
         }
     }
 
-    fn remove_inactive_players<B: HQMServerBehaviour>(&mut self, behaviour: &mut B) {
-        let inactive_players: smallvec::SmallVec<[_; 8]> = self
+    /// Steps of packets to keep, enough for the delta encoding and for the longest feed delay
+    fn packet_history_len(&self) -> usize {
+        let longest_delay = self
             .players
-            .iter_mut()
-            .filter_map(|(player_index, player)| {
-                if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
-                    data.inactivity += 1;
-                    if data.inactivity > 500 {
-                        Some((player_index, player.player_name.clone()))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
+            .iter()
+            .filter_map(|(_, player)| match &player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data.feed_delay,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => None,
             })
-            .collect();
-        for (player_index, player_name) in inactive_players {
-            behaviour.before_player_exit(self, player_index);
-            self.remove_player(player_index, true);
-            info!("{} ({}) timed out", player_name, player_index);
-            let chat_msg = format!("{} timed out", player_name);
-            self.messages.add_server_chat_message(chat_msg);
-        }
+            .fold(self.config.spectator_delay, u32::max);
+        192 + longest_delay as usize * 100
     }
 
-    async fn tick<B: HQMServerBehaviour>(
-        &mut self,
-        socket: &UdpSocket,
-        behaviour: &mut B,
-        write_buf: &mut BytesMut,
-    ) {
-        if self.player_count() != 0 {
-            if !self.has_current_game_been_active {
-                self.start_time = Utc::now();
-                self.has_current_game_been_active = true;
-                behaviour.game_started(self);
-                info!("New game {} started", self.game_id);
-            }
+    /// Sends the same state again while the simulation is frozen, so that clients keep
+    /// getting updates and chat
+    fn frozen_step(&mut self) {
+        let packets = hqm_parse::get_packets(&self.world.objects.objects);
+        self.saved_packets.truncate(self.packet_history_len() - 1);
+        self.saved_packets.push_front(packets);
+        self.packet = self.packet.wrapping_add(1);
+        self.saved_pings.truncate(100 - 1);
+        self.saved_pings.push_front(Instant::now());
+    }
 
-            let (game_step, forced_view) = tokio::task::block_in_place(|| {
-                self.remove_inactive_players(behaviour);
+    /// Checks that debug commands are enabled and that the player is an admin
+    fn allow_debug_command(&mut self, player_index: HQMServerPlayerIndex) -> bool {
+        if let Some(player) = self.players.get(player_index) {
+            if !self.config.debug_commands {
+                self.messages
+                    .add_directed_server_chat_message("Debug commands are disabled", player_index);
+            } else if player.is_admin {
+                return true;
+            } else {
+                self.admin_deny_message(player_index);
+            }
+        }
+        false
+    }
 
-                let has_replay_data = if let Some(replay_element) = self.replay_queue.front_mut() {
-                    if let Some(tick) = replay_element.data.pop_front() {
-                        Some((replay_element.force_view, tick))
-                    } else {
-                        self.replay_queue.pop_front();
-                        None
-                    }
-                } else {
-                    None
+    fn freeze(&mut self, player_index: HQMServerPlayerIndex, frozen: bool) {
+        if !self.allow_debug_command(player_index) || self.frozen == frozen {
+            return;
+        }
+        self.frozen = frozen;
+        self.pending_steps = 0;
+        if let Some(player) = self.players.get(player_index) {
+            let msg = if frozen {
+                info!(
+                    "{} ({}) froze the simulation at tick {}",
+                    player.player_name, player_index, self.game_step
+                );
+                format!(
+                    "Simulation frozen by {} at tick {}",
+                    player.player_name, self.game_step
+                )
+            } else {
+                info!(
+                    "{} ({}) unfroze the simulation at tick {}",
+                    player.player_name, player_index, self.game_step
+                );
+                format!("Simulation unfrozen by {}", player.player_name)
+            };
+            self.messages.add_server_chat_message(msg);
+        }
+    }
+
+    fn step(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        if !self.allow_debug_command(player_index) {
+            return;
+        }
+        let steps = if arg.is_empty() {
+            1
+        } else {
+            match arg.parse::<u32>() {
+                Ok(steps) if steps > 0 => steps,
+                _ => {
+                    self.messages
+                        .add_directed_server_chat_message("Usage: /step [ticks]", player_index);
+                    return;
+                }
+            }
+        };
+        // Stepping freezes the simulation first, so /step can be used right away
+        self.frozen = true;
+        self.pending_steps = self.pending_steps.saturating_add(steps);
+        let target = self.game_step.wrapping_add(self.pending_steps);
+        info!(
+            "{} ({}) stepped the simulation {} ticks to tick {}",
+            self.players
+                .get(player_index)
+                .map(|x| x.player_name.as_str())
+                .unwrap_or_default(),
+            player_index,
+            steps,
+            target
+        );
+        let msg = format!("Stepping {} ticks to tick {}", steps, target);
+        self.messages.add_server_chat_message(msg);
+    }
+
+    /// Warns the players who are above a connection limit, and kicks them if they stay there
+    fn check_network_quality<B: HQMServerBehaviour>(&mut self, behaviour: &mut B) {
+        if self.last_network_check.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_network_check = Instant::now();
+        let limits = self.config.network_limits.clone();
+        if limits.ping == 0 && limits.jitter == 0 {
+            return;
+        }
+        let mut warnings: smallvec::SmallVec<[_; 4]> = smallvec::SmallVec::new();
+        for (player_index, player) in self.players.iter_mut() {
+            let ping = player.ping_data();
+            let data = match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => continue,
+            };
+            let ping = ping.map_or(0, |ping| (ping.avg * 1000.0) as u32);
+            let jitter = (data.network_quality.jitter() * 1000.0) as u32;
+            let reason = if limits.ping > 0 && ping > limits.ping {
+                Some(format!("ping {} ms, the limit is {} ms", ping, limits.ping))
+            } else if limits.jitter > 0 && jitter > limits.jitter {
+                Some(format!(
+                    "jitter {} ms, the limit is {} ms",
+                    jitter, limits.jitter
+                ))
+            } else {
+                None
+            };
+            let seconds = data.network_quality.count_over_limit(reason.is_some());
+            if let Some(reason) = reason {
+                if seconds == WARN_SECONDS || (limits.kick && seconds >= KICK_SECONDS) {
+                    warnings.push((player_index, player.player_name.clone(), reason, seconds));
+                }
+            }
+        }
+        for (player_index, player_name, reason, seconds) in warnings {
+            if seconds >= KICK_SECONDS {
+                behaviour.before_player_exit(self, player_index);
+                self.remove_player(player_index, true);
+                info!(
+                    player = %player_name,
+                    player_index = player_index.0,
+                    "{} ({}) was kicked for a poor connection: {}",
+                    player_name,
+                    player_index,
+                    reason
+                );
+                let msg = format!("{} was kicked for a poor connection", player_name);
+                self.messages.add_server_chat_message(msg);
+            } else {
+                info!(
+                    "{} ({}) has a poor connection: {}",
+                    player_name, player_index, reason
+                );
+                let msg = if limits.kick {
+                    format!(
+                        "Poor connection: {}. You will be kicked in {} seconds if it doesn't improve",
+                        reason,
+                        KICK_SECONDS - WARN_SECONDS
+                    )
+                } else {
+                    format!("Poor connection: {}", reason)
+                };
+                self.messages
+                    .add_directed_server_chat_message(msg, player_index);
+            }
+        }
+    }
+
+    fn remove_inactive_players<B: HQMServerBehaviour>(&mut self, behaviour: &mut B) {
+        let inactive_players: smallvec::SmallVec<[_; 8]> = self
+            .players
+            .iter_mut()
+            .filter_map(|(player_index, player)| {
+                if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
+                    data.inactivity += 1;
+                    data.handshake_steps += 1;
+                    if data.inactivity > 500 {
+                        Some((player_index, player.player_name.clone(), None))
+                    } else if data.handshake_steps > HANDSHAKE_TIMEOUT {
+                        // The client sends updates, but never for the game it was sent
+                        Some((player_index, player.player_name.clone(), Some(data.addr)))
+                    } else {
+                        None
+                    }
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (player_index, player_name, zombie_addr) in inactive_players {
+            behaviour.before_player_disconnect(self, player_index);
+            behaviour.before_player_exit(self, player_index);
+            self.remove_player(player_index, true);
+            if let Some(addr) = zombie_addr {
+                self.packet_rate_limiter
+                    .report_incomplete_join(addr.ip(), Instant::now());
+                info!(
+                    player = %player_name,
+                    player_index = player_index.0,
+                    %addr,
+                    "{} ({}) never finished joining",
+                    player_name,
+                    player_index
+                );
+            } else {
+                info!(
+                    player = %player_name,
+                    player_index = player_index.0,
+                    "{} ({}) timed out",
+                    player_name,
+                    player_index
+                );
+            }
+            let chat_msg = format!("{} timed out", player_name);
+            self.messages.add_server_chat_message(chat_msg);
+        }
+    }
+
+    async fn tick<B: HQMServerBehaviour>(
+        &mut self,
+        socket: &UdpSocket,
+        behaviour: &mut B,
+        write_buf: &mut BytesMut,
+    ) {
+        if self.player_count() != 0 {
+            if !self.has_current_game_been_active {
+                self.start_time = Utc::now();
+                self.has_current_game_been_active = true;
+                behaviour.game_started(self);
+                info!(target: GAME_LOG, "New game {} started", self.game_id);
+                if let Some(snapshot) = self.resume_snapshot.take() {
+                    self.restore_game(&snapshot, behaviour);
+                }
+            }
+
+            let (game_step, forced_view) = tokio::task::block_in_place(|| {
+                self.remove_inactive_players(behaviour);
+                self.check_network_quality(behaviour);
+
+                let has_replay_data = if let Some(replay_element) = self.replay_queue.front_mut() {
+                    if let Some(tick) = replay_element.data.pop_front() {
+                        Some((replay_element.force_view, tick))
+                    } else {
+                        self.replay_queue.pop_front();
+                        None
+                    }
+                } else {
+                    None
                 };
 
                 if let Some((forced_view, tick)) = has_replay_data {
                     let game_step = tick.game_step;
                     let packets = tick.packets;
-                    self.saved_packets.truncate(192 - 1);
+                    self.saved_packets.truncate(self.packet_history_len() - 1);
                     self.saved_packets.push_front(packets);
                     self.saved_pings.truncate(100 - 1);
                     self.saved_pings.push_front(Instant::now());
 
                     self.packet = self.packet.wrapping_add(1);
                     (game_step, forced_view)
+                } else if self.frozen && self.pending_steps == 0 {
+                    self.frozen_step();
+                    (self.game_step, None)
                 } else {
+                    if self.frozen {
+                        self.pending_steps -= 1;
+                    }
                     self.game_step(behaviour);
                     (self.game_step, None)
                 }
@@ -1187,25 +2512,74 @@ impl HQMServer {
                 }
             }
 
+            self.saved_steps.truncate(self.packet_history_len() - 1);
+            self.saved_steps.push_front(HQMSavedStep {
+                game_step,
+                values: self.values,
+                force_view: forced_view,
+            });
+
             send_updates(
                 self.game_id,
                 &self.saved_packets,
-                game_step,
-                self.values.game_over,
-                self.values.red_score,
-                self.values.blue_score,
-                self.values.time,
-                self.values.goal_message_timer,
-                self.values.period,
-                self.values.rules_state,
+                &self.saved_steps,
+                self.config.spectator_delay,
                 self.packet,
-                &self.players.players,
+                &mut self.players.players,
                 socket,
-                forced_view,
                 write_buf,
+                self.config.compression,
+                &mut self.compression_stats,
+                &mut self.send_batch,
             )
             .await;
 
+            if self.packet.wrapping_sub(self.last_world_checksum) >= WORLD_CHECKSUM_INTERVAL {
+                self.last_world_checksum = self.packet;
+                self.send_world_checksums(socket, game_step, write_buf)
+                    .await;
+            }
+            self.send_replay_streams(socket, game_step, write_buf).await;
+
+            self.compression_stats.log_timer += 1;
+            if self.compression_stats.log_timer >= 6000 {
+                self.compression_stats.log_timer = 0;
+            }
+            if self.compression_stats.log_timer == 0
+                && self.compression_stats.compressed_packets > 0
+            {
+                let stats = &self.compression_stats;
+                info!(
+                    "Compressed {} of {} update packets, {} bytes uncompressed, {} bytes sent",
+                    stats.compressed_packets,
+                    stats.compressed_packets + stats.fallback_packets,
+                    stats.uncompressed_bytes,
+                    stats.sent_bytes
+                );
+            }
+            if self.compression_stats.log_timer == 0 && self.send_batch.stats.packets > 0 {
+                let stats = &self.send_batch.stats;
+                debug!(
+                    "Sent {} update packets with {} system calls",
+                    stats.packets, stats.system_calls
+                );
+            }
+
+            if self.standby_game.is_none() && self.values.game_over {
+                // Prepare the next game while this one is over, so that the tick that starts the
+                // next game doesn't have to. Games that are reset early build their world then
+                let physics_config = match &self.physics_override {
+                    Some(physics_override) => physics_override.clone(),
+                    None => self.world.physics_config.clone(),
+                };
+                self.standby_game = Some(HQMStandbyGame::new(
+                    self.world.puck_slots,
+                    physics_config,
+                    &self.config.rink,
+                    self.config.replays_enabled,
+                ));
+            }
+
             let game_step = self.game_step;
             while let Some((start_step, end_step, force_view)) = self.requested_replays.pop_front()
             {
@@ -1223,80 +2597,812 @@ impl HQMServer {
                 self.replay_queue
                     .push_back(ReplayElement { data, force_view })
             }
-        } else if self.has_current_game_been_active {
+        } else if self.has_current_game_been_active && self.shutdown.is_none() {
             info!("Game {} abandoned", self.game_id);
             self.new_game(behaviour.get_initial_game_values());
             behaviour.game_started(self);
             self.allow_join = true;
         }
+        let autosave_interval = Duration::from_secs(self.config.autosave_interval.into());
+        if autosave_interval > Duration::ZERO
+            && self.autosave.last_save.elapsed() >= autosave_interval
+        {
+            tokio::task::block_in_place(|| self.autosave());
+        }
+        self.report_failed_objects();
+        if self.alerts.check_due() {
+            self.check_alerts();
+        }
+        self.deliver_stats_replies();
+        self.update_status();
+        if self
+            .boards
+            .update(&self.config.boards, self.config.board_rotation)
+        {
+            self.send_rink_info_updates(socket, write_buf).await;
+        }
+        self.send_sound_cues(socket, write_buf).await;
+        self.update_shutdown();
     }
 
-    pub fn new_game(&mut self, v: HQMInitialGameValues) {
-        self.values = v.values;
-        self.world = HQMGameWorld::new(v.puck_slots, v.physics_configuration);
-        self.game_id += 1;
-        self.messages.clear();
+    fn load_ban_list(&mut self, path: PathBuf) {
+        let mut ban_file = HQMAutosaveFile::new(path);
+        let ban_list = ban_file.load(|contents| {
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| line.parse::<IpAddr>().ok())
+                .collect::<Option<HashSet<_>>>()
+        });
+        if let Some(ban_list) = ban_list {
+            info!(
+                "Loaded {} bans from {}",
+                ban_list.len(),
+                ban_file.path().display()
+            );
+            self.ban_list = ban_list;
+        }
+        self.autosave.ban_file = Some(ban_file);
+    }
 
-        self.replay_msg_pos = 0;
-        self.packet = u32::MAX;
-        self.replay_last_packet = u32::MAX;
-        self.game_step = u32::MAX;
+    fn load_preferences(&mut self, path: PathBuf) {
+        let mut preferences_file = HQMAutosaveFile::new(path);
+        if let Some(preferences) = preferences_file.load(HQMPreferences::parse) {
+            info!(
+                "Loaded the preferences of {} players from {}",
+                preferences.len(),
+                preferences_file.path().display()
+            );
+            self.preferences = preferences;
+        }
+        self.autosave.preferences_file = Some(preferences_file);
+    }
 
-        self.saved_packets.clear();
-        self.saved_pings.clear();
-        self.saved_history.clear();
-        self.replay_queue.clear();
-        self.has_current_game_been_active = false;
+    fn load_snapshot(&mut self, path: PathBuf) {
+        let mut snapshot_file = HQMAutosaveFile::new(path);
+        if self.config.resume {
+            let snapshot = snapshot_file
+                .load(|contents| serde_json::from_str::<Option<HQMGameSnapshot>>(contents).ok());
+            match snapshot {
+                Some(Some(snapshot)) => {
+                    info!(
+                        "Loaded game snapshot from {}, it's resumed when a player joins",
+                        snapshot_file.path().display()
+                    );
+                    self.resume_snapshot = Some(snapshot);
+                }
+                _ => info!(
+                    "No game in progress in {} to resume",
+                    snapshot_file.path().display()
+                ),
+            }
+        }
+        self.autosave.snapshot_file = Some(snapshot_file);
+    }
+
+    pub(crate) fn restore_game<B: HQMServerBehaviour>(
+        &mut self,
+        snapshot: &HQMGameSnapshot,
+        behaviour: &mut B,
+    ) {
+        self.values.game_over = false;
+        self.values.red_score = snapshot.red_score;
+        self.values.blue_score = snapshot.blue_score;
+        self.values.period = snapshot.period;
+        self.values.time = snapshot.time;
+        snapshot.restore_pucks(&mut self.world);
+        if let Some(game_record) = snapshot.game_record() {
+            // The puck drops from here on use the seed of the new game
+            let drop_seed = self.game_record.drop_seed;
+            self.game_record = game_record.clone();
+            self.game_record.drop_seed = drop_seed;
+        }
+        info!(
+            target: GAME_LOG,
+            "Game restored from snapshot in period {} at {}-{}",
+            snapshot.period,
+            snapshot.red_score,
+            snapshot.blue_score
+        );
+        behaviour.game_resumed(self, snapshot);
+    }
+
+    fn ban_list_contents(&self) -> String {
+        let mut ban_list: Vec<_> = self.ban_list.iter().collect();
+        ban_list.sort();
+        ban_list.iter().map(|ip| format!("{}\n", ip)).collect()
+    }
 
-        let old_replay_data = std::mem::replace(&mut self.replay_data, BytesMut::new());
+    /// Writes the persistent files whose contents changed since they were last written
+    fn autosave(&mut self) {
+        self.autosave.last_save = Instant::now();
+        let ban_list_contents = self.ban_list_contents();
+        if let Some(ban_file) = &mut self.autosave.ban_file {
+            ban_file.save(ban_list_contents);
+        }
+        if let Some(preferences_file) = &mut self.autosave.preferences_file {
+            preferences_file.save(self.preferences.contents());
+        }
+        // Kept until the snapshot that is waiting to be resumed has been restored
+        if self.resume_snapshot.is_none() {
+            let snapshot = HQMGameSnapshot::take(self);
+            if let Some(snapshot_file) = &mut self.autosave.snapshot_file {
+                snapshot_file.save(serde_json::to_string(&snapshot).unwrap());
+            }
+        }
+    }
 
+    async fn send_rink_info_updates(&self, socket: &UdpSocket, write_buf: &mut BytesMut) {
+        for (_, player) in self.players.iter() {
+            match &player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => {
+                    if data.rink_info {
+                        send_rink_info(socket, data.addr, &self.boards, write_buf).await;
+                    }
+                }
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {}
+            }
+        }
+    }
+
+    fn get_status(&self) -> HQMServerStatus {
+        HQMServerStatus {
+            players: self.player_count(),
+            player_max: self.config.player_max,
+            game_id: self.game_id,
+            game_uuid: self.game_record.id,
+            period: self.values.period,
+            time: self.values.time,
+            red_score: self.values.red_score,
+            blue_score: self.values.blue_score,
+            game_over: self.values.game_over,
+        }
+    }
+
+    /// Updates the status shown by the HTTP API
+    fn update_status(&mut self) {
+        if let Some(sender) = &self.status {
+            let status = self.get_status();
+            sender.send_if_modified(|old_status| {
+                if *old_status == status {
+                    false
+                } else {
+                    *old_status = status;
+                    true
+                }
+            });
+        }
+    }
+
+    /// Sums up the game that just ended in chat
+    fn send_box_score(&mut self) {
+        let lines = self.game_record.box_score(
+            self.values.red_score,
+            self.values.blue_score,
+            self.values.period,
+        );
+        for line in lines {
+            self.messages.add_server_chat_message(line);
+        }
+    }
+
+    /// Sends the statistics of the game that just ended to the statistics database and the
+    /// results directory, and shares the link to its statistics page
+    fn save_game_record(&mut self, regular_periods: Option<u32>) {
+        self.game_record.award_stars();
+        let game = HQMFinishedGame {
+            record: self.game_record.clone(),
+            server: self.config.server_name.clone(),
+            started: self.start_time,
+            ended: Utc::now(),
+            red_score: self.values.red_score,
+            blue_score: self.values.blue_score,
+            overtime: self.game_record.went_to_overtime(regular_periods),
+        };
+        if let Some(directory) = &self.config.results_directory {
+            export_game_result(directory.clone(), &game);
+        }
+        if let Some(stats) = &self.stats {
+            stats.record_game(game);
+            if let Some(url) = &self.config.stats_page_url {
+                let link = format!(
+                    "{}{}{}",
+                    url.trim_end_matches('/'),
+                    GAME_PAGE_PATH,
+                    self.game_record.id.simple()
+                );
+                info!("Statistics of game {} at {}", self.game_id, link);
+                // Longer links would be cut off in chat
+                if link.len() <= 63 {
+                    self.messages.add_server_chat_message("Game summary:");
+                    self.messages.add_server_chat_message(link);
+                }
+            }
+        }
+    }
+
+    /// Warns the log and the admins about pucks and skaters that didn't fit in the object slots,
+    /// at most every ten seconds. Skaters that don't fit stay spectators.
+    fn report_failed_objects(&mut self) {
+        let (pucks, skaters) = (self.world.failed_pucks, self.world.failed_skaters);
+        if pucks == 0 && skaters == 0
+            || matches!(self.last_failed_objects_warning, Some(last) if last.elapsed() < Duration::from_secs(10))
+        {
+            return;
+        }
+        self.world.failed_pucks = 0;
+        self.world.failed_skaters = 0;
+        self.last_failed_objects_warning = Some(Instant::now());
+        let mut messages = vec![];
+        if pucks > 0 {
+            warn!(
+                pucks,
+                "Could not create {} pucks, the world already has {} pucks",
+                pucks,
+                self.world.objects.pucks().count()
+            );
+            messages.push(format!(
+                "No object slot for {} pucks, max is {}",
+                pucks, MAX_PUCKS
+            ));
+        }
+        if skaters > 0 {
+            warn!(
+                skaters,
+                "Could not create {} skaters, all {} network object slots are in use",
+                skaters,
+                NETWORK_OBJECT_SLOTS
+            );
+            messages.push(format!(
+                "No object slot for {} skaters, too many pucks",
+                skaters
+            ));
+        }
+        let admins: Vec<_> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.is_admin)
+            .map(|(player_index, _)| player_index)
+            .collect();
+        for message in messages {
+            for &admin_index in admins.iter() {
+                self.messages
+                    .add_directed_server_chat_message(message.clone(), admin_index);
+            }
+        }
+    }
+
+    /// Sends an alert to the admins who subscribed to alerts, unless the same alert about the
+    /// same subject was sent recently
+    pub(crate) fn send_alert(&mut self, kind: HQMAlertKind, subject: &str, msg: String) {
+        if !self.alerts.should_send(kind, subject) {
+            return;
+        }
+        warn!("Alert: {}", msg);
+        let subscribers: smallvec::SmallVec<[_; 8]> = self
+            .players
+            .iter()
+            .filter(|(_, player)| {
+                matches!(&player.data, HQMServerPlayerData::NetworkPlayer { data } if player.is_admin && data.alerts)
+            })
+            .map(|(admin_index, _)| admin_index)
+            .collect();
+        for admin_index in subscribers {
+            self.messages
+                .add_directed_server_chat_message(msg.clone(), admin_index);
+        }
+    }
+
+    /// Checks the pings of the players, the master servers and the free disk space
+    fn check_alerts(&mut self) {
+        let ping_limit = self.config.alerts.ping;
+        if ping_limit > 0 {
+            let high_pings: smallvec::SmallVec<[_; 8]> = self
+                .players
+                .iter()
+                .filter_map(|(player_index, player)| {
+                    let ping = (player.ping_data()?.avg * 1000.0) as u32;
+                    (ping > ping_limit).then(|| (player_index, player.player_name.clone(), ping))
+                })
+                .collect();
+            for (player_index, player_name, ping) in high_pings {
+                let msg = format!(
+                    "High ping: {} ({}) has {} ms, the limit is {} ms",
+                    player_name, player_index, ping, ping_limit
+                );
+                self.send_alert(HQMAlertKind::Ping, &player_name, msg);
+            }
+        }
+
+        let mut master_server_failures = vec![];
+        for (i, notifier) in self.master_servers.iter().enumerate() {
+            let (failures, last_error) = notifier.failures();
+            let new_failures = self.alerts.new_master_server_failures(i, failures);
+            if new_failures > 0 {
+                master_server_failures.push((
+                    notifier.master_server.to_string(),
+                    new_failures,
+                    last_error.unwrap_or_default(),
+                ));
+            }
+        }
+        for (master_server, new_failures, last_error) in master_server_failures {
+            let msg = format!(
+                "Could not register with master server {} ({} new failures): {}",
+                master_server, new_failures, last_error
+            );
+            self.send_alert(HQMAlertKind::MasterServer, &master_server, msg);
+        }
+
+        let disk_space_limit = self.config.alerts.disk_space;
+        if disk_space_limit > 0 {
+            if let Some(free_disk_space) = self.alerts.free_disk_space() {
+                let free_megabytes = free_disk_space / (1024 * 1024);
+                if free_megabytes < u64::from(disk_space_limit) {
+                    let msg = format!(
+                        "Disk nearly full: {} MB free, the limit is {} MB",
+                        free_megabytes, disk_space_limit
+                    );
+                    self.send_alert(HQMAlertKind::DiskSpace, "", msg);
+                }
+            }
+        }
+    }
+
+    /// Compares how the skaters of the clients moved in the last tick with what skating can do
+    fn check_movement<B: HQMServerBehaviour>(&mut self, behaviour: &mut B) {
+        let movement_check = self.config.movement_check;
+        if movement_check == HQMMovementCheck::Off {
+            return;
+        }
+        let mut strikes: smallvec::SmallVec<[_; 4]> = smallvec::SmallVec::new();
+        for (player_index, player) in self.players.iter_mut() {
+            let data = match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => continue,
+            };
+            let skater = player
+                .object
+                .and_then(|(object_index, _)| self.world.objects.get_skater(object_index));
+            match skater {
+                Some(skater) => {
+                    let strike = data
+                        .movement_monitor
+                        .check(&skater.body.linear_velocity, &self.world.physics_config);
+                    if let Some(strike) = strike {
+                        strikes.push((player_index, player.player_name.clone(), strike));
+                    }
+                }
+                None => data.movement_monitor.reset(),
+            }
+        }
+        for (player_index, player_name, strike) in strikes {
+            warn!(
+                "{} ({}) moved faster than skating allows in {} ticks of the last second, up to {:.1} m/s ({} strikes)",
+                player_name, player_index, strike.anomalies, strike.top_speed, strike.strikes
+            );
+            if !strike.flag || movement_check == HQMMovementCheck::Log {
+                continue;
+            }
+            if movement_check == HQMMovementCheck::Kick {
+                behaviour.before_player_exit(self, player_index);
+                self.remove_player(player_index, true);
+                info!(
+                    player = %player_name,
+                    player_index = player_index.0,
+                    "{} ({}) was kicked for impossible movement",
+                    player_name,
+                    player_index
+                );
+                let msg = format!("{} was kicked for impossible movement", player_name);
+                self.messages.add_server_chat_message(msg);
+            } else {
+                let msg = format!(
+                    "Impossible movement: {} ({}), up to {:.1} m/s",
+                    player_name, player_index, strike.top_speed
+                );
+                let admins: smallvec::SmallVec<[_; 8]> = self
+                    .players
+                    .iter()
+                    .filter(|(_, player)| player.is_admin)
+                    .map(|(admin_index, _)| admin_index)
+                    .collect();
+                for admin_index in admins {
+                    self.messages
+                        .add_directed_server_chat_message(msg.clone(), admin_index);
+                }
+            }
+        }
+    }
+
+    /// Warns the players on the ice whose input hasn't changed for a while, and moves them to
+    /// the spectators when afk_time is reached
+    fn check_afk(&mut self) {
+        let afk_time = self.config.afk_time;
+        if afk_time == 0 {
+            return;
+        }
+        let mut warned: smallvec::SmallVec<[_; 4]> = smallvec::SmallVec::new();
+        let mut away: smallvec::SmallVec<[_; 4]> = smallvec::SmallVec::new();
+        for (player_index, player) in self.players.iter_mut() {
+            let data = match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => continue,
+            };
+            if player.object.is_none() {
+                data.idle_steps = 0;
+                continue;
+            }
+            data.idle_steps += 1;
+            match afk_status(data.idle_steps, afk_time) {
+                HQMAfkStatus::Warn { seconds_left } => warned.push((player_index, seconds_left)),
+                HQMAfkStatus::Away => away.push((player_index, player.player_name.clone())),
+                HQMAfkStatus::Active => {}
+            }
+        }
+        for (player_index, seconds) in warned {
+            let msg = format!(
+                "You will be moved to the spectators in {} seconds if you don't move",
+                seconds
+            );
+            self.messages
+                .add_directed_server_chat_message(msg, player_index);
+        }
+        for (player_index, player_name) in away {
+            if self.move_to_spectator(player_index) {
+                info!(
+                    "{} ({}) was moved to the spectators for being away",
+                    player_name, player_index
+                );
+                let msg = format!("{} was moved to the spectators for being away", player_name);
+                self.messages.add_server_chat_message(msg);
+            }
+        }
+    }
+
+    fn report_impossible_input(
+        &mut self,
+        player_index: HQMServerPlayerIndex,
+        raw_input: &HQMPlayerInput,
+    ) {
+        let (player_name, impossible_inputs) = match self.players.get(player_index) {
+            Some(HQMServerPlayer {
+                player_name,
+                data: HQMServerPlayerData::NetworkPlayer { data },
+                ..
+            }) => (player_name.clone(), data.input_monitor.impossible_inputs()),
+            _ => return,
+        };
+        warn!(
+            "{} ({}) sent {} packets with impossible input values, the last one was {:?}",
+            player_name, player_index, impossible_inputs, raw_input
+        );
+        let msg = format!(
+            "Impossible input values: {} ({}), possibly a modified client",
+            player_name, player_index
+        );
+        let admins: smallvec::SmallVec<[_; 8]> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.is_admin)
+            .map(|(admin_index, _)| admin_index)
+            .collect();
+        for admin_index in admins {
+            self.messages
+                .add_directed_server_chat_message(msg.clone(), admin_index);
+        }
+    }
+
+    fn report_input_macro(&mut self, player_index: HQMServerPlayerIndex) {
+        let (player_name, strikes, summary, recording) = match self.players.get(player_index) {
+            Some(HQMServerPlayer {
+                player_name,
+                data: HQMServerPlayerData::NetworkPlayer { data },
+                ..
+            }) => (
+                player_name.clone(),
+                data.input_monitor.strikes(),
+                data.input_monitor.summary(),
+                data.input_monitor.recording(),
+            ),
+            _ => return,
+        };
+        warn!(
+            "{} ({}) toggled keys too fast during {} seconds: {}",
+            player_name, player_index, strikes, summary
+        );
+        let msg = format!(
+            "Possible input macro: {} ({}), {}",
+            player_name, player_index, summary
+        );
+        let admins: smallvec::SmallVec<[_; 8]> = self
+            .players
+            .iter()
+            .filter(|(_, player)| player.is_admin)
+            .map(|(admin_index, _)| admin_index)
+            .collect();
+        for admin_index in admins {
+            self.messages
+                .add_directed_server_chat_message(msg.clone(), admin_index);
+        }
+
+        let time = Utc::now().format("%Y-%m-%dT%H%M%S").to_string();
+        let safe_name: String = player_name
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let file_name = format!("{}.{}.{}.txt", self.config.server_name, safe_name, time);
+        let contents = format!(
+            "{} ({})\n{}\n{}",
+            player_name, player_index, summary, recording
+        );
+        tokio::spawn(async move {
+            if tokio::fs::create_dir_all("input_logs").await.is_err() {
+                return;
+            }
+            let path: PathBuf = ["input_logs", &file_name].iter().collect();
+            if let Err(e) = tokio::fs::write(path, contents).await {
+                warn!("Could not save input recording: {}", e);
+            }
+        });
+    }
+
+    /// Sets the highest rate of replay streams, in kilobytes per second. Streams that are faster
+    /// are slowed down, and all of them are stopped if the rate is 0
+    pub fn set_replay_stream_rate(&mut self, rate: u32) {
+        self.config.replay_stream_rate = rate;
+        for (player_index, player) in self.players.iter_mut() {
+            let replay_stream = match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => &mut data.replay_stream,
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => continue,
+            };
+            if rate == 0 {
+                if let Some(old_stream) = replay_stream.take() {
+                    info!(
+                        "Stopped the replay stream of {} ({}), {} steps were skipped",
+                        player.player_name, player_index, old_stream.skipped_steps
+                    );
+                }
+            } else if let Some(replay_stream) = replay_stream {
+                replay_stream.limit_rate(rate.saturating_mul(1000));
+            }
+        }
+    }
+
+    pub(crate) fn set_public(&mut self, public: bool) {
+        self.config.public = public;
+        for master_server in self.master_servers.iter_mut() {
+            if public {
+                master_server.start();
+            } else {
+                master_server.stop();
+            }
+        }
+    }
+
+    pub fn start_shutdown(&mut self, seconds: u32) {
+        // A shutdown that replaces another keeps what joining was like before the first one
+        let allow_join_before = match &self.shutdown {
+            Some(shutdown) => shutdown.allow_join_before,
+            None => self.allow_join,
+        };
+        self.allow_join = false;
+        let mut countdown = HQMChatCountdown::new();
+        countdown.update(seconds * 100);
+        self.shutdown = Some(HQMShutdown {
+            timer: seconds * 100,
+            countdown,
+            allow_join_before,
+        });
+        info!("Server shutting down in {} seconds", seconds);
+        let msg = if seconds == 0 {
+            "Server shutting down".to_string()
+        } else {
+            format!("Server shutting down in {} seconds", seconds)
+        };
+        self.messages.add_server_chat_message(msg);
+    }
+
+    pub fn cancel_shutdown(&mut self) -> bool {
+        if let Some(shutdown) = self.shutdown.take() {
+            self.allow_join = shutdown.allow_join_before;
+            info!("Server shutdown cancelled");
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_some()
+    }
+
+    fn update_shutdown(&mut self) {
+        let player_count = self.player_count();
+        if let Some(shutdown) = &mut self.shutdown {
+            if player_count == 0 {
+                shutdown.timer = 0;
+            }
+            shutdown.timer = shutdown.timer.saturating_sub(1);
+            if let Some(seconds) = shutdown.countdown.update(shutdown.timer) {
+                let msg = if seconds == 1 {
+                    "Server shutting down in 1 second".to_string()
+                } else {
+                    format!("Server shutting down in {} seconds", seconds)
+                };
+                self.messages.add_server_chat_message(msg);
+            }
+        }
+    }
+
+    fn is_shutdown_due(&self) -> bool {
+        matches!(self.shutdown, Some(HQMShutdown { timer: 0, .. }))
+    }
+
+    /// Saves the replay of the current game and the persistent files, and waits for all replays
+    /// that are still being saved
+    async fn finish_shutdown(&mut self) {
+        self.autosave();
+        if self.has_current_game_been_active {
+            let replay_data = std::mem::take(&mut self.replay_data);
+            self.save_replay(replay_data);
+        }
+        for handle in self.pending_replay_saves.drain(..) {
+            let _ = handle.await;
+        }
+        info!("Server shut down");
+    }
+
+    fn save_replay(&mut self, old_replay_data: BytesMut) {
+        self.pending_replay_saves
+            .retain(|handle| !handle.is_finished());
+        let old_replay_data = old_replay_data.freeze();
+        let time = self.start_time.format("%Y-%m-%dT%H%M%S").to_string();
+        let highlights = std::mem::take(&mut self.highlights).into_highlights();
+        if let Some(directory) = &self.config.highlights_directory {
+            if !highlights.is_empty() && !old_replay_data.is_empty() {
+                let directory = directory.clone();
+                let replay_name = format!("{}.{}", self.config.server_name, time);
+                let replay_data = old_replay_data.clone();
+                // Reading the whole replay takes a while, so it's not done on the game's thread
+                let handle = tokio::task::spawn_blocking(move || {
+                    let replay_file = replay_file_data(&replay_data);
+                    save_highlights(&directory, &replay_name, &replay_file, &highlights);
+                });
+                self.pending_replay_saves.push(handle);
+            }
+        }
         if self.config.replays_enabled == ReplayEnabled::On && !old_replay_data.is_empty() {
-            let size = old_replay_data.len();
-            let mut replay_data = BytesMut::with_capacity(size + 8);
-            replay_data.put_u32_le(0u32);
-            replay_data.put_u32_le(size as u32);
-            replay_data.put_slice(&old_replay_data);
-            let replay_data = replay_data.freeze();
-            let time = self.start_time.format("%Y-%m-%dT%H%M%S").to_string();
             let file_name = format!("{}.{}.hrp", self.config.server_name, time);
             let server_name = self.config.server_name.clone();
             match self.config.replay_saving {
-                ReplaySaving::File => {
-                    tokio::spawn(async move {
-                        if tokio::fs::create_dir_all("replays").await.is_err() {
+                ReplaySaving::File {
+                    ref directory,
+                    max_files,
+                } => {
+                    let directory = directory.clone();
+                    let handle = tokio::spawn(async move {
+                        let replay_data = replay_file_data(&old_replay_data);
+                        if let Err(e) = tokio::fs::create_dir_all(&directory).await {
+                            warn!(error = %e, "Could not create {}: {}", directory.display(), e);
                             return;
                         };
-                        let path: PathBuf = ["replays", &file_name].iter().collect();
+                        let path = directory.join(&file_name);
 
-                        let mut file_handle = match File::create(path).await {
+                        let mut file_handle = match File::create(&path).await {
                             Ok(file) => file,
                             Err(e) => {
-                                println!("{:?}", e);
+                                warn!(error = %e, "Could not save replay {}: {}", file_name, e);
                                 return;
                             }
                         };
 
-                        let _x = file_handle.write(&replay_data).await;
+                        let _x = file_handle.write_all(&replay_data).await;
                         let _x = file_handle.sync_all().await;
+                        info!("Saved replay {}", path.display());
+                        if max_files > 0 {
+                            remove_old_replays(&directory, &server_name, max_files).await;
+                        }
                     });
+                    self.pending_replay_saves.push(handle);
                 }
                 ReplaySaving::Endpoint { ref url } => {
                     let client = self.reqwest_client.clone();
-                    let form = reqwest::multipart::Form::new()
-                        .text("time", time)
-                        .text("server", server_name)
-                        .part(
-                            "replay",
-                            reqwest::multipart::Part::stream(replay_data).file_name(file_name),
-                        );
+                    let url = url.clone();
+                    let handle = tokio::spawn(async move {
+                        let replay_data = replay_file_data(&old_replay_data);
+                        let form = reqwest::multipart::Form::new()
+                            .text("time", time)
+                            .text("server", server_name)
+                            .part(
+                                "replay",
+                                reqwest::multipart::Part::stream(replay_data).file_name(file_name),
+                            );
 
-                    let request = client.post(url).multipart(form);
-                    tokio::spawn(async move {
+                        let request = client.post(url).multipart(form);
                         let _x = request.send().await;
                     });
+                    self.pending_replay_saves.push(handle);
                 }
             }
         }
+    }
+
+    /// Records the seed of the random puck drop delays with the game, so that the drops can be
+    /// reproduced from the game report
+    pub(crate) fn record_drop_seed(&mut self, seed: u64) {
+        self.game_record.drop_seed = Some(seed);
+    }
+
+    /// Number of the game since the server started, which goes up with every new game
+    pub fn game_id(&self) -> u32 {
+        self.game_id
+    }
+
+    pub fn new_game(&mut self, v: HQMInitialGameValues) {
+        let was_game_over = self.values.game_over;
+        let physics_configuration = match &self.physics_override {
+            Some(physics_override) => physics_override.clone(),
+            None => v.physics_configuration,
+        };
+        let standby_game = match self.standby_game.take() {
+            Some(standby_game)
+                if standby_game.world.puck_slots == v.puck_slots
+                    && standby_game.world.physics_config == physics_configuration =>
+            {
+                standby_game
+            }
+            _ => HQMStandbyGame::new(
+                v.puck_slots,
+                physics_configuration,
+                &self.config.rink,
+                self.config.replays_enabled,
+            ),
+        };
+        self.values = v.values;
+        self.world = standby_game.world;
+        self.game_id += 1;
+        self.game_record = HQMGameRecord::new();
+        self.sticks_held = false;
+        self.messages.clear();
+        self.autosave();
+
+        if was_game_over {
+            self.completed_games += 1;
+            let restart_after_games = self.config.restart_after_games;
+            if restart_after_games > 0
+                && self.completed_games >= restart_after_games
+                && self.shutdown.is_none()
+            {
+                info!("Restarting after {} games", self.completed_games);
+                let msg = format!("Server restarts after {} games", self.completed_games);
+                self.messages.add_server_chat_message(msg);
+                self.start_shutdown(10);
+            }
+        }
+
+        self.replay_msg_pos = 0;
+        self.packet = u32::MAX;
+        self.replay_last_packet = u32::MAX;
+        self.replay_steps = 0;
+        self.game_step = u32::MAX;
+
+        self.saved_packets.clear();
+        self.saved_steps.clear();
+        for (_, player) in self.players.iter_mut() {
+            match &mut player.data {
+                HQMServerPlayerData::NetworkPlayer { data } => data.object_slots.clear(),
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => {}
+            }
+        }
+        self.saved_pings.clear();
+        self.saved_history.clear();
+        self.replay_queue.clear();
+        self.has_current_game_been_active = false;
+
+        let old_replay_data = std::mem::replace(&mut self.replay_data, standby_game.replay_data);
+        self.save_replay(old_replay_data);
 
         for (player_index, p) in self.players.players.iter_mut().enumerate() {
             let player_index = HQMServerPlayerIndex(player_index);
@@ -1316,20 +3422,86 @@ impl HQMServer {
                 }
             }
         }
-    }
-
-    pub fn add_replay_to_queue(
-        &mut self,
-        start_step: u32,
-        end_step: u32,
-        force_view: Option<HQMServerPlayerIndex>,
-    ) {
-        if start_step > end_step {
-            warn!("start_step must be less than or equal to end_step");
+    }
+
+    pub fn add_replay_to_queue(
+        &mut self,
+        start_step: u32,
+        end_step: u32,
+        force_view: Option<HQMServerPlayerIndex>,
+    ) {
+        if start_step > end_step {
+            warn!("start_step must be less than or equal to end_step");
+            return;
+        }
+        self.requested_replays
+            .push_back((start_step, end_step, force_view));
+    }
+
+    /// Replays the last seconds of the game to everyone from the history of the game mode. The
+    /// game waits while the replay plays, like it does for goal replays
+    fn instant_replay(&mut self, player_index: HQMServerPlayerIndex, arg: &str) {
+        let player = match self.players.get(player_index) {
+            Some(player) => player,
+            None => return,
+        };
+        match self.config.instant_replay {
+            HQMInstantReplay::Off => {
+                self.messages
+                    .add_directed_server_chat_message("Instant replays are disabled", player_index);
+                return;
+            }
+            HQMInstantReplay::Admins if !player.is_admin => {
+                self.admin_deny_message(player_index);
+                return;
+            }
+            _ => {}
+        }
+        let player_name = player.player_name.clone();
+        let max_seconds = (self.history_length / 100) as u32;
+        if max_seconds == 0 {
+            self.messages.add_directed_server_chat_message(
+                "Instant replays aren't available in this game mode",
+                player_index,
+            );
+            return;
+        }
+        let seconds = if arg.is_empty() {
+            Ok(DEFAULT_INSTANT_REPLAY_SECONDS.min(max_seconds))
+        } else {
+            arg.parse::<u32>()
+        };
+        let seconds = match seconds {
+            Ok(seconds) if (1..=max_seconds).contains(&seconds) => seconds,
+            _ => {
+                let msg = format!("Usage: /replay [seconds], at most {}", max_seconds);
+                self.messages
+                    .add_directed_server_chat_message(msg, player_index);
+                return;
+            }
+        };
+        if !self.replay_queue.is_empty() || !self.requested_replays.is_empty() {
+            self.messages
+                .add_directed_server_chat_message("A replay is already playing", player_index);
+            return;
+        }
+        // The history can be shorter than the replay at the start of a game
+        let steps = (seconds * 100).min(self.saved_history.len().saturating_sub(1) as u32);
+        if steps == 0 {
+            self.messages
+                .add_directed_server_chat_message("There is nothing to replay yet", player_index);
             return;
         }
-        self.requested_replays
-            .push_back((start_step, end_step, force_view));
+        info!(
+            "{} ({}) started an instant replay of {} seconds",
+            player_name, player_index, seconds
+        );
+        self.add_replay_to_queue(self.game_step - steps, self.game_step, None);
+        let msg = format!(
+            "Instant replay of the last {} seconds by {}",
+            seconds, player_name
+        );
+        self.messages.add_server_chat_message(msg);
     }
 
     pub fn current_game_id(&self) -> u32 {
@@ -1369,7 +3541,14 @@ impl HQMServer {
 
         let packets = &self.saved_packets;
 
-        hqm_parse::write_objects(&mut writer, packets, self.packet, self.replay_last_packet);
+        // Replays only contain the objects in the network slots
+        hqm_parse::write_objects(
+            &mut writer,
+            packets,
+            None,
+            self.packet,
+            self.replay_last_packet,
+        );
         self.replay_last_packet = self.packet;
 
         writer.write_bits(16, remaining_messages as u32);
@@ -1380,13 +3559,277 @@ impl HQMServer {
         }
         self.replay_msg_pos = self.messages.replay_messages.len();
         writer.replay_fix();
+        self.replay_steps += 1;
+    }
+
+    /// The clock and score now, with the step of the replay that is written next
+    fn highlight_time(&self) -> HQMHighlightTime {
+        HQMHighlightTime {
+            step: self.replay_steps,
+            period: self.values.period,
+            time: self.values.time,
+            red_score: self.values.red_score,
+            blue_score: self.values.blue_score,
+        }
+    }
+}
+
+/// True if the file is a whole game replay saved by the server with this name, like
+/// "MyServer.2024-01-01T200000.hrp", and not a highlight or a replay of another server
+fn is_replay_of(file_name: &str, server_name: &str) -> bool {
+    file_name
+        .strip_prefix(server_name)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.strip_suffix(".hrp"))
+        .is_some_and(|time| !time.is_empty() && !time.contains('.'))
+}
+
+/// Deletes the oldest replays of this server in the directory, so that at most max_files are left.
+/// Other files in the directory are left alone, as other servers may save their replays there.
+async fn remove_old_replays(directory: &Path, server_name: &str, max_files: usize) {
+    let mut replays = vec![];
+    if let Ok(mut entries) = tokio::fs::read_dir(directory).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if !entry
+                .file_name()
+                .to_str()
+                .is_some_and(|file_name| is_replay_of(file_name, server_name))
+            {
+                continue;
+            }
+            if let Ok(modified) = entry.metadata().await.and_then(|x| x.modified()) {
+                replays.push((modified, path));
+            }
+        }
+    }
+    if replays.len() <= max_files {
+        return;
+    }
+    replays.sort();
+    for (_, path) in replays.iter().take(replays.len() - max_files) {
+        match tokio::fs::remove_file(path).await {
+            Ok(()) => info!("Deleted old replay {}", path.display()),
+            Err(e) => warn!("Could not delete old replay {}: {}", path.display(), e),
+        }
+    }
+}
+
+pub(crate) fn replay_file_data(replay_data: &[u8]) -> bytes::Bytes {
+    let size = replay_data.len();
+    let mut data = BytesMut::with_capacity(size + 8);
+    data.put_u32_le(0u32);
+    data.put_u32_le(size as u32);
+    data.put_slice(replay_data);
+    data.freeze()
+}
+
+/// A game world and replay buffer that are built ahead of time, and swapped in by [`HQMServer::new_game`]
+pub(crate) struct HQMStandbyGame {
+    world: HQMGameWorld,
+    replay_data: BytesMut,
+}
+
+impl HQMStandbyGame {
+    fn new(
+        puck_slots: usize,
+        physics_config: HQMPhysicsProfile,
+        rink_config: &HQMRinkConfiguration,
+        replays_enabled: ReplayEnabled,
+    ) -> Self {
+        let replay_data = if replays_enabled == ReplayEnabled::Off {
+            BytesMut::new()
+        } else {
+            BytesMut::with_capacity(64 * 1024 * 1024)
+        };
+        HQMStandbyGame {
+            world: HQMGameWorld::new(puck_slots, physics_config, rink_config),
+            replay_data,
+        }
+    }
+}
+
+struct HQMPacketRate {
+    window_start: Instant,
+    packets: u32,
+    limited: bool,
+}
+
+/// Steps a client gets to send an update for the current game, after joining or after a new
+/// game started, before its slot is given up
+const HANDSHAKE_TIMEOUT: u32 = 500;
+
+/// Clients from an address that keep joining without finishing the handshake
+struct HQMIncompleteJoins {
+    strikes: u32,
+    blocked_until: Instant,
+}
+
+/// Counts packets per source address in windows of one second, and keeps addresses whose
+/// clients never finish joining from joining again for a while
+struct HQMPacketRateLimiter {
+    sources: HashMap<SocketAddr, HQMPacketRate>,
+    incomplete_joins: HashMap<IpAddr, HQMIncompleteJoins>,
+    last_cleanup: Instant,
+}
+
+impl HQMPacketRateLimiter {
+    fn new() -> Self {
+        HQMPacketRateLimiter {
+            sources: HashMap::new(),
+            incomplete_joins: HashMap::new(),
+            last_cleanup: Instant::now(),
+        }
+    }
+
+    /// Blocks joins from the address for 10 seconds, doubling with every strike up to
+    /// 10 minutes. Strikes are forgotten 10 minutes after the block ends.
+    fn report_incomplete_join(&mut self, ip: IpAddr, now: Instant) {
+        let record = self
+            .incomplete_joins
+            .entry(ip)
+            .or_insert(HQMIncompleteJoins {
+                strikes: 0,
+                blocked_until: now,
+            });
+        record.strikes = record.strikes.saturating_add(1);
+        let block = Duration::from_secs(10)
+            .saturating_mul(1 << (record.strikes - 1).min(6))
+            .min(Duration::from_secs(600));
+        record.blocked_until = now + block;
+        warn!(
+            "Blocking joins from {} for {} seconds, clients from it didn't finish joining {} times",
+            ip,
+            block.as_secs(),
+            record.strikes
+        );
+    }
+
+    fn is_join_blocked(&self, ip: IpAddr, now: Instant) -> bool {
+        matches!(self.incomplete_joins.get(&ip), Some(record) if now < record.blocked_until)
     }
+
+    fn allow(&mut self, addr: SocketAddr, limit: u32, now: Instant) -> bool {
+        if now.duration_since(self.last_cleanup) >= Duration::from_secs(10) {
+            self.sources
+                .retain(|_, rate| now.duration_since(rate.window_start) < Duration::from_secs(10));
+            self.incomplete_joins.retain(|_, record| {
+                now.saturating_duration_since(record.blocked_until) < Duration::from_secs(600)
+            });
+            self.last_cleanup = now;
+        }
+        if limit == 0 {
+            return true;
+        }
+        let rate = self.sources.entry(addr).or_insert(HQMPacketRate {
+            window_start: now,
+            packets: 0,
+            limited: false,
+        });
+        if now.duration_since(rate.window_start) >= Duration::from_secs(1) {
+            if rate.limited && rate.packets <= limit {
+                info!("{:?} is no longer rate limited", addr);
+                rate.limited = false;
+            }
+            rate.window_start = now;
+            rate.packets = 0;
+        }
+        rate.packets = rate.packets.saturating_add(1);
+        if rate.packets > limit {
+            if !rate.limited {
+                warn!(
+                    "Rate limiting {:?}, more than {} packets per second",
+                    addr, limit
+                );
+                rate.limited = true;
+            }
+            false
+        } else {
+            true
+        }
+    }
+}
+
+/// Number of join requests that are remembered while waiting for the client to answer the challenge
+const MAX_PENDING_JOINS: usize = 256;
+
+/// Answers join requests with a game ID derived from the source address and a secret key, and only
+/// gives the client a slot once it sends an update with that game ID. Senders with spoofed addresses
+/// never see the game ID, so they can't take up slots or cause join messages
+struct HQMJoinChallenge {
+    key: RandomState,
+    start: Instant,
+    pending: VecDeque<(SocketAddr, String)>,
+}
+
+impl HQMJoinChallenge {
+    fn new() -> Self {
+        HQMJoinChallenge {
+            key: RandomState::new(),
+            start: Instant::now(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Game IDs change every 10 seconds, and the previous one is still accepted
+    fn epoch(&self, now: Instant) -> u64 {
+        now.duration_since(self.start).as_secs() / 10
+    }
+
+    fn cookie(&self, addr: SocketAddr, epoch: u64) -> u32 {
+        let mut hasher = self.key.build_hasher();
+        epoch.hash(&mut hasher);
+        addr.hash(&mut hasher);
+        hasher.finish() as u32
+    }
+
+    fn challenge(&mut self, addr: SocketAddr, name: String, now: Instant) -> u32 {
+        self.pending
+            .retain(|(pending_addr, _)| *pending_addr != addr);
+        if self.pending.len() >= MAX_PENDING_JOINS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((addr, name));
+        self.cookie(addr, self.epoch(now))
+    }
+
+    /// Returns the name from the join request if the game ID is the one sent to this address
+    fn verify(&mut self, addr: SocketAddr, cookie: u32, now: Instant) -> Option<String> {
+        let epoch = self.epoch(now);
+        let is_valid = cookie == self.cookie(addr, epoch)
+            || (epoch > 0 && cookie == self.cookie(addr, epoch - 1));
+        if !is_valid {
+            return None;
+        }
+        let index = self
+            .pending
+            .iter()
+            .position(|(pending_addr, _)| *pending_addr == addr)?;
+        self.pending.remove(index).map(|(_, name)| name)
+    }
+}
+
+/// Countdown until the server shuts down, in ticks
+struct HQMShutdown {
+    timer: u32,
+    countdown: HQMChatCountdown,
+    /// Whether players could join before the shutdown closed the server, restored if it's cancelled
+    allow_join_before: bool,
+}
+
+/// The header values sent with the objects of a step, kept so delayed feeds can send them
+/// later
+#[derive(Copy, Clone)]
+struct HQMSavedStep {
+    game_step: u32,
+    values: HQMGameValues,
+    force_view: Option<HQMServerPlayerIndex>,
 }
 
 #[derive(Clone, Debug)]
 struct ReplayTick {
     game_step: u32,
-    packets: [HQMObjectPacket; 32],
+    packets: Vec<HQMObjectPacket>,
 }
 
 struct ReplayElement {
@@ -1396,7 +3839,6 @@ struct ReplayElement {
 
 pub async fn run_server<B: HQMServerBehaviour>(
     port: u16,
-    public: Option<&str>,
     config: HQMServerConfiguration,
     mut behaviour: B,
 ) -> std::io::Result<()> {
@@ -1414,11 +3856,13 @@ pub async fn run_server<B: HQMServerBehaviour>(
         },
         messages: HQMServerMessages::new(),
         ban_list: HashSet::new(),
+        preferences: HQMPreferences::default(),
         allow_join: true,
         values: initial_values.values,
         world: HQMGameWorld::new(
             initial_values.puck_slots,
             initial_values.physics_configuration,
+            &config.rink,
         ),
         is_muted: false,
         config,
@@ -1430,12 +3874,47 @@ pub async fn run_server<B: HQMServerBehaviour>(
         replay_msg_pos: 0,
         packet: u32::MAX,
         replay_last_packet: u32::MAX,
+        replay_steps: 0,
+        highlights: HQMHighlightTracker::default(),
 
         saved_packets: VecDeque::with_capacity(192),
+        saved_steps: VecDeque::with_capacity(192),
+        playback_packets: None,
+        last_world_checksum: 0,
         saved_pings: VecDeque::with_capacity(100),
         saved_history: VecDeque::new(),
         has_current_game_been_active: false,
         history_length: 0,
+        chat_history: VecDeque::new(),
+        compression_stats: HQMCompressionStats::default(),
+        send_batch: HQMSendBatch::default(),
+        standby_game: None,
+        shutdown: None,
+        completed_games: 0,
+        physics_override: None,
+        master_servers: vec![],
+        packet_rate_limiter: HQMPacketRateLimiter::new(),
+        join_challenge: HQMJoinChallenge::new(),
+        event_feed: HQMEventFeed::new(),
+        stats: None,
+        alerts: HQMAlerts::new(),
+        last_network_check: Instant::now(),
+        game_record: HQMGameRecord::new(),
+        status: None,
+        autosave: HQMAutosave {
+            ban_file: None,
+            snapshot_file: None,
+            preferences_file: None,
+            last_save: Instant::now(),
+        },
+        resume_snapshot: None,
+        last_failed_objects_warning: None,
+        frozen: false,
+        sticks_held: false,
+        pending_steps: 0,
+        boards: HQMBoards::new(),
+        sound_cues: vec![],
+        pending_replay_saves: vec![],
         game_step: u32::MAX,
         start_time: Default::default(),
     };
@@ -1455,48 +3934,81 @@ pub async fn run_server<B: HQMServerBehaviour>(
         socket.local_addr().unwrap()
     );
 
-    async fn get_http_response(
-        client: &reqwest::Client,
-        address: &str,
-    ) -> Result<SocketAddr, Box<dyn Error + Send + Sync>> {
-        let response = client.get(address).send().await?.text().await?;
-
-        let split = response.split_ascii_whitespace().collect::<Vec<&str>>();
-
-        let addr = split.get(1).unwrap_or(&"").parse::<IpAddr>()?;
-        let port = split.get(2).unwrap_or(&"").parse::<u16>()?;
-        Ok(SocketAddr::new(addr, port))
+    server.master_servers = server
+        .config
+        .master_servers
+        .iter()
+        .map(|master_server| {
+            HQMMasterServerNotifier::new(
+                master_server.clone(),
+                socket.clone(),
+                reqwest_client.clone(),
+            )
+        })
+        .collect();
+    if server.config.public {
+        server.set_public(true);
     }
-
-    if let Some(public) = public {
-        let socket = socket.clone();
-        let reqwest_client = reqwest_client.clone();
-        let address = public.to_string();
+    if let Some(path) = server.config.ban_file.clone() {
+        server.load_ban_list(path);
+    }
+    if let Some(path) = server.config.snapshot_file.clone() {
+        server.load_snapshot(path);
+    }
+    if let Some(path) = server.config.preferences_file.clone() {
+        server.load_preferences(path);
+    }
+    if let Some(path) = server.config.stats_database.clone() {
+        match HQMStatsStore::open(path) {
+            Ok(stats) => server.stats = Some(stats),
+            Err(e) => warn!("Could not open statistics database: {}", e),
+        }
+    }
+    if let Some(address) = server.config.stats_page {
+        let (sender, receiver) = tokio::sync::watch::channel(server.get_status());
+        server.status = Some(sender);
+        let server_name = server.config.server_name.clone();
+        let database = server.config.stats_database.clone();
         tokio::spawn(async move {
-            loop {
-                let master_server = get_http_response(&reqwest_client, &address).await;
-                match master_server {
-                    Ok(addr) => {
-                        for _ in 0..60 {
-                            let msg = b"Hock\x20";
-                            let res = socket.send_to(msg, addr).await;
-                            if res.is_err() {
-                                break;
-                            }
-                            tokio::time::sleep(Duration::from_secs(10)).await;
-                        }
-                    }
-                    Err(e) => {
-                        tracing::warn!(e);
-                        tokio::time::sleep(Duration::from_secs(15)).await;
-                    }
-                }
+            if let Err(e) = run_stats_page(address, server_name, database, receiver).await {
+                warn!("Statistics pages stopped: {}", e);
+            }
+        });
+    }
+    if let Some(address) = server.config.event_feed {
+        let sender = server.event_feed.enable();
+        tokio::spawn(async move {
+            if let Err(e) = run_event_feed(address, sender).await {
+                warn!("Event feed stopped: {}", e);
+            }
+        });
+    }
+    for url in server.config.webhooks.iter() {
+        // Subscribed before the game starts, so that no event is missed
+        let receiver = server.event_feed.enable().subscribe();
+        tokio::spawn(run_webhook(
+            url.clone(),
+            reqwest_client.clone(),
+            receiver,
+            server.config.webhook_retries,
+        ));
+    }
+    let mut console_receiver = None;
+    if let Some(console_config) = server.config.console.clone() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        console_receiver = Some(receiver);
+        tokio::spawn(async move {
+            if let Err(e) = run_console(console_config, sender).await {
+                warn!("Admin console stopped: {}", e);
             }
         });
     }
+
     enum Msg {
         Time,
         Message(SocketAddr, HQMClientToServerMessage),
+        Console(HQMConsoleCommand),
+        Shutdown,
     }
 
     let timeout_stream = stream! {
@@ -1520,48 +4032,195 @@ pub async fn run_server<B: HQMServerBehaviour>(
                             yield Msg::Message(addr, data)
                         }
                     }
-                    Err(_) => {}
+                    Err(e) => {
+                        debug!(error = %e, "Could not receive packet: {}", e);
+                    }
                 }
             }
         }
     };
     tokio::pin!(packet_stream);
+    let console_stream = stream! {
+        if let Some(mut receiver) = console_receiver {
+            while let Some(command) = receiver.recv().await {
+                yield Msg::Console(command)
+            }
+        }
+    };
+    tokio::pin!(console_stream);
+    let shutdown_stream = stream! {
+        loop {
+            wait_for_shutdown_signal().await;
+            yield Msg::Shutdown;
+        }
+    };
+    tokio::pin!(shutdown_stream);
 
-    let mut stream = futures::stream_select!(timeout_stream, packet_stream);
+    let mut stream = futures::stream_select!(
+        timeout_stream,
+        packet_stream,
+        console_stream,
+        shutdown_stream
+    );
     let mut write_buf = BytesMut::with_capacity(4096);
     while let Some(msg) = stream.next().await {
         match msg {
-            Msg::Time => server.tick(&socket, &mut behaviour, &mut write_buf).await,
+            Msg::Time => {
+                server.tick(&socket, &mut behaviour, &mut write_buf).await;
+                if server.is_shutdown_due() {
+                    break;
+                }
+            }
             Msg::Message(addr, data) => {
                 server
                     .handle_message(addr, &socket, data, &mut behaviour, &mut write_buf)
                     .await
             }
+            Msg::Console(command) => {
+                let response = server.process_console_command(&command.line, &mut behaviour);
+                let _ = command.response.send(response);
+            }
+            Msg::Shutdown => {
+                if server.is_shutting_down() {
+                    // A second signal skips the countdown
+                    server.start_shutdown(0);
+                } else {
+                    server.start_shutdown(SIGNAL_SHUTDOWN_SECONDS);
+                }
+            }
         }
     }
+    server.finish_shutdown().await;
     Ok(())
 }
 
+const SIGNAL_SHUTDOWN_SECONDS: u32 = 10;
+
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut sigterm) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = sigterm.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn send_packet(socket: &UdpSocket, data: &[u8], addr: SocketAddr) {
+    if let Err(e) = socket.send_to(data, addr).await {
+        debug!(%addr, error = %e, "Could not send packet to {}: {}", addr, e);
+    }
+}
+
+async fn send_rink_info(
+    socket: &UdpSocket,
+    addr: SocketAddr,
+    boards: &HQMBoards,
+    write_buf: &mut BytesMut,
+) {
+    write_buf.clear();
+    let mut writer = HQMMessageWriter::new(write_buf);
+    writer.write_bytes_aligned(GAME_HEADER);
+    writer.write_byte_aligned(RINK_INFO);
+    boards.write_rink_info(&mut writer);
+    let slice: &[u8] = write_buf;
+    send_packet(socket, slice, addr).await;
+}
+
 async fn send_updates(
     game_id: u32,
-    packets: &VecDeque<[HQMObjectPacket; 32]>,
-    game_step: u32,
-    game_over: bool,
-    red_score: u32,
-    blue_score: u32,
-    time: u32,
-    goal_message_time: u32,
-    period: u32,
-    rules_state: HQMRulesState,
+    packets: &VecDeque<Vec<HQMObjectPacket>>,
+    steps: &VecDeque<HQMSavedStep>,
+    spectator_delay: u32,
     current_packet: u32,
-    players: &[Option<HQMServerPlayer>],
+    players: &mut [Option<HQMServerPlayer>],
     socket: &UdpSocket,
-    force_view: Option<HQMServerPlayerIndex>,
     write_buf: &mut BytesMut,
+    compression: bool,
+    compression_stats: &mut HQMCompressionStats,
+    send_batch: &mut HQMSendBatch,
 ) {
-    for player in players.iter() {
+    // Spectators can get a delayed feed, made of the packets and steps of some time ago
+    let feed_delays: Vec<usize> = players
+        .iter()
+        .map(|player| match player {
+            Some(HQMServerPlayer {
+                object: None,
+                data: HQMServerPlayerData::NetworkPlayer { data },
+                ..
+            }) => data.feed_delay.unwrap_or(spectator_delay) as usize * 100,
+            _ => 0,
+        })
+        .collect();
+    // Clients only need their own object selection if there are more objects than network slots
+    let view_positions: Vec<_> = players
+        .iter()
+        .zip(feed_delays.iter())
+        .map(|(player, &offset)| {
+            let current_packets = delayed_packet(packets, offset, 0)?;
+            if current_packets.len() <= NETWORK_OBJECT_SLOTS {
+                return None;
+            }
+            let view_player_index = match &player.as_ref()?.data {
+                HQMServerPlayerData::NetworkPlayer { data } => delayed_packet(steps, offset, 0)?
+                    .force_view
+                    .unwrap_or(data.view_player_index),
+                HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => return None,
+            };
+            let position = players
+                .get(view_player_index.0)
+                .and_then(|player| player.as_ref()?.object)
+                .and_then(|(object_index, _)| {
+                    object_position(current_packets.get(object_index.0)?)
+                });
+            Some(position)
+        })
+        .collect();
+    for (player_index, player) in players.iter_mut().enumerate() {
         if let Some(player) = player {
-            if let HQMServerPlayerData::NetworkPlayer { data } = &player.data {
+            if let HQMServerPlayerData::NetworkPlayer { data } = &mut player.data {
+                let offset = feed_delays[player_index];
+                let step = match delayed_packet(steps, offset, 0) {
+                    Some(step) => step,
+                    None => continue,
+                };
+                if offset != data.feed_offset {
+                    data.feed_offset = offset;
+                    data.feed_offset_packet = current_packet;
+                }
+                let known_packet = if data.known_packet >= data.feed_offset_packet {
+                    data.known_packet
+                } else {
+                    u32::MAX
+                };
+                let message_count = delayed_message_count(
+                    &mut data.feed_message_counts,
+                    offset,
+                    data.messages.len(),
+                );
+
+                let slots = match (
+                    view_positions[player_index],
+                    delayed_packet(packets, offset, 0),
+                ) {
+                    (Some(view_position), Some(current_packets)) => {
+                        let selected = select_objects(
+                            current_packets,
+                            view_position,
+                            data.object_slots.front(),
+                        );
+                        data.object_slots.truncate(192 - 1);
+                        data.object_slots.push_front(selected);
+                        Some(&data.object_slots)
+                    }
+                    _ => None,
+                };
+
                 write_buf.clear();
                 let mut writer = HQMMessageWriter::new(write_buf);
 
@@ -1573,21 +4232,21 @@ async fn send_updates(
                     writer.write_bytes_aligned(GAME_HEADER);
                     writer.write_byte_aligned(5);
                     writer.write_u32_aligned(game_id);
-                    writer.write_u32_aligned(game_step);
+                    writer.write_u32_aligned(step.game_step);
                     writer.write_bits(
                         1,
-                        match game_over {
+                        match step.values.game_over {
                             true => 1,
                             false => 0,
                         },
                     );
-                    writer.write_bits(8, red_score);
-                    writer.write_bits(8, blue_score);
-                    writer.write_bits(16, time);
+                    writer.write_bits(8, step.values.red_score);
+                    writer.write_bits(8, step.values.blue_score);
+                    writer.write_bits(16, step.values.time);
 
-                    writer.write_bits(16, goal_message_time);
-                    writer.write_bits(8, period);
-                    let view = force_view.unwrap_or(data.view_player_index).0 as u32;
+                    writer.write_bits(16, step.values.goal_message_timer);
+                    writer.write_bits(8, step.values.period);
+                    let view = step.force_view.unwrap_or(data.view_player_index).0 as u32;
                     writer.write_bits(8, view);
 
                     // if using a non-cryptic version, send ping
@@ -1597,7 +4256,7 @@ async fn send_updates(
 
                     // if baba's second version or above, send rules
                     if data.client_version.has_rules() {
-                        let num = match rules_state {
+                        let mut num = match step.values.rules_state {
                             HQMRulesState::Regular {
                                 offside_warning,
                                 icing_warning,
@@ -1614,20 +4273,43 @@ async fn send_updates(
                             HQMRulesState::Offside => 4,
                             HQMRulesState::Icing => 8,
                         };
+                        if data.extended_rules {
+                            num |= extended_rules_bits(&step.values);
+                        }
                         writer.write_u32_aligned(num);
                     }
 
-                    write_objects(&mut writer, packets, current_packet, data.known_packet);
+                    write_delayed_objects(
+                        &mut writer,
+                        packets,
+                        offset,
+                        slots,
+                        current_packet,
+                        known_packet,
+                    );
 
-                    let (start, remaining_messages) = if data.known_msgpos > data.messages.len() {
-                        (data.messages.len(), 0)
+                    let (start, remaining_messages) = if data.known_msgpos > message_count {
+                        (message_count, 0)
                     } else {
                         (
                             data.known_msgpos,
-                            min(data.messages.len() - data.known_msgpos, 15),
+                            min(message_count - data.known_msgpos, 15),
                         )
                     };
 
+                    // Leave messages for later packets if they don't fit within the client's limit
+                    let limit_bits = data.packet_size.limit() * 8;
+                    let mut bits = writer.bytes_written() * 8 + 20;
+                    let mut messages_that_fit = 0;
+                    for message in &data.messages[start..start + remaining_messages] {
+                        bits += message_bits(message);
+                        if messages_that_fit > 0 && bits > limit_bits {
+                            break;
+                        }
+                        messages_that_fit += 1;
+                    }
+                    let remaining_messages = messages_that_fit;
+
                     writer.write_bits(4, remaining_messages as u32);
                     writer.write_bits(16, start as u32);
 
@@ -1637,10 +4319,67 @@ async fn send_updates(
                 }
 
                 let slice: &[u8] = &write_buf;
-                let _ = socket.send_to(slice, data.addr).await;
+                if compression && data.client_version.has_compression() && data.game_id == game_id {
+                    // Only the payload after the header and command byte is compressed,
+                    // fall back to the uncompressed packet if compression doesn't help
+                    if let Some(compressed) = compress_update(slice) {
+                        compression_stats.compressed_packets += 1;
+                        compression_stats.uncompressed_bytes += slice.len() as u64;
+                        compression_stats.sent_bytes += compressed.len() as u64;
+                        data.packet_size.record_sent(compressed.len());
+                        send_batch.push(data.addr, &compressed);
+                        continue;
+                    }
+                    compression_stats.fallback_packets += 1;
+                    compression_stats.uncompressed_bytes += slice.len() as u64;
+                    compression_stats.sent_bytes += slice.len() as u64;
+                }
+                data.packet_size.record_sent(slice.len());
+                send_batch.push(data.addr, slice);
             }
         }
     }
+    send_batch.send(socket).await;
+}
+
+/// Number of messages a feed that is `delay` steps behind shows, given the number of messages
+/// of each step since the player started spectating, newest first
+fn delayed_message_count(counts: &mut VecDeque<usize>, delay: usize, messages: usize) -> usize {
+    if delay > 0 {
+        counts.truncate(delay);
+        counts.push_front(messages);
+        counts.back().copied().unwrap_or_default()
+    } else {
+        counts.clear();
+        messages
+    }
+}
+
+fn compress_update(update: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Write;
+
+    let header_len = GAME_HEADER.len() + 1;
+    let mut compressed = Vec::with_capacity(update.len());
+    compressed.extend_from_slice(GAME_HEADER);
+    compressed.push(0x15);
+    compressed.extend_from_slice(&(update.len() as u16 - header_len as u16).to_le_bytes());
+    let mut encoder = ZlibEncoder::new(compressed, Compression::fast());
+    encoder.write_all(&update[header_len..]).ok()?;
+    let compressed = encoder.finish().ok()?;
+    if compressed.len() < update.len() {
+        Some(compressed)
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HQMCompressionStats {
+    pub compressed_packets: u64,
+    pub fallback_packets: u64,
+    pub uncompressed_bytes: u64,
+    pub sent_bytes: u64,
+    log_timer: u32,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -1661,10 +4400,57 @@ pub struct HQMNetworkPlayerData {
     pub(crate) view_player_index: HQMServerPlayerIndex,
     pub game_id: u32,
     pub(crate) messages: Vec<Rc<HQMMessage>>,
+    pub(crate) input_monitor: HQMInputMonitor,
+    pub(crate) movement_monitor: HQMMovementMonitor,
+    network_quality: HQMNetworkQuality,
+    object_slots: VecDeque<HQMObjectSlots>,
+    packet_size: HQMPacketSizeProbe,
+    /// The client asked for rink info, so it's sent again when it changes
+    rink_info: bool,
+    /// Steps since the client last sent an update for the current game
+    handshake_steps: u32,
+    /// Set if the client asked for world checksums
+    world_checksums: Option<HQMChecksums>,
+    /// Set if the client asked for a replay stream
+    replay_stream: Option<HQMReplayStream>,
+    /// The client asked for the extended rules bits
+    extended_rules: bool,
+    /// The client asked for the sound cues of quick-chat signals
+    sound_cues: bool,
+    last_quick_chat: Option<Instant>,
+    /// The admin subscribed to alerts with /alerts on
+    pub(crate) alerts: bool,
+    /// The player joined in one of the spectator slots, and can only join a team once a skater
+    /// slot is free
+    pub(crate) spectator_slot: bool,
+    /// Made a caster by an admin with /caster, which keeps the player off the teams and lets
+    /// them chat while the chat is muted
+    pub(crate) caster: bool,
+    /// Seconds the feed of the player is delayed while spectating, set with /delay instead of
+    /// spectator_delay
+    pub(crate) feed_delay: Option<u32>,
+    /// Steps the objects sent to the player are delayed by
+    feed_offset: usize,
+    /// The first packet sent with the current feed_offset, older packets can't be used for
+    /// the delta encoding
+    feed_offset_packet: u32,
+    /// Number of messages of the player at each of the delayed steps, empty if the feed isn't
+    /// delayed
+    feed_message_counts: VecDeque<usize>,
+    /// Steps with the clock running since the input of the player on the ice last changed
+    idle_steps: u32,
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum HQMServerPlayerData {
-    NetworkPlayer { data: HQMNetworkPlayerData },
+    NetworkPlayer {
+        data: HQMNetworkPlayerData,
+    },
+    /// A skater controlled by the server, see [crate::hqm_bot]
+    Bot,
+    /// A skater without input that is placed and moved by the server, like the dummies of
+    /// [crate::hqm_practice]
+    Dummy,
 }
 
 pub struct HQMServerPlayer {
@@ -1685,6 +4471,8 @@ impl HQMServerPlayer {
         player_name: String,
         addr: SocketAddr,
         global_messages: &[Rc<HQMMessage>],
+        max_packet_size: usize,
+        spectator_slot: bool,
     ) -> Self {
         HQMServerPlayer {
             player_name: Rc::new(player_name),
@@ -1704,6 +4492,26 @@ impl HQMServerPlayer {
                     view_player_index: player_index,
                     game_id: u32::MAX,
                     messages: global_messages.into_iter().cloned().collect(),
+                    input_monitor: HQMInputMonitor::new(),
+                    movement_monitor: HQMMovementMonitor::new(),
+                    network_quality: HQMNetworkQuality::new(),
+                    object_slots: VecDeque::new(),
+                    packet_size: HQMPacketSizeProbe::new(max_packet_size),
+                    rink_info: false,
+                    last_quick_chat: None,
+                    handshake_steps: 0,
+                    world_checksums: None,
+                    replay_stream: None,
+                    extended_rules: false,
+                    sound_cues: false,
+                    alerts: false,
+                    spectator_slot,
+                    caster: false,
+                    feed_delay: None,
+                    feed_offset: 0,
+                    feed_offset_packet: 0,
+                    feed_message_counts: VecDeque::new(),
+                    idle_steps: 0,
                 },
             },
             is_admin: false,
@@ -1714,6 +4522,32 @@ impl HQMServerPlayer {
         }
     }
 
+    fn new_server_player(player_name: String, data: HQMServerPlayerData) -> Self {
+        HQMServerPlayer {
+            player_name: Rc::new(player_name),
+            object: None,
+            id: Uuid::new_v4(),
+            data,
+            is_admin: false,
+            input: Default::default(),
+            is_muted: HQMMuteStatus::NotMuted,
+            hand: HQMSkaterHand::Right,
+            mass: 1.0,
+        }
+    }
+
+    pub fn is_bot(&self) -> bool {
+        matches!(self.data, HQMServerPlayerData::Bot)
+    }
+
+    pub fn is_dummy(&self) -> bool {
+        matches!(self.data, HQMServerPlayerData::Dummy)
+    }
+
+    pub fn is_caster(&self) -> bool {
+        matches!(&self.data, HQMServerPlayerData::NetworkPlayer { data } if data.caster)
+    }
+
     fn reset(&mut self, player_index: HQMServerPlayerIndex) -> bool {
         self.object = None;
         if let HQMServerPlayerData::NetworkPlayer { data } = &mut self.data {
@@ -1721,8 +4555,15 @@ impl HQMServerPlayer {
             data.known_packet = u32::MAX;
             data.messages.clear();
             data.view_player_index = player_index;
+            data.feed_offset = 0;
+            data.feed_offset_packet = 0;
+            data.feed_message_counts.clear();
+            if let Some(checksums) = &mut data.world_checksums {
+                checksums.clear();
+            }
         }
-        return true;
+        // Dummies stand on the ice of one game only
+        !self.is_dummy()
     }
 
     fn get_update_message(&self, player_index: HQMServerPlayerIndex) -> HQMMessage {
@@ -1750,6 +4591,18 @@ impl HQMServerPlayer {
             HQMServerPlayerData::NetworkPlayer {
                 data: HQMNetworkPlayerData { addr, .. },
             } => Some(addr),
+            HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => None,
+        }
+    }
+
+    /// The jitter of a client, for /ping and the console
+    pub fn network_quality(&self) -> Option<String> {
+        match &self.data {
+            HQMServerPlayerData::NetworkPlayer { data } => Some(format!(
+                "jitter {:.1} ms",
+                data.network_quality.jitter() * 1000.0
+            )),
+            HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => None,
         }
     }
 
@@ -1782,6 +4635,7 @@ impl HQMServerPlayer {
                     deviation: dev,
                 })
             }
+            HQMServerPlayerData::Bot | HQMServerPlayerData::Dummy => None,
         }
     }
 }
@@ -1796,8 +4650,35 @@ pub struct PingData {
 
 #[derive(Debug, Clone)]
 pub enum ReplaySaving {
-    File,
-    Endpoint { url: String },
+    /// Replays are saved in the directory, and the oldest are deleted when there are more than
+    /// max_files, unless it's 0
+    File {
+        directory: PathBuf,
+        max_files: usize,
+    },
+    Endpoint {
+        url: String,
+    },
+}
+
+/// Who can start an instant replay with /replay
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub enum HQMInstantReplay {
+    Off,
+    Admins,
+    All,
+}
+
+/// What is done about players whose skaters keep moving faster than the physics allow
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub enum HQMMovementCheck {
+    Off,
+    /// Strikes are logged
+    Log,
+    /// Players are also reported to the admins after a few strikes
+    Flag,
+    /// Players are kicked instead of reported
+    Kick,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
@@ -1812,21 +4693,81 @@ pub struct HQMServerConfiguration {
     pub welcome: Vec<String>,
     pub password: String,
     pub player_max: usize,
+    /// Extra slots for spectators on top of player_max
+    pub spectator_slots: usize,
+    /// Seconds the feed of spectators is delayed
+    pub spectator_delay: u32,
+    /// Seconds with the clock running before a player whose input doesn't change is moved to
+    /// the spectators, 0 disables it
+    pub afk_time: u32,
+    /// Dimensions of the rink of every game, from the [Rink] section
+    pub rink: HQMRinkConfiguration,
 
     pub replays_enabled: ReplayEnabled,
     pub replay_saving: ReplaySaving,
     pub server_name: String,
     pub server_service: Option<String>,
+
+    pub chat_history: usize,
+    pub compression: bool,
+    pub input_toggle_limit: u32,
+    pub restart_after_games: u32,
+    pub packet_rate_limit: u32,
+    pub join_challenge: bool,
+    /// Allows admins to freeze and step the simulation, for reproducing bugs
+    pub debug_commands: bool,
+    pub max_packet_size: usize,
+
+    pub public: bool,
+    pub master_servers: Vec<HQMMasterServer>,
+
+    pub console: Option<HQMConsoleConfiguration>,
+    pub event_feed: Option<SocketAddr>,
+    /// URLs that every event of the event feed is posted to
+    pub webhooks: Vec<String>,
+    pub webhook_retries: u32,
+    pub ban_file: Option<PathBuf>,
+    /// File that the game in progress is saved in, to be resumed after a crash
+    pub snapshot_file: Option<PathBuf>,
+    /// File that the preferences of the players are saved in
+    pub preferences_file: Option<PathBuf>,
+    /// Directory of the games saved with /savegame
+    pub savegame_directory: PathBuf,
+    /// Resume the game saved in snapshot_file when the first player joins
+    pub resume: bool,
+    pub autosave_interval: u32,
+    /// SQLite database that games and player statistics are recorded in
+    pub stats_database: Option<PathBuf>,
+    /// Address of the HTTP listener for statistics pages of recorded games
+    pub stats_page: Option<SocketAddr>,
+    /// Public address of the statistics pages, used to share links to them
+    pub stats_page_url: Option<String>,
+    /// Directory that a JSON report of every finished game is written to
+    pub results_directory: Option<PathBuf>,
+    /// Directory that clips of the goals, the hardest hit and the fastest shot of every game
+    /// are saved to
+    pub highlights_directory: Option<PathBuf>,
+    /// Sponsor texts on the boards, for clients that can show them
+    pub boards: Vec<String>,
+    /// Seconds between board rotations, 0 doesn't rotate
+    pub board_rotation: u32,
+    pub alerts: HQMAlertConfig,
+    pub instant_replay: HQMInstantReplay,
+    pub movement_check: HQMMovementCheck,
+    pub network_limits: HQMNetworkLimits,
+    /// Highest rate of replay streams in kilobytes per second, 0 turns them off
+    pub replay_stream_rate: u32,
 }
 
 #[derive(Debug, Clone)]
 pub struct HQMInitialGameValues {
     pub values: HQMGameValues,
     pub puck_slots: usize,
-    pub physics_configuration: HQMPhysicsConfiguration,
+    pub physics_configuration: HQMPhysicsProfile,
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum HQMTeam {
     Red,
     Blue,
@@ -1856,3 +4797,118 @@ impl Display for HQMTeam {
         }
     }
 }
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum HQMAfkStatus {
+    Active,
+    /// Warned once that they will be moved soon
+    Warn {
+        seconds_left: u32,
+    },
+    Away,
+}
+
+/// What happens to a player on the ice whose input hasn't changed for a number of steps with
+/// the clock running, when players are moved to the spectators after afk_time seconds. The
+/// warning comes 10 seconds before, or halfway for short times
+fn afk_status(idle_steps: u32, afk_time: u32) -> HQMAfkStatus {
+    let afk_steps = afk_time * 100;
+    let warn_steps = afk_steps
+        .saturating_sub(AFK_WARNING_STEPS)
+        .max(afk_steps / 2);
+    if afk_steps == 0 {
+        HQMAfkStatus::Active
+    } else if idle_steps >= afk_steps {
+        HQMAfkStatus::Away
+    } else if idle_steps == warn_steps {
+        HQMAfkStatus::Warn {
+            seconds_left: (afk_steps - warn_steps) / 100,
+        }
+    } else {
+        HQMAfkStatus::Active
+    }
+}
+
+/// Network players in skater slots and in spectator slots. Players who join when the skater
+/// slots are taken get a spectator slot, and can't join a team until a skater slot is free
+#[derive(Debug, Default, Copy, Clone)]
+struct HQMSlotCounts {
+    skaters: usize,
+    spectators: usize,
+}
+
+impl HQMSlotCounts {
+    fn skater_slot_free(&self, player_max: usize) -> bool {
+        self.skaters < player_max
+    }
+
+    /// Whether someone can join, in a skater or a spectator slot
+    fn slot_free(&self, player_max: usize, spectator_slots: usize) -> bool {
+        self.skater_slot_free(player_max) || self.spectators < spectator_slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_replays_of_this_server_are_pruned() {
+        assert!(is_replay_of("MyServer.2024-01-01T200000.hrp", "MyServer"));
+        assert!(is_replay_of("My.Server.2024-01-01T200000.hrp", "My.Server"));
+        assert!(!is_replay_of("Other.2024-01-01T200000.hrp", "MyServer"));
+        assert!(!is_replay_of("MyServer2.2024-01-01T200000.hrp", "MyServer"));
+        assert!(!is_replay_of(
+            "MyServer.2024-01-01T200000.goal1.hrp",
+            "MyServer"
+        ));
+        assert!(!is_replay_of("MyServer.2024-01-01T200000.json", "MyServer"));
+        assert!(!is_replay_of("MyServer..hrp", "MyServer"));
+    }
+
+    #[test]
+    fn delayed_feed_shows_the_messages_of_earlier_steps() {
+        let mut counts = VecDeque::new();
+        let shown: Vec<_> = [1, 2, 2, 5, 6, 6]
+            .into_iter()
+            .map(|messages| delayed_message_count(&mut counts, 2, messages))
+            .collect();
+        assert_eq!(shown, vec![1, 1, 1, 2, 2, 5]);
+        // Without a delay, every message is shown right away
+        assert_eq!(delayed_message_count(&mut counts, 0, 7), 7);
+        assert!(counts.is_empty());
+    }
+
+    #[test]
+    fn spectator_slots_are_used_when_skater_slots_are_taken() {
+        let counts = |skaters, spectators| HQMSlotCounts {
+            skaters,
+            spectators,
+        };
+        // Two skater slots and one spectator slot
+        assert!(counts(1, 0).skater_slot_free(2));
+        assert!(!counts(2, 0).skater_slot_free(2));
+        assert!(counts(2, 0).slot_free(2, 1));
+        assert!(!counts(2, 1).slot_free(2, 1));
+        // A skater left, so the spectator can take the slot
+        assert!(counts(1, 1).skater_slot_free(2));
+        // Without spectator slots, only skater slots count
+        assert!(!counts(2, 0).slot_free(2, 0));
+    }
+
+    #[test]
+    fn players_are_warned_before_they_are_moved_for_being_away() {
+        assert_eq!(afk_status(100, 60), HQMAfkStatus::Active);
+        assert_eq!(
+            afk_status(5000, 60),
+            HQMAfkStatus::Warn { seconds_left: 10 }
+        );
+        assert_eq!(afk_status(5001, 60), HQMAfkStatus::Active);
+        assert_eq!(afk_status(6000, 60), HQMAfkStatus::Away);
+        assert_eq!(afk_status(7000, 60), HQMAfkStatus::Away);
+        // Short times warn halfway
+        assert_eq!(afk_status(500, 10), HQMAfkStatus::Warn { seconds_left: 5 });
+        // 0 turns it off
+        assert_eq!(afk_status(100000, 0), HQMAfkStatus::Active);
+    }
+}
@@ -1,4 +1,4 @@
-use migo_hqm_server::hqm_game::{HQMGameValues, HQMObjectIndex, HQMPhysicsConfiguration};
+use migo_hqm_server::hqm_game::{HQMGameValues, HQMObjectIndex, HQMPhysicsProfile};
 use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex, HQMTeam};
 use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
 use nalgebra::{Point3, Rotation3, Vector3};
@@ -30,14 +30,14 @@ enum HQMShootoutStatus {
 pub struct HQMShootoutBehaviour {
     attempts: u32,
     status: HQMShootoutStatus,
-    physics_config: HQMPhysicsConfiguration,
+    physics_config: HQMPhysicsProfile,
     paused: bool,
     team_switch_timer: HashMap<HQMServerPlayerIndex, u32>,
     team_max: usize,
 }
 
 impl HQMShootoutBehaviour {
-    pub fn new(attempts: u32, physics_config: HQMPhysicsConfiguration) -> Self {
+    pub fn new(attempts: u32, physics_config: HQMPhysicsProfile) -> Self {
         HQMShootoutBehaviour {
             attempts,
             status: HQMShootoutStatus::WaitingForGame,
@@ -90,10 +90,9 @@ impl HQMShootoutBehaviour {
         server.values.period = 1;
         server.world.clear_pucks();
 
-        let length = server.world.rink.length;
-        let width = server.world.rink.width;
+        let center = server.world.rink.center_ice();
 
-        let puck_pos = Point3::new(width / 2.0, 1.0, length / 2.0);
+        let puck_pos = Point3::new(center.x, 1.0, center.z);
         server
             .world
             .create_puck_object(puck_pos, Rotation3::identity());
@@ -101,8 +100,8 @@ impl HQMShootoutBehaviour {
         let red_rot = Rotation3::identity();
         let blue_rot = Rotation3::from_euler_angles(0.0, PI, 0.0);
 
-        let red_goalie_pos = Point3::new(width / 2.0, 1.5, length - 5.0);
-        let blue_goalie_pos = Point3::new(width / 2.0, 1.5, 5.0);
+        let red_goalie_pos = server.world.rink.red_net.goalie_position(1.5);
+        let blue_goalie_pos = server.world.rink.blue_net.goalie_position(1.5);
         let (attacking_players, defending_players, attacking_rot, defending_rot, goalie_pos) =
             match team {
                 HQMTeam::Red => (
@@ -114,7 +113,7 @@ impl HQMShootoutBehaviour {
                 ),
                 HQMTeam::Blue => (blue_players, red_players, blue_rot, red_rot, red_goalie_pos),
             };
-        let center_pos = Point3::new(width / 2.0, 1.5, length / 2.0);
+        let center_pos = Point3::new(center.x, 1.5, center.z);
         for (index, player_index) in attacking_players.into_iter().enumerate() {
             let mut pos = center_pos + &attacking_rot * Vector3::new(0.0, 0.0, 3.0);
             if index > 0 {
@@ -326,17 +325,8 @@ impl HQMShootoutBehaviour {
     }
 
     fn reset_game(&mut self, server: &mut HQMServer, player_index: HQMServerPlayerIndex) {
-        if let Some(player) = server.players.get(player_index) {
-            if player.is_admin {
-                info!("{} ({}) reset game", player.player_name, player_index);
-                let msg = format!("Game reset by {}", player.player_name);
-
-                server.new_game(self.get_initial_game_values());
-
-                server.messages.add_server_chat_message(msg);
-            } else {
-                server.admin_deny_message(player_index);
-            }
+        if let Some(source) = server.admin_source(player_index) {
+            server.reset_game_by(&source, self.get_initial_game_values());
         }
     }
 
@@ -651,18 +641,13 @@ impl HQMServerBehaviour for HQMShootoutBehaviour {
                             self.end_attempt(server, false);
                         } else {
                             if let Some(puck) = server.world.objects.get_puck(HQMObjectIndex(0)) {
-                                let puck_pos = &puck.body.pos;
-                                let center_pos = Point3::new(
-                                    server.world.rink.width / 2.0,
-                                    0.0,
-                                    server.world.rink.length / 2.0,
-                                );
-                                let pos_diff = puck_pos - center_pos;
-                                let normal = match *team {
-                                    HQMTeam::Red => -Vector3::z(),
-                                    HQMTeam::Blue => Vector3::z(),
+                                let rink = &server.world.rink;
+                                let net = match *team {
+                                    HQMTeam::Red => &rink.blue_net,
+                                    HQMTeam::Blue => &rink.red_net,
                                 };
-                                let progress = pos_diff.dot(&normal);
+                                let progress = (puck.body.pos - rink.center_ice())
+                                    .dot(&rink.direction_to_net(net));
                                 if let HQMShootoutAttemptState::Attack {
                                     progress: current_progress,
                                 } = state
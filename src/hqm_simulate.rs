@@ -1,7 +1,7 @@
 use crate::hqm_game::HQMRinkSideOfLine::{BlueSide, RedSide};
 use crate::hqm_game::{
-    HQMBody, HQMGameObject, HQMGameWorld, HQMObjectIndex, HQMPhysicsConfiguration, HQMPuck,
-    HQMRink, HQMRinkNet, HQMSkater, HQMSkaterCollisionBall, HQMSkaterHand,
+    HQMBody, HQMGameObject, HQMGameWorld, HQMObjectIndex, HQMPhysicsProfile, HQMPuck, HQMRink,
+    HQMRinkNet, HQMSkater, HQMSkaterCollisionBall, HQMSkaterHand,
 };
 use crate::hqm_server::HQMTeam;
 use nalgebra::{vector, Point3, Rotation3, Unit, Vector2, Vector3};
@@ -20,6 +20,12 @@ pub enum HQMSimulationEvent {
         player: HQMObjectIndex,
         puck: HQMObjectIndex,
     },
+    /// The puck went into the body of a skater and changed course there without a stick
+    /// touching it. Pucks go through skaters, so the rules only count pucks that were deflected
+    PuckTouchedBody {
+        player: HQMObjectIndex,
+        puck: HQMObjectIndex,
+    },
     PuckReachedDefensiveLine {
         team: HQMTeam,
         puck: HQMObjectIndex,
@@ -57,6 +63,46 @@ pub enum HQMSimulationEvent {
         team: HQMTeam,
         puck: HQMObjectIndex,
     },
+    /// The puck went over the boards
+    PuckOutOfPlay {
+        puck: HQMObjectIndex,
+    },
+    /// A skater knocked the net off its moorings
+    NetDislodged {
+        team: HQMTeam,
+        player: HQMObjectIndex,
+    },
+    /// Two skaters ran into each other hard enough for a hit. `player_a` is the one that moved
+    /// towards the other faster
+    PlayerCollision {
+        player_a: HQMObjectIndex,
+        player_b: HQMObjectIndex,
+        /// Speed at which they came together, in meters per tick
+        impact: f32,
+    },
+}
+
+impl HQMSimulationEvent {
+    /// The puck the event is about, if any
+    pub fn puck(&self) -> Option<HQMObjectIndex> {
+        let puck = match *self {
+            HQMSimulationEvent::PuckTouch { puck, .. }
+            | HQMSimulationEvent::PuckTouchedBody { puck, .. }
+            | HQMSimulationEvent::PuckReachedDefensiveLine { puck, .. }
+            | HQMSimulationEvent::PuckPassedDefensiveLine { puck, .. }
+            | HQMSimulationEvent::PuckReachedCenterLine { puck, .. }
+            | HQMSimulationEvent::PuckPassedCenterLine { puck, .. }
+            | HQMSimulationEvent::PuckReachedOffensiveZone { puck, .. }
+            | HQMSimulationEvent::PuckEnteredOffensiveZone { puck, .. }
+            | HQMSimulationEvent::PuckEnteredNet { puck, .. }
+            | HQMSimulationEvent::PuckPassedGoalLine { puck, .. }
+            | HQMSimulationEvent::PuckTouchedNet { puck, .. }
+            | HQMSimulationEvent::PuckOutOfPlay { puck } => puck,
+            HQMSimulationEvent::NetDislodged { .. }
+            | HQMSimulationEvent::PlayerCollision { .. } => return None,
+        };
+        Some(puck)
+    }
 }
 
 fn replace_nan(v: f32, d: f32) -> f32 {
@@ -69,6 +115,8 @@ fn replace_nan(v: f32, d: f32) -> f32 {
 
 type SimulationList = SmallVec<[HQMSimulationEvent; 16]>;
 type CollisionList = SmallVec<[HQMCollision; 32]>;
+/// Positions and radii of the collision balls of a skater
+type CollisionBallList = SmallVec<[(Point3<f32>, f32); 8]>;
 
 impl HQMGameWorld {
     pub(crate) fn simulate_step(&mut self) -> SimulationList {
@@ -77,12 +125,25 @@ impl HQMGameWorld {
         let mut pucks: SmallVec<[(usize, &mut HQMPuck); 32]> = smallvec::SmallVec::new();
         for (i, o) in self.objects.objects.iter_mut().enumerate() {
             match o {
-                HQMGameObject::Player(player) => players.push((i, player)),
+                HQMGameObject::Player(player) if !player.ghost => players.push((i, player)),
                 HQMGameObject::Puck(puck) => pucks.push((i, puck)),
                 _ => {}
             }
         }
 
+        // Where the skaters were before this step, to tell new collisions from skaters that
+        // were already leaning on each other
+        let old_balls: SmallVec<[CollisionBallList; 32]> = players
+            .iter()
+            .map(|(_, player)| {
+                player
+                    .collision_balls
+                    .iter()
+                    .map(|ball| (ball.pos, ball.radius))
+                    .collect()
+            })
+            .collect();
+
         let mut collisions: CollisionList = smallvec::SmallVec::new();
         for (i, (_, player)) in players.iter_mut().enumerate() {
             update_player(i, player, &self.physics_config, &self.rink, &mut collisions);
@@ -90,15 +151,17 @@ impl HQMGameWorld {
 
         for i in 0..players.len() {
             let (a, b) = players.split_at_mut(i + 1);
-            let (_, ref mut p1) = &mut a[i];
+            let (p1_index, ref mut p1) = &mut a[i];
 
-            for (j, (_, p2)) in ((i + 1)..).zip(b.iter_mut()) {
+            for (j, (p2_index, p2)) in ((i + 1)..).zip(b.iter_mut()) {
+                let mut touching = false;
                 for (ib, p1_collision_ball) in p1.collision_balls.iter().enumerate() {
                     for (jb, p2_collision_ball) in p2.collision_balls.iter().enumerate() {
                         let pos_diff = &p1_collision_ball.pos - &p2_collision_ball.pos;
                         let radius_sum = &p1_collision_ball.radius + &p2_collision_ball.radius;
                         if pos_diff.norm() < radius_sum {
                             let overlap = radius_sum - pos_diff.norm();
+                            touching = true;
 
                             collisions.push(HQMCollision::PlayerPlayer(
                                 (i, ib),
@@ -109,6 +172,11 @@ impl HQMGameWorld {
                         }
                     }
                 }
+                if touching && !balls_touch(&old_balls[i], &old_balls[j]) {
+                    if let Some(event) = player_collision((*p1_index, p1), (*p2_index, p2)) {
+                        events.push(event);
+                    }
+                }
                 let stick_v = &p1.stick_pos - &p2.stick_pos;
                 let stick_distance = stick_v.norm();
                 if stick_distance < 0.25 {
@@ -128,6 +196,8 @@ impl HQMGameWorld {
 
         let pucks_old_pos: SmallVec<[Point3<f32>; 32]> =
             pucks.iter().map(|x| x.1.body.pos.clone()).collect();
+        let pucks_old_velocity: SmallVec<[Vector3<f32>; 32]> =
+            pucks.iter().map(|x| x.1.body.linear_velocity).collect();
 
         for (_, puck) in pucks.iter_mut() {
             puck.body.linear_velocity[1] -= self.physics_config.gravity;
@@ -141,9 +211,23 @@ impl HQMGameWorld {
             &self.physics_config,
         );
 
-        for ((puck_index, puck), old_puck_pos) in pucks.iter_mut().zip(pucks_old_pos.iter()) {
+        for (((puck_index, puck), old_puck_pos), old_puck_velocity) in pucks
+            .iter_mut()
+            .zip(pucks_old_pos.iter())
+            .zip(pucks_old_velocity.iter())
+        {
+            // Before the air drag, so that only the forces of this step change the velocity
+            puck_body_detection(
+                puck,
+                *puck_index,
+                old_puck_pos,
+                old_puck_velocity,
+                &players,
+                &mut events,
+            );
             if puck.body.linear_velocity.norm() > 1.0 / 65536.0 {
-                let scale = puck.body.linear_velocity.norm().powi(2) * 0.125 * 0.125;
+                let scale =
+                    puck.body.linear_velocity.norm().powi(2) * self.physics_config.puck_air_drag;
                 let scaled = scale * puck.body.linear_velocity.normalize();
                 puck.body.linear_velocity -= scaled;
             }
@@ -159,16 +243,104 @@ impl HQMGameWorld {
         }
 
         apply_collisions(&mut players, &collisions);
+        net_dislodge_detection(&players, &mut self.rink, &mut events);
         events
     }
 }
 
+/// Speed in meters per tick at which two skaters have to come together for a hit
+const HIT_IMPACT: f32 = 0.02;
+
+fn balls_touch(a: &[(Point3<f32>, f32)], b: &[(Point3<f32>, f32)]) -> bool {
+    a.iter().any(|(a_pos, a_radius)| {
+        b.iter()
+            .any(|(b_pos, b_radius)| (a_pos - b_pos).norm() < a_radius + b_radius)
+    })
+}
+
+/// A hit between two skaters that just ran into each other, if they came together fast enough
+fn player_collision(
+    (a_index, a): (usize, &HQMSkater),
+    (b_index, b): (usize, &HQMSkater),
+) -> Option<HQMSimulationEvent> {
+    let diff = b.body.pos - a.body.pos;
+    let diff = Vector3::new(diff.x, 0.0, diff.z);
+    if diff.norm() == 0.0 {
+        return None;
+    }
+    let normal = diff.normalize();
+    let a_speed = a.body.linear_velocity.dot(&normal);
+    let b_speed = -b.body.linear_velocity.dot(&normal);
+    let impact = a_speed + b_speed;
+    if impact < HIT_IMPACT {
+        return None;
+    }
+    let (player_a, player_b) = if a_speed >= b_speed {
+        (a_index, b_index)
+    } else {
+        (b_index, a_index)
+    };
+    Some(HQMSimulationEvent::PlayerCollision {
+        player_a: HQMObjectIndex(player_a),
+        player_b: HQMObjectIndex(player_b),
+        impact,
+    })
+}
+
+/// How far a net is pushed when it's knocked off its moorings, in meters
+const NET_DISLODGE_DISTANCE: f32 = 0.5;
+
+/// Moves a net that a skater runs into fast enough. Clients keep drawing the net in its place,
+/// so a dislodged net takes no part in collisions or goals until it's put back at the faceoff,
+/// and creases and goalie spots stay where the net is moored. Game modes only set
+/// `net_dislodge_speed` while play is live, so that the net is put back by the rule call
+fn net_dislodge_detection(
+    players: &[(usize, &mut HQMSkater)],
+    rink: &mut HQMRink,
+    events: &mut SimulationList,
+) {
+    let dislodge_speed = rink.net_dislodge_speed;
+    if dislodge_speed <= 0.0 {
+        return;
+    }
+    let nets = [
+        (HQMTeam::Red, &mut rink.red_net),
+        (HQMTeam::Blue, &mut rink.blue_net),
+    ];
+    for (team, net) in nets {
+        if net.is_dislodged() {
+            continue;
+        }
+        for (player_index, player) in players.iter() {
+            let velocity = player.body.linear_velocity;
+            let velocity = Vector3::new(velocity.x, 0.0, velocity.z);
+            if velocity.norm() < dislodge_speed {
+                continue;
+            }
+            let hits_net = player.collision_balls.iter().any(|ball| {
+                collision_between_sphere_and_net(&ball.pos, ball.radius, net).is_some()
+                    || net.posts.iter().any(|post| {
+                        collision_between_sphere_and_post(&ball.pos, ball.radius, post).is_some()
+                    })
+            });
+            if hits_net {
+                net.shift(NET_DISLODGE_DISTANCE * velocity.normalize());
+                events.push(HQMSimulationEvent::NetDislodged {
+                    team,
+                    player: HQMObjectIndex(*player_index),
+                });
+                break;
+            }
+        }
+    }
+}
+
 fn update_sticks_and_pucks(
     players: &mut [(usize, &mut HQMSkater)],
     pucks: &mut [(usize, &mut HQMPuck)],
     rink: &HQMRink,
     events: &mut SimulationList,
-    physics_config: &HQMPhysicsConfiguration,
+    physics_config: &HQMPhysicsProfile,
 ) {
     for i in 0..10 {
         for (_, player) in players.iter_mut() {
@@ -188,7 +360,7 @@ fn update_sticks_and_pucks(
                     rink,
                     &puck_linear_velocity_before,
                     &puck_angular_velocity_before,
-                    physics_config.puck_rink_friction,
+                    physics_config,
                 );
             }
             for (player_index, player) in players.iter_mut() {
@@ -202,6 +374,7 @@ fn update_sticks_and_pucks(
                         &puck_linear_velocity_before,
                         &puck_angular_velocity_before,
                         &old_stick_velocity,
+                        physics_config.stick_puck_force,
                     );
                     if has_touched {
                         events.push(HQMSimulationEvent::PuckTouch {
@@ -370,7 +543,7 @@ fn update_stick(
 fn update_player(
     i: usize,
     player: &mut HQMSkater,
-    physics_config: &HQMPhysicsConfiguration,
+    physics_config: &HQMPhysicsProfile,
     rink: &HQMRink,
     collisions: &mut CollisionList,
 ) {
@@ -410,10 +583,11 @@ fn update_player(
             player.body.linear_velocity += limit_vector_length(&new_acceleration, max_acceleration);
         }
         if player.input.jump() && !player.jumped_last_frame {
+            let jump_speed = physics_config.jump_speed;
             let diff = if physics_config.limit_jump_speed {
-                (0.025 - player.body.linear_velocity[1]).clamp(0.0, 0.025)
+                (jump_speed - player.body.linear_velocity[1]).clamp(0.0, jump_speed)
             } else {
-                0.025 // 0.025 is the jump acceleration per frame when jumping
+                jump_speed
             };
             if diff != 0.0 {
                 player.body.linear_velocity[1] += diff;
@@ -632,6 +806,59 @@ fn apply_collisions(players: &mut [(usize, &mut HQMSkater)], collisions: &[HQMCo
     }
 }
 
+/// Change of the horizontal puck velocity in meters per tick, beyond friction, for a puck
+/// inside a skater to count as deflected by them
+const BODY_DEFLECTION: f32 = 0.005;
+
+fn puck_body_detection(
+    puck: &HQMPuck,
+    puck_index: usize,
+    old_puck_pos: &Point3<f32>,
+    old_puck_velocity: &Vector3<f32>,
+    players: &[(usize, &mut HQMSkater)],
+    events: &mut SimulationList,
+) {
+    // A stick touch of the same step explains the change of velocity
+    let touched_by_stick = events.iter().any(
+        |event| matches!(event, HQMSimulationEvent::PuckTouch { puck, .. } if puck.0 == puck_index),
+    );
+    if touched_by_stick {
+        return;
+    }
+    for (player_index, player) in players.iter() {
+        if puck_deflected_by_body(
+            (old_puck_pos, &puck.body.pos),
+            (old_puck_velocity, &puck.body.linear_velocity),
+            puck.radius,
+            &player.collision_balls,
+        ) {
+            events.push(HQMSimulationEvent::PuckTouchedBody {
+                player: HQMObjectIndex(*player_index),
+                puck: HQMObjectIndex(puck_index),
+            });
+        }
+    }
+}
+
+/// Whether the puck just went into one of the collision balls of a skater and changed course
+/// there. Pucks go through skaters, so a puck that just passes through, like a screened shot,
+/// doesn't count
+fn puck_deflected_by_body(
+    (old_pos, pos): (&Point3<f32>, &Point3<f32>),
+    (old_velocity, velocity): (&Vector3<f32>, &Vector3<f32>),
+    puck_radius: f32,
+    balls: &[HQMSkaterCollisionBall],
+) -> bool {
+    let inside = |pos: &Point3<f32>, ball: &HQMSkaterCollisionBall| {
+        (pos - ball.pos).norm() < ball.radius + puck_radius
+    };
+    let entered = balls
+        .iter()
+        .any(|ball| inside(pos, ball) && !inside(old_pos, ball));
+    let change = velocity - old_velocity;
+    entered && Vector2::new(change.x, change.z).norm() > BODY_DEFLECTION
+}
+
 fn puck_detection(
     puck: &mut HQMPuck,
     puck_index: usize,
@@ -727,6 +954,9 @@ fn puck_detection(
         team: HQMTeam,
         events: &mut SimulationList,
     ) {
+        if net.is_dislodged() {
+            return;
+        }
         if (&net.left_post - puck_pos).dot(&net.normal) >= 0.0 {
             if (&net.left_post - old_puck_pos).dot(&net.normal) < 0.0 {
                 if (&net.left_post - puck_pos).dot(&net.left_post_inside) < 0.0
@@ -783,6 +1013,16 @@ fn puck_detection(
         HQMTeam::Blue,
         events,
     );
+
+    if let Some(boards_height) = rink.boards_height {
+        let over_boards = |pos: &Point3<f32>| {
+            pos.y > boards_height
+                && collision_between_sphere_and_boards(pos, puck.radius, rink).is_some()
+        };
+        if over_boards(puck_pos) && !over_boards(old_puck_pos) {
+            events.push(HQMSimulationEvent::PuckOutOfPlay { puck: puck_index });
+        }
+    }
 }
 
 fn do_puck_net_forces(
@@ -792,6 +1032,9 @@ fn do_puck_net_forces(
     puck_angular_velocity: &Vector3<f32>,
 ) -> bool {
     let mut res = false;
+    if net.is_dislodged() {
+        return res;
+    }
     if let Some((overlap_pos, overlap, normal)) =
         collision_between_sphere_and_net(&puck.body.pos, puck.radius, net)
     {
@@ -821,6 +1064,9 @@ fn do_puck_post_forces(
     puck_angular_velocity: &Vector3<f32>,
 ) -> bool {
     let mut res = false;
+    if net.is_dislodged() {
+        return res;
+    }
     for post in net.posts.iter() {
         let collision = collision_between_sphere_and_post(&puck.body.pos, puck.radius, post);
         if let Some((overlap, normal)) = collision {
@@ -850,6 +1096,7 @@ fn do_puck_stick_forces(
     puck_linear_velocity: &Vector3<f32>,
     puck_angular_velocity: &Vector3<f32>,
     stick_velocity: &Vector3<f32>,
+    stick_puck_force: f32,
 ) -> bool {
     let stick_surfaces = get_stick_surfaces(player);
     let mut res = false;
@@ -870,7 +1117,7 @@ fn do_puck_stick_forces(
             if puck_force.dot(&normal) > 0.0 {
                 limit_friction(&mut puck_force, &normal, 0.5);
                 player.stick_velocity -= 0.25 * puck_force;
-                puck_force *= 0.75;
+                puck_force *= stick_puck_force;
                 apply_acceleration_to_object(&mut puck.body, &puck_force, &puck_vertex);
             }
         }
@@ -884,7 +1131,7 @@ fn do_puck_rink_forces(
     rink: &HQMRink,
     puck_linear_velocity: &Vector3<f32>,
     puck_angular_velocity: &Vector3<f32>,
-    friction: f32,
+    physics_config: &HQMPhysicsProfile,
 ) {
     for vertex in puck_vertices.iter() {
         let c = collision_between_vertex_and_rink(vertex, rink);
@@ -895,10 +1142,12 @@ fn do_puck_rink_forces(
                 puck_linear_velocity,
                 puck_angular_velocity,
             );
-            let mut puck_force = 0.125 * 0.125 * (overlap * 0.5 * *normal - vertex_velocity);
+            let mut puck_force = 0.125
+                * 0.125
+                * (overlap * physics_config.puck_rink_bounce * *normal - vertex_velocity);
 
             if normal.dot(&puck_force) > 0.0 {
-                limit_friction(&mut puck_force, &normal, friction);
+                limit_friction(&mut puck_force, &normal, physics_config.puck_rink_friction);
                 apply_acceleration_to_object(&mut puck.body, &puck_force, &vertex);
             }
         }
@@ -1062,10 +1311,32 @@ fn collision_between_sphere_and_rink(
     pos: &Point3<f32>,
     radius: f32,
     rink: &HQMRink,
+) -> Option<(f32, Unit<Vector3<f32>>)> {
+    collision_between_sphere_and_rink_parts(pos, radius, rink, true)
+}
+
+/// Like [`collision_between_sphere_and_rink`], but only with the boards and not with the ice
+fn collision_between_sphere_and_boards(
+    pos: &Point3<f32>,
+    radius: f32,
+    rink: &HQMRink,
+) -> Option<(f32, Unit<Vector3<f32>>)> {
+    collision_between_sphere_and_rink_parts(pos, radius, rink, false)
+}
+
+fn collision_between_sphere_and_rink_parts(
+    pos: &Point3<f32>,
+    radius: f32,
+    rink: &HQMRink,
+    with_ice: bool,
 ) -> Option<(f32, Unit<Vector3<f32>>)> {
     let mut max_overlap = 0f32;
     let mut coll_normal = None;
-    for (p, normal) in rink.planes.iter() {
+    let planes = rink
+        .planes
+        .iter()
+        .filter(|(_, normal)| with_ice || normal.y == 0.0);
+    for (p, normal) in planes {
         let overlap = (p - pos).dot(normal) + radius;
         if overlap > max_overlap {
             max_overlap = overlap;
@@ -1182,3 +1453,215 @@ fn rotate_matrix_around_axis(v: &mut Rotation3<f32>, axis: &Unit<Vector3<f32>>,
     let rot = Rotation3::from_axis_angle(axis, -angle);
     *v = rot * *v;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hqm_game::HQMRinkConfiguration;
+
+    fn ball(pos: Point3<f32>) -> HQMSkaterCollisionBall {
+        HQMSkaterCollisionBall {
+            offset: Vector3::zeros(),
+            pos,
+            velocity: Vector3::zeros(),
+            radius: 0.25,
+            mass: 1.0,
+        }
+    }
+
+    #[test]
+    fn puck_passing_through_is_not_a_body_touch() {
+        let balls = [ball(Point3::new(15.0, 0.5, 20.0))];
+        let velocity = Vector3::new(0.0, 0.0, 0.4);
+        let mut pos = Point3::new(15.0, 0.5, 19.0);
+        for _ in 0..5 {
+            let new_pos = pos + velocity;
+            assert!(!puck_deflected_by_body(
+                (&pos, &new_pos),
+                (&velocity, &velocity),
+                0.125,
+                &balls
+            ));
+            pos = new_pos;
+        }
+    }
+
+    #[test]
+    fn deflection_counts_once_when_entering() {
+        let balls = [ball(Point3::new(15.0, 0.5, 20.0))];
+        let old_velocity = Vector3::new(0.0, 0.0, 0.2);
+        let velocity = Vector3::new(0.1, 0.0, 0.15);
+        let outside = Point3::new(15.0, 0.5, 19.5);
+        let inside = Point3::new(15.0, 0.5, 19.8);
+        assert!(puck_deflected_by_body(
+            (&outside, &inside),
+            (&old_velocity, &velocity),
+            0.125,
+            &balls
+        ));
+        let further_inside = Point3::new(15.05, 0.5, 19.9);
+        assert!(!puck_deflected_by_body(
+            (&inside, &further_inside),
+            (&old_velocity, &velocity),
+            0.125,
+            &balls
+        ));
+    }
+
+    #[test]
+    fn net_is_dislodged_once_by_a_fast_skater() {
+        let mut rink = HQMRink::new(&HQMRinkConfiguration::default());
+        let goal_line_center = rink.red_net.goal_line_center();
+        let pos = goal_line_center - 0.5 * rink.red_net.normal + Vector3::new(0.0, 0.9, 0.0);
+        let mut skater = HQMSkater::new(pos, Rotation3::identity(), HQMSkaterHand::Left, 1.0);
+        skater.body.linear_velocity = Vector3::new(0.0, 0.0, 0.2);
+        let players = [(0, &mut skater)];
+
+        let mut events = SimulationList::new();
+        net_dislodge_detection(&players, &mut rink, &mut events);
+        assert!(events.is_empty());
+        assert!(!rink.red_net.is_dislodged());
+
+        rink.net_dislodge_speed = 0.1;
+        net_dislodge_detection(&players, &mut rink, &mut events);
+        assert!(matches!(
+            events[..],
+            [HQMSimulationEvent::NetDislodged {
+                team: HQMTeam::Red,
+                ..
+            }]
+        ));
+        assert!(rink.red_net.is_dislodged());
+        assert_eq!(rink.red_net.goal_line_center(), goal_line_center);
+
+        net_dislodge_detection(&players, &mut rink, &mut events);
+        assert_eq!(events.len(), 1);
+        rink.red_net.reset();
+        assert!(!rink.red_net.is_dislodged());
+    }
+
+    #[test]
+    fn faster_skater_makes_the_hit() {
+        let skater = |x: f32, speed: f32| {
+            let pos = Point3::new(x, 1.0, 30.0);
+            let mut skater = HQMSkater::new(pos, Rotation3::identity(), HQMSkaterHand::Left, 1.0);
+            skater.body.linear_velocity = Vector3::new(speed, 0.0, 0.0);
+            skater
+        };
+        let slow = skater(15.0, 0.005);
+        let fast = skater(14.0, 0.05);
+        assert!(matches!(
+            player_collision((0, &slow), (1, &fast)),
+            Some(HQMSimulationEvent::PlayerCollision {
+                player_a: HQMObjectIndex(1),
+                player_b: HQMObjectIndex(0),
+                impact,
+            }) if (impact - 0.045).abs() < 1e-6
+        ));
+
+        // Skating alongside each other isn't a hit
+        let alongside = skater(14.0, 0.01);
+        assert!(player_collision((0, &slow), (1, &alongside)).is_none());
+        let away = skater(14.0, -0.05);
+        assert!(player_collision((0, &slow), (1, &away)).is_none());
+    }
+
+    #[test]
+    fn ghosts_are_left_out_of_the_simulation() {
+        for ghost in [false, true] {
+            let mut world = HQMGameWorld::new(
+                1,
+                HQMPhysicsProfile::classic(),
+                &HQMRinkConfiguration::default(),
+            );
+            let pos = Point3::new(15.0, 1.5, 30.0);
+            let skater = world
+                .create_player_object(pos, Rotation3::identity(), HQMSkaterHand::Left, 1.0)
+                .unwrap();
+            let other_pos = Point3::new(15.3, 1.5, 30.0);
+            let other = world
+                .create_player_object(other_pos, Rotation3::identity(), HQMSkaterHand::Left, 1.0)
+                .unwrap();
+            world.objects.get_skater_mut(skater).unwrap().ghost = ghost;
+            for _ in 0..50 {
+                world.simulate_step();
+            }
+            // The skaters push each other apart, but the ghost doesn't even fall to the ice
+            let skater_pos = world.objects.get_skater(skater).unwrap().body.pos;
+            let pushed = world.objects.get_skater(other).unwrap().body.pos.x - other_pos.x;
+            if ghost {
+                assert_eq!(skater_pos, pos);
+                assert!(pushed.abs() < 0.01);
+            } else {
+                assert!(skater_pos.y < pos.y);
+                assert!(pushed > 0.02);
+            }
+        }
+    }
+
+    #[test]
+    fn bouncier_ice_bounces_pucks_higher() {
+        let bounce_height = |puck_rink_bounce: f32| {
+            let physics = HQMPhysicsProfile {
+                puck_rink_bounce,
+                ..HQMPhysicsProfile::classic()
+            };
+            let mut world = HQMGameWorld::new(1, physics, &HQMRinkConfiguration::default());
+            let puck = world
+                .create_puck_object(Point3::new(15.0, 0.1, 30.0), Rotation3::identity())
+                .unwrap();
+            world
+                .objects
+                .get_puck_mut(puck)
+                .unwrap()
+                .body
+                .linear_velocity = Vector3::new(0.0, -0.1, 0.0);
+            let mut height: f32 = 0.0;
+            let mut landed = false;
+            for _ in 0..100 {
+                world.simulate_step();
+                let puck = world.objects.get_puck(puck).unwrap();
+                landed |= puck.body.linear_velocity.y > 0.0;
+                if landed {
+                    height = height.max(puck.body.pos.y);
+                }
+            }
+            height
+        };
+        let classic = bounce_height(HQMPhysicsProfile::classic().puck_rink_bounce);
+        assert!(bounce_height(0.1) < classic);
+        assert!(bounce_height(2.0) > classic);
+    }
+
+    #[test]
+    fn puck_over_the_boards_is_out_of_play() {
+        let out_of_play_count = |boards_height: Option<f32>, y: f32| {
+            let rink = HQMRinkConfiguration {
+                boards_height,
+                ..HQMRinkConfiguration::default()
+            };
+            let mut world = HQMGameWorld::new(1, HQMPhysicsProfile::classic(), &rink);
+            let puck = world
+                .create_puck_object(Point3::new(1.0, y, 30.0), Rotation3::identity())
+                .unwrap();
+            world
+                .objects
+                .get_puck_mut(puck)
+                .unwrap()
+                .body
+                .linear_velocity = Vector3::new(-0.2, 0.0, 0.0);
+            let mut count = 0;
+            for _ in 0..20 {
+                count += world
+                    .simulate_step()
+                    .iter()
+                    .filter(|event| matches!(event, HQMSimulationEvent::PuckOutOfPlay { .. }))
+                    .count();
+            }
+            count
+        };
+        assert_eq!(out_of_play_count(Some(1.0), 2.0), 1);
+        assert_eq!(out_of_play_count(Some(1.0), 0.3), 0);
+        assert_eq!(out_of_play_count(None, 2.0), 0);
+    }
+}
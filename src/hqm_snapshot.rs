@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use nalgebra::{Matrix3, Point3, Rotation3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::hqm_game::HQMGameWorld;
+use crate::hqm_server::{HQMServer, HQMTeam};
+use crate::hqm_stats::HQMGameRecord;
+
+/// The state of a game in progress, saved periodically so that the game can be resumed with
+/// `--resume` if the server stops in the middle of it, and by admins with /savegame
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HQMGameSnapshot {
+    pub red_score: u32,
+    pub blue_score: u32,
+    pub period: u32,
+    pub time: u32,
+    pucks: Vec<HQMPuckSnapshot>,
+    /// The players on the ice and their teams, who are put back on their teams when they
+    /// join again
+    pub roster: Vec<HQMRosterEntry>,
+    /// Goals, faceoffs and the other statistics of the game so far. Missing in snapshots saved
+    /// before it was added
+    #[serde(default)]
+    game_record: Option<HQMGameRecord>,
+    /// Red defends the blue end of the rink and the other way around
+    #[serde(default)]
+    pub ends_swapped: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HQMPuckSnapshot {
+    pos: [f32; 3],
+    linear_velocity: [f32; 3],
+    /// Rotation matrix in column-major order
+    rot: [f32; 9],
+    angular_velocity: [f32; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HQMRosterEntry {
+    pub name: String,
+    pub team: HQMTeam,
+}
+
+impl HQMGameSnapshot {
+    /// Takes a snapshot of the current game, or None if there is no game in progress to resume
+    pub(crate) fn take(server: &HQMServer) -> Option<Self> {
+        if server.values.period == 0 || server.values.game_over {
+            return None;
+        }
+        let pucks = server
+            .world
+            .objects
+            .pucks()
+            .map(|(_, puck)| {
+                let mut rot = [0.0; 9];
+                rot.copy_from_slice(puck.body.rot.matrix().as_slice());
+                HQMPuckSnapshot {
+                    pos: puck.body.pos.coords.into(),
+                    linear_velocity: puck.body.linear_velocity.into(),
+                    rot,
+                    angular_velocity: puck.body.angular_velocity.into(),
+                }
+            })
+            .collect();
+        let roster = server
+            .players
+            .iter()
+            .filter_map(|(_, player)| {
+                let (_, team) = player.object?;
+                Some(HQMRosterEntry {
+                    name: player.player_name.to_string(),
+                    team,
+                })
+            })
+            .collect();
+        Some(HQMGameSnapshot {
+            red_score: server.values.red_score,
+            blue_score: server.values.blue_score,
+            period: server.values.period,
+            time: server.values.time,
+            pucks,
+            roster,
+            game_record: Some(server.game_record.clone()),
+            ends_swapped: server.values.ends_swapped,
+        })
+    }
+
+    /// The statistics of the game when the snapshot was taken
+    pub(crate) fn game_record(&self) -> Option<&HQMGameRecord> {
+        self.game_record.as_ref()
+    }
+
+    /// Replaces the pucks of the world with the ones in the snapshot
+    pub(crate) fn restore_pucks(&self, world: &mut HQMGameWorld) {
+        world.clear_pucks();
+        for puck in self.pucks.iter() {
+            let rot = Rotation3::from_matrix_unchecked(Matrix3::from_column_slice(&puck.rot));
+            if let Some(object_index) = world.create_puck_object(Point3::from(puck.pos), rot) {
+                if let Some(new_puck) = world.objects.get_puck_mut(object_index) {
+                    new_puck.body.linear_velocity = Vector3::from(puck.linear_velocity);
+                    new_puck.body.angular_velocity = Vector3::from(puck.angular_velocity);
+                }
+            }
+        }
+    }
+}
+
+/// File of a game saved with /savegame, or None if the name isn't allowed. Names can only have
+/// letters, digits, - and _, so that they can't point outside the directory
+pub(crate) fn savegame_path(directory: &Path, name: &str) -> Option<PathBuf> {
+    let allowed = !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if allowed {
+        Some(directory.join(format!("{}.json", name)))
+    } else {
+        None
+    }
+}
+
+/// Reads a game saved with /savegame
+pub(crate) fn read_savegame(path: &Path) -> Result<HQMGameSnapshot, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents).map_err(|e| e.to_string())
+}
+
+/// Names of the games saved with /savegame, in alphabetical order
+pub(crate) fn list_savegames(directory: &Path) -> Vec<String> {
+    let mut names: Vec<_> = std::fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hqm_autosave::write_atomic;
+
+    #[test]
+    fn saved_game_is_loaded_by_name() {
+        let directory = std::env::temp_dir().join(format!("savegames-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        assert!(savegame_path(&directory, "../final").is_none());
+        assert!(savegame_path(&directory, "").is_none());
+
+        let mut game_record = HQMGameRecord::new();
+        game_record.red_saved_shots = 4;
+        let snapshot = HQMGameSnapshot {
+            red_score: 2,
+            blue_score: 1,
+            period: 3,
+            time: 12345,
+            pucks: vec![HQMPuckSnapshot {
+                pos: [15.0, 0.5, 30.5],
+                linear_velocity: [0.0, 0.0, 0.1],
+                rot: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+                angular_velocity: [0.0; 3],
+            }],
+            roster: vec![HQMRosterEntry {
+                name: "migo".to_string(),
+                team: HQMTeam::Red,
+            }],
+            game_record: Some(game_record),
+            ends_swapped: true,
+        };
+        let path = savegame_path(&directory, "final-1").unwrap();
+        let contents = serde_json::to_string_pretty(&snapshot).unwrap();
+        write_atomic(&path, contents.as_bytes()).unwrap();
+
+        assert_eq!(list_savegames(&directory), vec!["final-1"]);
+        let loaded = read_savegame(&path).unwrap();
+        assert_eq!(
+            (
+                loaded.red_score,
+                loaded.blue_score,
+                loaded.period,
+                loaded.time
+            ),
+            (2, 1, 3, 12345)
+        );
+        assert_eq!(loaded.pucks[0].pos, [15.0, 0.5, 30.5]);
+        assert_eq!(loaded.roster[0].name, "migo");
+        assert!(loaded.ends_swapped);
+        assert_eq!(loaded.game_record().unwrap().red_saved_shots, 4);
+        assert!(read_savegame(&savegame_path(&directory, "missing").unwrap()).is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
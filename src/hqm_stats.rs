@@ -0,0 +1,1238 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::hqm_event_feed::{HQMIcePosition, HQMRuleCall};
+use crate::hqm_names::normalize_name;
+use crate::hqm_server::{HQMServerPlayerIndex, HQMTeam};
+use crate::hqm_stats_page::{format_period, format_time};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS games (
+    id TEXT PRIMARY KEY,
+    server TEXT NOT NULL,
+    started TEXT NOT NULL,
+    ended TEXT NOT NULL,
+    red_score INTEGER NOT NULL,
+    blue_score INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS goals (
+    game_id TEXT NOT NULL REFERENCES games(id),
+    team TEXT NOT NULL,
+    scorer TEXT,
+    assist TEXT,
+    period INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    speed REAL,
+    shot_speed REAL
+);
+CREATE INDEX IF NOT EXISTS goals_game_id ON goals(game_id);
+CREATE TABLE IF NOT EXISTS game_players (
+    game_id TEXT NOT NULL REFERENCES games(id),
+    player TEXT NOT NULL,
+    name TEXT NOT NULL,
+    team TEXT NOT NULL,
+    goals INTEGER NOT NULL,
+    assists INTEGER NOT NULL,
+    faceoffs_won INTEGER NOT NULL DEFAULT 0,
+    faceoffs_lost INTEGER NOT NULL DEFAULT 0,
+    saves INTEGER NOT NULL DEFAULT 0,
+    hits INTEGER NOT NULL DEFAULT 0,
+    possession INTEGER NOT NULL DEFAULT 0,
+    star INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (game_id, player)
+);
+CREATE TABLE IF NOT EXISTS faceoffs (
+    game_id TEXT NOT NULL REFERENCES games(id),
+    team TEXT NOT NULL,
+    winner TEXT,
+    loser TEXT,
+    period INTEGER NOT NULL,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS faceoffs_game_id ON faceoffs(game_id);
+CREATE TABLE IF NOT EXISTS penalties (
+    game_id TEXT NOT NULL REFERENCES games(id),
+    team TEXT NOT NULL,
+    player TEXT NOT NULL,
+    minutes INTEGER NOT NULL,
+    period INTEGER NOT NULL,
+    time INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS penalties_game_id ON penalties(game_id);
+CREATE TABLE IF NOT EXISTS shots (
+    game_id TEXT NOT NULL REFERENCES games(id),
+    team TEXT NOT NULL,
+    shooter TEXT,
+    goal INTEGER NOT NULL,
+    x REAL NOT NULL,
+    z REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS shots_game_id ON shots(game_id);
+CREATE TABLE IF NOT EXISTS players (
+    player TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    games INTEGER NOT NULL,
+    wins INTEGER NOT NULL,
+    goals INTEGER NOT NULL,
+    assists INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS league_games (
+    game_id TEXT PRIMARY KEY REFERENCES games(id),
+    red_team TEXT NOT NULL,
+    blue_team TEXT NOT NULL,
+    red_score INTEGER NOT NULL,
+    blue_score INTEGER NOT NULL,
+    overtime INTEGER NOT NULL
+);
+";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMGoalRecord {
+    pub(crate) team: HQMTeam,
+    pub(crate) scorer: Option<String>,
+    pub(crate) assist: Option<String>,
+    pub(crate) period: u32,
+    /// Time left of the period, measured in hundredths of a second
+    pub(crate) time: u32,
+    /// Speed of the puck across the goal line in meters per second, None in games recorded
+    /// before speeds were
+    pub(crate) speed: Option<f32>,
+    /// Speed of the puck when it left the stick in meters per second
+    pub(crate) shot_speed: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMRuleCallRecord {
+    pub(crate) rule: HQMRuleCall,
+    /// The team that broke the rule
+    pub(crate) team: HQMTeam,
+    pub(crate) player: Option<String>,
+    pub(crate) period: u32,
+    /// Time left of the period, measured in hundredths of a second
+    pub(crate) time: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMPenaltyRecord {
+    pub(crate) team: HQMTeam,
+    pub(crate) player: String,
+    pub(crate) minutes: u32,
+    pub(crate) period: u32,
+    /// Time left of the period, measured in hundredths of a second
+    pub(crate) time: u32,
+}
+
+/// A shot on goal, for the shot map
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMShotRecord {
+    /// The team that shot
+    pub(crate) team: HQMTeam,
+    pub(crate) shooter: Option<String>,
+    /// Whether the shot went in, otherwise it was saved
+    pub(crate) goal: bool,
+    /// Where the puck left the stick
+    pub(crate) position: HQMIcePosition,
+}
+
+/// Goals of each team in the shootout after overtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMShootoutRecord {
+    pub(crate) red_goals: u32,
+    pub(crate) blue_goals: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMFaceoffRecord {
+    /// The team that won the faceoff
+    pub(crate) team: HQMTeam,
+    pub(crate) winner: Option<String>,
+    pub(crate) loser: Option<String>,
+    pub(crate) period: u32,
+    /// Time left of the period, measured in hundredths of a second
+    pub(crate) time: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMPlayerGameRecord {
+    /// The name as it was shown, the player is stored by the normalized name
+    pub(crate) name: String,
+    /// The team the player was on last
+    pub(crate) team: HQMTeam,
+    pub(crate) goals: u32,
+    pub(crate) assists: u32,
+    pub(crate) faceoffs_won: u32,
+    pub(crate) faceoffs_lost: u32,
+    pub(crate) saves: u32,
+    /// Hits on skaters of the other team
+    pub(crate) hits: u32,
+    /// Time the player had the puck, measured in hundredths of a second
+    pub(crate) possession: u32,
+    /// 1 to 3 if the player was one of the three stars of the game
+    pub(crate) star: Option<u32>,
+}
+
+impl HQMPlayerGameRecord {
+    /// Rates the game of a player for the three stars
+    fn star_score(&self) -> f32 {
+        3.0 * self.goals as f32
+            + 2.0 * self.assists as f32
+            + 0.5 * self.saves as f32
+            + self.possession as f32 / 3000.0
+    }
+}
+
+/// Statistics of the current game, collected while it's played
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMGameRecord {
+    pub(crate) id: Uuid,
+    pub(crate) goals: Vec<HQMGoalRecord>,
+    pub(crate) faceoffs: Vec<HQMFaceoffRecord>,
+    pub(crate) rule_calls: Vec<HQMRuleCallRecord>,
+    pub(crate) penalties: Vec<HQMPenaltyRecord>,
+    /// Goals and saved shots where it's known where they were taken from. Missing in snapshots
+    /// saved before it was added
+    #[serde(default)]
+    pub(crate) shots: Vec<HQMShotRecord>,
+    /// Set if the game went to a shootout
+    pub(crate) shootout: Option<HQMShootoutRecord>,
+    /// Shots that were saved, by the team that shot
+    pub(crate) red_saved_shots: u32,
+    pub(crate) blue_saved_shots: u32,
+    /// Everyone who played in the game, by normalized name
+    pub(crate) players: HashMap<String, HQMPlayerGameRecord>,
+    /// Set if the game is a league game between two teams
+    pub(crate) matchup: Option<HQMMatchup>,
+    /// Seed of the random puck drop delays, None if pucks are dropped at once
+    pub(crate) drop_seed: Option<u64>,
+}
+
+/// The league teams playing as red and blue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct HQMMatchup {
+    pub(crate) red: String,
+    pub(crate) blue: String,
+}
+
+impl HQMGameRecord {
+    pub(crate) fn new() -> Self {
+        HQMGameRecord {
+            id: Uuid::new_v4(),
+            goals: vec![],
+            faceoffs: vec![],
+            rule_calls: vec![],
+            penalties: vec![],
+            shots: vec![],
+            shootout: None,
+            red_saved_shots: 0,
+            blue_saved_shots: 0,
+            players: HashMap::new(),
+            matchup: None,
+            drop_seed: None,
+        }
+    }
+
+    fn player(&mut self, name: &str, team: HQMTeam) -> &mut HQMPlayerGameRecord {
+        self.players
+            .entry(normalize_name(name))
+            .or_insert_with(|| HQMPlayerGameRecord {
+                name: name.to_string(),
+                team,
+                goals: 0,
+                assists: 0,
+                faceoffs_won: 0,
+                faceoffs_lost: 0,
+                saves: 0,
+                hits: 0,
+                possession: 0,
+                star: None,
+            })
+    }
+
+    /// Marks a player as playing in the game for a team
+    pub(crate) fn add_player(&mut self, name: &str, team: HQMTeam) {
+        self.player(name, team).team = team;
+    }
+
+    pub(crate) fn add_goal(&mut self, goal: HQMGoalRecord) {
+        if let Some(scorer) = &goal.scorer {
+            self.player(scorer, goal.team).goals += 1;
+        }
+        if let Some(assist) = &goal.assist {
+            self.player(assist, goal.team).assists += 1;
+        }
+        self.goals.push(goal);
+    }
+
+    pub(crate) fn add_faceoff(&mut self, faceoff: HQMFaceoffRecord) {
+        if let Some(winner) = &faceoff.winner {
+            self.player(winner, faceoff.team).faceoffs_won += 1;
+        }
+        if let Some(loser) = &faceoff.loser {
+            self.player(loser, faceoff.team.get_other_team())
+                .faceoffs_lost += 1;
+        }
+        self.faceoffs.push(faceoff);
+    }
+
+    pub(crate) fn add_rule_call(&mut self, rule_call: HQMRuleCallRecord) {
+        self.rule_calls.push(rule_call);
+    }
+
+    /// Records a shot by a team that the goalie of the other team saved
+    pub(crate) fn add_save(&mut self, team: HQMTeam, goalie: &str) {
+        self.player(goalie, team.get_other_team()).saves += 1;
+        match team {
+            HQMTeam::Red => self.red_saved_shots += 1,
+            HQMTeam::Blue => self.blue_saved_shots += 1,
+        }
+    }
+
+    /// Records a hit by a player of a team on a skater of the other team
+    pub(crate) fn add_hit(&mut self, team: HQMTeam, hitter: &str) {
+        self.player(hitter, team).hits += 1;
+    }
+
+    /// Hits by the players of a team
+    fn hits(&self, team: HQMTeam) -> u32 {
+        self.players
+            .values()
+            .filter(|player| player.team == team)
+            .map(|player| player.hits)
+            .sum()
+    }
+
+    /// Records that a player had the puck for a step of the game
+    pub(crate) fn add_possession(&mut self, name: &str, team: HQMTeam) {
+        self.player(name, team).possession += 1;
+    }
+
+    /// The three players with the best games, rated by goals, assists, saves and possession,
+    /// as the keys of players
+    pub(crate) fn three_stars(&self) -> Vec<&str> {
+        let mut players = self
+            .players
+            .iter()
+            .map(|(key, player)| (key.as_str(), player.star_score()))
+            .filter(|(_, score)| *score > 0.0)
+            .collect::<Vec<_>>();
+        players.sort_by(|(a_key, a), (b_key, b)| b.total_cmp(a).then_with(|| a_key.cmp(b_key)));
+        players.into_iter().take(3).map(|(key, _)| key).collect()
+    }
+
+    /// Marks the three stars on their records
+    pub(crate) fn award_stars(&mut self) {
+        let stars = self
+            .three_stars()
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        for (key, player) in self.players.iter_mut() {
+            player.star = stars
+                .iter()
+                .position(|star| star == key)
+                .map(|i| i as u32 + 1);
+        }
+    }
+
+    /// Shots on goal of a team, the goals and the saved shots
+    pub(crate) fn shots(&self, team: HQMTeam) -> u32 {
+        let goals = self.goals.iter().filter(|goal| goal.team == team).count() as u32;
+        let saved = match team {
+            HQMTeam::Red => self.red_saved_shots,
+            HQMTeam::Blue => self.blue_saved_shots,
+        };
+        goals + saved
+    }
+
+    /// Whether the game was decided in overtime or a shootout. The period is raised when the
+    /// clock runs out, so only a goal after the regular periods or a shootout tells that the game
+    /// went past regulation
+    pub(crate) fn went_to_overtime(&self, regular_periods: Option<u32>) -> bool {
+        self.shootout.is_some()
+            || regular_periods
+                .is_some_and(|periods| self.goals.iter().any(|goal| goal.period > periods))
+    }
+
+    /// Chat lines summing up the game: the score, the scoring by period, every goal, the shots
+    /// and the three players with the most points
+    pub(crate) fn box_score(&self, red_score: u32, blue_score: u32, period: u32) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Final: Red {} - {} Blue{}",
+            red_score,
+            blue_score,
+            if self.shootout.is_some() { " (SO)" } else { "" }
+        )];
+
+        // The shootout started when the clock went past the last overtime period
+        let period = if self.shootout.is_some() {
+            period.saturating_sub(1)
+        } else {
+            period
+        };
+        let last_period = self
+            .goals
+            .iter()
+            .map(|goal| goal.period)
+            .fold(period, u32::max);
+        if last_period > 0 {
+            let periods = (1..=last_period)
+                .map(|period| {
+                    let goals = |team| {
+                        self.goals
+                            .iter()
+                            .filter(|goal| goal.period == period && goal.team == team)
+                            .count()
+                    };
+                    format!(
+                        "{} {}-{}",
+                        short_period(period),
+                        goals(HQMTeam::Red),
+                        goals(HQMTeam::Blue)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!("By period: {}", periods));
+        }
+
+        for goal in self.goals.iter() {
+            let mut line = format!(
+                "{} {} {}: {}",
+                short_period(goal.period),
+                format_time(goal.time),
+                goal.team,
+                goal.scorer.as_deref().unwrap_or("(unknown)")
+            );
+            if let Some(assist) = &goal.assist {
+                line.push_str(&format!(" ({})", assist));
+            }
+            lines.push(line);
+        }
+        if let Some(shootout) = &self.shootout {
+            lines.push(format!(
+                "Shootout: Red {} - {} Blue",
+                shootout.red_goals, shootout.blue_goals
+            ));
+        }
+
+        lines.push(format!(
+            "Shots: Red {} - {} Blue",
+            self.shots(HQMTeam::Red),
+            self.shots(HQMTeam::Blue)
+        ));
+
+        // The player with the most hits, the first by name if several have as many
+        let most_hits = self
+            .players
+            .values()
+            .filter(|player| player.hits > 0)
+            .max_by(|a, b| a.hits.cmp(&b.hits).then_with(|| b.name.cmp(&a.name)));
+        if let Some(player) = most_hits {
+            lines.push(format!(
+                "Hits: Red {} - {} Blue, most by {} ({})",
+                self.hits(HQMTeam::Red),
+                self.hits(HQMTeam::Blue),
+                player.name,
+                player.hits
+            ));
+        }
+
+        let stars = self.three_stars();
+        if !stars.is_empty() {
+            let stars = stars
+                .iter()
+                .enumerate()
+                .map(|(i, key)| format!("{}. {}", i + 1, self.players[*key].name))
+                .collect::<Vec<_>>()
+                .join(" ");
+            lines.push(format!("Stars: {}", stars));
+        }
+        lines
+    }
+}
+
+/// Periods as P1 to P3 and OT, short enough for a chat line
+fn short_period(period: u32) -> String {
+    match period {
+        1..=3 => format!("P{}", period),
+        _ => format_period(period),
+    }
+}
+
+/// A finished game, ready to be written to the database
+#[derive(Debug)]
+pub(crate) struct HQMFinishedGame {
+    pub(crate) record: HQMGameRecord,
+    pub(crate) server: String,
+    pub(crate) started: DateTime<Utc>,
+    pub(crate) ended: DateTime<Utc>,
+    pub(crate) red_score: u32,
+    pub(crate) blue_score: u32,
+    /// Whether the game was decided in overtime or a shootout
+    pub(crate) overtime: bool,
+}
+
+/// Number of players listed by /top
+const TOP_PLAYERS: usize = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum HQMTopCategory {
+    Goals,
+    Assists,
+    Games,
+}
+
+impl HQMTopCategory {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "goals" => Some(HQMTopCategory::Goals),
+            "assists" => Some(HQMTopCategory::Assists),
+            "games" => Some(HQMTopCategory::Games),
+            _ => None,
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            HQMTopCategory::Goals => "goals",
+            HQMTopCategory::Assists => "assists",
+            HQMTopCategory::Games => "games",
+        }
+    }
+}
+
+/// The player who asked for statistics. The ID makes sure that the answer doesn't go to
+/// another player who got the same index after the player left.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct HQMStatsReceiver {
+    pub(crate) player_index: HQMServerPlayerIndex,
+    pub(crate) player_id: Uuid,
+}
+
+#[derive(Debug)]
+enum HQMStatsRequest {
+    SaveGame(Box<HQMFinishedGame>),
+    PlayerStats {
+        name: String,
+        receiver: HQMStatsReceiver,
+    },
+    Top {
+        category: HQMTopCategory,
+        receiver: HQMStatsReceiver,
+    },
+    Standings {
+        receiver: HQMStatsReceiver,
+    },
+}
+
+/// Chat messages that answer a request for statistics
+#[derive(Debug)]
+pub(crate) struct HQMStatsReply {
+    pub(crate) receiver: HQMStatsReceiver,
+    pub(crate) messages: Vec<String>,
+}
+
+/// Sends finished games and requests for statistics to the task that uses the database,
+/// and receives the answers
+pub(crate) struct HQMStatsStore {
+    sender: mpsc::UnboundedSender<HQMStatsRequest>,
+    replies: mpsc::UnboundedReceiver<HQMStatsReply>,
+}
+
+impl HQMStatsStore {
+    /// Opens or creates the database, and starts the task that uses it. Reads and writes are
+    /// done on a blocking thread so that the tick loop never waits for the disk.
+    pub(crate) fn open(path: PathBuf) -> rusqlite::Result<Self> {
+        let connection = open_database(&path)?;
+        info!("Recording statistics in {}", path.display());
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let (reply_sender, replies) = mpsc::unbounded_channel();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection;
+            while let Some(request) = receiver.blocking_recv() {
+                let (receiver, result) = match request {
+                    HQMStatsRequest::SaveGame(game) => {
+                        if let Err(e) = write_game(&mut connection, &game) {
+                            warn!(
+                                "Could not save statistics of game {}: {}",
+                                game.record.id, e
+                            );
+                        }
+                        continue;
+                    }
+                    HQMStatsRequest::PlayerStats { name, receiver } => {
+                        (receiver, player_stats(&connection, &name))
+                    }
+                    HQMStatsRequest::Top { category, receiver } => {
+                        (receiver, top_players(&connection, category))
+                    }
+                    HQMStatsRequest::Standings { receiver } => {
+                        (receiver, standings_messages(&connection))
+                    }
+                };
+                let messages = result.unwrap_or_else(|e| {
+                    warn!("Could not read statistics: {}", e);
+                    vec!["Statistics are not available right now".to_string()]
+                });
+                let _ = reply_sender.send(HQMStatsReply { receiver, messages });
+            }
+        });
+        Ok(HQMStatsStore { sender, replies })
+    }
+
+    pub(crate) fn record_game(&self, game: HQMFinishedGame) {
+        let _ = self.sender.send(HQMStatsRequest::SaveGame(Box::new(game)));
+    }
+
+    /// Asks for the career statistics of a player, the answer comes later from reply
+    pub(crate) fn request_player_stats(&self, name: String, receiver: HQMStatsReceiver) {
+        let _ = self
+            .sender
+            .send(HQMStatsRequest::PlayerStats { name, receiver });
+    }
+
+    /// Asks for the players with the most goals, assists or games, the answer comes later
+    /// from reply
+    pub(crate) fn request_top(&self, category: HQMTopCategory, receiver: HQMStatsReceiver) {
+        let _ = self
+            .sender
+            .send(HQMStatsRequest::Top { category, receiver });
+    }
+
+    /// Asks for the league standings, the answer comes later from reply
+    pub(crate) fn request_standings(&self, receiver: HQMStatsReceiver) {
+        let _ = self.sender.send(HQMStatsRequest::Standings { receiver });
+    }
+
+    /// Returns an answer to a request, if one is ready
+    pub(crate) fn reply(&mut self) -> Option<HQMStatsReply> {
+        self.replies.try_recv().ok()
+    }
+}
+
+/// A recorded game, as it's read back from the database
+#[derive(Debug, Serialize)]
+pub(crate) struct HQMGameSummary {
+    pub(crate) server: String,
+    pub(crate) started: String,
+    pub(crate) ended: String,
+    pub(crate) red_score: u32,
+    pub(crate) blue_score: u32,
+    /// In the order they were scored
+    pub(crate) goals: Vec<HQMGoalRecord>,
+    /// In the order they were taken
+    pub(crate) faceoffs: Vec<HQMFaceoffRecord>,
+    /// In the order they were given
+    pub(crate) penalties: Vec<HQMPenaltyRecord>,
+    /// Goals and saved shots, in the order they were taken
+    pub(crate) shots: Vec<HQMShotRecord>,
+    /// Ordered by points, then goals
+    pub(crate) players: Vec<HQMPlayerGameRecord>,
+}
+
+/// Reads a recorded game from the database. Opens a connection of its own, so it can be
+/// used from any thread while games are written.
+pub(crate) fn load_game(path: &Path, id: Uuid) -> rusqlite::Result<Option<HQMGameSummary>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let id = id.to_string();
+    let game = connection
+        .query_row(
+            "SELECT server, started, ended, red_score, blue_score FROM games WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(HQMGameSummary {
+                    server: row.get(0)?,
+                    started: row.get(1)?,
+                    ended: row.get(2)?,
+                    red_score: row.get(3)?,
+                    blue_score: row.get(4)?,
+                    goals: vec![],
+                    faceoffs: vec![],
+                    penalties: vec![],
+                    shots: vec![],
+                    players: vec![],
+                })
+            },
+        )
+        .optional()?;
+    let mut game = match game {
+        Some(game) => game,
+        None => return Ok(None),
+    };
+    let mut statement = connection.prepare(
+        "SELECT team, scorer, assist, period, time, speed, shot_speed FROM goals
+         WHERE game_id = ?1 ORDER BY rowid",
+    )?;
+    game.goals = statement
+        .query_map(params![id], |row| {
+            Ok(HQMGoalRecord {
+                team: parse_team(&row.get::<_, String>(0)?),
+                scorer: row.get(1)?,
+                assist: row.get(2)?,
+                period: row.get(3)?,
+                time: row.get(4)?,
+                speed: row.get(5)?,
+                shot_speed: row.get(6)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut statement = connection.prepare(
+        "SELECT team, winner, loser, period, time FROM faceoffs WHERE game_id = ?1 ORDER BY rowid",
+    )?;
+    game.faceoffs = statement
+        .query_map(params![id], |row| {
+            Ok(HQMFaceoffRecord {
+                team: parse_team(&row.get::<_, String>(0)?),
+                winner: row.get(1)?,
+                loser: row.get(2)?,
+                period: row.get(3)?,
+                time: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut statement = connection.prepare(
+        "SELECT team, player, minutes, period, time FROM penalties
+         WHERE game_id = ?1 ORDER BY rowid",
+    )?;
+    game.penalties = statement
+        .query_map(params![id], |row| {
+            Ok(HQMPenaltyRecord {
+                team: parse_team(&row.get::<_, String>(0)?),
+                player: row.get(1)?,
+                minutes: row.get(2)?,
+                period: row.get(3)?,
+                time: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut statement = connection
+        .prepare("SELECT team, shooter, goal, x, z FROM shots WHERE game_id = ?1 ORDER BY rowid")?;
+    game.shots = statement
+        .query_map(params![id], |row| {
+            Ok(HQMShotRecord {
+                team: parse_team(&row.get::<_, String>(0)?),
+                shooter: row.get(1)?,
+                goal: row.get(2)?,
+                position: HQMIcePosition {
+                    x: row.get(3)?,
+                    z: row.get(4)?,
+                },
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut statement = connection.prepare(
+        "SELECT name, team, goals, assists, faceoffs_won, faceoffs_lost, saves, possession, star,
+                hits
+         FROM game_players
+         WHERE game_id = ?1
+         ORDER BY goals + assists DESC, goals DESC, name",
+    )?;
+    game.players = statement
+        .query_map(params![id], |row| {
+            Ok(HQMPlayerGameRecord {
+                name: row.get(0)?,
+                team: parse_team(&row.get::<_, String>(1)?),
+                goals: row.get(2)?,
+                assists: row.get(3)?,
+                faceoffs_won: row.get(4)?,
+                faceoffs_lost: row.get(5)?,
+                saves: row.get(6)?,
+                possession: row.get(7)?,
+                star: Some(row.get(8)?).filter(|star| *star > 0),
+                hits: row.get(9)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(Some(game))
+}
+
+fn parse_team(team: &str) -> HQMTeam {
+    if team == "Blue" {
+        HQMTeam::Blue
+    } else {
+        HQMTeam::Red
+    }
+}
+
+fn open_database(path: &Path) -> rusqlite::Result<Connection> {
+    let connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.execute_batch(SCHEMA)?;
+    // Databases created before faceoffs were tracked
+    add_column(&connection, "game_players", "faceoffs_won", COUNT_COLUMN)?;
+    add_column(&connection, "game_players", "faceoffs_lost", COUNT_COLUMN)?;
+    // and before the three stars
+    add_column(&connection, "game_players", "saves", COUNT_COLUMN)?;
+    add_column(&connection, "game_players", "possession", COUNT_COLUMN)?;
+    add_column(&connection, "game_players", "star", COUNT_COLUMN)?;
+    // and before hits
+    add_column(&connection, "game_players", "hits", COUNT_COLUMN)?;
+    // and before goal speeds
+    add_column(&connection, "goals", "speed", "REAL")?;
+    add_column(&connection, "goals", "shot_speed", "REAL")?;
+    Ok(connection)
+}
+
+/// Type of columns that count something, for rows written before the column existed
+const COUNT_COLUMN: &str = "INTEGER NOT NULL DEFAULT 0";
+
+/// Adds a column, if the table doesn't have it yet
+fn add_column(
+    connection: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut statement = connection.prepare(&format!("PRAGMA table_info({})", table))?;
+    let mut columns = statement.query_map([], |row| row.get::<_, String>(1))?;
+    if columns.any(|name| name.as_deref() == Ok(column)) {
+        return Ok(());
+    }
+    connection.execute_batch(&format!(
+        "ALTER TABLE {} ADD COLUMN {} {}",
+        table, column, definition
+    ))
+}
+
+fn player_stats(connection: &Connection, name: &str) -> rusqlite::Result<Vec<String>> {
+    let stats = connection
+        .query_row(
+            "SELECT name, games, goals, assists FROM players WHERE player = ?1",
+            params![normalize_name(name)],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, u32>(3)?,
+                ))
+            },
+        )
+        .optional()?;
+    let messages = match stats {
+        Some((name, games, goals, assists)) => {
+            let goals_per_game = if games > 0 {
+                goals as f32 / games as f32
+            } else {
+                0.0
+            };
+            vec![
+                format!("Career statistics of {}", name),
+                format!(
+                    "{} games, {} goals, {} assists, {:.2} goals per game",
+                    games, goals, assists, goals_per_game
+                ),
+            ]
+        }
+        None => vec![format!("No statistics for {}", name)],
+    };
+    Ok(messages)
+}
+
+fn top_players(connection: &Connection, category: HQMTopCategory) -> rusqlite::Result<Vec<String>> {
+    let column = category.column();
+    let mut statement = connection.prepare(&format!(
+        "SELECT name, {} FROM players ORDER BY {} DESC, games, name LIMIT {}",
+        column, column, TOP_PLAYERS
+    ))?;
+    let mut messages = vec![format!("Most {}:", column)];
+    let rows = statement.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?))
+    })?;
+    for (i, row) in rows.enumerate() {
+        let (name, value) = row?;
+        messages.push(format!("{}. {}: {}", i + 1, name, value));
+    }
+    if messages.len() == 1 {
+        messages.push("No games have been recorded yet".to_string());
+    }
+    Ok(messages)
+}
+
+/// Number of teams listed by /standings
+const STANDINGS_TEAMS: usize = 8;
+
+/// The result of a league game, as it's stored
+#[derive(Debug, Clone)]
+pub(crate) struct HQMLeagueResult {
+    pub(crate) red_team: String,
+    pub(crate) blue_team: String,
+    pub(crate) red_score: u32,
+    pub(crate) blue_score: u32,
+    pub(crate) overtime: bool,
+}
+
+/// The record of a team in the league. A win is worth 2 points, and an overtime loss or a
+/// tie 1 point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct HQMStanding {
+    pub(crate) team: String,
+    pub(crate) games: u32,
+    pub(crate) wins: u32,
+    pub(crate) losses: u32,
+    pub(crate) overtime_losses: u32,
+    pub(crate) ties: u32,
+    pub(crate) goals_for: u32,
+    pub(crate) goals_against: u32,
+    pub(crate) goal_differential: i64,
+    pub(crate) points: u32,
+}
+
+impl HQMStanding {
+    fn new(team: &str) -> Self {
+        HQMStanding {
+            team: team.to_string(),
+            games: 0,
+            wins: 0,
+            losses: 0,
+            overtime_losses: 0,
+            ties: 0,
+            goals_for: 0,
+            goals_against: 0,
+            goal_differential: 0,
+            points: 0,
+        }
+    }
+
+    fn add_game(&mut self, goals_for: u32, goals_against: u32, overtime: bool) {
+        self.games += 1;
+        self.goals_for += goals_for;
+        self.goals_against += goals_against;
+        self.goal_differential = self.goals_for as i64 - self.goals_against as i64;
+        match goals_for.cmp(&goals_against) {
+            std::cmp::Ordering::Greater => {
+                self.wins += 1;
+                self.points += 2;
+            }
+            std::cmp::Ordering::Less if overtime => {
+                self.overtime_losses += 1;
+                self.points += 1;
+            }
+            std::cmp::Ordering::Less => self.losses += 1,
+            std::cmp::Ordering::Equal => {
+                self.ties += 1;
+                self.points += 1;
+            }
+        }
+    }
+}
+
+/// Sums up league games into standings, ordered by points, then wins, then goal differential
+pub(crate) fn standings(results: &[HQMLeagueResult]) -> Vec<HQMStanding> {
+    let mut teams: HashMap<&str, HQMStanding> = HashMap::new();
+    for result in results {
+        teams
+            .entry(&result.red_team)
+            .or_insert_with(|| HQMStanding::new(&result.red_team))
+            .add_game(result.red_score, result.blue_score, result.overtime);
+        teams
+            .entry(&result.blue_team)
+            .or_insert_with(|| HQMStanding::new(&result.blue_team))
+            .add_game(result.blue_score, result.red_score, result.overtime);
+    }
+    let mut standings: Vec<_> = teams.into_values().collect();
+    standings.sort_by(|a, b| {
+        b.points
+            .cmp(&a.points)
+            .then(b.wins.cmp(&a.wins))
+            .then(b.goal_differential.cmp(&a.goal_differential))
+            .then(a.team.cmp(&b.team))
+    });
+    standings
+}
+
+fn read_standings(connection: &Connection) -> rusqlite::Result<Vec<HQMStanding>> {
+    let mut statement = connection
+        .prepare("SELECT red_team, blue_team, red_score, blue_score, overtime FROM league_games")?;
+    let results = statement
+        .query_map([], |row| {
+            Ok(HQMLeagueResult {
+                red_team: row.get(0)?,
+                blue_team: row.get(1)?,
+                red_score: row.get(2)?,
+                blue_score: row.get(3)?,
+                overtime: row.get(4)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(standings(&results))
+}
+
+/// Reads the league standings from the database. Opens a connection of its own, so it can be
+/// used from any thread while games are written.
+pub(crate) fn load_standings(path: &Path) -> rusqlite::Result<Vec<HQMStanding>> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    read_standings(&connection)
+}
+
+fn standings_messages(connection: &Connection) -> rusqlite::Result<Vec<String>> {
+    let standings = read_standings(connection)?;
+    if standings.is_empty() {
+        return Ok(vec!["No league games have been recorded yet".to_string()]);
+    }
+    let with_ties = standings.iter().any(|standing| standing.ties > 0);
+    let mut messages = vec![if with_ties {
+        "Standings, W-L-OTL-T:".to_string()
+    } else {
+        "Standings, W-L-OTL:".to_string()
+    }];
+    for (i, standing) in standings.iter().take(STANDINGS_TEAMS).enumerate() {
+        let ties = if with_ties {
+            format!("-{}", standing.ties)
+        } else {
+            String::new()
+        };
+        messages.push(format!(
+            "{}. {} {} pts, {}-{}-{}{}, goals {}-{} ({:+})",
+            i + 1,
+            standing.team,
+            standing.points,
+            standing.wins,
+            standing.losses,
+            standing.overtime_losses,
+            ties,
+            standing.goals_for,
+            standing.goals_against,
+            standing.goal_differential
+        ));
+    }
+    Ok(messages)
+}
+
+fn write_game(connection: &mut Connection, game: &HQMFinishedGame) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    let id = game.record.id.to_string();
+    let winner = match game.red_score.cmp(&game.blue_score) {
+        std::cmp::Ordering::Greater => Some(HQMTeam::Red),
+        std::cmp::Ordering::Less => Some(HQMTeam::Blue),
+        std::cmp::Ordering::Equal => None,
+    };
+    transaction.execute(
+        "INSERT INTO games (id, server, started, ended, red_score, blue_score)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            id,
+            game.server,
+            game.started.to_rfc3339(),
+            game.ended.to_rfc3339(),
+            game.red_score,
+            game.blue_score
+        ],
+    )?;
+    for goal in game.record.goals.iter() {
+        transaction.execute(
+            "INSERT INTO goals (game_id, team, scorer, assist, period, time, speed, shot_speed)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                id,
+                goal.team.to_string(),
+                goal.scorer,
+                goal.assist,
+                goal.period,
+                goal.time,
+                goal.speed,
+                goal.shot_speed
+            ],
+        )?;
+    }
+    for faceoff in game.record.faceoffs.iter() {
+        transaction.execute(
+            "INSERT INTO faceoffs (game_id, team, winner, loser, period, time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                faceoff.team.to_string(),
+                faceoff.winner,
+                faceoff.loser,
+                faceoff.period,
+                faceoff.time
+            ],
+        )?;
+    }
+    for penalty in game.record.penalties.iter() {
+        transaction.execute(
+            "INSERT INTO penalties (game_id, team, player, minutes, period, time)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                penalty.team.to_string(),
+                penalty.player,
+                penalty.minutes,
+                penalty.period,
+                penalty.time
+            ],
+        )?;
+    }
+    for shot in game.record.shots.iter() {
+        transaction.execute(
+            "INSERT INTO shots (game_id, team, shooter, goal, x, z)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                shot.team.to_string(),
+                shot.shooter,
+                shot.goal,
+                shot.position.x,
+                shot.position.z
+            ],
+        )?;
+    }
+    for (player, record) in game.record.players.iter() {
+        transaction.execute(
+            "INSERT INTO game_players
+             (game_id, player, name, team, goals, assists, faceoffs_won, faceoffs_lost,
+              saves, possession, star, hits)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                id,
+                player,
+                record.name,
+                record.team.to_string(),
+                record.goals,
+                record.assists,
+                record.faceoffs_won,
+                record.faceoffs_lost,
+                record.saves,
+                record.possession,
+                record.star.unwrap_or(0),
+                record.hits
+            ],
+        )?;
+        let win = u32::from(winner == Some(record.team));
+        transaction.execute(
+            "INSERT INTO players (player, name, games, wins, goals, assists)
+             VALUES (?1, ?2, 1, ?3, ?4, ?5)
+             ON CONFLICT (player) DO UPDATE SET
+                name = excluded.name,
+                games = games + 1,
+                wins = wins + excluded.wins,
+                goals = goals + excluded.goals,
+                assists = assists + excluded.assists",
+            params![player, record.name, win, record.goals, record.assists],
+        )?;
+    }
+    if let Some(matchup) = &game.record.matchup {
+        transaction.execute(
+            "INSERT INTO league_games
+             (game_id, red_team, blue_team, red_score, blue_score, overtime)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                matchup.red,
+                matchup.blue,
+                game.red_score,
+                game.blue_score,
+                game.overtime
+            ],
+        )?;
+    }
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_score_sums_up_the_game() {
+        let mut record = HQMGameRecord::new();
+        record.add_goal(HQMGoalRecord {
+            team: HQMTeam::Red,
+            scorer: Some("migo".to_string()),
+            assist: Some("ehl".to_string()),
+            period: 1,
+            time: 12345,
+            speed: Some(30.0),
+            shot_speed: None,
+        });
+        record.add_goal(HQMGoalRecord {
+            team: HQMTeam::Blue,
+            scorer: Some("hqm".to_string()),
+            assist: None,
+            period: 4,
+            time: 9000,
+            speed: Some(25.0),
+            shot_speed: Some(28.0),
+        });
+        record.add_save(HQMTeam::Red, "goalie");
+        record.add_save(HQMTeam::Red, "goalie");
+        record.add_hit(HQMTeam::Red, "ehl");
+        record.add_hit(HQMTeam::Blue, "hqm");
+        record.add_hit(HQMTeam::Blue, "hqm");
+        assert_eq!(
+            record.box_score(1, 1, 4),
+            vec![
+                "Final: Red 1 - 1 Blue",
+                "By period: P1 1-0, P2 0-0, P3 0-0, OT 0-1",
+                "P1 2:03 Red: migo (ehl)",
+                "OT 1:30 Blue: hqm",
+                "Shots: Red 3 - 1 Blue",
+                "Hits: Red 1 - 2 Blue, most by hqm (2)",
+                "Stars: 1. hqm 2. migo 3. ehl",
+            ]
+        );
+    }
+
+    #[test]
+    fn standings_count_overtime_losses_and_ties() {
+        let result = |red: &str, blue: &str, red_score, blue_score, overtime| HQMLeagueResult {
+            red_team: red.to_string(),
+            blue_team: blue.to_string(),
+            red_score,
+            blue_score,
+            overtime,
+        };
+        let standings = standings(&[
+            result("Ducks", "Geese", 3, 1, false),
+            result("Geese", "Swans", 2, 1, true),
+            result("Swans", "Ducks", 2, 2, false),
+        ]);
+        let teams: Vec<_> = standings
+            .iter()
+            .map(|standing| (standing.team.as_str(), standing.points))
+            .collect();
+        assert_eq!(teams, vec![("Ducks", 3), ("Geese", 2), ("Swans", 2)]);
+        let swans = &standings[2];
+        assert_eq!((swans.wins, swans.losses), (0, 0));
+        assert_eq!((swans.overtime_losses, swans.ties), (1, 1));
+        assert_eq!(swans.goal_differential, -1);
+    }
+
+    #[test]
+    fn overtime_goes_by_the_regular_periods() {
+        let mut record = HQMGameRecord::new();
+        record.add_goal(HQMGoalRecord {
+            team: HQMTeam::Red,
+            scorer: Some("migo".to_string()),
+            assist: None,
+            period: 2,
+            time: 4500,
+            speed: None,
+            shot_speed: None,
+        });
+        assert!(record.went_to_overtime(Some(1)));
+        assert!(!record.went_to_overtime(Some(3)));
+        assert!(!record.went_to_overtime(None));
+        record.goals[0].period = 4;
+        assert!(!record.went_to_overtime(Some(4)));
+        assert!(record.went_to_overtime(Some(3)));
+    }
+
+    #[test]
+    fn top_categories_are_parsed_in_any_case() {
+        assert_eq!(HQMTopCategory::parse("goals"), Some(HQMTopCategory::Goals));
+        assert_eq!(
+            HQMTopCategory::parse("Assists"),
+            Some(HQMTopCategory::Assists)
+        );
+        assert_eq!(HQMTopCategory::parse("GAMES"), Some(HQMTopCategory::Games));
+        assert_eq!(HQMTopCategory::parse("saves"), None);
+    }
+}
@@ -0,0 +1,642 @@
+use std::fmt::Write;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::DateTime;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::hqm_event_feed::SCHEMA_VERSION;
+use crate::hqm_game::HQMRinkConfiguration;
+use crate::hqm_server::HQMTeam;
+use crate::hqm_stats::{load_game, load_standings, HQMGameSummary, HQMShotRecord, HQMStanding};
+
+/// Path of game pages, followed by the game UUID
+pub(crate) const GAME_PAGE_PATH: &str = "/games/";
+/// Path of game results as JSON, followed by the game UUID
+const GAME_API_PATH: &str = "/api/games/";
+const STATUS_API_PATH: &str = "/api/status";
+const STANDINGS_API_PATH: &str = "/api/standings";
+const OPENAPI_PATH: &str = "/openapi.json";
+
+/// Describes the JSON API, so that clients can be generated from it
+const OPENAPI: &str = include_str!("openapi.json");
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// The state of the server that the status endpoint shows. It's updated by the server, and
+/// only sent on when something changed.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HQMServerStatus {
+    pub(crate) players: usize,
+    pub(crate) player_max: usize,
+    pub(crate) game_id: u32,
+    pub(crate) game_uuid: Uuid,
+    pub(crate) period: u32,
+    pub(crate) time: u32,
+    pub(crate) red_score: u32,
+    pub(crate) blue_score: u32,
+    pub(crate) game_over: bool,
+}
+
+#[derive(Serialize)]
+struct HQMServerStatusMessage<'a> {
+    version: u32,
+    server: &'a str,
+    players: usize,
+    player_max: usize,
+    game_id: u32,
+    game_uuid: String,
+    period: u32,
+    /// Time left of the period, measured in hundredths of a second
+    time: u32,
+    red_score: u32,
+    blue_score: u32,
+    game_over: bool,
+}
+
+#[derive(Serialize)]
+struct HQMGameResultMessage<'a> {
+    version: u32,
+    id: String,
+    #[serde(flatten)]
+    game: &'a HQMGameSummary,
+}
+
+#[derive(Serialize)]
+struct HQMStandingsMessage {
+    version: u32,
+    teams: Vec<HQMStanding>,
+}
+
+#[derive(Serialize)]
+struct HQMErrorMessage<'a> {
+    version: u32,
+    error: &'a str,
+}
+
+struct HQMHttpContext {
+    server_name: String,
+    database: Option<PathBuf>,
+    status: watch::Receiver<HQMServerStatus>,
+}
+
+struct HQMHttpResponse {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl HQMHttpResponse {
+    fn html(status: &'static str, body: String) -> Self {
+        HQMHttpResponse {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body,
+        }
+    }
+
+    fn json<T: Serialize>(status: &'static str, value: &T) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => HQMHttpResponse {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => {
+                warn!("Could not serialize response: {}", e);
+                HQMHttpResponse::html("500 Internal Server Error", error_page("Internal error"))
+            }
+        }
+    }
+
+    fn json_error(status: &'static str, error: &str) -> Self {
+        HQMHttpResponse::json(
+            status,
+            &HQMErrorMessage {
+                version: SCHEMA_VERSION,
+                error,
+            },
+        )
+    }
+}
+
+/// Serves a summary page of every recorded game over HTTP, at /games/ followed by the game
+/// UUID, and a JSON API with the status of the server, the results of recorded games and the
+/// league standings that is described at /openapi.json
+pub(crate) async fn run_stats_page(
+    address: SocketAddr,
+    server_name: String,
+    database: Option<PathBuf>,
+    status: watch::Receiver<HQMServerStatus>,
+) -> std::io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    info!(
+        "Statistics pages listening at address {:?}",
+        listener.local_addr()?
+    );
+    let context = Arc::new(HQMHttpContext {
+        server_name,
+        database,
+        status,
+    });
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &context).await {
+                debug!("Statistics page request from {:?} failed: {}", addr, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, context: &HQMHttpContext) -> std::io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream))
+        .await
+        .map_err(|_| std::io::Error::from(ErrorKind::TimedOut))??;
+    let response = respond(&request, context).await;
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reads until the end of the request headers. Requests don't have bodies that matter here.
+async fn read_request_head(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut request = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|x| x == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                "request is too large",
+            ));
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+async fn respond(request: &str, context: &HQMHttpContext) -> HQMHttpResponse {
+    let mut parts = request.split_ascii_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method, path),
+        _ => return HQMHttpResponse::html("400 Bad Request", error_page("Bad request")),
+    };
+    let is_api = path.starts_with("/api/");
+    if method != "GET" {
+        let status = "405 Method Not Allowed";
+        return if is_api {
+            HQMHttpResponse::json_error(status, "Method not allowed")
+        } else {
+            HQMHttpResponse::html(status, error_page("Method not allowed"))
+        };
+    }
+    if path == OPENAPI_PATH {
+        return HQMHttpResponse {
+            status: "200 OK",
+            content_type: "application/json",
+            body: OPENAPI.to_string(),
+        };
+    }
+    if path == STATUS_API_PATH {
+        return status_response(context);
+    }
+    if path == STANDINGS_API_PATH {
+        return standings_response(context).await;
+    }
+    let parse_id = |id: &str| Uuid::parse_str(id.trim_end_matches('/')).ok();
+    if let Some(id) = path.strip_prefix(GAME_API_PATH) {
+        let game = match parse_id(id) {
+            Some(id) => load(context, id).await.map(|game| (id, game)),
+            None => Err(("404 Not Found", "Not found")),
+        };
+        return match game {
+            Ok((id, game)) => HQMHttpResponse::json(
+                "200 OK",
+                &HQMGameResultMessage {
+                    version: SCHEMA_VERSION,
+                    id: id.to_string(),
+                    game: &game,
+                },
+            ),
+            Err((status, error)) => HQMHttpResponse::json_error(status, error),
+        };
+    }
+    if let Some(id) = path.strip_prefix(GAME_PAGE_PATH) {
+        let game = match parse_id(id) {
+            Some(id) => load(context, id).await,
+            None => Err(("404 Not Found", "Not found")),
+        };
+        return match game {
+            Ok(game) => HQMHttpResponse::html("200 OK", game_page(&game)),
+            Err((status, error)) => HQMHttpResponse::html(status, error_page(error)),
+        };
+    }
+    if is_api {
+        HQMHttpResponse::json_error("404 Not Found", "Not found")
+    } else {
+        HQMHttpResponse::html("404 Not Found", error_page("Not found"))
+    }
+}
+
+fn status_response(context: &HQMHttpContext) -> HQMHttpResponse {
+    let status = context.status.borrow().clone();
+    HQMHttpResponse::json(
+        "200 OK",
+        &HQMServerStatusMessage {
+            version: SCHEMA_VERSION,
+            server: &context.server_name,
+            players: status.players,
+            player_max: status.player_max,
+            game_id: status.game_id,
+            game_uuid: status.game_uuid.to_string(),
+            period: status.period,
+            time: status.time,
+            red_score: status.red_score,
+            blue_score: status.blue_score,
+            game_over: status.game_over,
+        },
+    )
+}
+
+async fn standings_response(context: &HQMHttpContext) -> HQMHttpResponse {
+    let database = match &context.database {
+        Some(database) => database.clone(),
+        None => return HQMHttpResponse::json_error("404 Not Found", "Statistics are not enabled"),
+    };
+    let result = tokio::task::spawn_blocking(move || load_standings(&database))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()));
+    match result {
+        Ok(teams) => HQMHttpResponse::json(
+            "200 OK",
+            &HQMStandingsMessage {
+                version: SCHEMA_VERSION,
+                teams,
+            },
+        ),
+        Err(e) => {
+            warn!("Could not read standings from statistics database: {}", e);
+            HQMHttpResponse::json_error("500 Internal Server Error", "Internal error")
+        }
+    }
+}
+
+/// Reads a game from the statistics database, or returns the status and the error message
+async fn load(
+    context: &HQMHttpContext,
+    id: Uuid,
+) -> Result<HQMGameSummary, (&'static str, &'static str)> {
+    let database = match &context.database {
+        Some(database) => database.clone(),
+        None => return Err(("404 Not Found", "Statistics are not enabled")),
+    };
+    let result = tokio::task::spawn_blocking(move || load_game(&database, id))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()));
+    match result {
+        Ok(Some(game)) => Ok(game),
+        Ok(None) => Err(("404 Not Found", "No game with this ID")),
+        Err(e) => {
+            warn!("Could not read game {} from statistics database: {}", id, e);
+            Err(("500 Internal Server Error", "Internal error"))
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn page(title: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>\n\
+         body {{ font-family: sans-serif; max-width: 40em; margin: 1em auto; padding: 0 1em; }}\n\
+         table {{ border-collapse: collapse; width: 100%; margin-bottom: 1em; }}\n\
+         th, td {{ text-align: left; padding: 0.2em 0.5em; border-bottom: 1px solid #ccc; }}\n\
+         .red {{ color: #c00; }} .blue {{ color: #00c; }}\n\
+         </style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        content
+    )
+}
+
+fn error_page(message: &str) -> String {
+    page(message, &format!("<h1>{}</h1>\n", escape(message)))
+}
+
+fn team_class(team: HQMTeam) -> &'static str {
+    match team {
+        HQMTeam::Red => "red",
+        HQMTeam::Blue => "blue",
+    }
+}
+
+pub(crate) fn format_period(period: u32) -> String {
+    match period {
+        0 => "Warmup".to_string(),
+        1..=3 => period.to_string(),
+        4 => "OT".to_string(),
+        _ => format!("OT{}", period - 3),
+    }
+}
+
+/// Formats a time measured in hundredths of a second as M:SS
+pub(crate) fn format_time(time: u32) -> String {
+    let seconds = time / 100;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn format_timestamp(timestamp: &str) -> String {
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(timestamp) => timestamp.format("%Y-%m-%d %H:%M UTC").to_string(),
+        Err(_) => timestamp.to_string(),
+    }
+}
+
+/// Formats faceoffs as won-lost and the percentage won, empty if there were none
+fn format_faceoffs(won: u32, lost: u32) -> String {
+    let total = won + lost;
+    if total == 0 {
+        return String::new();
+    }
+    format!("{}-{} ({}%)", won, lost, won * 100 / total)
+}
+
+/// Marks the three stars of the game after their names
+fn format_star(star: Option<u32>) -> &'static str {
+    match star {
+        Some(1) => " (1st star)",
+        Some(2) => " (2nd star)",
+        Some(3) => " (3rd star)",
+        _ => "",
+    }
+}
+
+fn game_page(game: &HQMGameSummary) -> String {
+    let mut content = String::new();
+    let _ = writeln!(
+        content,
+        "<h1><span class=\"red\">Red</span> {} - {} <span class=\"blue\">Blue</span></h1>",
+        game.red_score, game.blue_score
+    );
+    let _ = writeln!(
+        content,
+        "<p>{}, {} to {}</p>",
+        escape(&game.server),
+        format_timestamp(&game.started),
+        format_timestamp(&game.ended)
+    );
+
+    content.push_str("<h2>Box score</h2>\n");
+    for team in [HQMTeam::Red, HQMTeam::Blue] {
+        let _ = writeln!(
+            content,
+            "<table>\n<tr><th class=\"{}\">{}</th><th>G</th><th>A</th><th>P</th><th>FO</th><th>SV</th><th>H</th><th>Poss</th></tr>",
+            team_class(team),
+            team
+        );
+        for player in game.players.iter().filter(|x| x.team == team) {
+            let _ = writeln!(
+                content,
+                "<tr><td>{}{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&player.name),
+                format_star(player.star),
+                player.goals,
+                player.assists,
+                player.goals + player.assists,
+                format_faceoffs(player.faceoffs_won, player.faceoffs_lost),
+                player.saves,
+                player.hits,
+                format_time(player.possession)
+            );
+        }
+        let faceoffs_won = game.faceoffs.iter().filter(|x| x.team == team).count() as u32;
+        let faceoffs_lost = game.faceoffs.len() as u32 - faceoffs_won;
+        let hits: u32 = game
+            .players
+            .iter()
+            .filter(|x| x.team == team)
+            .map(|x| x.hits)
+            .sum();
+        let _ = writeln!(
+            content,
+            "<tr><th>Team</th><th></th><th></th><th></th><th>{}</th><th></th><th>{}</th><th></th></tr>",
+            format_faceoffs(faceoffs_won, faceoffs_lost),
+            hits
+        );
+        content.push_str("</table>\n");
+    }
+
+    content.push_str("<h2>Scoring</h2>\n");
+    if game.goals.is_empty() {
+        content.push_str("<p>No goals</p>\n");
+    } else {
+        content.push_str(
+            "<table>\n<tr><th>Period</th><th>Time left</th><th>Team</th><th>Goal</th><th>Assist</th><th>Score</th></tr>\n",
+        );
+        let (mut red_score, mut blue_score) = (0, 0);
+        for goal in game.goals.iter() {
+            match goal.team {
+                HQMTeam::Red => red_score += 1,
+                HQMTeam::Blue => blue_score += 1,
+            }
+            let _ = writeln!(
+                content,
+                "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{} - {}</td></tr>",
+                format_period(goal.period),
+                format_time(goal.time),
+                team_class(goal.team),
+                goal.team,
+                escape(goal.scorer.as_deref().unwrap_or("")),
+                escape(goal.assist.as_deref().unwrap_or("")),
+                red_score,
+                blue_score
+            );
+        }
+        content.push_str("</table>\n");
+    }
+
+    content.push_str("<h2>Penalties</h2>\n");
+    if game.penalties.is_empty() {
+        content.push_str("<p>No penalties</p>\n");
+    } else {
+        content.push_str(
+            "<table>\n<tr><th>Period</th><th>Time left</th><th>Team</th><th>Player</th><th>Minutes</th></tr>\n",
+        );
+        for penalty in game.penalties.iter() {
+            let _ = writeln!(
+                content,
+                "<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{}</td></tr>",
+                format_period(penalty.period),
+                format_time(penalty.time),
+                team_class(penalty.team),
+                penalty.team,
+                escape(&penalty.player),
+                penalty.minutes
+            );
+        }
+        content.push_str("</table>\n");
+    }
+
+    if !game.shots.is_empty() {
+        content.push_str("<h2>Shots</h2>\n");
+        content.push_str(&shot_map(&game.shots));
+    }
+
+    let title = format!(
+        "{}: Red {} - {} Blue",
+        game.server, game.red_score, game.blue_score
+    );
+    page(&title, &content)
+}
+
+/// A drawing of the rink from above with a dot where each shot was taken from, filled for
+/// goals. The rink is drawn in the default size, with the length across the page
+fn shot_map(shots: &[HQMShotRecord]) -> String {
+    let rink = HQMRinkConfiguration::default();
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg viewBox=\"-1 -1 {} {}\" width=\"100%\" role=\"img\" aria-label=\"Shot map\">",
+        rink.length + 2.0,
+        rink.width + 2.0
+    );
+    let _ = writeln!(
+        svg,
+        "<rect width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"none\" stroke=\"#999\" stroke-width=\"0.3\"/>",
+        rink.length, rink.width, rink.corner_radius
+    );
+    let lines = [
+        (rink.goal_line_distance, "#c00"),
+        (rink.blue_line_distance, "#00c"),
+        (rink.length / 2.0, "#c00"),
+        (rink.length - rink.blue_line_distance, "#00c"),
+        (rink.length - rink.goal_line_distance, "#c00"),
+    ];
+    for (z, color) in lines {
+        let _ = writeln!(
+            svg,
+            "<line x1=\"{0}\" y1=\"0\" x2=\"{0}\" y2=\"{1}\" stroke=\"{2}\" stroke-width=\"0.3\" opacity=\"0.4\"/>",
+            z, rink.width, color
+        );
+    }
+    for shot in shots {
+        let fill = if shot.goal { "currentColor" } else { "none" };
+        let _ = writeln!(
+            svg,
+            "<circle class=\"{}\" cx=\"{:.1}\" cy=\"{:.1}\" r=\"0.6\" fill=\"{}\" stroke=\"currentColor\" stroke-width=\"0.2\"><title>{}</title></circle>",
+            team_class(shot.team),
+            shot.position.z,
+            shot.position.x,
+            fill,
+            escape(shot.shooter.as_deref().unwrap_or(""))
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hqm_event_feed::HQMIcePosition;
+    use crate::hqm_stats::HQMPenaltyRecord;
+
+    #[test]
+    fn openapi_document_describes_the_api() {
+        let document: serde_json::Value = serde_json::from_str(OPENAPI).unwrap();
+        let paths = document["paths"].as_object().unwrap();
+        assert!(paths.contains_key(STATUS_API_PATH));
+        assert!(paths.contains_key(STANDINGS_API_PATH));
+        assert!(paths.contains_key(&format!("{}{{id}}", GAME_API_PATH)));
+        assert!(paths.contains_key(&format!("{}{{id}}", GAME_PAGE_PATH)));
+        assert!(paths.contains_key(OPENAPI_PATH));
+        assert_eq!(document["info"]["version"], SCHEMA_VERSION.to_string());
+    }
+
+    #[test]
+    fn game_page_has_the_penalties_and_the_shot_map() {
+        let game = HQMGameSummary {
+            server: "Test".to_string(),
+            started: "2026-10-15T19:00:00+00:00".to_string(),
+            ended: "2026-10-15T19:20:00+00:00".to_string(),
+            red_score: 1,
+            blue_score: 0,
+            goals: vec![],
+            faceoffs: vec![],
+            penalties: vec![HQMPenaltyRecord {
+                team: HQMTeam::Blue,
+                player: "<b>migo</b>".to_string(),
+                minutes: 2,
+                period: 1,
+                time: 12000,
+            }],
+            shots: vec![
+                HQMShotRecord {
+                    team: HQMTeam::Red,
+                    shooter: Some("a".to_string()),
+                    goal: true,
+                    position: HQMIcePosition { x: 15.0, z: 10.3 },
+                },
+                HQMShotRecord {
+                    team: HQMTeam::Blue,
+                    shooter: None,
+                    goal: false,
+                    position: HQMIcePosition { x: 12.0, z: 50.0 },
+                },
+            ],
+            players: vec![],
+        };
+        let page = game_page(&game);
+        assert!(page.contains(
+            "<td>1</td><td>2:00</td><td class=\"blue\">Blue</td><td>&lt;b&gt;migo&lt;/b&gt;</td><td>2</td>"
+        ));
+        assert_eq!(page.matches("<circle").count(), 2);
+        assert!(page.contains("cx=\"10.3\" cy=\"15.0\" r=\"0.6\" fill=\"currentColor\""));
+        assert!(page.contains("cx=\"50.0\" cy=\"12.0\" r=\"0.6\" fill=\"none\""));
+
+        let game = HQMGameSummary {
+            penalties: vec![],
+            shots: vec![],
+            ..game
+        };
+        let page = game_page(&game);
+        assert!(page.contains("<p>No penalties</p>"));
+        assert!(!page.contains("<svg"));
+    }
+}
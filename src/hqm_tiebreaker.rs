@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::hqm_game::HQMObjectIndex;
+use crate::hqm_server::{HQMServerPlayerIndex, HQMTeam};
+
+/// Time each shooter has for an attempt, measured in hundredths of a second
+pub(crate) const SHOOTOUT_ATTEMPT_TIME: u32 = 2000;
+/// Time between the end of an attempt and the next one
+pub(crate) const SHOOTOUT_ATTEMPT_BREAK: u32 = 500;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HQMShootoutAttemptState {
+    /// Waiting for the next attempt. Goal is set if the last attempt was a goal
+    Break { timer: u32, goal: bool },
+    /// The puck hasn't been touched by the goalie, hit the post or moved backwards yet. Progress
+    /// is how far the puck has come towards the net
+    Attack { progress: f32 },
+    /// The puck may still enter the net, but the shooter may not touch it again
+    NoMoreAttack { final_progress: f32 },
+}
+
+/// The attempt being taken
+#[derive(Debug, Clone)]
+pub(crate) struct HQMShootoutAttempt {
+    pub(crate) team: HQMTeam,
+    pub(crate) shooter: HQMServerPlayerIndex,
+    pub(crate) puck: HQMObjectIndex,
+}
+
+/// A shootout after overtime. The teams take turns shooting, red first, until one team can't
+/// catch up anymore within the rounds. If they are still tied after the rounds, the shootout
+/// goes on round by round until a round ends with one team ahead
+#[derive(Debug, Clone)]
+pub(crate) struct HQMShootoutTiebreaker {
+    rounds: u32,
+    red: Vec<bool>,
+    blue: Vec<bool>,
+    pub(crate) state: HQMShootoutAttemptState,
+    pub(crate) attempt: Option<HQMShootoutAttempt>,
+    /// Attempts taken by each player, so that everyone gets to shoot before anyone shoots twice
+    shots_taken: HashMap<Rc<String>, u32>,
+}
+
+impl HQMShootoutTiebreaker {
+    pub(crate) fn new(rounds: u32) -> Self {
+        HQMShootoutTiebreaker {
+            rounds,
+            red: vec![],
+            blue: vec![],
+            state: HQMShootoutAttemptState::Break {
+                timer: SHOOTOUT_ATTEMPT_BREAK,
+                goal: false,
+            },
+            attempt: None,
+            shots_taken: HashMap::new(),
+        }
+    }
+
+    /// The team that shoots next
+    pub(crate) fn next_team(&self) -> HQMTeam {
+        if self.red.len() > self.blue.len() {
+            HQMTeam::Blue
+        } else {
+            HQMTeam::Red
+        }
+    }
+
+    /// The round of the next attempt, starting at 1
+    pub(crate) fn next_round(&self) -> u32 {
+        self.blue.len() as u32 + 1
+    }
+
+    pub(crate) fn is_sudden_death(&self, round: u32) -> bool {
+        round > self.rounds
+    }
+
+    pub(crate) fn goals(&self, team: HQMTeam) -> u32 {
+        let attempts = match team {
+            HQMTeam::Red => &self.red,
+            HQMTeam::Blue => &self.blue,
+        };
+        attempts.iter().filter(|goal| **goal).count() as u32
+    }
+
+    pub(crate) fn rounds(&self) -> u32 {
+        self.rounds
+    }
+
+    pub(crate) fn add_attempt(&mut self, team: HQMTeam, goal: bool) {
+        match team {
+            HQMTeam::Red => self.red.push(goal),
+            HQMTeam::Blue => self.blue.push(goal),
+        }
+    }
+
+    /// Picks the shooter who has taken the fewest attempts, in the order the players are given
+    pub(crate) fn pick_shooter(
+        &mut self,
+        players: &[(HQMServerPlayerIndex, Rc<String>)],
+    ) -> Option<HQMServerPlayerIndex> {
+        let (player_index, name) = players
+            .iter()
+            .min_by_key(|(_, name)| self.shots_taken.get(name).copied().unwrap_or(0))?;
+        *self.shots_taken.entry(name.clone()).or_insert(0) += 1;
+        Some(*player_index)
+    }
+
+    /// The team that has won, once the other team can't catch up with its remaining attempts
+    pub(crate) fn winner(&self) -> Option<HQMTeam> {
+        let red_goals = self.goals(HQMTeam::Red);
+        let blue_goals = self.goals(HQMTeam::Blue);
+        let attempts = (self.rounds as usize).max(self.red.len());
+        let red_left = (attempts - self.red.len()) as u32;
+        let blue_left = (attempts - self.blue.len()) as u32;
+        if red_goals > blue_goals + blue_left {
+            Some(HQMTeam::Red)
+        } else if blue_goals > red_goals + red_left {
+            Some(HQMTeam::Blue)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hqm_server::HQMTeam;
+    use crate::hqm_tiebreaker::HQMShootoutTiebreaker;
+
+    #[test]
+    fn shootout_ends_when_a_team_cant_catch_up() {
+        let mut shootout = HQMShootoutTiebreaker::new(3);
+        shootout.add_attempt(HQMTeam::Red, true);
+        shootout.add_attempt(HQMTeam::Blue, false);
+        shootout.add_attempt(HQMTeam::Red, true);
+        assert_eq!(shootout.winner(), None);
+        // Blue can score at most once more
+        shootout.add_attempt(HQMTeam::Blue, false);
+        assert_eq!(shootout.winner(), Some(HQMTeam::Red));
+    }
+
+    #[test]
+    fn sudden_death_rounds_are_played_out() {
+        let mut shootout = HQMShootoutTiebreaker::new(1);
+        shootout.add_attempt(HQMTeam::Red, false);
+        shootout.add_attempt(HQMTeam::Blue, false);
+        assert_eq!(shootout.winner(), None);
+        assert!(shootout.is_sudden_death(shootout.next_round()));
+        // Blue still gets to answer in the round
+        shootout.add_attempt(HQMTeam::Red, true);
+        assert_eq!(shootout.winner(), None);
+        assert_eq!(shootout.next_team(), HQMTeam::Blue);
+        shootout.add_attempt(HQMTeam::Blue, true);
+        assert_eq!(shootout.winner(), None);
+        shootout.add_attempt(HQMTeam::Red, false);
+        shootout.add_attempt(HQMTeam::Blue, true);
+        assert_eq!(shootout.winner(), Some(HQMTeam::Blue));
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use tracing::info;
+
+use migo_hqm_server::hqm_match_util::{
+    HQMIcingConfiguration, HQMMatchConfiguration, HQMOffsideConfiguration, HQMVoteVoters,
+};
+use migo_hqm_server::hqm_server::{HQMServer, HQMServerPlayerIndex};
+
+use crate::hqm_config::{icing_by_name, offside_by_name};
+
+/// What a vote is about, applied when the vote passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HQMVoteSubject {
+    ResetGame,
+    StartGame,
+    Icing(HQMIcingConfiguration),
+    Offside(HQMOffsideConfiguration),
+    Mercy(u32),
+    FirstTo(u32),
+}
+
+impl HQMVoteSubject {
+    pub(crate) fn parse(arg: &str) -> Option<Self> {
+        let args: Vec<&str> = arg.split_whitespace().collect();
+        let goals = |s: &str| {
+            if s == "off" {
+                Some(0)
+            } else {
+                s.parse::<u32>().ok()
+            }
+        };
+        match args.as_slice() {
+            ["resetgame" | "reset"] => Some(HQMVoteSubject::ResetGame),
+            ["startgame" | "start"] => Some(HQMVoteSubject::StartGame),
+            ["icing", rule] => icing_by_name(rule).map(HQMVoteSubject::Icing),
+            ["offside", rule] => offside_by_name(rule).map(HQMVoteSubject::Offside),
+            ["mercy", num] => goals(num).map(HQMVoteSubject::Mercy),
+            ["first", num] => goals(num).map(HQMVoteSubject::FirstTo),
+            _ => None,
+        }
+    }
+}
+
+impl Display for HQMVoteSubject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HQMVoteSubject::ResetGame => write!(f, "reset the game"),
+            HQMVoteSubject::StartGame => write!(f, "start the game"),
+            HQMVoteSubject::Icing(HQMIcingConfiguration::Touch) => write!(f, "use touch icing"),
+            HQMVoteSubject::Icing(HQMIcingConfiguration::NoTouch) => {
+                write!(f, "use no-touch icing")
+            }
+            HQMVoteSubject::Icing(HQMIcingConfiguration::Off) => write!(f, "turn off icing"),
+            HQMVoteSubject::Offside(HQMOffsideConfiguration::Delayed) => {
+                write!(f, "use delayed offside")
+            }
+            HQMVoteSubject::Offside(HQMOffsideConfiguration::Immediate) => {
+                write!(f, "use immediate offside")
+            }
+            HQMVoteSubject::Offside(HQMOffsideConfiguration::Off) => write!(f, "turn off offside"),
+            HQMVoteSubject::Mercy(0) => write!(f, "turn off the mercy rule"),
+            HQMVoteSubject::Mercy(goals) => write!(f, "set the mercy rule to {} goals", goals),
+            HQMVoteSubject::FirstTo(0) => write!(f, "turn off the first-to rule"),
+            HQMVoteSubject::FirstTo(goals) => write!(f, "play first to {} goals", goals),
+        }
+    }
+}
+
+/// Yes votes needed for more than the threshold percentage of the voters, so that a vote with
+/// the default threshold of 50 needs a majority. Always at least one and at most all voters
+fn needed_votes(voters: usize, threshold: u32) -> usize {
+    let threshold = threshold.min(100) as usize;
+    (voters * threshold / 100 + 1).min(voters).max(1)
+}
+
+/// A vote started by a player with /vote. The voters are fixed when the vote starts, and the
+/// vote passes as soon as more than the threshold percentage of them voted yes, or fails when
+/// that can't happen anymore or the time runs out
+pub(crate) struct HQMVote {
+    pub(crate) subject: HQMVoteSubject,
+    voters: HashMap<HQMServerPlayerIndex, Option<bool>>,
+    threshold: u32,
+    /// Yes votes needed to pass
+    needed: usize,
+    /// Steps until the vote fails
+    time_left: u32,
+}
+
+impl HQMVote {
+    /// Starts a vote with a yes vote of the player who started it, if the player may vote
+    pub(crate) fn open(
+        server: &mut HQMServer,
+        config: &HQMMatchConfiguration,
+        player_index: HQMServerPlayerIndex,
+        subject: HQMVoteSubject,
+    ) -> Option<Self> {
+        let mut voters: HashMap<_, _> = server
+            .players
+            .iter()
+            .filter(|(_, player)| {
+                config.vote_voters == HQMVoteVoters::Everyone || player.object.is_some()
+            })
+            .map(|(player_index, _)| (player_index, None))
+            .collect();
+        let player_name = server.players.get(player_index)?.player_name.clone();
+        match voters.get_mut(&player_index) {
+            Some(vote) => *vote = Some(true),
+            None => {
+                server.messages.add_directed_server_chat_message(
+                    "Only players on the ice can start votes",
+                    player_index,
+                );
+                return None;
+            }
+        }
+        let needed = needed_votes(voters.len(), config.vote_threshold);
+        info!(
+            "{} ({}) started a vote to {}",
+            player_name, player_index, subject
+        );
+        let msg = format!(
+            "{} started a vote to {}, /vote yes or /vote no within {} seconds",
+            player_name, subject, config.vote_time
+        );
+        server.messages.add_server_chat_message(msg);
+        Some(HQMVote {
+            subject,
+            voters,
+            threshold: config.vote_threshold,
+            needed,
+            time_left: config.vote_time * 100,
+        })
+    }
+
+    /// Records a vote, returns the result if the vote is decided
+    pub(crate) fn vote(
+        &mut self,
+        server: &mut HQMServer,
+        player_index: HQMServerPlayerIndex,
+        arg: &str,
+    ) -> Option<bool> {
+        let vote = match arg {
+            "yes" | "y" => true,
+            "no" | "n" => false,
+            _ => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("Usage: /vote yes|no", player_index);
+                return None;
+            }
+        };
+        match self.voters.get_mut(&player_index) {
+            Some(voter) => *voter = Some(vote),
+            None => {
+                server
+                    .messages
+                    .add_directed_server_chat_message("You can't vote in this vote", player_index);
+                return None;
+            }
+        }
+        let (yes, no) = self.count();
+        if let Some(player) = server.players.get(player_index) {
+            let msg = format!(
+                "{} voted {} ({}/{} yes, {} no)",
+                player.player_name,
+                if vote { "yes" } else { "no" },
+                yes,
+                self.needed,
+                no
+            );
+            server.messages.add_server_chat_message(msg);
+        }
+        self.result()
+    }
+
+    pub(crate) fn remove_player(&mut self, player_index: HQMServerPlayerIndex) -> Option<bool> {
+        if self.voters.remove(&player_index).is_some() {
+            self.needed = needed_votes(self.voters.len(), self.threshold);
+            self.result()
+        } else {
+            None
+        }
+    }
+
+    /// Called every step, returns true when the time is up
+    pub(crate) fn tick(&mut self) -> bool {
+        self.time_left = self.time_left.saturating_sub(1);
+        self.time_left == 0
+    }
+
+    fn count(&self) -> (usize, usize) {
+        let count = |vote| {
+            self.voters
+                .values()
+                .filter(|voter| **voter == Some(vote))
+                .count()
+        };
+        (count(true), count(false))
+    }
+
+    /// Whether the vote passed or failed, None while it's undecided
+    pub(crate) fn result(&self) -> Option<bool> {
+        let (yes, no) = self.count();
+        if self.voters.is_empty() || self.voters.len() - no < self.needed {
+            Some(false)
+        } else if yes >= self.needed {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn votes_need_more_than_the_threshold() {
+        // A majority with the default threshold
+        assert_eq!(needed_votes(1, 50), 1);
+        assert_eq!(needed_votes(2, 50), 2);
+        assert_eq!(needed_votes(3, 50), 2);
+        assert_eq!(needed_votes(4, 50), 3);
+        assert_eq!(needed_votes(10, 66), 7);
+        // Everyone, or a single vote
+        assert_eq!(needed_votes(6, 100), 6);
+        assert_eq!(needed_votes(6, 0), 1);
+    }
+
+    #[test]
+    fn vote_subjects_use_the_rule_names_of_the_settings() {
+        assert_eq!(
+            HQMVoteSubject::parse("icing notouch"),
+            Some(HQMVoteSubject::Icing(HQMIcingConfiguration::NoTouch))
+        );
+        assert_eq!(
+            HQMVoteSubject::parse("offside imm"),
+            Some(HQMVoteSubject::Offside(HQMOffsideConfiguration::Immediate))
+        );
+        assert_eq!(HQMVoteSubject::parse("icing sometimes"), None);
+        assert_eq!(
+            HQMVoteSubject::parse("mercy off"),
+            Some(HQMVoteSubject::Mercy(0))
+        );
+    }
+}
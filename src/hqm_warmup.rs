@@ -1,19 +1,20 @@
+use crate::hqm_drill::HQMDrillControl;
 use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
-use migo_hqm_server::hqm_game::HQMPhysicsConfiguration;
+use migo_hqm_server::hqm_game::HQMPhysicsProfile;
 use migo_hqm_server::hqm_match_util::{get_spawnpoint, HQMSpawnPoint};
 use migo_hqm_server::hqm_server::{HQMInitialGameValues, HQMServer, HQMServerPlayerIndex, HQMTeam};
 use migo_hqm_server::hqm_simulate::HQMSimulationEvent;
-use nalgebra::{Point3, Rotation3};
 
 pub struct HQMPermanentWarmup {
-    physics_config: HQMPhysicsConfiguration,
+    physics_config: HQMPhysicsProfile,
     pucks: usize,
     spawn_point: HQMSpawnPoint,
+    drills: HQMDrillControl,
 }
 
 impl HQMPermanentWarmup {
     pub fn new(
-        physics_config: HQMPhysicsConfiguration,
+        physics_config: HQMPhysicsProfile,
         pucks: usize,
         spawn_point: HQMSpawnPoint,
     ) -> Self {
@@ -21,8 +22,10 @@ impl HQMPermanentWarmup {
             physics_config,
             pucks,
             spawn_point,
+            drills: HQMDrillControl::default(),
         }
     }
+
     fn update_players(&mut self, server: &mut HQMServer) {
         let mut spectating_players = vec![];
         let mut joining_team = vec![];
@@ -59,22 +62,35 @@ impl HQMPermanentWarmup {
     }
 }
 
+fn spawn_pucks(server: &mut HQMServer, pucks: usize) {
+    server.world.create_puck_line(pucks, 1.5);
+}
+
 impl HQMServerBehaviour for HQMPermanentWarmup {
     fn before_tick(&mut self, server: &mut HQMServer) {
         self.update_players(server);
     }
 
-    fn after_tick(&mut self, _server: &mut HQMServer, _events: &[HQMSimulationEvent]) {
-        // Nothing
+    fn after_tick(&mut self, server: &mut HQMServer, events: &[HQMSimulationEvent]) {
+        let pucks = self.pucks;
+        self.drills
+            .update(server, events, |server| spawn_pucks(server, pucks));
     }
 
     fn handle_command(
         &mut self,
-        _server: &mut HQMServer,
-        _cmd: &str,
-        _arg: &str,
-        _player_index: HQMServerPlayerIndex,
+        server: &mut HQMServer,
+        cmd: &str,
+        arg: &str,
+        player_index: HQMServerPlayerIndex,
     ) {
+        if cmd == "drill" {
+            let pucks = self.pucks;
+            self.drills
+                .command(server, arg, player_index, pucks, |server| {
+                    spawn_pucks(server, pucks)
+                });
+        }
     }
 
     fn get_number_of_players(&self) -> u32 {
@@ -92,17 +108,7 @@ impl HQMServerBehaviour for HQMPermanentWarmup {
     }
 
     fn game_started(&mut self, server: &mut HQMServer) {
-        let warmup_pucks = self.pucks;
-        let puck_line_start = server.world.rink.width / 2.0 - 0.4 * ((warmup_pucks - 1) as f32);
-
-        for i in 0..warmup_pucks {
-            let pos = Point3::new(
-                puck_line_start + 0.8 * (i as f32),
-                1.5,
-                server.world.rink.length / 2.0,
-            );
-            let rot = Rotation3::identity();
-            server.world.create_puck_object(pos, rot);
-        }
+        self.drills.clear();
+        spawn_pucks(server, self.pucks);
     }
 }
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Posts every event of the event feed to a webhook URL as JSON, one event per request and in
+/// the order they happened. Failed posts are retried with a delay that doubles every time.
+pub(crate) async fn run_webhook(
+    url: String,
+    client: reqwest::Client,
+    mut receiver: broadcast::Receiver<Arc<str>>,
+    retries: u32,
+) {
+    info!("Posting events to webhook {}", url);
+    loop {
+        match receiver.recv().await {
+            Ok(event) => post_event(&client, &url, &event, retries).await,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Webhook {} missed {} events", url, skipped);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn post_event(client: &reqwest::Client, url: &str, event: &str, retries: u32) {
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 0..=retries {
+        let result = client
+            .post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .header(CONTENT_TYPE, "application/json")
+            .body(event.to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let e = match result {
+            Ok(_) => return,
+            Err(e) => e,
+        };
+        // Other client errors mean that the request itself is wrong, sending it again won't help
+        let can_retry = match e.status() {
+            Some(status) if status.is_client_error() => {
+                status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => true,
+        };
+        if !can_retry || attempt == retries {
+            warn!("Could not post event to webhook {}: {}", url, e);
+            return;
+        }
+        warn!(
+            "Could not post event to webhook {}, retrying in {} seconds: {}",
+            url,
+            delay.as_secs(),
+            e
+        );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    }
+}
@@ -0,0 +1,53 @@
+/// Goals per second for each team at even strength, about one goal every 90 seconds
+const GOAL_RATE: f32 = 1.0 / 90.0;
+
+/// Estimates the probability that red wins, assuming both teams keep scoring at a steady rate
+/// that depends on how many players each team has on the ice. The remaining goal difference is
+/// approximated by a normal distribution, and a tie counts as a coin flip.
+pub(crate) fn win_probability(
+    red_score: u32,
+    blue_score: u32,
+    seconds_left: f32,
+    red_skaters: usize,
+    blue_skaters: usize,
+    sudden_death: bool,
+) -> f32 {
+    let strength = (red_skaters.max(1) as f32 / blue_skaters.max(1) as f32).sqrt();
+    let red_rate = GOAL_RATE * strength;
+    let blue_rate = GOAL_RATE / strength;
+    let diff = red_score as f32 - blue_score as f32;
+
+    if sudden_death {
+        // The next goal wins
+        return red_rate / (red_rate + blue_rate);
+    }
+
+    let mean = diff + (red_rate - blue_rate) * seconds_left;
+    let std_dev = ((red_rate + blue_rate) * seconds_left).sqrt();
+    if std_dev < 0.001 {
+        return if diff > 0.0 {
+            1.0
+        } else if diff < 0.0 {
+            0.0
+        } else {
+            0.5
+        };
+    }
+    0.5 * (normal_cdf((mean - 0.5) / std_dev) + normal_cdf((mean + 0.5) / std_dev))
+}
+
+fn normal_cdf(x: f32) -> f32 {
+    0.5 * (1.0 + erf(x / std::f32::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun approximation 7.1.26
+fn erf(x: f32) -> f32 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0
+        - (((((1.0614054 * t - 1.4531521) * t) + 1.4214138) * t - 0.28449672) * t + 0.2548296)
+            * t
+            * (-x * x).exp();
+    sign * y
+}
@@ -1,9 +1,44 @@
 mod hqm_admin_commands;
+pub mod hqm_alerts;
+pub mod hqm_autosave;
 
 pub mod hqm_behaviour;
+pub mod hqm_boards;
+pub mod hqm_bot;
+mod hqm_checksum;
+pub mod hqm_console;
+pub mod hqm_countdown;
+pub mod hqm_event_feed;
+mod hqm_extended_rules;
 pub mod hqm_game;
+pub mod hqm_game_log;
+mod hqm_goal_streak;
+mod hqm_highlights;
+mod hqm_input_monitor;
+pub mod hqm_master_server;
 mod hqm_match_commands;
+mod hqm_match_hints;
 pub mod hqm_match_util;
+mod hqm_movement_monitor;
+mod hqm_names;
+pub mod hqm_network_quality;
+mod hqm_object_selection;
+mod hqm_packet_size;
 mod hqm_parse;
+mod hqm_penalty;
+mod hqm_positions;
+pub mod hqm_practice;
+mod hqm_preferences;
+mod hqm_quick_chat;
+pub mod hqm_replay;
+mod hqm_replay_stream;
+mod hqm_results;
+mod hqm_send_batch;
 pub mod hqm_server;
 pub mod hqm_simulate;
+pub mod hqm_snapshot;
+mod hqm_stats;
+mod hqm_stats_page;
+mod hqm_tiebreaker;
+mod hqm_webhook;
+mod hqm_win_probability;
@@ -2,391 +2,557 @@ use std::path::Path;
 
 // INI Crate For configuration
 extern crate ini;
-use ini::Ini;
+use ini::{Ini, Properties};
 use std::env;
 
+mod hqm_config;
+mod hqm_drill;
+mod hqm_ghost;
 mod hqm_match;
+mod hqm_mode_state;
+mod hqm_playback;
+mod hqm_playlist;
+mod hqm_practice_mode;
+mod hqm_puck_machine;
+mod hqm_rejoin;
+mod hqm_rematch;
 
 mod hqm_russian;
+mod hqm_series;
 mod hqm_shootout;
+mod hqm_vote;
 mod hqm_warmup;
 
 use crate::hqm_match::HQMMatchBehaviour;
-
+use crate::hqm_mode_state::HQMModeState;
+use crate::hqm_playback::HQMPlaybackBehaviour;
+use crate::hqm_playlist::{HQMPlaylistBehaviour, HQMPlaylistEntry};
+use crate::hqm_practice_mode::HQMPracticeMode;
+
+use crate::hqm_config::{
+    env_overrides, get_optional, is_true, load_afk_time, load_alerts, load_autosave_interval,
+    load_ban_file, load_board_rotation, load_boards, load_chat_history, load_compression,
+    load_config, load_console_config, load_debug_commands, load_event_feed_address,
+    load_game_log_config, load_highlights_directory, load_input_toggle_limit, load_instant_replay,
+    load_join_challenge, load_log_json, load_log_level, load_log_name, load_master_servers,
+    load_match_config, load_max_packet_size, load_movement_check, load_network_limits,
+    load_packet_rate_limit, load_physics_config, load_playlist, load_playlist_config,
+    load_practice_config, load_preferences_file, load_replay_file_saving, load_replay_stream_rate,
+    load_restart_after_games, load_results_directory, load_rink_config, load_savegame_directory,
+    load_snapshot_file, load_spawn_point, load_spectator_delay, load_spectator_slots,
+    load_stats_database, load_stats_page_address, load_webhook_retries, load_webhooks,
+    load_welcome, parse_command_line, validate_config, HQMConfigSource, LOG_KEYS,
+};
 use crate::hqm_russian::HQMRussianBehaviour;
 use crate::hqm_shootout::HQMShootoutBehaviour;
 use crate::hqm_warmup::HQMPermanentWarmup;
-use ini::Properties;
-use migo_hqm_server::hqm_game::HQMPhysicsConfiguration;
-use migo_hqm_server::hqm_match_util::{
-    HQMIcingConfiguration, HQMMatchConfiguration, HQMOffsideConfiguration,
-    HQMOffsideLineConfiguration, HQMSpawnPoint, HQMTwoLinePassConfiguration,
-};
+use migo_hqm_server::hqm_behaviour::HQMServerBehaviour;
+use migo_hqm_server::hqm_game_log::game_log_layer;
+use migo_hqm_server::hqm_replay::HQMReplayPlayback;
 use migo_hqm_server::hqm_server;
 use migo_hqm_server::hqm_server::{HQMServerConfiguration, ReplayEnabled, ReplaySaving};
+use tracing::{error, info_span, Instrument};
 use tracing_appender;
 use tracing_subscriber;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 
 enum HQMServerMode {
     Match,
     PermanentWarmup,
+    Practice,
     Russian,
     Shootout,
+    Playback,
 }
 
-fn is_true(s: &str) -> bool {
-    s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("on")
+impl HQMServerMode {
+    /// The name of the mode in the configuration file
+    fn name(&self) -> &'static str {
+        match self {
+            HQMServerMode::Match => "match",
+            HQMServerMode::PermanentWarmup => "warmup",
+            HQMServerMode::Practice => "practice",
+            HQMServerMode::Russian => "russian",
+            HQMServerMode::Shootout => "shootout",
+            HQMServerMode::Playback => "playback",
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let args: Vec<String> = env::args().collect();
-
-    let config_path = if args.len() > 1 {
-        &args[1]
-    } else {
-        "config.ini"
+    let command_line = match parse_command_line(env::args().skip(1)) {
+        Ok(command_line) => command_line,
+        Err(e) => {
+            println!("{}", e);
+            println!(
+                "Usage: migo-hqm-server [config path]... [--fresh] [--resume] [--key value]..."
+            );
+            return Ok(());
+        }
     };
 
-    // Load configuration (if exists)
-    if Path::new(config_path).exists() {
-        // Load configuration file
-        let conf = Ini::load_from_file(config_path).unwrap();
+    // Overrides would apply to every server, so that --port would make all of them use the same port
+    if command_line.config_paths.len() > 1 {
+        let env_overrides = env_overrides();
+        if !command_line.overrides.is_empty() || !env_overrides.is_empty() {
+            println!("Settings can't be overridden when more than one configuration file is given");
+            for name in env_overrides {
+                println!("  Environment variable {} is set", name);
+            }
+            return Ok(());
+        }
+    }
+
+    let mut configs = vec![];
+    for config_path in command_line.config_paths.iter() {
+        // Load configuration (if exists)
+        if !Path::new(config_path).exists() {
+            println!("Could not open configuration file {}!", config_path);
+            return Ok(());
+        }
+        // Load configuration file, with overrides from environment variables and command line
+        let conf = load_config(config_path, None, &command_line.overrides).unwrap();
 
-        // Server information
-        let server_section = conf.section(Some("Server")).unwrap();
-        let server_name = server_section
-            .get("name")
-            .unwrap()
-            .parse::<String>()
-            .unwrap();
-        let server_port = server_section.get("port").unwrap().parse::<u16>().unwrap();
-        let server_public = is_true(server_section.get("public").unwrap());
-        let public_address = if server_public {
-            Some(
-                server_section
-                    .get("public_address")
-                    .unwrap_or("https://sam2.github.io/HQMMasterServerEndpoint/"),
-            )
-        } else {
-            None
-        };
-        let server_player_max = server_section
-            .get("player_max")
-            .unwrap()
-            .parse::<usize>()
-            .unwrap();
-        let server_team_max = server_section
-            .get("team_max")
-            .unwrap()
-            .parse::<usize>()
-            .unwrap();
-
-        let server_password = server_section
-            .get("password")
-            .unwrap()
-            .parse::<String>()
-            .unwrap();
-        let mode = server_section
-            .get("mode")
-            .map_or(HQMServerMode::Match, |x| match x {
-                "warmup" => HQMServerMode::PermanentWarmup,
-                "match" => HQMServerMode::Match,
-                "russian" => HQMServerMode::Russian,
-                "shootout" => HQMServerMode::Shootout,
-                _ => HQMServerMode::Match,
-            });
+        let problems = validate_config(&conf);
+        for warning in problems.warnings {
+            println!("Warning in configuration file {}: {}", config_path, warning);
+        }
+        if !problems.errors.is_empty() {
+            println!("Configuration file {} is invalid:", config_path);
+            for error in problems.errors {
+                println!("  {}", error);
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Invalid configuration",
+            ));
+        }
 
-        let replays_enabled = match server_section.get("replays") {
-            Some(s) if is_true(s) => ReplayEnabled::On,
-            Some(s) if s.eq_ignore_ascii_case("standby") => ReplayEnabled::Standby,
-            _ => ReplayEnabled::Off,
+        // Settings changed while the server last ran are applied over the configuration file
+        let mut state = conf
+            .section(Some("Server"))
+            .and_then(|x| x.get("state_file"))
+            .map(|path| HQMModeState::load(path, command_line.fresh));
+        // The saved settings were made for the mode they were saved with
+        let file_mode = load_mode(conf.section(Some("Server")).unwrap()).name();
+        if let Some(state) = state.as_mut() {
+            if state.mode().is_some_and(|mode| mode != file_mode) {
+                println!(
+                    "The mode in {} changed to {}, ignoring the settings saved in {}",
+                    config_path,
+                    file_mode,
+                    state.path().display()
+                );
+                state.clear();
+            }
+        }
+        let conf = match state.as_mut() {
+            Some(state) if state.settings().is_some() => {
+                let resumed =
+                    load_config(config_path, state.settings(), &command_line.overrides).unwrap();
+                if validate_config(&resumed).errors.is_empty() {
+                    println!(
+                        "Resuming with the settings saved in {}, start with --fresh to ignore them",
+                        state.path().display()
+                    );
+                    resumed
+                } else {
+                    println!(
+                        "Settings saved in {} are invalid, ignoring them",
+                        state.path().display()
+                    );
+                    state.clear_settings();
+                    conf
+                }
+            }
+            _ => conf,
         };
+        configs.push((config_path.clone(), conf, state));
+    }
 
-        let log_name = server_section
-            .get("log_name")
-            .map_or(format!("{}.log", server_name), |x| String::from(x));
-
-        let welcome = server_section.get("welcome").unwrap_or("");
-
-        let welcome_str = welcome
-            .lines()
-            .map(String::from)
-            .filter(|x| !x.is_empty())
-            .collect();
-
-        let replay_saving =
-            server_section
-                .get("replay_endpoint")
-                .map_or(ReplaySaving::File, |url| ReplaySaving::Endpoint {
-                    url: url.to_string(),
-                });
-
-        fn get_optional<U, F: FnOnce(&str) -> U>(
-            section: Option<&Properties>,
-            property: &str,
-            default: U,
-            f: F,
-        ) -> U {
-            section.and_then(|x| x.get(property)).map_or(default, f)
+    // All servers in this process write to one log, in which each line is tagged with the server
+    // it came from, so the log settings have to be the same in every configuration file
+    let log_settings = |conf: &Ini| {
+        let server_section = conf.section(Some("Server")).unwrap();
+        LOG_KEYS
+            .iter()
+            .map(|key| server_section.get(key).map(String::from))
+            .collect::<Vec<_>>()
+    };
+    for (config_path, conf, _) in configs.iter().skip(1) {
+        if log_settings(conf) != log_settings(&configs[0].1) {
+            println!(
+                "The log settings ({}) of {} differ from those of {}",
+                LOG_KEYS.join(", "),
+                config_path,
+                configs[0].0
+            );
+            return Ok(());
         }
-
-        let server_service = server_section.get("service").map(|x| x.to_owned());
-
-        // Game
-        let game_section = conf.section(Some("Game"));
-
-        let limit_jump_speed = get_optional(game_section, "limit_jump_speed", false, is_true);
-
-        let config = HQMServerConfiguration {
-            welcome: welcome_str,
-            password: server_password,
-            player_max: server_player_max,
-            replays_enabled,
-            replay_saving,
-            server_name,
-            server_service,
+    }
+    let log_section = configs[0].1.section(Some("Server")).unwrap();
+    let log_name = load_log_name(log_section);
+    let file_appender = tracing_appender::rolling::daily("log", log_name);
+    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
+    let log_layer = tracing_subscriber::fmt::layer()
+        .with_line_number(false)
+        .with_file(false)
+        .with_target(false)
+        .with_writer(non_blocking);
+    let log_layer = if load_log_json(log_section) {
+        log_layer.json().boxed()
+    } else {
+        log_layer.boxed()
+    };
+    let (game_log_layer, _game_log_guard) = match load_game_log_config(log_section) {
+        Some(game_log_config) => match game_log_layer(game_log_config) {
+            Ok((layer, guard)) => (Some(layer), Some(guard)),
+            Err(e) => {
+                println!("Could not create game log: {}", e);
+                return Ok(());
+            }
+        },
+        None => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(log_layer.with_filter(LevelFilter::from_level(load_log_level(log_section))))
+        .with(game_log_layer)
+        .init();
+
+    if configs.len() == 1 {
+        let (config_path, conf, state) = configs.pop().unwrap();
+        let source = HQMConfigSource {
+            path: config_path,
+            overrides: command_line.overrides,
         };
+        run_configured_server(source, conf, state, command_line.resume).await
+    } else {
+        // Several servers in one process, each with its own port, configuration and game. Each
+        // runs as a task of its own, so that one that fails or panics doesn't stop the others
+        let local = tokio::task::LocalSet::new();
+        let servers: Vec<_> = configs
+            .into_iter()
+            .map(|(config_path, conf, state)| {
+                let name = conf
+                    .section(Some("Server"))
+                    .and_then(|x| x.get("name"))
+                    .unwrap_or_default()
+                    .to_string();
+                let source = HQMConfigSource {
+                    path: config_path,
+                    overrides: command_line.overrides.clone(),
+                };
+                let server = run_configured_server(source, conf, state, command_line.resume)
+                    .instrument(info_span!("server", name));
+                (name, local.spawn_local(server))
+            })
+            .collect();
+        local
+            .run_until(async {
+                for (name, server) in servers {
+                    match server.await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => error!("Server {} stopped: {}", name, e),
+                        Err(e) => error!("Server {} crashed: {}", name, e),
+                    }
+                }
+            })
+            .await;
+        Ok(())
+    }
+}
 
-        // Physics
-        let physics_section = conf.section(Some("Physics"));
-        let gravity = get_optional(physics_section, "gravity", 0.000680555, |x| {
-            x.parse::<f32>().unwrap() / 10000.0
-        });
-        let player_acceleration =
-            get_optional(physics_section, "player_acceleration", 0.000208333, |x| {
-                x.parse::<f32>().unwrap() / 10000.0
-            });
-        let player_deceleration =
-            get_optional(physics_section, "player_deceleration", 0.000555555, |x| {
-                x.parse::<f32>().unwrap() / 10000.0
-            });
-        let max_player_speed = get_optional(physics_section, "max_player_speed", 0.05, |x| {
-            x.parse::<f32>().unwrap() / 100.0
-        });
-        let max_player_shift_speed =
-            get_optional(physics_section, "max_player_shift_speed", 0.0333333, |x| {
-                x.parse::<f32>().unwrap() / 100.0
-            });
+async fn run_configured_server(
+    config_source: HQMConfigSource,
+    conf: Ini,
+    mut state: Option<HQMModeState>,
+    resume: bool,
+) -> std::io::Result<()> {
+    // Server information
+    let server_section = conf.section(Some("Server")).unwrap();
+    let server_name = server_section
+        .get("name")
+        .unwrap()
+        .parse::<String>()
+        .unwrap();
+    let server_port = server_section.get("port").unwrap().parse::<u16>().unwrap();
+    let server_player_max = server_section
+        .get("player_max")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let server_password = server_section
+        .get("password")
+        .unwrap()
+        .parse::<String>()
+        .unwrap();
+    if let Some(state) = &mut state {
+        state.set_mode(load_mode(server_section).name());
+    }
+
+    let replays_enabled = match server_section.get("replays") {
+        Some(s) if is_true(s) => ReplayEnabled::On,
+        Some(s) if s.eq_ignore_ascii_case("standby") => ReplayEnabled::Standby,
+        _ => ReplayEnabled::Off,
+    };
 
-        let puck_rink_friction = get_optional(physics_section, "puck_rink_friction", 0.05, |x| {
-            x.parse::<f32>().unwrap()
-        });
-        let player_turning = get_optional(physics_section, "player_turning", 0.00041666666, |x| {
-            x.parse::<f32>().unwrap() / 10000.0
-        });
-        let player_shift_turning = get_optional(
-            physics_section,
-            "player_shift_turning",
-            0.00038888888,
-            |x| x.parse::<f32>().unwrap() / 10000.0,
-        );
-
-        let player_shift_acceleration = get_optional(
-            physics_section,
-            "player_shift_acceleration",
-            0.00027777,
-            |x| x.parse::<f32>().unwrap() / 10000.0,
-        );
-
-        let physics_config = HQMPhysicsConfiguration {
-            gravity,
-            limit_jump_speed,
-            player_acceleration,
-            player_deceleration,
-            player_shift_acceleration,
-            max_player_speed,
-            max_player_shift_speed,
-            puck_rink_friction,
-            player_turning,
-            player_shift_turning,
-        };
+    let welcome_str = load_welcome(server_section);
+
+    let replay_saving = server_section.get("replay_endpoint").map_or_else(
+        || load_replay_file_saving(server_section),
+        |url| ReplaySaving::Endpoint {
+            url: url.to_string(),
+        },
+    );
+
+    let server_service = server_section.get("service").map(|x| x.to_owned());
+
+    let chat_history = load_chat_history(server_section);
+    let spectator_slots = load_spectator_slots(server_section);
+    let spectator_delay = load_spectator_delay(server_section);
+    let afk_time = load_afk_time(server_section);
+    let rink = load_rink_config(&conf);
+    let compression = load_compression(server_section);
+    let input_toggle_limit = load_input_toggle_limit(server_section);
+    let restart_after_games = load_restart_after_games(server_section);
+    let packet_rate_limit = load_packet_rate_limit(server_section);
+    let join_challenge = load_join_challenge(server_section);
+    let debug_commands = load_debug_commands(server_section);
+    let max_packet_size = load_max_packet_size(server_section);
+    let public = is_true(server_section.get("public").unwrap());
+    let master_servers = load_master_servers(&conf);
+    let console = load_console_config(server_section);
+    let event_feed = load_event_feed_address(server_section);
+    let webhooks = load_webhooks(server_section);
+    let webhook_retries = load_webhook_retries(server_section);
+    let ban_file = load_ban_file(server_section);
+    let snapshot_file = load_snapshot_file(server_section);
+    let preferences_file = load_preferences_file(server_section);
+    let savegame_directory = load_savegame_directory(server_section);
+    let autosave_interval = load_autosave_interval(server_section);
+    let stats_database = load_stats_database(server_section);
+    let stats_page = load_stats_page_address(server_section);
+    let stats_page_url = server_section.get("stats_page_url").map(String::from);
+    let results_directory = load_results_directory(server_section);
+    let highlights_directory = load_highlights_directory(server_section);
+    let boards = load_boards(server_section);
+    let board_rotation = load_board_rotation(server_section);
+    let alerts = load_alerts(server_section);
+    let instant_replay = load_instant_replay(server_section);
+    let movement_check = load_movement_check(server_section);
+    let network_limits = load_network_limits(server_section);
+    let replay_stream_rate = load_replay_stream_rate(server_section);
+
+    let config = HQMServerConfiguration {
+        welcome: welcome_str,
+        password: server_password,
+        player_max: server_player_max,
+        spectator_slots,
+        spectator_delay,
+        afk_time,
+        rink,
+        replays_enabled,
+        replay_saving,
+        server_name,
+        server_service,
+        chat_history,
+        compression,
+        input_toggle_limit,
+        restart_after_games,
+        packet_rate_limit,
+        join_challenge,
+        debug_commands,
+        max_packet_size,
+        public,
+        master_servers,
+        console,
+        event_feed,
+        webhooks,
+        webhook_retries,
+        ban_file,
+        snapshot_file,
+        preferences_file,
+        savegame_directory,
+        resume,
+        autosave_interval,
+        stats_database,
+        stats_page,
+        stats_page_url,
+        results_directory,
+        highlights_directory,
+        boards,
+        board_rotation,
+        alerts,
+        instant_replay,
+        movement_check,
+        network_limits,
+        replay_stream_rate,
+    };
 
-        let file_appender = tracing_appender::rolling::daily("log", log_name);
-        let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-        tracing_subscriber::fmt()
-            .with_line_number(false)
-            .with_file(false)
-            .with_target(false)
-            .with_writer(non_blocking)
-            .init();
-
-        return match mode {
-            HQMServerMode::Match => {
-                let periods =
-                    get_optional(game_section, "periods", 3, |x| x.parse::<u32>().unwrap());
-
-                let rules_time_period = get_optional(game_section, "time_period", 300, |x| {
-                    x.parse::<u32>().unwrap()
-                });
-                let rules_time_warmup = get_optional(game_section, "time_warmup", 300, |x| {
-                    x.parse::<u32>().unwrap()
-                });
-                let rule_time_break = get_optional(game_section, "time_break", 10, |x| {
-                    x.parse::<u32>().unwrap()
-                });
-                let rule_time_intermission =
-                    get_optional(game_section, "time_intermission", 20, |x| {
-                        x.parse::<u32>().unwrap()
-                    });
-                let warmup_pucks = get_optional(game_section, "warmup_pucks", 1, |x| {
-                    x.parse::<usize>().unwrap()
+    let behaviour = match load_playlist(server_section) {
+        playlist if playlist.is_empty() => create_behaviour(&conf, config_source, state)?,
+        playlist => {
+            let mut entries = vec![];
+            for (path, games) in playlist {
+                let entry_conf = match load_playlist_config(&conf, &path) {
+                    Ok(entry_conf) => entry_conf,
+                    Err(e) => {
+                        println!("Could not open playlist entry {}: {}", path, e);
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "Invalid playlist",
+                        ));
+                    }
+                };
+                let problems = validate_config(&entry_conf);
+                for warning in problems.warnings {
+                    println!("Warning in playlist entry {}: {}", path, warning);
+                }
+                let mut errors = problems.errors;
+                match load_mode(entry_conf.section(Some("Server")).unwrap()) {
+                    HQMServerMode::PermanentWarmup => {
+                        errors.push(
+                            "mode warmup never ends, so it can't be in a playlist".to_string(),
+                        );
+                    }
+                    HQMServerMode::Practice => {
+                        errors.push(
+                            "mode practice never ends, so it can't be in a playlist".to_string(),
+                        );
+                    }
+                    HQMServerMode::Playback => {
+                        errors.push(
+                            "mode playback never ends, so it can't be in a playlist".to_string(),
+                        );
+                    }
+                    _ => {}
+                }
+                if !errors.is_empty() {
+                    println!("Playlist entry {} is invalid:", path);
+                    for error in errors {
+                        println!("  {}", error);
+                    }
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Invalid playlist",
+                    ));
+                }
+                let name = Path::new(&path)
+                    .file_stem()
+                    .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                entries.push(HQMPlaylistEntry {
+                    name,
+                    games,
+                    behaviour: create_behaviour(&entry_conf, config_source.clone(), None)?,
                 });
+            }
+            Box::new(HQMPlaylistBehaviour::new(entries))
+        }
+    };
 
-                let mercy = get_optional(game_section, "mercy", 0, |x| x.parse::<u32>().unwrap());
-                let first_to =
-                    get_optional(game_section, "first", 0, |x| x.parse::<u32>().unwrap());
-
-                let icing =
-                    get_optional(
-                        game_section,
-                        "icing",
-                        HQMIcingConfiguration::Off,
-                        |x| match x {
-                            "on" | "touch" => HQMIcingConfiguration::Touch,
-                            "notouch" => HQMIcingConfiguration::NoTouch,
-                            _ => HQMIcingConfiguration::Off,
-                        },
-                    );
+    hqm_server::run_server(server_port, config, behaviour).await
+}
 
-                let offside = get_optional(
-                    game_section,
-                    "offside",
-                    HQMOffsideConfiguration::Off,
-                    |x| match x {
-                        "on" | "delayed" => HQMOffsideConfiguration::Delayed,
-                        "immediate" | "imm" => HQMOffsideConfiguration::Immediate,
-                        _ => HQMOffsideConfiguration::Off,
-                    },
-                );
+fn load_mode(server_section: &Properties) -> HQMServerMode {
+    server_section
+        .get("mode")
+        .map_or(HQMServerMode::Match, |x| match x {
+            "warmup" => HQMServerMode::PermanentWarmup,
+            "practice" => HQMServerMode::Practice,
+            "match" => HQMServerMode::Match,
+            "russian" => HQMServerMode::Russian,
+            "shootout" => HQMServerMode::Shootout,
+            "playback" => HQMServerMode::Playback,
+            _ => HQMServerMode::Match,
+        })
+}
 
-                let offside_line = get_optional(
-                    game_section,
-                    "offsideline",
-                    HQMOffsideLineConfiguration::OffensiveBlue,
-                    |x| match x {
-                        "blue" => HQMOffsideLineConfiguration::OffensiveBlue,
-                        "center" => HQMOffsideLineConfiguration::Center,
-                        _ => HQMOffsideLineConfiguration::OffensiveBlue,
-                    },
-                );
+/// Creates the game mode of the configuration, with the settings of its [Game] section
+fn create_behaviour(
+    conf: &Ini,
+    config_source: HQMConfigSource,
+    state: Option<HQMModeState>,
+) -> std::io::Result<Box<dyn HQMServerBehaviour>> {
+    let server_section = conf.section(Some("Server")).unwrap();
+    let team_max = server_section
+        .get("team_max")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let game_section = conf.section(Some("Game"));
+
+    let physics_config = load_physics_config(conf);
+
+    let behaviour: Box<dyn HQMServerBehaviour> = match load_mode(server_section) {
+        HQMServerMode::Match => {
+            let match_config = load_match_config(conf, physics_config);
+            let spawn_point = load_spawn_point(conf);
+
+            Box::new(HQMMatchBehaviour::new(
+                match_config,
+                team_max,
+                spawn_point,
+                config_source,
+                state,
+            ))
+        }
+        HQMServerMode::PermanentWarmup => {
+            let warmup_pucks = get_optional(game_section, "warmup_pucks", 1, |x| {
+                x.parse::<usize>().unwrap()
+            });
 
-                let twoline_pass = get_optional(
-                    game_section,
-                    "twolinepass",
-                    HQMTwoLinePassConfiguration::Off,
-                    |x| match x {
-                        "on" => HQMTwoLinePassConfiguration::On,
-                        "forward" => HQMTwoLinePassConfiguration::Forward,
-                        "double" | "both" => HQMTwoLinePassConfiguration::Double,
-                        "blue" | "three" | "threeline" => HQMTwoLinePassConfiguration::ThreeLine,
-                        _ => HQMTwoLinePassConfiguration::Off,
-                    },
-                );
+            let spawn_point = load_spawn_point(conf);
 
-                let spawn_point =
-                    get_optional(game_section, "spawn", HQMSpawnPoint::Center, |x| match x {
-                        "bench" => HQMSpawnPoint::Bench,
-                        _ => HQMSpawnPoint::Center,
-                    });
+            Box::new(HQMPermanentWarmup::new(
+                physics_config,
+                warmup_pucks,
+                spawn_point,
+            ))
+        }
+        HQMServerMode::Practice => {
+            let practice_config = load_practice_config(conf, physics_config);
 
-                let spawn_point_offset = get_optional(game_section, "spawn_offset", 2.75f32, |x| {
-                    x.parse::<f32>().unwrap()
-                });
+            Box::new(HQMPracticeMode::new(practice_config))
+        }
+        HQMServerMode::Russian => {
+            let attempts =
+                get_optional(game_section, "attempts", 10, |x| x.parse::<u32>().unwrap());
 
-                let spawn_player_altitude =
-                    get_optional(game_section, "spawn_player_altitude", 1.5f32, |x| {
-                        x.parse::<f32>().unwrap()
-                    });
-
-                let spawn_puck_altitude =
-                    get_optional(game_section, "spawn_puck_altitude", 1.5f32, |x| {
-                        x.parse::<f32>().unwrap()
-                    });
-
-                let spawn_keep_stick_position =
-                    get_optional(game_section, "spawn_player_keep_stick", false, is_true);
-
-                let use_mph = get_optional(game_section, "use_mph", false, is_true);
-
-                let goal_replay = get_optional(game_section, "goal_replay", false, is_true);
-
-                let match_config = HQMMatchConfiguration {
-                    time_period: rules_time_period,
-                    time_warmup: rules_time_warmup,
-                    time_break: rule_time_break,
-                    time_intermission: rule_time_intermission,
-                    mercy,
-                    first_to,
-                    icing,
-                    offside,
-                    offside_line,
-                    twoline_pass,
-                    warmup_pucks,
-                    use_mph,
-                    goal_replay,
-                    physics_config,
-                    periods,
-                    spawn_point_offset,
-                    spawn_player_altitude,
-                    spawn_puck_altitude,
-                    spawn_keep_stick_position,
-                };
+            Box::new(HQMRussianBehaviour::new(attempts, team_max, physics_config))
+        }
+        HQMServerMode::Shootout => {
+            let attempts = get_optional(game_section, "attempts", 5, |x| x.parse::<u32>().unwrap());
 
-                hqm_server::run_server(
-                    server_port,
-                    public_address,
-                    config,
-                    HQMMatchBehaviour::new(match_config, server_team_max, spawn_point),
-                )
-                .await
-            }
-            HQMServerMode::PermanentWarmup => {
-                let warmup_pucks = get_optional(game_section, "warmup_pucks", 1, |x| {
-                    x.parse::<usize>().unwrap()
+            Box::new(HQMShootoutBehaviour::new(attempts, physics_config))
+        }
+        HQMServerMode::Playback => {
+            let replay_file = game_section.and_then(|x| x.get("replay_file")).unwrap();
+            let playback = match HQMReplayPlayback::load(Path::new(replay_file)) {
+                Ok(playback) => playback,
+                Err(e) => {
+                    println!("Could not load replay {}: {}", replay_file, e);
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "Invalid replay",
+                    ));
+                }
+            };
+            let file_name = Path::new(replay_file)
+                .file_name()
+                .map_or(replay_file.to_string(), |name| {
+                    name.to_string_lossy().into_owned()
                 });
 
-                let spawn_point =
-                    get_optional(game_section, "spawn", HQMSpawnPoint::Center, |x| match x {
-                        "bench" => HQMSpawnPoint::Bench,
-                        _ => HQMSpawnPoint::Center,
-                    });
-
-                hqm_server::run_server(
-                    server_port,
-                    public_address,
-                    config,
-                    HQMPermanentWarmup::new(physics_config, warmup_pucks, spawn_point),
-                )
-                .await
-            }
-            HQMServerMode::Russian => {
-                let attempts =
-                    get_optional(game_section, "attempts", 10, |x| x.parse::<u32>().unwrap());
-
-                hqm_server::run_server(
-                    server_port,
-                    public_address,
-                    config,
-                    HQMRussianBehaviour::new(attempts, server_team_max, physics_config),
-                )
-                .await
-            }
-            HQMServerMode::Shootout => {
-                let attempts =
-                    get_optional(game_section, "attempts", 5, |x| x.parse::<u32>().unwrap());
-
-                hqm_server::run_server(
-                    server_port,
-                    public_address,
-                    config,
-                    HQMShootoutBehaviour::new(attempts, physics_config),
-                )
-                .await
-            }
-        };
-    } else {
-        println!("Could not open configuration file {}!", config_path);
-        return Ok(());
+            Box::new(HQMPlaybackBehaviour::new(
+                playback,
+                file_name,
+                physics_config,
+            ))
+        }
     };
+    Ok(behaviour)
 }